@@ -0,0 +1,56 @@
+// File: crates/storm-ai/src/error.rs
+// Typed AI errors with stable codes and retry classification
+
+use thiserror::Error;
+
+use crate::TaskType;
+
+/// AI dispatch failures
+#[derive(Error, Debug)]
+pub enum AiError {
+    #[error("{backend} backend not available")]
+    BackendUnavailable { backend: &'static str },
+
+    #[error("Model not available for task: {task:?}")]
+    ModelUnavailable { task: TaskType },
+
+    #[error("Grok API error: HTTP {status}")]
+    Api { status: u16 },
+
+    #[error("No response from Grok API")]
+    EmptyResponse,
+
+    #[error("Inference failed: {0}")]
+    Inference(String),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl AiError {
+    /// Stable numeric code, in the 4000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            AiError::BackendUnavailable { .. } => 4001,
+            AiError::ModelUnavailable { .. } => 4002,
+            AiError::Api { .. } => 4003,
+            AiError::EmptyResponse => 4004,
+            AiError::Inference(_) => 4005,
+            AiError::Other(_) => 4000,
+        }
+    }
+
+    /// Rate limits, server errors and empty replies are worth another attempt
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AiError::Api { status } => *status == 429 || *status >= 500,
+            AiError::EmptyResponse => true,
+            _ => false,
+        }
+    }
+
+    /// AI enhancements are optional, so no AI failure stops the engine
+    pub fn is_fatal(&self) -> bool {
+        false
+    }
+}
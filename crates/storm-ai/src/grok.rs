@@ -4,7 +4,7 @@
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
-use crate::{AIRequest, TaskType};
+use crate::{AIRequest, AiError, TaskType};
 
 #[derive(Clone)]
 pub struct GrokClient {
@@ -81,7 +81,7 @@ impl GrokClient {
             .await?;
 
         if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Grok API error: {}", response.status()));
+            return Err(AiError::Api { status: response.status().as_u16() }.into());
         }
 
         let grok_response: GrokResponse = response.json().await?;
@@ -89,7 +89,7 @@ impl GrokClient {
         if let Some(choice) = grok_response.choices.first() {
             Ok(choice.message.content.as_bytes().to_vec())
         } else {
-            Err(anyhow::anyhow!("No response from Grok API"))
+            Err(AiError::EmptyResponse.into())
         }
     }
 
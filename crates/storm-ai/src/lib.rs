@@ -2,7 +2,7 @@
 // AI Dispatcher and ML models for StormCore
 // Fixed RwLock usage and removed unused imports
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock, Semaphore};
 use serde::{Deserialize, Serialize};
@@ -13,9 +13,11 @@ pub mod models;
 pub mod dispatcher;
 pub mod grok;
 pub mod local_ml;
+pub mod error;
 
 pub use dispatcher::*;
 pub use models::*;
+pub use error::AiError;
 
 /// AI system configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,8 +51,12 @@ pub struct AIDispatcher {
     grok_client: Option<grok::GrokClient>,
     local_ml: Option<local_ml::LocalMLEngine>,
     request_semaphore: Arc<Semaphore>,
+    completed_metrics: Arc<std::sync::Mutex<VecDeque<AIMetrics>>>,
 }
 
+/// Completed request metrics kept until the engine collects them
+const MAX_PENDING_METRICS: usize = 1024;
+
 /// AI request structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AIRequest {
@@ -144,6 +150,7 @@ impl AIDispatcher {
         };
 
         let request_semaphore = Arc::new(Semaphore::new(config.max_concurrent_requests));
+        let completed_metrics = Arc::new(std::sync::Mutex::new(VecDeque::new()));
 
         let dispatcher = Self {
             config: config.clone(),
@@ -152,6 +159,7 @@ impl AIDispatcher {
             grok_client,
             local_ml,
             request_semaphore,
+            completed_metrics: completed_metrics.clone(),
         };
 
         // Spawn request processing task
@@ -166,10 +174,11 @@ impl AIDispatcher {
                 let grok = grok.clone();
                 let local = local.clone();
                 let semaphore = semaphore.clone();
+                let completed_metrics = completed_metrics.clone();
 
                 tokio::spawn(async move {
                     let _permit = semaphore.acquire().await.unwrap();
-                    Self::process_request(request, handlers, grok, local).await;
+                    Self::process_request(request, handlers, grok, local, completed_metrics).await;
                 });
             }
         });
@@ -202,6 +211,11 @@ impl AIDispatcher {
         Ok(())
     }
 
+    /// Take the metrics of requests completed since the last call
+    pub fn drain_metrics(&self) -> Vec<AIMetrics> {
+        self.completed_metrics.lock().unwrap().drain(..).collect()
+    }
+
     /// Shutdown the AI dispatcher
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down AI dispatcher");
@@ -218,6 +232,7 @@ impl AIDispatcher {
         handlers: Arc<RwLock<HashMap<uuid::Uuid, ResponseCallback>>>,
         grok_client: Option<grok::GrokClient>,
         local_ml: Option<local_ml::LocalMLEngine>,
+        completed_metrics: Arc<std::sync::Mutex<VecDeque<AIMetrics>>>,
     ) {
         let start_time = std::time::Instant::now();
 
@@ -226,14 +241,14 @@ impl AIDispatcher {
                 if let Some(ref local) = local_ml {
                     local.process_request(&request).await
                 } else {
-                    Err(anyhow::anyhow!("Local ML not available"))
+                    Err(AiError::BackendUnavailable { backend: "Local ML" }.into())
                 }
             }
             AITier::High => {
                 if let Some(ref grok) = grok_client {
                     grok.process_request(&request).await
                 } else {
-                    Err(anyhow::anyhow!("Grok API not available"))
+                    Err(AiError::BackendUnavailable { backend: "Grok API" }.into())
                 }
             }
         };
@@ -252,6 +267,14 @@ impl AIDispatcher {
             confidence: 0.8, // Placeholder
         };
 
+        {
+            let mut completed = completed_metrics.lock().unwrap();
+            if completed.len() >= MAX_PENDING_METRICS {
+                completed.pop_front();
+            }
+            completed.push_back(response.metrics.clone());
+        }
+
         // Call response handler - fixed RwLock usage
         {
             let handlers_guard = handlers.read().await;
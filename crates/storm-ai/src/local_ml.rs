@@ -6,7 +6,7 @@ use candle_nn::{linear, Linear, Module, VarBuilder, VarMap};
 use std::collections::HashMap;
 use std::sync::Arc;
 use anyhow::Result;
-use crate::{AIRequest, TaskType};
+use crate::{AIRequest, AiError, TaskType};
 
 pub struct LocalMLEngine {
     device: Device,
@@ -100,7 +100,7 @@ impl LocalMLEngine {
 
             Ok(output_bytes)
         } else {
-            Err(anyhow::anyhow!("Model not available for task: {:?}", request.task_type))
+            Err(AiError::ModelUnavailable { task: request.task_type.clone() }.into())
         }
     }
 
@@ -150,13 +150,13 @@ impl LocalMLEngine {
         // Create tensor with explicit shape
         let shape = Shape::from_dims(&[1, 64]);
         Tensor::from_vec(padded_floats, shape, &self.device)
-            .map_err(|e| anyhow::anyhow!("Tensor creation error: {}", e))
+            .map_err(|e| AiError::Inference(format!("tensor creation: {}", e)).into())
     }
 
     fn tensor_to_bytes(&self, tensor: &Tensor) -> Result<Vec<u8>> {
         // Convert tensor back to bytes with modern API
         let data = tensor.to_vec2::<f32>()
-            .map_err(|e| anyhow::anyhow!("Tensor conversion error: {}", e))?;
+            .map_err(|e| AiError::Inference(format!("tensor conversion: {}", e)))?;
 
         let bytes: Vec<u8> = data.into_iter()
             .flatten()
@@ -3,6 +3,7 @@
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use crate::{AssetId, AssetData, AssetMetadata, Vertex};
 
 /// Asset cache implementation
@@ -10,8 +11,12 @@ pub struct AssetCache {
     assets: HashMap<AssetId, AssetData>,
     metadata: HashMap<AssetId, AssetMetadata>,
     path_to_id: HashMap<PathBuf, AssetId>,
+    /// Eviction order; lower priorities go first
+    priorities: HashMap<AssetId, f32>,
     total_memory: usize,
     max_memory: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
 }
 
 impl AssetCache {
@@ -20,27 +25,60 @@ impl AssetCache {
             assets: HashMap::new(),
             metadata: HashMap::new(),
             path_to_id: HashMap::new(),
+            priorities: HashMap::new(),
             total_memory: 0,
             max_memory: 1024 * 1024 * 1024, // 1GB default
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         }
     }
 
     pub fn insert(&mut self, id: AssetId, data: AssetData, metadata: AssetMetadata) {
+        let priority = self.priorities.get(&id).copied().unwrap_or(0.0);
+        self.insert_with_priority(id, data, metadata, priority);
+    }
+
+    /// Insert an asset that is evicted before anything of higher priority
+    pub fn insert_with_priority(&mut self, id: AssetId, data: AssetData, metadata: AssetMetadata, priority: f32) {
+        self.remove(id);
         let data_size = self.estimate_size(&data);
 
         // Check if we need to free memory
         if self.total_memory + data_size > self.max_memory {
-            self.evict_lru(data_size);
+            self.evict(data_size);
         }
 
         self.path_to_id.insert(metadata.file_path.clone(), id);
         self.assets.insert(id, data);
         self.metadata.insert(id, metadata);
+        self.priorities.insert(id, priority);
         self.total_memory += data_size;
     }
 
+    /// Change how long an asset survives eviction; false if it is not cached
+    pub fn set_priority(&mut self, id: AssetId, priority: f32) -> bool {
+        match self.priorities.get_mut(&id) {
+            Some(current) => {
+                *current = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_priority(&self, id: AssetId) -> Option<f32> {
+        self.priorities.get(&id).copied()
+    }
+
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        self.max_memory = max_memory;
+        if self.total_memory > max_memory {
+            self.evict(0);
+        }
+    }
+
     pub fn get_data(&self, id: AssetId) -> Option<&AssetData> {
-        self.assets.get(&id)
+        self.record_lookup(self.assets.get(&id))
     }
 
     pub fn get_metadata(&self, id: AssetId) -> Option<&AssetMetadata> {
@@ -48,7 +86,7 @@ impl AssetCache {
     }
 
     pub fn get_by_path(&self, path: &Path) -> Option<AssetId> {
-        self.path_to_id.get(path).copied()
+        self.record_lookup(self.path_to_id.get(path).copied())
     }
 
     pub fn update_data(&mut self, id: AssetId, data: AssetData) {
@@ -64,6 +102,7 @@ impl AssetCache {
     pub fn remove(&mut self, id: AssetId) -> bool {
         if let Some(data) = self.assets.remove(&id) {
             self.total_memory -= self.estimate_size(&data);
+            self.priorities.remove(&id);
 
             if let Some(metadata) = self.metadata.remove(&id) {
                 self.path_to_id.remove(&metadata.file_path);
@@ -80,10 +119,29 @@ impl AssetCache {
             asset_count: self.assets.len(),
             memory_usage: self.total_memory,
             max_memory: self.max_memory,
-            cache_hit_rate: 0.95, // Placeholder
+            cache_hit_rate: self.hit_rate(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Fraction of lookups served from the cache, zero before the first lookup
+    fn hit_rate(&self) -> f32 {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let total = hits + self.misses.load(Ordering::Relaxed);
+        if total == 0 {
+            0.0
+        } else {
+            hits as f32 / total as f32
         }
     }
 
+    fn record_lookup<T>(&self, found: Option<T>) -> Option<T> {
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
     fn estimate_size(&self, data: &AssetData) -> usize {
         match data {
             AssetData::Mesh(mesh) => {
@@ -97,22 +155,15 @@ impl AssetCache {
         }
     }
 
-    fn evict_lru(&mut self, needed_space: usize) {
-        // Simple LRU eviction - remove oldest assets until we have enough space
-        // In a real implementation, this would track access times
-        let mut freed_space = 0;
-        let mut to_remove = Vec::new();
+    /// Free space for `needed_space` more bytes, dropping the least important assets first
+    fn evict(&mut self, needed_space: usize) {
+        let mut candidates: Vec<(AssetId, f32)> = self.priorities.iter().map(|(&id, &priority)| (id, priority)).collect();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
 
-        for (&id, data) in &self.assets {
-            if freed_space >= needed_space {
+        for (id, _) in candidates {
+            if self.total_memory + needed_space <= self.max_memory {
                 break;
             }
-
-            freed_space += self.estimate_size(data);
-            to_remove.push(id);
-        }
-
-        for id in to_remove {
             self.remove(id);
         }
     }
@@ -124,4 +175,6 @@ pub struct CacheStats {
     pub memory_usage: usize,
     pub max_memory: usize,
     pub cache_hit_rate: f32,
+    pub hits: u64,
+    pub misses: u64,
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+// File: crates/storm-assets/src/error.rs
+// Typed asset errors with stable codes and retry classification
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::AssetId;
+
+/// Asset loading and processing failures
+#[derive(Error, Debug)]
+pub enum AssetError {
+    #[error("No loader available for extension: {extension}")]
+    NoLoader { extension: String },
+
+    #[error("No processor found: {name}")]
+    NoProcessor { name: String },
+
+    #[error("Asset not found: {asset_id}")]
+    NotFound { asset_id: AssetId },
+
+    #[error("Asset metadata not found: {asset_id}")]
+    MetadataMissing { asset_id: AssetId },
+
+    #[error("Failed to read {}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid {format} data: {message}")]
+    Decode { format: String, message: String },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl AssetError {
+    /// Stable numeric code, in the 5000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            AssetError::NoLoader { .. } => 5001,
+            AssetError::NoProcessor { .. } => 5002,
+            AssetError::NotFound { .. } => 5003,
+            AssetError::MetadataMissing { .. } => 5004,
+            AssetError::Io { .. } => 5005,
+            AssetError::Decode { .. } => 5006,
+            AssetError::Other(_) => 5000,
+        }
+    }
+
+    /// Transient file system errors may clear up on another attempt
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AssetError::Io { source, .. } => matches!(
+                source.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+
+    /// A missing asset only affects the objects that use it
+    pub fn is_fatal(&self) -> bool {
+        false
+    }
+
+    /// Asset the error relates to, if any
+    pub fn asset_id(&self) -> Option<AssetId> {
+        match self {
+            AssetError::NotFound { asset_id } | AssetError::MetadataMissing { asset_id } => Some(*asset_id),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// File: crates/storm-assets/src/j2k/dwt.rs
+// Reversible 5/3 and irreversible 9/7 lifting wavelets with symmetric extension
+
+const ALPHA: f32 = -1.586_134_3;
+const BETA: f32 = -0.052_980_12;
+const GAMMA: f32 = 0.882_911_1;
+const DELTA: f32 = 0.443_506_87;
+const K: f32 = 1.230_174_1;
+
+/// Index into a signal of `n` samples with whole-sample symmetric extension
+fn mirror(i: isize, n: usize) -> usize {
+    if n == 1 {
+        return 0;
+    }
+    let period = 2 * (n as isize - 1);
+    let m = i.rem_euclid(period);
+    (if m >= n as isize { period - m } else { m }) as usize
+}
+
+/// Add `weight * (left + right)` to every sample of one parity
+fn lift(signal: &mut [f32], parity: usize, weight: f32) {
+    let n = signal.len();
+    for i in (parity..n).step_by(2) {
+        let i = i as isize;
+        signal[i as usize] += weight * (signal[mirror(i - 1, n)] + signal[mirror(i + 1, n)]);
+    }
+}
+
+/// Inverse transform of one line; `first_odd` when the line starts at an odd absolute coordinate
+pub fn synthesize(signal: &mut [f32], first_odd: bool, reversible: bool) {
+    let n = signal.len();
+    let (low, high) = if first_odd { (1, 0) } else { (0, 1) };
+    if n == 1 {
+        if first_odd {
+            signal[0] /= 2.0;
+        }
+        return;
+    }
+    if reversible {
+        for i in (low..n).step_by(2) {
+            let i = i as isize;
+            let sum = signal[mirror(i - 1, n)] + signal[mirror(i + 1, n)];
+            signal[i as usize] -= ((sum + 2.0) / 4.0).floor();
+        }
+        for i in (high..n).step_by(2) {
+            let i = i as isize;
+            let sum = signal[mirror(i - 1, n)] + signal[mirror(i + 1, n)];
+            signal[i as usize] += (sum / 2.0).floor();
+        }
+    } else {
+        for i in (low..n).step_by(2) {
+            signal[i] *= K;
+        }
+        for i in (high..n).step_by(2) {
+            signal[i] /= K;
+        }
+        lift(signal, low, -DELTA);
+        lift(signal, high, -GAMMA);
+        lift(signal, low, -BETA);
+        lift(signal, high, -ALPHA);
+    }
+}
+
+/// Forward transform of one line, leaving low-pass samples at even and high-pass at odd absolute positions
+pub fn analyze(signal: &mut [f32], first_odd: bool, reversible: bool) {
+    let n = signal.len();
+    let (low, high) = if first_odd { (1, 0) } else { (0, 1) };
+    if n == 1 {
+        if first_odd {
+            signal[0] *= 2.0;
+        }
+        return;
+    }
+    if reversible {
+        for i in (high..n).step_by(2) {
+            let i = i as isize;
+            let sum = signal[mirror(i - 1, n)] + signal[mirror(i + 1, n)];
+            signal[i as usize] -= (sum / 2.0).floor();
+        }
+        for i in (low..n).step_by(2) {
+            let i = i as isize;
+            let sum = signal[mirror(i - 1, n)] + signal[mirror(i + 1, n)];
+            signal[i as usize] += ((sum + 2.0) / 4.0).floor();
+        }
+    } else {
+        lift(signal, high, ALPHA);
+        lift(signal, low, BETA);
+        lift(signal, high, GAMMA);
+        lift(signal, low, DELTA);
+        for i in (low..n).step_by(2) {
+            signal[i] /= K;
+        }
+        for i in (high..n).step_by(2) {
+            signal[i] *= K;
+        }
+    }
+}
+
+/// Samples of a resolution level as a row-major rectangle on its own grid
+#[derive(Debug, Clone, Default)]
+pub struct Plane {
+    pub x0: u32,
+    pub y0: u32,
+    pub width: usize,
+    pub height: usize,
+    pub samples: Vec<f32>,
+}
+
+impl Plane {
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Self {
+        let width = (x1 - x0) as usize;
+        let height = (y1 - y0) as usize;
+        Self { x0, y0, width, height, samples: vec![0.0; width * height] }
+    }
+
+    /// Run `transform` over every row and every column, rows first when `rows_first`
+    fn lines(&mut self, rows_first: bool, transform: impl Fn(&mut [f32], bool)) {
+        let mut column = vec![0.0; self.height];
+        let rows = |plane: &mut Self| {
+            for row in plane.samples.chunks_mut(plane.width.max(1)) {
+                transform(row, plane.x0 % 2 == 1);
+            }
+        };
+        if rows_first {
+            rows(self);
+        }
+        for x in 0..self.width {
+            for (y, sample) in column.iter_mut().enumerate() {
+                *sample = self.samples[y * self.width + x];
+            }
+            transform(&mut column, self.y0 % 2 == 1);
+            for (y, sample) in column.iter().enumerate() {
+                self.samples[y * self.width + x] = *sample;
+            }
+        }
+        if !rows_first {
+            rows(self);
+        }
+    }
+
+    /// Inverse 2D transform of interleaved subband samples
+    pub fn synthesize(&mut self, reversible: bool) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        self.lines(true, |line, first_odd| synthesize(line, first_odd, reversible));
+    }
+
+    /// Forward 2D transform, leaving subbands interleaved by coordinate parity
+    pub fn analyze(&mut self, reversible: bool) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        self.lines(false, |line, first_odd| analyze(line, first_odd, reversible));
+    }
+}
@@ -0,0 +1,982 @@
+// File: crates/storm-assets/src/j2k/mod.rs
+// JPEG 2000 codestreams, the texture format of OpenSim and Second Life grids
+// Baseline (Part 1) decoding with discard levels and quality layers, plus an encoder for uploads
+
+mod dwt;
+mod mq;
+mod t1;
+mod tile;
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{AssetError, TextureData, TextureFormat};
+use dwt::Plane;
+use tile::{ceil_shift, Resolution, Tile, TileComponent};
+
+pub use t1::style as block_style;
+
+const SOC: u16 = 0xFF4F;
+const SIZ: u16 = 0xFF51;
+const COD: u16 = 0xFF52;
+const COC: u16 = 0xFF53;
+const QCD: u16 = 0xFF5C;
+const QCC: u16 = 0xFF5D;
+const RGN: u16 = 0xFF5E;
+const POC: u16 = 0xFF5F;
+const PPM: u16 = 0xFF60;
+const PPT: u16 = 0xFF61;
+const SOT: u16 = 0xFF90;
+const SOD: u16 = 0xFF93;
+const EOC: u16 = 0xFFD9;
+
+const QUANT_NONE: u8 = 0;
+const QUANT_DERIVED: u8 = 1;
+const QUANT_EXPOUNDED: u8 = 2;
+
+const JP2_SIGNATURE: [u8; 12] = [0, 0, 0, 0x0C, b'j', b'P', b' ', b' ', 0x0D, 0x0A, 0x87, 0x0A];
+
+/// Bytes a viewer requests first; enough for the headers and the lowest resolution of most textures
+pub const FIRST_PACKET_SIZE: usize = 600;
+
+fn decode_error(message: impl Into<String>) -> anyhow::Error {
+    AssetError::Decode { format: "JPEG 2000".to_string(), message: message.into() }.into()
+}
+
+/// Packet progression order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Progression {
+    Lrcp,
+    Rlcp,
+    Rpcl,
+    Pcrl,
+    Cprl,
+}
+
+impl Progression {
+    fn from_u8(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => Progression::Lrcp,
+            1 => Progression::Rlcp,
+            2 => Progression::Rpcl,
+            3 => Progression::Pcrl,
+            4 => Progression::Cprl,
+            other => return Err(decode_error(format!("unknown progression order {}", other))),
+        })
+    }
+}
+
+/// Image layout from the main header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct J2kInfo {
+    pub width: u32,
+    pub height: u32,
+    pub components: u16,
+    pub levels: u8,
+    pub layers: u16,
+    pub reversible: bool,
+}
+
+impl J2kInfo {
+    /// Image size after dropping `discard_level` resolution levels
+    pub fn size_at(&self, discard_level: u8) -> (u32, u32) {
+        let discard = discard_level.min(self.levels) as u32;
+        (ceil_shift(self.width, discard), ceil_shift(self.height, discard))
+    }
+
+    /// Codestream bytes a viewer expects to need for a discard level, as used for range requests
+    pub fn estimated_bytes(&self, discard_level: u8) -> usize {
+        let (width, height) = self.size_at(discard_level);
+        let bytes = width as usize * height as usize * self.components as usize / 8;
+        bytes.max(FIRST_PACKET_SIZE)
+    }
+}
+
+/// How much of a codestream to decode
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// Resolution levels to drop; each halves the output size
+    pub discard_level: u8,
+    /// Quality layers to use, all when unset
+    pub max_layers: Option<u16>,
+}
+
+/// Encoder settings
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    pub levels: u8,
+    pub layers: u16,
+    /// Lossless 5/3 wavelet; otherwise 9/7 with `quantization_step`
+    pub reversible: bool,
+    pub quantization_step: f32,
+    pub progression: Progression,
+    /// log2 of the code-block width and height
+    pub code_block: (u8, u8),
+    pub block_style: u8,
+    /// log2 of the precinct width and height at every resolution, maximal when unset
+    pub precincts: Option<(u8, u8)>,
+    pub tile_size: Option<(u32, u32)>,
+    pub sop: bool,
+    pub eph: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            levels: 5,
+            layers: 1,
+            reversible: true,
+            quantization_step: 1.0,
+            progression: Progression::Lrcp,
+            code_block: (6, 6),
+            block_style: 0,
+            precincts: None,
+            tile_size: None,
+            sop: false,
+            eph: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ComponentInfo {
+    precision: u8,
+    dx: u8,
+    dy: u8,
+}
+
+/// Image and tile grid from SIZ
+#[derive(Debug, Clone)]
+struct ImageSize {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    tile_x0: u32,
+    tile_y0: u32,
+    tile_width: u32,
+    tile_height: u32,
+    components: Vec<ComponentInfo>,
+}
+
+impl ImageSize {
+    fn tiles_wide(&self) -> u32 {
+        (self.x1 - self.tile_x0).div_ceil(self.tile_width)
+    }
+
+    fn tile_count(&self) -> u32 {
+        self.tiles_wide() * (self.y1 - self.tile_y0).div_ceil(self.tile_height)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Quantization {
+    style: u8,
+    guard_bits: u8,
+    /// Exponent and mantissa per subband, LL first
+    steps: Vec<(u8, u16)>,
+}
+
+impl Quantization {
+    fn step(&self, band: usize) -> (u8, u16) {
+        if self.style == QUANT_DERIVED {
+            let (exponent, mantissa) = self.steps.first().copied().unwrap_or_default();
+            let level = if band == 0 { 0 } else { (band - 1) / 3 };
+            return (exponent.saturating_sub(level as u8), mantissa);
+        }
+        self.steps.get(band).or(self.steps.last()).copied().unwrap_or_default()
+    }
+
+    fn parse(reader: &mut Reader) -> Result<Self> {
+        let sq = reader.u8()?;
+        let style = sq & 0x1F;
+        let mut steps = Vec::new();
+        while reader.remaining() > 0 {
+            steps.push(match style {
+                QUANT_NONE => (reader.u8()? >> 3, 0),
+                QUANT_DERIVED | QUANT_EXPOUNDED => {
+                    let value = reader.u16()?;
+                    ((value >> 11) as u8, value & 0x7FF)
+                }
+                other => return Err(decode_error(format!("unknown quantization style {}", other))),
+            });
+        }
+        if steps.is_empty() {
+            return Err(decode_error("quantization marker without step sizes"));
+        }
+        Ok(Self { style, guard_bits: sq >> 5, steps })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.style | (self.guard_bits << 5));
+        for &(exponent, mantissa) in &self.steps {
+            if self.style == QUANT_NONE {
+                out.push(exponent << 3);
+            } else {
+                out.extend_from_slice(&(((exponent as u16) << 11) | mantissa).to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Per-component coding style from COD/COC and quantization from QCD/QCC
+#[derive(Debug, Clone)]
+struct ComponentCoding {
+    levels: u8,
+    cb_width: u8,
+    cb_height: u8,
+    block_style: u8,
+    reversible: bool,
+    precincts: Vec<(u8, u8)>,
+    quantization: Quantization,
+}
+
+impl Default for ComponentCoding {
+    fn default() -> Self {
+        Self {
+            levels: 5,
+            cb_width: 6,
+            cb_height: 6,
+            block_style: 0,
+            reversible: false,
+            precincts: vec![(15, 15); 6],
+            quantization: Quantization::default(),
+        }
+    }
+}
+
+impl ComponentCoding {
+    fn parse(&mut self, reader: &mut Reader, precincts_defined: bool) -> Result<()> {
+        self.levels = reader.u8()?;
+        let (cb_width, cb_height) = (reader.u8()?, reader.u8()?);
+        if self.levels > 32 || cb_width > 8 || cb_height > 8 || cb_width + cb_height > 8 {
+            return Err(decode_error("invalid decomposition or code-block size"));
+        }
+        self.cb_width = cb_width + 2;
+        self.cb_height = cb_height + 2;
+        self.block_style = reader.u8()?;
+        if self.block_style & 0xC0 != 0 {
+            return Err(decode_error("high-throughput code-blocks are not supported"));
+        }
+        self.reversible = reader.u8()? == 1;
+        self.precincts = (0..=self.levels)
+            .map(|_| {
+                if precincts_defined {
+                    reader.u8().map(|value| (value & 0x0F, value >> 4))
+                } else {
+                    Ok((15, 15))
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    fn write(&self, out: &mut Vec<u8>, precincts_defined: bool) {
+        out.extend_from_slice(&[
+            self.levels,
+            self.cb_width - 2,
+            self.cb_height - 2,
+            self.block_style,
+            self.reversible as u8,
+        ]);
+        if precincts_defined {
+            out.extend(self.precincts.iter().map(|&(x, y)| x | (y << 4)));
+        }
+    }
+}
+
+/// Coding parameters in effect for a tile
+#[derive(Debug, Clone)]
+struct Coding {
+    progression: Progression,
+    layers: u16,
+    mct: bool,
+    sop: bool,
+    eph: bool,
+    components: Vec<ComponentCoding>,
+}
+
+/// Big-endian cursor over marker segments
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + count)
+            .ok_or_else(|| decode_error("truncated codestream header"))?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    /// Next marker and its segment body
+    fn segment(&mut self) -> Result<(u16, &'a [u8])> {
+        let marker = self.u16()?;
+        if marker == SOD || marker == EOC {
+            return Ok((marker, &[]));
+        }
+        let length = self.u16()? as usize;
+        if length < 2 {
+            return Err(decode_error(format!("bad length for marker {:04X}", marker)));
+        }
+        Ok((marker, self.bytes(length - 2)?))
+    }
+}
+
+/// COD/COC/QCD/QCC segments of one header, applied by precedence once the header is read
+#[derive(Default)]
+struct HeaderMarkers<'a> {
+    cod: Option<&'a [u8]>,
+    coc: Vec<&'a [u8]>,
+    qcd: Option<&'a [u8]>,
+    qcc: Vec<&'a [u8]>,
+}
+
+impl<'a> HeaderMarkers<'a> {
+    /// Record a marker; false if the marker is not one this header collects
+    fn add(&mut self, marker: u16, body: &'a [u8]) -> Result<bool> {
+        match marker {
+            COD => self.cod = Some(body),
+            COC => self.coc.push(body),
+            QCD => self.qcd = Some(body),
+            QCC => self.qcc.push(body),
+            RGN | POC | PPM | PPT => {
+                return Err(decode_error(format!("marker {:04X} is not supported", marker)));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn apply(&self, coding: &mut Coding) -> Result<()> {
+        let count = coding.components.len();
+        let component = |reader: &mut Reader| -> Result<usize> {
+            let index = if count < 257 { reader.u8()? as usize } else { reader.u16()? as usize };
+            if index >= count {
+                return Err(decode_error(format!("component {} out of range", index)));
+            }
+            Ok(index)
+        };
+        if let Some(body) = self.cod {
+            let mut reader = Reader::new(body, 0);
+            let scod = reader.u8()?;
+            coding.sop = scod & 0x02 != 0;
+            coding.eph = scod & 0x04 != 0;
+            coding.progression = Progression::from_u8(reader.u8()?)?;
+            coding.layers = reader.u16()?;
+            coding.mct = reader.u8()? != 0;
+            let start = reader.pos;
+            for style in &mut coding.components {
+                reader.pos = start;
+                style.parse(&mut reader, scod & 0x01 != 0)?;
+            }
+        }
+        for body in &self.coc {
+            let mut reader = Reader::new(body, 0);
+            let index = component(&mut reader)?;
+            let scoc = reader.u8()?;
+            coding.components[index].parse(&mut reader, scoc & 0x01 != 0)?;
+        }
+        if let Some(body) = self.qcd {
+            let quantization = Quantization::parse(&mut Reader::new(body, 0))?;
+            for style in &mut coding.components {
+                style.quantization = quantization.clone();
+            }
+        }
+        for body in &self.qcc {
+            let mut reader = Reader::new(body, 0);
+            let index = component(&mut reader)?;
+            coding.components[index].quantization = Quantization::parse(&mut reader)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_size(body: &[u8]) -> Result<ImageSize> {
+    let mut reader = Reader::new(body, 0);
+    let _capabilities = reader.u16()?;
+    let (x1, y1, x0, y0) = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+    let (tile_width, tile_height, tile_x0, tile_y0) = (reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?);
+    let count = reader.u16()?;
+    let components = (0..count)
+        .map(|_| {
+            let ssiz = reader.u8()?;
+            Ok(ComponentInfo { precision: (ssiz & 0x7F) + 1, dx: reader.u8()?, dy: reader.u8()? })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if x1 <= x0 || y1 <= y0 || tile_width == 0 || tile_height == 0 || tile_x0 > x0 || tile_y0 > y0 || components.is_empty() {
+        return Err(decode_error("invalid image or tile size"));
+    }
+    if components.iter().any(|c| c.dx != 1 || c.dy != 1) {
+        return Err(decode_error("subsampled components are not supported"));
+    }
+    if components.iter().any(|c| c.precision > 16) {
+        return Err(decode_error("components deeper than 16 bits are not supported"));
+    }
+    Ok(ImageSize { x0, y0, x1, y1, tile_x0, tile_y0, tile_width, tile_height, components })
+}
+
+/// The raw codestream, unwrapping a JP2 file if needed
+fn codestream(data: &[u8]) -> Result<&[u8]> {
+    if data.starts_with(&SOC.to_be_bytes()) {
+        return Ok(data);
+    }
+    if !data.starts_with(&JP2_SIGNATURE) {
+        return Err(decode_error("not a JPEG 2000 codestream"));
+    }
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[pos..pos + 4].try_into()?) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let (header, length) = match length {
+            0 => (8, data.len() - pos),
+            1 => {
+                let extended = data.get(pos + 8..pos + 16).ok_or_else(|| decode_error("truncated JP2 box"))?;
+                (16, u64::from_be_bytes(extended.try_into()?) as usize)
+            }
+            _ => (8, length),
+        };
+        if length < header {
+            return Err(decode_error("bad JP2 box length"));
+        }
+        if kind == b"jp2c" {
+            return Ok(&data[pos + header..(pos + length).min(data.len())]);
+        }
+        pos += length;
+    }
+    Err(decode_error("JP2 file without a codestream box"))
+}
+
+/// Whether the data looks like a JPEG 2000 codestream or JP2 file
+pub fn is_j2k(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0x4F, 0xFF, 0x51]) || data.starts_with(&JP2_SIGNATURE)
+}
+
+/// Main header up to the first tile-part; returns the position of the first SOT
+fn parse_main(data: &[u8]) -> Result<(ImageSize, Coding, usize)> {
+    let mut reader = Reader::new(data, 0);
+    if reader.u16()? != SOC {
+        return Err(decode_error("missing start of codestream"));
+    }
+    let (marker, body) = reader.segment()?;
+    if marker != SIZ {
+        return Err(decode_error("SIZ must follow the start of codestream"));
+    }
+    let size = parse_size(body)?;
+    let mut markers = HeaderMarkers::default();
+    loop {
+        let start = reader.pos;
+        let (marker, body) = reader.segment()?;
+        match marker {
+            SOT => {
+                if markers.cod.is_none() || markers.qcd.is_none() {
+                    return Err(decode_error("main header without COD or QCD"));
+                }
+                let mut coding = Coding {
+                    progression: Progression::Lrcp,
+                    layers: 1,
+                    mct: false,
+                    sop: false,
+                    eph: false,
+                    components: vec![ComponentCoding::default(); size.components.len()],
+                };
+                markers.apply(&mut coding)?;
+                return Ok((size, coding, start));
+            }
+            EOC | SOD => return Err(decode_error("codestream without tiles")),
+            _ => {
+                markers.add(marker, body)?;
+            }
+        }
+    }
+}
+
+/// Header and concatenated tile-part bodies of one tile
+struct TileData {
+    coding: Coding,
+    data: Vec<u8>,
+}
+
+/// Collect tile-parts; a truncated stream keeps whatever arrived
+fn parse_tiles(data: &[u8], size: &ImageSize, coding: &Coding, mut pos: usize) -> Result<BTreeMap<u32, TileData>> {
+    let mut tiles: BTreeMap<u32, TileData> = BTreeMap::new();
+    while data.get(pos..pos + 2) == Some(&SOT.to_be_bytes()) {
+        let start = pos;
+        let mut reader = Reader::new(data, pos + 2);
+        let Ok(header) = reader.bytes(10) else { break };
+        let index = u16::from_be_bytes([header[2], header[3]]) as u32;
+        let length = u32::from_be_bytes(header[4..8].try_into()?) as usize;
+        if index >= size.tile_count() {
+            return Err(decode_error(format!("tile {} out of range", index)));
+        }
+
+        let mut markers = HeaderMarkers::default();
+        let mut complete = false;
+        while let Ok((marker, body)) = reader.segment() {
+            if marker == SOD {
+                complete = true;
+                break;
+            }
+            markers.add(marker, body)?;
+        }
+        if !complete {
+            break;
+        }
+        let end = if length == 0 { data.len() } else { (start + length).min(data.len()) };
+        let tile = tiles.entry(index).or_insert_with(|| TileData { coding: coding.clone(), data: Vec::new() });
+        markers.apply(&mut tile.coding)?;
+        tile.data.extend_from_slice(&data[reader.pos.min(end)..end]);
+        if length == 0 {
+            break;
+        }
+        pos = start + length;
+    }
+    Ok(tiles)
+}
+
+/// Read the image layout without decoding any tiles
+pub fn read_info(data: &[u8]) -> Result<J2kInfo> {
+    let (size, coding, _) = parse_main(codestream(data)?)?;
+    let style = &coding.components[0];
+    Ok(J2kInfo {
+        width: size.x1 - size.x0,
+        height: size.y1 - size.y0,
+        components: size.components.len() as u16,
+        levels: style.levels,
+        layers: coding.layers,
+        reversible: style.reversible,
+    })
+}
+
+/// Subband (or `None` for the lower resolution) and sample index holding a sample of an interleaved resolution
+fn subband_sample(resolution: &Resolution, lower: &Plane, x: u32, y: u32) -> (Option<usize>, usize) {
+    let (u, v) = (x >> 1, y >> 1);
+    let band = match (x & 1, y & 1) {
+        (0, 0) => return (None, (v - lower.y0) as usize * lower.width + (u - lower.x0) as usize),
+        (1, 0) => 0,
+        (0, _) => 1,
+        _ => 2,
+    };
+    let band_info = &resolution.bands[band];
+    (Some(band), (v - band_info.y0) as usize * band_info.width() + (u - band_info.x0) as usize)
+}
+
+/// Dequantized coefficients of one subband
+fn decode_band(band: &tile::Band, style: &ComponentCoding) -> Vec<f32> {
+    let mut coefficients = vec![0.0; band.width() * band.height()];
+    for block in band.precincts.iter().flat_map(|precinct| &precinct.blocks) {
+        if block.segments.is_empty() {
+            continue;
+        }
+        let planes = band.magnitude_bits.saturating_sub(block.zero_planes);
+        let values = t1::decode_block(block.width(), block.height(), band.orientation, style.block_style, planes, &block.segments);
+        for y in 0..block.height() {
+            let row = (block.y0 - band.y0) as usize + y;
+            for x in 0..block.width() {
+                let value = values[y * block.width() + x];
+                coefficients[row * band.width() + (block.x0 - band.x0) as usize + x] = if style.reversible {
+                    (value.signum() * (value.abs() >> 1)) as f32
+                } else {
+                    value as f32 * 0.5 * band.step
+                };
+            }
+        }
+    }
+    coefficients
+}
+
+/// Synthesize a tile-component up to resolution `target`
+fn reconstruct(component: &TileComponent, style: &ComponentCoding, target: usize) -> Plane {
+    let lowest = &component.resolutions[0];
+    let mut plane = Plane::new(lowest.x0, lowest.y0, lowest.x1, lowest.y1);
+    plane.samples = decode_band(&lowest.bands[0], style);
+    for resolution in &component.resolutions[1..=target] {
+        let bands: Vec<Vec<f32>> = resolution.bands.iter().map(|band| decode_band(band, style)).collect();
+        let mut next = Plane::new(resolution.x0, resolution.y0, resolution.x1, resolution.y1);
+        for y in 0..next.height {
+            for x in 0..next.width {
+                let (band, index) = subband_sample(resolution, &plane, next.x0 + x as u32, next.y0 + y as u32);
+                next.samples[y * next.width + x] = match band {
+                    Some(band) => bands[band][index],
+                    None => plane.samples[index],
+                };
+            }
+        }
+        next.synthesize(style.reversible);
+        plane = next;
+    }
+    plane
+}
+
+fn inverse_mct(planes: &mut [Plane], reversible: bool) {
+    let [y, cb, cr] = &mut planes[..3] else { return };
+    for i in 0..y.samples.len().min(cb.samples.len()).min(cr.samples.len()) {
+        let (luma, blue, red) = (y.samples[i], cb.samples[i], cr.samples[i]);
+        let (r, g, b) = if reversible {
+            let g = luma - ((blue + red) / 4.0).floor();
+            (red + g, g, blue + g)
+        } else {
+            (luma + 1.402 * red, luma - 0.344_13 * blue - 0.714_14 * red, luma + 1.772 * blue)
+        };
+        y.samples[i] = r;
+        cb.samples[i] = g;
+        cr.samples[i] = b;
+    }
+}
+
+fn forward_mct(planes: &mut [Plane], reversible: bool) {
+    let [y, cb, cr] = &mut planes[..3] else { return };
+    for i in 0..y.samples.len() {
+        let (r, g, b) = (y.samples[i], cb.samples[i], cr.samples[i]);
+        let (luma, blue, red) = if reversible {
+            (((r + 2.0 * g + b) / 4.0).floor(), b - g, r - g)
+        } else {
+            (
+                0.299 * r + 0.587 * g + 0.114 * b,
+                -0.168_75 * r - 0.331_26 * g + 0.5 * b,
+                0.5 * r - 0.418_69 * g - 0.081_31 * b,
+            )
+        };
+        y.samples[i] = luma;
+        cb.samples[i] = blue;
+        cr.samples[i] = red;
+    }
+}
+
+/// Decoded image samples, one plane per component at the output size
+struct Canvas {
+    x0: u32,
+    y0: u32,
+    width: usize,
+    height: usize,
+    planes: Vec<Vec<u16>>,
+}
+
+fn decode_tile(size: &ImageSize, tile: &TileData, index: u32, options: &DecodeOptions, discard: u32, canvas: &mut Canvas) {
+    let coding = &tile.coding;
+    let mut geometry = Tile::new(size, coding, index);
+    let layers = options.max_layers.unwrap_or(u16::MAX);
+    let mut pos = 0;
+    for packet in geometry.packets(size, coding) {
+        let levels = coding.components[packet.component].levels as u32;
+        let keep = packet.layer < layers && packet.resolution as u32 + discard <= levels;
+        match geometry.read_packet(&tile.data, pos, packet, coding, keep) {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+
+    let mut planes: Vec<Plane> = geometry
+        .components
+        .iter()
+        .zip(&coding.components)
+        .map(|(component, style)| reconstruct(component, style, (style.levels as u32 - discard) as usize))
+        .collect();
+    if coding.mct && planes.len() >= 3 {
+        inverse_mct(&mut planes, coding.components[0].reversible);
+    }
+    for (c, plane) in planes.iter().enumerate() {
+        let info = size.components[c];
+        let offset = (1u32 << (info.precision - 1)) as f32;
+        let max = ((1u32 << info.precision) - 1) as f32;
+        for y in 0..plane.height {
+            let row = (plane.y0 + y as u32 - canvas.y0) as usize;
+            for x in 0..plane.width {
+                let column = (plane.x0 + x as u32 - canvas.x0) as usize;
+                if row < canvas.height && column < canvas.width {
+                    let value = (plane.samples[y * plane.width + x] + offset).round().clamp(0.0, max);
+                    canvas.planes[c][row * canvas.width + column] = value as u16;
+                }
+            }
+        }
+    }
+}
+
+/// Decode a codestream (or JP2 file) into 8-bit texture data
+pub fn decode(data: &[u8], options: &DecodeOptions) -> Result<TextureData> {
+    let stream = codestream(data)?;
+    let (size, coding, first_tile) = parse_main(stream)?;
+    let tiles = parse_tiles(stream, &size, &coding, first_tile)?;
+
+    let levels = tiles
+        .values()
+        .flat_map(|tile| tile.coding.components.iter())
+        .chain(coding.components.iter())
+        .map(|style| style.levels)
+        .min()
+        .unwrap_or(0);
+    let discard = options.discard_level.min(levels) as u32;
+    let (x0, y0) = (ceil_shift(size.x0, discard), ceil_shift(size.y0, discard));
+    let (width, height) = (
+        (ceil_shift(size.x1, discard) - x0) as usize,
+        (ceil_shift(size.y1, discard) - y0) as usize,
+    );
+    let mut canvas = Canvas {
+        x0,
+        y0,
+        width,
+        height,
+        planes: size
+            .components
+            .iter()
+            .map(|info| vec![1u16 << (info.precision - 1); width * height])
+            .collect(),
+    };
+    for (&index, tile) in &tiles {
+        decode_tile(&size, tile, index, options, discard, &mut canvas);
+    }
+
+    let eight_bit = |c: usize, i: usize| -> u8 {
+        let precision = size.components[c].precision as u32;
+        let value = canvas.planes[c][i] as u32;
+        (if precision > 8 { value >> (precision - 8) } else { value << (8 - precision) }) as u8
+    };
+    let pixels = width * height;
+    let (format, data) = match size.components.len() {
+        1 => (TextureFormat::R8, (0..pixels).map(|i| eight_bit(0, i)).collect()),
+        2 => (
+            TextureFormat::RGBA8,
+            (0..pixels)
+                .flat_map(|i| {
+                    let grey = eight_bit(0, i);
+                    [grey, grey, grey, eight_bit(1, i)]
+                })
+                .collect(),
+        ),
+        3 => (TextureFormat::RGB8, (0..pixels).flat_map(|i| [0, 1, 2].map(|c| eight_bit(c, i))).collect()),
+        _ => (TextureFormat::RGBA8, (0..pixels).flat_map(|i| [0, 1, 2, 3].map(|c| eight_bit(c, i))).collect()),
+    };
+    Ok(TextureData { width: width as u32, height: height as u32, format, data, mip_levels: 1 })
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u16, body: &[u8]) {
+    out.extend_from_slice(&marker.to_be_bytes());
+    out.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(body);
+}
+
+/// Quantize one tile-component into subbands, `[resolution][band]`
+fn analyze_component(component: &TileComponent, mut plane: Plane, reversible: bool) -> Vec<Vec<Vec<i32>>> {
+    let mut subbands = vec![Vec::new(); component.resolutions.len()];
+    for r in (1..component.resolutions.len()).rev() {
+        let resolution = &component.resolutions[r];
+        let lower = &component.resolutions[r - 1];
+        plane.analyze(reversible);
+        let mut low = Plane::new(lower.x0, lower.y0, lower.x1, lower.y1);
+        let mut bands: Vec<Vec<f32>> = resolution.bands.iter().map(|band| vec![0.0; band.width() * band.height()]).collect();
+        for y in 0..plane.height {
+            for x in 0..plane.width {
+                let value = plane.samples[y * plane.width + x];
+                match subband_sample(resolution, &low, plane.x0 + x as u32, plane.y0 + y as u32) {
+                    (Some(band), index) => bands[band][index] = value,
+                    (None, index) => low.samples[index] = value,
+                }
+            }
+        }
+        subbands[r] = bands.into_iter().zip(&resolution.bands).map(|(values, band)| quantize(&values, band.step)).collect();
+        plane = low;
+    }
+    subbands[0] = vec![quantize(&plane.samples, component.resolutions[0].bands[0].step)];
+    subbands
+}
+
+fn quantize(values: &[f32], step: f32) -> Vec<i32> {
+    values.iter().map(|value| ((value.abs() / step).floor() as i32) * value.signum() as i32).collect()
+}
+
+/// Encode 8-bit texture data as a codestream
+pub fn encode(texture: &TextureData, options: &EncodeOptions) -> Result<Vec<u8>> {
+    let components = match texture.format {
+        TextureFormat::R8 => 1,
+        TextureFormat::RGB8 => 3,
+        TextureFormat::RGBA8 => 4,
+        ref other => return Err(anyhow!("cannot encode {:?} textures as JPEG 2000", other)),
+    };
+    let (width, height) = (texture.width, texture.height);
+    if width == 0 || height == 0 || texture.data.len() < (width * height) as usize * components {
+        return Err(anyhow!("texture data does not match its {}x{} size", width, height));
+    }
+    if options.layers == 0 || options.code_block.0 + options.code_block.1 > 12 || options.code_block.0.min(options.code_block.1) < 2 {
+        return Err(anyhow!("invalid layer count or code-block size"));
+    }
+    let levels = options.levels.min((u32::BITS - 1 - width.min(height).leading_zeros()) as u8);
+    let (tile_width, tile_height) = options.tile_size.unwrap_or((width, height));
+    let size = ImageSize {
+        x0: 0,
+        y0: 0,
+        x1: width,
+        y1: height,
+        tile_x0: 0,
+        tile_y0: 0,
+        tile_width: tile_width.max(1),
+        tile_height: tile_height.max(1),
+        components: vec![ComponentInfo { precision: 8, dx: 1, dy: 1 }; components],
+    };
+
+    let steps = (0..=3 * levels as usize)
+        .map(|band| {
+            let gain = if band == 0 { 0 } else { [1, 1, 2][(band - 1) % 3] };
+            let range = 8 + gain;
+            if options.reversible {
+                return (range, 0);
+            }
+            let exponent = options.quantization_step.max(1e-6).log2().floor();
+            let mantissa = ((options.quantization_step / 2f32.powf(exponent) - 1.0) * 2048.0).round() as u16;
+            ((range as f32 - exponent).clamp(0.0, 31.0) as u8, mantissa.min(2047))
+        })
+        .collect();
+    let mut quantization = Quantization {
+        style: if options.reversible { QUANT_NONE } else { QUANT_EXPOUNDED },
+        guard_bits: 2,
+        steps,
+    };
+    let precincts_defined = options.precincts.is_some();
+    let style = ComponentCoding {
+        levels,
+        cb_width: options.code_block.0,
+        cb_height: options.code_block.1,
+        block_style: options.block_style,
+        reversible: options.reversible,
+        precincts: vec![options.precincts.unwrap_or((15, 15)); levels as usize + 1],
+        quantization: quantization.clone(),
+    };
+    let mut coding = Coding {
+        progression: options.progression,
+        layers: options.layers,
+        mct: components >= 3,
+        sop: options.sop,
+        eph: options.eph,
+        components: vec![style; components],
+    };
+
+    // Transform every tile first; the guard bits must cover the largest coefficient anywhere
+    let mut quantized = Vec::new();
+    for index in 0..size.tile_count() {
+        let geometry = Tile::new(&size, &coding, index);
+        let mut planes: Vec<Plane> = (0..components)
+            .map(|c| {
+                let mut plane = Plane::new(geometry.x0, geometry.y0, geometry.x1, geometry.y1);
+                for y in 0..plane.height {
+                    for x in 0..plane.width {
+                        let pixel = (geometry.y0 as usize + y) * width as usize + geometry.x0 as usize + x;
+                        plane.samples[y * plane.width + x] = texture.data[pixel * components + c] as f32 - 128.0;
+                    }
+                }
+                plane
+            })
+            .collect();
+        if coding.mct {
+            forward_mct(&mut planes, options.reversible);
+        }
+        let tile: Vec<_> = geometry
+            .components
+            .iter()
+            .zip(planes)
+            .map(|(component, plane)| analyze_component(component, plane, options.reversible))
+            .collect();
+        quantized.push(tile);
+    }
+    for (band, &(exponent, _)) in quantization.steps.iter().enumerate() {
+        let (resolution, offset) = if band == 0 { (0, 0) } else { ((band - 1) / 3 + 1, (band - 1) % 3) };
+        let largest = quantized
+            .iter()
+            .flatten()
+            .flat_map(|component| component[resolution][offset].iter())
+            .map(|value| value.unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        let needed = (u32::BITS - largest.leading_zeros()) as i32 - exponent as i32 + 1;
+        quantization.guard_bits = quantization.guard_bits.max(needed.clamp(0, 7) as u8);
+    }
+    for style in &mut coding.components {
+        style.quantization = quantization.clone();
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&SOC.to_be_bytes());
+    let mut siz = Vec::new();
+    siz.extend_from_slice(&0u16.to_be_bytes());
+    for value in [width, height, 0, 0, size.tile_width, size.tile_height, 0, 0] {
+        siz.extend_from_slice(&value.to_be_bytes());
+    }
+    siz.extend_from_slice(&(components as u16).to_be_bytes());
+    for _ in 0..components {
+        siz.extend_from_slice(&[7, 1, 1]);
+    }
+    write_segment(&mut out, SIZ, &siz);
+    let mut cod = vec![precincts_defined as u8 | (coding.sop as u8) << 1 | (coding.eph as u8) << 2, options.progression as u8];
+    cod.extend_from_slice(&coding.layers.to_be_bytes());
+    cod.push(coding.mct as u8);
+    coding.components[0].write(&mut cod, precincts_defined);
+    write_segment(&mut out, COD, &cod);
+    let mut qcd = Vec::new();
+    quantization.write(&mut qcd);
+    write_segment(&mut out, QCD, &qcd);
+
+    for (index, bands) in quantized.into_iter().enumerate() {
+        let mut geometry = Tile::new(&size, &coding, index as u32);
+        for (component, component_bands) in geometry.components.iter_mut().zip(&bands) {
+            for (resolution, resolution_bands) in component.resolutions.iter_mut().zip(component_bands) {
+                for (band, values) in resolution.bands.iter_mut().zip(resolution_bands) {
+                    let band_width = band.width();
+                    for precinct in &mut band.precincts {
+                        for (j, block) in precinct.blocks.iter_mut().enumerate() {
+                            let mut coefficients = Vec::with_capacity(block.width() * block.height());
+                            for y in block.y0..block.y1 {
+                                let row = (y - band.y0) as usize * band_width;
+                                coefficients.extend_from_slice(
+                                    &values[row + (block.x0 - band.x0) as usize..row + (block.x1 - band.x0) as usize],
+                                );
+                            }
+                            block.encoded = t1::encode_block(block.width(), block.height(), band.orientation, options.block_style, &coefficients);
+                            let passes = block.encoded.pass_ends.len() as u32;
+                            block.layer_passes = (1..=options.layers as u32).map(|l| (passes * l).div_ceil(options.layers as u32)).collect();
+                            let first_layer = block.layer_passes.iter().position(|&p| p > 0).unwrap_or(options.layers as usize);
+                            precinct.inclusion.set_value(j, first_layer as u32);
+                            precinct.zero_planes.set_value(j, band.magnitude_bits.saturating_sub(block.encoded.planes));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut body = Vec::new();
+        for (sequence, packet) in geometry.packets(&size, &coding).into_iter().enumerate() {
+            geometry.write_packet(&mut body, packet, &coding, sequence);
+        }
+        out.extend_from_slice(&SOT.to_be_bytes());
+        out.extend_from_slice(&10u16.to_be_bytes());
+        out.extend_from_slice(&(index as u16).to_be_bytes());
+        out.extend_from_slice(&(body.len() as u32 + 14).to_be_bytes());
+        out.extend_from_slice(&[0, 1]);
+        out.extend_from_slice(&SOD.to_be_bytes());
+        out.extend_from_slice(&body);
+    }
+    out.extend_from_slice(&EOC.to_be_bytes());
+    Ok(out)
+}
@@ -0,0 +1,388 @@
+// File: crates/storm-assets/src/j2k/mq.rs
+// MQ arithmetic coder and raw bypass bits used by code-block coding
+
+/// Probability states: (Qe, next state after MPS, next state after LPS, swap MPS on LPS)
+const STATES: [(u32, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true),
+    (0x3401, 2, 6, false),
+    (0x1801, 3, 9, false),
+    (0x0AC1, 4, 12, false),
+    (0x0521, 5, 29, false),
+    (0x0221, 38, 33, false),
+    (0x5601, 7, 6, true),
+    (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false),
+    (0x3801, 10, 14, false),
+    (0x3001, 11, 17, false),
+    (0x2401, 12, 18, false),
+    (0x1C01, 13, 20, false),
+    (0x1601, 29, 21, false),
+    (0x5601, 15, 14, true),
+    (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false),
+    (0x4801, 18, 16, false),
+    (0x3801, 19, 17, false),
+    (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false),
+    (0x2801, 22, 19, false),
+    (0x2401, 23, 20, false),
+    (0x2201, 24, 21, false),
+    (0x1C01, 25, 22, false),
+    (0x1801, 26, 23, false),
+    (0x1601, 27, 24, false),
+    (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false),
+    (0x1101, 30, 27, false),
+    (0x0AC1, 31, 28, false),
+    (0x09C1, 32, 29, false),
+    (0x08A1, 33, 30, false),
+    (0x0521, 34, 31, false),
+    (0x0441, 35, 32, false),
+    (0x02A1, 36, 33, false),
+    (0x0221, 37, 34, false),
+    (0x0141, 38, 35, false),
+    (0x0111, 39, 36, false),
+    (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false),
+    (0x0025, 42, 39, false),
+    (0x0015, 43, 40, false),
+    (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false),
+    (0x0001, 45, 43, false),
+    (0x5601, 46, 46, false),
+];
+
+/// Zero coding 0-8, sign coding 9-13, refinement 14-16, run length and uniform
+pub const CONTEXTS: usize = 19;
+pub const CTX_RUN_LENGTH: usize = 17;
+pub const CTX_UNIFORM: usize = 18;
+
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    state: u8,
+    mps: u32,
+}
+
+fn initial_contexts() -> [Context; CONTEXTS] {
+    let mut contexts = [Context { state: 0, mps: 0 }; CONTEXTS];
+    contexts[0].state = 4;
+    contexts[CTX_RUN_LENGTH].state = 3;
+    contexts[CTX_UNIFORM].state = 46;
+    contexts
+}
+
+/// Move a context to its next state after coding an MPS or LPS
+fn adapt(context: &mut Context, mps_path: bool, switch: bool, nmps: u8, nlps: u8) {
+    if mps_path {
+        context.state = nmps;
+    } else {
+        if switch {
+            context.mps = 1 - context.mps;
+        }
+        context.state = nlps;
+    }
+}
+
+pub struct MqDecoder<'a> {
+    data: &'a [u8],
+    bp: usize,
+    a: u32,
+    c: u32,
+    ct: u32,
+    contexts: [Context; CONTEXTS],
+}
+
+impl<'a> MqDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut decoder = Self {
+            data,
+            bp: 0,
+            a: 0,
+            c: 0,
+            ct: 0,
+            contexts: initial_contexts(),
+        };
+        decoder.start(data);
+        decoder
+    }
+
+    /// Begin a new terminated segment; context states carry over
+    pub fn start(&mut self, data: &'a [u8]) {
+        self.data = data;
+        self.bp = 0;
+        self.c = (self.byte(0) as u32) << 16;
+        self.byte_in();
+        self.c <<= 7;
+        self.ct -= 7;
+        self.a = 0x8000;
+    }
+
+    pub fn reset_contexts(&mut self) {
+        self.contexts = initial_contexts();
+    }
+
+    /// Bytes past the segment read as a marker, which feeds 1 bits
+    fn byte(&self, index: usize) -> u8 {
+        self.data.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn byte_in(&mut self) {
+        if self.byte(self.bp) == 0xFF {
+            let next = self.byte(self.bp + 1);
+            if next > 0x8F {
+                self.c = self.c.wrapping_add(0xFF00);
+                self.ct = 8;
+            } else {
+                self.bp += 1;
+                self.c = self.c.wrapping_add((next as u32) << 9);
+                self.ct = 7;
+            }
+        } else {
+            self.bp += 1;
+            self.c = self.c.wrapping_add((self.byte(self.bp) as u32) << 8);
+            self.ct = 8;
+        }
+    }
+
+    fn renormalize(&mut self) {
+        loop {
+            if self.ct == 0 {
+                self.byte_in();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+    }
+
+    pub fn decode(&mut self, cx: usize) -> u32 {
+        let mut context = self.contexts[cx];
+        let (qe, nmps, nlps, switch) = STATES[context.state as usize];
+        self.a -= qe;
+        if (self.c >> 16) < qe {
+            // LPS exchange: the smaller interval may still be the MPS
+            let mps_path = self.a < qe;
+            self.a = qe;
+            let bit = if mps_path { context.mps } else { 1 - context.mps };
+            adapt(&mut context, mps_path, switch, nmps, nlps);
+            self.contexts[cx] = context;
+            self.renormalize();
+            bit
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 != 0 {
+                return context.mps;
+            }
+            let mps_path = self.a >= qe;
+            let bit = if mps_path { context.mps } else { 1 - context.mps };
+            adapt(&mut context, mps_path, switch, nmps, nlps);
+            self.contexts[cx] = context;
+            self.renormalize();
+            bit
+        }
+    }
+}
+
+pub struct MqEncoder {
+    /// Output with a leading placeholder for the byte before the segment
+    out: Vec<u8>,
+    a: u32,
+    c: u32,
+    ct: u32,
+    contexts: [Context; CONTEXTS],
+}
+
+impl MqEncoder {
+    pub fn new() -> Self {
+        Self {
+            out: vec![0],
+            a: 0x8000,
+            c: 0,
+            ct: 12,
+            contexts: initial_contexts(),
+        }
+    }
+
+    pub fn reset_contexts(&mut self) {
+        self.contexts = initial_contexts();
+    }
+
+    /// Bytes produced so far in the current segment
+    pub fn len(&self) -> usize {
+        self.out.len() - 1
+    }
+
+    pub fn encode(&mut self, bit: u32, cx: usize) {
+        let mut context = self.contexts[cx];
+        let (qe, nmps, nlps, switch) = STATES[context.state as usize];
+        self.a -= qe;
+        if bit == context.mps {
+            if self.a & 0x8000 == 0 {
+                if self.a < qe {
+                    self.a = qe;
+                } else {
+                    self.c += qe;
+                }
+                adapt(&mut context, true, switch, nmps, nlps);
+                self.contexts[cx] = context;
+                self.renormalize();
+            } else {
+                self.c += qe;
+            }
+        } else {
+            if self.a < qe {
+                self.c += qe;
+            } else {
+                self.a = qe;
+            }
+            adapt(&mut context, false, switch, nmps, nlps);
+            self.contexts[cx] = context;
+            self.renormalize();
+        }
+    }
+
+    fn renormalize(&mut self) {
+        loop {
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.ct == 0 {
+                self.byte_out();
+            }
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+    }
+
+    fn byte_out(&mut self) {
+        let last = self.out.len() - 1;
+        if self.out[last] == 0xFF {
+            self.emit_stuffed();
+        } else if self.c < 0x8000000 {
+            self.out.push((self.c >> 19) as u8);
+            self.c &= 0x7FFFF;
+            self.ct = 8;
+        } else {
+            // Carry into the byte already written
+            self.out[last] = self.out[last].wrapping_add(1);
+            if self.out[last] == 0xFF {
+                self.c &= 0x7FFFFFF;
+                self.emit_stuffed();
+            } else {
+                self.out.push((self.c >> 19) as u8);
+                self.c &= 0x7FFFF;
+                self.ct = 8;
+            }
+        }
+    }
+
+    /// After 0xFF only seven bits go into the next byte
+    fn emit_stuffed(&mut self) {
+        self.out.push((self.c >> 20) as u8);
+        self.c &= 0xFFFFF;
+        self.ct = 7;
+    }
+
+    /// Terminate the segment and return its bytes; the coder is ready for the next segment
+    pub fn flush(&mut self) -> Vec<u8> {
+        let temp = self.c + self.a;
+        self.c |= 0xFFFF;
+        if self.c >= temp {
+            self.c -= 0x8000;
+        }
+        self.c <<= self.ct;
+        self.byte_out();
+        self.c <<= self.ct;
+        self.byte_out();
+
+        let mut bytes = self.out.split_off(1);
+        if bytes.last() == Some(&0xFF) {
+            bytes.pop();
+        }
+        self.out = vec![0];
+        self.a = 0x8000;
+        self.c = 0;
+        self.ct = 12;
+        bytes
+    }
+}
+
+/// Uncoded bits of bypassed passes, with the same 0xFF stuffing as the MQ coder
+pub struct RawDecoder<'a> {
+    data: &'a [u8],
+    bp: usize,
+    c: u32,
+    ct: u32,
+}
+
+impl<'a> RawDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, bp: 0, c: 0, ct: 0 }
+    }
+
+    pub fn decode(&mut self) -> u32 {
+        if self.ct == 0 {
+            let next = self.data.get(self.bp).copied().unwrap_or(0xFF);
+            if self.c == 0xFF {
+                if next > 0x8F {
+                    self.c = 0xFF;
+                    self.ct = 8;
+                } else {
+                    self.c = next as u32;
+                    self.bp += 1;
+                    self.ct = 7;
+                }
+            } else {
+                self.c = next as u32;
+                self.bp += 1;
+                self.ct = 8;
+            }
+        }
+        self.ct -= 1;
+        (self.c >> self.ct) & 1
+    }
+}
+
+#[derive(Default)]
+pub struct RawEncoder {
+    out: Vec<u8>,
+    byte: u8,
+    /// Free bits left in `byte`
+    free: u32,
+    used: bool,
+}
+
+impl RawEncoder {
+    pub fn new() -> Self {
+        Self { free: 8, ..Default::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.out.len() + self.used as usize
+    }
+
+    pub fn encode(&mut self, bit: u32) {
+        self.free -= 1;
+        self.byte |= (bit as u8) << self.free;
+        self.used = true;
+        if self.free == 0 {
+            self.out.push(self.byte);
+            self.free = if self.byte == 0xFF { 7 } else { 8 };
+            self.byte = 0;
+            self.used = false;
+        }
+    }
+
+    pub fn flush(&mut self) -> Vec<u8> {
+        if self.used {
+            self.out.push(self.byte);
+        }
+        let bytes = std::mem::take(&mut self.out);
+        *self = Self::new();
+        bytes
+    }
+}
@@ -0,0 +1,463 @@
+// File: crates/storm-assets/src/j2k/t1.rs
+// Code-block bit-plane coding passes, shared by the decoder and the encoder
+
+use super::mq::{MqDecoder, MqEncoder, RawDecoder, RawEncoder, CTX_RUN_LENGTH, CTX_UNIFORM};
+
+/// Code-block style flags from COD/COC
+pub mod style {
+    /// Raw (bypassed) significance and refinement passes after the fourth bit-plane
+    pub const BYPASS: u8 = 0x01;
+    /// Reset context probabilities after every pass
+    pub const RESET: u8 = 0x02;
+    /// Terminate the coder after every pass
+    pub const TERMALL: u8 = 0x04;
+    /// Stripe-causal contexts
+    pub const VCAUSAL: u8 = 0x08;
+    /// Predictable termination; decoders can ignore it
+    pub const PTERM: u8 = 0x10;
+    /// Segmentation symbol after each cleanup pass
+    pub const SEGSYM: u8 = 0x20;
+}
+
+const SIGNIFICANT: u8 = 0x01;
+const NEGATIVE: u8 = 0x02;
+const VISITED: u8 = 0x04;
+const REFINED: u8 = 0x08;
+/// Sign of the sample being encoded
+const SOURCE_NEGATIVE: u8 = 0x10;
+
+/// Subband orientation; HL is high-pass horizontally, LH vertically
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    LL,
+    HL,
+    LH,
+    HH,
+}
+
+impl Orientation {
+    /// Offsets of the band's samples in the interleaved resolution
+    pub fn offsets(self) -> (u32, u32) {
+        match self {
+            Orientation::LL => (0, 0),
+            Orientation::HL => (1, 0),
+            Orientation::LH => (0, 1),
+            Orientation::HH => (1, 1),
+        }
+    }
+
+    /// log2 of the band's nominal gain
+    pub fn gain(self) -> u32 {
+        match self {
+            Orientation::LL => 0,
+            Orientation::HL | Orientation::LH => 1,
+            Orientation::HH => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PassKind {
+    Significance,
+    Refinement,
+    Cleanup,
+}
+
+fn pass_kind(pass: u32) -> PassKind {
+    match pass.checked_sub(1).map(|p| p % 3) {
+        Some(0) => PassKind::Significance,
+        Some(1) => PassKind::Refinement,
+        _ => PassKind::Cleanup,
+    }
+}
+
+/// Whether a pass is written as raw bits instead of through the MQ coder
+pub fn is_raw(block_style: u8, pass: u32) -> bool {
+    block_style & style::BYPASS != 0 && pass >= 10 && pass_kind(pass) != PassKind::Cleanup
+}
+
+/// Whether a codeword segment ends after a pass
+pub fn ends_segment(block_style: u8, pass: u32) -> bool {
+    if block_style & style::TERMALL != 0 {
+        return true;
+    }
+    block_style & style::BYPASS != 0 && (pass == 9 || (pass >= 10 && !(pass - 10).is_multiple_of(3)))
+}
+
+/// Zero coding context from significant horizontal, vertical and diagonal neighbours
+fn zero_context(orientation: Orientation, h: u32, v: u32, d: u32) -> usize {
+    let (h, v) = if orientation == Orientation::LH { (v, h) } else { (h, v) };
+    let context = match orientation {
+        Orientation::HH => match d {
+            0 => (h + v).min(2),
+            1 => 3 + (h + v).min(2),
+            2 => if h + v == 0 { 6 } else { 7 },
+            _ => 8,
+        },
+        _ => match h {
+            0 => match v {
+                0 => d.min(2),
+                1 => 3,
+                _ => 4,
+            },
+            1 => match (v, d) {
+                (0, 0) => 5,
+                (0, _) => 6,
+                _ => 7,
+            },
+            _ => 8,
+        },
+    };
+    context as usize
+}
+
+/// Where coded symbols come from (decoding) or go to (encoding)
+trait Symbols {
+    /// Code `bit` in an MQ context; decoders ignore `bit` and return what they read
+    fn mq(&mut self, bit: u32, context: usize) -> u32;
+    fn raw(&mut self, bit: u32) -> u32;
+}
+
+struct Decoding<'s, 'a> {
+    mq: &'s mut MqDecoder<'a>,
+    raw: &'s mut RawDecoder<'a>,
+}
+
+impl Symbols for Decoding<'_, '_> {
+    fn mq(&mut self, _bit: u32, context: usize) -> u32 {
+        self.mq.decode(context)
+    }
+
+    fn raw(&mut self, _bit: u32) -> u32 {
+        self.raw.decode()
+    }
+}
+
+struct Encoding<'s> {
+    mq: &'s mut MqEncoder,
+    raw: &'s mut RawEncoder,
+}
+
+impl Symbols for Encoding<'_> {
+    fn mq(&mut self, bit: u32, context: usize) -> u32 {
+        self.mq.encode(bit, context);
+        bit
+    }
+
+    fn raw(&mut self, bit: u32) -> u32 {
+        self.raw.encode(bit);
+        bit
+    }
+}
+
+/// Sample state of one code-block, with a one sample border
+struct Block {
+    width: usize,
+    height: usize,
+    orientation: Orientation,
+    style: u8,
+    flags: Vec<u8>,
+    /// Reconstructed magnitudes at twice their scale, so the half step below the last decoded plane fits
+    values: Vec<u64>,
+    /// Magnitudes being encoded; empty when decoding
+    source: Vec<u32>,
+}
+
+impl Block {
+    fn new(width: usize, height: usize, orientation: Orientation, style: u8) -> Self {
+        Self {
+            width,
+            height,
+            orientation,
+            style,
+            flags: vec![0; (width + 2) * (height + 2)],
+            values: vec![0; width * height],
+            source: Vec::new(),
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + 1) * (self.width + 2) + x + 1
+    }
+
+    fn source_bit(&self, x: usize, y: usize, plane: u32) -> u32 {
+        self.source.get(y * self.width + x).map_or(0, |magnitude| (magnitude >> plane) & 1)
+    }
+
+    /// The last row of a stripe ignores the stripe below in causal mode
+    fn causal(&self, y: usize) -> bool {
+        self.style & style::VCAUSAL != 0 && y % 4 == 3
+    }
+
+    fn neighbours(&self, i: usize, y: usize) -> (u32, u32, u32) {
+        let stride = self.width + 2;
+        let significant = |j: usize| (self.flags[j] & SIGNIFICANT) as u32;
+        let h = significant(i - 1) + significant(i + 1);
+        let mut v = significant(i - stride);
+        let mut d = significant(i - stride - 1) + significant(i - stride + 1);
+        if !self.causal(y) {
+            v += significant(i + stride);
+            d += significant(i + stride - 1) + significant(i + stride + 1);
+        }
+        (h, v, d)
+    }
+
+    fn has_neighbours(&self, i: usize, y: usize) -> bool {
+        let (h, v, d) = self.neighbours(i, y);
+        h + v + d > 0
+    }
+
+    fn sign_context(&self, i: usize, y: usize) -> (usize, u32) {
+        let stride = self.width + 2;
+        let contribution = |j: usize| match self.flags[j] & (SIGNIFICANT | NEGATIVE) {
+            SIGNIFICANT => 1,
+            0 => 0,
+            _ => -1,
+        };
+        let south = if self.causal(y) { 0 } else { contribution(i + stride) };
+        let h = (contribution(i - 1) + contribution(i + 1)).clamp(-1, 1);
+        let v = (contribution(i - stride) + south).clamp(-1, 1);
+        match (h, v) {
+            (1, 1) => (13, 0),
+            (1, 0) => (12, 0),
+            (1, _) => (11, 0),
+            (0, 1) => (10, 0),
+            (0, 0) => (9, 0),
+            (0, _) => (10, 1),
+            (_, 1) => (11, 1),
+            (_, 0) => (12, 1),
+            _ => (13, 1),
+        }
+    }
+
+    fn code_sign(&mut self, symbols: &mut impl Symbols, x: usize, y: usize, raw: bool) {
+        let i = self.index(x, y);
+        let negative = (self.flags[i] & SOURCE_NEGATIVE != 0) as u32;
+        let sign = if raw {
+            symbols.raw(negative)
+        } else {
+            let (context, flip) = self.sign_context(i, y);
+            symbols.mq(negative ^ flip, context) ^ flip
+        };
+        self.flags[i] |= SIGNIFICANT | if sign == 1 { NEGATIVE } else { 0 };
+    }
+
+    fn become_significant(&mut self, symbols: &mut impl Symbols, x: usize, y: usize, plane: u32, raw: bool) {
+        self.code_sign(symbols, x, y, raw);
+        self.values[y * self.width + x] = 3 << plane;
+    }
+
+    fn significance_pass(&mut self, symbols: &mut impl Symbols, plane: u32, raw: bool) {
+        for stripe in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                for y in stripe..(stripe + 4).min(self.height) {
+                    let i = self.index(x, y);
+                    if self.flags[i] & SIGNIFICANT != 0 || !self.has_neighbours(i, y) {
+                        continue;
+                    }
+                    let bit = self.source_bit(x, y, plane);
+                    let bit = if raw {
+                        symbols.raw(bit)
+                    } else {
+                        let (h, v, d) = self.neighbours(i, y);
+                        symbols.mq(bit, zero_context(self.orientation, h, v, d))
+                    };
+                    if bit == 1 {
+                        self.become_significant(symbols, x, y, plane, raw);
+                    }
+                    self.flags[i] |= VISITED;
+                }
+            }
+        }
+    }
+
+    fn refinement_pass(&mut self, symbols: &mut impl Symbols, plane: u32, raw: bool) {
+        for stripe in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                for y in stripe..(stripe + 4).min(self.height) {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (SIGNIFICANT | VISITED) != SIGNIFICANT {
+                        continue;
+                    }
+                    let bit = self.source_bit(x, y, plane);
+                    let bit = if raw {
+                        symbols.raw(bit)
+                    } else {
+                        let context = if self.flags[i] & REFINED != 0 {
+                            16
+                        } else if self.has_neighbours(i, y) {
+                            15
+                        } else {
+                            14
+                        };
+                        symbols.mq(bit, context)
+                    };
+                    let value = &mut self.values[y * self.width + x];
+                    *value = if bit == 1 { *value + (1 << plane) } else { *value - (1 << plane) };
+                    self.flags[i] |= REFINED;
+                }
+            }
+        }
+    }
+
+    fn cleanup_pass(&mut self, symbols: &mut impl Symbols, plane: u32) {
+        for stripe in (0..self.height).step_by(4) {
+            let end = (stripe + 4).min(self.height);
+            for x in 0..self.width {
+                let mut first = stripe;
+                // A full stripe column with no significance around it is coded as a run
+                let run = end - stripe == 4
+                    && (stripe..end).all(|y| {
+                        let i = self.index(x, y);
+                        self.flags[i] & (SIGNIFICANT | VISITED) == 0 && !self.has_neighbours(i, y)
+                    });
+                if run {
+                    let hit = (stripe..end).find(|y| self.source_bit(x, *y, plane) == 1);
+                    if symbols.mq(hit.is_some() as u32, CTX_RUN_LENGTH) == 0 {
+                        continue;
+                    }
+                    let position = hit.map_or(0, |y| (y - stripe) as u32);
+                    let high = symbols.mq(position >> 1, CTX_UNIFORM);
+                    let low = symbols.mq(position & 1, CTX_UNIFORM);
+                    let y = stripe + (high * 2 + low) as usize;
+                    self.become_significant(symbols, x, y, plane, false);
+                    first = y + 1;
+                }
+                for y in first..end {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (SIGNIFICANT | VISITED) != 0 {
+                        continue;
+                    }
+                    let (h, v, d) = self.neighbours(i, y);
+                    let bit = symbols.mq(self.source_bit(x, y, plane), zero_context(self.orientation, h, v, d));
+                    if bit == 1 {
+                        self.become_significant(symbols, x, y, plane, false);
+                    }
+                }
+            }
+        }
+        for flag in &mut self.flags {
+            *flag &= !VISITED;
+        }
+        if self.style & style::SEGSYM != 0 {
+            for bit in [1, 0, 1, 0] {
+                symbols.mq(bit, CTX_UNIFORM);
+            }
+        }
+    }
+
+    fn run_pass(&mut self, symbols: &mut impl Symbols, pass: u32, planes: u32) {
+        let plane = planes - 1 - pass.div_ceil(3);
+        let raw = is_raw(self.style, pass);
+        match pass_kind(pass) {
+            PassKind::Significance => self.significance_pass(symbols, plane, raw),
+            PassKind::Refinement => self.refinement_pass(symbols, plane, raw),
+            PassKind::Cleanup => self.cleanup_pass(symbols, plane),
+        }
+    }
+
+    /// Signed magnitudes, still at twice their scale
+    fn signed_values(&self) -> Vec<i64> {
+        let mut values = Vec::with_capacity(self.values.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let value = self.values[y * self.width + x] as i64;
+                values.push(if self.flags[self.index(x, y)] & NEGATIVE != 0 { -value } else { value });
+            }
+        }
+        values
+    }
+}
+
+/// A terminated run of coding passes
+#[derive(Debug, Clone, Default)]
+pub struct Segment {
+    pub data: Vec<u8>,
+    pub passes: u32,
+}
+
+/// Decode a code-block whose coded bit-planes start at `planes - 1`; values come back doubled
+pub fn decode_block(
+    width: usize,
+    height: usize,
+    orientation: Orientation,
+    block_style: u8,
+    planes: u32,
+    segments: &[Segment],
+) -> Vec<i64> {
+    let mut block = Block::new(width, height, orientation, block_style);
+    let max_passes = (planes * 3).saturating_sub(2);
+    let mut mq = MqDecoder::new(&[]);
+    let mut pass = 0;
+    for segment in segments {
+        let mut raw = RawDecoder::new(&segment.data);
+        if !is_raw(block_style, pass) {
+            mq.start(&segment.data);
+        }
+        for _ in 0..segment.passes {
+            if pass >= max_passes {
+                break;
+            }
+            block.run_pass(&mut Decoding { mq: &mut mq, raw: &mut raw }, pass, planes);
+            if block_style & style::RESET != 0 {
+                mq.reset_contexts();
+            }
+            pass += 1;
+        }
+    }
+    block.signed_values()
+}
+
+/// Coded passes of one code-block, ready for packetization
+#[derive(Debug, Clone, Default)]
+pub struct EncodedBlock {
+    pub data: Vec<u8>,
+    /// Byte length of the codeword up to the end of each pass
+    pub pass_ends: Vec<usize>,
+    /// Coded bit-planes, counted from the most significant non-zero one
+    pub planes: u32,
+}
+
+/// Encode a code-block of signed quantization indices
+pub fn encode_block(width: usize, height: usize, orientation: Orientation, block_style: u8, coefficients: &[i32]) -> EncodedBlock {
+    let mut block = Block::new(width, height, orientation, block_style);
+    block.source = coefficients.iter().map(|c| c.unsigned_abs()).collect();
+    for y in 0..height {
+        for x in 0..width {
+            if coefficients[y * width + x] < 0 {
+                let i = block.index(x, y);
+                block.flags[i] |= SOURCE_NEGATIVE;
+            }
+        }
+    }
+    let largest = block.source.iter().copied().max().unwrap_or(0);
+    let planes = u32::BITS - largest.leading_zeros();
+    let passes = (planes * 3).saturating_sub(2);
+
+    let mut mq = MqEncoder::new();
+    let mut raw = RawEncoder::new();
+    let mut encoded = EncodedBlock { planes, ..Default::default() };
+    let mut segment_start = 0;
+    for pass in 0..passes {
+        block.run_pass(&mut Encoding { mq: &mut mq, raw: &mut raw }, pass, planes);
+        if block_style & style::RESET != 0 {
+            mq.reset_contexts();
+        }
+        if ends_segment(block_style, pass) || pass == passes - 1 {
+            let bytes = if is_raw(block_style, pass) { raw.flush() } else { mq.flush() };
+            encoded.data.extend_from_slice(&bytes);
+            // Truncation points inside the segment cannot reach past its terminated end
+            let end = encoded.data.len();
+            for pass_end in &mut encoded.pass_ends[segment_start..] {
+                *pass_end = (*pass_end).min(end);
+            }
+            encoded.pass_ends.push(end);
+            segment_start = encoded.pass_ends.len();
+        } else {
+            // A few bytes past what the coder emitted let a truncated codeword still decode the pass
+            let pending = if is_raw(block_style, pass) { raw.len() } else { mq.len() + 3 };
+            encoded.pass_ends.push(encoded.data.len() + pending);
+        }
+    }
+    encoded
+}
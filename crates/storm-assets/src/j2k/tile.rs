@@ -0,0 +1,650 @@
+// File: crates/storm-assets/src/j2k/tile.rs
+// Tile geometry, packet ordering and packet headers (tier-2)
+
+use super::t1::{self, EncodedBlock, Orientation, Segment};
+use super::{Coding, ImageSize, Progression};
+
+/// `ceil(value / 2^shift)`
+pub fn ceil_shift(value: u32, shift: u32) -> u32 {
+    ((value as u64 + (1u64 << shift) - 1) >> shift) as u32
+}
+
+fn floor_log2(value: u32) -> u32 {
+    u32::BITS - 1 - value.leading_zeros()
+}
+
+/// Packet header bits; a byte after 0xFF carries only seven
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    byte: u8,
+    left: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos, byte: 0, left: 0 }
+    }
+
+    pub fn bit(&mut self) -> Option<u32> {
+        if self.left == 0 {
+            self.left = if self.byte == 0xFF { 7 } else { 8 };
+            self.byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+        }
+        self.left -= 1;
+        Some(((self.byte >> self.left) & 1) as u32)
+    }
+
+    pub fn bits(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()?;
+        }
+        Some(value)
+    }
+
+    /// Finish the header, skipping the stuffing byte after a trailing 0xFF
+    pub fn align(self) -> usize {
+        self.pos + (self.byte == 0xFF) as usize
+    }
+}
+
+pub struct BitWriter {
+    out: Vec<u8>,
+    byte: u8,
+    free: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self { out: Vec::new(), byte: 0, free: 8 }
+    }
+
+    pub fn bit(&mut self, bit: u32) {
+        if self.free == 0 {
+            self.out.push(self.byte);
+            self.free = if self.byte == 0xFF { 7 } else { 8 };
+            self.byte = 0;
+        }
+        self.free -= 1;
+        self.byte |= (bit as u8) << self.free;
+    }
+
+    pub fn bits(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.bit((value >> i) & 1);
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.out.push(self.byte);
+        if self.byte == 0xFF {
+            self.out.push(0);
+        }
+        self.out
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TagNode {
+    parent: Option<usize>,
+    value: u32,
+    low: u32,
+    known: bool,
+}
+
+/// Quad-tree coding of per-block minimums, used for inclusion and zero bit-planes
+#[derive(Debug, Clone)]
+pub struct TagTree {
+    nodes: Vec<TagNode>,
+}
+
+impl TagTree {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut nodes = Vec::new();
+        let (mut w, mut h) = (width, height);
+        let mut start = 0;
+        loop {
+            let root = w * h <= 1;
+            let next = start + w * h;
+            let parent_width = w.div_ceil(2);
+            for y in 0..h {
+                for x in 0..w {
+                    let parent = (!root).then(|| next + (y / 2) * parent_width + x / 2);
+                    nodes.push(TagNode { parent, value: u32::MAX, low: 0, known: false });
+                }
+            }
+            if root {
+                break;
+            }
+            start = next;
+            w = parent_width;
+            h = h.div_ceil(2);
+        }
+        Self { nodes }
+    }
+
+    fn path(&self, leaf: usize) -> Vec<usize> {
+        let mut path = vec![leaf];
+        while let Some(parent) = self.nodes[*path.last().unwrap()].parent {
+            path.push(parent);
+        }
+        path.reverse();
+        path
+    }
+
+    pub fn value(&self, leaf: usize) -> u32 {
+        self.nodes[leaf].value
+    }
+
+    /// Set a leaf for encoding; parents keep the minimum of their children
+    pub fn set_value(&mut self, leaf: usize, value: u32) {
+        let mut node = Some(leaf);
+        while let Some(i) = node {
+            if self.nodes[i].value <= value && i != leaf {
+                break;
+            }
+            self.nodes[i].value = value;
+            node = self.nodes[i].parent;
+        }
+    }
+
+    /// Read whether the leaf's value is below `threshold`
+    pub fn decode(&mut self, bits: &mut BitReader, leaf: usize, threshold: u32) -> Option<bool> {
+        let mut low = 0;
+        for i in self.path(leaf) {
+            let node = &mut self.nodes[i];
+            low = low.max(node.low);
+            while low < threshold && low < node.value {
+                if bits.bit()? == 1 {
+                    node.value = low;
+                } else {
+                    low += 1;
+                }
+            }
+            node.low = low;
+        }
+        Some(self.nodes[leaf].value < threshold)
+    }
+
+    pub fn encode(&mut self, bits: &mut BitWriter, leaf: usize, threshold: u32) {
+        let mut low = 0;
+        for i in self.path(leaf) {
+            let node = &mut self.nodes[i];
+            low = low.max(node.low);
+            while low < threshold {
+                if low >= node.value {
+                    if !node.known {
+                        bits.bit(1);
+                        node.known = true;
+                    }
+                    break;
+                }
+                bits.bit(0);
+                low += 1;
+            }
+            node.low = low;
+        }
+    }
+}
+
+fn read_pass_count(bits: &mut BitReader) -> Option<u32> {
+    if bits.bit()? == 0 {
+        return Some(1);
+    }
+    if bits.bit()? == 0 {
+        return Some(2);
+    }
+    let two = bits.bits(2)?;
+    if two != 3 {
+        return Some(3 + two);
+    }
+    let five = bits.bits(5)?;
+    if five != 31 {
+        return Some(6 + five);
+    }
+    Some(37 + bits.bits(7)?)
+}
+
+fn write_pass_count(bits: &mut BitWriter, count: u32) {
+    match count {
+        1 => bits.bit(0),
+        2 => bits.bits(0b10, 2),
+        3..=5 => bits.bits(0b1100 | (count - 3), 4),
+        6..=36 => bits.bits((0b1111 << 5) | (count - 6), 9),
+        _ => bits.bits((0b1_1111_1111 << 7) | (count - 37), 16),
+    }
+}
+
+/// Split passes `first..first + count` into runs that end at segment boundaries
+fn pass_chunks(block_style: u8, first: u32, count: u32) -> Vec<u32> {
+    let mut chunks = Vec::new();
+    let mut run = 0;
+    for pass in first..first + count {
+        run += 1;
+        if t1::ends_segment(block_style, pass) || pass + 1 == first + count {
+            chunks.push(run);
+            run = 0;
+        }
+    }
+    chunks
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeBlock {
+    /// Rectangle in band coordinates
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub included: bool,
+    pub lblock: u32,
+    pub passes: u32,
+    pub zero_planes: u32,
+    pub segments: Vec<Segment>,
+    pub encoded: EncodedBlock,
+    /// Passes included up to and including each layer, when encoding
+    pub layer_passes: Vec<u32>,
+}
+
+impl CodeBlock {
+    fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Self {
+        Self {
+            x0,
+            y0,
+            x1,
+            y1,
+            included: false,
+            lblock: 3,
+            passes: 0,
+            zero_planes: 0,
+            segments: Vec::new(),
+            encoded: EncodedBlock::default(),
+            layer_passes: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        (self.x1 - self.x0) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.y1 - self.y0) as usize
+    }
+
+    fn append(&mut self, block_style: u8, data: &[u8], passes: u32) {
+        let open = self.passes > 0 && !t1::ends_segment(block_style, self.passes - 1);
+        match self.segments.last_mut() {
+            Some(segment) if open => {
+                segment.data.extend_from_slice(data);
+                segment.passes += passes;
+            }
+            _ => self.segments.push(Segment { data: data.to_vec(), passes }),
+        }
+        self.passes += passes;
+    }
+
+    /// Byte offset of the codeword after `passes` passes
+    fn offset(&self, passes: u32) -> usize {
+        passes.checked_sub(1).map_or(0, |pass| self.encoded.pass_ends[pass as usize])
+    }
+
+    fn new_passes(&self, layer: u16) -> (u32, u32) {
+        let layer = layer as usize;
+        let before = if layer == 0 { 0 } else { self.layer_passes[layer - 1] };
+        (before, self.layer_passes[layer] - before)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Precinct {
+    pub blocks: Vec<CodeBlock>,
+    pub inclusion: TagTree,
+    pub zero_planes: TagTree,
+}
+
+#[derive(Debug, Clone)]
+pub struct Band {
+    pub orientation: Orientation,
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub magnitude_bits: u32,
+    /// Dequantization step; 1 for reversible coding
+    pub step: f32,
+    pub precincts: Vec<Precinct>,
+}
+
+impl Band {
+    pub fn width(&self) -> usize {
+        (self.x1 - self.x0) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.y1 - self.y0) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub precinct_exp: (u8, u8),
+    pub precincts_wide: u32,
+    pub precincts_high: u32,
+    pub bands: Vec<Band>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TileComponent {
+    pub resolutions: Vec<Resolution>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketId {
+    pub layer: u16,
+    pub resolution: usize,
+    pub component: usize,
+    pub precinct: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub components: Vec<TileComponent>,
+}
+
+impl Tile {
+    pub fn new(size: &ImageSize, coding: &Coding, index: u32) -> Self {
+        let tiles_wide = size.tiles_wide();
+        let (tx, ty) = (index % tiles_wide, index / tiles_wide);
+        let x0 = (size.tile_x0 + tx * size.tile_width).max(size.x0);
+        let y0 = (size.tile_y0 + ty * size.tile_height).max(size.y0);
+        let x1 = (size.tile_x0 + (tx + 1) * size.tile_width).min(size.x1);
+        let y1 = (size.tile_y0 + (ty + 1) * size.tile_height).min(size.y1);
+
+        let components = size
+            .components
+            .iter()
+            .zip(&coding.components)
+            .map(|(component, style)| {
+                let (dx, dy) = (component.dx as u32, component.dy as u32);
+                let (cx0, cy0, cx1, cy1) = (x0.div_ceil(dx), y0.div_ceil(dy), x1.div_ceil(dx), y1.div_ceil(dy));
+                let levels = style.levels as u32;
+                let resolutions = (0..=levels)
+                    .map(|r| {
+                        let shift = levels - r;
+                        let (rx0, ry0) = (ceil_shift(cx0, shift), ceil_shift(cy0, shift));
+                        let (rx1, ry1) = (ceil_shift(cx1, shift), ceil_shift(cy1, shift));
+                        let (ppx, ppy) = style.precincts.get(r as usize).copied().unwrap_or((15, 15));
+                        let span = |a0: u32, a1: u32, exp: u8| {
+                            if a1 > a0 { ceil_shift(a1, exp as u32) - (a0 >> exp) } else { 0 }
+                        };
+                        let precincts_wide = span(rx0, rx1, ppx);
+                        let precincts_high = span(ry0, ry1, ppy);
+
+                        let orientations: &[Orientation] = if r == 0 {
+                            &[Orientation::LL]
+                        } else {
+                            &[Orientation::HL, Orientation::LH, Orientation::HH]
+                        };
+                        let split = (r > 0) as u8;
+                        let cbw = style.cb_width.min(ppx.saturating_sub(split)) as u32;
+                        let cbh = style.cb_height.min(ppy.saturating_sub(split)) as u32;
+                        let bands = orientations
+                            .iter()
+                            .enumerate()
+                            .map(|(b, &orientation)| {
+                                let (xob, yob) = orientation.offsets();
+                                let (bx0, by0, bx1, by1) = if r == 0 {
+                                    (rx0, ry0, rx1, ry1)
+                                } else {
+                                    ((rx0 + 1 - xob) / 2, (ry0 + 1 - yob) / 2, (rx1 + 1 - xob) / 2, (ry1 + 1 - yob) / 2)
+                                };
+                                let band_index = if r == 0 { 0 } else { 1 + 3 * (r as usize - 1) + b };
+                                let (exponent, mantissa) = style.quantization.step(band_index);
+                                let magnitude_bits = (style.quantization.guard_bits as u32 + exponent as u32).saturating_sub(1);
+                                let step = if style.reversible {
+                                    1.0
+                                } else {
+                                    let range = component.precision as i32 + orientation.gain() as i32;
+                                    2f32.powi(range - exponent as i32) * (1.0 + mantissa as f32 / 2048.0)
+                                };
+                                let precincts = (0..precincts_wide * precincts_high)
+                                    .map(|k| {
+                                        let (px, py) = (k % precincts_wide, k / precincts_wide);
+                                        let mut px0 = ((rx0 >> ppx) + px) << ppx;
+                                        let mut py0 = ((ry0 >> ppy) + py) << ppy;
+                                        let mut px1 = px0 + (1 << ppx);
+                                        let mut py1 = py0 + (1 << ppy);
+                                        if r > 0 {
+                                            (px0, py0, px1, py1) = (px0 / 2, py0 / 2, px1 / 2, py1 / 2);
+                                        }
+                                        let (px0, py0) = (px0.max(bx0), py0.max(by0));
+                                        let (px1, py1) = (px1.min(bx1), py1.min(by1));
+                                        precinct(px0, py0, px1, py1, cbw, cbh)
+                                    })
+                                    .collect();
+                                Band { orientation, x0: bx0, y0: by0, x1: bx1, y1: by1, magnitude_bits, step, precincts }
+                            })
+                            .collect();
+                        Resolution {
+                            x0: rx0,
+                            y0: ry0,
+                            x1: rx1,
+                            y1: ry1,
+                            precinct_exp: (ppx, ppy),
+                            precincts_wide,
+                            precincts_high,
+                            bands,
+                        }
+                    })
+                    .collect();
+                TileComponent { resolutions }
+            })
+            .collect();
+        Self { x0, y0, x1, y1, components }
+    }
+
+    /// Every packet of the tile in the order of its progression
+    pub fn packets(&self, size: &ImageSize, coding: &Coding) -> Vec<PacketId> {
+        let mut packets = Vec::new();
+        for (c, component) in self.components.iter().enumerate() {
+            let levels = component.resolutions.len() as u32 - 1;
+            let (dx, dy) = (size.components[c].dx as u64, size.components[c].dy as u64);
+            for (r, resolution) in component.resolutions.iter().enumerate() {
+                let shift = levels - r as u32;
+                let (ppx, ppy) = resolution.precinct_exp;
+                for k in 0..(resolution.precincts_wide * resolution.precincts_high) as usize {
+                    let px = k as u32 % resolution.precincts_wide;
+                    let py = k as u32 / resolution.precincts_wide;
+                    // Precincts reaching past the tile's corner are visited at the corner
+                    let x = ((resolution.x0 >> ppx) + px) << ppx;
+                    let y = ((resolution.y0 >> ppy) + py) << ppy;
+                    let x = if x < resolution.x0 { self.x0 as u64 } else { ((x as u64) << shift) * dx };
+                    let y = if y < resolution.y0 { self.y0 as u64 } else { ((y as u64) << shift) * dy };
+                    for layer in 0..coding.layers {
+                        let (l, r, c, k) = (layer as u64, r as u64, c as u64, k as u64);
+                        let key = match coding.progression {
+                            Progression::Lrcp => [l, r, c, k, 0],
+                            Progression::Rlcp => [r, l, c, k, 0],
+                            Progression::Rpcl => [r, y, x, c, l],
+                            Progression::Pcrl => [y, x, c, r, l],
+                            Progression::Cprl => [c, y, x, r, l],
+                        };
+                        packets.push((key, PacketId {
+                            layer,
+                            resolution: r as usize,
+                            component: c as usize,
+                            precinct: k as usize,
+                        }));
+                    }
+                }
+            }
+        }
+        packets.sort_by_key(|(key, _)| *key);
+        packets.into_iter().map(|(_, packet)| packet).collect()
+    }
+
+    /// Read one packet at `pos`, keeping its data only if `keep`; `None` once the data runs out
+    pub fn read_packet(&mut self, data: &[u8], mut pos: usize, packet: PacketId, coding: &Coding, keep: bool) -> Option<usize> {
+        let block_style = coding.components[packet.component].block_style;
+        if data.get(pos..pos + 2) == Some(&[0xFF, 0x91]) {
+            pos += 6;
+        }
+
+        let mut bits = BitReader::new(data, pos);
+        let resolution = &mut self.components[packet.component].resolutions[packet.resolution];
+        let mut contributions = Vec::new();
+        if bits.bit()? == 1 {
+            for (b, band) in resolution.bands.iter_mut().enumerate() {
+                let magnitude_bits = band.magnitude_bits;
+                let precinct = &mut band.precincts[packet.precinct];
+                for j in 0..precinct.blocks.len() {
+                    let block = &mut precinct.blocks[j];
+                    let included = if block.included {
+                        bits.bit()? == 1
+                    } else {
+                        precinct.inclusion.decode(&mut bits, j, packet.layer as u32 + 1)?
+                    };
+                    if !included {
+                        continue;
+                    }
+                    if !block.included {
+                        let mut threshold = 1;
+                        while !precinct.zero_planes.decode(&mut bits, j, threshold)? {
+                            threshold += 1;
+                        }
+                        block.zero_planes = precinct.zero_planes.value(j).min(magnitude_bits);
+                        block.included = true;
+                    }
+                    let passes = read_pass_count(&mut bits)?;
+                    while bits.bit()? == 1 {
+                        block.lblock += 1;
+                    }
+                    // Codeword lengths with their pass counts
+                    let mut chunks: Vec<(usize, u32)> = Vec::new();
+                    for count in pass_chunks(block_style, block.passes, passes) {
+                        chunks.push((bits.bits(block.lblock + floor_log2(count))? as usize, count));
+                    }
+                    contributions.push((b, j, chunks));
+                }
+            }
+        }
+        pos = bits.align();
+        if data.get(pos..pos + 2) == Some(&[0xFF, 0x92]) {
+            pos += 2;
+        }
+
+        for (b, j, chunks) in contributions {
+            let block = &mut resolution.bands[b].precincts[packet.precinct].blocks[j];
+            for (length, count) in chunks {
+                let end = (pos + length).min(data.len());
+                if keep {
+                    block.append(block_style, &data[pos..end], count);
+                } else {
+                    block.passes += count;
+                }
+                if end < pos + length {
+                    return None;
+                }
+                pos = end;
+            }
+        }
+        Some(pos)
+    }
+
+    /// Append one packet built from the tile's encoded code-blocks
+    pub fn write_packet(&mut self, out: &mut Vec<u8>, packet: PacketId, coding: &Coding, sequence: usize) {
+        let block_style = coding.components[packet.component].block_style;
+        if coding.sop {
+            out.extend_from_slice(&[0xFF, 0x91, 0x00, 0x04]);
+            out.extend_from_slice(&(sequence as u16).to_be_bytes());
+        }
+
+        let resolution = &mut self.components[packet.component].resolutions[packet.resolution];
+        let empty = resolution.bands.iter().all(|band| {
+            band.precincts[packet.precinct].blocks.iter().all(|block| block.new_passes(packet.layer).1 == 0)
+        });
+        let mut bits = BitWriter::new();
+        let mut body = Vec::new();
+        bits.bit(!empty as u32);
+        if !empty {
+            for band in &mut resolution.bands {
+                let precinct = &mut band.precincts[packet.precinct];
+                for j in 0..precinct.blocks.len() {
+                    let block = &mut precinct.blocks[j];
+                    let (before, count) = block.new_passes(packet.layer);
+                    if block.included {
+                        bits.bit((count > 0) as u32);
+                    } else {
+                        precinct.inclusion.encode(&mut bits, j, packet.layer as u32 + 1);
+                    }
+                    if count == 0 {
+                        continue;
+                    }
+                    if !block.included {
+                        precinct.zero_planes.encode(&mut bits, j, precinct.zero_planes.value(j) + 1);
+                        block.included = true;
+                    }
+                    write_pass_count(&mut bits, count);
+
+                    let mut chunks = Vec::new();
+                    let mut first = before;
+                    for chunk in pass_chunks(block_style, before, count) {
+                        chunks.push((block.offset(first + chunk) - block.offset(first), chunk));
+                        first += chunk;
+                    }
+                    let needed = chunks
+                        .iter()
+                        .map(|&(length, chunk)| (u32::BITS - (length as u32).leading_zeros()).saturating_sub(floor_log2(chunk)))
+                        .max()
+                        .unwrap_or(0);
+                    while block.lblock < needed {
+                        bits.bit(1);
+                        block.lblock += 1;
+                    }
+                    bits.bit(0);
+                    for &(length, chunk) in &chunks {
+                        bits.bits(length as u32, block.lblock + floor_log2(chunk));
+                    }
+                    body.extend_from_slice(&block.encoded.data[block.offset(before)..block.offset(before + count)]);
+                }
+            }
+        }
+        out.extend_from_slice(&bits.finish());
+        if coding.eph {
+            out.extend_from_slice(&[0xFF, 0x92]);
+        }
+        out.extend_from_slice(&body);
+    }
+}
+
+/// Code-blocks of one precinct in one band, clipped to `x0..x1` by `y0..y1`
+fn precinct(x0: u32, y0: u32, x1: u32, y1: u32, cbw: u32, cbh: u32) -> Precinct {
+    if x1 <= x0 || y1 <= y0 {
+        return Precinct { blocks: Vec::new(), inclusion: TagTree::new(0, 0), zero_planes: TagTree::new(0, 0) };
+    }
+    let (first_x, first_y) = (x0 >> cbw, y0 >> cbh);
+    let blocks_wide = (ceil_shift(x1, cbw) - first_x) as usize;
+    let blocks_high = (ceil_shift(y1, cbh) - first_y) as usize;
+    let mut blocks = Vec::with_capacity(blocks_wide * blocks_high);
+    for by in 0..blocks_high as u32 {
+        for bx in 0..blocks_wide as u32 {
+            let (cx, cy) = ((first_x + bx) << cbw, (first_y + by) << cbh);
+            blocks.push(CodeBlock::new(cx.max(x0), cy.max(y0), (cx + (1 << cbw)).min(x1), (cy + (1 << cbh)).min(y1)));
+        }
+    }
+    Precinct {
+        blocks,
+        inclusion: TagTree::new(blocks_wide, blocks_high),
+        zero_planes: TagTree::new(blocks_wide, blocks_high),
+    }
+}
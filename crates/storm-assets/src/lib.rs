@@ -14,10 +14,13 @@ use anyhow::Result;
 pub mod loaders;
 pub mod cache;
 pub mod processors;
+pub mod error;
+pub mod j2k;
 
 pub use loaders::*;
 pub use cache::*;
 pub use processors::*;
+pub use error::AssetError;
 
 /// Asset identifier
 pub type AssetId = uuid::Uuid;
@@ -158,6 +161,9 @@ impl AssetManager {
         loaders.insert("png".to_string(), Arc::new(ImageLoader));
         loaders.insert("jpg".to_string(), Arc::new(ImageLoader));
         loaders.insert("jpeg".to_string(), Arc::new(ImageLoader));
+        for extension in J2kLoader.supported_extensions() {
+            loaders.insert(extension.to_string(), Arc::new(J2kLoader));
+        }
         loaders.insert("wav".to_string(), Arc::new(AudioLoader));
         loaders.insert("ogg".to_string(), Arc::new(AudioLoader));
 
@@ -196,13 +202,16 @@ impl AssetManager {
             .to_lowercase();
 
         let loader = self.loaders.get(&extension)
-            .ok_or_else(|| anyhow::anyhow!("No loader available for extension: {}", extension))?;
+            .ok_or_else(|| AssetError::NoLoader { extension: extension.clone() })?;
 
         // Load the asset
         let asset_data = loader.load(&full_path).await?;
 
         // Create metadata
-        let metadata = fs::metadata(&full_path).await?;
+        let metadata = fs::metadata(&full_path).await.map_err(|source| AssetError::Io {
+            path: full_path.clone(),
+            source,
+        })?;
         let asset_id = AssetId::new_v4();
 
         let asset_metadata = AssetMetadata {
@@ -249,15 +258,15 @@ impl AssetManager {
     /// Process an asset (e.g., optimize, compress)
     pub async fn process_asset(&self, asset_id: AssetId, processor_name: &str) -> Result<()> {
         let processor = self.processors.get(processor_name)
-            .ok_or_else(|| anyhow::anyhow!("No processor found: {}", processor_name))?;
+            .ok_or_else(|| AssetError::NoProcessor { name: processor_name.to_string() })?;
 
         let (asset_data, metadata) = {
             let cache = self.cache.read().await;
             let data = cache.get_data(asset_id)
-                .ok_or_else(|| anyhow::anyhow!("Asset not found: {}", asset_id))?
+                .ok_or(AssetError::NotFound { asset_id })?
                 .clone();
             let meta = cache.get_metadata(asset_id)
-                .ok_or_else(|| anyhow::anyhow!("Asset metadata not found: {}", asset_id))?
+                .ok_or(AssetError::MetadataMissing { asset_id })?
                 .clone();
             (data, meta)
         };
@@ -274,6 +283,37 @@ impl AssetManager {
         Ok(())
     }
 
+    /// Cache an asset that did not come from disk, such as a texture fetched from a grid
+    pub async fn insert_asset(&self, asset_id: AssetId, name: &str, asset_type: AssetType, data: AssetData, priority: f32) {
+        let file_size = match &data {
+            AssetData::Texture(texture) => texture.data.len(),
+            AssetData::Audio(audio) => audio.data.len(),
+            AssetData::Raw(bytes) => bytes.len(),
+            _ => 0,
+        } as u64;
+        let metadata = AssetMetadata {
+            id: asset_id,
+            name: name.to_string(),
+            asset_type,
+            file_path: PathBuf::from(asset_id.to_string()),
+            file_size,
+            last_modified: std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            dependencies: Vec::new(),
+            tags: Vec::new(),
+        };
+        let mut cache = self.cache.write().await;
+        cache.insert_with_priority(asset_id, data, metadata, priority);
+    }
+
+    /// Update an asset's eviction priority, e.g. as it grows or shrinks on screen
+    pub async fn set_priority(&self, asset_id: AssetId, priority: f32) -> bool {
+        let mut cache = self.cache.write().await;
+        cache.set_priority(asset_id, priority)
+    }
+
     /// Unload an asset from cache
     pub async fn unload_asset(&self, asset_id: AssetId) -> bool {
         let mut cache = self.cache.write().await;
@@ -289,7 +329,7 @@ impl AssetManager {
     fn determine_asset_type(&self, extension: &str) -> AssetType {
         match extension {
             "gltf" | "glb" | "obj" | "fbx" => AssetType::Mesh,
-            "png" | "jpg" | "jpeg" | "tga" | "bmp" => AssetType::Texture,
+            "png" | "jpg" | "jpeg" | "tga" | "bmp" | "j2c" | "j2k" | "jp2" => AssetType::Texture,
             "wav" | "ogg" | "mp3" | "flac" => AssetType::Audio,
             "glsl" | "hlsl" | "wgsl" => AssetType::Shader,
             "json" | "scene" => AssetType::Scene,
@@ -325,8 +365,149 @@ mod tests {
         assert!(cache.get_data(asset_id).is_some());
         assert!(cache.get_metadata(asset_id).is_some());
 
+        assert!(cache.get_data(AssetId::new_v4()).is_none());
+
         let stats = cache.get_stats();
         assert_eq!(stats.asset_count, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.cache_hit_rate, 0.5);
+    }
+
+    #[test]
+    fn test_cache_evicts_lowest_priority_first() {
+        let mut cache = AssetCache::new();
+        cache.set_max_memory(10);
+        let ids: Vec<AssetId> = (0..3).map(|_| AssetId::new_v4()).collect();
+        let metadata = |id: AssetId| AssetMetadata {
+            id,
+            name: "texture".to_string(),
+            asset_type: AssetType::Texture,
+            file_path: PathBuf::from(id.to_string()),
+            file_size: 4,
+            last_modified: 0,
+            dependencies: Vec::new(),
+            tags: Vec::new(),
+        };
+
+        cache.insert_with_priority(ids[0], AssetData::Raw(vec![0; 4]), metadata(ids[0]), 5.0);
+        cache.insert_with_priority(ids[1], AssetData::Raw(vec![0; 4]), metadata(ids[1]), 1.0);
+        assert!(cache.set_priority(ids[1], 9.0));
+        cache.insert_with_priority(ids[2], AssetData::Raw(vec![0; 4]), metadata(ids[2]), 2.0);
+
+        assert!(cache.get_data(ids[0]).is_none());
+        assert!(cache.get_data(ids[1]).is_some());
+        assert_eq!(cache.get_priority(ids[2]), Some(2.0));
+        assert_eq!(cache.get_stats().memory_usage, 8);
+    }
+
+    fn test_texture(width: u32, height: u32, format: TextureFormat, channels: usize) -> TextureData {
+        let mut seed = 0x2545_f491u32;
+        let data = (0..(width * height) as usize * channels)
+            .map(|i| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let (x, y) = ((i / channels) as u32 % width, (i / channels) as u32 / width);
+                ((x * 7 + y * 3 + (i % channels) as u32 * 50) % 200) as u8 + (seed % 40) as u8
+            })
+            .collect();
+        TextureData { width, height, format, data, mip_levels: 1 }
+    }
+
+    #[test]
+    fn test_j2k_lossless_round_trip() {
+        let texture = test_texture(37, 23, TextureFormat::RGBA8, 4);
+        let stream = j2k::encode(&texture, &j2k::EncodeOptions::default()).unwrap();
+        assert!(j2k::is_j2k(&stream));
+
+        let info = j2k::read_info(&stream).unwrap();
+        assert_eq!((info.width, info.height, info.components), (37, 23, 4));
+        assert!(info.reversible);
+
+        let decoded = j2k::decode(&stream, &j2k::DecodeOptions::default()).unwrap();
+        assert_eq!((decoded.width, decoded.height, decoded.format.clone()), (37, 23, TextureFormat::RGBA8));
+        assert_eq!(decoded.data, texture.data);
+    }
+
+    #[test]
+    fn test_j2k_coding_options_round_trip() {
+        use j2k::{block_style, EncodeOptions, Progression};
+
+        let texture = test_texture(45, 30, TextureFormat::RGB8, 3);
+        let styles = [
+            0,
+            block_style::BYPASS,
+            block_style::BYPASS | block_style::TERMALL | block_style::RESET,
+            block_style::VCAUSAL | block_style::SEGSYM | block_style::PTERM,
+        ];
+        let progressions = [Progression::Lrcp, Progression::Rlcp, Progression::Rpcl, Progression::Pcrl, Progression::Cprl];
+        for (i, progression) in progressions.into_iter().enumerate() {
+            let options = EncodeOptions {
+                levels: 3,
+                layers: 3,
+                progression,
+                code_block: (4, 3),
+                block_style: styles[i % styles.len()],
+                precincts: Some((4, 4)),
+                tile_size: (i % 2 == 1).then_some((20, 16)),
+                sop: i % 2 == 0,
+                eph: i % 3 == 0,
+                ..Default::default()
+            };
+            let stream = j2k::encode(&texture, &options).unwrap();
+            let decoded = j2k::decode(&stream, &j2k::DecodeOptions::default()).unwrap();
+            assert_eq!(decoded.data, texture.data, "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn test_j2k_progressive_quality() {
+        let texture = test_texture(64, 48, TextureFormat::R8, 1);
+        let options = j2k::EncodeOptions { layers: 4, ..Default::default() };
+        let stream = j2k::encode(&texture, &options).unwrap();
+        let info = j2k::read_info(&stream).unwrap();
+        assert_eq!(info.size_at(2), (16, 12));
+
+        let error = |decoded: &TextureData| -> f64 {
+            let step = (texture.width / decoded.width) as usize;
+            let mut total = 0.0;
+            for y in 0..decoded.height as usize {
+                for x in 0..decoded.width as usize {
+                    let original = texture.data[y * step * texture.width as usize + x * step] as f64;
+                    total += (decoded.data[y * decoded.width as usize + x] as f64 - original).abs();
+                }
+            }
+            total / decoded.data.len() as f64
+        };
+
+        let quarter = j2k::decode(&stream, &j2k::DecodeOptions { discard_level: 2, max_layers: None }).unwrap();
+        assert_eq!((quarter.width, quarter.height), (16, 12));
+        assert!(error(&quarter) < 40.0);
+
+        let first_layer = j2k::decode(&stream, &j2k::DecodeOptions { discard_level: 0, max_layers: Some(1) }).unwrap();
+        let all_layers = j2k::decode(&stream, &j2k::DecodeOptions::default()).unwrap();
+        assert!(error(&first_layer) > 0.0);
+        assert_eq!(error(&all_layers), 0.0);
+
+        // A cut-off download still decodes at lower quality
+        let truncated = j2k::decode(&stream[..stream.len() / 3], &j2k::DecodeOptions::default()).unwrap();
+        assert_eq!((truncated.width, truncated.height), (64, 48));
+        assert!(error(&truncated) < error(&j2k::decode(&stream[..200], &j2k::DecodeOptions::default()).unwrap()));
+    }
+
+    #[test]
+    fn test_j2k_irreversible_and_invalid_data() {
+        let texture = test_texture(33, 17, TextureFormat::RGBA8, 4);
+        let options = j2k::EncodeOptions { reversible: false, quantization_step: 0.5, ..Default::default() };
+        let stream = j2k::encode(&texture, &options).unwrap();
+        let decoded = j2k::decode(&stream, &j2k::DecodeOptions::default()).unwrap();
+        let worst = decoded.data.iter().zip(&texture.data).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+        assert!(worst <= 3, "worst error {}", worst);
+
+        let error = j2k::decode(&[0xFF, 0x4F, 0xFF, 0x51, 0x00], &j2k::DecodeOptions::default()).unwrap_err();
+        assert_eq!(error.downcast_ref::<AssetError>().map(AssetError::code), Some(5006));
+        assert!(!j2k::is_j2k(b"PNG"));
     }
 
     #[tokio::test]
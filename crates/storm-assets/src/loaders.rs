@@ -4,7 +4,7 @@
 use async_trait::async_trait;
 use std::path::Path;
 use anyhow::Result;
-use crate::AssetData;
+use crate::{j2k, AssetData};
 
 /// Asset loader trait
 #[async_trait]
@@ -58,6 +58,21 @@ impl AssetLoader for ImageLoader {
     }
 }
 
+/// JPEG 2000 loader for grid textures (.j2c codestreams and .jp2 files)
+pub struct J2kLoader;
+
+#[async_trait]
+impl AssetLoader for J2kLoader {
+    async fn load(&self, path: &Path) -> Result<AssetData> {
+        let bytes = tokio::fs::read(path).await?;
+        Ok(AssetData::Texture(j2k::decode(&bytes, &j2k::DecodeOptions::default())?))
+    }
+
+    fn supported_extensions(&self) -> Vec<&'static str> {
+        vec!["j2c", "j2k", "jp2"]
+    }
+}
+
 /// Audio loader
 pub struct AudioLoader;
 
@@ -0,0 +1,45 @@
+// File: crates/storm-audio/src/error.rs
+// Typed audio errors with stable codes and retry classification
+
+use thiserror::Error;
+
+/// Audio failures
+///
+/// Functions in this crate return `anyhow::Result`; these errors travel inside it and can be
+/// recovered with `downcast_ref::<AudioError>()`.
+#[derive(Error, Debug)]
+pub enum AudioError {
+    #[error("Invalid audio configuration: {reason}")]
+    InvalidConfig { reason: String },
+
+    #[error("Audio output device unavailable: {reason}")]
+    DeviceUnavailable { reason: String },
+
+    #[error("All {limit} audio sources are in use")]
+    TooManySources { limit: usize },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl AudioError {
+    /// Stable numeric code, in the 7000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            AudioError::InvalidConfig { .. } => 7001,
+            AudioError::DeviceUnavailable { .. } => 7002,
+            AudioError::TooManySources { .. } => 7003,
+            AudioError::Other(_) => 7000,
+        }
+    }
+
+    /// Devices come back and sources free up, so those may succeed later
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, AudioError::DeviceUnavailable { .. } | AudioError::TooManySources { .. })
+    }
+
+    /// Audio is optional; the engine keeps running without it
+    pub fn is_fatal(&self) -> bool {
+        false
+    }
+}
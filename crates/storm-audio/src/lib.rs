@@ -6,6 +6,10 @@ use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 use anyhow::Result;
 
+pub mod error;
+
+pub use error::AudioError;
+
 /// Audio engine configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioConfig {
@@ -26,9 +30,32 @@ impl Default for AudioConfig {
     }
 }
 
+impl AudioConfig {
+    /// Reject settings no output stream can be opened with
+    pub fn validate(&self) -> Result<(), AudioError> {
+        if self.sample_rate == 0 || self.buffer_size == 0 {
+            return Err(AudioError::InvalidConfig {
+                reason: format!("sample rate {} and buffer size {} must be non-zero", self.sample_rate, self.buffer_size),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Ambient soundscape levels supplied by the environment simulation, each 0.0..1.0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct AmbienceLevels {
+    pub wind: f32,
+    pub rain: f32,
+    pub thunder: f32,
+    pub birdsong: f32,
+    pub insects: f32,
+}
+
 /// Audio engine main struct
 pub struct AudioEngine {
     config: AudioConfig,
+    ambience: std::sync::RwLock<AmbienceLevels>,
     #[cfg(feature = "spatial")]
     spatial_processor: Option<SpatialAudioProcessor>,
 }
@@ -36,6 +63,7 @@ pub struct AudioEngine {
 impl AudioEngine {
     pub async fn new(config: &AudioConfig) -> Result<Self> {
         info!("Initializing audio engine");
+        config.validate()?;
 
         #[cfg(feature = "spatial")]
         let spatial_processor = if config.spatial_audio_enabled {
@@ -46,11 +74,23 @@ impl AudioEngine {
 
         Ok(Self {
             config: config.clone(),
+            ambience: std::sync::RwLock::new(AmbienceLevels::default()),
             #[cfg(feature = "spatial")]
             spatial_processor,
         })
     }
 
+    /// Set the ambient loop levels mixed under positional sources
+    pub fn set_ambience(&self, levels: AmbienceLevels) {
+        if let Ok(mut ambience) = self.ambience.write() {
+            *ambience = levels;
+        }
+    }
+
+    pub fn ambience(&self) -> AmbienceLevels {
+        self.ambience.read().map(|ambience| *ambience).unwrap_or_default()
+    }
+
     pub async fn update(&self, delta_time: f32) -> Result<()> {
         // Update audio processing
         #[cfg(feature = "spatial")]
@@ -98,4 +138,13 @@ mod tests {
         let engine = AudioEngine::new(&config).await;
         assert!(engine.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_invalid_config_is_typed() {
+        let config = AudioConfig { sample_rate: 0, ..Default::default() };
+        let err = AudioEngine::new(&config).await.err().unwrap();
+        let err = err.downcast_ref::<AudioError>().unwrap();
+        assert_eq!(err.code(), 7001);
+        assert!(!err.is_retryable());
+    }
 }
\ No newline at end of file
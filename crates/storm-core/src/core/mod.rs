@@ -5,6 +5,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::environment::EnvironmentConfig;
+
 pub mod config;
 pub mod handle;
 
@@ -19,6 +21,10 @@ pub struct StormConfig {
     pub render_config: RenderConfig,
     pub audio_config: AudioConfig,
     pub physics_config: PhysicsConfig,
+    pub environment_config: EnvironmentConfig,
+    /// Directory the asset manager resolves local asset paths against
+    #[serde(default = "default_asset_path")]
+    pub asset_path: std::path::PathBuf,
 
     // Feature flags
     pub enable_rendering: bool,
@@ -39,6 +45,8 @@ impl Default for StormConfig {
             render_config: RenderConfig::default(),
             audio_config: AudioConfig::default(),
             physics_config: PhysicsConfig::default(),
+            environment_config: EnvironmentConfig::default(),
+            asset_path: default_asset_path(),
             enable_rendering: true,
             enable_audio: true,
             enable_physics: true,
@@ -49,6 +57,10 @@ impl Default for StormConfig {
     }
 }
 
+fn default_asset_path() -> std::path::PathBuf {
+    "assets".into()
+}
+
 /// AI system configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AIConfig {
@@ -81,6 +93,12 @@ pub struct NetworkConfig {
     pub packet_buffer_size: usize,
     pub compression_enabled: bool,
     pub encryption_enabled: bool,
+    #[serde(default = "default_heartbeat_interval_ms")]
+    pub heartbeat_interval_ms: u64,
+}
+
+fn default_heartbeat_interval_ms() -> u64 {
+    1000
 }
 
 impl Default for NetworkConfig {
@@ -91,6 +109,7 @@ impl Default for NetworkConfig {
             packet_buffer_size: 8192,
             compression_enabled: true,
             encryption_enabled: true,
+            heartbeat_interval_ms: 1000,
         }
     }
 }
@@ -246,6 +265,7 @@ impl From<NetworkConfig> for storm_networking::NetworkConfig {
             packet_buffer_size: config.packet_buffer_size,
             compression_enabled: config.compression_enabled,
             encryption_enabled: config.encryption_enabled,
+            heartbeat_interval_ms: config.heartbeat_interval_ms,
         }
     }
 }
@@ -297,6 +317,33 @@ impl From<PhysicsConfig> for storm_physics::PhysicsConfig {
     }
 }
 
+#[cfg(feature = "rendering")]
+impl From<crate::environment::LightingParams> for storm_rendering::EnvironmentLighting {
+    fn from(params: crate::environment::LightingParams) -> Self {
+        storm_rendering::EnvironmentLighting {
+            sun_direction: params.sun_direction,
+            sun_color: params.sun_color,
+            sun_intensity: params.sun_intensity,
+            ambient_color: params.ambient_color,
+            ambient_intensity: params.ambient_intensity,
+            fog_density: params.fog_density,
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl From<crate::environment::AmbienceParams> for storm_audio::AmbienceLevels {
+    fn from(params: crate::environment::AmbienceParams) -> Self {
+        storm_audio::AmbienceLevels {
+            wind: params.wind,
+            rain: params.rain,
+            thunder: params.thunder,
+            birdsong: params.birdsong,
+            insects: params.insects,
+        }
+    }
+}
+
 #[cfg(feature = "rendering")]
 impl From<RenderBackend> for storm_rendering::RenderBackend {
     fn from(backend: RenderBackend) -> Self {
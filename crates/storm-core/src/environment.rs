@@ -0,0 +1,592 @@
+// File: crates/storm-core/src/environment.rs
+// Environment simulation: time of day, sun position, seasons and weather per world
+// Deterministic from a seed so every client sharing a world sees the same sky
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+/// Sim seconds between weather state machine steps
+const WEATHER_STEP_SECS: f32 = 60.0;
+
+/// How quickly weather intensity eases toward its target, per sim second
+const WEATHER_EASE_RATE: f32 = 0.01;
+
+/// Environment settings for one world
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentConfig {
+    pub seed: u64,
+    /// Real seconds for a full day at a time dilation of 1.0
+    pub day_length_secs: f32,
+    pub days_per_season: u32,
+    pub latitude_degrees: f32,
+    pub time_dilation: f32,
+    /// Hour of day the simulation starts at, 0.0..24.0
+    pub start_hour: f32,
+    pub start_season: Season,
+    pub day_night_cycle: bool,
+    pub seasonal_changes: bool,
+    pub weather_enabled: bool,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0x5707_C0DE,
+            day_length_secs: 4.0 * 3600.0,
+            days_per_season: 7,
+            latitude_degrees: 35.0,
+            time_dilation: 1.0,
+            start_hour: 9.0,
+            start_season: Season::Spring,
+            day_night_cycle: true,
+            seasonal_changes: true,
+            weather_enabled: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    fn next(self) -> Self {
+        match self {
+            Season::Spring => Season::Summer,
+            Season::Summer => Season::Autumn,
+            Season::Autumn => Season::Winter,
+            Season::Winter => Season::Spring,
+        }
+    }
+
+    fn index(self) -> u32 {
+        match self {
+            Season::Spring => 0,
+            Season::Summer => 1,
+            Season::Autumn => 2,
+            Season::Winter => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeatherKind {
+    Clear,
+    Cloudy,
+    Overcast,
+    Fog,
+    Rain,
+    Storm,
+    Snow,
+}
+
+impl WeatherKind {
+    /// Name used by the Finalverse `WeatherChange` message
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "clear",
+            WeatherKind::Cloudy => "cloudy",
+            WeatherKind::Overcast => "overcast",
+            WeatherKind::Fog => "fog",
+            WeatherKind::Rain => "rain",
+            WeatherKind::Storm => "storm",
+            WeatherKind::Snow => "snow",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "clear" | "sunny" => Some(WeatherKind::Clear),
+            "cloudy" => Some(WeatherKind::Cloudy),
+            "overcast" => Some(WeatherKind::Overcast),
+            "fog" | "mist" => Some(WeatherKind::Fog),
+            "rain" => Some(WeatherKind::Rain),
+            "storm" | "thunderstorm" => Some(WeatherKind::Storm),
+            "snow" => Some(WeatherKind::Snow),
+            _ => None,
+        }
+    }
+
+    fn cloud_cover(self) -> f32 {
+        match self {
+            WeatherKind::Clear => 0.05,
+            WeatherKind::Cloudy => 0.45,
+            WeatherKind::Overcast => 0.85,
+            WeatherKind::Fog => 0.6,
+            WeatherKind::Rain => 0.9,
+            WeatherKind::Storm => 1.0,
+            WeatherKind::Snow => 0.9,
+        }
+    }
+
+    /// Weighted next states; the season then swaps rain and snow where it makes sense
+    fn transitions(self) -> &'static [(WeatherKind, u32)] {
+        match self {
+            WeatherKind::Clear => &[(WeatherKind::Clear, 80), (WeatherKind::Cloudy, 15), (WeatherKind::Fog, 5)],
+            WeatherKind::Cloudy => &[
+                (WeatherKind::Cloudy, 60),
+                (WeatherKind::Clear, 20),
+                (WeatherKind::Overcast, 15),
+                (WeatherKind::Rain, 5),
+            ],
+            WeatherKind::Overcast => &[
+                (WeatherKind::Overcast, 60),
+                (WeatherKind::Cloudy, 20),
+                (WeatherKind::Rain, 15),
+                (WeatherKind::Storm, 5),
+            ],
+            WeatherKind::Fog => &[(WeatherKind::Fog, 70), (WeatherKind::Clear, 20), (WeatherKind::Cloudy, 10)],
+            WeatherKind::Rain => &[
+                (WeatherKind::Rain, 65),
+                (WeatherKind::Overcast, 25),
+                (WeatherKind::Storm, 10),
+            ],
+            WeatherKind::Storm => &[(WeatherKind::Storm, 50), (WeatherKind::Rain, 50)],
+            WeatherKind::Snow => &[(WeatherKind::Snow, 70), (WeatherKind::Overcast, 30)],
+        }
+    }
+}
+
+/// Current weather
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WeatherState {
+    pub kind: WeatherKind,
+    /// 0.0..1.0, eases toward the target after each change
+    pub intensity: f32,
+    pub cloud_cover: f32,
+    pub wind_speed: f32,
+    /// Unit vector on the ground plane the wind blows toward
+    pub wind_direction: [f32; 2],
+}
+
+/// Snapshot of a world's environment
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentState {
+    /// Hour of day, 0.0..24.0
+    pub time_of_day: f32,
+    pub day: u32,
+    pub season: Season,
+    pub phase: DayPhase,
+    /// Unit vector toward the sun; z is up, x east, y north as in region coordinates
+    pub sun_direction: [f32; 3],
+    pub moon_direction: [f32; 3],
+    pub weather: WeatherState,
+}
+
+/// Parameters the renderer needs to light the scene
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LightingParams {
+    pub sun_direction: [f32; 3],
+    pub sun_color: [f32; 3],
+    pub sun_intensity: f32,
+    pub ambient_color: [f32; 3],
+    pub ambient_intensity: f32,
+    pub fog_density: f32,
+}
+
+/// Parameters for the ambient soundscape, each 0.0..1.0
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AmbienceParams {
+    pub wind: f32,
+    pub rain: f32,
+    pub thunder: f32,
+    pub birdsong: f32,
+    pub insects: f32,
+}
+
+/// Changes broadcast to subscribers
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvironmentEvent {
+    /// Sent once per in-world hour
+    TimeOfDay { world: String, time_of_day: f32, day: u32 },
+    PhaseChanged { world: String, phase: DayPhase },
+    SeasonChanged { world: String, season: Season },
+    WeatherChanged { world: String, from: WeatherKind, to: WeatherKind, intensity: f32 },
+}
+
+/// Small deterministic generator (SplitMix64); identical seeds give identical weather everywhere
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in 0.0..1.0
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Environment simulation for a single world
+#[derive(Debug, Clone)]
+pub struct EnvironmentSimulation {
+    config: EnvironmentConfig,
+    rng: SplitMix64,
+    state: EnvironmentState,
+    target_intensity: f32,
+    weather_clock: f32,
+    last_hour: u32,
+}
+
+impl EnvironmentSimulation {
+    pub fn new(config: EnvironmentConfig) -> Self {
+        let mut rng = SplitMix64(config.seed);
+        let wind_angle = rng.next_f32() * std::f32::consts::TAU;
+        let weather = WeatherState {
+            kind: WeatherKind::Clear,
+            intensity: 0.0,
+            cloud_cover: WeatherKind::Clear.cloud_cover(),
+            wind_speed: 2.0,
+            wind_direction: [wind_angle.cos(), wind_angle.sin()],
+        };
+        let time_of_day = config.start_hour.rem_euclid(24.0);
+
+        let mut simulation = Self {
+            rng,
+            state: EnvironmentState {
+                time_of_day,
+                day: 0,
+                season: config.start_season,
+                phase: DayPhase::Day,
+                sun_direction: [0.0, 0.0, 1.0],
+                moon_direction: [0.0, 0.0, -1.0],
+                weather,
+            },
+            target_intensity: 0.0,
+            weather_clock: 0.0,
+            last_hour: time_of_day as u32,
+            config,
+        };
+        simulation.update_sky();
+        simulation.state.phase = simulation.current_phase();
+        simulation
+    }
+
+    pub fn state(&self) -> &EnvironmentState {
+        &self.state
+    }
+
+    pub fn config(&self) -> &EnvironmentConfig {
+        &self.config
+    }
+
+    pub fn set_time_dilation(&mut self, time_dilation: f32) {
+        self.config.time_dilation = time_dilation.max(0.0);
+    }
+
+    /// Jump to an hour of day, e.g. when the server sends an authoritative time
+    pub fn set_time_of_day(&mut self, hour: f32) {
+        self.state.time_of_day = hour.rem_euclid(24.0);
+        self.last_hour = self.state.time_of_day as u32;
+        self.update_sky();
+        self.state.phase = self.current_phase();
+    }
+
+    /// Override the weather, e.g. from a server `WeatherChange` message
+    pub fn force_weather(&mut self, kind: WeatherKind, intensity: f32) {
+        self.state.weather.kind = kind;
+        self.state.weather.cloud_cover = kind.cloud_cover();
+        self.target_intensity = intensity.clamp(0.0, 1.0);
+        self.state.weather.intensity = self.target_intensity;
+    }
+
+    /// Advance by `delta_time` real seconds, returning what changed
+    pub fn update(&mut self, world: &str, delta_time: f32) -> Vec<EnvironmentEvent> {
+        let mut events = Vec::new();
+        let sim_secs = delta_time.max(0.0) * self.config.time_dilation;
+
+        if self.config.day_night_cycle && self.config.day_length_secs > 0.0 {
+            self.advance_clock(world, sim_secs * 24.0 / self.config.day_length_secs, &mut events);
+        }
+
+        if self.config.weather_enabled {
+            self.weather_clock += sim_secs;
+            while self.weather_clock >= WEATHER_STEP_SECS {
+                self.weather_clock -= WEATHER_STEP_SECS;
+                self.step_weather(world, &mut events);
+            }
+            let weather = &mut self.state.weather;
+            let ease = (WEATHER_EASE_RATE * sim_secs).min(1.0);
+            weather.intensity += (self.target_intensity - weather.intensity) * ease;
+            weather.cloud_cover += (weather.kind.cloud_cover() - weather.cloud_cover) * ease;
+        }
+
+        self.update_sky();
+        let phase = self.current_phase();
+        if phase != self.state.phase {
+            self.state.phase = phase;
+            events.push(EnvironmentEvent::PhaseChanged { world: world.to_string(), phase });
+        }
+
+        events
+    }
+
+    /// Lighting derived from the sun and weather
+    pub fn lighting(&self) -> LightingParams {
+        let sun = self.state.sun_direction;
+        let elevation = sun[2].max(0.0);
+        let weather = &self.state.weather;
+        let shade = 1.0 - 0.7 * weather.cloud_cover;
+
+        // Low sun is warmer and dimmer
+        let warmth = 1.0 - elevation.min(0.5) * 2.0;
+        let sun_color = [1.0, 0.95 - 0.35 * warmth, 0.9 - 0.6 * warmth];
+        let sun_intensity = (elevation * 4.0).min(1.0) * shade;
+
+        // Moonlight keeps nights from going fully black
+        let night_floor = 0.05;
+        let daylight = (sun[2] * 3.0 + 0.3).clamp(0.0, 1.0);
+        let ambient_intensity = (night_floor + 0.35 * daylight) * (1.0 - 0.3 * weather.cloud_cover);
+        let ambient_color = [
+            0.4 + 0.3 * daylight,
+            0.45 + 0.3 * daylight,
+            0.6 + 0.25 * daylight,
+        ];
+
+        let fog_density = match weather.kind {
+            WeatherKind::Fog => 0.02 + 0.06 * weather.intensity,
+            WeatherKind::Rain | WeatherKind::Snow => 0.005 + 0.01 * weather.intensity,
+            WeatherKind::Storm => 0.01 + 0.015 * weather.intensity,
+            _ => 0.001,
+        };
+
+        LightingParams {
+            sun_direction: sun,
+            sun_color,
+            sun_intensity,
+            ambient_color,
+            ambient_intensity,
+            fog_density,
+        }
+    }
+
+    /// Ambient sound levels derived from time, season and weather
+    pub fn ambience(&self) -> AmbienceParams {
+        let weather = &self.state.weather;
+        let precipitation = matches!(weather.kind, WeatherKind::Rain | WeatherKind::Storm);
+        let daytime = matches!(self.state.phase, DayPhase::Dawn | DayPhase::Day);
+        let calm = if precipitation { 0.2 } else { 1.0 };
+
+        let birdsong = match (daytime, self.state.season) {
+            (false, _) | (_, Season::Winter) => 0.0,
+            (true, _) if self.state.phase == DayPhase::Dawn => 1.0 * calm,
+            (true, _) => 0.5 * calm,
+        };
+        let insects = match (self.state.phase, self.state.season) {
+            (DayPhase::Night | DayPhase::Dusk, Season::Summer) => 0.8 * calm,
+            (DayPhase::Night | DayPhase::Dusk, Season::Spring | Season::Autumn) => 0.3 * calm,
+            _ => 0.0,
+        };
+
+        AmbienceParams {
+            wind: (weather.wind_speed / 20.0).clamp(0.0, 1.0),
+            rain: if precipitation { weather.intensity } else { 0.0 },
+            thunder: if weather.kind == WeatherKind::Storm { weather.intensity } else { 0.0 },
+            birdsong,
+            insects,
+        }
+    }
+
+    fn advance_clock(&mut self, world: &str, hours: f32, events: &mut Vec<EnvironmentEvent>) {
+        let mut time = self.state.time_of_day + hours;
+        while time >= 24.0 {
+            time -= 24.0;
+            self.state.day += 1;
+            if self.config.seasonal_changes
+                && self.config.days_per_season > 0
+                && self.state.day % self.config.days_per_season == 0
+            {
+                self.state.season = self.state.season.next();
+                events.push(EnvironmentEvent::SeasonChanged {
+                    world: world.to_string(),
+                    season: self.state.season,
+                });
+            }
+        }
+        self.state.time_of_day = time;
+
+        let hour = time as u32;
+        if hour != self.last_hour {
+            self.last_hour = hour;
+            events.push(EnvironmentEvent::TimeOfDay {
+                world: world.to_string(),
+                time_of_day: time,
+                day: self.state.day,
+            });
+        }
+    }
+
+    fn step_weather(&mut self, world: &str, events: &mut Vec<EnvironmentEvent>) {
+        let current = self.state.weather.kind;
+        let transitions = current.transitions();
+        let total: u32 = transitions.iter().map(|(_, weight)| weight).sum();
+        let mut roll = (self.rng.next_f32() * total as f32) as u32;
+
+        let mut next = current;
+        for (kind, weight) in transitions {
+            if roll < *weight {
+                next = *kind;
+                break;
+            }
+            roll -= weight;
+        }
+
+        next = match (next, self.state.season) {
+            (WeatherKind::Rain, Season::Winter) => WeatherKind::Snow,
+            (WeatherKind::Snow, Season::Spring | Season::Summer) => WeatherKind::Rain,
+            (kind, _) => kind,
+        };
+
+        // Wind drifts every step, gustier in bad weather
+        let gust = match next {
+            WeatherKind::Storm => 15.0,
+            WeatherKind::Rain | WeatherKind::Snow => 8.0,
+            WeatherKind::Fog => 1.0,
+            _ => 4.0,
+        };
+        let weather = &mut self.state.weather;
+        weather.wind_speed = gust * (0.5 + self.rng.next_f32());
+        let angle = weather.wind_direction[1].atan2(weather.wind_direction[0]) + (self.rng.next_f32() - 0.5) * 0.5;
+        weather.wind_direction = [angle.cos(), angle.sin()];
+
+        if next != current {
+            self.target_intensity = 0.3 + 0.7 * self.rng.next_f32();
+            self.state.weather.kind = next;
+            debug!("Weather in {} changed from {:?} to {:?}", world, current, next);
+            events.push(EnvironmentEvent::WeatherChanged {
+                world: world.to_string(),
+                from: current,
+                to: next,
+                intensity: self.target_intensity,
+            });
+        }
+    }
+
+    fn update_sky(&mut self) {
+        let latitude = self.config.latitude_degrees.to_radians();
+
+        // Day of a 365-day year, with spring starting at the March equinox
+        let days_per_season = self.config.days_per_season.max(1) as f32;
+        let season_progress = (self.state.day as f32 % days_per_season) / days_per_season;
+        let year_fraction = (self.state.season.index() as f32 + season_progress) / 4.0;
+        let declination = 23.44f32.to_radians() * (year_fraction * std::f32::consts::TAU).sin();
+
+        let hour_angle = ((self.state.time_of_day - 12.0) * 15.0).to_radians();
+        let east = -declination.cos() * hour_angle.sin();
+        let north = declination.sin() * latitude.cos() - declination.cos() * latitude.sin() * hour_angle.cos();
+        let up = declination.sin() * latitude.sin() + declination.cos() * latitude.cos() * hour_angle.cos();
+
+        self.state.sun_direction = [east, north, up];
+        self.state.moon_direction = [-east, -north, -up];
+    }
+
+    fn phase_for(sun_height: f32, time_of_day: f32) -> DayPhase {
+        // Roughly 6 degrees either side of the horizon counts as twilight
+        const TWILIGHT: f32 = 0.1;
+        if sun_height > TWILIGHT {
+            DayPhase::Day
+        } else if sun_height < -TWILIGHT {
+            DayPhase::Night
+        } else if time_of_day < 12.0 {
+            DayPhase::Dawn
+        } else {
+            DayPhase::Dusk
+        }
+    }
+
+    fn current_phase(&self) -> DayPhase {
+        Self::phase_for(self.state.sun_direction[2], self.state.time_of_day)
+    }
+}
+
+/// Runs the environment simulation for every connected world
+pub struct EnvironmentManager {
+    worlds: HashMap<String, EnvironmentSimulation>,
+    active_world: Option<String>,
+    events: broadcast::Sender<EnvironmentEvent>,
+}
+
+impl EnvironmentManager {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            worlds: HashMap::new(),
+            active_world: None,
+            events,
+        }
+    }
+
+    /// Start simulating a world; the most recently added world drives lighting and audio
+    pub fn add_world(&mut self, world: &str, config: EnvironmentConfig) {
+        self.worlds.insert(world.to_string(), EnvironmentSimulation::new(config));
+        self.active_world = Some(world.to_string());
+    }
+
+    pub fn remove_world(&mut self, world: &str) -> bool {
+        if self.active_world.as_deref() == Some(world) {
+            self.active_world = None;
+        }
+        self.worlds.remove(world).is_some()
+    }
+
+    pub fn set_active_world(&mut self, world: &str) -> bool {
+        let known = self.worlds.contains_key(world);
+        if known {
+            self.active_world = Some(world.to_string());
+        }
+        known
+    }
+
+    pub fn world(&self, world: &str) -> Option<&EnvironmentSimulation> {
+        self.worlds.get(world)
+    }
+
+    pub fn world_mut(&mut self, world: &str) -> Option<&mut EnvironmentSimulation> {
+        self.worlds.get_mut(world)
+    }
+
+    /// Simulation of the world currently shown to the user
+    pub fn active(&self) -> Option<&EnvironmentSimulation> {
+        self.active_world.as_deref().and_then(|world| self.worlds.get(world))
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EnvironmentEvent> {
+        self.events.subscribe()
+    }
+
+    /// Advance every world and broadcast the resulting events
+    pub fn update(&mut self, delta_time: f32) {
+        for (world, simulation) in self.worlds.iter_mut() {
+            for event in simulation.update(world, delta_time) {
+                // No subscribers is fine; events are advisory
+                let _ = self.events.send(event);
+            }
+        }
+    }
+}
+
+impl Default for EnvironmentManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,10 +3,48 @@
 
 use thiserror::Error;
 
+use storm_ai::AiError;
+use storm_assets::{AssetError, AssetId};
+#[cfg(feature = "audio")]
+use storm_audio::AudioError;
+use storm_ecs::{EcsError, EntityId};
+use storm_networking::{ConnectionId, NetworkError};
+#[cfg(feature = "physics")]
+use storm_physics::PhysicsError;
+use storm_protocol_adapters::ProtocolError;
+#[cfg(feature = "rendering")]
+use storm_rendering::RenderError;
+
 /// StormCore result type alias
 pub type StormResult<T> = Result<T, StormError>;
 
+/// Subsystem an error originated in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorDomain {
+    Core,
+    Network,
+    Protocol,
+    Ecs,
+    Ai,
+    Rendering,
+    Audio,
+    Physics,
+    Asset,
+    Platform,
+}
+
+/// Structured details attached to an error
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub connection_id: Option<ConnectionId>,
+    pub entity: Option<EntityId>,
+    pub asset_id: Option<AssetId>,
+}
+
 /// Comprehensive error types for StormCore engine
+///
+/// Subsystem errors keep their typed form, so codes, retry hints and context survive the
+/// trip to the FFI and WASM layers.
 #[derive(Error, Debug)]
 pub enum StormError {
     #[error("Initialization failed: {0}")]
@@ -16,28 +54,31 @@ pub enum StormError {
     ConfigurationError(String),
 
     #[error("Network error: {0}")]
-    NetworkError(String),
+    NetworkError(#[from] NetworkError),
 
     #[error("Protocol error: {0}")]
-    ProtocolError(String),
+    ProtocolError(#[from] ProtocolError),
 
     #[error("ECS error: {0}")]
-    EcsError(String),
+    EcsError(#[from] EcsError),
 
     #[error("AI dispatcher error: {0}")]
-    AiError(String),
+    AiError(#[from] AiError),
 
+    #[cfg(feature = "rendering")]
     #[error("Rendering error: {0}")]
-    RenderingError(String),
+    RenderingError(#[from] RenderError),
 
+    #[cfg(feature = "audio")]
     #[error("Audio error: {0}")]
-    AudioError(String),
+    AudioError(#[from] AudioError),
 
+    #[cfg(feature = "physics")]
     #[error("Physics error: {0}")]
-    PhysicsError(String),
+    PhysicsError(#[from] PhysicsError),
 
     #[error("Asset loading error: {0}")]
-    AssetError(String),
+    AssetError(#[from] AssetError),
 
     #[error("Platform not supported: {0}")]
     PlatformNotSupported(String),
@@ -52,23 +93,219 @@ pub enum StormError {
     TaskJoinError(#[from] tokio::task::JoinError),
 
     #[error("Generic error: {0}")]
-    Generic(String),
+    Generic(#[source] anyhow::Error),
+}
+
+impl StormError {
+    /// Wrap a networking failure, keeping a typed `NetworkError` if one is inside
+    pub fn network(err: anyhow::Error) -> Self {
+        StormError::NetworkError(err.downcast().unwrap_or_else(NetworkError::Other))
+    }
+
+    /// Wrap a protocol adapter failure; transport errors stay network errors
+    pub fn protocol(err: anyhow::Error) -> Self {
+        match err.downcast::<NetworkError>() {
+            Ok(err) => StormError::NetworkError(err),
+            Err(err) => StormError::ProtocolError(err.downcast().unwrap_or_else(ProtocolError::Other)),
+        }
+    }
+
+    pub fn ai(err: anyhow::Error) -> Self {
+        StormError::AiError(err.downcast().unwrap_or_else(AiError::Other))
+    }
+
+    pub fn asset(err: anyhow::Error) -> Self {
+        StormError::AssetError(err.downcast().unwrap_or_else(AssetError::Other))
+    }
+
+    #[cfg(feature = "rendering")]
+    pub fn rendering(err: anyhow::Error) -> Self {
+        StormError::RenderingError(err.downcast().unwrap_or_else(RenderError::Other))
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn audio(err: anyhow::Error) -> Self {
+        StormError::AudioError(err.downcast().unwrap_or_else(AudioError::Other))
+    }
+
+    #[cfg(feature = "physics")]
+    pub fn physics(err: anyhow::Error) -> Self {
+        StormError::PhysicsError(err.downcast().unwrap_or_else(PhysicsError::Other))
+    }
+
+    /// Wrap the boxed error returned by `World` operations
+    pub fn ecs(err: Box<dyn std::error::Error>) -> Self {
+        match err.downcast::<EcsError>() {
+            Ok(err) => StormError::EcsError(*err),
+            Err(err) => StormError::EcsError(EcsError::SystemFailed {
+                system: "world",
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    /// Stable numeric code
+    ///
+    /// Subsystem errors use their crate's range (network 1000, protocol 2000, ECS 3000,
+    /// AI 4000, assets 5000, rendering 6000, audio 7000, physics 8000); engine-level errors
+    /// use the 100 range.
+    pub fn code(&self) -> u32 {
+        match self {
+            StormError::InitializationError(_) => 101,
+            StormError::ConfigurationError(_) => 102,
+            StormError::PlatformNotSupported(_) => 103,
+            StormError::IoError(_) => 104,
+            StormError::SerializationError(_) => 105,
+            StormError::TaskJoinError(_) => 106,
+            #[cfg(feature = "rendering")]
+            StormError::RenderingError(err) => err.code(),
+            #[cfg(feature = "audio")]
+            StormError::AudioError(err) => err.code(),
+            #[cfg(feature = "physics")]
+            StormError::PhysicsError(err) => err.code(),
+            StormError::NetworkError(err) => err.code(),
+            StormError::ProtocolError(err) => err.code(),
+            StormError::EcsError(err) => err.code(),
+            StormError::AiError(err) => err.code(),
+            StormError::AssetError(err) => err.code(),
+            StormError::Generic(_) => 100,
+        }
+    }
+
+    pub fn domain(&self) -> ErrorDomain {
+        match self {
+            StormError::NetworkError(_) => ErrorDomain::Network,
+            StormError::ProtocolError(_) => ErrorDomain::Protocol,
+            StormError::EcsError(_) => ErrorDomain::Ecs,
+            StormError::AiError(_) => ErrorDomain::Ai,
+            #[cfg(feature = "rendering")]
+            StormError::RenderingError(_) => ErrorDomain::Rendering,
+            #[cfg(feature = "audio")]
+            StormError::AudioError(_) => ErrorDomain::Audio,
+            #[cfg(feature = "physics")]
+            StormError::PhysicsError(_) => ErrorDomain::Physics,
+            StormError::AssetError(_) => ErrorDomain::Asset,
+            StormError::PlatformNotSupported(_) => ErrorDomain::Platform,
+            _ => ErrorDomain::Core,
+        }
+    }
+
+    /// Whether repeating the failed operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StormError::NetworkError(err) => err.is_retryable(),
+            StormError::ProtocolError(err) => err.is_retryable(),
+            StormError::EcsError(err) => err.is_retryable(),
+            StormError::AiError(err) => err.is_retryable(),
+            StormError::AssetError(err) => err.is_retryable(),
+            #[cfg(feature = "rendering")]
+            StormError::RenderingError(err) => err.is_retryable(),
+            #[cfg(feature = "audio")]
+            StormError::AudioError(err) => err.is_retryable(),
+            #[cfg(feature = "physics")]
+            StormError::PhysicsError(err) => err.is_retryable(),
+            StormError::IoError(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the engine should be shut down rather than kept running
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            StormError::InitializationError(_)
+            | StormError::ConfigurationError(_)
+            | StormError::PlatformNotSupported(_) => true,
+            StormError::NetworkError(err) => err.is_fatal(),
+            StormError::ProtocolError(err) => err.is_fatal(),
+            StormError::EcsError(err) => err.is_fatal(),
+            StormError::AiError(err) => err.is_fatal(),
+            StormError::AssetError(err) => err.is_fatal(),
+            #[cfg(feature = "rendering")]
+            StormError::RenderingError(err) => err.is_fatal(),
+            #[cfg(feature = "audio")]
+            StormError::AudioError(err) => err.is_fatal(),
+            #[cfg(feature = "physics")]
+            StormError::PhysicsError(err) => err.is_fatal(),
+            _ => false,
+        }
+    }
+
+    /// Connection, entity and asset the error relates to
+    pub fn context(&self) -> ErrorContext {
+        match self {
+            StormError::NetworkError(err) => ErrorContext {
+                connection_id: err.connection_id(),
+                ..Default::default()
+            },
+            StormError::ProtocolError(err) => ErrorContext {
+                connection_id: err.connection_id(),
+                ..Default::default()
+            },
+            StormError::EcsError(err) => ErrorContext {
+                entity: err.entity(),
+                ..Default::default()
+            },
+            StormError::AssetError(err) => ErrorContext {
+                asset_id: err.asset_id(),
+                ..Default::default()
+            },
+            _ => ErrorContext::default(),
+        }
+    }
 }
 
 impl From<anyhow::Error> for StormError {
     fn from(err: anyhow::Error) -> Self {
-        StormError::Generic(err.to_string())
+        // Recover typed subsystem errors that travelled through anyhow
+        let err = match err.downcast::<NetworkError>() {
+            Ok(err) => return StormError::NetworkError(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ProtocolError>() {
+            Ok(err) => return StormError::ProtocolError(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<AiError>() {
+            Ok(err) => return StormError::AiError(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<AssetError>() {
+            Ok(err) => return StormError::AssetError(err),
+            Err(err) => err,
+        };
+        #[cfg(feature = "rendering")]
+        let err = match err.downcast::<RenderError>() {
+            Ok(err) => return StormError::RenderingError(err),
+            Err(err) => err,
+        };
+        #[cfg(feature = "audio")]
+        let err = match err.downcast::<AudioError>() {
+            Ok(err) => return StormError::AudioError(err),
+            Err(err) => err,
+        };
+        #[cfg(feature = "physics")]
+        let err = match err.downcast::<PhysicsError>() {
+            Ok(err) => return StormError::PhysicsError(err),
+            Err(err) => err,
+        };
+        match err.downcast::<EcsError>() {
+            Ok(err) => StormError::EcsError(err),
+            Err(err) => StormError::Generic(err),
+        }
     }
 }
 
 impl From<&str> for StormError {
     fn from(err: &str) -> Self {
-        StormError::Generic(err.to_string())
+        StormError::Generic(anyhow::anyhow!(err.to_string()))
     }
 }
 
 impl From<String> for StormError {
     fn from(err: String) -> Self {
-        StormError::Generic(err)
+        StormError::Generic(anyhow::anyhow!(err))
     }
-}
\ No newline at end of file
+}
@@ -2,13 +2,17 @@
 // StormCore - Main library entry point and public API
 // Coordinates all major subsystems and exposes core functionality via FFI
 
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
-use tracing::{info, span, Level};
+use tracing::{debug_span, info, info_span, span, Instrument, Level};
 
 // Re-export major modules for internal use
 pub mod core;
 pub mod error;
+pub mod environment;
+pub mod metrics;
 
 // Re-export from workspace crates
 pub use storm_ecs as ecs;
@@ -29,7 +33,9 @@ pub use storm_physics as physics;
 
 // Public API types
 pub use core::{StormConfig, WorldConfig, ProtocolType, PlatformType, RenderBackend};
-pub use error::{StormError, StormResult};
+pub use error::{ErrorContext, ErrorDomain, StormError, StormResult};
+pub use environment::{EnvironmentConfig, EnvironmentEvent, EnvironmentManager, EnvironmentState};
+pub use metrics::{EngineMetrics, MetricsRegistry, MetricsSnapshot};
 
 /// StormCore - The main engine coordination struct
 /// Manages all subsystems and provides unified API for virtual world interactions
@@ -39,6 +45,9 @@ pub struct StormCore {
     ai_dispatcher: Arc<ai::AIDispatcher>,
     network_manager: Arc<RwLock<networking::NetworkManager>>, // Changed to RwLock for mutable access
     protocol_router: Arc<protocol_adapters::ProtocolRouter>,
+    environment: Arc<RwLock<EnvironmentManager>>,
+    asset_manager: Arc<assets::AssetManager>,
+    metrics: Arc<EngineMetrics>,
 
     #[cfg(feature = "rendering")]
     render_pipeline: Option<Arc<rendering::RenderPipeline>>,
@@ -63,14 +72,14 @@ impl StormCore {
         let ai_config: ai::AIConfig = config.ai_config.clone().into();
         let ai_dispatcher = Arc::new(
             ai::AIDispatcher::new(&ai_config).await
-                .map_err(|e| StormError::AiError(e.to_string()))?
+                .map_err(StormError::ai)?
         );
 
         // Initialize networking with protocol support - convert config
         let network_config: networking::NetworkConfig = config.network_config.clone().into();
         let network_manager = Arc::new(RwLock::new(
             networking::NetworkManager::new(&network_config).await
-                .map_err(|e| StormError::NetworkError(e.to_string()))?
+                .map_err(StormError::network)?
         ));
 
         // Initialize protocol adapters for OpenSim/MutSea and Finalverse
@@ -79,7 +88,7 @@ impl StormCore {
                 ecs_world.clone(),
                 ai_dispatcher.clone()
             ).await
-                .map_err(|e| StormError::ProtocolError(e.to_string()))?
+                .map_err(StormError::protocol)?
         );
 
         // Optional rendering pipeline (platform-dependent)
@@ -88,7 +97,7 @@ impl StormCore {
             let render_config: rendering::RenderConfig = config.render_config.clone().into();
             Some(Arc::new(
                 rendering::RenderPipeline::new(&render_config).await
-                    .map_err(|e| StormError::RenderingError(e.to_string()))?
+                    .map_err(StormError::rendering)?
             ))
         } else {
             None
@@ -100,7 +109,7 @@ impl StormCore {
             let audio_config: audio_engine::AudioConfig = config.audio_config.clone().into();
             Some(Arc::new(
                 audio_engine::AudioEngine::new(&audio_config).await
-                    .map_err(|e| StormError::AudioError(e.to_string()))?
+                    .map_err(StormError::audio)?
             ))
         } else {
             None
@@ -112,12 +121,14 @@ impl StormCore {
             let physics_config: physics::PhysicsConfig = config.physics_config.clone().into();
             Some(Arc::new(RwLock::new(
                 physics::PhysicsWorld::new(&physics_config)
-                    .map_err(|e| StormError::PhysicsError(e.to_string()))?
+                    .map_err(StormError::physics)?
             )))
         } else {
             None
         };
 
+        let asset_manager = Arc::new(assets::AssetManager::new(&config.asset_path));
+
         info!("StormCore engine initialized successfully");
 
         Ok(StormCore {
@@ -126,6 +137,9 @@ impl StormCore {
             ai_dispatcher,
             network_manager,
             protocol_router,
+            environment: Arc::new(RwLock::new(EnvironmentManager::new())),
+            asset_manager,
+            metrics: Arc::new(EngineMetrics::new()),
 
             #[cfg(feature = "rendering")]
             render_pipeline,
@@ -174,7 +188,11 @@ impl StormCore {
         // Initialize world-specific ECS entities and components
         let mut world = self.ecs_world.write().await;
         world.initialize_for_world(&ecs_config)
-            .map_err(|e| StormError::EcsError(format!("{:?}", e)))?;
+            .map_err(StormError::ecs)?;
+
+        // Start the sky and weather for this world
+        self.environment.write().await
+            .add_world(&world_config.name, self.config.environment_config.clone());
 
         info!("Successfully connected to world: {}", world_config.name);
         Ok(())
@@ -182,49 +200,181 @@ impl StormCore {
 
     /// Main engine update loop - should be called each frame
     pub async fn update(&self, delta_time: f32) -> StormResult<()> {
+        let frame_start = Instant::now();
+        let result = self
+            .update_stages(delta_time)
+            .instrument(info_span!("storm_update", delta_time))
+            .await;
+
+        self.metrics.observe_frame(frame_start.elapsed());
+        self.collect_metrics().await;
+        result
+    }
+
+    async fn update_stages(&self, delta_time: f32) -> StormResult<()> {
         // Update ECS systems
-        {
+        self.stage("ecs", async {
             let mut world = self.ecs_world.write().await;
-            world.update(delta_time)
-                .map_err(|e| StormError::EcsError(format!("{:?}", e)))?;
+            world.update(delta_time).map_err(StormError::ecs)
+        })
+        .await?;
+
+        // Advance time of day and weather, then feed lighting and ambience
+        let (lighting, ambience) = self
+            .stage("environment", async {
+                let mut environment = self.environment.write().await;
+                environment.update(delta_time);
+                match environment.active() {
+                    Some(active) => (Some(active.lighting()), Some(active.ambience())),
+                    None => (None, None),
+                }
+            })
+            .await;
+
+        #[cfg(feature = "rendering")]
+        if let (Some(renderer), Some(lighting)) = (&self.render_pipeline, lighting) {
+            renderer.set_environment_lighting(lighting.into());
+        }
+
+        // Hand terrain that changed since the last frame to physics and rendering
+        #[cfg(any(feature = "rendering", feature = "physics"))]
+        self.stage("terrain", self.sync_terrain()).await;
+
+        // Rebuild avatar models whose appearance changed and drop those that left
+        #[cfg(feature = "rendering")]
+        self.stage("avatars", self.sync_avatars()).await;
+
+        #[cfg(feature = "audio")]
+        if let (Some(audio), Some(ambience)) = (&self.audio_engine, ambience) {
+            audio.set_ambience(ambience.into());
         }
 
         // Process AI enhancements asynchronously
-        self.ai_dispatcher.process_pending_requests().await
-            .map_err(|e| StormError::AiError(e.to_string()))?;
+        self.stage("ai", self.ai_dispatcher.process_pending_requests())
+            .await
+            .map_err(StormError::ai)?;
 
         // Update networking - now with proper RwLock access
-        {
-            let mut network = self.network_manager.write().await;
-            network.update().await
-                .map_err(|e| StormError::NetworkError(e.to_string()))?;
-        }
+        self.stage("network", async {
+            let network = self.network_manager.write().await;
+            network.update().await.map_err(StormError::network)
+        })
+        .await?;
 
         // Update rendering if enabled
         #[cfg(feature = "rendering")]
         if let Some(ref renderer) = self.render_pipeline {
-            renderer.update(delta_time).await
-                .map_err(|e| StormError::RenderingError(e.to_string()))?;
+            self.stage("rendering", renderer.update(delta_time))
+                .await
+                .map_err(StormError::rendering)?;
         }
 
         // Update audio if enabled
         #[cfg(feature = "audio")]
         if let Some(ref audio) = self.audio_engine {
-            audio.update(delta_time).await
-                .map_err(|e| StormError::AudioError(e.to_string()))?;
+            self.stage("audio", audio.update(delta_time))
+                .await
+                .map_err(StormError::audio)?;
         }
 
         // Update physics if enabled - now with proper RwLock access
         #[cfg(feature = "physics")]
         if let Some(ref physics_arc) = self.physics_world {
-            let mut physics = physics_arc.write().await;
-            physics.update(delta_time)
-                .map_err(|e| StormError::PhysicsError(e.to_string()))?;
+            self.stage("physics", async {
+                let mut physics = physics_arc.write().await;
+                physics.update(delta_time).map_err(StormError::physics)
+            })
+            .await?;
         }
 
         Ok(())
     }
 
+    #[cfg(any(feature = "rendering", feature = "physics"))]
+    async fn sync_terrain(&self) {
+        let world = self.ecs_world.read().await;
+
+        #[cfg(feature = "physics")]
+        if let Some(ref physics_arc) = self.physics_world {
+            let mut physics = physics_arc.write().await;
+            for (_, heightfield) in world.query::<ecs::Heightfield>() {
+                if physics.terrain_revision(heightfield.region_handle) != Some(heightfield.revision) {
+                    physics.set_terrain(heightfield);
+                }
+            }
+        }
+
+        #[cfg(feature = "rendering")]
+        if let Some(ref renderer) = self.render_pipeline {
+            for (_, heightfield) in world.query::<ecs::Heightfield>() {
+                if renderer.terrain_revision(heightfield.region_handle) != Some(heightfield.revision) {
+                    renderer.update_terrain(rendering::TerrainMesh::from_heights(
+                        heightfield.region_handle,
+                        heightfield.revision,
+                        heightfield.width,
+                        heightfield.depth,
+                        &heightfield.heights,
+                    ));
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "rendering")]
+    async fn sync_avatars(&self) {
+        let Some(ref renderer) = self.render_pipeline else {
+            return;
+        };
+        let world = self.ecs_world.read().await;
+        let mut present = std::collections::HashSet::new();
+        for (entity, appearance) in world.query::<ecs::AvatarAppearance>() {
+            present.insert(entity.id);
+            if renderer.avatar_revision(entity.id) == Some(appearance.revision) {
+                continue;
+            }
+            let mut baked_textures = [None; 6];
+            for (slot, region) in baked_textures.iter_mut().zip(ecs::BakedRegion::ALL) {
+                *slot = appearance.baked_texture(region).map(|texture| texture.as_u128());
+            }
+            renderer.update_avatar(rendering::AvatarModel {
+                entity_id: entity.id,
+                revision: appearance.revision,
+                baked_textures,
+                visual_params: appearance.visual_params.clone(),
+                size: appearance.size,
+                hover_height: appearance.hover_height,
+            });
+        }
+        for entity_id in renderer.avatar_entities() {
+            if !present.contains(&entity_id) {
+                renderer.remove_avatar(entity_id);
+            }
+        }
+    }
+
+    /// Run one update stage inside its span and record how long it took
+    async fn stage<T>(&self, name: &'static str, work: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let output = work.instrument(debug_span!("update_stage", stage = name)).await;
+        self.metrics.observe_stage(name, start.elapsed());
+        output
+    }
+
+    /// Copy subsystem counters into the metrics registry
+    async fn collect_metrics(&self) {
+        let traffic = self.network_manager.read().await.traffic_stats().await;
+        self.metrics.record_traffic(&traffic);
+
+        for completed in self.ai_dispatcher.drain_metrics() {
+            self.metrics.record_ai(&completed);
+        }
+
+        let entities = self.ecs_world.read().await.entity_count();
+        self.metrics.record_entity_count(entities);
+
+        self.metrics.record_asset_cache(&self.asset_manager.get_cache_stats().await);
+    }
+
     /// Shutdown engine gracefully
     pub async fn shutdown(&self) -> StormResult<()> {
         info!("Shutting down StormCore engine");
@@ -233,24 +383,24 @@ impl StormCore {
         #[cfg(feature = "rendering")]
         if let Some(ref renderer) = self.render_pipeline {
             renderer.shutdown().await
-                .map_err(|e| StormError::RenderingError(e.to_string()))?;
+                .map_err(StormError::rendering)?;
         }
 
         #[cfg(feature = "audio")]
         if let Some(ref audio) = self.audio_engine {
             audio.shutdown().await
-                .map_err(|e| StormError::AudioError(e.to_string()))?;
+                .map_err(StormError::audio)?;
         }
 
         // Shutdown network manager with proper RwLock access
         {
             let network = self.network_manager.read().await;
             network.shutdown().await
-                .map_err(|e| StormError::NetworkError(e.to_string()))?;
+                .map_err(StormError::network)?;
         }
 
         self.ai_dispatcher.shutdown().await
-            .map_err(|e| StormError::AiError(e.to_string()))?;
+            .map_err(StormError::ai)?;
 
         info!("StormCore engine shutdown complete");
         Ok(())
@@ -269,6 +419,30 @@ impl StormCore {
         self.network_manager.clone()
     }
 
+    /// Shared asset cache; subsystems that fetch assets insert into it
+    pub fn asset_manager(&self) -> Arc<assets::AssetManager> {
+        self.asset_manager.clone()
+    }
+
+    /// Frame timings and subsystem counters, exportable as Prometheus text or JSON
+    pub fn metrics(&self) -> Arc<EngineMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Teleport, region crossing and neighbour events for the UI
+    pub fn subscribe_region_events(&self) -> tokio::sync::broadcast::Receiver<protocol_adapters::RegionEvent> {
+        self.protocol_router.subscribe_region_events()
+    }
+
+    pub fn environment(&self) -> Arc<RwLock<EnvironmentManager>> {
+        self.environment.clone()
+    }
+
+    /// Time of day, season and weather changes for every connected world
+    pub async fn subscribe_environment_events(&self) -> tokio::sync::broadcast::Receiver<EnvironmentEvent> {
+        self.environment.read().await.subscribe()
+    }
+
     #[cfg(feature = "physics")]
     pub fn physics_world(&self) -> Option<Arc<RwLock<physics::PhysicsWorld>>> {
         self.physics_world.clone()
@@ -298,4 +472,147 @@ mod tests {
         let core = StormCore::new(config).await;
         assert!(core.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_environment_is_deterministic_from_seed() {
+        let config = EnvironmentConfig {
+            day_length_secs: 600.0,
+            ..Default::default()
+        };
+        let mut a = environment::EnvironmentSimulation::new(config.clone());
+        let mut b = environment::EnvironmentSimulation::new(config);
+
+        let mut events_a = Vec::new();
+        let mut events_b = Vec::new();
+        for _ in 0..2000 {
+            events_a.extend(a.update("test", 1.0));
+            events_b.extend(b.update("test", 1.0));
+        }
+
+        assert_eq!(a.state(), b.state());
+        assert_eq!(events_a, events_b);
+        assert!(events_a.iter().any(|e| matches!(e, EnvironmentEvent::TimeOfDay { .. })));
+    }
+
+    #[test]
+    fn test_sun_follows_time_of_day() {
+        let mut simulation = environment::EnvironmentSimulation::new(EnvironmentConfig::default());
+
+        simulation.set_time_of_day(12.0);
+        assert!(simulation.state().sun_direction[2] > 0.5);
+        assert_eq!(simulation.state().phase, environment::DayPhase::Day);
+        let noon = simulation.lighting();
+
+        simulation.set_time_of_day(0.0);
+        assert!(simulation.state().sun_direction[2] < 0.0);
+        assert_eq!(simulation.state().phase, environment::DayPhase::Night);
+        let midnight = simulation.lighting();
+
+        assert_eq!(midnight.sun_intensity, 0.0);
+        assert!(noon.ambient_intensity > midnight.ambient_intensity);
+    }
+
+    #[test]
+    fn test_forced_weather_drives_ambience() {
+        let mut simulation = environment::EnvironmentSimulation::new(EnvironmentConfig::default());
+        simulation.force_weather(environment::WeatherKind::Storm, 0.8);
+
+        let ambience = simulation.ambience();
+        assert_eq!(ambience.rain, 0.8);
+        assert_eq!(ambience.thunder, 0.8);
+        assert!(simulation.lighting().fog_density > 0.01);
+    }
+
+    #[test]
+    fn test_typed_errors_survive_anyhow() {
+        let connection_id = uuid::Uuid::new_v4();
+        let err: anyhow::Error = networking::NetworkError::Timeout {
+            connection_id: Some(connection_id),
+        }
+        .into();
+
+        let storm_error = StormError::from(err.context("sending AgentUpdate"));
+        assert_eq!(storm_error.domain(), ErrorDomain::Network);
+        assert_eq!(storm_error.code(), 1003);
+        assert!(storm_error.is_retryable());
+        assert!(!storm_error.is_fatal());
+        assert_eq!(storm_error.context().connection_id, Some(connection_id));
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let asset_id = uuid::Uuid::new_v4();
+        let missing = StormError::asset(assets::AssetError::NotFound { asset_id }.into());
+        assert_eq!(missing.code(), 5003);
+        assert_eq!(missing.context().asset_id, Some(asset_id));
+
+        let protocol = StormError::protocol(anyhow::anyhow!("adapter exploded"));
+        assert_eq!(protocol.domain(), ErrorDomain::Protocol);
+        assert_eq!(protocol.code(), 2000);
+
+        let init = StormError::InitializationError("no GPU".to_string());
+        assert!(init.is_fatal());
+        assert!(!init.is_retryable());
+    }
+
+    #[test]
+    #[cfg(all(feature = "rendering", feature = "audio", feature = "physics"))]
+    fn test_subsystem_errors_keep_codes() {
+        let backend = rendering::RenderError::BackendUnavailable { backend: rendering::RenderBackend::Metal };
+        let render = StormError::rendering(anyhow::Error::from(backend).context("creating pipeline"));
+        assert_eq!(render.domain(), ErrorDomain::Rendering);
+        assert_eq!(render.code(), 6001);
+        assert!(render.is_fatal());
+
+        let audio = StormError::from(anyhow::Error::from(audio_engine::AudioError::TooManySources { limit: 64 }));
+        assert_eq!(audio.domain(), ErrorDomain::Audio);
+        assert_eq!(audio.code(), 7003);
+        assert!(audio.is_retryable());
+
+        let physics = StormError::physics(anyhow::anyhow!("solver diverged"));
+        assert_eq!(physics.code(), 8000);
+        assert!(!physics.is_retryable());
+    }
+
+    #[test]
+    fn test_metrics_export() {
+        let metrics = EngineMetrics::new();
+        metrics.observe_frame(std::time::Duration::from_millis(3));
+        metrics.observe_stage("ecs", std::time::Duration::from_micros(800));
+        metrics.record_traffic(&networking::TrafficStats {
+            packets_in: 12,
+            bytes_in: 3400,
+            ..Default::default()
+        });
+        metrics.record_entity_count(42);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("# TYPE storm_frame_seconds histogram"));
+        assert!(text.contains("storm_frame_seconds_bucket{le=\"0.004\"} 1"));
+        assert!(text.contains("storm_frame_seconds_bucket{le=\"0.002\"} 0"));
+        assert!(text.contains("storm_update_stage_seconds_count{stage=\"ecs\"} 1"));
+        assert!(text.contains("storm_network_packets_total{direction=\"in\"} 12"));
+        assert!(text.contains("storm_ecs_entities 42"));
+
+        let snapshot = metrics.snapshot();
+        let bytes = snapshot.get("storm_network_bytes_total", &[("direction", "in")]).unwrap();
+        assert_eq!(bytes.value, Some(3400.0));
+        assert!(metrics.to_json().unwrap().contains("storm_ecs_entities"));
+    }
+
+    #[tokio::test]
+    async fn test_asset_cache_metrics() {
+        let assets = assets::AssetManager::new("assets");
+        let asset_id = uuid::Uuid::new_v4();
+        assets.insert_asset(asset_id, "blob", assets::AssetType::Raw, assets::AssetData::Raw(vec![0; 64]), 1.0).await;
+        assert!(assets.get_asset(asset_id).await.is_some());
+        assert!(assets.get_asset(uuid::Uuid::new_v4()).await.is_none());
+
+        let metrics = EngineMetrics::new();
+        metrics.record_asset_cache(&assets.get_cache_stats().await);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("storm_asset_cache_hit_ratio 0.5"));
+        assert!(text.contains("storm_asset_cache_assets 1"));
+    }
+}
@@ -0,0 +1,448 @@
+// File: crates/storm-core/src/metrics.rs
+// Engine metrics: counters, gauges and histograms with Prometheus and JSON export
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::AIMetrics;
+use crate::assets::CacheStats;
+use crate::networking::TrafficStats;
+
+/// Bucket bounds in seconds for frame and stage timings
+pub const TIMING_BUCKETS: &[f64] = &[0.0005, 0.001, 0.002, 0.004, 0.008, 0.016, 0.033, 0.066, 0.1, 0.25, 0.5, 1.0];
+
+/// Bucket bounds in seconds for AI request latency
+pub const AI_LATENCY_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Update stages timed by `StormCore::update`
+pub const UPDATE_STAGES: &[&str] = &["ecs", "environment", "ai", "network", "rendering", "audio", "physics"];
+
+/// Monotonically increasing count
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Mirror a total that is counted elsewhere, such as the network manager's traffic counters
+    pub fn set(&self, total: u64) {
+        self.0.store(total, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value that can go up and down
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Distribution of observations over fixed buckets
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// One count per bound plus the +Inf bucket, not cumulative
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let index = self.bounds.iter().position(|bound| value <= *bound).unwrap_or(self.bounds.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        let mut current = self.sum.load(Ordering::Relaxed);
+        loop {
+            let updated = (f64::from_bits(current) + value).to_bits();
+            match self.sum.compare_exchange_weak(current, updated, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let mut buckets = Vec::with_capacity(self.bounds.len());
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            buckets.push((*bound, cumulative));
+        }
+
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum: f64::from_bits(self.sum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Cumulative bucket counts, as in Prometheus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    /// (upper bound, observations at or below it)
+    pub buckets: Vec<(f64, u64)>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+enum Series {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+struct Family {
+    help: String,
+    kind: MetricKind,
+    series: BTreeMap<Labels, Series>,
+}
+
+/// Named metric families, each holding one series per label set
+#[derive(Default)]
+pub struct MetricsRegistry {
+    families: RwLock<BTreeMap<String, Family>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get or create a counter; panics if `name` is already registered as another kind
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Counter> {
+        match self.series(name, help, MetricKind::Counter, labels, || Series::Counter(Arc::default())) {
+            Series::Counter(counter) => counter,
+            _ => unreachable!("series kind matches family kind"),
+        }
+    }
+
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Gauge> {
+        match self.series(name, help, MetricKind::Gauge, labels, || Series::Gauge(Arc::default())) {
+            Series::Gauge(gauge) => gauge,
+            _ => unreachable!("series kind matches family kind"),
+        }
+    }
+
+    pub fn histogram(&self, name: &str, help: &str, labels: &[(&str, &str)], bounds: &[f64]) -> Arc<Histogram> {
+        let create = || Series::Histogram(Arc::new(Histogram::new(bounds)));
+        match self.series(name, help, MetricKind::Histogram, labels, create) {
+            Series::Histogram(histogram) => histogram,
+            _ => unreachable!("series kind matches family kind"),
+        }
+    }
+
+    fn series(
+        &self,
+        name: &str,
+        help: &str,
+        kind: MetricKind,
+        labels: &[(&str, &str)],
+        create: impl FnOnce() -> Series,
+    ) -> Series {
+        let mut families = self.families.write().unwrap();
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            kind,
+            series: BTreeMap::new(),
+        });
+        assert_eq!(family.kind, kind, "metric {} registered as {:?}", name, family.kind);
+
+        let labels: Labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        match family.series.entry(labels).or_insert_with(create) {
+            Series::Counter(counter) => Series::Counter(counter.clone()),
+            Series::Gauge(gauge) => Series::Gauge(gauge.clone()),
+            Series::Histogram(histogram) => Series::Histogram(histogram.clone()),
+        }
+    }
+
+    /// Current value of every series
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let families = self.families.read().unwrap();
+        let mut metrics = Vec::new();
+
+        for (name, family) in families.iter() {
+            for (labels, series) in &family.series {
+                let (value, histogram) = match series {
+                    Series::Counter(counter) => (Some(counter.get() as f64), None),
+                    Series::Gauge(gauge) => (Some(gauge.get()), None),
+                    Series::Histogram(histogram) => (None, Some(histogram.snapshot())),
+                };
+                metrics.push(MetricSample {
+                    name: name.clone(),
+                    kind: family.kind,
+                    labels: labels.iter().cloned().collect(),
+                    value,
+                    histogram,
+                });
+            }
+        }
+
+        MetricsSnapshot {
+            timestamp_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            metrics,
+        }
+    }
+
+    /// Render every series in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let families = self.families.read().unwrap();
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind.as_str());
+
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Counter(counter) => {
+                        let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), counter.get());
+                    }
+                    Series::Gauge(gauge) => {
+                        let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), gauge.get());
+                    }
+                    Series::Histogram(histogram) => {
+                        let snapshot = histogram.snapshot();
+                        for (bound, count) in &snapshot.buckets {
+                            let le = bound.to_string();
+                            let _ = writeln!(out, "{}_bucket{} {}", name, format_labels(labels, Some(&le)), count);
+                        }
+                        let _ = writeln!(out, "{}_bucket{} {}", name, format_labels(labels, Some("+Inf")), snapshot.count);
+                        let _ = writeln!(out, "{}_sum{} {}", name, format_labels(labels, None), snapshot.sum);
+                        let _ = writeln!(out, "{}_count{} {}", name, format_labels(labels, None), snapshot.count);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// JSON-friendly copy of every series
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub timestamp_ms: u64,
+    pub metrics: Vec<MetricSample>,
+}
+
+impl MetricsSnapshot {
+    /// Find a series by name and labels
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<&MetricSample> {
+        self.metrics.iter().find(|sample| {
+            sample.name == name
+                && sample.labels.len() == labels.len()
+                && labels.iter().all(|(k, v)| sample.labels.get(*k).map(String::as_str) == Some(*v))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricSample {
+    pub name: String,
+    pub kind: MetricKind,
+    pub labels: BTreeMap<String, String>,
+    /// Counter or gauge value
+    pub value: Option<f64>,
+    pub histogram: Option<HistogramSnapshot>,
+}
+
+/// The engine's standard metrics, registered up front so the hot path only touches atomics
+pub struct EngineMetrics {
+    registry: MetricsRegistry,
+    frame_seconds: Arc<Histogram>,
+    frames: Arc<Counter>,
+    stage_seconds: HashMap<&'static str, Arc<Histogram>>,
+    packets_in: Arc<Counter>,
+    packets_out: Arc<Counter>,
+    bytes_in: Arc<Counter>,
+    bytes_out: Arc<Counter>,
+    resends: Arc<Counter>,
+    connections: Arc<Gauge>,
+    ai_requests: Arc<Counter>,
+    ai_cache_hits: Arc<Counter>,
+    ai_latency_seconds: Arc<Histogram>,
+    asset_cache_hit_ratio: Arc<Gauge>,
+    asset_cache_bytes: Arc<Gauge>,
+    asset_count: Arc<Gauge>,
+    ecs_entities: Arc<Gauge>,
+}
+
+impl Default for EngineMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EngineMetrics {
+    pub fn new() -> Self {
+        let registry = MetricsRegistry::new();
+
+        let stage_seconds = UPDATE_STAGES
+            .iter()
+            .map(|stage| {
+                let histogram = registry.histogram(
+                    "storm_update_stage_seconds",
+                    "Time spent in each StormCore::update stage",
+                    &[("stage", stage)],
+                    TIMING_BUCKETS,
+                );
+                (*stage, histogram)
+            })
+            .collect();
+
+        Self {
+            frame_seconds: registry.histogram("storm_frame_seconds", "Total StormCore::update time", &[], TIMING_BUCKETS),
+            frames: registry.counter("storm_frames_total", "Frames updated", &[]),
+            stage_seconds,
+            packets_in: registry.counter("storm_network_packets_total", "Packets moved by the network manager", &[("direction", "in")]),
+            packets_out: registry.counter("storm_network_packets_total", "Packets moved by the network manager", &[("direction", "out")]),
+            bytes_in: registry.counter("storm_network_bytes_total", "Bytes moved by the network manager", &[("direction", "in")]),
+            bytes_out: registry.counter("storm_network_bytes_total", "Bytes moved by the network manager", &[("direction", "out")]),
+            resends: registry.counter("storm_network_resends_total", "Reliable packets retransmitted", &[]),
+            connections: registry.gauge("storm_network_connections", "Open connections", &[]),
+            ai_requests: registry.counter("storm_ai_requests_total", "Completed AI requests", &[]),
+            ai_cache_hits: registry.counter("storm_ai_cache_hits_total", "AI requests served from cache", &[]),
+            ai_latency_seconds: registry.histogram("storm_ai_latency_seconds", "AI request latency", &[], AI_LATENCY_BUCKETS),
+            asset_cache_hit_ratio: registry.gauge("storm_asset_cache_hit_ratio", "Fraction of asset lookups served from cache", &[]),
+            asset_cache_bytes: registry.gauge("storm_asset_cache_bytes", "Estimated memory held by cached assets", &[]),
+            asset_count: registry.gauge("storm_asset_cache_assets", "Assets held in cache", &[]),
+            ecs_entities: registry.gauge("storm_ecs_entities", "Live ECS entities", &[]),
+            registry,
+        }
+    }
+
+    /// Registry for metrics outside the standard set
+    pub fn registry(&self) -> &MetricsRegistry {
+        &self.registry
+    }
+
+    pub fn observe_frame(&self, duration: Duration) {
+        self.frames.inc();
+        self.frame_seconds.observe_duration(duration);
+    }
+
+    pub fn observe_stage(&self, stage: &str, duration: Duration) {
+        if let Some(histogram) = self.stage_seconds.get(stage) {
+            histogram.observe_duration(duration);
+        }
+    }
+
+    pub fn record_traffic(&self, traffic: &TrafficStats) {
+        self.packets_in.set(traffic.packets_in);
+        self.packets_out.set(traffic.packets_out);
+        self.bytes_in.set(traffic.bytes_in);
+        self.bytes_out.set(traffic.bytes_out);
+        self.resends.set(traffic.resends);
+        self.connections.set(traffic.active_connections as f64);
+    }
+
+    pub fn record_ai(&self, metrics: &AIMetrics) {
+        self.ai_requests.inc();
+        if metrics.cache_hit {
+            self.ai_cache_hits.inc();
+        }
+        self.ai_latency_seconds.observe(metrics.latency_ms as f64 / 1000.0);
+    }
+
+    /// Fed from the engine's `AssetManager::get_cache_stats` on every update
+    pub fn record_asset_cache(&self, stats: &CacheStats) {
+        self.asset_cache_hit_ratio.set(stats.cache_hit_rate as f64);
+        self.asset_cache_bytes.set(stats.memory_usage as f64);
+        self.asset_count.set(stats.asset_count as f64);
+    }
+
+    pub fn record_entity_count(&self, count: usize) {
+        self.ecs_entities.set(count as f64);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.registry.snapshot()
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        self.registry.to_prometheus()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.snapshot())
+    }
+}
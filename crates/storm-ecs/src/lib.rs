@@ -30,6 +30,47 @@ impl Entity {
     }
 }
 
+/// ECS failures with stable codes in the 3000 range
+#[derive(Debug, thiserror::Error)]
+pub enum EcsError {
+    #[error("Entity {entity} does not exist")]
+    EntityNotFound { entity: EntityId },
+
+    #[error("Entity {entity} has no {component} component")]
+    ComponentMissing { entity: EntityId, component: &'static str },
+
+    #[error("System {system} failed: {message}")]
+    SystemFailed { system: &'static str, message: String },
+}
+
+impl EcsError {
+    pub fn code(&self) -> u32 {
+        match self {
+            EcsError::EntityNotFound { .. } => 3001,
+            EcsError::ComponentMissing { .. } => 3002,
+            EcsError::SystemFailed { .. } => 3003,
+        }
+    }
+
+    /// ECS operations are deterministic, so repeating them gives the same result
+    pub fn is_retryable(&self) -> bool {
+        false
+    }
+
+    /// A failing system leaves the world partially updated
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, EcsError::SystemFailed { .. })
+    }
+
+    /// Entity the error relates to, if any
+    pub fn entity(&self) -> Option<EntityId> {
+        match self {
+            EcsError::EntityNotFound { entity } | EcsError::ComponentMissing { entity, .. } => Some(*entity),
+            EcsError::SystemFailed { .. } => None,
+        }
+    }
+}
+
 /// ECS World - manages all entities and components
 pub struct World {
     next_entity_id: AtomicU64,
@@ -56,6 +97,11 @@ impl World {
         entity
     }
 
+    /// Number of live entities
+    pub fn entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
     /// Remove an entity and all its components
     pub fn remove_entity(&mut self, entity: Entity) -> bool {
         if self.entities.remove(&entity.id).is_some() {
@@ -127,15 +173,22 @@ impl World {
         // Temporarily take the systems out of self
         let mut systems = std::mem::take(&mut self.systems);
 
-        // Update each system
+        // Update each system, stopping at the first failure
+        let mut result = Ok(());
         for system in &mut systems {
-            system.update(self, delta_time)?;
+            if let Err(e) = system.update(self, delta_time) {
+                result = Err(EcsError::SystemFailed {
+                    system: system.name(),
+                    message: e.to_string(),
+                });
+                break;
+            }
         }
 
         // Put the systems back
         self.systems = systems;
 
-        Ok(())
+        result.map_err(Into::into)
     }
 
     /// Initialize world for a specific virtual world - Fixed to use local WorldConfig
@@ -195,6 +248,11 @@ impl ComponentStorage {
 /// System trait for ECS processing
 pub trait System: Send + Sync {
     fn update(&mut self, world: &mut World, delta_time: f32) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Name reported when the system fails
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 // Core Components
@@ -261,6 +319,136 @@ impl Component for Velocity {
     }
 }
 
+/// Terrain elevation samples for one region, updated in place as patches arrive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heightfield {
+    /// Region the terrain belongs to, used as its key by physics and rendering
+    pub region_handle: u64,
+    /// Samples along x
+    pub width: u32,
+    /// Samples along y
+    pub depth: u32,
+    /// Row-major heights in meters, `y * width + x`
+    pub heights: Vec<f32>,
+    /// Bumped on every change so consumers can tell when to resync
+    pub revision: u64,
+}
+
+impl Heightfield {
+    pub fn new(region_handle: u64, width: u32, depth: u32) -> Self {
+        Self {
+            region_handle,
+            width,
+            depth,
+            heights: vec![0.0; (width * depth) as usize],
+            revision: 0,
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<f32> {
+        (x < self.width && y < self.depth).then(|| self.heights[(y * self.width + x) as usize])
+    }
+
+    /// Copy a square block of samples with its corner at (x, y); samples outside the field are dropped
+    pub fn set_block(&mut self, x: u32, y: u32, size: u32, samples: &[f32]) {
+        for row in 0..size.min(self.depth.saturating_sub(y)) {
+            for column in 0..size.min(self.width.saturating_sub(x)) {
+                let index = ((y + row) * self.width + x + column) as usize;
+                self.heights[index] = samples[(row * size + column) as usize];
+            }
+        }
+        self.revision += 1;
+    }
+
+    /// Bilinearly interpolated height at a point in region meters, clamped to the field
+    pub fn height_at(&self, x: f32, y: f32) -> f32 {
+        if self.width == 0 || self.depth == 0 {
+            return 0.0;
+        }
+        let x = x.clamp(0.0, (self.width - 1) as f32);
+        let y = y.clamp(0.0, (self.depth - 1) as f32);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.depth - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let sample = |x, y| self.heights[(y * self.width + x) as usize];
+        let near = sample(x0, y0) + (sample(x1, y0) - sample(x0, y0)) * fx;
+        let far = sample(x0, y1) + (sample(x1, y1) - sample(x0, y1)) * fx;
+        near + (far - near) * fy
+    }
+}
+
+impl Component for Heightfield {
+    fn type_name() -> &'static str {
+        "Heightfield"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Body regions an avatar's skin and clothing layers are baked into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BakedRegion {
+    Head,
+    UpperBody,
+    LowerBody,
+    Eyes,
+    Skirt,
+    Hair,
+}
+
+impl BakedRegion {
+    pub const ALL: [BakedRegion; 6] = [
+        BakedRegion::Head,
+        BakedRegion::UpperBody,
+        BakedRegion::LowerBody,
+        BakedRegion::Eyes,
+        BakedRegion::Skirt,
+        BakedRegion::Hair,
+    ];
+}
+
+/// How an avatar looks: one baked texture per body region plus its shape parameters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AvatarAppearance {
+    pub agent_id: uuid::Uuid,
+    /// Baked textures by region; regions without one are left out
+    pub baked_textures: Vec<(BakedRegion, uuid::Uuid)>,
+    /// Shape and layer parameters from 0 to 1, in the order the protocol sends them
+    pub visual_params: Vec<f32>,
+    /// Bounding box in meters, z being the standing height
+    pub size: [f32; 3],
+    /// Offset above the ground the owner chose, in meters
+    pub hover_height: f32,
+    /// Bumped on every change so consumers can tell when to resync
+    pub revision: u64,
+}
+
+impl AvatarAppearance {
+    pub fn baked_texture(&self, region: BakedRegion) -> Option<uuid::Uuid> {
+        self.baked_textures.iter().find(|(baked, _)| *baked == region).map(|(_, texture)| *texture)
+    }
+}
+
+impl Component for AvatarAppearance {
+    fn type_name() -> &'static str {
+        "AvatarAppearance"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
 /// Simple protocol type for this crate
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProtocolType {
@@ -396,4 +584,42 @@ mod tests {
         let transform = world.get_component::<Transform>(entity).unwrap();
         assert_eq!(transform.position[0], 1.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_heightfield_blocks_and_interpolation() {
+        let mut field = Heightfield::new(1, 4, 4);
+        field.set_block(2, 2, 2, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(field.revision, 1);
+        assert_eq!(field.get(3, 3), Some(4.0));
+        assert_eq!(field.get(4, 0), None);
+        assert_eq!(field.height_at(2.5, 2.0), 1.5);
+        assert_eq!(field.height_at(2.5, 2.5), 2.5);
+        assert_eq!(field.height_at(10.0, 10.0), 4.0);
+
+        // Blocks hanging over the edge keep only what fits
+        field.set_block(3, 3, 2, &[9.0, 9.0, 9.0, 9.0]);
+        assert_eq!(field.get(3, 3), Some(9.0));
+    }
+
+    struct FailingSystem;
+
+    impl System for FailingSystem {
+        fn update(&mut self, _world: &mut World, _delta_time: f32) -> Result<(), Box<dyn std::error::Error>> {
+            Err("out of bounds".into())
+        }
+    }
+
+    #[test]
+    fn test_failing_system_reports_ecs_error() {
+        let mut world = World::new();
+        world.add_system(FailingSystem);
+
+        let error = world.update(0.1).unwrap_err();
+        let ecs_error = error.downcast_ref::<EcsError>().unwrap();
+        assert_eq!(ecs_error.code(), 3003);
+        assert!(ecs_error.to_string().contains("FailingSystem"));
+
+        // Systems survive the failure and run again next frame
+        assert!(world.update(0.1).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 // File: crates/storm-ffi/src/error.rs
 // FFI error handling
 
+use std::cell::RefCell;
+use std::ffi::CString;
+
 /// FFI error codes
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,4 +20,56 @@ impl From<anyhow::Error> for ErrorCode {
     fn from(_: anyhow::Error) -> Self {
         ErrorCode::GenericError
     }
-}
\ No newline at end of file
+}
+/// Detailed description of the last error raised on the calling thread
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CErrorInfo {
+    /// Coarse category, identical to the value the failing call returned
+    pub category: crate::StormErrorCode,
+    /// Stable numeric code from `StormError::code`
+    pub code: u32,
+    pub retryable: bool,
+    pub fatal: bool,
+    /// Entity the error relates to, 0 when none
+    pub entity_id: u64,
+    pub has_connection_id: bool,
+    pub connection_id: [u8; 16],
+    pub has_asset_id: bool,
+    pub asset_id: [u8; 16],
+}
+
+struct LastError {
+    info: CErrorInfo,
+    message: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+/// Record `error` as the calling thread's last error and return its category
+pub(crate) fn record_error(error: &storm_core::StormError, category: crate::StormErrorCode) -> crate::StormErrorCode {
+    let context = error.context();
+    let info = CErrorInfo {
+        category,
+        code: error.code(),
+        retryable: error.is_retryable(),
+        fatal: error.is_fatal(),
+        entity_id: context.entity.unwrap_or(0),
+        has_connection_id: context.connection_id.is_some(),
+        connection_id: context.connection_id.map(|id| *id.as_bytes()).unwrap_or_default(),
+        has_asset_id: context.asset_id.is_some(),
+        asset_id: context.asset_id.map(|id| *id.as_bytes()).unwrap_or_default(),
+    };
+    // Interior NULs cannot cross the C boundary
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(LastError { info, message }));
+    category
+}
+
+/// Run `f` against the calling thread's last error, if any
+pub(crate) fn with_last_error<R>(f: impl FnOnce(Option<(&CErrorInfo, &CString)>) -> R) -> R {
+    LAST_ERROR.with(|last| f(last.borrow().as_ref().map(|error| (&error.info, &error.message))))
+}
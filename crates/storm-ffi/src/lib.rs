@@ -367,7 +367,11 @@ pub unsafe extern "C" fn storm_get_entity_transform(
         };
         StormErrorCode::Success
     } else {
-        StormErrorCode::EcsError
+        let error = storm_ecs::EcsError::ComponentMissing {
+            entity: entity_id,
+            component: "Transform",
+        };
+        error_from_storm_error(error.into())
     }
 }
 
@@ -384,12 +388,36 @@ pub unsafe extern "C" fn storm_get_version() -> *const c_char {
 /// Get last error message
 ///
 /// # Safety
-/// Returns a pointer to internal storage that may be invalidated by subsequent calls.
+/// Returns a pointer to thread-local storage that is invalidated by the next failing call on
+/// the same thread.
 #[no_mangle]
 pub unsafe extern "C" fn storm_get_last_error() -> *const c_char {
-    // In a real implementation, this would return the last error message
-    static ERROR_MSG: &[u8] = b"No error\0";
-    ERROR_MSG.as_ptr() as *const c_char
+    static NO_ERROR: &[u8] = b"No error\0";
+    with_last_error(|last| match last {
+        Some((_, message)) => message.as_ptr(),
+        None => NO_ERROR.as_ptr() as *const c_char,
+    })
+}
+
+/// Get the code, retry hint and context of the last error on this thread
+///
+/// Returns false when no error has been recorded.
+///
+/// # Safety
+/// out_info must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn storm_get_last_error_info(out_info: *mut CErrorInfo) -> bool {
+    if out_info.is_null() {
+        return false;
+    }
+
+    with_last_error(|last| match last {
+        Some((info, _)) => {
+            *out_info = *info;
+            true
+        }
+        None => false,
+    })
 }
 
 // Helper functions for conversions
@@ -415,21 +443,24 @@ fn protocol_from_u32(value: u32) -> storm_core::ProtocolType {
 }
 
 fn error_from_storm_error(error: storm_core::StormError) -> StormErrorCode {
-    use storm_core::StormError;
-
-    match error {
-        StormError::InitializationError(_) => StormErrorCode::InitializationFailed,
-        StormError::NetworkError(_) => StormErrorCode::NetworkError,
-        StormError::ProtocolError(_) => StormErrorCode::ProtocolError,
-        StormError::EcsError(_) => StormErrorCode::EcsError,
-        StormError::AiError(_) => StormErrorCode::AiError,
-        StormError::RenderingError(_) => StormErrorCode::RenderingError,
-        StormError::AudioError(_) => StormErrorCode::AudioError,
-        StormError::PhysicsError(_) => StormErrorCode::PhysicsError,
-        StormError::AssetError(_) => StormErrorCode::AssetError,
-        StormError::PlatformNotSupported(_) => StormErrorCode::PlatformNotSupported,
-        _ => StormErrorCode::GenericError,
-    }
+    use storm_core::{ErrorDomain, StormError};
+
+    let category = match error.domain() {
+        ErrorDomain::Network => StormErrorCode::NetworkError,
+        ErrorDomain::Protocol => StormErrorCode::ProtocolError,
+        ErrorDomain::Ecs => StormErrorCode::EcsError,
+        ErrorDomain::Ai => StormErrorCode::AiError,
+        ErrorDomain::Rendering => StormErrorCode::RenderingError,
+        ErrorDomain::Audio => StormErrorCode::AudioError,
+        ErrorDomain::Physics => StormErrorCode::PhysicsError,
+        ErrorDomain::Asset => StormErrorCode::AssetError,
+        ErrorDomain::Platform => StormErrorCode::PlatformNotSupported,
+        ErrorDomain::Core => match error {
+            StormError::InitializationError(_) => StormErrorCode::InitializationFailed,
+            _ => StormErrorCode::GenericError,
+        },
+    };
+    record_error(&error, category)
 }
 
 #[cfg(test)]
@@ -453,4 +484,26 @@ mod tests {
         assert_eq!(platform_from_u32(1), storm_core::PlatformType::iOS);
         assert_eq!(platform_from_u32(999), storm_core::PlatformType::detect());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_last_error_info() {
+        let error = storm_ecs::EcsError::ComponentMissing {
+            entity: 42,
+            component: "Transform",
+        };
+        assert_eq!(error_from_storm_error(error.into()), StormErrorCode::EcsError);
+
+        unsafe {
+            let mut info = std::mem::MaybeUninit::<CErrorInfo>::uninit();
+            assert!(storm_get_last_error_info(info.as_mut_ptr()));
+            let info = info.assume_init();
+            assert_eq!(info.category, StormErrorCode::EcsError);
+            assert_eq!(info.code, 3002);
+            assert_eq!(info.entity_id, 42);
+            assert!(!info.retryable);
+
+            let message = CStr::from_ptr(storm_get_last_error()).to_str().unwrap();
+            assert!(message.contains("Transform"));
+        }
+    }
+}
@@ -28,6 +28,7 @@ pub enum FinalverseMessage {
     WorldUpdate { entities: Vec<EntityUpdate> },
     EntitySpawn { entity: EntityData },
     EntityDespawn { entity_id: String },
+    RegionChange { region_id: String, region_name: String, origin: [f64; 2], position: [f32; 3] },
 
     // User actions
     Movement { position: [f32; 3], rotation: [f32; 4] },
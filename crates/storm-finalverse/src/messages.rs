@@ -321,6 +321,7 @@ impl FinalverseMessage {
             FinalverseMessage::WorldUpdate { .. } => "WorldUpdate",
             FinalverseMessage::EntitySpawn { .. } => "EntitySpawn",
             FinalverseMessage::EntityDespawn { .. } => "EntityDespawn",
+            FinalverseMessage::RegionChange { .. } => "RegionChange",
             FinalverseMessage::Movement { .. } => "Movement",
             FinalverseMessage::Chat { .. } => "Chat",
             FinalverseMessage::Interaction { .. } => "Interaction",
@@ -348,6 +349,7 @@ impl FinalverseMessage {
             FinalverseMessage::WorldUpdate { .. } => 7,
             FinalverseMessage::EntitySpawn { .. } => 7,
             FinalverseMessage::EntityDespawn { .. } => 7,
+            FinalverseMessage::RegionChange { .. } => 9,
             FinalverseMessage::Interaction { .. } => 5,
             FinalverseMessage::AiRequest { .. } => 4,
             FinalverseMessage::AiResponse { .. } => 4,
@@ -0,0 +1,79 @@
+// File: crates/storm-networking/benches/transforms.rs
+// Throughput and size savings of the packet transform stages
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use storm_networking::{train_dictionary, AeadTransform, PacketTransform, ZstdTransform};
+
+/// Object-update-like packets: a fixed header, an id and some slowly changing floats
+fn sample_packets(count: usize, size: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let mut packet = Vec::with_capacity(size);
+            packet.extend_from_slice(b"\xffObjectUpdate\x00\x01region=1000,1000;");
+            packet.extend_from_slice(&(i as u32 % 50).to_le_bytes());
+            while packet.len() < size {
+                let value = (i as f32 * 0.25) + (packet.len() as f32 / 8.0).floor();
+                packet.extend_from_slice(&value.to_le_bytes());
+            }
+            packet.truncate(size);
+            packet
+        })
+        .collect()
+}
+
+fn encoded_size(transform: &mut dyn PacketTransform, packets: &[Vec<u8>]) -> usize {
+    packets
+        .iter()
+        .map(|packet| transform.encode(packet.clone()).expect("encode").len())
+        .sum()
+}
+
+fn compression(c: &mut Criterion) {
+    let training = sample_packets(2000, 96);
+    let dictionary = train_dictionary(&training, 4096).expect("dictionary training");
+
+    let mut group = c.benchmark_group("zstd");
+    for size in [48usize, 96, 512, 4096] {
+        let packets = sample_packets(256, size);
+        let raw: usize = packets.iter().map(Vec::len).sum();
+
+        let mut plain = ZstdTransform::new(3, None).expect("compressor");
+        let mut primed = ZstdTransform::new(3, Some(&dictionary)).expect("compressor");
+        println!(
+            "{} byte packets: {:.1}% of original without a dictionary, {:.1}% with one",
+            size,
+            encoded_size(&mut plain, &packets) as f64 * 100.0 / raw as f64,
+            encoded_size(&mut primed, &packets) as f64 * 100.0 / raw as f64,
+        );
+
+        group.throughput(Throughput::Bytes(raw as u64));
+        group.bench_with_input(BenchmarkId::new("plain", size), &packets, |b, packets| {
+            b.iter(|| encoded_size(&mut plain, black_box(packets)))
+        });
+        group.bench_with_input(BenchmarkId::new("dictionary", size), &packets, |b, packets| {
+            b.iter(|| encoded_size(&mut primed, black_box(packets)))
+        });
+    }
+    group.finish();
+}
+
+fn encryption(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chacha20-poly1305");
+    for size in [64usize, 1200, 16 * 1024] {
+        let packet = vec![0x5a; size];
+        let mut sender = AeadTransform::new(&[1; 32], &[2; 32]).expect("keys");
+        let mut receiver = AeadTransform::new(&[2; 32], &[1; 32]).expect("keys");
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("seal+open", size), &packet, |b, packet| {
+            b.iter(|| {
+                let sealed = sender.encode(black_box(packet.clone())).expect("seal");
+                receiver.decode(sealed).expect("open")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compression, encryption);
+criterion_main!(benches);
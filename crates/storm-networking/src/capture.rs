@@ -0,0 +1,485 @@
+// File: crates/storm-networking/src/capture.rs
+// Packet capture to pcapng with a custom link type, and replay of recorded sessions
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+use anyhow::Result;
+
+use crate::{ConnectionId, IncomingPacket, NetworkManager, PacketPriority, ProtocolType};
+
+/// pcapng link type for StormCore records (LINKTYPE_USER0)
+pub const CAPTURE_LINK_TYPE: u16 = 147;
+/// Version of the per-packet record header
+pub const CAPTURE_RECORD_VERSION: u8 = 1;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const RECORD_HEADER_SIZE: usize = 39;
+/// Blocks larger than this are treated as corruption rather than allocated
+const MAX_BLOCK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Whether a packet was received or sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// One captured packet with its connection metadata
+///
+/// Data is recorded as the application sees it: after decryption and decompression on the
+/// way in, before them on the way out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub timestamp: SystemTime,
+    pub direction: CaptureDirection,
+    pub connection_id: ConnectionId,
+    pub protocol: ProtocolType,
+    /// Send priority; not known for received packets
+    pub priority: Option<PacketPriority>,
+    pub remote_addr: Option<SocketAddr>,
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    /// Record layout: version, direction, protocol, priority (255 when unknown), connection id,
+    /// address family (0, 4 or 6), 16 address bytes, big-endian port, then the packet data
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(RECORD_HEADER_SIZE + self.data.len());
+        out.push(CAPTURE_RECORD_VERSION);
+        out.push(match self.direction {
+            CaptureDirection::Incoming => 0,
+            CaptureDirection::Outgoing => 1,
+        });
+        out.push(protocol_code(self.protocol));
+        out.push(self.priority.map(|priority| priority as u8).unwrap_or(u8::MAX));
+        out.extend_from_slice(self.connection_id.as_bytes());
+
+        let mut address = [0u8; 16];
+        let (family, port) = match self.remote_addr {
+            Some(SocketAddr::V4(addr)) => {
+                address[..4].copy_from_slice(&addr.ip().octets());
+                (4, addr.port())
+            }
+            Some(SocketAddr::V6(addr)) => {
+                address.copy_from_slice(&addr.ip().octets());
+                (6, addr.port())
+            }
+            None => (0, 0),
+        };
+        out.push(family);
+        out.extend_from_slice(&address);
+        out.extend_from_slice(&port.to_be_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    fn decode(data: &[u8], timestamp: SystemTime) -> Result<Self> {
+        if data.len() < RECORD_HEADER_SIZE {
+            return Err(anyhow::anyhow!("Capture record of {} bytes is too short", data.len()));
+        }
+        if data[0] != CAPTURE_RECORD_VERSION {
+            return Err(anyhow::anyhow!("Unsupported capture record version {}", data[0]));
+        }
+
+        let direction = match data[1] {
+            0 => CaptureDirection::Incoming,
+            1 => CaptureDirection::Outgoing,
+            other => return Err(anyhow::anyhow!("Unknown capture direction {}", other)),
+        };
+        let protocol = protocol_from_code(data[2])?;
+        let priority = match data[3] {
+            0 => Some(PacketPriority::Low),
+            1 => Some(PacketPriority::Normal),
+            2 => Some(PacketPriority::High),
+            3 => Some(PacketPriority::Critical),
+            _ => None,
+        };
+        let connection_id = ConnectionId::from_slice(&data[4..20])?;
+        let address: [u8; 16] = data[21..37].try_into().expect("length checked above");
+        let port = u16::from_be_bytes([data[37], data[38]]);
+        let remote_addr = match data[20] {
+            4 => Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(address[0], address[1], address[2], address[3])),
+                port,
+            )),
+            6 => Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(address)), port)),
+            _ => None,
+        };
+
+        Ok(Self {
+            timestamp,
+            direction,
+            connection_id,
+            protocol,
+            priority,
+            remote_addr,
+            data: data[RECORD_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+fn protocol_code(protocol: ProtocolType) -> u8 {
+    match protocol {
+        ProtocolType::LLUDP => 0,
+        ProtocolType::WebSocket => 1,
+        ProtocolType::QUIC => 2,
+        ProtocolType::TCP => 3,
+    }
+}
+
+fn protocol_from_code(code: u8) -> Result<ProtocolType> {
+    Ok(match code {
+        0 => ProtocolType::LLUDP,
+        1 => ProtocolType::WebSocket,
+        2 => ProtocolType::QUIC,
+        3 => ProtocolType::TCP,
+        other => return Err(anyhow::anyhow!("Unknown protocol code {} in capture", other)),
+    })
+}
+
+/// Writes capture records as a pcapng file with one StormCore interface
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture, writing the section header and interface description
+    pub fn new(mut writer: W) -> Result<Self> {
+        // Section header: byte-order magic, version 1.0, unknown section length
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        write_block(&mut writer, SECTION_HEADER_BLOCK, &body)?;
+
+        // Interface description: link type, reserved, no snap length limit; microsecond timestamps
+        let mut body = Vec::with_capacity(8);
+        body.extend_from_slice(&CAPTURE_LINK_TYPE.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        write_block(&mut writer, INTERFACE_DESCRIPTION_BLOCK, &body)?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write_record(&mut self, record: &CaptureRecord) -> Result<()> {
+        let micros = record
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let packet = record.encode();
+
+        let mut body = Vec::with_capacity(20 + packet.len() + 3);
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(micros as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&packet);
+        body.resize(body.len().next_multiple_of(4), 0);
+        write_block(&mut self.writer, ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<()> {
+    let total = (12 + body.len()) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&total.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&total.to_le_bytes())?;
+    Ok(())
+}
+
+/// Reads capture records back from a pcapng stream
+///
+/// Blocks of other types and packets on interfaces with other link types are skipped, so
+/// captures merged with ordinary network traces still load.
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    /// Link type of each interface in the current section
+    interfaces: Vec<u16>,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            big_endian: false,
+            interfaces: Vec::new(),
+        }
+    }
+
+    fn u16_at(&self, data: &[u8], offset: usize) -> u16 {
+        let bytes = [data[offset], data[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32_at(&self, data: &[u8], offset: usize) -> u32 {
+        let bytes = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    /// Next block type and body, or `None` at a clean end of file
+    fn next_block(&mut self) -> Result<Option<(u32, Vec<u8>)>> {
+        let mut header = [0u8; 8];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let raw_type = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        if raw_type == SECTION_HEADER_BLOCK {
+            // The byte-order magic decides how this section, including its length, is read
+            let mut magic = [0u8; 4];
+            self.reader.read_exact(&mut magic)?;
+            self.big_endian = match u32::from_le_bytes(magic) {
+                BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err(anyhow::anyhow!("Not a pcapng capture")),
+            };
+            self.interfaces.clear();
+            let total = self.u32_at(&header, 4) as usize;
+            let body = self.read_body(total, 4)?;
+            return Ok(Some((SECTION_HEADER_BLOCK, body)));
+        }
+
+        let block_type = self.u32_at(&header, 0);
+        let total = self.u32_at(&header, 4) as usize;
+        let body = self.read_body(total, 0)?;
+        Ok(Some((block_type, body)))
+    }
+
+    /// Read the rest of a block, dropping the trailing length; `consumed` body bytes are already read
+    fn read_body(&mut self, total: usize, consumed: usize) -> Result<Vec<u8>> {
+        if total < 12 + consumed || !total.is_multiple_of(4) || total > MAX_BLOCK_SIZE {
+            return Err(anyhow::anyhow!("Corrupt pcapng block length {}", total));
+        }
+        let mut body = vec![0u8; total - 8 - consumed];
+        self.reader.read_exact(&mut body)?;
+        body.truncate(body.len() - 4);
+        Ok(body)
+    }
+
+    fn next_record(&mut self) -> Result<Option<CaptureRecord>> {
+        while let Some((block_type, body)) = self.next_block()? {
+            match block_type {
+                INTERFACE_DESCRIPTION_BLOCK if body.len() >= 2 => {
+                    let link_type = self.u16_at(&body, 0);
+                    self.interfaces.push(link_type);
+                }
+                ENHANCED_PACKET_BLOCK if body.len() >= 20 => {
+                    let interface = self.u32_at(&body, 0) as usize;
+                    if self.interfaces.get(interface) != Some(&CAPTURE_LINK_TYPE) {
+                        continue;
+                    }
+                    let micros = ((self.u32_at(&body, 4) as u64) << 32) | self.u32_at(&body, 8) as u64;
+                    let captured = self.u32_at(&body, 12) as usize;
+                    let data = body
+                        .get(20..20 + captured)
+                        .ok_or_else(|| anyhow::anyhow!("Truncated pcapng packet block"))?;
+                    let timestamp = UNIX_EPOCH + Duration::from_micros(micros);
+                    return CaptureRecord::decode(data, timestamp).map(Some);
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// Load every record from a capture file
+pub fn read_capture(path: impl AsRef<Path>) -> Result<Vec<CaptureRecord>> {
+    let file = std::fs::File::open(path)?;
+    CaptureReader::new(std::io::BufReader::new(file)).collect()
+}
+
+/// Capture state shared by the receive paths and senders of one manager
+pub(crate) struct PacketCapture {
+    active: AtomicBool,
+    writer: std::sync::Mutex<Option<CaptureWriter<Box<dyn Write + Send>>>>,
+    /// Wall clock and monotonic clock read together, to date packets stamped with `Instant`
+    epoch: (SystemTime, Instant),
+}
+
+impl PacketCapture {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            writer: std::sync::Mutex::new(None),
+            epoch: (SystemTime::now(), Instant::now()),
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn start(&self, writer: Box<dyn Write + Send>) -> Result<()> {
+        let writer = CaptureWriter::new(writer)?;
+        let previous = self.writer.lock().expect("capture lock poisoned").replace(writer);
+        if let Some(mut previous) = previous {
+            previous.flush()?;
+        }
+        self.active.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Stop capturing and flush; false if no capture was running
+    pub(crate) fn stop(&self) -> Result<bool> {
+        self.active.store(false, Ordering::Relaxed);
+        match self.writer.lock().expect("capture lock poisoned").take() {
+            Some(mut writer) => {
+                writer.flush()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub(crate) fn wall_clock(&self, at: Instant) -> SystemTime {
+        let (system, instant) = self.epoch;
+        match at.checked_duration_since(instant) {
+            Some(elapsed) => system + elapsed,
+            None => system - instant.duration_since(at),
+        }
+    }
+
+    pub(crate) fn record(&self, record: CaptureRecord) {
+        let mut writer = self.writer.lock().expect("capture lock poisoned");
+        if let Some(capture) = writer.as_mut() {
+            if let Err(e) = capture.write_record(&record) {
+                // A full disk should not take the network down with it
+                warn!("Packet capture stopped: {}", e);
+                *writer = None;
+                self.active.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// What a replay did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// Received packets fed back through the manager
+    pub replayed: u64,
+    /// Sent packets in the capture, which are not replayed
+    pub skipped_outgoing: u64,
+}
+
+/// Feeds the received side of a capture back through a manager's handlers and incoming queue
+///
+/// Packets keep their recorded connection ids, so handler replies to those connections are
+/// dropped unless the test has set up matching connections.
+pub struct Replayer {
+    records: Vec<CaptureRecord>,
+    /// Playback speed multiplier; `None` replays without waiting
+    speed: Option<f64>,
+    connection: Option<ConnectionId>,
+}
+
+impl Replayer {
+    /// Replay at the original pace
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        Self {
+            records,
+            speed: Some(1.0),
+            connection: None,
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(read_capture(path)?))
+    }
+
+    /// Replay `factor` times faster than recorded
+    pub fn at_speed(mut self, factor: f64) -> Self {
+        self.speed = Some(factor).filter(|factor| factor.is_finite() && *factor > 0.0);
+        self
+    }
+
+    /// Replay back to back, ignoring the recorded timing
+    pub fn unpaced(mut self) -> Self {
+        self.speed = None;
+        self
+    }
+
+    /// Only replay packets of one connection
+    pub fn only_connection(mut self, connection_id: ConnectionId) -> Self {
+        self.connection = Some(connection_id);
+        self
+    }
+
+    pub fn records(&self) -> &[CaptureRecord] {
+        &self.records
+    }
+
+    pub async fn replay(&self, manager: &NetworkManager) -> Result<ReplayStats> {
+        let mut stats = ReplayStats::default();
+        let started = tokio::time::Instant::now();
+        let first = self.records.first().map(|record| record.timestamp);
+
+        for record in &self.records {
+            if self.connection.is_some_and(|id| id != record.connection_id) {
+                continue;
+            }
+            if record.direction == CaptureDirection::Outgoing {
+                stats.skipped_outgoing += 1;
+                continue;
+            }
+
+            if let (Some(speed), Some(first)) = (self.speed, first) {
+                let offset = record.timestamp.duration_since(first).unwrap_or_default();
+                tokio::time::sleep_until(started + offset.div_f64(speed)).await;
+            }
+
+            let packet = IncomingPacket {
+                connection_id: record.connection_id,
+                protocol: record.protocol,
+                data: record.data.clone(),
+                timestamp: Instant::now(),
+            };
+            if !manager.sink.dispatch(packet).await {
+                return Err(anyhow::anyhow!("Network manager stopped during replay"));
+            }
+            stats.replayed += 1;
+        }
+
+        info!("Replayed {} packets ({} sent packets skipped)", stats.replayed, stats.skipped_outgoing);
+        Ok(stats)
+    }
+}
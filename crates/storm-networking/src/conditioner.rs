@@ -0,0 +1,400 @@
+// File: crates/storm-networking/src/conditioner.rs
+// Link conditioner: seeded latency, jitter, loss, duplication, reordering and bandwidth caps
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use anyhow::Result;
+
+/// Impairments applied to one direction of a link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkConditions {
+    /// Fixed one-way delay
+    pub latency: Duration,
+    /// Random extra delay, uniform between zero and this
+    pub jitter: Duration,
+    /// Probability a packet is dropped
+    pub loss: f64,
+    /// Probability a packet is delivered twice
+    pub duplicate: f64,
+    /// Probability a packet is held back by `reorder_delay` so later ones overtake it
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Link rate in bits per second; packets queue behind each other when set
+    pub bandwidth_bps: Option<u64>,
+    /// Bytes that may wait for a capped link before new packets are dropped
+    pub queue_bytes: usize,
+    /// Largest packet the link carries; bigger ones are dropped, as on a path that does not fragment
+    pub mtu: Option<usize>,
+    /// Seed for every random decision, so a run can be repeated exactly
+    pub seed: u64,
+}
+
+impl LinkConditions {
+    /// A perfect link, to adjust from
+    pub fn ideal() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(20),
+            bandwidth_bps: None,
+            queue_bytes: 64 * 1024,
+            mtu: None,
+            seed: 0,
+        }
+    }
+
+    /// A poor consumer connection: 80 ms ± 20 ms, 2% loss, some duplication and reordering
+    pub fn lossy_broadband() -> Self {
+        Self {
+            latency: Duration::from_millis(80),
+            jitter: Duration::from_millis(20),
+            loss: 0.02,
+            duplicate: 0.005,
+            reorder: 0.01,
+            bandwidth_bps: Some(2_000_000),
+            ..Self::ideal()
+        }
+    }
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self::ideal()
+    }
+}
+
+/// What happened to the packets given to a conditioner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionerStats {
+    pub submitted: u64,
+    pub delivered: u64,
+    pub lost: u64,
+    /// Dropped because the bandwidth queue was full or the link was down
+    pub overflowed: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    /// Dropped for exceeding the link MTU
+    pub oversized: u64,
+}
+
+/// One direction of an impaired link, driven by the caller's clock
+///
+/// Submit packets as they are sent and `poll` for the ones due to arrive. Nothing reads the
+/// system clock, so tests can step time and get the same result for the same seed.
+#[derive(Debug)]
+pub struct LinkConditioner {
+    conditions: LinkConditions,
+    rng: StdRng,
+    /// Ordered by arrival time, then submission order
+    in_flight: BinaryHeap<Reverse<(Instant, u64, Vec<u8>)>>,
+    next_id: u64,
+    /// When the capped link finishes sending what is already queued
+    link_free_at: Option<Instant>,
+    down: bool,
+    stats: ConditionerStats,
+}
+
+impl LinkConditioner {
+    pub fn new(conditions: LinkConditions) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(conditions.seed),
+            conditions,
+            in_flight: BinaryHeap::new(),
+            next_id: 0,
+            link_free_at: None,
+            down: false,
+            stats: ConditionerStats::default(),
+        }
+    }
+
+    pub fn conditions(&self) -> &LinkConditions {
+        &self.conditions
+    }
+
+    /// Change the impairments for packets submitted from now on, keeping the random sequence
+    pub fn set_conditions(&mut self, conditions: LinkConditions) {
+        self.conditions = conditions;
+    }
+
+    /// Take the link down, dropping everything until it comes back; for reconnect tests
+    pub fn set_down(&mut self, down: bool) {
+        self.down = down;
+        if down {
+            self.stats.overflowed += self.in_flight.len() as u64;
+            self.in_flight.clear();
+            self.link_free_at = None;
+        }
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.down
+    }
+
+    pub fn stats(&self) -> ConditionerStats {
+        self.stats
+    }
+
+    /// Packets submitted but not yet delivered
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Arrival time of the next packet, for sleeping until it is due
+    pub fn next_delivery(&self) -> Option<Instant> {
+        self.in_flight.peek().map(|Reverse((at, _, _))| *at)
+    }
+
+    /// Send a packet into the link at `now`
+    pub fn submit(&mut self, mut data: Vec<u8>, now: Instant) {
+        self.stats.submitted += 1;
+        if self.down {
+            self.stats.overflowed += 1;
+            return;
+        }
+        if self.conditions.mtu.is_some_and(|mtu| data.len() > mtu) {
+            self.stats.oversized += 1;
+            return;
+        }
+        if self.rng.gen_bool(self.conditions.loss.clamp(0.0, 1.0)) {
+            self.stats.lost += 1;
+            return;
+        }
+
+        let Some(departs) = self.serialize(data.len(), now) else {
+            self.stats.overflowed += 1;
+            return;
+        };
+
+        let copies = if self.rng.gen_bool(self.conditions.duplicate.clamp(0.0, 1.0)) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        for copy in 1..=copies {
+            let mut arrives = departs + self.conditions.latency + self.jitter();
+            if self.rng.gen_bool(self.conditions.reorder.clamp(0.0, 1.0)) {
+                self.stats.reordered += 1;
+                arrives += self.conditions.reorder_delay;
+            }
+            let packet = if copy == copies { std::mem::take(&mut data) } else { data.clone() };
+            self.in_flight.push(Reverse((arrives, self.next_id, packet)));
+            self.next_id += 1;
+        }
+    }
+
+    /// Packets that have arrived by `now`, in arrival order
+    pub fn poll(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut arrived = Vec::new();
+        while let Some(Reverse((at, _, _))) = self.in_flight.peek() {
+            if *at > now {
+                break;
+            }
+            let Reverse((_, _, data)) = self.in_flight.pop().expect("peeked above");
+            arrived.push(data);
+        }
+        self.stats.delivered += arrived.len() as u64;
+        arrived
+    }
+
+    /// When the packet finishes leaving a capped link, or `None` if the queue is full
+    fn serialize(&mut self, len: usize, now: Instant) -> Option<Instant> {
+        let Some(bps) = self.conditions.bandwidth_bps.filter(|bps| *bps > 0) else {
+            return Some(now);
+        };
+
+        let start = self.link_free_at.filter(|free| *free > now).unwrap_or(now);
+        // Bytes still waiting ahead of this packet
+        let backlog = (start - now).as_secs_f64() * bps as f64 / 8.0;
+        if backlog + len as f64 > self.conditions.queue_bytes as f64 {
+            return None;
+        }
+
+        let done = start + Duration::from_secs_f64(len as f64 * 8.0 / bps as f64);
+        self.link_free_at = Some(done);
+        Some(done)
+    }
+
+    fn jitter(&mut self) -> Duration {
+        if self.conditions.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        self.conditions.jitter.mul_f64(self.rng.gen::<f64>())
+    }
+}
+
+/// UDP proxy that impairs traffic between real clients and a server
+///
+/// Each client gets its own upstream socket, so the server sees one address per client.
+pub struct UdpProxy {
+    socket: Arc<UdpSocket>,
+    upstream: SocketAddr,
+    to_server: LinkConditions,
+    to_client: LinkConditions,
+    idle_timeout: Duration,
+}
+
+struct ProxySession {
+    upstream: Arc<UdpSocket>,
+    to_server: LinkConditioner,
+    to_client: LinkConditioner,
+    last_activity: Instant,
+    reader: tokio::task::JoinHandle<()>,
+}
+
+impl UdpProxy {
+    pub async fn bind(
+        listen: SocketAddr,
+        upstream: SocketAddr,
+        to_server: LinkConditions,
+        to_client: LinkConditions,
+    ) -> Result<Self> {
+        Ok(Self {
+            socket: Arc::new(UdpSocket::bind(listen).await?),
+            upstream,
+            to_server,
+            to_client,
+            idle_timeout: Duration::from_secs(120),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Forget clients that have been silent this long
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Forward traffic until the task is dropped or the listen socket fails
+    pub async fn run(self) -> Result<()> {
+        info!("Proxying {} -> {}", self.socket.local_addr()?, self.upstream);
+
+        let mut sessions: HashMap<SocketAddr, ProxySession> = HashMap::new();
+        let (from_server_tx, mut from_server) = mpsc::unbounded_channel::<(SocketAddr, Vec<u8>)>();
+        let mut buf = vec![0u8; 65536];
+        let mut client_count = 0u64;
+        let mut sweep = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            let next_delivery = sessions
+                .values()
+                .flat_map(|session| [session.to_server.next_delivery(), session.to_client.next_delivery()])
+                .flatten()
+                .min();
+            let wake = tokio::time::Instant::from_std(next_delivery.unwrap_or_else(|| Instant::now() + Duration::from_secs(3600)));
+
+            tokio::select! {
+                received = self.socket.recv_from(&mut buf) => {
+                    let (len, client) = received?;
+                    let now = Instant::now();
+                    let session = match sessions.entry(client) {
+                        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                        std::collections::hash_map::Entry::Vacant(entry) => {
+                            // Vary the seed per client so clients do not lose the same packets
+                            client_count += 1;
+                            match self.open_session(client, client_count, from_server_tx.clone()).await {
+                                Ok(session) => entry.insert(session),
+                                Err(e) => {
+                                    warn!("Could not open upstream socket for {}: {}", client, e);
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    session.last_activity = now;
+                    session.to_server.submit(buf[..len].to_vec(), now);
+                }
+                Some((client, data)) = from_server.recv() => {
+                    if let Some(session) = sessions.get_mut(&client) {
+                        session.to_client.submit(data, Instant::now());
+                    }
+                }
+                _ = tokio::time::sleep_until(wake) => {}
+                _ = sweep.tick() => {
+                    let now = Instant::now();
+                    // Dropping a session stops its upstream reader
+                    sessions.retain(|client, session| {
+                        let alive = now.duration_since(session.last_activity) < self.idle_timeout;
+                        if !alive {
+                            debug!("Proxy session for {} expired", client);
+                        }
+                        alive
+                    });
+                }
+            }
+
+            let now = Instant::now();
+            for (client, session) in sessions.iter_mut() {
+                for data in session.to_server.poll(now) {
+                    if let Err(e) = session.upstream.send(&data).await {
+                        debug!("Upstream send for {} failed: {}", client, e);
+                    }
+                }
+                for data in session.to_client.poll(now) {
+                    if let Err(e) = self.socket.send_to(&data, client).await {
+                        debug!("Send to client {} failed: {}", client, e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn open_session(
+        &self,
+        client: SocketAddr,
+        index: u64,
+        from_server: mpsc::UnboundedSender<(SocketAddr, Vec<u8>)>,
+    ) -> Result<ProxySession> {
+        let bind_addr: SocketAddr = if self.upstream.is_ipv6() {
+            "[::]:0".parse()?
+        } else {
+            "0.0.0.0:0".parse()?
+        };
+        let upstream = Arc::new(UdpSocket::bind(bind_addr).await?);
+        upstream.connect(self.upstream).await?;
+        info!("Proxy session for {} via {}", client, upstream.local_addr()?);
+
+        let reader_socket = upstream.clone();
+        let reader = tokio::spawn(async move {
+            let mut buf = vec![0u8; 65536];
+            while let Ok(len) = reader_socket.recv(&mut buf).await {
+                if from_server.send((client, buf[..len].to_vec())).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let seeded = |conditions: &LinkConditions, stream: u64| LinkConditions {
+            seed: conditions.seed.wrapping_add(index.wrapping_mul(2).wrapping_add(stream)),
+            ..conditions.clone()
+        };
+        Ok(ProxySession {
+            upstream,
+            to_server: LinkConditioner::new(seeded(&self.to_server, 0)),
+            to_client: LinkConditioner::new(seeded(&self.to_client, 1)),
+            last_activity: Instant::now(),
+            reader,
+        })
+    }
+}
+
+impl Drop for ProxySession {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
@@ -0,0 +1,97 @@
+// File: crates/storm-networking/src/error.rs
+// Typed networking errors with stable codes and retry classification
+
+use thiserror::Error;
+
+use crate::{ConnectionId, ProtocolType};
+
+/// Networking failures
+///
+/// Functions in this crate return `anyhow::Result`; these errors travel inside it and can be
+/// recovered with `downcast_ref::<NetworkError>()`.
+#[derive(Error, Debug)]
+pub enum NetworkError {
+    #[error("Connection not found: {connection_id}")]
+    ConnectionNotFound { connection_id: ConnectionId },
+
+    #[error("Connection {connection_id} closed by peer")]
+    ConnectionClosed { connection_id: ConnectionId },
+
+    #[error("Connection timed out{}", connection_suffix(.connection_id))]
+    Timeout { connection_id: Option<ConnectionId> },
+
+    #[error("{protocol:?} transport is not supported")]
+    UnsupportedProtocol { protocol: ProtocolType },
+
+    #[error("Handshake with connection {connection_id} failed: {reason}")]
+    HandshakeFailed { connection_id: ConnectionId, reason: String },
+
+    #[error("Socket error{}: {source}", connection_suffix(.connection_id))]
+    Io {
+        connection_id: Option<ConnectionId>,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl NetworkError {
+    /// Stable numeric code, in the 1000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            NetworkError::ConnectionNotFound { .. } => 1001,
+            NetworkError::ConnectionClosed { .. } => 1002,
+            NetworkError::Timeout { .. } => 1003,
+            NetworkError::UnsupportedProtocol { .. } => 1004,
+            NetworkError::Io { .. } => 1005,
+            NetworkError::HandshakeFailed { .. } => 1006,
+            NetworkError::Other(_) => 1000,
+        }
+    }
+
+    /// Whether repeating the operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NetworkError::ConnectionClosed { .. } | NetworkError::Timeout { .. } => true,
+            NetworkError::Io { source, .. } => matches!(
+                source.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::Interrupted
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the networking layer cannot continue at all
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, NetworkError::UnsupportedProtocol { .. })
+    }
+
+    /// Connection the error relates to, if any
+    pub fn connection_id(&self) -> Option<ConnectionId> {
+        match self {
+            NetworkError::ConnectionNotFound { connection_id }
+            | NetworkError::ConnectionClosed { connection_id }
+            | NetworkError::HandshakeFailed { connection_id, .. } => {
+                Some(*connection_id)
+            }
+            NetworkError::Timeout { connection_id } | NetworkError::Io { connection_id, .. } => *connection_id,
+            NetworkError::UnsupportedProtocol { .. } | NetworkError::Other(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(source: std::io::Error) -> Self {
+        NetworkError::Io { connection_id: None, source }
+    }
+}
+
+fn connection_suffix(connection_id: &Option<ConnectionId>) -> String {
+    connection_id.map(|id| format!(" on connection {}", id)).unwrap_or_default()
+}
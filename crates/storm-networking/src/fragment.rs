@@ -0,0 +1,274 @@
+// File: crates/storm-networking/src/fragment.rs
+// Fragmentation, reassembly and path MTU probing for reliable-UDP messages
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use anyhow::Result;
+
+/// Size of the header in front of each fragment's share of the message
+pub const FRAGMENT_HEADER_SIZE: usize = 8;
+
+/// Probes stop once the confirmed MTU is within this many bytes of the largest untested size
+pub const MTU_PROBE_STEP: usize = 8;
+
+/// Times a probe size is sent before the path is taken not to carry it
+pub const MTU_PROBE_ATTEMPTS: u32 = 3;
+
+/// Position of a fragment within its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentHeader {
+    /// Identifies the message among others being reassembled on the same channel
+    pub group: u32,
+    pub index: u16,
+    pub count: u16,
+}
+
+impl FragmentHeader {
+    pub fn encode(&self, chunk: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(FRAGMENT_HEADER_SIZE + chunk.len());
+        data.extend_from_slice(&self.group.to_be_bytes());
+        data.extend_from_slice(&self.index.to_be_bytes());
+        data.extend_from_slice(&self.count.to_be_bytes());
+        data.extend_from_slice(chunk);
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<(Self, &[u8])> {
+        if data.len() < FRAGMENT_HEADER_SIZE {
+            return Err(anyhow::anyhow!("Fragment too short: {} bytes", data.len()));
+        }
+        let header = Self {
+            group: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            index: u16::from_be_bytes([data[4], data[5]]),
+            count: u16::from_be_bytes([data[6], data[7]]),
+        };
+        if header.count == 0 || header.index >= header.count {
+            return Err(anyhow::anyhow!("Fragment {} of {} is out of range", header.index, header.count));
+        }
+        Ok((header, &data[FRAGMENT_HEADER_SIZE..]))
+    }
+}
+
+/// Split a message into fragment bodies of at most `max_body` bytes, headers included
+pub fn fragment(group: u32, message: &[u8], max_body: usize) -> Result<Vec<Vec<u8>>> {
+    let chunk_size = max_body.saturating_sub(FRAGMENT_HEADER_SIZE);
+    if chunk_size == 0 {
+        return Err(anyhow::anyhow!("MTU of {} bytes leaves no room for fragment data", max_body));
+    }
+    let count = message.len().div_ceil(chunk_size).max(1);
+    let count = u16::try_from(count).map_err(|_| anyhow::anyhow!("Message of {} bytes needs too many fragments", message.len()))?;
+
+    let chunks: Vec<&[u8]> = if message.is_empty() { vec![&[]] } else { message.chunks(chunk_size).collect() };
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            FragmentHeader {
+                group,
+                index: index as u16,
+                count,
+            }
+            .encode(chunk)
+        })
+        .collect())
+}
+
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    bytes: usize,
+    started: Instant,
+}
+
+/// Collects fragments until their message is complete
+///
+/// Partial messages are dropped when they outlive the timeout, when one grows past the
+/// message size limit, and oldest first when all of them together would exceed the memory
+/// limit.
+pub struct Reassembler {
+    timeout: Duration,
+    max_bytes: usize,
+    max_message_size: usize,
+    partials: HashMap<(u8, u32), Partial>,
+    buffered: usize,
+    dropped: u64,
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration, max_bytes: usize, max_message_size: usize) -> Self {
+        Self {
+            timeout,
+            max_bytes,
+            max_message_size,
+            partials: HashMap::new(),
+            buffered: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Messages waiting for more fragments
+    pub fn pending(&self) -> usize {
+        self.partials.len()
+    }
+
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered
+    }
+
+    /// Partial messages thrown away so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Add a fragment body; returns the whole message once its last fragment arrives
+    pub fn insert(&mut self, channel: u8, body: &[u8], now: Instant) -> Result<Option<Vec<u8>>> {
+        let (header, chunk) = FragmentHeader::decode(body)?;
+        let key = (channel, header.group);
+
+        if let Some(partial) = self.partials.get(&key) {
+            if partial.chunks.len() != header.count as usize {
+                self.discard(key);
+                return Err(anyhow::anyhow!("Fragment count changed within message {}", header.group));
+            }
+            if partial.chunks[header.index as usize].is_some() {
+                return Ok(None);
+            }
+            if partial.bytes + chunk.len() > self.max_message_size {
+                self.discard(key);
+                return Err(anyhow::anyhow!("Message {} exceeds {} bytes", header.group, self.max_message_size));
+            }
+        }
+
+        while self.buffered + chunk.len() > self.max_bytes {
+            let oldest = self
+                .partials
+                .iter()
+                .filter(|(other, _)| **other != key)
+                .min_by_key(|(_, partial)| partial.started)
+                .map(|(other, _)| *other);
+            match oldest {
+                Some(oldest) => {
+                    warn!("Reassembly memory full, dropping partial message {} on channel {}", oldest.1, oldest.0);
+                    self.discard(oldest);
+                }
+                None => {
+                    self.discard(key);
+                    return Err(anyhow::anyhow!("Fragment does not fit in {} bytes of reassembly memory", self.max_bytes));
+                }
+            }
+        }
+
+        let partial = self.partials.entry(key).or_insert_with(|| Partial {
+            chunks: vec![None; header.count as usize],
+            received: 0,
+            bytes: 0,
+            started: now,
+        });
+        partial.chunks[header.index as usize] = Some(chunk.to_vec());
+        partial.received += 1;
+        partial.bytes += chunk.len();
+        self.buffered += chunk.len();
+
+        if partial.received < partial.chunks.len() {
+            return Ok(None);
+        }
+        let partial = self.partials.remove(&key).expect("inserted above");
+        self.buffered -= partial.bytes;
+        Ok(Some(partial.chunks.into_iter().flatten().flatten().collect()))
+    }
+
+    /// Drop partial messages older than the timeout; returns how many
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let expired: Vec<(u8, u32)> = self
+            .partials
+            .iter()
+            .filter(|(_, partial)| now.saturating_duration_since(partial.started) >= self.timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &expired {
+            self.discard(*key);
+        }
+        expired.len()
+    }
+
+    fn discard(&mut self, key: (u8, u32)) {
+        if let Some(partial) = self.partials.remove(&key) {
+            self.buffered -= partial.bytes;
+            self.dropped += 1;
+        }
+    }
+}
+
+struct Probe {
+    size: usize,
+    sent_at: Instant,
+    attempts: u32,
+}
+
+/// Searches for the largest datagram the path delivers, by binary search between a size
+/// known to work and an upper bound
+///
+/// Probes are padded packets the peer acknowledges like any other. A size is confirmed by one
+/// ack and ruled out after `MTU_PROBE_ATTEMPTS` unanswered probes, so loss slows the search
+/// but only repeated loss of the same size lowers the result.
+pub struct MtuProber {
+    confirmed: usize,
+    /// Largest size not yet ruled out
+    ceiling: usize,
+    probe: Option<Probe>,
+}
+
+impl MtuProber {
+    pub fn new(mtu: usize, max_mtu: usize) -> Self {
+        Self {
+            confirmed: mtu,
+            ceiling: max_mtu.max(mtu),
+            probe: None,
+        }
+    }
+
+    /// Largest size confirmed so far
+    pub fn mtu(&self) -> usize {
+        self.confirmed
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.probe.is_none() && self.ceiling - self.confirmed < MTU_PROBE_STEP
+    }
+
+    /// Size of the probe to send now, if one is due; `timeout` is how long to wait for its ack
+    pub fn next_probe(&mut self, now: Instant, timeout: Duration) -> Option<usize> {
+        if let Some(probe) = &mut self.probe {
+            if now.saturating_duration_since(probe.sent_at) < timeout {
+                return None;
+            }
+            if probe.attempts < MTU_PROBE_ATTEMPTS {
+                probe.attempts += 1;
+                probe.sent_at = now;
+                return Some(probe.size);
+            }
+            self.ceiling = probe.size - 1;
+            self.probe = None;
+        }
+
+        if self.ceiling - self.confirmed < MTU_PROBE_STEP {
+            return None;
+        }
+        let size = (self.confirmed + self.ceiling).div_ceil(2);
+        self.probe = Some(Probe {
+            size,
+            sent_at: now,
+            attempts: 1,
+        });
+        Some(size)
+    }
+
+    /// A probe of `size` bytes was acknowledged
+    pub fn acked(&mut self, size: usize) {
+        self.confirmed = self.confirmed.max(size);
+        if self.probe.as_ref().is_some_and(|probe| probe.size <= size) {
+            self.probe = None;
+        }
+    }
+}
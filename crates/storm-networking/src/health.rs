@@ -0,0 +1,343 @@
+// File: crates/storm-networking/src/health.rs
+// Connection liveness: heartbeats, RTT and loss tracking, idle timeouts and connection events
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::{ConnectionId, ConnectionStats, PacketType, ProtocolType};
+
+/// Size of a heartbeat frame: type, kind and a 4-byte nonce
+///
+/// LLUDP packets are never this short, so heartbeats can share a socket with them.
+pub const HEARTBEAT_SIZE: usize = 6;
+
+const HEARTBEAT_PING: u8 = 0;
+const HEARTBEAT_PONG: u8 = 1;
+
+/// Unanswered heartbeats after which a peer that never answered is no longer pinged
+const MAX_UNANSWERED: u64 = 3;
+
+/// Events queued for `NetworkManager::subscribe_events`
+const EVENT_CAPACITY: usize = 1024;
+
+/// Why a connection went away
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    /// Closed with `NetworkManager::close_connection`
+    Closed,
+    /// The peer closed the connection
+    ClosedByPeer,
+    /// Nothing was received within the connection timeout
+    TimedOut,
+    /// Compression or encryption could not be agreed with the peer
+    HandshakeFailed(String),
+    /// The transport failed
+    Error(String),
+    /// The network manager shut down
+    Shutdown,
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::Closed => write!(f, "closed locally"),
+            DisconnectReason::ClosedByPeer => write!(f, "closed by peer"),
+            DisconnectReason::TimedOut => write!(f, "timed out"),
+            DisconnectReason::HandshakeFailed(reason) => write!(f, "handshake failed: {}", reason),
+            DisconnectReason::Error(reason) => write!(f, "transport error: {}", reason),
+            DisconnectReason::Shutdown => write!(f, "network manager shut down"),
+        }
+    }
+}
+
+/// Connection lifecycle notifications
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkEvent {
+    Connected {
+        connection_id: ConnectionId,
+        protocol: ProtocolType,
+        remote_addr: SocketAddr,
+    },
+    /// Carries the connection's final stats
+    Disconnected {
+        connection_id: ConnectionId,
+        reason: DisconnectReason,
+        stats: ConnectionStats,
+    },
+}
+
+/// Liveness probe; a ping is answered with a pong carrying the same nonce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Heartbeat {
+    pub(crate) reply: bool,
+    pub(crate) nonce: u32,
+}
+
+impl Heartbeat {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(HEARTBEAT_SIZE);
+        frame.push(PacketType::Heartbeat.as_u8());
+        frame.push(if self.reply { HEARTBEAT_PONG } else { HEARTBEAT_PING });
+        frame.extend_from_slice(&self.nonce.to_be_bytes());
+        frame
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != HEARTBEAT_SIZE || data[0] != PacketType::Heartbeat.as_u8() {
+            return None;
+        }
+        let reply = match data[1] {
+            HEARTBEAT_PING => false,
+            HEARTBEAT_PONG => true,
+            _ => return None,
+        };
+        Some(Self {
+            reply,
+            nonce: u32::from_be_bytes([data[2], data[3], data[4], data[5]]),
+        })
+    }
+}
+
+struct ConnectionHealth {
+    protocol: ProtocolType,
+    remote_addr: SocketAddr,
+    connected_at: Instant,
+    last_activity: Instant,
+    last_heartbeat: Option<Instant>,
+    next_nonce: u32,
+    outstanding: VecDeque<(u32, Instant)>,
+    srtt: Option<Duration>,
+    last_rtt: Option<Duration>,
+    jitter: Option<Duration>,
+    packets_in: u64,
+    packets_out: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    resends: u64,
+    heartbeats_sent: u64,
+    heartbeats_answered: u64,
+    heartbeats_lost: u64,
+}
+
+impl ConnectionHealth {
+    fn new(protocol: ProtocolType, remote_addr: SocketAddr, now: Instant) -> Self {
+        Self {
+            protocol,
+            remote_addr,
+            connected_at: now,
+            last_activity: now,
+            last_heartbeat: None,
+            next_nonce: 0,
+            outstanding: VecDeque::new(),
+            srtt: None,
+            last_rtt: None,
+            jitter: None,
+            packets_in: 0,
+            packets_out: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            resends: 0,
+            heartbeats_sent: 0,
+            heartbeats_answered: 0,
+            heartbeats_lost: 0,
+        }
+    }
+
+    fn sample_rtt(&mut self, rtt: Duration) {
+        self.srtt = Some(match self.srtt {
+            Some(srtt) => (srtt * 7 + rtt) / 8,
+            None => rtt,
+        });
+        // RFC 3550 interarrival jitter, over round trips instead of one-way transit times
+        if let Some(last) = self.last_rtt {
+            let deviation = last.abs_diff(rtt);
+            let jitter = self.jitter.unwrap_or(Duration::ZERO);
+            self.jitter = Some(if deviation > jitter {
+                jitter + (deviation - jitter) / 16
+            } else {
+                jitter - (jitter - deviation) / 16
+            });
+        }
+        self.last_rtt = Some(rtt);
+    }
+
+    fn stats(&self, connection_id: ConnectionId, now: Instant) -> ConnectionStats {
+        let answered = self.heartbeats_answered > 0;
+        ConnectionStats {
+            connection_id,
+            protocol: self.protocol,
+            remote_addr: self.remote_addr,
+            connected_for: now.saturating_duration_since(self.connected_at),
+            idle_for: now.saturating_duration_since(self.last_activity),
+            rtt_ms: self.srtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+            jitter_ms: self.jitter.map(|jitter| jitter.as_secs_f64() * 1000.0),
+            loss_rate: answered.then(|| {
+                self.heartbeats_lost as f64 / (self.heartbeats_answered + self.heartbeats_lost) as f64
+            }),
+            packets_in: self.packets_in,
+            packets_out: self.packets_out,
+            bytes_in: self.bytes_in,
+            bytes_out: self.bytes_out,
+            resends: self.resends,
+            heartbeats_sent: self.heartbeats_sent,
+            heartbeats_answered: self.heartbeats_answered,
+        }
+    }
+}
+
+/// Per-connection health, shared by the manager and its receive tasks
+pub(crate) struct HealthMonitor {
+    heartbeat_interval: Option<Duration>,
+    timeout: Duration,
+    connections: Mutex<HashMap<ConnectionId, ConnectionHealth>>,
+    events: broadcast::Sender<NetworkEvent>,
+}
+
+impl HealthMonitor {
+    /// A zero interval disables heartbeats
+    pub(crate) fn new(heartbeat_interval: Duration, timeout: Duration) -> Self {
+        Self {
+            heartbeat_interval: (!heartbeat_interval.is_zero()).then_some(heartbeat_interval),
+            timeout,
+            connections: Mutex::new(HashMap::new()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<ConnectionId, ConnectionHealth>> {
+        self.connections.lock().expect("health lock poisoned")
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start tracking a new connection
+    pub(crate) fn register(&self, connection_id: ConnectionId, protocol: ProtocolType, remote_addr: SocketAddr) {
+        let health = ConnectionHealth::new(protocol, remote_addr, Instant::now());
+        self.lock().insert(connection_id, health);
+        // Nobody may be subscribed yet, which is fine
+        let _ = self.events.send(NetworkEvent::Connected {
+            connection_id,
+            protocol,
+            remote_addr,
+        });
+    }
+
+    /// Stop tracking a connection and announce why it went away; None if it was already gone
+    pub(crate) fn disconnect(&self, connection_id: ConnectionId, reason: DisconnectReason) -> Option<ConnectionStats> {
+        let health = self.lock().remove(&connection_id)?;
+        let stats = health.stats(connection_id, Instant::now());
+        debug!("Connection {} disconnected: {}", connection_id, reason);
+        let _ = self.events.send(NetworkEvent::Disconnected {
+            connection_id,
+            reason,
+            stats: stats.clone(),
+        });
+        Some(stats)
+    }
+
+    pub(crate) fn record_in(&self, connection_id: ConnectionId, bytes: usize) {
+        if let Some(health) = self.lock().get_mut(&connection_id) {
+            health.last_activity = Instant::now();
+            health.packets_in += 1;
+            health.bytes_in += bytes as u64;
+        }
+    }
+
+    pub(crate) fn record_out(&self, connection_id: ConnectionId, bytes: usize) {
+        if let Some(health) = self.lock().get_mut(&connection_id) {
+            health.packets_out += 1;
+            health.bytes_out += bytes as u64;
+        }
+    }
+
+    pub(crate) fn record_resends(&self, connection_id: ConnectionId, count: u64) {
+        if let Some(health) = self.lock().get_mut(&connection_id) {
+            health.resends += count;
+        }
+    }
+
+    /// Match a pong to the ping it answers; late and unknown pongs are ignored
+    pub(crate) fn heartbeat_reply(&self, connection_id: ConnectionId, nonce: u32, now: Instant) {
+        let mut connections = self.lock();
+        let Some(health) = connections.get_mut(&connection_id) else {
+            return;
+        };
+        let Some(index) = health.outstanding.iter().position(|(sent, _)| *sent == nonce) else {
+            return;
+        };
+        let (_, sent_at) = health.outstanding.remove(index).expect("index found above");
+        health.heartbeats_answered += 1;
+        health.sample_rtt(now.saturating_duration_since(sent_at));
+    }
+
+    /// Pings due now, one per connection, marked as sent
+    ///
+    /// Pings unanswered for four intervals count as lost. Peers that never answered one, such
+    /// as LLUDP viewers, are no longer pinged once `MAX_UNANSWERED` have been lost.
+    pub(crate) fn due_heartbeats(&self, now: Instant) -> Vec<(ConnectionId, Vec<u8>)> {
+        let Some(interval) = self.heartbeat_interval else {
+            return Vec::new();
+        };
+        let lost_after = interval * 4;
+
+        let mut due = Vec::new();
+        for (connection_id, health) in self.lock().iter_mut() {
+            while let Some(&(_, sent_at)) = health.outstanding.front() {
+                if now.saturating_duration_since(sent_at) < lost_after {
+                    break;
+                }
+                health.outstanding.pop_front();
+                health.heartbeats_lost += 1;
+            }
+
+            if health.heartbeats_answered == 0 && health.heartbeats_lost >= MAX_UNANSWERED {
+                continue;
+            }
+            if health.last_heartbeat.is_some_and(|last| now.saturating_duration_since(last) < interval) {
+                continue;
+            }
+
+            let nonce = health.next_nonce;
+            health.next_nonce = health.next_nonce.wrapping_add(1);
+            health.last_heartbeat = Some(now);
+            health.outstanding.push_back((nonce, now));
+            health.heartbeats_sent += 1;
+            due.push((*connection_id, Heartbeat { reply: false, nonce }.encode()));
+        }
+        due
+    }
+
+    /// Connections that have been silent for the whole timeout
+    pub(crate) fn expired(&self, now: Instant) -> Vec<ConnectionId> {
+        self.lock()
+            .iter()
+            .filter(|(_, health)| now.saturating_duration_since(health.last_activity) >= self.timeout)
+            .map(|(connection_id, _)| *connection_id)
+            .collect()
+    }
+
+    pub(crate) fn stats(&self, connection_id: ConnectionId) -> Option<ConnectionStats> {
+        let now = Instant::now();
+        self.lock()
+            .get(&connection_id)
+            .map(|health| health.stats(connection_id, now))
+    }
+
+    pub(crate) fn all_stats(&self) -> Vec<ConnectionStats> {
+        let now = Instant::now();
+        let mut stats: Vec<ConnectionStats> = self
+            .lock()
+            .iter()
+            .map(|(connection_id, health)| health.stats(*connection_id, now))
+            .collect();
+        stats.sort_by_key(|stats| std::cmp::Reverse(stats.connected_for));
+        stats
+    }
+}
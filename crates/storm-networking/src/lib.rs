@@ -7,18 +7,53 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::{TcpListener, UdpSocket};
 use tokio::sync::{mpsc, RwLock, Mutex};
-use tokio_tungstenite::{WebSocketStream, MaybeTlsStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::MaybeTlsStream;
 use serde::{Deserialize, Serialize};
-use tracing::{info, warn, error};
+use tracing::{debug, info, warn};
 use anyhow::Result;
 
 pub mod packet;
 pub mod connection;
 pub mod protocol;
+pub mod error;
+pub mod stats;
+pub mod reliable;
+pub mod quic;
+pub mod stream;
+pub mod scheduler;
+pub mod transform;
+pub mod conditioner;
+pub mod capture;
+pub mod health;
+pub mod fragment;
 
 pub use packet::*;
 pub use connection::*;
 pub use protocol::*;
+pub use error::NetworkError;
+pub use stats::{ConnectionStats, NetworkStats, TrafficCounters, TrafficStats};
+pub use quic::{QuicConnection, QuicIdentity, MAX_STREAM_MESSAGE};
+pub use stream::MAX_FRAME_SIZE;
+pub use scheduler::{ScheduledPacket, SchedulerStats, SendScheduler, ThrottleCategory, ThrottleConfig, TokenBucket};
+pub use transform::{
+    dictionary_id, train_dictionary, AeadTransform, Negotiated, PacketTransform, TransformConfig, TransformPipeline,
+    ZstdTransform,
+};
+pub use conditioner::{ConditionerStats, LinkConditioner, LinkConditions, UdpProxy};
+pub use capture::{
+    read_capture, CaptureDirection, CaptureReader, CaptureRecord, CaptureWriter, ReplayStats, Replayer, CAPTURE_LINK_TYPE,
+};
+pub use health::{DisconnectReason, NetworkEvent, HEARTBEAT_SIZE};
+pub use fragment::{FragmentHeader, MtuProber, Reassembler, FRAGMENT_HEADER_SIZE};
+use capture::PacketCapture;
+use health::{HealthMonitor, Heartbeat};
+use stream::{Outbound, StreamTransport};
+use transform::{Inbound, TransformRegistry};
+pub use reliable::{
+    ChannelKind, Delivered, ReliabilityConfig, ReliabilityStats, ReliableConnection, ReliableEndpoint, RttEstimator,
+    SequenceWindow,
+};
 
 /// Network configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,8 +61,18 @@ pub struct NetworkConfig {
     pub max_connections: usize,
     pub connection_timeout_ms: u64,
     pub packet_buffer_size: usize,
+    /// Offer zstd compression to TCP and QUIC peers; LLUDP and WebSocket warn and send plain
     pub compression_enabled: bool,
+    /// Require encryption on TCP connections; QUIC is always encrypted by TLS, and LLUDP and
+    /// WebSocket connections warn and stay unencrypted
     pub encryption_enabled: bool,
+    /// How often idle connections are pinged to measure RTT and keep them alive; 0 disables
+    #[serde(default = "default_heartbeat_interval_ms")]
+    pub heartbeat_interval_ms: u64,
+}
+
+fn default_heartbeat_interval_ms() -> u64 {
+    1000
 }
 
 /// Network manager - coordinates all network operations
@@ -38,8 +83,26 @@ pub struct NetworkManager {
     packet_handlers: Arc<RwLock<HashMap<ProtocolType, Box<dyn PacketHandler>>>>,
 
     // Channels for communication
-    incoming_packets: mpsc::UnboundedSender<IncomingPacket>,
-    outgoing_packets: mpsc::UnboundedReceiver<OutgoingPacket>,
+    sink: PacketSink,
+    incoming_receiver: Mutex<Option<mpsc::UnboundedReceiver<IncomingPacket>>>,
+    outgoing_packets: Mutex<mpsc::UnboundedReceiver<OutgoingPacket>>,
+
+    // Remote peers seen on UDP listeners, so repeated datagrams map to one connection
+    udp_peers: Arc<RwLock<HashMap<SocketAddr, ConnectionId>>>,
+
+    // Background receive tasks owned by listeners
+    listener_tasks: Mutex<Vec<JoinHandle<()>>>,
+
+    traffic: Arc<TrafficCounters>,
+
+    // Certificate presented by QUIC listeners, generated on first use if not set
+    quic_identity: Mutex<Option<QuicIdentity>>,
+    // Certificates QUIC clients trust instead of the platform roots
+    quic_pinned_certificates: RwLock<Vec<Vec<u8>>>,
+
+    // Bandwidth limits for new connections; None sends everything immediately
+    default_throttle: RwLock<Option<ThrottleConfig>>,
+    schedulers: Mutex<HashMap<ConnectionId, SendScheduler>>,
 }
 
 /// Connection identifier
@@ -50,14 +113,16 @@ pub type ConnectionId = uuid::Uuid;
 pub enum ProtocolType {
     LLUDP,     // OpenSim/MutSea UDP protocol
     WebSocket, // Finalverse WebSocket protocol
-    QUIC,      // Future high-performance protocol
+    QUIC,      // Multiplexed streams and datagrams over quinn
+    TCP,       // Length-prefixed frames, for tools and server-to-server links
 }
 
 /// Network listener for incoming connections
 pub enum Listener {
-    Tcp(TcpListener),
-    Udp(UdpSocket),
-    WebSocket(TcpListener),
+    Tcp(Arc<TcpListener>),
+    Udp(Arc<UdpSocket>),
+    WebSocket(Arc<TcpListener>),
+    Quic(quinn::Endpoint),
 }
 
 /// Connection wrapper for different transport types
@@ -65,14 +130,79 @@ pub enum Connection {
     Tcp(TcpConnection),
     Udp(UdpConnection),
     WebSocket(WebSocketConnection),
+    Quic(Box<QuicConnection>),
+}
+
+impl Connection {
+    /// Start a graceful close; the connection must already be out of the map
+    fn close(&self) {
+        match self {
+            Connection::Tcp(TcpConnection { outbound, .. })
+            | Connection::WebSocket(WebSocketConnection { outbound, .. }) => {
+                let _ = outbound.send(Outbound::Close);
+            }
+            Connection::Quic(quic) => quic.close(),
+            Connection::Udp(_) => {}
+        }
+    }
+
+    fn protocol(&self) -> ProtocolType {
+        match self {
+            Connection::Tcp(_) => ProtocolType::TCP,
+            Connection::Udp(_) => ProtocolType::LLUDP,
+            Connection::WebSocket(_) => ProtocolType::WebSocket,
+            Connection::Quic(_) => ProtocolType::QUIC,
+        }
+    }
+
+    fn remote_addr(&self) -> SocketAddr {
+        match self {
+            Connection::Tcp(tcp) => tcp.remote_addr,
+            Connection::Udp(udp) => udp.remote_addr,
+            Connection::WebSocket(ws) => ws.remote_addr,
+            Connection::Quic(quic) => quic.remote_addr,
+        }
+    }
+}
+
+/// Register a connected TCP or WebSocket stream and start its reader and writer
+async fn register_stream_connection(
+    connections: &Arc<RwLock<HashMap<ConnectionId, Connection>>>,
+    sink: &PacketSink,
+    transport: StreamTransport,
+    remote_addr: SocketAddr,
+    protocol: ProtocolType,
+) -> ConnectionId {
+    let id = ConnectionId::new_v4();
+    let is_websocket = matches!(transport, StreamTransport::WebSocket(_));
+
+    // Hold the lock while the tasks start so a reader that finishes at once cannot miss its entry
+    let mut connections_guard = connections.write().await;
+    sink.health.register(id, protocol, remote_addr);
+    let outbound = stream::spawn_stream_tasks(transport, id, protocol, sink.clone(), connections.clone());
+    if !is_websocket {
+        // The hello has to be the first frame on the stream
+        match sink.transforms.start(id, true).await {
+            Ok(hello) => {
+                let _ = outbound.send(Outbound::Data(hello));
+            }
+            Err(e) => warn!("Could not start transform handshake for {}: {}", id, e),
+        }
+    }
+    let connection = if is_websocket {
+        Connection::WebSocket(WebSocketConnection { id, outbound, remote_addr })
+    } else {
+        Connection::Tcp(TcpConnection { id, outbound, remote_addr })
+    };
+    connections_guard.insert(id, connection);
+    id
 }
 
-/// TCP connection wrapper
+/// TCP connection wrapper; its socket is owned by the reader and writer tasks
 pub struct TcpConnection {
     id: ConnectionId,
-    stream: tokio::net::TcpStream,
+    outbound: mpsc::UnboundedSender<Outbound>,
     remote_addr: SocketAddr,
-    last_activity: std::time::Instant,
 }
 
 /// UDP connection wrapper
@@ -80,15 +210,15 @@ pub struct UdpConnection {
     id: ConnectionId,
     socket: Arc<UdpSocket>,
     remote_addr: SocketAddr,
-    last_activity: std::time::Instant,
+    /// True when the socket belongs to a listener and is shared by every peer on it
+    shared: bool,
 }
 
-/// WebSocket connection wrapper
+/// WebSocket connection wrapper; its stream is owned by the reader and writer tasks
 pub struct WebSocketConnection {
     id: ConnectionId,
-    stream: WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
+    outbound: mpsc::UnboundedSender<Outbound>,
     remote_addr: SocketAddr,
-    last_activity: std::time::Instant,
 }
 
 /// Incoming packet from network
@@ -119,25 +249,225 @@ pub enum PacketPriority {
 }
 
 /// Trait for handling protocol-specific packets
+///
+/// A registered handler consumes every packet of its protocol; the packets it returns are
+/// sent on the next `NetworkManager::update`.
 pub trait PacketHandler: Send + Sync {
     fn handle_packet(&self, packet: &IncomingPacket) -> Result<Vec<OutgoingPacket>>;
     fn protocol_type(&self) -> ProtocolType;
 }
 
+/// Hands received packets to the protocol's handler, or to the incoming queue if it has none
+#[derive(Clone)]
+pub(crate) struct PacketSink {
+    incoming: mpsc::UnboundedSender<IncomingPacket>,
+    outgoing: mpsc::UnboundedSender<OutgoingPacket>,
+    handlers: Arc<RwLock<HashMap<ProtocolType, Box<dyn PacketHandler>>>>,
+    traffic: Arc<TrafficCounters>,
+    transforms: Arc<TransformRegistry>,
+    capture: Arc<PacketCapture>,
+    health: Arc<HealthMonitor>,
+    // Read to label captured packets with their peer address and to answer heartbeats
+    connections: Arc<RwLock<HashMap<ConnectionId, Connection>>>,
+}
+
+impl PacketSink {
+    /// Deliver one packet; false once the manager is gone and receive loops should stop
+    pub(crate) async fn deliver(&self, mut packet: IncomingPacket) -> bool {
+        self.traffic.record_in(packet.data.len());
+        self.health.record_in(packet.connection_id, packet.data.len());
+
+        // WebSocket peers use the protocol's own ping frames instead, and stream transports get
+        // theirs from the transforms so a forged frame cannot pass for one
+        if packet.protocol == ProtocolType::LLUDP {
+            if let Some(heartbeat) = Heartbeat::decode(&packet.data) {
+                self.heartbeat(packet.connection_id, heartbeat, packet.timestamp).await;
+                return true;
+            }
+        }
+
+        if matches!(packet.protocol, ProtocolType::TCP | ProtocolType::QUIC) {
+            match self.transforms.inbound(packet.connection_id, std::mem::take(&mut packet.data)).await {
+                Inbound::Data(data) => packet.data = data,
+                Inbound::Control(data) => {
+                    match Heartbeat::decode(&data) {
+                        Some(heartbeat) => self.heartbeat(packet.connection_id, heartbeat, packet.timestamp).await,
+                        None => debug!("Dropping malformed heartbeat from {}", packet.connection_id),
+                    }
+                    return true;
+                }
+                Inbound::Established { pending, received } => {
+                    // Held-back sends go out on the next update, after anything already queued
+                    for (data, priority) in pending {
+                        let reply = OutgoingPacket {
+                            connection_id: packet.connection_id,
+                            protocol: packet.protocol,
+                            data,
+                            priority,
+                        };
+                        if self.outgoing.send(reply).is_err() {
+                            return false;
+                        }
+                    }
+                    for data in received {
+                        let early = IncomingPacket {
+                            connection_id: packet.connection_id,
+                            protocol: packet.protocol,
+                            data,
+                            timestamp: packet.timestamp,
+                        };
+                        if !self.dispatch(early).await {
+                            return false;
+                        }
+                    }
+                    return true;
+                }
+                Inbound::Nothing => return true,
+            }
+        }
+
+        self.dispatch(packet).await
+    }
+
+    /// Answer a ping, or match a pong to the ping it answers
+    pub(crate) async fn heartbeat(&self, connection_id: ConnectionId, heartbeat: Heartbeat, received: std::time::Instant) {
+        if heartbeat.reply {
+            self.health.heartbeat_reply(connection_id, heartbeat.nonce, received);
+            return;
+        }
+        let pong = Heartbeat { reply: true, nonce: heartbeat.nonce };
+        if let Err(e) = self.send_control(connection_id, pong.encode()).await {
+            debug!("Could not answer heartbeat from {}: {}", connection_id, e);
+        }
+    }
+
+    /// Send a heartbeat frame around the scheduler
+    ///
+    /// Stream transports carry it through the negotiated transforms as a control frame, so it is
+    /// authenticated like data; one due during the handshake is skipped.
+    pub(crate) async fn send_control(&self, connection_id: ConnectionId, frame: Vec<u8>) -> Result<()> {
+        let connections = self.connections.read().await;
+        let connection = connections
+            .get(&connection_id)
+            .ok_or(NetworkError::ConnectionNotFound { connection_id })?;
+        let len = match connection {
+            Connection::Udp(udp) => {
+                let sent = if udp.shared {
+                    udp.socket.send_to(&frame, udp.remote_addr).await
+                } else {
+                    udp.socket.send(&frame).await
+                };
+                sent.map_err(|source| NetworkError::Io { connection_id: Some(connection_id), source })?;
+                Some(frame.len())
+            }
+            Connection::Tcp(TcpConnection { outbound, .. }) => {
+                self.transforms
+                    .outbound_control_with(connection_id, frame, |frame| {
+                        let len = frame.len();
+                        outbound
+                            .send(Outbound::Data(frame))
+                            .map_err(|_| NetworkError::ConnectionClosed { connection_id })?;
+                        Ok(len)
+                    })
+                    .await?
+            }
+            Connection::WebSocket(WebSocketConnection { outbound, .. }) => {
+                let len = frame.len();
+                outbound
+                    .send(Outbound::Ping(frame))
+                    .map_err(|_| NetworkError::ConnectionClosed { connection_id })?;
+                Some(len)
+            }
+            Connection::Quic(quic) => match self.transforms.outbound_control_with(connection_id, frame, Ok).await? {
+                Some(frame) => {
+                    quic.send(&frame, PacketPriority::Critical).await?;
+                    Some(frame.len())
+                }
+                None => None,
+            },
+        };
+        if let Some(len) = len {
+            self.record_out(connection_id, len);
+        }
+        Ok(())
+    }
+
+    fn record_out(&self, connection_id: ConnectionId, bytes: usize) {
+        self.traffic.record_out(bytes);
+        self.health.record_out(connection_id, bytes);
+    }
+
+    async fn dispatch(&self, packet: IncomingPacket) -> bool {
+        if self.capture.is_active() {
+            let remote_addr = self.connections.read().await.get(&packet.connection_id).map(Connection::remote_addr);
+            self.capture.record(CaptureRecord {
+                timestamp: self.capture.wall_clock(packet.timestamp),
+                direction: CaptureDirection::Incoming,
+                connection_id: packet.connection_id,
+                protocol: packet.protocol,
+                priority: None,
+                remote_addr,
+                data: packet.data.clone(),
+            });
+        }
+
+        let handlers = self.handlers.read().await;
+        if let Some(handler) = handlers.get(&packet.protocol) {
+            match handler.handle_packet(&packet) {
+                Ok(replies) => {
+                    for reply in replies {
+                        if self.outgoing.send(reply).is_err() {
+                            return false;
+                        }
+                    }
+                }
+                Err(e) => warn!("{:?} handler rejected packet from {}: {}", packet.protocol, packet.connection_id, e),
+            }
+            return true;
+        }
+        drop(handlers);
+
+        self.incoming.send(packet).is_ok()
+    }
+}
+
 impl NetworkManager {
     pub async fn new(config: &NetworkConfig) -> Result<Self> {
         info!("Initializing network manager");
 
-        let (incoming_tx, _incoming_rx) = mpsc::unbounded_channel();
-        let (_outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+        let packet_handlers = Arc::new(RwLock::new(HashMap::new()));
+        let traffic = Arc::new(TrafficCounters::default());
+        let connections = Arc::new(RwLock::new(HashMap::new()));
 
         let manager = Self {
             config: config.clone(),
-            connections: Arc::new(RwLock::new(HashMap::new())),
+            connections: connections.clone(),
             listeners: Arc::new(Mutex::new(Vec::new())),
-            packet_handlers: Arc::new(RwLock::new(HashMap::new())),
-            incoming_packets: incoming_tx,
-            outgoing_packets: outgoing_rx,
+            sink: PacketSink {
+                incoming: incoming_tx,
+                outgoing: outgoing_tx,
+                handlers: packet_handlers.clone(),
+                traffic: traffic.clone(),
+                transforms: Arc::new(TransformRegistry::new(TransformConfig::from_network_config(config))),
+                capture: Arc::new(PacketCapture::new()),
+                health: Arc::new(HealthMonitor::new(
+                    std::time::Duration::from_millis(config.heartbeat_interval_ms),
+                    std::time::Duration::from_millis(config.connection_timeout_ms),
+                )),
+                connections,
+            },
+            packet_handlers,
+            incoming_receiver: Mutex::new(Some(incoming_rx)),
+            outgoing_packets: Mutex::new(outgoing_rx),
+            udp_peers: Arc::new(RwLock::new(HashMap::new())),
+            listener_tasks: Mutex::new(Vec::new()),
+            traffic,
+            quic_identity: Mutex::new(None),
+            quic_pinned_certificates: RwLock::new(Vec::new()),
+            default_throttle: RwLock::new(None),
+            schedulers: Mutex::new(HashMap::new()),
         };
 
         info!("Network manager initialized successfully");
@@ -145,33 +475,419 @@ impl NetworkManager {
     }
 
     /// Start listening on a specific address and protocol
-    pub async fn start_listener(&self, addr: SocketAddr, protocol: ProtocolType) -> Result<()> {
+    ///
+    /// Returns the locally bound address, which differs from `addr` when binding to port 0.
+    pub async fn start_listener(&self, addr: SocketAddr, protocol: ProtocolType) -> Result<SocketAddr> {
         info!("Starting listener for {:?} on {}", protocol, addr);
+        self.warn_unsupported_transforms(protocol);
 
-        let listener = match protocol {
+        let (listener, local_addr) = match protocol {
             ProtocolType::LLUDP => {
-                let socket = UdpSocket::bind(addr).await?;
-                Listener::Udp(socket)
+                let socket = Arc::new(UdpSocket::bind(addr).await?);
+                let local_addr = socket.local_addr()?;
+                let task = self.spawn_udp_receiver(socket.clone(), protocol);
+                self.listener_tasks.lock().await.push(task);
+                (Listener::Udp(socket), local_addr)
             }
             ProtocolType::WebSocket => {
-                let tcp_listener = TcpListener::bind(addr).await?;
-                Listener::WebSocket(tcp_listener)
+                let tcp_listener = Arc::new(TcpListener::bind(addr).await?);
+                let local_addr = tcp_listener.local_addr()?;
+                let task = self.spawn_stream_acceptor(tcp_listener.clone(), protocol);
+                self.listener_tasks.lock().await.push(task);
+                (Listener::WebSocket(tcp_listener), local_addr)
+            }
+            ProtocolType::TCP => {
+                let tcp_listener = Arc::new(TcpListener::bind(addr).await?);
+                let local_addr = tcp_listener.local_addr()?;
+                let task = self.spawn_stream_acceptor(tcp_listener.clone(), protocol);
+                self.listener_tasks.lock().await.push(task);
+                (Listener::Tcp(tcp_listener), local_addr)
             }
             ProtocolType::QUIC => {
-                // QUIC implementation would go here
-                return Err(anyhow::anyhow!("QUIC not implemented yet"));
+                let identity = self.quic_identity().await?;
+                let server_config = quic::server_config(&identity, self.idle_timeout())?;
+                let endpoint = quinn::Endpoint::server(server_config, addr)?;
+                let local_addr = endpoint.local_addr()?;
+                let task = self.spawn_quic_acceptor(endpoint.clone());
+                self.listener_tasks.lock().await.push(task);
+                (Listener::Quic(endpoint), local_addr)
             }
         };
 
         let mut listeners = self.listeners.lock().await;
         listeners.push(listener);
 
+        Ok(local_addr)
+    }
+
+    /// Take the receiving end of the incoming packet channel
+    ///
+    /// Only one consumer can own the stream; later calls return `None`.
+    pub async fn take_incoming(&self) -> Option<mpsc::UnboundedReceiver<IncomingPacket>> {
+        self.incoming_receiver.lock().await.take()
+    }
+
+    /// Receive datagrams on a listener socket, registering each new remote peer as a connection
+    fn spawn_udp_receiver(&self, socket: Arc<UdpSocket>, protocol: ProtocolType) -> JoinHandle<()> {
+        let connections = self.connections.clone();
+        let udp_peers = self.udp_peers.clone();
+        let sink = self.sink.clone();
+        let buffer_size = self.config.packet_buffer_size.max(1500);
+        let max_connections = self.config.max_connections;
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; buffer_size];
+
+            loop {
+                let (len, remote_addr) = match socket.recv_from(&mut buf).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        // ICMP port-unreachable surfaces here on some platforms; keep serving
+                        warn!("UDP listener receive error: {}", e);
+                        continue;
+                    }
+                };
+
+                let known = udp_peers.read().await.get(&remote_addr).copied();
+                let connection_id = match known {
+                    Some(id) => id,
+                    None => {
+                        let mut connections = connections.write().await;
+                        if connections.len() >= max_connections {
+                            warn!("Dropping datagram from {}: connection limit reached", remote_addr);
+                            continue;
+                        }
+
+                        let id = ConnectionId::new_v4();
+                        connections.insert(id, Connection::Udp(UdpConnection {
+                            id,
+                            socket: socket.clone(),
+                            remote_addr,
+                            shared: true,
+                        }));
+                        udp_peers.write().await.insert(remote_addr, id);
+                        sink.health.register(id, protocol, remote_addr);
+                        info!("New UDP peer {} registered as {}", remote_addr, id);
+                        id
+                    }
+                };
+
+                let packet = IncomingPacket {
+                    connection_id,
+                    protocol,
+                    data: buf[..len].to_vec(),
+                    timestamp: std::time::Instant::now(),
+                };
+
+                if !sink.deliver(packet).await {
+                    // Manager dropped; nobody left to deliver to
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Accept QUIC connections on a listener endpoint
+    fn spawn_quic_acceptor(&self, endpoint: quinn::Endpoint) -> JoinHandle<()> {
+        let connections = self.connections.clone();
+        let sink = self.sink.clone();
+        let max_connections = self.config.max_connections;
+
+        tokio::spawn(async move {
+            while let Some(connecting) = endpoint.accept().await {
+                let connections = connections.clone();
+                let sink = sink.clone();
+
+                // Handshakes run concurrently so a slow peer cannot stall the listener
+                tokio::spawn(async move {
+                    let connection = match connecting.await {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            warn!("QUIC handshake failed: {}", e);
+                            return;
+                        }
+                    };
+
+                    let mut connections = connections.write().await;
+                    if connections.len() >= max_connections {
+                        warn!("Refusing QUIC peer {}: connection limit reached", connection.remote_address());
+                        connection.close(0u32.into(), b"server full");
+                        return;
+                    }
+
+                    let id = ConnectionId::new_v4();
+                    info!("New QUIC peer {} registered as {}", connection.remote_address(), id);
+                    sink.health.register(id, ProtocolType::QUIC, connection.remote_address());
+                    let hello = sink.transforms.start(id, false).await;
+                    quic::spawn_readers(connection.clone(), id, sink);
+                    let quic = Box::new(QuicConnection::new(id, connection, None));
+                    match hello {
+                        Ok(hello) => {
+                            if let Err(e) = quic.send(&hello, PacketPriority::Critical).await {
+                                warn!("Could not send transform handshake to {}: {}", id, e);
+                            }
+                        }
+                        Err(e) => warn!("Could not start transform handshake for {}: {}", id, e),
+                    }
+                    connections.insert(id, Connection::Quic(quic));
+                });
+            }
+        })
+    }
+
+    /// Accept TCP or WebSocket connections on a listener
+    fn spawn_stream_acceptor(&self, listener: Arc<TcpListener>, protocol: ProtocolType) -> JoinHandle<()> {
+        let connections = self.connections.clone();
+        let sink = self.sink.clone();
+        let max_connections = self.config.max_connections;
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // Usually a transient resource limit such as running out of file descriptors
+                        warn!("{:?} listener accept error: {}", protocol, e);
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                if connections.read().await.len() >= max_connections {
+                    warn!("Refusing {:?} peer {}: connection limit reached", protocol, remote_addr);
+                    continue;
+                }
+
+                let connections = connections.clone();
+                let sink = sink.clone();
+                // Handshakes run concurrently so a slow peer cannot stall the listener
+                tokio::spawn(async move {
+                    let _ = stream.set_nodelay(true);
+                    let transport = if protocol == ProtocolType::WebSocket {
+                        match tokio_tungstenite::accept_async(MaybeTlsStream::Plain(stream)).await {
+                            Ok(ws_stream) => StreamTransport::WebSocket(Box::new(ws_stream)),
+                            Err(e) => {
+                                warn!("WebSocket handshake with {} failed: {}", remote_addr, e);
+                                return;
+                            }
+                        }
+                    } else {
+                        StreamTransport::Tcp(stream)
+                    };
+
+                    let id = register_stream_connection(&connections, &sink, transport, remote_addr, protocol).await;
+                    info!("New {:?} peer {} registered as {}", protocol, remote_addr, id);
+                });
+            }
+        })
+    }
+
+    /// Receive datagrams on a connected client socket; they all belong to one connection
+    fn spawn_client_udp_receiver(
+        &self,
+        socket: Arc<UdpSocket>,
+        connection_id: ConnectionId,
+        protocol: ProtocolType,
+    ) -> JoinHandle<()> {
+        let connections = self.connections.clone();
+        let sink = self.sink.clone();
+        let buffer_size = self.config.packet_buffer_size.max(1500);
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; buffer_size];
+
+            loop {
+                let len = match socket.recv(&mut buf).await {
+                    Ok(len) => len,
+                    Err(e) => {
+                        warn!("UDP receive error on {}: {}", connection_id, e);
+                        continue;
+                    }
+                };
+
+                // Connection closed; stop reading its socket
+                if !connections.read().await.contains_key(&connection_id) {
+                    break;
+                }
+
+                let packet = IncomingPacket {
+                    connection_id,
+                    protocol,
+                    data: buf[..len].to_vec(),
+                    timestamp: std::time::Instant::now(),
+                };
+
+                if !sink.deliver(packet).await {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Remote address of a connection, if it is still open
+    pub async fn remote_addr(&self, connection_id: ConnectionId) -> Option<SocketAddr> {
+        self.connections.read().await.get(&connection_id).map(Connection::remote_addr)
+    }
+
+    /// Close a connection
+    ///
+    /// Stream and QUIC peers see a graceful close after data already queued for them is sent;
+    /// UDP peers are simply forgotten.
+    pub async fn close_connection(&self, connection_id: ConnectionId) -> bool {
+        self.close_with_reason(connection_id, DisconnectReason::Closed).await
+    }
+
+    async fn close_with_reason(&self, connection_id: ConnectionId, reason: DisconnectReason) -> bool {
+        if !matches!(reason, DisconnectReason::Closed) {
+            info!("Closing {}: {}", connection_id, reason);
+        }
+        let removed = self.connections.write().await.remove(&connection_id);
+        self.sink.health.disconnect(connection_id, reason);
+        self.schedulers.lock().await.remove(&connection_id);
+        self.sink.transforms.remove(connection_id).await;
+        if let Some(connection) = &removed {
+            if let Connection::Udp(udp) = connection {
+                self.udp_peers.write().await.remove(&udp.remote_addr);
+            }
+            connection.close();
+        }
+        removed.is_some()
+    }
+
+    /// Use a specific certificate for QUIC listeners started after this call
+    pub async fn set_quic_identity(&self, identity: QuicIdentity) {
+        *self.quic_identity.lock().await = Some(identity);
+    }
+
+    /// Certificate QUIC listeners present, generating a development one if none was set
+    ///
+    /// Clients of a development server pass the certificate to `pin_quic_certificate`.
+    pub async fn quic_identity(&self) -> Result<QuicIdentity> {
+        let mut identity = self.quic_identity.lock().await;
+        if identity.is_none() {
+            info!("Generating self-signed QUIC certificate for development");
+            *identity = Some(QuicIdentity::development()?);
+        }
+        Ok(identity.clone().expect("identity set above"))
+    }
+
+    /// Trust a certificate for outgoing QUIC connections
+    ///
+    /// Once any certificate is pinned, the platform trust roots are no longer used.
+    pub async fn pin_quic_certificate(&self, certificate_der: Vec<u8>) {
+        self.quic_pinned_certificates.write().await.push(certificate_der);
+    }
+
+    /// Prime compression with a dictionary, used with peers that hold the same one
+    ///
+    /// Applies to connections opened afterwards.
+    pub fn set_compression_dictionary(&self, dictionary: Option<Vec<u8>>) {
+        self.sink
+            .transforms
+            .update_config(|config| config.dictionary = dictionary.map(Arc::new));
+    }
+
+    /// Mix a key shared out of band into encryption keys, which authenticates the peer
+    ///
+    /// Applies to connections opened afterwards.
+    pub fn set_pre_shared_key(&self, key: Option<Vec<u8>>) {
+        self.sink
+            .transforms
+            .update_config(|config| config.pre_shared_key = key.map(Arc::new));
+    }
+
+    /// Transforms agreed with a TCP or QUIC peer, once its handshake has completed
+    pub async fn negotiated_transforms(&self, connection_id: ConnectionId) -> Option<Negotiated> {
+        self.sink.transforms.negotiated(connection_id).await
+    }
+
+    /// Wait for a new connection's transform handshake, closing it if that fails
+    async fn await_transforms(&self, connection_id: ConnectionId) -> Result<()> {
+        if let Err(e) = self.sink.transforms.wait_established(connection_id, self.idle_timeout()).await {
+            self.close_with_reason(connection_id, DisconnectReason::HandshakeFailed(e.to_string())).await;
+            return Err(e);
+        }
         Ok(())
     }
 
+    /// Connect over QUIC, verifying the server certificate against `server_name`
+    pub async fn connect_quic(&self, addr: SocketAddr, server_name: &str) -> Result<ConnectionId> {
+        info!("Connecting to {} ({}) via QUIC", addr, server_name);
+
+        let pinned = self.quic_pinned_certificates.read().await.clone();
+        let client_config = quic::client_config(&pinned, self.idle_timeout())?;
+        let bind_addr: SocketAddr = if addr.is_ipv6() {
+            "[::]:0".parse()?
+        } else {
+            "0.0.0.0:0".parse()?
+        };
+        let endpoint = quinn::Endpoint::client(bind_addr)?;
+        let connection = endpoint
+            .connect_with(client_config, addr, server_name)?
+            .await
+            .map_err(|e| match e {
+                quinn::ConnectionError::TimedOut => anyhow::Error::from(NetworkError::Timeout { connection_id: None }),
+                other => anyhow::anyhow!("QUIC handshake with {} failed: {}", addr, other),
+            })?;
+
+        let connection_id = ConnectionId::new_v4();
+        // TLS already encrypts QUIC, so only compression is negotiated
+        let hello = self.sink.transforms.start(connection_id, false).await?;
+        self.sink.health.register(connection_id, ProtocolType::QUIC, connection.remote_address());
+        quic::spawn_readers(connection.clone(), connection_id, self.sink.clone());
+        let quic = Box::new(QuicConnection::new(connection_id, connection, Some(endpoint)));
+        quic.send(&hello, PacketPriority::Critical).await?;
+        self.connections.write().await.insert(connection_id, Connection::Quic(quic));
+        self.await_transforms(connection_id).await?;
+
+        info!("Connected to {} with ID: {}", addr, connection_id);
+        Ok(connection_id)
+    }
+
+    /// Send an unreliable datagram, for state updates that are stale by the time a resend arrives
+    ///
+    /// QUIC connections use datagram frames; UDP connections send the data as-is, and other
+    /// transports fall back to a low priority reliable send.
+    pub async fn send_datagram(&self, connection_id: ConnectionId, data: Vec<u8>) -> Result<()> {
+        {
+            let connections = self.connections.read().await;
+            if let Some(Connection::Quic(quic)) = connections.get(&connection_id) {
+                let Some(frame) = self.sink.transforms.outbound_datagram(connection_id, data).await? else {
+                    return Ok(());
+                };
+                let len = frame.len();
+                quic.send_datagram(frame)?;
+                self.sink.record_out(connection_id, len);
+                return Ok(());
+            }
+        }
+        self.send_packet(connection_id, data, PacketPriority::Low).await
+    }
+
+    /// Largest datagram a connection can carry, if it is known
+    pub async fn max_datagram_size(&self, connection_id: ConnectionId) -> Option<usize> {
+        match self.connections.read().await.get(&connection_id)? {
+            Connection::Quic(quic) => quic.max_datagram_size(),
+            Connection::Udp(_) => Some(self.config.packet_buffer_size),
+            _ => None,
+        }
+    }
+
+    fn warn_unsupported_transforms(&self, protocol: ProtocolType) {
+        let unsupported = self.sink.transforms.config().unsupported_by(protocol);
+        if !unsupported.is_empty() {
+            warn!("{:?} cannot negotiate {}; its traffic is sent untransformed", protocol, unsupported.join(" or "));
+        }
+    }
+
+    fn idle_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.config.connection_timeout_ms)
+    }
+
     /// Connect to a remote address
     pub async fn connect(&self, addr: SocketAddr, protocol: ProtocolType) -> Result<ConnectionId> {
         info!("Connecting to {} via {:?}", addr, protocol);
+        self.warn_unsupported_transforms(protocol);
 
         let connection_id = ConnectionId::new_v4();
 
@@ -179,11 +895,15 @@ impl NetworkManager {
             ProtocolType::LLUDP => {
                 let socket = UdpSocket::bind("0.0.0.0:0").await?;
                 socket.connect(addr).await?;
+                let socket = Arc::new(socket);
+                self.sink.health.register(connection_id, protocol, addr);
+                let task = self.spawn_client_udp_receiver(socket.clone(), connection_id, protocol);
+                self.listener_tasks.lock().await.push(task);
                 Connection::Udp(UdpConnection {
                     id: connection_id,
-                    socket: Arc::new(socket),
+                    socket,
                     remote_addr: addr,
-                    last_activity: std::time::Instant::now(),
+                    shared: false,
                 })
             }
             ProtocolType::WebSocket => {
@@ -191,15 +911,24 @@ impl NetworkManager {
                     format!("ws://{}", addr)
                 ).await?;
 
-                Connection::WebSocket(WebSocketConnection {
-                    id: connection_id,
-                    stream: ws_stream,
-                    remote_addr: addr,
-                    last_activity: std::time::Instant::now(),
-                })
+                let transport = StreamTransport::WebSocket(Box::new(ws_stream));
+                let id = register_stream_connection(&self.connections, &self.sink, transport, addr, protocol).await;
+                info!("Connected to {} with ID: {}", addr, id);
+                return Ok(id);
+            }
+            ProtocolType::TCP => {
+                let stream = tokio::net::TcpStream::connect(addr).await?;
+                let _ = stream.set_nodelay(true);
+
+                let transport = StreamTransport::Tcp(stream);
+                let id = register_stream_connection(&self.connections, &self.sink, transport, addr, protocol).await;
+                self.await_transforms(id).await?;
+                info!("Connected to {} with ID: {}", addr, id);
+                return Ok(id);
             }
             ProtocolType::QUIC => {
-                return Err(anyhow::anyhow!("QUIC not implemented yet"));
+                // Certificates for IP addresses carry them as subject alternative names
+                return self.connect_quic(addr, &addr.ip().to_string()).await;
             }
         };
 
@@ -211,29 +940,248 @@ impl NetworkManager {
     }
 
     /// Send a packet to a specific connection
+    ///
+    /// When the connection is throttled the packet is queued as `ThrottleCategory::Task` traffic
+    /// and may go out on a later `update`; use `send_scheduled` to pick the category.
     pub async fn send_packet(&self, connection_id: ConnectionId, data: Vec<u8>, priority: PacketPriority) -> Result<()> {
+        if self.is_throttled(connection_id).await {
+            let packet = ScheduledPacket::new(data, priority, ThrottleCategory::Task);
+            return self.send_scheduled(connection_id, packet).await;
+        }
+        self.transmit(connection_id, data, priority).await
+    }
+
+    /// Queue a packet behind the connection's throttles, sending whatever they allow now
+    ///
+    /// Unthrottled connections send it immediately.
+    pub async fn send_scheduled(&self, connection_id: ConnectionId, packet: ScheduledPacket) -> Result<()> {
+        if !self.connections.read().await.contains_key(&connection_id) {
+            return Err(NetworkError::ConnectionNotFound { connection_id }.into());
+        }
+
+        {
+            let mut schedulers = self.schedulers.lock().await;
+            let scheduler = match schedulers.get_mut(&connection_id) {
+                Some(scheduler) => scheduler,
+                None => match self.default_throttle.read().await.clone() {
+                    Some(config) => schedulers
+                        .entry(connection_id)
+                        .or_insert_with(|| SendScheduler::new(config, std::time::Instant::now())),
+                    None => {
+                        drop(schedulers);
+                        return self.transmit(connection_id, packet.data, packet.priority).await;
+                    }
+                },
+            };
+            scheduler.enqueue(packet);
+        }
+
+        self.pump_scheduler(connection_id).await
+    }
+
+    /// Throttle every connection, or stop throttling with `None`
+    ///
+    /// Per-connection limits set with `set_connection_throttle` are replaced. Packets still
+    /// queued when throttling stops are sent at once.
+    pub async fn set_throttle(&self, config: Option<ThrottleConfig>) -> Result<()> {
+        *self.default_throttle.write().await = config.clone();
+
+        let now = std::time::Instant::now();
+        let drained: Vec<(ConnectionId, Vec<ScheduledPacket>)> = {
+            let mut schedulers = self.schedulers.lock().await;
+            match config {
+                Some(config) => {
+                    for scheduler in schedulers.values_mut() {
+                        scheduler.set_config(config.clone(), now);
+                    }
+                    Vec::new()
+                }
+                None => schedulers
+                    .drain()
+                    .map(|(connection_id, mut scheduler)| (connection_id, scheduler.drain()))
+                    .collect(),
+            }
+        };
+
+        for (connection_id, packets) in drained {
+            for packet in packets {
+                // The connection may have closed while its packets were queued
+                let _ = self.transmit(connection_id, packet.data, packet.priority).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Limit one connection, e.g. to the rates a viewer asked for in AgentThrottle
+    pub async fn set_connection_throttle(&self, connection_id: ConnectionId, config: ThrottleConfig) -> Result<()> {
+        if !self.connections.read().await.contains_key(&connection_id) {
+            return Err(NetworkError::ConnectionNotFound { connection_id }.into());
+        }
+
+        let now = std::time::Instant::now();
+        self.schedulers
+            .lock()
+            .await
+            .entry(connection_id)
+            .and_modify(|scheduler| scheduler.set_config(config.clone(), now))
+            .or_insert_with(|| SendScheduler::new(config, now));
+        Ok(())
+    }
+
+    /// Queue depth and drop counters for a throttled connection
+    pub async fn scheduler_stats(&self, connection_id: ConnectionId) -> Option<SchedulerStats> {
+        self.schedulers.lock().await.get(&connection_id).map(SendScheduler::stats)
+    }
+
+    async fn is_throttled(&self, connection_id: ConnectionId) -> bool {
+        self.default_throttle.read().await.is_some() || self.schedulers.lock().await.contains_key(&connection_id)
+    }
+
+    /// Send what the connection's throttles currently allow
+    async fn pump_scheduler(&self, connection_id: ConnectionId) -> Result<()> {
+        let ready = match self.schedulers.lock().await.get_mut(&connection_id) {
+            Some(scheduler) => scheduler.poll(std::time::Instant::now()),
+            None => return Ok(()),
+        };
+
+        for packet in ready {
+            self.transmit(connection_id, packet.data, packet.priority).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_scheduled(&self) {
+        let connection_ids: Vec<ConnectionId> = self.schedulers.lock().await.keys().copied().collect();
+        for connection_id in connection_ids {
+            if let Err(e) = self.pump_scheduler(connection_id).await {
+                debug!("Scheduled send to {} failed: {}", connection_id, e);
+            }
+        }
+    }
+
+    async fn transmit(&self, connection_id: ConnectionId, data: Vec<u8>, priority: PacketPriority) -> Result<()> {
         let connections = self.connections.read().await;
 
         if let Some(connection) = connections.get(&connection_id) {
+            if self.sink.capture.is_active() {
+                self.sink.capture.record(CaptureRecord {
+                    timestamp: std::time::SystemTime::now(),
+                    direction: CaptureDirection::Outgoing,
+                    connection_id,
+                    protocol: connection.protocol(),
+                    priority: Some(priority),
+                    remote_addr: Some(connection.remote_addr()),
+                    data: data.clone(),
+                });
+            }
             match connection {
                 Connection::Udp(udp_conn) => {
-                    udp_conn.socket.send(&data).await?;
+                    let sent = if udp_conn.shared {
+                        udp_conn.socket.send_to(&data, udp_conn.remote_addr).await
+                    } else {
+                        udp_conn.socket.send(&data).await
+                    };
+                    sent.map_err(|source| NetworkError::Io { connection_id: Some(connection_id), source })?;
+                    self.sink.record_out(connection_id, data.len());
                 }
-                Connection::WebSocket(ws_conn) => {
-                    // WebSocket sending would be implemented here
-                    // This is a simplified placeholder
+                // Stream transports carry one ordered stream, so priority does not apply
+                Connection::WebSocket(WebSocketConnection { outbound, .. }) => {
+                    let len = data.len();
+                    outbound
+                        .send(Outbound::Data(data))
+                        .map_err(|_| NetworkError::ConnectionClosed { connection_id })?;
+                    self.sink.record_out(connection_id, len);
                 }
-                Connection::Tcp(tcp_conn) => {
-                    // TCP sending would be implemented here
+                Connection::Tcp(TcpConnection { outbound, .. }) => {
+                    let sent = self
+                        .sink
+                        .transforms
+                        .outbound_with(connection_id, data, priority, |frame| {
+                            let len = frame.len();
+                            outbound
+                                .send(Outbound::Data(frame))
+                                .map_err(|_| NetworkError::ConnectionClosed { connection_id })?;
+                            Ok(len)
+                        })
+                        .await?;
+                    if let Some(len) = sent {
+                        self.sink.record_out(connection_id, len);
+                    }
+                }
+                Connection::Quic(quic_conn) => {
+                    if let Some(frame) = self.sink.transforms.outbound(connection_id, data, priority).await? {
+                        quic_conn.send(&frame, priority).await?;
+                        self.sink.record_out(connection_id, frame.len());
+                    }
                 }
             }
         } else {
-            return Err(anyhow::anyhow!("Connection not found: {}", connection_id));
+            return Err(NetworkError::ConnectionNotFound { connection_id }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Totals of packets and bytes moved since the manager started
+    pub async fn traffic_stats(&self) -> TrafficStats {
+        let active_connections = self.connections.read().await.len();
+        self.traffic.snapshot(active_connections)
+    }
+
+    /// Shared counters, for protocol layers that report resends
+    pub fn traffic_counters(&self) -> Arc<TrafficCounters> {
+        self.traffic.clone()
+    }
+
+    /// Count retransmissions on a connection, in its stats and in the totals
+    pub fn record_resends(&self, connection_id: ConnectionId, count: u64) {
+        for _ in 0..count {
+            self.traffic.record_resend();
+        }
+        self.sink.health.record_resends(connection_id, count);
+    }
+
+    /// Traffic totals and the health of every open connection
+    pub async fn stats(&self) -> NetworkStats {
+        NetworkStats {
+            traffic: self.traffic_stats().await,
+            connections: self.sink.health.all_stats(),
         }
+    }
+
+    /// RTT, loss and traffic of one connection
+    pub fn connection_stats(&self, connection_id: ConnectionId) -> Option<ConnectionStats> {
+        self.sink.health.stats(connection_id)
+    }
 
+    /// Receive connect and disconnect notifications
+    ///
+    /// Each subscriber sees events from the moment it subscribes; one that falls more than
+    /// 1024 events behind loses the oldest.
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<NetworkEvent> {
+        self.sink.health.subscribe()
+    }
+
+    /// Record every packet received and sent to a pcapng file until `stop_capture`
+    ///
+    /// Records use link type `CAPTURE_LINK_TYPE`; load them with `read_capture` or `Replayer`.
+    pub fn start_capture(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let file = std::fs::File::create(path.as_ref())?;
+        self.start_capture_to(Box::new(std::io::BufWriter::new(file)))?;
+        info!("Capturing packets to {}", path.as_ref().display());
         Ok(())
     }
 
+    /// Record packets to any writer, replacing a running capture
+    pub fn start_capture_to(&self, writer: Box<dyn std::io::Write + Send>) -> Result<()> {
+        self.sink.capture.start(writer)
+    }
+
+    /// Stop capturing and flush the file; false if no capture was running
+    pub fn stop_capture(&self) -> Result<bool> {
+        self.sink.capture.stop()
+    }
+
     /// Register a packet handler for a protocol
     pub async fn register_packet_handler<H: PacketHandler + 'static>(&self, handler: H) {
         let mut handlers = self.packet_handlers.write().await;
@@ -242,20 +1190,59 @@ impl NetworkManager {
 
     /// Update network manager (called from main loop)
     pub async fn update(&self) -> Result<()> {
-        // Process outgoing packets
-        // Handle connection timeouts
-        // Clean up dead connections
+        self.flush_outgoing().await;
+        self.flush_scheduled().await;
+        self.send_heartbeats().await;
         self.cleanup_dead_connections().await?;
         Ok(())
     }
 
+    async fn send_heartbeats(&self) {
+        for (connection_id, ping) in self.sink.health.due_heartbeats(std::time::Instant::now()) {
+            if let Err(e) = self.sink.send_control(connection_id, ping).await {
+                debug!("Heartbeat to {} failed: {}", connection_id, e);
+            }
+        }
+    }
+
+    /// Queue a packet to be sent on the next `update`
+    pub fn queue_packet(&self, packet: OutgoingPacket) -> Result<()> {
+        self.sink
+            .outgoing
+            .send(packet)
+            .map_err(|_| anyhow::anyhow!("Network manager is shutting down"))
+    }
+
+    /// Send packets queued by `queue_packet` and by packet handlers
+    async fn flush_outgoing(&self) {
+        let mut outgoing = self.outgoing_packets.lock().await;
+        while let Ok(packet) = outgoing.try_recv() {
+            if let Err(e) = self.send_packet(packet.connection_id, packet.data, packet.priority).await {
+                // The connection may have closed since the packet was queued
+                warn!("Dropping queued {:?} packet for {}: {}", packet.protocol, packet.connection_id, e);
+            }
+        }
+    }
+
     /// Shutdown network manager
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down network manager");
 
-        // Close all connections
+        // Stop listener receive loops before dropping their sockets
+        for task in self.listener_tasks.lock().await.drain(..) {
+            task.abort();
+        }
+
+        // Send what is already queued, then close all connections
+        self.flush_outgoing().await;
         let mut connections = self.connections.write().await;
-        connections.clear();
+        for (connection_id, connection) in connections.drain() {
+            connection.close();
+            self.sink.health.disconnect(connection_id, DisconnectReason::Shutdown);
+        }
+        self.udp_peers.write().await.clear();
+        self.schedulers.lock().await.clear();
+        self.sink.transforms.retain(&Default::default()).await;
 
         // Close all listeners
         let mut listeners = self.listeners.lock().await;
@@ -266,19 +1253,42 @@ impl NetworkManager {
     }
 
     async fn cleanup_dead_connections(&self) -> Result<()> {
-        let mut connections = self.connections.write().await;
-        let timeout = std::time::Duration::from_millis(self.config.connection_timeout_ms);
-        let now = std::time::Instant::now();
+        for connection_id in self.sink.transforms.failed().await {
+            let reason = DisconnectReason::HandshakeFailed("transform negotiation failed".to_string());
+            self.close_with_reason(connection_id, reason).await;
+        }
 
-        connections.retain(|_id, connection| {
-            let last_activity = match connection {
-                Connection::Tcp(tcp) => tcp.last_activity,
-                Connection::Udp(udp) => udp.last_activity,
-                Connection::WebSocket(ws) => ws.last_activity,
-            };
+        // Nothing heard, not even a heartbeat reply, for the whole connection timeout
+        for connection_id in self.sink.health.expired(std::time::Instant::now()) {
+            self.close_with_reason(connection_id, DisconnectReason::TimedOut).await;
+        }
 
-            now.duration_since(last_activity) < timeout
-        });
+        let failed: Vec<(ConnectionId, DisconnectReason)> = self
+            .connections
+            .read()
+            .await
+            .iter()
+            .filter_map(|(connection_id, connection)| {
+                let reason = match connection {
+                    // Stream readers remove their connection on close; this catches failed writers
+                    Connection::Tcp(TcpConnection { outbound, .. })
+                    | Connection::WebSocket(WebSocketConnection { outbound, .. }) => outbound
+                        .is_closed()
+                        .then(|| DisconnectReason::Error("stream writer stopped".to_string())),
+                    Connection::Quic(quic) => quic.close_reason(),
+                    Connection::Udp(_) => None,
+                };
+                reason.map(|reason| (*connection_id, reason))
+            })
+            .collect();
+        for (connection_id, reason) in failed {
+            self.close_with_reason(connection_id, reason).await;
+        }
+
+        // Drop queues of connections that closed by any route
+        let open: std::collections::HashSet<ConnectionId> = self.connections.read().await.keys().copied().collect();
+        self.schedulers.lock().await.retain(|connection_id, _| open.contains(connection_id));
+        self.sink.transforms.retain(&open).await;
 
         Ok(())
     }
@@ -296,9 +1306,1156 @@ mod tests {
             packet_buffer_size: 8192,
             compression_enabled: true,
             encryption_enabled: true,
+            heartbeat_interval_ms: 1000,
         };
 
         let manager = NetworkManager::new(&config).await;
         assert!(manager.is_ok());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_udp_listener_registers_peers() {
+        let config = NetworkConfig {
+            max_connections: 100,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+
+        let manager = NetworkManager::new(&config).await.unwrap();
+        let mut incoming = manager.take_incoming().await.unwrap();
+        assert!(manager.take_incoming().await.is_none());
+
+        let addr = manager
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::LLUDP)
+            .await
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"hello", addr).await.unwrap();
+        client.send_to(b"again", addr).await.unwrap();
+
+        let first = incoming.recv().await.unwrap();
+        let second = incoming.recv().await.unwrap();
+        assert_eq!(first.data, b"hello");
+        assert_eq!(first.connection_id, second.connection_id);
+
+        // Replies go back through the shared listener socket
+        manager
+            .send_packet(first.connection_id, b"reply".to_vec(), PacketPriority::Normal)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 16];
+        let (len, from) = client.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"reply");
+        assert_eq!(from, addr);
+
+        let stats = manager.traffic_stats().await;
+        assert_eq!(stats.packets_in, 2);
+        assert_eq!(stats.bytes_in, 10);
+        assert_eq!(stats.packets_out, 1);
+        assert_eq!(stats.active_connections, 1);
+
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_connection_error_is_typed() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+
+        let manager = NetworkManager::new(&config).await.unwrap();
+        let connection_id = uuid::Uuid::new_v4();
+        let error = manager
+            .send_packet(connection_id, vec![1, 2, 3], PacketPriority::Normal)
+            .await
+            .unwrap_err();
+
+        let network_error = error.downcast_ref::<NetworkError>().unwrap();
+        assert_eq!(network_error.code(), 1001);
+        assert_eq!(network_error.connection_id(), Some(connection_id));
+        assert!(!network_error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_quic_streams_and_datagrams() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+
+        let server = NetworkManager::new(&config).await.unwrap();
+        let mut server_incoming = server.take_incoming().await.unwrap();
+        let addr = server
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::QUIC)
+            .await
+            .unwrap();
+
+        let client = NetworkManager::new(&config).await.unwrap();
+        let mut client_incoming = client.take_incoming().await.unwrap();
+        client
+            .pin_quic_certificate(server.quic_identity().await.unwrap().certificate_der)
+            .await;
+        let connection_id = client.connect(addr, ProtocolType::QUIC).await.unwrap();
+
+        client
+            .send_packet(connection_id, b"critical".to_vec(), PacketPriority::Critical)
+            .await
+            .unwrap();
+        client
+            .send_packet(connection_id, b"bulk".to_vec(), PacketPriority::Low)
+            .await
+            .unwrap();
+        client.send_datagram(connection_id, b"position".to_vec()).await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let packet = server_incoming.recv().await.unwrap();
+            assert_eq!(packet.protocol, ProtocolType::QUIC);
+            received.push(packet);
+        }
+        let peer = received[0].connection_id;
+        let mut payloads: Vec<Vec<u8>> = received.into_iter().map(|p| p.data).collect();
+        payloads.sort();
+        assert_eq!(payloads, vec![b"bulk".to_vec(), b"critical".to_vec(), b"position".to_vec()]);
+
+        server
+            .send_packet(peer, b"welcome".to_vec(), PacketPriority::Normal)
+            .await
+            .unwrap();
+        let reply = client_incoming.recv().await.unwrap();
+        assert_eq!(reply.connection_id, connection_id);
+        assert_eq!(reply.data, b"welcome");
+
+        client.shutdown().await.unwrap();
+        server.shutdown().await.unwrap();
+    }
+
+    struct EchoHandler;
+
+    impl PacketHandler for EchoHandler {
+        fn handle_packet(&self, packet: &IncomingPacket) -> Result<Vec<OutgoingPacket>> {
+            Ok(vec![OutgoingPacket {
+                connection_id: packet.connection_id,
+                protocol: packet.protocol,
+                data: packet.data.clone(),
+                priority: PacketPriority::Normal,
+            }])
+        }
+
+        fn protocol_type(&self) -> ProtocolType {
+            ProtocolType::TCP
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_frames_reach_packet_handlers() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+
+        let server = NetworkManager::new(&config).await.unwrap();
+        server.register_packet_handler(EchoHandler).await;
+        let addr = server
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::TCP)
+            .await
+            .unwrap();
+
+        let client = NetworkManager::new(&config).await.unwrap();
+        let mut incoming = client.take_incoming().await.unwrap();
+        let connection_id = client.connect(addr, ProtocolType::TCP).await.unwrap();
+
+        let large = vec![7u8; 100_000];
+        client
+            .send_packet(connection_id, b"ping".to_vec(), PacketPriority::Normal)
+            .await
+            .unwrap();
+        client
+            .send_packet(connection_id, large.clone(), PacketPriority::Normal)
+            .await
+            .unwrap();
+
+        // Echoes are queued by the handler and sent from update()
+        let mut echoed = Vec::new();
+        while echoed.len() < 2 {
+            server.update().await.unwrap();
+            if let Ok(Some(packet)) =
+                tokio::time::timeout(std::time::Duration::from_millis(20), incoming.recv()).await
+            {
+                assert_eq!(packet.connection_id, connection_id);
+                echoed.push(packet.data);
+            }
+        }
+        assert_eq!(echoed, vec![b"ping".to_vec(), large]);
+
+        // A graceful close removes the connection on both ends
+        assert!(client.close_connection(connection_id).await);
+        for _ in 0..100 {
+            if server.traffic_stats().await.active_connections == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.traffic_stats().await.active_connections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_send_and_receive() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+
+        let server = NetworkManager::new(&config).await.unwrap();
+        let mut server_incoming = server.take_incoming().await.unwrap();
+        let addr = server
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::WebSocket)
+            .await
+            .unwrap();
+
+        let client = NetworkManager::new(&config).await.unwrap();
+        let mut client_incoming = client.take_incoming().await.unwrap();
+        let connection_id = client.connect(addr, ProtocolType::WebSocket).await.unwrap();
+
+        client
+            .send_packet(connection_id, b"hello".to_vec(), PacketPriority::Normal)
+            .await
+            .unwrap();
+        let packet = server_incoming.recv().await.unwrap();
+        assert_eq!(packet.protocol, ProtocolType::WebSocket);
+        assert_eq!(packet.data, b"hello");
+
+        server
+            .send_packet(packet.connection_id, b"welcome".to_vec(), PacketPriority::Normal)
+            .await
+            .unwrap();
+        let reply = client_incoming.recv().await.unwrap();
+        assert_eq!(reply.connection_id, connection_id);
+        assert_eq!(reply.data, b"welcome");
+
+        // The server's close frame removes the connection on the client
+        server.shutdown().await.unwrap();
+        for _ in 0..100 {
+            if client.remote_addr(connection_id).await.is_none() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(client.remote_addr(connection_id).await.is_none());
+    }
+
+    #[test]
+    fn test_transforms_unsupported_by_datagram_and_websocket() {
+        let config = TransformConfig::from_network_config(&NetworkConfig {
+            max_connections: 16,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: true,
+            encryption_enabled: true,
+            heartbeat_interval_ms: 0,
+        });
+        assert_eq!(config.unsupported_by(ProtocolType::LLUDP), vec!["compression", "encryption"]);
+        assert_eq!(config.unsupported_by(ProtocolType::WebSocket), vec!["compression", "encryption"]);
+        assert!(config.unsupported_by(ProtocolType::TCP).is_empty());
+        assert!(config.unsupported_by(ProtocolType::QUIC).is_empty());
+
+        let plain = TransformConfig { compression: false, encryption: false, ..config };
+        assert!(plain.unsupported_by(ProtocolType::LLUDP).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transform_handshake_negotiates_and_round_trips() {
+        let dictionary = Arc::new(train_dictionary(&vec![b"ObjectUpdate position rotation".to_vec(); 64], 1024).unwrap_or_default());
+        let config = |compression, encryption, dictionary: Option<Arc<Vec<u8>>>| TransformConfig {
+            compression,
+            encryption,
+            compression_level: 3,
+            dictionary,
+            pre_shared_key: None,
+        };
+        let (a_id, b_id) = (ConnectionId::new_v4(), ConnectionId::new_v4());
+
+        // Compression and encryption agreed; the dictionary only on one side, so unused
+        let a = TransformRegistry::new(config(true, true, Some(dictionary.clone())));
+        let b = TransformRegistry::new(config(true, true, None));
+        let a_hello = a.start(a_id, true).await.unwrap();
+        let b_hello = b.start(b_id, true).await.unwrap();
+        assert_eq!(a_hello[0], PacketType::Handshake.as_u8());
+
+        // Sends before the handshake completes are held back
+        assert!(a.outbound(a_id, b"early".to_vec(), PacketPriority::High).await.unwrap().is_none());
+        match a.inbound(a_id, b_hello).await {
+            Inbound::Established { pending, received } => {
+                assert_eq!(pending, vec![(b"early".to_vec(), PacketPriority::High)]);
+                assert!(received.is_empty());
+            }
+            _ => panic!("handshake did not complete"),
+        }
+        assert!(matches!(b.inbound(b_id, a_hello).await, Inbound::Established { .. }));
+
+        let expected = Negotiated {
+            compression: true,
+            dictionary: false,
+            encryption: true,
+        };
+        assert_eq!(a.negotiated(a_id).await, Some(expected));
+        assert_eq!(b.negotiated(b_id).await, Some(expected));
+
+        let payload = b"position rotation velocity ".repeat(20);
+        let frame = a.outbound(a_id, payload.clone(), PacketPriority::Normal).await.unwrap().unwrap();
+        assert!(frame.len() < payload.len());
+        match b.inbound(b_id, frame.clone()).await {
+            Inbound::Data(data) => assert_eq!(data, payload),
+            _ => panic!("frame was not decoded"),
+        }
+
+        // A replayed frame is rejected and fails the encrypted session
+        assert!(matches!(b.inbound(b_id, frame).await, Inbound::Nothing));
+        assert_eq!(b.failed().await, vec![b_id]);
+
+        // A peer that will not encrypt fails the handshake for one that requires it
+        let a = TransformRegistry::new(config(false, true, None));
+        let b = TransformRegistry::new(config(true, false, None));
+        let a_hello = a.start(a_id, true).await.unwrap();
+        let b_hello = b.start(b_id, true).await.unwrap();
+        assert!(matches!(a.inbound(a_id, b_hello).await, Inbound::Nothing));
+        assert_eq!(a.failed().await, vec![a_id]);
+        assert!(matches!(b.inbound(b_id, a_hello).await, Inbound::Established { .. }));
+        assert_eq!(
+            b.negotiated(b_id).await,
+            Some(Negotiated {
+                compression: false,
+                dictionary: false,
+                encryption: false,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_heartbeats_are_authenticated() {
+        let config = TransformConfig {
+            compression: false,
+            encryption: true,
+            compression_level: 3,
+            dictionary: None,
+            pre_shared_key: None,
+        };
+        let (a_id, b_id) = (ConnectionId::new_v4(), ConnectionId::new_v4());
+        let a = TransformRegistry::new(config.clone());
+        let b = TransformRegistry::new(config);
+        let a_hello = a.start(a_id, true).await.unwrap();
+        let b_hello = b.start(b_id, true).await.unwrap();
+        let ping = Heartbeat { reply: false, nonce: 7 }.encode();
+
+        // Heartbeats due during the handshake are skipped rather than held with the data
+        assert!(a.outbound_control_with(a_id, ping.clone(), Ok).await.unwrap().is_none());
+        assert!(matches!(a.inbound(a_id, b_hello).await, Inbound::Established { pending, .. } if pending.is_empty()));
+        assert!(matches!(b.inbound(b_id, a_hello).await, Inbound::Established { .. }));
+
+        // Application data shaped like a heartbeat stays data
+        let frame = a.outbound(a_id, ping.clone(), PacketPriority::Normal).await.unwrap().unwrap();
+        assert!(matches!(b.inbound(b_id, frame).await, Inbound::Data(data) if data == ping));
+
+        let frame = a.outbound_control_with(a_id, ping.clone(), Ok).await.unwrap().unwrap();
+        assert_eq!(frame[0], PacketType::Heartbeat.as_u8());
+        assert!(matches!(b.inbound(b_id, frame).await, Inbound::Control(data) if data == ping));
+
+        // A plaintext heartbeat injected into the encrypted stream fails authentication
+        assert!(matches!(b.inbound(b_id, ping).await, Inbound::Nothing));
+        assert_eq!(b.failed().await, vec![b_id]);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_compression_and_encryption_end_to_end() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: true,
+            encryption_enabled: true,
+            heartbeat_interval_ms: 1000,
+        };
+        let dictionary = b"ObjectUpdate FullID LocalID ParentID Position Rotation Velocity".repeat(8);
+
+        let server = NetworkManager::new(&config).await.unwrap();
+        server.register_packet_handler(EchoHandler).await;
+        server.set_compression_dictionary(Some(dictionary.clone()));
+        server.set_pre_shared_key(Some(b"grid secret".to_vec()));
+        let addr = server
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::TCP)
+            .await
+            .unwrap();
+
+        let client = NetworkManager::new(&config).await.unwrap();
+        client.set_compression_dictionary(Some(dictionary));
+        client.set_pre_shared_key(Some(b"grid secret".to_vec()));
+        let mut incoming = client.take_incoming().await.unwrap();
+        let connection_id = client.connect(addr, ProtocolType::TCP).await.unwrap();
+        assert_eq!(
+            client.negotiated_transforms(connection_id).await,
+            Some(Negotiated {
+                compression: true,
+                dictionary: true,
+                encryption: true,
+            })
+        );
+
+        let compressible = b"Position Rotation Velocity ".repeat(400);
+        for payload in [b"ping".to_vec(), compressible.clone()] {
+            client
+                .send_packet(connection_id, payload, PacketPriority::Normal)
+                .await
+                .unwrap();
+        }
+
+        let mut echoed = Vec::new();
+        while echoed.len() < 2 {
+            server.update().await.unwrap();
+            if let Ok(Some(packet)) =
+                tokio::time::timeout(std::time::Duration::from_millis(20), incoming.recv()).await
+            {
+                echoed.push(packet.data);
+            }
+        }
+        assert_eq!(echoed, vec![b"ping".to_vec(), compressible.clone()]);
+
+        // Wire bytes, not payload bytes, are counted
+        assert!(client.traffic_stats().await.bytes_out < compressible.len() as u64 / 2);
+
+        // Mismatched pre-shared keys complete the exchange but the first frame fails authentication
+        let intruder = NetworkManager::new(&config).await.unwrap();
+        intruder.set_pre_shared_key(Some(b"wrong".to_vec()));
+        let intruder_id = intruder.connect(addr, ProtocolType::TCP).await.unwrap();
+        intruder
+            .send_packet(intruder_id, b"let me in".to_vec(), PacketPriority::Normal)
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            server.update().await.unwrap();
+            if server.traffic_stats().await.active_connections == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.traffic_stats().await.active_connections, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_heartbeats_measure_rtt_and_report_disconnects() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: true,
+            heartbeat_interval_ms: 20,
+        };
+
+        let server = NetworkManager::new(&config).await.unwrap();
+        let mut server_events = server.subscribe_events();
+        let addr = server
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::TCP)
+            .await
+            .unwrap();
+
+        let client = NetworkManager::new(&config).await.unwrap();
+        let mut client_events = client.subscribe_events();
+        let connection_id = client.connect(addr, ProtocolType::TCP).await.unwrap();
+        match client_events.recv().await.unwrap() {
+            NetworkEvent::Connected { connection_id: id, protocol, remote_addr } => {
+                assert_eq!((id, protocol, remote_addr), (connection_id, ProtocolType::TCP, addr));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        let server_connection = match server_events.recv().await.unwrap() {
+            NetworkEvent::Connected { connection_id, .. } => connection_id,
+            other => panic!("unexpected event {:?}", other),
+        };
+
+        // Pongs are answered by the peer's receive task, so only the pinging side needs updates
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            client.update().await.unwrap();
+            let stats = client.connection_stats(connection_id).unwrap();
+            if stats.heartbeats_answered >= 3 {
+                assert!(stats.rtt_ms.unwrap() < 1000.0);
+                assert!(stats.jitter_ms.is_some());
+                assert_eq!(stats.loss_rate, Some(0.0));
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "heartbeats never answered: {:?}", stats);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let server_stats = server.stats().await;
+        assert_eq!(server_stats.connections.len(), 1);
+        assert_eq!(server_stats.connections[0].connection_id, server_connection);
+        assert!(server_stats.connections[0].packets_in >= 4);
+        assert!(server_stats.connections[0].rtt_ms.is_none());
+
+        // The server closing is seen by the client as the peer going away
+        assert!(server.close_connection(server_connection).await);
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(5), client_events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match closed {
+            NetworkEvent::Disconnected { connection_id: id, reason, stats } => {
+                assert_eq!(id, connection_id);
+                assert_eq!(reason, DisconnectReason::ClosedByPeer);
+                assert!(stats.heartbeats_answered >= 3);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        match server_events.recv().await.unwrap() {
+            NetworkEvent::Disconnected { reason, .. } => assert_eq!(reason, DisconnectReason::Closed),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(client.connection_stats(connection_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_silent_udp_peer_times_out_with_reason() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 150,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 20,
+        };
+
+        let server = NetworkManager::new(&config).await.unwrap();
+        let mut events = server.subscribe_events();
+        let mut incoming = server.take_incoming().await.unwrap();
+        let addr = server
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::LLUDP)
+            .await
+            .unwrap();
+
+        // A plain socket, like a viewer, never answers heartbeats
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        peer.send_to(b"UseCircuitCode", addr).await.unwrap();
+        let connection_id = incoming.recv().await.unwrap().connection_id;
+        assert!(matches!(events.recv().await.unwrap(), NetworkEvent::Connected { .. }));
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let (reason, stats) = loop {
+            server.update().await.unwrap();
+            if let Ok(NetworkEvent::Disconnected { connection_id: id, reason, stats }) = events.try_recv() {
+                assert_eq!(id, connection_id);
+                break (reason, stats);
+            }
+            assert!(std::time::Instant::now() < deadline, "silent peer never timed out");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        assert_eq!(reason, DisconnectReason::TimedOut);
+        assert_eq!((stats.packets_in, stats.bytes_in), (1, 14));
+        assert!(stats.heartbeats_sent >= 1);
+        assert_eq!(stats.heartbeats_answered, 0);
+        assert_eq!((stats.rtt_ms, stats.loss_rate), (None, None));
+
+        // The peer's heartbeats arrived as short datagrams it could tell apart from LLUDP
+        let mut buf = [0u8; 64];
+        let (len, _) = peer.recv_from(&mut buf).await.unwrap();
+        assert_eq!(len, HEARTBEAT_SIZE);
+        assert!(server.stats().await.connections.is_empty());
+    }
+
+    #[test]
+    fn test_link_conditioner_is_seeded_and_caps_bandwidth() {
+        let start = std::time::Instant::now();
+        let conditions = LinkConditions {
+            latency: std::time::Duration::from_millis(50),
+            jitter: std::time::Duration::from_millis(30),
+            loss: 0.1,
+            duplicate: 0.05,
+            reorder: 0.1,
+            seed: 7,
+            ..LinkConditions::ideal()
+        };
+
+        let run = |conditions: LinkConditions| {
+            let mut link = LinkConditioner::new(conditions);
+            for i in 0..1000u32 {
+                link.submit(i.to_be_bytes().to_vec(), start + std::time::Duration::from_millis(i as u64));
+            }
+            let mut arrived = Vec::new();
+            for ms in 0..1200 {
+                arrived.extend(link.poll(start + std::time::Duration::from_millis(ms)));
+            }
+            (arrived, link.stats())
+        };
+
+        let (first, stats) = run(conditions.clone());
+        let (second, _) = run(conditions.clone());
+        assert_eq!(first, second);
+        let (other_seed, _) = run(LinkConditions { seed: 8, ..conditions });
+        assert_ne!(first, other_seed);
+
+        assert_eq!(stats.submitted, 1000);
+        assert!((50..150).contains(&stats.lost), "lost {}", stats.lost);
+        assert!(stats.duplicated > 0 && stats.reordered > 0);
+        assert_eq!(stats.delivered, 1000 - stats.lost + stats.duplicated);
+        assert!(first.windows(2).any(|pair| pair[0] > pair[1]), "nothing was reordered");
+
+        // 80 kbit/s carries one 1000 byte packet per 100 ms and queues the rest
+        let mut link = LinkConditioner::new(LinkConditions {
+            bandwidth_bps: Some(80_000),
+            queue_bytes: 3000,
+            ..LinkConditions::ideal()
+        });
+        for _ in 0..5 {
+            link.submit(vec![0; 1000], start);
+        }
+        assert_eq!(link.stats().overflowed, 2);
+        assert_eq!(link.poll(start + std::time::Duration::from_millis(99)).len(), 0);
+        assert_eq!(link.poll(start + std::time::Duration::from_millis(100)).len(), 1);
+        assert_eq!(link.poll(start + std::time::Duration::from_millis(300)).len(), 2);
+
+        // A downed link drops what was in flight and everything sent while down
+        link.submit(vec![0; 10], start + std::time::Duration::from_millis(300));
+        link.set_down(true);
+        link.submit(vec![0; 10], start + std::time::Duration::from_millis(300));
+        assert_eq!(link.in_flight(), 0);
+        link.set_down(false);
+        link.submit(vec![0; 10], start + std::time::Duration::from_millis(400));
+        assert_eq!(link.poll(start + std::time::Duration::from_millis(410)).len(), 1);
+    }
+
+    #[test]
+    fn test_reliable_delivery_over_conditioned_link() {
+        let config = ReliabilityConfig::default();
+        let mut sender = ReliableConnection::new(config.clone());
+        let mut receiver = ReliableConnection::new(config);
+        let conditions = LinkConditions {
+            latency: std::time::Duration::from_millis(40),
+            jitter: std::time::Duration::from_millis(40),
+            loss: 0.2,
+            duplicate: 0.05,
+            reorder: 0.1,
+            seed: 42,
+            ..LinkConditions::ideal()
+        };
+        let mut forward = LinkConditioner::new(conditions.clone());
+        let mut back = LinkConditioner::new(LinkConditions { seed: 43, ..conditions });
+
+        let start = std::time::Instant::now();
+        let mut delivered = Vec::new();
+        for ms in 0..20_000u64 {
+            let now = start + std::time::Duration::from_millis(ms);
+            if ms < 200 {
+                for datagram in sender.send(1, (ms as u32).to_be_bytes().to_vec(), now).unwrap() {
+                    forward.submit(datagram, now);
+                }
+            }
+            for datagram in sender.poll(now).unwrap() {
+                forward.submit(datagram, now);
+            }
+            for datagram in forward.poll(now) {
+                delivered.extend(receiver.receive(&datagram, now).unwrap().into_iter().map(|d| d.payload));
+            }
+            for datagram in receiver.poll(now).unwrap() {
+                back.submit(datagram, now);
+            }
+            for datagram in back.poll(now) {
+                sender.receive(&datagram, now).unwrap();
+            }
+            if delivered.len() == 200 && sender.stats().in_flight == 0 {
+                break;
+            }
+        }
+
+        let expected: Vec<Vec<u8>> = (0..200u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        assert_eq!(delivered, expected);
+        assert_eq!(sender.stats().in_flight, 0);
+        assert!(sender.stats().resends > 0);
+        assert!(receiver.stats().duplicates > 0);
+    }
+
+    #[tokio::test]
+    async fn test_udp_proxy_delays_both_directions() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            while let Ok((len, from)) = server.recv_from(&mut buf).await {
+                let _ = server.send_to(&buf[..len], from).await;
+            }
+        });
+
+        let conditions = LinkConditions {
+            latency: std::time::Duration::from_millis(30),
+            ..LinkConditions::ideal()
+        };
+        let proxy = UdpProxy::bind("127.0.0.1:0".parse().unwrap(), server_addr, conditions.clone(), conditions)
+            .await
+            .unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let proxy_task = tokio::spawn(proxy.run());
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(proxy_addr).await.unwrap();
+        let sent_at = std::time::Instant::now();
+        client.send(b"hello region").await.unwrap();
+
+        let mut buf = [0u8; 1500];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .expect("echo through proxy")
+            .unwrap();
+        assert_eq!(&buf[..len], b"hello region");
+        assert!(sent_at.elapsed() >= std::time::Duration::from_millis(60));
+        proxy_task.abort();
+    }
+
+    #[test]
+    fn test_capture_records_round_trip_through_pcapng() {
+        let start = std::time::UNIX_EPOCH + std::time::Duration::from_micros(1_700_000_000_123_456);
+        let records = vec![
+            CaptureRecord {
+                timestamp: start,
+                direction: CaptureDirection::Incoming,
+                connection_id: ConnectionId::new_v4(),
+                protocol: ProtocolType::LLUDP,
+                priority: None,
+                remote_addr: Some("10.0.0.7:9000".parse().unwrap()),
+                data: b"UseCircuitCode".to_vec(),
+            },
+            CaptureRecord {
+                timestamp: start + std::time::Duration::from_millis(15),
+                direction: CaptureDirection::Outgoing,
+                connection_id: ConnectionId::new_v4(),
+                protocol: ProtocolType::QUIC,
+                priority: Some(PacketPriority::Critical),
+                remote_addr: Some("[2001:db8::1]:443".parse().unwrap()),
+                data: vec![1, 2, 3],
+            },
+        ];
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.write_record(record).unwrap();
+        }
+        let mut bytes = writer.into_inner();
+        // pcapng section header magic, then our link type in the interface block
+        assert_eq!(&bytes[..4], &[0x0A, 0x0D, 0x0D, 0x0A]);
+        assert_eq!(u16::from_le_bytes([bytes[36], bytes[37]]), CAPTURE_LINK_TYPE);
+
+        // Packets on an interface of another link type are skipped
+        let mut foreign = Vec::new();
+        foreign.extend_from_slice(&1u32.to_le_bytes());
+        foreign.extend_from_slice(&20u32.to_le_bytes());
+        foreign.extend_from_slice(&1u16.to_le_bytes());
+        foreign.extend_from_slice(&[0; 6]);
+        foreign.extend_from_slice(&20u32.to_le_bytes());
+        foreign.extend_from_slice(&6u32.to_le_bytes());
+        foreign.extend_from_slice(&36u32.to_le_bytes());
+        foreign.extend_from_slice(&1u32.to_le_bytes());
+        foreign.extend_from_slice(&[0; 8]);
+        foreign.extend_from_slice(&4u32.to_le_bytes());
+        foreign.extend_from_slice(&4u32.to_le_bytes());
+        foreign.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        foreign.extend_from_slice(&36u32.to_le_bytes());
+        bytes.extend_from_slice(&foreign);
+
+        let read: Vec<CaptureRecord> = CaptureReader::new(bytes.as_slice()).collect::<Result<_>>().unwrap();
+        assert_eq!(read, records);
+
+        // A truncated file is an error, not a silent end
+        let truncated = &bytes[..bytes.len() - foreign.len() - 3];
+        assert!(CaptureReader::new(truncated).any(|record| record.is_err()));
+    }
+
+    #[tokio::test]
+    async fn test_capture_and_replay_udp_session() {
+        let config = NetworkConfig {
+            max_connections: 100,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+        let path = std::env::temp_dir().join(format!("storm-capture-{}.pcapng", ConnectionId::new_v4()));
+
+        let manager = NetworkManager::new(&config).await.unwrap();
+        let mut incoming = manager.take_incoming().await.unwrap();
+        let addr = manager
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::LLUDP)
+            .await
+            .unwrap();
+        manager.start_capture(&path).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"first", addr).await.unwrap();
+        let first = incoming.recv().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        client.send_to(b"second", addr).await.unwrap();
+        incoming.recv().await.unwrap();
+        manager
+            .send_packet(first.connection_id, b"reply".to_vec(), PacketPriority::High)
+            .await
+            .unwrap();
+        assert!(manager.stop_capture().unwrap());
+        assert!(!manager.stop_capture().unwrap());
+
+        let records = read_capture(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let summary: Vec<(CaptureDirection, &[u8])> = records.iter().map(|r| (r.direction, r.data.as_slice())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (CaptureDirection::Incoming, b"first".as_slice()),
+                (CaptureDirection::Incoming, b"second".as_slice()),
+                (CaptureDirection::Outgoing, b"reply".as_slice()),
+            ]
+        );
+        assert!(records.iter().all(|r| r.connection_id == first.connection_id));
+        assert_eq!(records[0].remote_addr, Some(client.local_addr().unwrap()));
+        assert_eq!(records[2].priority, Some(PacketPriority::High));
+        let gap = records[1].timestamp.duration_since(records[0].timestamp).unwrap();
+        assert!(gap >= std::time::Duration::from_millis(100));
+
+        // Replay the received side into a fresh manager at 4x speed
+        let replay_target = NetworkManager::new(&config).await.unwrap();
+        let mut replayed = replay_target.take_incoming().await.unwrap();
+        let started = std::time::Instant::now();
+        let stats = Replayer::new(records).at_speed(4.0).replay(&replay_target).await.unwrap();
+        assert_eq!(stats, ReplayStats { replayed: 2, skipped_outgoing: 1 });
+        assert!(started.elapsed() >= gap.div_f64(4.0));
+        assert_eq!(replayed.recv().await.unwrap().data, b"first");
+        let second = replayed.recv().await.unwrap();
+        assert_eq!(second.data, b"second");
+        assert_eq!(second.protocol, ProtocolType::LLUDP);
+    }
+
+    #[test]
+    fn test_scheduler_orders_by_priority_and_throttles_categories() {
+        let mut config = ThrottleConfig::with_total(10_000_000);
+        config.category_bps[ThrottleCategory::Texture as usize] = 8_000;
+        let start = std::time::Instant::now();
+        let mut scheduler = SendScheduler::new(config, start);
+
+        for _ in 0..3 {
+            scheduler.enqueue(ScheduledPacket::new(vec![0; 1000], PacketPriority::Low, ThrottleCategory::Texture));
+        }
+        scheduler.enqueue(ScheduledPacket::new(vec![1], PacketPriority::High, ThrottleCategory::Task));
+        scheduler.enqueue(ScheduledPacket::new(vec![2], PacketPriority::Critical, ThrottleCategory::Resend));
+
+        // The texture bucket starts with one datagram of burst and may overdraw once
+        let sent = scheduler.poll(start);
+        let priorities: Vec<PacketPriority> = sent.iter().map(|p| p.priority).collect();
+        assert_eq!(priorities, vec![PacketPriority::Critical, PacketPriority::High, PacketPriority::Low, PacketPriority::Low]);
+        assert_eq!(scheduler.stats().queued_packets, 1);
+
+        // 8 kbit/s refills 1000 bytes a second
+        assert!(scheduler.poll(start + std::time::Duration::from_millis(100)).is_empty());
+        assert_eq!(scheduler.poll(start + std::time::Duration::from_secs(1)).len(), 1);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_scheduler_merges_and_sheds_low_priority() {
+        let config = ThrottleConfig {
+            max_queued_bytes: 2500,
+            ..ThrottleConfig::default()
+        };
+        let mut scheduler = SendScheduler::new(config, std::time::Instant::now());
+
+        // A newer transform for the same object replaces the queued one
+        scheduler.enqueue(ScheduledPacket::new(vec![1; 100], PacketPriority::Normal, ThrottleCategory::Task).merging(42));
+        scheduler.enqueue(ScheduledPacket::new(vec![2; 100], PacketPriority::Normal, ThrottleCategory::Task).merging(42));
+        assert_eq!(scheduler.stats().merged, 1);
+        assert_eq!(scheduler.stats().queued_packets, 1);
+
+        for fill in 3..6 {
+            scheduler.enqueue(ScheduledPacket::new(vec![fill; 1000], PacketPriority::Low, ThrottleCategory::Texture));
+        }
+        let stats = scheduler.stats();
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.queued_bytes, 2100);
+
+        let drained = scheduler.drain();
+        assert_eq!(drained[0].data, vec![2; 100]);
+        assert_eq!(drained[1].data, vec![4; 1000]);
+        assert_eq!(drained.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_connection_queues_until_released() {
+        let config = NetworkConfig {
+            max_connections: 10,
+            connection_timeout_ms: 5000,
+            packet_buffer_size: 8192,
+            compression_enabled: false,
+            encryption_enabled: false,
+            heartbeat_interval_ms: 1000,
+        };
+
+        let manager = NetworkManager::new(&config).await.unwrap();
+        let mut incoming = manager.take_incoming().await.unwrap();
+        let addr = manager
+            .start_listener("127.0.0.1:0".parse().unwrap(), ProtocolType::LLUDP)
+            .await
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"hello", addr).await.unwrap();
+        let peer = incoming.recv().await.unwrap().connection_id;
+
+        // 8 kbit/s lets the first 1500 bytes of burst through and queues the rest
+        manager.set_throttle(Some(ThrottleConfig::with_total(8_000))).await.unwrap();
+        for _ in 0..5 {
+            manager.send_packet(peer, vec![9; 1000], PacketPriority::Normal).await.unwrap();
+        }
+        let stats = manager.scheduler_stats(peer).await.unwrap();
+        assert!(stats.queued_packets >= 3);
+
+        manager.set_throttle(None).await.unwrap();
+        assert!(manager.scheduler_stats(peer).await.is_none());
+
+        let mut buf = [0u8; 2048];
+        for _ in 0..5 {
+            let (len, _) = client.recv_from(&mut buf).await.unwrap();
+            assert_eq!(len, 1000);
+        }
+        manager.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_reliable_ordered_delivery_survives_reordering() {
+        let config = ReliabilityConfig::default();
+        let mut sender = ReliableConnection::new(config.clone());
+        let mut receiver = ReliableConnection::new(config);
+        let now = std::time::Instant::now();
+
+        let first = sender.send(1, b"one".to_vec(), now).unwrap().remove(0);
+        let second = sender.send(1, b"two".to_vec(), now).unwrap().remove(0);
+        let third = sender.send(1, b"three".to_vec(), now).unwrap().remove(0);
+
+        assert!(receiver.receive(&third, now).unwrap().is_empty());
+        assert!(receiver.receive(&second, now).unwrap().is_empty());
+        let delivered: Vec<Vec<u8>> = receiver
+            .receive(&first, now)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.payload)
+            .collect();
+        assert_eq!(delivered, vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]);
+
+        // Duplicates are dropped, corrupt datagrams rejected
+        assert!(receiver.receive(&second, now).unwrap().is_empty());
+        let mut corrupt = first.clone();
+        corrupt[reliable::RELIABLE_HEADER_SIZE] ^= 0xFF;
+        assert!(receiver.receive(&corrupt, now).is_err());
+        assert_eq!(receiver.stats().duplicates, 1);
+        assert_eq!(receiver.stats().corrupt, 1);
+    }
+
+    #[test]
+    fn test_reliable_ordered_backlog_overflow_resumes_after_resend() {
+        let config = ReliabilityConfig { max_ordered_backlog: 2, ..ReliabilityConfig::default() };
+        let mut sender = ReliableConnection::new(config.clone());
+        let mut receiver = ReliableConnection::new(config.clone());
+        let start = std::time::Instant::now();
+
+        // The first message is lost; the next two fill the backlog and the fourth overflows it
+        let _lost = sender.send(1, b"one".to_vec(), start).unwrap();
+        for payload in [&b"two"[..], b"three", b"four"] {
+            let datagram = sender.send(1, payload.to_vec(), start).unwrap().remove(0);
+            assert!(receiver.receive(&datagram, start).unwrap().is_empty());
+        }
+
+        // Only the buffered messages are acked
+        let ack_time = start + config.ack_delay;
+        for ack in receiver.poll(ack_time).unwrap() {
+            sender.receive(&ack, ack_time).unwrap();
+        }
+        assert_eq!(sender.stats().in_flight, 2);
+
+        let resend_time = start + config.initial_rto;
+        let mut delivered = Vec::new();
+        for datagram in sender.poll(resend_time).unwrap() {
+            delivered.extend(receiver.receive(&datagram, resend_time).unwrap().into_iter().map(|d| d.payload));
+        }
+        assert_eq!(delivered, vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec(), b"four".to_vec()]);
+    }
+
+    #[test]
+    fn test_reliable_resends_lost_packets_and_samples_rtt() {
+        let config = ReliabilityConfig::default();
+        let mut sender = ReliableConnection::new(config.clone());
+        let mut receiver = ReliableConnection::new(config.clone());
+        let start = std::time::Instant::now();
+
+        // First transmission is lost
+        let _lost = sender.send(2, b"payload".to_vec(), start).unwrap();
+        assert!(sender.poll(start).unwrap().is_empty());
+
+        let later = start + config.initial_rto;
+        let resent = sender.poll(later).unwrap();
+        assert_eq!(resent.len(), 1);
+        assert_eq!(sender.stats().resends, 1);
+
+        let delivered = receiver.receive(&resent[0], later).unwrap();
+        assert_eq!(delivered, vec![Delivered { channel: 2, payload: b"payload".to_vec() }]);
+
+        // The receiver owes an ack and flushes it once the ack delay passes
+        let ack_time = later + config.ack_delay;
+        let acks = receiver.poll(ack_time).unwrap();
+        assert_eq!(acks.len(), 1);
+
+        sender.receive(&acks[0], ack_time).unwrap();
+        let stats = sender.stats();
+        assert_eq!(stats.in_flight, 0);
+        assert!(stats.srtt_ms.unwrap() >= config.ack_delay.as_secs_f64() * 1000.0);
+        assert!(sender.poll(ack_time + config.max_rto).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fragmented_messages_over_lossy_link_with_mtu_probing() {
+        let config = ReliabilityConfig {
+            max_mtu: Some(1472),
+            ..ReliabilityConfig::default()
+        };
+        let mut sender = ReliableConnection::new(config.clone());
+        let mut receiver = ReliableConnection::new(config);
+        // The path silently drops anything over 1400 bytes, like a tunnel that does not fragment
+        let conditions = LinkConditions {
+            latency: std::time::Duration::from_millis(30),
+            jitter: std::time::Duration::from_millis(20),
+            loss: 0.1,
+            reorder: 0.1,
+            mtu: Some(1400),
+            seed: 7,
+            ..LinkConditions::ideal()
+        };
+        let mut forward = LinkConditioner::new(conditions.clone());
+        let mut back = LinkConditioner::new(LinkConditions { seed: 8, ..conditions });
+
+        // Small and large messages interleaved on the ordered channel, each large one unique
+        let messages: Vec<Vec<u8>> = (0..30u32)
+            .map(|i| match i % 3 {
+                0 => i.to_be_bytes().to_vec(),
+                _ => (0..(3000 + i * 997)).map(|b| (b * 31 + i) as u8).collect(),
+            })
+            .collect();
+
+        let start = std::time::Instant::now();
+        let mut delivered = Vec::new();
+        for ms in 0..60_000u64 {
+            let now = start + std::time::Duration::from_millis(ms);
+            if ms % 50 == 0 && (ms / 50) < messages.len() as u64 {
+                for datagram in sender.send(1, messages[(ms / 50) as usize].clone(), now).unwrap() {
+                    forward.submit(datagram, now);
+                }
+            }
+            for datagram in sender.poll(now).unwrap() {
+                forward.submit(datagram, now);
+            }
+            for datagram in forward.poll(now) {
+                delivered.extend(receiver.receive(&datagram, now).unwrap().into_iter().map(|d| d.payload));
+            }
+            for datagram in receiver.poll(now).unwrap() {
+                back.submit(datagram, now);
+            }
+            for datagram in back.poll(now) {
+                sender.receive(&datagram, now).unwrap();
+            }
+            if delivered.len() == messages.len() && sender.stats().in_flight == 0 && ms > 20_000 {
+                break;
+            }
+        }
+
+        assert_eq!(delivered, messages);
+        let stats = sender.stats();
+        assert!(stats.fragments_sent > 20);
+        assert!(stats.path_mtu > 1400 - fragment::MTU_PROBE_STEP && stats.path_mtu <= 1400, "{:?}", stats);
+        assert!(forward.stats().oversized > 0);
+        assert_eq!(receiver.stats().messages_reassembled, 20);
+        assert_eq!(receiver.stats().reassembly_dropped, 0);
+
+        // Messages over the limit are refused up front
+        assert!(sender.send(1, vec![0; 2 * 1024 * 1024], start).is_err());
+    }
+
+    #[test]
+    fn test_reassembly_enforces_timeout_and_memory_limits() {
+        let start = std::time::Instant::now();
+        let mut reassembler = Reassembler::new(std::time::Duration::from_secs(1), 2500, 2500);
+        let message: Vec<u8> = (0..2000u32).map(|b| b as u8).collect();
+        let bodies = fragment::fragment(1, &message, 508).unwrap();
+        assert_eq!(bodies.len(), 4);
+
+        // Out of order and duplicated fragments still make one message
+        assert_eq!(reassembler.insert(0, &bodies[3], start).unwrap(), None);
+        assert_eq!(reassembler.insert(0, &bodies[1], start).unwrap(), None);
+        assert_eq!(reassembler.insert(0, &bodies[1], start).unwrap(), None);
+        assert_eq!(reassembler.insert(0, &bodies[0], start).unwrap(), None);
+        assert_eq!(reassembler.insert(0, &bodies[2], start).unwrap(), Some(message.clone()));
+        assert_eq!((reassembler.pending(), reassembler.buffered_bytes()), (0, 0));
+
+        // An incomplete message is dropped once it is too old
+        reassembler.insert(0, &bodies[0], start).unwrap();
+        assert_eq!(reassembler.expire(start + std::time::Duration::from_millis(500)), 0);
+        assert_eq!(reassembler.expire(start + std::time::Duration::from_secs(1)), 1);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+
+        // Filling memory evicts the oldest partial message to make room for a newer one
+        let other = fragment::fragment(2, &message, 508).unwrap();
+        for body in &bodies[..3] {
+            reassembler.insert(0, body, start).unwrap();
+        }
+        let later = start + std::time::Duration::from_millis(10);
+        for body in &other[..3] {
+            reassembler.insert(0, body, later).unwrap();
+        }
+        assert_eq!(reassembler.pending(), 1);
+        assert!(reassembler.buffered_bytes() <= 2500);
+        assert_eq!(reassembler.insert(0, &other[3], later).unwrap(), Some(message.clone()));
+
+        // Messages larger than the size limit and malformed fragments are rejected
+        let huge = fragment::fragment(3, &vec![0; 2600], 508).unwrap();
+        let results: Vec<bool> = huge.iter().map(|body| reassembler.insert(1, body, start).is_ok()).collect();
+        assert!(results.contains(&false));
+        assert!(reassembler.insert(1, &[0; 4], start).is_err());
+        assert_eq!(reassembler.dropped(), 3);
+    }
+
+    #[test]
+    fn test_reliable_gives_up_after_max_sends() {
+        let config = ReliabilityConfig {
+            max_sends: 2,
+            ..ReliabilityConfig::default()
+        };
+        let mut sender = ReliableConnection::new(config.clone());
+        let start = std::time::Instant::now();
+
+        sender.send(1, b"never acked".to_vec(), start).unwrap();
+        assert_eq!(sender.poll(start + config.max_rto).unwrap().len(), 1);
+        let error = sender.poll(start + config.max_rto * 3).unwrap_err();
+        assert_eq!(error.downcast_ref::<NetworkError>().unwrap().code(), 1003);
+    }
+}
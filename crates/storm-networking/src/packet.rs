@@ -20,11 +20,43 @@ pub struct PacketHeader {
 }
 
 /// Packet types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PacketType {
     Handshake,
     Data,
     Ack,
     Heartbeat,
     Disconnect,
+    /// Padded packet testing whether the path carries datagrams of its size
+    MtuProbe,
+    /// Part of a message too large for one datagram
+    Fragment,
+}
+
+impl PacketType {
+    /// Wire representation
+    pub fn as_u8(self) -> u8 {
+        match self {
+            PacketType::Handshake => 0,
+            PacketType::Data => 1,
+            PacketType::Ack => 2,
+            PacketType::Heartbeat => 3,
+            PacketType::Disconnect => 4,
+            PacketType::MtuProbe => 5,
+            PacketType::Fragment => 6,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(PacketType::Handshake),
+            1 => Some(PacketType::Data),
+            2 => Some(PacketType::Ack),
+            3 => Some(PacketType::Heartbeat),
+            4 => Some(PacketType::Disconnect),
+            5 => Some(PacketType::MtuProbe),
+            6 => Some(PacketType::Fragment),
+            _ => None,
+        }
+    }
 }
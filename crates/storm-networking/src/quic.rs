@@ -0,0 +1,276 @@
+// File: crates/storm-networking/src/quic.rs
+// QUIC transport: endpoint configuration, development certificates and priority streams
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+use anyhow::Result;
+
+use crate::{ConnectionId, DisconnectReason, IncomingPacket, NetworkError, PacketPriority, PacketSink, ProtocolType};
+
+/// Largest message accepted on a QUIC stream
+pub const MAX_STREAM_MESSAGE: usize = 16 * 1024 * 1024;
+
+/// Certificate and private key presented by QUIC listeners
+#[derive(Clone)]
+pub struct QuicIdentity {
+    pub certificate_der: Vec<u8>,
+    private_key_der: Vec<u8>,
+}
+
+impl QuicIdentity {
+    pub fn from_der(certificate_der: Vec<u8>, private_key_der: Vec<u8>) -> Self {
+        Self {
+            certificate_der,
+            private_key_der,
+        }
+    }
+
+    /// Generate a self-signed certificate for development servers
+    ///
+    /// Names that parse as IP addresses become IP subject alternative names.
+    pub fn self_signed(names: &[&str]) -> Result<Self> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let certificate = rcgen::generate_simple_self_signed(names)?;
+        Ok(Self {
+            certificate_der: certificate.serialize_der()?,
+            private_key_der: certificate.serialize_private_key_der(),
+        })
+    }
+
+    /// Identity for local testing, valid for localhost and the loopback addresses
+    pub fn development() -> Result<Self> {
+        Self::self_signed(&["localhost", "127.0.0.1", "::1"])
+    }
+}
+
+impl std::fmt::Debug for QuicIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuicIdentity")
+            .field("certificate_der", &format!("{} bytes", self.certificate_der.len()))
+            .finish()
+    }
+}
+
+fn transport_config(idle_timeout: Duration) -> Result<Arc<quinn::TransportConfig>> {
+    let mut transport = quinn::TransportConfig::default();
+    transport.max_idle_timeout(Some(quinn::IdleTimeout::try_from(idle_timeout)?));
+    transport.keep_alive_interval(Some(idle_timeout / 3));
+    Ok(Arc::new(transport))
+}
+
+pub(crate) fn server_config(identity: &QuicIdentity, idle_timeout: Duration) -> Result<quinn::ServerConfig> {
+    let mut config = quinn::ServerConfig::with_single_cert(
+        vec![rustls::Certificate(identity.certificate_der.clone())],
+        rustls::PrivateKey(identity.private_key_der.clone()),
+    )?;
+    config.transport_config(transport_config(idle_timeout)?);
+    Ok(config)
+}
+
+/// Client configuration trusting only `pinned` certificates, or the platform roots when none are given
+pub(crate) fn client_config(pinned: &[Vec<u8>], idle_timeout: Duration) -> Result<quinn::ClientConfig> {
+    let mut config = if pinned.is_empty() {
+        quinn::ClientConfig::with_native_roots()
+    } else {
+        let mut roots = rustls::RootCertStore::empty();
+        for der in pinned {
+            roots.add(&rustls::Certificate(der.clone()))?;
+        }
+        quinn::ClientConfig::with_root_certificates(roots)
+    };
+    config.transport_config(transport_config(idle_timeout)?);
+    Ok(config)
+}
+
+/// Stream priority passed to quinn; higher values are sent first
+fn stream_priority(priority: PacketPriority) -> i32 {
+    match priority {
+        PacketPriority::Low => 0,
+        PacketPriority::Normal => 1,
+        PacketPriority::High => 2,
+        PacketPriority::Critical => 3,
+    }
+}
+
+fn stream_index(priority: PacketPriority) -> usize {
+    stream_priority(priority) as usize
+}
+
+/// QUIC connection wrapper
+///
+/// Each `PacketPriority` gets its own long-lived unidirectional stream carrying
+/// length-prefixed messages, so a backlog of bulk data never delays critical traffic.
+pub struct QuicConnection {
+    pub(crate) id: ConnectionId,
+    pub(crate) connection: quinn::Connection,
+    pub(crate) remote_addr: SocketAddr,
+    streams: [Mutex<Option<quinn::SendStream>>; 4],
+    /// Client connections own their endpoint; listener connections share the listener's
+    _endpoint: Option<quinn::Endpoint>,
+}
+
+impl QuicConnection {
+    pub(crate) fn new(id: ConnectionId, connection: quinn::Connection, endpoint: Option<quinn::Endpoint>) -> Self {
+        Self {
+            id,
+            remote_addr: connection.remote_address(),
+            connection,
+            streams: Default::default(),
+            _endpoint: endpoint,
+        }
+    }
+
+    /// Send a reliable message on the stream for its priority
+    pub(crate) async fn send(&self, data: &[u8], priority: PacketPriority) -> Result<()> {
+        if data.len() > MAX_STREAM_MESSAGE {
+            return Err(anyhow::anyhow!("QUIC message of {} bytes exceeds {} byte limit", data.len(), MAX_STREAM_MESSAGE));
+        }
+
+        let mut slot = self.streams[stream_index(priority)].lock().await;
+        if slot.is_none() {
+            let stream = self
+                .connection
+                .open_uni()
+                .await
+                .map_err(|e| self.connection_error(e))?;
+            let _ = stream.set_priority(stream_priority(priority));
+            *slot = Some(stream);
+        }
+
+        let stream = slot.as_mut().expect("stream opened above");
+        let written = match stream.write_all(&(data.len() as u32).to_be_bytes()).await {
+            Ok(()) => stream.write_all(data).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            // Reopen on the next send; the peer may only have stopped this stream
+            *slot = None;
+            return Err(match e {
+                quinn::WriteError::ConnectionLost(e) => self.connection_error(e).into(),
+                other => NetworkError::Other(anyhow::anyhow!("QUIC stream write failed: {}", other)).into(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Send an unreliable datagram
+    pub(crate) fn send_datagram(&self, data: Vec<u8>) -> Result<()> {
+        self.connection.send_datagram(data.into()).map_err(|e| match e {
+            quinn::SendDatagramError::ConnectionLost(e) => self.connection_error(e).into(),
+            quinn::SendDatagramError::TooLarge => anyhow::anyhow!(
+                "Datagram exceeds the {} byte limit of connection {}",
+                self.max_datagram_size().unwrap_or(0),
+                self.id
+            ),
+            other => NetworkError::Other(anyhow::anyhow!("QUIC datagram failed: {}", other)).into(),
+        })
+    }
+
+    /// Largest datagram the peer currently accepts, if it accepts any
+    pub(crate) fn max_datagram_size(&self) -> Option<usize> {
+        self.connection.max_datagram_size()
+    }
+
+    /// Why the connection closed, once it has
+    pub(crate) fn close_reason(&self) -> Option<DisconnectReason> {
+        self.connection.close_reason().map(|error| match error {
+            // quinn closes the connection itself once its idle timeout passes
+            quinn::ConnectionError::TimedOut => DisconnectReason::TimedOut,
+            quinn::ConnectionError::LocallyClosed => DisconnectReason::Closed,
+            quinn::ConnectionError::ApplicationClosed(_) | quinn::ConnectionError::ConnectionClosed(_) => {
+                DisconnectReason::ClosedByPeer
+            }
+            other => DisconnectReason::Error(other.to_string()),
+        })
+    }
+
+    pub(crate) fn close(&self) {
+        self.connection.close(0u32.into(), b"closed");
+    }
+
+    fn connection_error(&self, error: quinn::ConnectionError) -> NetworkError {
+        match error {
+            quinn::ConnectionError::TimedOut => NetworkError::Timeout {
+                connection_id: Some(self.id),
+            },
+            _ => NetworkError::ConnectionClosed { connection_id: self.id },
+        }
+    }
+}
+
+/// Deliver a connection's stream messages and datagrams to the packet sink
+///
+/// The task ends when the connection closes.
+pub(crate) fn spawn_readers(connection: quinn::Connection, connection_id: ConnectionId, sink: PacketSink) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                stream = connection.accept_uni() => match stream {
+                    Ok(stream) => {
+                        tokio::spawn(read_stream(stream, connection_id, sink.clone()));
+                    }
+                    Err(e) => {
+                        debug!("QUIC connection {} closed: {}", connection_id, e);
+                        break;
+                    }
+                },
+                datagram = connection.read_datagram() => match datagram {
+                    Ok(datagram) => {
+                        if !sink.deliver(packet(connection_id, datagram.to_vec())).await {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        debug!("QUIC connection {} closed: {}", connection_id, e);
+                        break;
+                    }
+                },
+            }
+        }
+    })
+}
+
+async fn read_stream(mut stream: quinn::RecvStream, connection_id: ConnectionId, sink: PacketSink) {
+    loop {
+        let mut length = [0u8; 4];
+        match stream.read_exact(&mut length).await {
+            Ok(()) => {}
+            // Peer finished the stream between messages
+            Err(quinn::ReadExactError::FinishedEarly) => break,
+            Err(e) => {
+                debug!("QUIC stream on {} ended: {}", connection_id, e);
+                break;
+            }
+        }
+
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_STREAM_MESSAGE {
+            warn!("QUIC peer {} sent a {} byte message; dropping stream", connection_id, length);
+            let _ = stream.stop(1u32.into());
+            break;
+        }
+
+        let mut data = vec![0u8; length];
+        if let Err(e) = stream.read_exact(&mut data).await {
+            debug!("QUIC stream on {} ended mid-message: {}", connection_id, e);
+            break;
+        }
+
+        if !sink.deliver(packet(connection_id, data)).await {
+            break;
+        }
+    }
+}
+
+fn packet(connection_id: ConnectionId, data: Vec<u8>) -> IncomingPacket {
+    IncomingPacket {
+        connection_id,
+        protocol: ProtocolType::QUIC,
+        data,
+        timestamp: std::time::Instant::now(),
+    }
+}
@@ -0,0 +1,771 @@
+// File: crates/storm-networking/src/reliable.rs
+// Reliable-UDP channels: sequencing, acks, RTT estimation, resends, duplicate suppression and
+// fragmentation of messages larger than the path MTU
+// The building blocks are transport-agnostic so LLUDP circuits can reuse them
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+use anyhow::Result;
+
+use crate::fragment::{self, MtuProber, Reassembler};
+use crate::{
+    ConnectionId, IncomingPacket, NetworkError, NetworkManager, PacketPriority, PacketType, ScheduledPacket, ThrottleCategory,
+};
+
+/// Size of the header `ReliableConnection` puts in front of every datagram
+pub const RELIABLE_HEADER_SIZE: usize = 22;
+
+/// Number of earlier sequences acknowledged alongside the latest one
+pub const ACK_BITS: u32 = 32;
+
+/// Delivery guarantees for a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChannelKind {
+    /// Fire and forget; duplicates and stale packets are still dropped
+    Unreliable,
+    /// Resent until acknowledged and delivered in send order
+    ReliableOrdered,
+    /// Resent until acknowledged and delivered as soon as it arrives
+    ReliableUnordered,
+}
+
+/// Tuning for resends and acknowledgements
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReliabilityConfig {
+    /// Channel kinds, indexed by channel id
+    pub channels: Vec<ChannelKind>,
+    pub initial_rto: Duration,
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+    /// Give up on the connection after a message has been sent this many times
+    pub max_sends: u32,
+    /// How long a received packet may wait for a piggybacked ack before a bare ack is sent
+    pub ack_delay: Duration,
+    /// Out-of-order messages buffered per ordered channel before new ones are dropped
+    pub max_ordered_backlog: usize,
+    /// Largest datagram assumed to reach the peer; bigger messages are sent in fragments
+    pub mtu: usize,
+    /// Probe for a path MTU up to this size; probes are padded packets, so this is opt-in
+    pub max_mtu: Option<usize>,
+    /// Larger messages are refused on send and dropped on reassembly
+    pub max_message_size: usize,
+    /// How long fragments of an incomplete message are kept
+    pub reassembly_timeout: Duration,
+    /// Memory for incomplete messages; the oldest is dropped to make room
+    pub max_reassembly_bytes: usize,
+}
+
+impl Default for ReliabilityConfig {
+    fn default() -> Self {
+        Self {
+            channels: vec![ChannelKind::Unreliable, ChannelKind::ReliableOrdered, ChannelKind::ReliableUnordered],
+            initial_rto: Duration::from_millis(500),
+            min_rto: Duration::from_millis(100),
+            max_rto: Duration::from_secs(5),
+            max_sends: 10,
+            ack_delay: Duration::from_millis(20),
+            max_ordered_backlog: 1024,
+            mtu: 1200,
+            max_mtu: None,
+            max_message_size: 1024 * 1024,
+            reassembly_timeout: Duration::from_secs(30),
+            max_reassembly_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// Smoothed round-trip time and retransmission timeout, per RFC 6298
+#[derive(Debug, Clone)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+    min_rto: Duration,
+    max_rto: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(initial_rto: Duration, min_rto: Duration, max_rto: Duration) -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: initial_rto,
+            min_rto,
+            max_rto,
+        }
+    }
+
+    /// Feed a round-trip sample; only use packets that were sent once (Karn's rule)
+    pub fn sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let deviation = srtt.abs_diff(rtt);
+                self.rttvar = (self.rttvar * 3 + deviation) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + (self.rttvar * 4).max(Duration::from_millis(1))).clamp(self.min_rto, self.max_rto);
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// Timeout for the `send_count`th transmission, doubling per resend
+    pub fn backoff(&self, send_count: u32) -> Duration {
+        let factor = 1u32 << send_count.saturating_sub(1).min(16);
+        (self.rto * factor).min(self.max_rto)
+    }
+}
+
+/// Sliding window over received sequence numbers
+///
+/// Tracks the highest sequence seen plus a bitfield of the 32 before it, which is both the
+/// duplicate filter and the ack information sent back to the peer. Sequences start at 1.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceWindow {
+    latest: u32,
+    bits: u32,
+}
+
+impl SequenceWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a sequence; false if it was already seen or is too old to tell
+    pub fn insert(&mut self, sequence: u32) -> bool {
+        if sequence == 0 {
+            return false;
+        }
+        if sequence > self.latest {
+            let shift = sequence - self.latest;
+            self.bits = if shift > ACK_BITS {
+                0
+            } else {
+                // The previous latest becomes bit shift - 1
+                let shifted = if shift == ACK_BITS { 0 } else { self.bits << shift };
+                if self.latest == 0 {
+                    shifted
+                } else {
+                    shifted | (1 << (shift - 1))
+                }
+            };
+            self.latest = sequence;
+            return true;
+        }
+
+        let offset = self.latest - sequence;
+        if offset == 0 || offset > ACK_BITS {
+            return false;
+        }
+        let mask = 1 << (offset - 1);
+        if self.bits & mask != 0 {
+            return false;
+        }
+        self.bits |= mask;
+        true
+    }
+
+    pub fn contains(&self, sequence: u32) -> bool {
+        if sequence == 0 || sequence > self.latest {
+            return false;
+        }
+        let offset = self.latest - sequence;
+        offset == 0 || (offset <= ACK_BITS && self.bits & (1 << (offset - 1)) != 0)
+    }
+
+    /// Latest sequence and the bitfield of the ones before it
+    pub fn ack(&self) -> (u32, u32) {
+        (self.latest, self.bits)
+    }
+
+    /// Expand an (ack, bits) pair into the sequences it acknowledges
+    pub fn acked_sequences(ack: u32, bits: u32) -> impl Iterator<Item = u32> {
+        let latest = (ack != 0).then_some(ack);
+        let earlier = (0..ACK_BITS)
+            .filter(move |i| bits & (1 << i) != 0)
+            .filter_map(move |i| ack.checked_sub(i + 1))
+            .filter(|sequence| *sequence != 0);
+        latest.into_iter().chain(earlier)
+    }
+}
+
+/// Decoded reliable-UDP header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReliableHeader {
+    pub packet_type: PacketType,
+    pub channel: u8,
+    pub sequence: u32,
+    pub ack: u32,
+    pub ack_bits: u32,
+    /// Per-channel message id, zero for unreliable messages and bare acks
+    pub message_id: u32,
+}
+
+impl ReliableHeader {
+    pub fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RELIABLE_HEADER_SIZE + payload.len());
+        data.push(self.packet_type.as_u8());
+        data.push(self.channel);
+        data.extend_from_slice(&self.sequence.to_be_bytes());
+        data.extend_from_slice(&self.ack.to_be_bytes());
+        data.extend_from_slice(&self.ack_bits.to_be_bytes());
+        data.extend_from_slice(&self.message_id.to_be_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(payload);
+
+        let checksum = crc32(&data);
+        data[18..22].copy_from_slice(&checksum.to_be_bytes());
+        data
+    }
+
+    /// Split a datagram into header and payload, verifying the checksum
+    pub fn decode(data: &[u8]) -> Result<(Self, &[u8])> {
+        if data.len() < RELIABLE_HEADER_SIZE {
+            return Err(anyhow::anyhow!("Reliable datagram too short: {} bytes", data.len()));
+        }
+
+        let word = |at: usize| u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let mut unchecked = data.to_vec();
+        unchecked[18..22].fill(0);
+        if crc32(&unchecked) != word(18) {
+            return Err(anyhow::anyhow!("Reliable datagram checksum mismatch"));
+        }
+
+        let packet_type = PacketType::from_u8(data[0])
+            .ok_or_else(|| anyhow::anyhow!("Unknown reliable packet type {}", data[0]))?;
+        let header = Self {
+            packet_type,
+            channel: data[1],
+            sequence: word(2),
+            ack: word(6),
+            ack_bits: word(10),
+            message_id: word(14),
+        };
+        Ok((header, &data[RELIABLE_HEADER_SIZE..]))
+    }
+}
+
+/// CRC-32 (IEEE) over a byte slice
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// A message handed to the application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivered {
+    pub channel: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Counters for one reliable connection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReliabilityStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub resends: u64,
+    pub duplicates: u64,
+    pub corrupt: u64,
+    pub acks_sent: u64,
+    /// Reliable messages waiting for an ack
+    pub in_flight: usize,
+    pub srtt_ms: Option<f64>,
+    pub rto_ms: f64,
+    /// Datagram size messages are currently split to
+    pub path_mtu: usize,
+    pub fragments_sent: u64,
+    pub messages_reassembled: u64,
+    /// Incomplete messages dropped on timeout or memory limits
+    pub reassembly_dropped: u64,
+}
+
+struct PendingMessage {
+    packet_type: PacketType,
+    payload: Vec<u8>,
+    last_sent: Instant,
+    send_count: u32,
+}
+
+struct SentPacket {
+    sent_at: Instant,
+    /// Reliable message carried by the packet, if any
+    message: Option<(u8, u32)>,
+    /// Size of the datagram if it was an MTU probe
+    probe: Option<usize>,
+}
+
+/// Receive-side state for one channel
+enum ChannelReceiver {
+    Unreliable,
+    Ordered {
+        next: u32,
+        backlog: BTreeMap<u32, (PacketType, Vec<u8>)>,
+    },
+    Unordered {
+        /// Every id up to and including this one has been delivered
+        floor: u32,
+        above: BTreeSet<u32>,
+    },
+}
+
+/// Reliability state for one peer, independent of any socket
+///
+/// `send` and `poll` return datagrams to transmit; `receive` consumes datagrams from the peer
+/// and returns the messages ready for the application.
+pub struct ReliableConnection {
+    config: ReliabilityConfig,
+    next_sequence: u32,
+    next_message_ids: Vec<u32>,
+    received: SequenceWindow,
+    receivers: Vec<ChannelReceiver>,
+    sent_packets: HashMap<u32, SentPacket>,
+    pending: BTreeMap<(u8, u32), PendingMessage>,
+    rtt: RttEstimator,
+    ack_owed_since: Option<Instant>,
+    prober: Option<MtuProber>,
+    reassembler: Reassembler,
+    next_fragment_group: u32,
+    stats: ReliabilityStats,
+}
+
+impl ReliableConnection {
+    pub fn new(config: ReliabilityConfig) -> Self {
+        let receivers = config
+            .channels
+            .iter()
+            .map(|kind| match kind {
+                ChannelKind::Unreliable => ChannelReceiver::Unreliable,
+                ChannelKind::ReliableOrdered => ChannelReceiver::Ordered {
+                    next: 1,
+                    backlog: BTreeMap::new(),
+                },
+                ChannelKind::ReliableUnordered => ChannelReceiver::Unordered {
+                    floor: 0,
+                    above: BTreeSet::new(),
+                },
+            })
+            .collect();
+
+        Self {
+            rtt: RttEstimator::new(config.initial_rto, config.min_rto, config.max_rto),
+            next_message_ids: vec![1; config.channels.len()],
+            receivers,
+            next_sequence: 1,
+            received: SequenceWindow::new(),
+            sent_packets: HashMap::new(),
+            pending: BTreeMap::new(),
+            ack_owed_since: None,
+            prober: config.max_mtu.map(|max_mtu| MtuProber::new(config.mtu, max_mtu)),
+            reassembler: Reassembler::new(
+                config.reassembly_timeout,
+                config.max_reassembly_bytes,
+                config.max_message_size,
+            ),
+            next_fragment_group: 1,
+            stats: ReliabilityStats::default(),
+            config,
+        }
+    }
+
+    pub fn channel_kind(&self, channel: u8) -> Option<ChannelKind> {
+        self.config.channels.get(channel as usize).copied()
+    }
+
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    pub fn stats(&self) -> ReliabilityStats {
+        ReliabilityStats {
+            in_flight: self.pending.len(),
+            srtt_ms: self.rtt.srtt().map(|d| d.as_secs_f64() * 1000.0),
+            rto_ms: self.rtt.rto().as_secs_f64() * 1000.0,
+            path_mtu: self.path_mtu(),
+            reassembly_dropped: self.reassembler.dropped(),
+            ..self.stats.clone()
+        }
+    }
+
+    /// Largest datagram currently sent, probed or configured
+    pub fn path_mtu(&self) -> usize {
+        self.prober.as_ref().map(MtuProber::mtu).unwrap_or(self.config.mtu)
+    }
+
+    /// Queue a message and return the datagrams carrying it
+    ///
+    /// Messages that do not fit in the path MTU are split into fragments, each sequenced,
+    /// acknowledged and resent on its own. Fragments of an unreliable message are not resent,
+    /// so losing any of them loses the message.
+    pub fn send(&mut self, channel: u8, payload: Vec<u8>, now: Instant) -> Result<Vec<Vec<u8>>> {
+        let kind = self
+            .channel_kind(channel)
+            .ok_or_else(|| anyhow::anyhow!("Channel {} is not configured", channel))?;
+
+        let max_body = self.path_mtu().saturating_sub(RELIABLE_HEADER_SIZE);
+        if payload.len() <= max_body {
+            return Ok(vec![self.queue(kind, PacketType::Data, channel, payload, now)]);
+        }
+        if payload.len() > self.config.max_message_size {
+            return Err(anyhow::anyhow!(
+                "Message of {} bytes exceeds the {} byte limit",
+                payload.len(),
+                self.config.max_message_size
+            ));
+        }
+
+        let group = self.next_fragment_group;
+        self.next_fragment_group = self.next_fragment_group.wrapping_add(1).max(1);
+        let bodies = fragment::fragment(group, &payload, max_body)?;
+        self.stats.fragments_sent += bodies.len() as u64;
+        Ok(bodies
+            .into_iter()
+            .map(|body| self.queue(kind, PacketType::Fragment, channel, body, now))
+            .collect())
+    }
+
+    fn queue(&mut self, kind: ChannelKind, packet_type: PacketType, channel: u8, payload: Vec<u8>, now: Instant) -> Vec<u8> {
+        if kind == ChannelKind::Unreliable {
+            return self.write_packet(packet_type, channel, 0, &payload, now);
+        }
+
+        let message_id = self.next_message_ids[channel as usize];
+        self.next_message_ids[channel as usize] += 1;
+        let datagram = self.write_packet(packet_type, channel, message_id, &payload, now);
+        self.pending.insert((channel, message_id), PendingMessage {
+            packet_type,
+            payload,
+            last_sent: now,
+            send_count: 1,
+        });
+        datagram
+    }
+
+    /// Process a datagram from the peer
+    pub fn receive(&mut self, datagram: &[u8], now: Instant) -> Result<Vec<Delivered>> {
+        let (header, payload) = match ReliableHeader::decode(datagram) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                self.stats.corrupt += 1;
+                return Err(e);
+            }
+        };
+
+        self.process_acks(header.ack, header.ack_bits, now);
+
+        // A message the ordered backlog has no room for is neither recorded nor acked, so the
+        // sender resends it once the backlog drains
+        if self.ordered_backlog_full(&header) {
+            warn!("Ordered channel {} backlog full, dropping message {}", header.channel, header.message_id);
+            return Ok(Vec::new());
+        }
+
+        if !self.received.insert(header.sequence) {
+            self.stats.duplicates += 1;
+            // The peer resent because our ack was lost, so ack again
+            if header.packet_type == PacketType::Data {
+                self.ack_owed_since.get_or_insert(now);
+            }
+            return Ok(Vec::new());
+        }
+        self.stats.packets_received += 1;
+
+        match header.packet_type {
+            PacketType::Data | PacketType::Fragment => {}
+            // Answer probes promptly; the sender is timing them
+            PacketType::MtuProbe => {
+                self.ack_owed_since.get_or_insert(now);
+                return Ok(Vec::new());
+            }
+            _ => return Ok(Vec::new()),
+        }
+        self.ack_owed_since.get_or_insert(now);
+
+        let channel = header.channel;
+        let Some(receiver) = self.receivers.get_mut(channel as usize) else {
+            return Err(anyhow::anyhow!("Peer sent on unconfigured channel {}", channel));
+        };
+
+        let packet_type = header.packet_type;
+        let mut ready = Vec::new();
+        match receiver {
+            ChannelReceiver::Unreliable => ready.push((packet_type, payload.to_vec())),
+            ChannelReceiver::Ordered { next, backlog } => {
+                let id = header.message_id;
+                if id < *next || backlog.contains_key(&id) {
+                    self.stats.duplicates += 1;
+                } else if id == *next {
+                    ready.push((packet_type, payload.to_vec()));
+                    *next += 1;
+                    while let Some(buffered) = backlog.remove(next) {
+                        ready.push(buffered);
+                        *next += 1;
+                    }
+                } else {
+                    backlog.insert(id, (packet_type, payload.to_vec()));
+                }
+            }
+            ChannelReceiver::Unordered { floor, above } => {
+                let id = header.message_id;
+                if id <= *floor || !above.insert(id) {
+                    self.stats.duplicates += 1;
+                } else {
+                    ready.push((packet_type, payload.to_vec()));
+                    while above.remove(&(*floor + 1)) {
+                        *floor += 1;
+                    }
+                }
+            }
+        }
+
+        // Fragments of an ordered message come out in order, so it completes in its place
+        let mut delivered = Vec::new();
+        for (packet_type, payload) in ready {
+            if packet_type == PacketType::Data {
+                delivered.push(Delivered { channel, payload });
+                continue;
+            }
+            match self.reassembler.insert(channel, &payload, now) {
+                Ok(Some(payload)) => {
+                    self.stats.messages_reassembled += 1;
+                    delivered.push(Delivered { channel, payload });
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Dropping fragmented message on channel {}: {}", channel, e),
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Whether a message would have to be buffered on an ordered channel whose backlog is full
+    fn ordered_backlog_full(&self, header: &ReliableHeader) -> bool {
+        if !matches!(header.packet_type, PacketType::Data | PacketType::Fragment) {
+            return false;
+        }
+        match self.receivers.get(header.channel as usize) {
+            Some(ChannelReceiver::Ordered { next, backlog }) => {
+                header.message_id > *next
+                    && !backlog.contains_key(&header.message_id)
+                    && backlog.len() >= self.config.max_ordered_backlog
+            }
+            _ => false,
+        }
+    }
+
+    /// Resend overdue reliable messages and flush owed acks
+    ///
+    /// Fails with `NetworkError::Timeout` once a message has been sent `max_sends` times.
+    pub fn poll(&mut self, now: Instant) -> Result<Vec<Vec<u8>>> {
+        let mut datagrams = Vec::new();
+
+        let overdue: Vec<(u8, u32)> = self
+            .pending
+            .iter()
+            .filter(|(_, message)| now.duration_since(message.last_sent) >= self.rtt.backoff(message.send_count))
+            .map(|(key, _)| *key)
+            .collect();
+
+        for (channel, message_id) in overdue {
+            let Some(message) = self.pending.get(&(channel, message_id)) else {
+                continue;
+            };
+            if message.send_count >= self.config.max_sends {
+                return Err(NetworkError::Timeout { connection_id: None }.into());
+            }
+
+            let (packet_type, payload) = (message.packet_type, message.payload.clone());
+            debug!("Resending message {} on channel {}", message_id, channel);
+            let datagram = self.write_packet(packet_type, channel, message_id, &payload, now);
+            if let Some(message) = self.pending.get_mut(&(channel, message_id)) {
+                message.last_sent = now;
+                message.send_count += 1;
+            }
+            self.stats.resends += 1;
+            datagrams.push(datagram);
+        }
+
+        // Probe once the peer has answered something, so probes do not go to a dead address
+        if self.rtt.srtt().is_some() {
+            let timeout = self.rtt.rto();
+            if let Some(size) = self.prober.as_mut().and_then(|prober| prober.next_probe(now, timeout)) {
+                let padding = vec![0; size.saturating_sub(RELIABLE_HEADER_SIZE)];
+                let datagram = self.write_packet(PacketType::MtuProbe, 0, 0, &padding, now);
+                if let Some(packet) = self.sent_packets.get_mut(&(self.next_sequence - 1)) {
+                    packet.probe = Some(size);
+                }
+                datagrams.push(datagram);
+            }
+        }
+
+        let expired = self.reassembler.expire(now);
+        if expired > 0 {
+            warn!("{} fragmented messages timed out before all fragments arrived", expired);
+        }
+
+        if let Some(since) = self.ack_owed_since {
+            if now.duration_since(since) >= self.config.ack_delay {
+                datagrams.push(self.write_packet(PacketType::Ack, 0, 0, &[], now));
+                self.stats.acks_sent += 1;
+            }
+        }
+
+        Ok(datagrams)
+    }
+
+    fn write_packet(&mut self, packet_type: PacketType, channel: u8, message_id: u32, payload: &[u8], now: Instant) -> Vec<u8> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let (ack, ack_bits) = self.received.ack();
+        // Every packet carries our acks
+        self.ack_owed_since = None;
+
+        let message = (message_id != 0).then_some((channel, message_id));
+        self.sent_packets.insert(sequence, SentPacket {
+            sent_at: now,
+            message,
+            probe: None,
+        });
+        // Packets older than the ack window can never be acknowledged; their messages resend on timeout
+        if sequence > 4 * ACK_BITS {
+            let horizon = sequence - 4 * ACK_BITS;
+            self.sent_packets.retain(|seq, _| *seq >= horizon);
+        }
+        self.stats.packets_sent += 1;
+
+        ReliableHeader {
+            packet_type,
+            channel,
+            sequence,
+            ack,
+            ack_bits,
+            message_id,
+        }
+        .encode(payload)
+    }
+
+    fn process_acks(&mut self, ack: u32, ack_bits: u32, now: Instant) {
+        for sequence in SequenceWindow::acked_sequences(ack, ack_bits) {
+            let Some(packet) = self.sent_packets.remove(&sequence) else {
+                continue;
+            };
+            // Each transmission has its own sequence, so every sample is unambiguous
+            self.rtt.sample(now.duration_since(packet.sent_at));
+            if let Some(key) = packet.message {
+                self.pending.remove(&key);
+            }
+            if let (Some(size), Some(prober)) = (packet.probe, self.prober.as_mut()) {
+                prober.acked(size);
+            }
+        }
+    }
+}
+
+/// Reliable channels for every connection of a `NetworkManager`
+pub struct ReliableEndpoint {
+    network: Arc<NetworkManager>,
+    config: ReliabilityConfig,
+    connections: Mutex<HashMap<ConnectionId, ReliableConnection>>,
+}
+
+impl ReliableEndpoint {
+    pub fn new(network: Arc<NetworkManager>, config: ReliabilityConfig) -> Self {
+        Self {
+            network,
+            config,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send a message on a channel of a connection
+    pub async fn send(&self, connection_id: ConnectionId, channel: u8, payload: Vec<u8>) -> Result<()> {
+        let datagrams = {
+            let mut connections = self.connections.lock().await;
+            connections
+                .entry(connection_id)
+                .or_insert_with(|| ReliableConnection::new(self.config.clone()))
+                .send(channel, payload, Instant::now())?
+        };
+        for datagram in datagrams {
+            self.network.send_packet(connection_id, datagram, PacketPriority::Normal).await?;
+        }
+        Ok(())
+    }
+
+    /// Process a packet from the network manager's incoming queue
+    pub async fn handle_incoming(&self, packet: &IncomingPacket) -> Result<Vec<Delivered>> {
+        let mut connections = self.connections.lock().await;
+        connections
+            .entry(packet.connection_id)
+            .or_insert_with(|| ReliableConnection::new(self.config.clone()))
+            .receive(&packet.data, Instant::now())
+    }
+
+    /// Resend overdue messages and flush acks; call regularly, e.g. every frame
+    ///
+    /// Connections that exhaust their resends are dropped and returned.
+    pub async fn tick(&self) -> Result<Vec<ConnectionId>> {
+        let now = Instant::now();
+        let mut outgoing = Vec::new();
+        let mut failed = Vec::new();
+        let mut resends = Vec::new();
+
+        {
+            let mut connections = self.connections.lock().await;
+            for (connection_id, connection) in connections.iter_mut() {
+                let before = connection.stats.resends;
+                match connection.poll(now) {
+                    Ok(datagrams) => outgoing.extend(datagrams.into_iter().map(|d| (*connection_id, d))),
+                    Err(_) => failed.push(*connection_id),
+                }
+                resends.push((*connection_id, connection.stats.resends - before));
+            }
+            for connection_id in &failed {
+                warn!("Reliable connection {} timed out", connection_id);
+                connections.remove(connection_id);
+            }
+        }
+
+        for (connection_id, count) in resends {
+            if count > 0 {
+                self.network.record_resends(connection_id, count);
+            }
+        }
+        for (connection_id, datagram) in outgoing {
+            let packet = ScheduledPacket::new(datagram, PacketPriority::High, ThrottleCategory::Resend);
+            if let Err(e) = self.network.send_scheduled(connection_id, packet).await {
+                debug!("Reliable send to {} failed: {}", connection_id, e);
+            }
+        }
+        Ok(failed)
+    }
+
+    pub async fn stats(&self, connection_id: ConnectionId) -> Option<ReliabilityStats> {
+        self.connections.lock().await.get(&connection_id).map(|c| c.stats())
+    }
+
+    /// Forget a connection's reliability state
+    pub async fn remove(&self, connection_id: ConnectionId) -> bool {
+        self.connections.lock().await.remove(&connection_id).is_some()
+    }
+}
@@ -0,0 +1,308 @@
+// File: crates/storm-networking/src/scheduler.rs
+// Per-connection send scheduling: priority queues, token-bucket throttles and congestion dropping
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::PacketPriority;
+
+/// Traffic categories throttled separately, in the order of the LLUDP AgentThrottle block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ThrottleCategory {
+    Resend,
+    Land,
+    Wind,
+    Cloud,
+    Task,
+    Texture,
+    Asset,
+}
+
+impl ThrottleCategory {
+    pub const ALL: [ThrottleCategory; 7] = [
+        ThrottleCategory::Resend,
+        ThrottleCategory::Land,
+        ThrottleCategory::Wind,
+        ThrottleCategory::Cloud,
+        ThrottleCategory::Task,
+        ThrottleCategory::Texture,
+        ThrottleCategory::Asset,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Share of the total bandwidth given to the category by default, matching viewer defaults
+    fn default_share(self) -> f64 {
+        match self {
+            ThrottleCategory::Resend => 0.10,
+            ThrottleCategory::Land => 0.15,
+            ThrottleCategory::Wind => 0.02,
+            ThrottleCategory::Cloud => 0.02,
+            ThrottleCategory::Task => 0.30,
+            ThrottleCategory::Texture => 0.30,
+            ThrottleCategory::Asset => 0.11,
+        }
+    }
+}
+
+/// Bandwidth limits for one connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThrottleConfig {
+    /// Cap on all traffic, in bits per second
+    pub total_bps: u64,
+    /// Per-category caps in bits per second, indexed like `ThrottleCategory::ALL`
+    pub category_bps: [u64; 7],
+    /// How much unused allowance a bucket may save up
+    pub burst: Duration,
+    /// Queue size above which Low and then Normal packets are dropped
+    pub max_queued_bytes: usize,
+}
+
+impl ThrottleConfig {
+    /// Split a total bandwidth between the categories using the default shares
+    pub fn with_total(total_bps: u64) -> Self {
+        let mut category_bps = [0; 7];
+        for category in ThrottleCategory::ALL {
+            category_bps[category.index()] = (total_bps as f64 * category.default_share()) as u64;
+        }
+        Self {
+            total_bps,
+            category_bps,
+            burst: Duration::from_millis(250),
+            max_queued_bytes: 256 * 1024,
+        }
+    }
+
+    /// Build from the seven bits-per-second values of an LLUDP AgentThrottle block
+    pub fn from_lludp_throttles(throttles: &[f32; 7]) -> Self {
+        let category_bps = throttles.map(|bps| bps.max(0.0) as u64);
+        Self {
+            total_bps: category_bps.iter().sum(),
+            category_bps,
+            ..Self::default()
+        }
+    }
+
+    pub fn category_bps(&self, category: ThrottleCategory) -> u64 {
+        self.category_bps[category.index()]
+    }
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        // 1.5 Mbit/s, the usual viewer default
+        Self::with_total(1_500_000)
+    }
+}
+
+/// Byte allowance refilled at a fixed rate
+///
+/// Sending is allowed while the balance is positive and may overdraw it, so packets larger
+/// than the burst size still go out instead of waiting forever.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    bytes_per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(bits_per_sec: u64, burst: Duration, now: Instant) -> Self {
+        let bytes_per_sec = bits_per_sec as f64 / 8.0;
+        // Always allow at least one full-size datagram of burst
+        let capacity = (bytes_per_sec * burst.as_secs_f64()).max(1500.0);
+        Self {
+            bytes_per_sec,
+            capacity,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    pub fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    pub fn has_tokens(&self) -> bool {
+        self.tokens > 0.0
+    }
+
+    pub fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+/// A packet waiting for bandwidth
+#[derive(Debug, Clone)]
+pub struct ScheduledPacket {
+    pub data: Vec<u8>,
+    pub priority: PacketPriority,
+    pub category: ThrottleCategory,
+    /// Queued packets with the same key replace each other, e.g. transform updates for one object
+    pub merge_key: Option<u64>,
+}
+
+impl ScheduledPacket {
+    pub fn new(data: Vec<u8>, priority: PacketPriority, category: ThrottleCategory) -> Self {
+        Self {
+            data,
+            priority,
+            category,
+            merge_key: None,
+        }
+    }
+
+    pub fn merging(mut self, key: u64) -> Self {
+        self.merge_key = Some(key);
+        self
+    }
+}
+
+/// Scheduler counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulerStats {
+    pub queued_packets: usize,
+    pub queued_bytes: usize,
+    pub sent: u64,
+    /// Packets discarded because the queue was over its byte budget
+    pub dropped: u64,
+    /// Queued packets replaced by a newer one with the same merge key
+    pub merged: u64,
+}
+
+/// Outgoing queue for one connection
+///
+/// Highest priority goes first. Within a priority, a packet whose category is out of
+/// bandwidth is skipped so it does not hold up other categories. Critical packets ignore the
+/// category throttles and still count against them.
+#[derive(Debug)]
+pub struct SendScheduler {
+    config: ThrottleConfig,
+    total: TokenBucket,
+    categories: Vec<TokenBucket>,
+    /// Indexed by priority, lowest first
+    queues: [VecDeque<ScheduledPacket>; 4],
+    queued_bytes: usize,
+    stats: SchedulerStats,
+}
+
+impl SendScheduler {
+    pub fn new(config: ThrottleConfig, now: Instant) -> Self {
+        let categories = ThrottleCategory::ALL
+            .iter()
+            .map(|category| TokenBucket::new(config.category_bps(*category), config.burst, now))
+            .collect();
+        Self {
+            total: TokenBucket::new(config.total_bps, config.burst, now),
+            categories,
+            queues: Default::default(),
+            queued_bytes: 0,
+            stats: SchedulerStats::default(),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &ThrottleConfig {
+        &self.config
+    }
+
+    /// Change the limits, keeping queued packets
+    pub fn set_config(&mut self, config: ThrottleConfig, now: Instant) {
+        let queues = std::mem::take(&mut self.queues);
+        let stats = self.stats;
+        *self = Self::new(config, now);
+        self.queued_bytes = queues.iter().flatten().map(|packet| packet.data.len()).sum();
+        self.queues = queues;
+        self.stats = stats;
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        SchedulerStats {
+            queued_packets: self.queues.iter().map(VecDeque::len).sum(),
+            queued_bytes: self.queued_bytes,
+            ..self.stats
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    pub fn enqueue(&mut self, packet: ScheduledPacket) {
+        let queue = &mut self.queues[packet.priority as usize];
+
+        if let Some(key) = packet.merge_key {
+            if let Some(queued) = queue.iter_mut().find(|queued| queued.merge_key == Some(key)) {
+                // Keep the older packet's place in line but send the newer data
+                self.queued_bytes = self.queued_bytes - queued.data.len() + packet.data.len();
+                *queued = packet;
+                self.stats.merged += 1;
+                return;
+            }
+        }
+
+        self.queued_bytes += packet.data.len();
+        queue.push_back(packet);
+        self.shed_load();
+    }
+
+    /// Drop the oldest Low, then Normal, packets until the queue fits its budget
+    fn shed_load(&mut self) {
+        for priority in [PacketPriority::Low, PacketPriority::Normal] {
+            while self.queued_bytes > self.config.max_queued_bytes {
+                let Some(dropped) = self.queues[priority as usize].pop_front() else {
+                    break;
+                };
+                self.queued_bytes -= dropped.data.len();
+                self.stats.dropped += 1;
+            }
+        }
+    }
+
+    /// Take the packets the throttles allow right now, in send order
+    pub fn poll(&mut self, now: Instant) -> Vec<ScheduledPacket> {
+        self.total.refill(now);
+        for bucket in &mut self.categories {
+            bucket.refill(now);
+        }
+
+        let mut ready = Vec::new();
+        for queue in self.queues.iter_mut().rev() {
+            let mut index = 0;
+            while index < queue.len() {
+                let packet = &queue[index];
+                let critical = packet.priority == PacketPriority::Critical;
+                if !critical && !self.total.has_tokens() {
+                    return ready;
+                }
+                let category = &mut self.categories[packet.category.index()];
+                if !critical && !category.has_tokens() {
+                    index += 1;
+                    continue;
+                }
+
+                let packet = queue.remove(index).expect("index checked above");
+                category.consume(packet.data.len());
+                self.total.consume(packet.data.len());
+                self.queued_bytes -= packet.data.len();
+                self.stats.sent += 1;
+                ready.push(packet);
+            }
+        }
+        ready
+    }
+
+    /// Take every queued packet in send order, ignoring the throttles
+    pub fn drain(&mut self) -> Vec<ScheduledPacket> {
+        self.queued_bytes = 0;
+        let drained: Vec<ScheduledPacket> = self.queues.iter_mut().rev().flat_map(|queue| queue.drain(..)).collect();
+        self.stats.sent += drained.len() as u64;
+        drained
+    }
+}
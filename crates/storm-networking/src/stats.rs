@@ -0,0 +1,92 @@
+// File: crates/storm-networking/src/stats.rs
+// Traffic counters shared by the network manager and its receive tasks, and per-connection health
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::{ConnectionId, ProtocolType};
+
+/// Running totals of traffic through the network manager
+#[derive(Debug, Default)]
+pub struct TrafficCounters {
+    packets_in: AtomicU64,
+    packets_out: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    resends: AtomicU64,
+}
+
+impl TrafficCounters {
+    pub fn record_in(&self, bytes: usize) {
+        self.packets_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_out(&self, bytes: usize) {
+        self.packets_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a retransmission; protocol layers call this when they resend a reliable packet
+    pub fn record_resend(&self) {
+        self.resends.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, active_connections: usize) -> TrafficStats {
+        TrafficStats {
+            packets_in: self.packets_in.load(Ordering::Relaxed),
+            packets_out: self.packets_out.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            resends: self.resends.load(Ordering::Relaxed),
+            active_connections,
+        }
+    }
+}
+
+/// Point-in-time copy of the traffic counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficStats {
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub resends: u64,
+    pub active_connections: usize,
+}
+
+/// Health and traffic of one connection
+///
+/// RTT, jitter and loss come from heartbeats and stay `None` for peers that do not answer them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    pub connection_id: ConnectionId,
+    pub protocol: ProtocolType,
+    pub remote_addr: SocketAddr,
+    pub connected_for: Duration,
+    /// Time since anything, including a heartbeat, was received
+    pub idle_for: Duration,
+    /// Smoothed round-trip time
+    pub rtt_ms: Option<f64>,
+    /// Mean deviation between consecutive round-trip samples
+    pub jitter_ms: Option<f64>,
+    /// Fraction of heartbeats that went unanswered, 0.0 to 1.0
+    pub loss_rate: Option<f64>,
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Retransmissions reported by protocol layers
+    pub resends: u64,
+    pub heartbeats_sent: u64,
+    pub heartbeats_answered: u64,
+}
+
+/// Traffic totals and the stats of every open connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkStats {
+    pub traffic: TrafficStats,
+    pub connections: Vec<ConnectionStats>,
+}
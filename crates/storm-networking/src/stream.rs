@@ -0,0 +1,211 @@
+// File: crates/storm-networking/src/stream.rs
+// Reader and writer tasks for stream transports: length-prefixed TCP and WebSocket
+
+use std::sync::Arc;
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, RwLock};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
+use anyhow::Result;
+
+use crate::health::Heartbeat;
+use crate::{Connection, ConnectionId, DisconnectReason, IncomingPacket, PacketSink, ProtocolType};
+
+/// Largest frame accepted on a TCP connection
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Work queued for a connection's writer task
+#[derive(Debug)]
+pub(crate) enum Outbound {
+    Data(Vec<u8>),
+    /// WebSocket ping carrying a heartbeat; TCP sends heartbeats as ordinary frames
+    Ping(Vec<u8>),
+    /// Flush everything queued before this, then close the stream
+    Close,
+}
+
+/// A connected stream ready to have its tasks started
+pub(crate) enum StreamTransport {
+    Tcp(TcpStream),
+    WebSocket(Box<WebSocketStream<MaybeTlsStream<TcpStream>>>),
+}
+
+/// Start the reader and writer tasks for a stream connection
+///
+/// The returned sender feeds the writer. When the peer closes the stream or it fails, the
+/// reader removes the connection, which drops the sender and stops the writer.
+pub(crate) fn spawn_stream_tasks(
+    transport: StreamTransport,
+    connection_id: ConnectionId,
+    protocol: ProtocolType,
+    sink: PacketSink,
+    connections: Arc<RwLock<std::collections::HashMap<ConnectionId, Connection>>>,
+) -> mpsc::UnboundedSender<Outbound> {
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+
+    match transport {
+        StreamTransport::Tcp(stream) => {
+            let (reader, writer) = stream.into_split();
+            tokio::spawn(async move {
+                if let Err(e) = write_frames(writer, outbound_rx).await {
+                    debug!("TCP writer for {} stopped: {}", connection_id, e);
+                }
+            });
+            tokio::spawn(async move {
+                let result = read_frames(reader, connection_id, protocol, &sink).await;
+                if let Err(e) = &result {
+                    debug!("TCP reader for {} stopped: {}", connection_id, e);
+                }
+                close_from_reader(&connections, &sink, connection_id, result).await;
+            });
+        }
+        StreamTransport::WebSocket(stream) => {
+            let (writer, reader) = (*stream).split();
+            tokio::spawn(async move {
+                if let Err(e) = write_messages(writer, outbound_rx).await {
+                    debug!("WebSocket writer for {} stopped: {}", connection_id, e);
+                }
+            });
+            tokio::spawn(async move {
+                let result = read_messages(reader, connection_id, protocol, &sink).await;
+                if let Err(e) = &result {
+                    debug!("WebSocket reader for {} stopped: {}", connection_id, e);
+                }
+                close_from_reader(&connections, &sink, connection_id, result).await;
+            });
+        }
+    }
+
+    outbound_tx
+}
+
+async fn close_from_reader(
+    connections: &RwLock<std::collections::HashMap<ConnectionId, Connection>>,
+    sink: &PacketSink,
+    connection_id: ConnectionId,
+    result: Result<()>,
+) {
+    // Already gone if it was closed locally or timed out
+    if connections.write().await.remove(&connection_id).is_none() {
+        return;
+    }
+    let reason = match result {
+        Ok(()) => DisconnectReason::ClosedByPeer,
+        Err(e) => DisconnectReason::Error(e.to_string()),
+    };
+    info!("Connection {} {}", connection_id, reason);
+    sink.health.disconnect(connection_id, reason);
+}
+
+/// Frame layout: 4-byte big-endian length, then the payload
+pub(crate) fn encode_frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + data.len());
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+async fn read_frames<R: AsyncRead + Unpin>(
+    mut reader: R,
+    connection_id: ConnectionId,
+    protocol: ProtocolType,
+    sink: &PacketSink,
+) -> Result<()> {
+    loop {
+        let mut length = [0u8; 4];
+        match reader.read_exact(&mut length).await {
+            Ok(_) => {}
+            // Clean shutdown between frames
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_FRAME_SIZE {
+            warn!("Peer {} sent a {} byte frame; closing", connection_id, length);
+            return Err(anyhow::anyhow!("Frame of {} bytes exceeds {} byte limit", length, MAX_FRAME_SIZE));
+        }
+
+        let mut data = vec![0u8; length];
+        reader.read_exact(&mut data).await?;
+        if !sink.deliver(packet(connection_id, protocol, data)).await {
+            return Ok(());
+        }
+    }
+}
+
+async fn write_frames<W: AsyncWrite + Unpin>(mut writer: W, mut outbound: mpsc::UnboundedReceiver<Outbound>) -> Result<()> {
+    while let Some(item) = outbound.recv().await {
+        match item {
+            Outbound::Data(data) | Outbound::Ping(data) => writer.write_all(&encode_frame(&data)).await?,
+            Outbound::Close => break,
+        }
+    }
+    // Half-close so the peer reads everything sent so far, then EOF
+    writer.shutdown().await?;
+    Ok(())
+}
+
+async fn read_messages<S>(mut reader: S, connection_id: ConnectionId, protocol: ProtocolType, sink: &PacketSink) -> Result<()>
+where
+    S: futures::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    while let Some(message) = reader.next().await {
+        let data = match message? {
+            Message::Binary(data) => data,
+            Message::Text(text) => text.into_bytes(),
+            Message::Close(frame) => {
+                debug!("WebSocket {} closed: {:?}", connection_id, frame);
+                break;
+            }
+            // Pings are answered by tungstenite itself, but still show the peer is alive
+            Message::Ping(data) => {
+                sink.health.record_in(connection_id, data.len());
+                continue;
+            }
+            Message::Pong(data) => {
+                sink.health.record_in(connection_id, data.len());
+                if let Some(heartbeat) = Heartbeat::decode(&data) {
+                    sink.health.heartbeat_reply(connection_id, heartbeat.nonce, std::time::Instant::now());
+                }
+                continue;
+            }
+            Message::Frame(_) => continue,
+        };
+
+        if !sink.deliver(packet(connection_id, protocol, data)).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn write_messages<S>(mut writer: S, mut outbound: mpsc::UnboundedReceiver<Outbound>) -> Result<()>
+where
+    S: futures::Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    while let Some(item) = outbound.recv().await {
+        match item {
+            Outbound::Data(data) => writer.send(Message::Binary(data)).await?,
+            Outbound::Ping(data) => writer.send(Message::Ping(data)).await?,
+            Outbound::Close => {
+                writer.send(Message::Close(None)).await?;
+                break;
+            }
+        }
+    }
+    writer.close().await?;
+    Ok(())
+}
+
+fn packet(connection_id: ConnectionId, protocol: ProtocolType, data: Vec<u8>) -> IncomingPacket {
+    IncomingPacket {
+        connection_id,
+        protocol,
+        data,
+        timestamp: std::time::Instant::now(),
+    }
+}
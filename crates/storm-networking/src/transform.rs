@@ -0,0 +1,736 @@
+// File: crates/storm-networking/src/transform.rs
+// Per-connection packet transforms: zstd compression, AEAD encryption and their handshake
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use ring::{aead, agreement, hkdf, rand as ring_rand};
+use tokio::sync::{watch, Mutex};
+use tracing::{debug, info, warn};
+use anyhow::Result;
+
+use crate::health::Heartbeat;
+use crate::reliable::crc32;
+use crate::{ConnectionId, NetworkConfig, NetworkError, PacketPriority, PacketType, ProtocolType, MAX_FRAME_SIZE};
+
+/// Handshake format version
+pub const TRANSFORM_VERSION: u8 = 1;
+
+const FLAG_COMPRESSION: u8 = 0b01;
+const FLAG_ENCRYPTION: u8 = 0b10;
+const HELLO_SIZE: usize = 39;
+/// Data frames buffered while waiting for the peer's hello
+const MAX_EARLY_FRAMES: usize = 64;
+
+/// One stage of a transform pipeline
+pub trait PacketTransform: Send {
+    fn name(&self) -> &'static str;
+    fn encode(&mut self, data: Vec<u8>) -> Result<Vec<u8>>;
+    fn decode(&mut self, data: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// Ordered transform stages; encoding runs them first to last, decoding last to first
+#[derive(Default)]
+pub struct TransformPipeline {
+    stages: Vec<Box<dyn PacketTransform>>,
+}
+
+impl TransformPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_stage(mut self, stage: Box<dyn PacketTransform>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    pub fn encode(&mut self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.stages.iter_mut().try_fold(data, |data, stage| stage.encode(data))
+    }
+
+    pub fn decode(&mut self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.stages.iter_mut().rev().try_fold(data, |data, stage| stage.decode(data))
+    }
+}
+
+/// zstd compression, optionally primed with a dictionary trained on typical packets
+///
+/// Output starts with a flag byte: 0 for data sent as-is, 1 for a compressed block followed by
+/// its 4-byte original length. Packets too small to benefit, or that do not shrink, go raw.
+pub struct ZstdTransform {
+    compressor: zstd::bulk::Compressor<'static>,
+    decompressor: zstd::bulk::Decompressor<'static>,
+    min_size: usize,
+}
+
+impl ZstdTransform {
+    /// Packets below this size are not worth compressing without a dictionary
+    pub const MIN_SIZE: usize = 64;
+    /// With a dictionary even tiny packets compress
+    pub const MIN_SIZE_WITH_DICTIONARY: usize = 16;
+
+    pub fn new(level: i32, dictionary: Option<&[u8]>) -> Result<Self> {
+        let dictionary = dictionary.unwrap_or_default();
+        Ok(Self {
+            compressor: zstd::bulk::Compressor::with_dictionary(level, dictionary)?,
+            decompressor: zstd::bulk::Decompressor::with_dictionary(dictionary)?,
+            min_size: if dictionary.is_empty() {
+                Self::MIN_SIZE
+            } else {
+                Self::MIN_SIZE_WITH_DICTIONARY
+            },
+        })
+    }
+}
+
+impl PacketTransform for ZstdTransform {
+    fn name(&self) -> &'static str {
+        "zstd"
+    }
+
+    fn encode(&mut self, data: Vec<u8>) -> Result<Vec<u8>> {
+        if data.len() >= self.min_size {
+            let compressed = self.compressor.compress(&data)?;
+            if compressed.len() + 5 < data.len() + 1 {
+                let mut out = Vec::with_capacity(compressed.len() + 5);
+                out.push(1);
+                out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                out.extend_from_slice(&compressed);
+                return Ok(out);
+            }
+        }
+
+        let mut out = Vec::with_capacity(data.len() + 1);
+        out.push(0);
+        out.extend_from_slice(&data);
+        Ok(out)
+    }
+
+    fn decode(&mut self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match data.first() {
+            Some(0) => Ok(data[1..].to_vec()),
+            Some(1) if data.len() >= 5 => {
+                let length = u32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+                if length > MAX_FRAME_SIZE {
+                    return Err(anyhow::anyhow!("Compressed packet claims {} bytes", length));
+                }
+                let decompressed = self.decompressor.decompress(&data[5..], length)?;
+                if decompressed.len() != length {
+                    return Err(anyhow::anyhow!("Compressed packet length mismatch"));
+                }
+                Ok(decompressed)
+            }
+            _ => Err(anyhow::anyhow!("Malformed compressed packet")),
+        }
+    }
+}
+
+/// Train a zstd dictionary from representative packets
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> Result<Vec<u8>> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+/// Identifier peers compare to check they hold the same dictionary
+pub fn dictionary_id(dictionary: &[u8]) -> u32 {
+    if dictionary.is_empty() {
+        0
+    } else {
+        // Zero means "no dictionary" on the wire
+        crc32(dictionary).max(1)
+    }
+}
+
+/// ChaCha20-Poly1305 with separate keys per direction
+///
+/// Each packet carries its 8-byte counter, which forms the nonce and is authenticated. The
+/// transport must deliver in order: a counter at or below the last one seen is rejected as a
+/// replay.
+pub struct AeadTransform {
+    seal_key: aead::LessSafeKey,
+    open_key: aead::LessSafeKey,
+    send_counter: u64,
+    last_received: Option<u64>,
+}
+
+impl AeadTransform {
+    pub fn new(seal_key: &[u8; 32], open_key: &[u8; 32]) -> Result<Self> {
+        let key = |bytes: &[u8; 32]| -> Result<aead::LessSafeKey> {
+            let unbound = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, bytes)
+                .map_err(|_| anyhow::anyhow!("Invalid encryption key"))?;
+            Ok(aead::LessSafeKey::new(unbound))
+        };
+        Ok(Self {
+            seal_key: key(seal_key)?,
+            open_key: key(open_key)?,
+            send_counter: 0,
+            last_received: None,
+        })
+    }
+
+    fn nonce(counter: u64) -> aead::Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        aead::Nonce::assume_unique_for_key(nonce)
+    }
+}
+
+impl PacketTransform for AeadTransform {
+    fn name(&self) -> &'static str {
+        "chacha20-poly1305"
+    }
+
+    fn encode(&mut self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        let counter = self.send_counter;
+        self.send_counter = counter
+            .checked_add(1)
+            .ok_or_else(|| anyhow::anyhow!("Encryption counter exhausted; reconnect to rekey"))?;
+
+        let counter_bytes = counter.to_be_bytes();
+        self.seal_key
+            .seal_in_place_append_tag(Self::nonce(counter), aead::Aad::from(counter_bytes), &mut data)
+            .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+
+        let mut out = Vec::with_capacity(8 + data.len());
+        out.extend_from_slice(&counter_bytes);
+        out.extend_from_slice(&data);
+        Ok(out)
+    }
+
+    fn decode(&mut self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        if data.len() < 8 + aead::CHACHA20_POLY1305.tag_len() {
+            return Err(anyhow::anyhow!("Encrypted packet too short"));
+        }
+        let counter_bytes: [u8; 8] = data[..8].try_into().expect("length checked above");
+        let counter = u64::from_be_bytes(counter_bytes);
+        if self.last_received.is_some_and(|last| counter <= last) {
+            return Err(anyhow::anyhow!("Replayed or reordered encrypted packet {}", counter));
+        }
+
+        let plaintext_len = self
+            .open_key
+            .open_in_place(Self::nonce(counter), aead::Aad::from(counter_bytes), &mut data[8..])
+            .map_err(|_| anyhow::anyhow!("Encrypted packet failed authentication"))?
+            .len();
+        self.last_received = Some(counter);
+        data.drain(..8);
+        data.truncate(plaintext_len);
+        Ok(data)
+    }
+}
+
+/// What a connection offers in its handshake
+#[derive(Clone)]
+pub struct TransformConfig {
+    pub compression: bool,
+    pub encryption: bool,
+    pub compression_level: i32,
+    /// Used when the peer holds the same dictionary, otherwise compression runs without one
+    pub dictionary: Option<Arc<Vec<u8>>>,
+    /// Mixed into key derivation; without it the key exchange only stops passive observers
+    pub pre_shared_key: Option<Arc<Vec<u8>>>,
+}
+
+impl TransformConfig {
+    pub fn from_network_config(config: &NetworkConfig) -> Self {
+        Self {
+            compression: config.compression_enabled,
+            encryption: config.encryption_enabled,
+            compression_level: 3,
+            dictionary: None,
+            pre_shared_key: None,
+        }
+    }
+
+    /// Requested transforms that `protocol` never applies
+    ///
+    /// LLUDP and WebSocket peers (OpenSim simulators, browsers) do not speak the transform
+    /// handshake, so both options are ignored there. QUIC encrypts through TLS instead.
+    pub fn unsupported_by(&self, protocol: ProtocolType) -> Vec<&'static str> {
+        match protocol {
+            ProtocolType::LLUDP | ProtocolType::WebSocket => [
+                (self.compression, "compression"),
+                (self.encryption, "encryption"),
+            ]
+            .into_iter()
+            .filter_map(|(requested, name)| requested.then_some(name))
+            .collect(),
+            ProtocolType::TCP | ProtocolType::QUIC => Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for TransformConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransformConfig")
+            .field("compression", &self.compression)
+            .field("encryption", &self.encryption)
+            .field("compression_level", &self.compression_level)
+            .field("dictionary_id", &self.dictionary.as_deref().map(|d| dictionary_id(d)))
+            .field("pre_shared_key", &self.pre_shared_key.is_some())
+            .finish()
+    }
+}
+
+/// Handshake message: type, version, feature flags, dictionary id and X25519 public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hello {
+    flags: u8,
+    dictionary_id: u32,
+    public_key: [u8; 32],
+}
+
+impl Hello {
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HELLO_SIZE);
+        data.push(PacketType::Handshake.as_u8());
+        data.push(TRANSFORM_VERSION);
+        data.push(self.flags);
+        data.extend_from_slice(&self.dictionary_id.to_be_bytes());
+        data.extend_from_slice(&self.public_key);
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() != HELLO_SIZE || data[0] != PacketType::Handshake.as_u8() {
+            return Err(anyhow::anyhow!("Malformed transform handshake"));
+        }
+        if data[1] != TRANSFORM_VERSION {
+            return Err(anyhow::anyhow!("Unsupported transform version {}", data[1]));
+        }
+        Ok(Self {
+            flags: data[2],
+            dictionary_id: u32::from_be_bytes([data[3], data[4], data[5], data[6]]),
+            public_key: data[7..39].try_into().expect("length checked above"),
+        })
+    }
+}
+
+/// Features agreed with a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+    pub compression: bool,
+    pub dictionary: bool,
+    pub encryption: bool,
+}
+
+enum SessionState {
+    AwaitingHello {
+        private_key: agreement::EphemeralPrivateKey,
+        pending: Vec<(Vec<u8>, PacketPriority)>,
+        early: Vec<Vec<u8>>,
+    },
+    Established {
+        pipeline: TransformPipeline,
+        negotiated: Negotiated,
+    },
+    Failed(String),
+}
+
+/// Result of feeding a frame to a session
+pub(crate) enum Inbound {
+    /// Application data, decoded
+    Data(Vec<u8>),
+    /// Heartbeat, decoded; only frames that passed the negotiated transforms get here
+    Control(Vec<u8>),
+    /// Handshake finished: outgoing data held back waiting for it, and decoded data that
+    /// arrived ahead of the peer's hello
+    Established {
+        pending: Vec<(Vec<u8>, PacketPriority)>,
+        received: Vec<Vec<u8>>,
+    },
+    /// Frame consumed or dropped
+    Nothing,
+}
+
+/// Transform state for one connection
+pub(crate) struct TransformSession {
+    config: TransformConfig,
+    local: Hello,
+    state: SessionState,
+    established: watch::Sender<bool>,
+}
+
+impl TransformSession {
+    /// Start a session; the returned frame is our hello and must reach the peer first
+    fn start(config: TransformConfig) -> Result<(Self, Vec<u8>)> {
+        let rng = ring_rand::SystemRandom::new();
+        let private_key = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+            .map_err(|_| anyhow::anyhow!("Key generation failed"))?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| anyhow::anyhow!("Key generation failed"))?;
+
+        let mut flags = 0;
+        if config.compression {
+            flags |= FLAG_COMPRESSION;
+        }
+        if config.encryption {
+            flags |= FLAG_ENCRYPTION;
+        }
+        let local = Hello {
+            flags,
+            dictionary_id: config.dictionary.as_deref().map(|d| dictionary_id(d)).unwrap_or(0),
+            public_key: public_key.as_ref().try_into()?,
+        };
+
+        let hello = local.encode();
+        let session = Self {
+            config,
+            local,
+            state: SessionState::AwaitingHello {
+                private_key,
+                pending: Vec::new(),
+                early: Vec::new(),
+            },
+            established: watch::channel(false).0,
+        };
+        Ok((session, hello))
+    }
+
+    fn negotiated(&self) -> Option<Negotiated> {
+        match &self.state {
+            SessionState::Established { negotiated, .. } => Some(*negotiated),
+            _ => None,
+        }
+    }
+
+    /// Encode outgoing data, or hold it until the handshake completes
+    fn outbound(&mut self, data: Vec<u8>, priority: PacketPriority) -> Result<Option<Vec<u8>>> {
+        match &mut self.state {
+            SessionState::Established { pipeline, .. } => {
+                let mut frame = vec![PacketType::Data.as_u8()];
+                frame.extend_from_slice(&pipeline.encode(data)?);
+                Ok(Some(frame))
+            }
+            SessionState::AwaitingHello { pending, .. } => {
+                pending.push((data, priority));
+                Ok(None)
+            }
+            SessionState::Failed(reason) => Err(anyhow::anyhow!("Transform handshake failed: {}", reason)),
+        }
+    }
+
+    /// Encode a heartbeat under its own frame type; it is dropped, not held, during the handshake
+    fn outbound_control(&mut self, data: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        match &mut self.state {
+            SessionState::Established { pipeline, .. } => {
+                let mut frame = vec![PacketType::Heartbeat.as_u8()];
+                frame.extend_from_slice(&pipeline.encode(data)?);
+                Ok(Some(frame))
+            }
+            SessionState::AwaitingHello { .. } => Ok(None),
+            SessionState::Failed(reason) => Err(anyhow::anyhow!("Transform handshake failed: {}", reason)),
+        }
+    }
+
+    fn inbound(&mut self, frame: Vec<u8>) -> Result<Inbound> {
+        let Some(&kind) = frame.first() else {
+            return Ok(Inbound::Nothing);
+        };
+
+        if kind == PacketType::Handshake.as_u8() {
+            let peer = Hello::decode(&frame)?;
+            return self.complete(peer);
+        }
+        let control = kind == PacketType::Heartbeat.as_u8();
+        if kind != PacketType::Data.as_u8() && !control {
+            return Err(anyhow::anyhow!("Unexpected frame type {}", kind));
+        }
+
+        match &mut self.state {
+            SessionState::Established { pipeline, negotiated } => match pipeline.decode(frame[1..].to_vec()) {
+                Ok(data) if control => Ok(Inbound::Control(data)),
+                Ok(data) => Ok(Inbound::Data(data)),
+                // A frame that fails authentication means tampering or mismatched keys
+                Err(e) if negotiated.encryption => {
+                    self.state = SessionState::Failed(e.to_string());
+                    Err(e)
+                }
+                Err(e) => Err(e),
+            },
+            // The peer only sends heartbeats once established, so there is nothing to keep
+            SessionState::AwaitingHello { .. } if control => Ok(Inbound::Nothing),
+            // Multiplexed transports can deliver data ahead of the hello on another stream
+            SessionState::AwaitingHello { early, .. } if early.len() < MAX_EARLY_FRAMES => {
+                early.push(frame[1..].to_vec());
+                Ok(Inbound::Nothing)
+            }
+            SessionState::AwaitingHello { .. } => {
+                let reason = "peer sent data without a handshake".to_string();
+                self.state = SessionState::Failed(reason.clone());
+                Err(anyhow::anyhow!(reason))
+            }
+            SessionState::Failed(_) => Ok(Inbound::Nothing),
+        }
+    }
+
+    fn complete(&mut self, peer: Hello) -> Result<Inbound> {
+        let state = std::mem::replace(&mut self.state, SessionState::Failed("handshake in progress".to_string()));
+        let SessionState::AwaitingHello { private_key, pending, early } = state else {
+            self.state = state;
+            return Err(anyhow::anyhow!("Duplicate transform handshake"));
+        };
+
+        let agreed = self.local.flags & peer.flags;
+        if self.local.flags & FLAG_ENCRYPTION != 0 && agreed & FLAG_ENCRYPTION == 0 {
+            let reason = "peer does not support encryption".to_string();
+            self.state = SessionState::Failed(reason.clone());
+            return Err(anyhow::anyhow!(reason));
+        }
+
+        let negotiated = Negotiated {
+            compression: agreed & FLAG_COMPRESSION != 0,
+            dictionary: agreed & FLAG_COMPRESSION != 0
+                && self.local.dictionary_id != 0
+                && self.local.dictionary_id == peer.dictionary_id,
+            encryption: agreed & FLAG_ENCRYPTION != 0,
+        };
+
+        let mut pipeline = TransformPipeline::new();
+        if negotiated.compression {
+            let dictionary = self.config.dictionary.as_deref().filter(|_| negotiated.dictionary);
+            pipeline = pipeline.with_stage(Box::new(ZstdTransform::new(
+                self.config.compression_level,
+                dictionary.map(|d| d.as_slice()),
+            )?));
+        }
+        if negotiated.encryption {
+            let (seal, open) = derive_keys(
+                private_key,
+                &self.local.public_key,
+                &peer.public_key,
+                self.config.pre_shared_key.as_deref().map(|k| k.as_slice()),
+            )?;
+            pipeline = pipeline.with_stage(Box::new(AeadTransform::new(&seal, &open)?));
+        }
+
+        let mut received = Vec::with_capacity(early.len());
+        for frame in early {
+            match pipeline.decode(frame) {
+                Ok(data) => received.push(data),
+                Err(e) => warn!("Dropping early frame: {}", e),
+            }
+        }
+
+        debug!("Transforms negotiated: {:?}", negotiated);
+        self.state = SessionState::Established { pipeline, negotiated };
+        let _ = self.established.send(true);
+        Ok(Inbound::Established { pending, received })
+    }
+}
+
+/// Derive (sealing, opening) keys from the X25519 exchange
+///
+/// Both sides order the public keys the same way, so each one's sealing key is the other's
+/// opening key.
+fn derive_keys(
+    private_key: agreement::EphemeralPrivateKey,
+    local_public: &[u8; 32],
+    peer_public: &[u8; 32],
+    pre_shared_key: Option<&[u8]>,
+) -> Result<([u8; 32], [u8; 32])> {
+    if local_public == peer_public {
+        return Err(anyhow::anyhow!("Peer echoed our public key"));
+    }
+    let local_is_low = local_public < peer_public;
+    let (low, high) = if local_is_low {
+        (local_public, peer_public)
+    } else {
+        (peer_public, local_public)
+    };
+
+    let peer_key = agreement::UnparsedPublicKey::new(&agreement::X25519, peer_public);
+    let prk = agreement::agree_ephemeral(private_key, &peer_key, |shared_secret| {
+        hkdf::Salt::new(hkdf::HKDF_SHA256, pre_shared_key.unwrap_or_default()).extract(shared_secret)
+    })
+    .map_err(|_| anyhow::anyhow!("Key agreement failed"))?;
+
+    let expand = |direction: &[u8]| -> Result<[u8; 32]> {
+        let info = [b"storm-transform v1 ".as_slice(), direction, low.as_slice(), high.as_slice()];
+        let mut key = [0u8; 32];
+        prk.expand(&info, hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut key))
+            .map_err(|_| anyhow::anyhow!("Key derivation failed"))?;
+        Ok(key)
+    };
+    let low_to_high = expand(b"low->high ")?;
+    let high_to_low = expand(b"high->low ")?;
+
+    Ok(if local_is_low {
+        (low_to_high, high_to_low)
+    } else {
+        (high_to_low, low_to_high)
+    })
+}
+
+/// Transform sessions for every connection of a manager
+pub(crate) struct TransformRegistry {
+    config: std::sync::RwLock<TransformConfig>,
+    sessions: Mutex<HashMap<ConnectionId, TransformSession>>,
+}
+
+impl TransformRegistry {
+    pub(crate) fn new(config: TransformConfig) -> Self {
+        Self {
+            config: std::sync::RwLock::new(config),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn config(&self) -> TransformConfig {
+        self.config.read().expect("transform config lock poisoned").clone()
+    }
+
+    pub(crate) fn update_config(&self, update: impl FnOnce(&mut TransformConfig)) {
+        update(&mut self.config.write().expect("transform config lock poisoned"));
+    }
+
+    /// Begin the handshake for a new connection, returning the hello frame to send
+    ///
+    /// `allow_encryption` is false for transports that already encrypt, such as QUIC.
+    pub(crate) async fn start(&self, connection_id: ConnectionId, allow_encryption: bool) -> Result<Vec<u8>> {
+        let mut config = self.config();
+        config.encryption &= allow_encryption;
+        let (session, hello) = TransformSession::start(config)?;
+        self.sessions.lock().await.insert(connection_id, session);
+        Ok(hello)
+    }
+
+    /// Encode data for a connection; `None` means it was held for the handshake
+    ///
+    /// Connections without a session pass data through untouched.
+    pub(crate) async fn outbound(
+        &self,
+        connection_id: ConnectionId,
+        data: Vec<u8>,
+        priority: PacketPriority,
+    ) -> Result<Option<Vec<u8>>> {
+        self.outbound_with(connection_id, data, priority, Ok).await
+    }
+
+    /// Encode data and pass the frame to `send` before releasing the session
+    ///
+    /// Ordered transports need this so encrypted frames are queued in counter order.
+    pub(crate) async fn outbound_with<R>(
+        &self,
+        connection_id: ConnectionId,
+        data: Vec<u8>,
+        priority: PacketPriority,
+        send: impl FnOnce(Vec<u8>) -> Result<R>,
+    ) -> Result<Option<R>> {
+        let mut sessions = self.sessions.lock().await;
+        let frame = match sessions.get_mut(&connection_id) {
+            Some(session) => session.outbound(data, priority).map_err(|e| handshake_error(connection_id, e))?,
+            None => Some(data),
+        };
+        frame.map(send).transpose()
+    }
+
+    /// Encode a heartbeat and pass the frame to `send` before releasing the session
+    ///
+    /// Connections without a session pass it through untouched; `None` means it was dropped
+    /// because the handshake is still running.
+    pub(crate) async fn outbound_control_with<R>(
+        &self,
+        connection_id: ConnectionId,
+        data: Vec<u8>,
+        send: impl FnOnce(Vec<u8>) -> Result<R>,
+    ) -> Result<Option<R>> {
+        let mut sessions = self.sessions.lock().await;
+        let frame = match sessions.get_mut(&connection_id) {
+            Some(session) => session.outbound_control(data).map_err(|e| handshake_error(connection_id, e))?,
+            None => Some(data),
+        };
+        frame.map(send).transpose()
+    }
+
+    /// Encode a datagram; it is dropped rather than held if the handshake is still running
+    pub(crate) async fn outbound_datagram(&self, connection_id: ConnectionId, data: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        match self.sessions.lock().await.get_mut(&connection_id) {
+            Some(session) if session.negotiated().is_none() => {
+                debug!("Dropping datagram for {} until its handshake completes", connection_id);
+                Ok(None)
+            }
+            Some(session) => session
+                .outbound(data, PacketPriority::Low)
+                .map_err(|e| handshake_error(connection_id, e)),
+            None => Ok(Some(data)),
+        }
+    }
+
+    pub(crate) async fn inbound(&self, connection_id: ConnectionId, frame: Vec<u8>) -> Inbound {
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(&connection_id) else {
+            // Without transforms there is nothing to authenticate, so heartbeats go as is
+            if Heartbeat::decode(&frame).is_some() {
+                return Inbound::Control(frame);
+            }
+            return Inbound::Data(frame);
+        };
+
+        match session.inbound(frame) {
+            Ok(established @ Inbound::Established { .. }) => {
+                info!("Connection {} negotiated {:?}", connection_id, session.negotiated());
+                established
+            }
+            Ok(inbound) => inbound,
+            Err(e) => {
+                warn!("Dropping frame from {}: {}", connection_id, e);
+                Inbound::Nothing
+            }
+        }
+    }
+
+    pub(crate) async fn negotiated(&self, connection_id: ConnectionId) -> Option<Negotiated> {
+        self.sessions.lock().await.get(&connection_id)?.negotiated()
+    }
+
+    /// Connections whose handshake failed and should be closed
+    pub(crate) async fn failed(&self) -> Vec<ConnectionId> {
+        let sessions = self.sessions.lock().await;
+        sessions
+            .iter()
+            .filter(|(_, session)| matches!(session.state, SessionState::Failed(_)))
+            .map(|(connection_id, _)| *connection_id)
+            .collect()
+    }
+
+    /// Wait until the connection's handshake completes
+    pub(crate) async fn wait_established(&self, connection_id: ConnectionId, timeout: Duration) -> Result<()> {
+        let mut established = match self.sessions.lock().await.get(&connection_id) {
+            Some(session) => session.established.subscribe(),
+            None => return Ok(()),
+        };
+
+        let waited = tokio::time::timeout(timeout, established.wait_for(|done| *done)).await;
+        match waited {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(_)) => Err(NetworkError::ConnectionClosed { connection_id }.into()),
+            Err(_) => Err(NetworkError::Timeout {
+                connection_id: Some(connection_id),
+            }
+            .into()),
+        }
+    }
+
+    pub(crate) async fn remove(&self, connection_id: ConnectionId) {
+        self.sessions.lock().await.remove(&connection_id);
+    }
+
+    pub(crate) async fn retain(&self, open: &std::collections::HashSet<ConnectionId>) {
+        self.sessions.lock().await.retain(|connection_id, _| open.contains(connection_id));
+    }
+}
+
+fn handshake_error(connection_id: ConnectionId, error: anyhow::Error) -> anyhow::Error {
+    NetworkError::HandshakeFailed {
+        connection_id,
+        reason: error.to_string(),
+    }
+    .into()
+}
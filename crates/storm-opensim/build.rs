@@ -0,0 +1,21 @@
+// File: crates/storm-opensim/build.rs
+// Generates the LLUDP message types and bodies from message_template.msg
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[path = "codegen/template.rs"]
+mod template;
+
+fn main() {
+    println!("cargo:rerun-if-changed=message_template.msg");
+    println!("cargo:rerun-if-changed=codegen/template.rs");
+
+    let source = fs::read_to_string("message_template.msg").expect("failed to read message_template.msg");
+    let template = template::parse(&source).unwrap_or_else(|e| panic!("message_template.msg: {}", e));
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    fs::write(out_dir.join("message_types.rs"), template::emit_message_types(&template)).expect("failed to write message_types.rs");
+    fs::write(out_dir.join("message_bodies.rs"), template::emit_message_bodies(&template)).expect("failed to write message_bodies.rs");
+}
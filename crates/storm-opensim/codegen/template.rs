@@ -0,0 +1,747 @@
+// File: crates/storm-opensim/codegen/template.rs
+// Parser for LL message_template.msg files and the Rust code generated from them
+//
+// Shared by the storm-opensim build script and `storm-code-gen protocol`, so it only uses std.
+
+use std::fmt::Write;
+
+/// How often a message is sent, which decides the width of its number on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    High,
+    Medium,
+    Low,
+    Fixed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    S8,
+    S16,
+    S32,
+    S64,
+    F32,
+    F64,
+    Bool,
+    Uuid,
+    Vector3,
+    Vector3d,
+    Vector4,
+    Quaternion,
+    IpAddr,
+    IpPort,
+    Fixed(usize),
+    /// Length-prefixed bytes; the prefix is 1 or 2 bytes
+    Variable(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    /// Rust identifier, unique within its block
+    pub ident: String,
+    pub field_type: FieldType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockQuantity {
+    Single,
+    /// Always exactly this many blocks, with no count on the wire
+    Multiple(usize),
+    /// A count byte followed by that many blocks
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub name: String,
+    /// Rust identifier of the message field holding it, unique within its message
+    pub ident: String,
+    /// Generated struct name, unique across the template
+    pub type_name: String,
+    pub quantity: BlockQuantity,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub name: String,
+    pub frequency: Frequency,
+    /// Number within the frequency; Fixed messages carry the whole 32-bit id
+    pub number: u32,
+    pub trusted: bool,
+    pub zerocoded: bool,
+    pub deprecated: bool,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub version: String,
+    pub messages: Vec<Message>,
+}
+
+struct Tokens<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut tokens = Vec::new();
+        for line in source.lines() {
+            let line = line.split("//").next().unwrap_or_default();
+            for word in line.split_whitespace() {
+                // Braces may be written against their contents, as in `{Code U32}`
+                let mut rest = word;
+                while !rest.is_empty() {
+                    if let Some(stripped) = rest.strip_prefix(['{', '}']) {
+                        tokens.push(&rest[..1]);
+                        rest = stripped;
+                        continue;
+                    }
+                    let end = rest.find(['{', '}']).unwrap_or(rest.len());
+                    tokens.push(&rest[..end]);
+                    rest = &rest[end..];
+                }
+            }
+        }
+        Self { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self.peek().ok_or("Unexpected end of message template")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(format!("Expected '{}' in message template, found '{}'", expected, token));
+        }
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        let token = self.next()?;
+        let parsed = match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => token.parse(),
+        };
+        parsed.map_err(|_| format!("Invalid number '{}' in message template", token))
+    }
+}
+
+/// Parse the text of a message template
+pub fn parse(source: &str) -> Result<Template, String> {
+    let mut tokens = Tokens::new(source);
+    let mut version = String::new();
+    if tokens.peek() == Some("version") {
+        tokens.next()?;
+        version = tokens.next()?.to_string();
+    }
+
+    let mut messages: Vec<Message> = Vec::new();
+    while tokens.peek().is_some() {
+        let message = parse_message(&mut tokens)?;
+        if let Some(other) = messages
+            .iter()
+            .find(|other| other.name == message.name || (other.frequency == message.frequency && other.number == message.number))
+        {
+            return Err(format!("Message {} clashes with {}", message.name, other.name));
+        }
+        messages.push(message);
+    }
+    assign_type_names(&mut messages)?;
+    Ok(Template { version, messages })
+}
+
+/// Names the generated code imports or defines besides the messages themselves
+const RESERVED_TYPES: &[&str] = &[
+    "Frequency", "Ipv4Addr", "LLUDPMessageType", "MessageBody", "PayloadReader", "PayloadWriter", "Result",
+    "TemplateMessage", "Unknown", "Uuid",
+];
+
+/// Give every block a struct name that no message, reserved name or other block already uses
+///
+/// Blocks are named `{Message}{Block}`; when that is another message's name the block gets a
+/// `Block` suffix until the name is free.
+fn assign_type_names(messages: &mut [Message]) -> Result<(), String> {
+    let mut taken: std::collections::HashSet<String> = RESERVED_TYPES.iter().map(|name| name.to_string()).collect();
+    for message in messages.iter() {
+        if !taken.insert(message.name.clone()) {
+            return Err(format!("Message {} clashes with a generated name", message.name));
+        }
+    }
+    for message in messages.iter_mut() {
+        for block in &mut message.blocks {
+            let mut type_name = format!("{}{}", message.name, block.name);
+            while taken.contains(&type_name) {
+                type_name.push_str("Block");
+            }
+            taken.insert(type_name.clone());
+            block.type_name = type_name;
+        }
+    }
+    Ok(())
+}
+
+/// Append `_2`, `_3`, ... to identifiers that snake_case maps onto an earlier one
+fn unique_ident(taken: &mut Vec<String>, name: &str) -> String {
+    let base = snake_case(name);
+    let mut ident = base.clone();
+    let mut suffix = 2;
+    while taken.contains(&ident) {
+        ident = format!("{}_{}", base, suffix);
+        suffix += 1;
+    }
+    taken.push(ident.clone());
+    ident
+}
+
+fn parse_message(tokens: &mut Tokens) -> Result<Message, String> {
+    tokens.expect("{")?;
+    let name = tokens.next()?.to_string();
+    let frequency = match tokens.next()? {
+        "High" => Frequency::High,
+        "Medium" => Frequency::Medium,
+        "Low" => Frequency::Low,
+        "Fixed" => Frequency::Fixed,
+        other => return Err(format!("Unknown frequency '{}' for {}", other, name)),
+    };
+    let number = tokens.number()?;
+    let valid = match frequency {
+        Frequency::High | Frequency::Medium => (1..=254).contains(&number),
+        Frequency::Low => (1..=0xFFFE).contains(&number),
+        Frequency::Fixed => number >= 0xFFFF_FF00,
+    };
+    if !valid {
+        return Err(format!("Message number {} is out of range for {:?} message {}", number, frequency, name));
+    }
+
+    let mut block_idents = Vec::new();
+    let mut message = Message {
+        name,
+        frequency,
+        number,
+        trusted: false,
+        zerocoded: false,
+        deprecated: false,
+        blocks: Vec::new(),
+    };
+    loop {
+        match tokens.next()? {
+            "Trusted" => message.trusted = true,
+            "NotTrusted" => message.trusted = false,
+            "Zerocoded" => message.zerocoded = true,
+            "Unencoded" => message.zerocoded = false,
+            "Deprecated" | "UDPDeprecated" | "UDPBlackListed" => message.deprecated = true,
+            "{" => message.blocks.push(parse_block(tokens, &mut block_idents)?),
+            "}" => return Ok(message),
+            other => return Err(format!("Unexpected '{}' in message {}", other, message.name)),
+        }
+    }
+}
+
+fn parse_block(tokens: &mut Tokens, taken: &mut Vec<String>) -> Result<Block, String> {
+    let name = tokens.next()?.to_string();
+    let ident = unique_ident(taken, &name);
+    let quantity = match tokens.next()? {
+        "Single" => BlockQuantity::Single,
+        "Variable" => BlockQuantity::Variable,
+        "Multiple" => BlockQuantity::Multiple(tokens.number()? as usize),
+        other => return Err(format!("Unknown block quantity '{}' for block {}", other, name)),
+    };
+
+    let mut fields = Vec::new();
+    let mut field_idents = Vec::new();
+    loop {
+        match tokens.next()? {
+            "{" => {
+                fields.push(parse_field(tokens, &mut field_idents)?);
+                tokens.expect("}")?;
+            }
+            // The type name depends on every message, so `parse` fills it in afterwards
+            "}" => return Ok(Block { name, ident, type_name: String::new(), quantity, fields }),
+            other => return Err(format!("Unexpected '{}' in block {}", other, name)),
+        }
+    }
+}
+
+fn parse_field(tokens: &mut Tokens, taken: &mut Vec<String>) -> Result<Field, String> {
+    let name = tokens.next()?.to_string();
+    let ident = unique_ident(taken, &name);
+    let field_type = match tokens.next()? {
+        "U8" => FieldType::U8,
+        "U16" => FieldType::U16,
+        "U32" => FieldType::U32,
+        "U64" => FieldType::U64,
+        "S8" => FieldType::S8,
+        "S16" => FieldType::S16,
+        "S32" => FieldType::S32,
+        "S64" => FieldType::S64,
+        "F32" => FieldType::F32,
+        "F64" => FieldType::F64,
+        "BOOL" => FieldType::Bool,
+        "LLUUID" => FieldType::Uuid,
+        "LLVector3" => FieldType::Vector3,
+        "LLVector3d" => FieldType::Vector3d,
+        "LLVector4" => FieldType::Vector4,
+        "LLQuaternion" => FieldType::Quaternion,
+        "IPADDR" => FieldType::IpAddr,
+        "IPPORT" => FieldType::IpPort,
+        "Fixed" => FieldType::Fixed(tokens.number()? as usize),
+        "Variable" => match tokens.number()? {
+            size @ (1 | 2) => FieldType::Variable(size as usize),
+            size => return Err(format!("Variable field {} has unsupported length prefix {}", name, size)),
+        },
+        other => return Err(format!("Unknown type '{}' for field {}", other, name)),
+    };
+    Ok(Field { name, ident, field_type })
+}
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn", "else", "enum",
+    "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut",
+    "override", "priv", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe",
+    "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Template names are CamelCase with acronyms, e.g. `SimIP` becomes `sim_ip`
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|next| next.is_ascii_lowercase());
+            if prev.is_ascii_lowercase() || prev.is_ascii_digit() || (prev.is_ascii_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    if KEYWORDS.contains(&out.as_str()) {
+        out.insert_str(0, "r#");
+    } else if matches!(out.as_str(), "self" | "super" | "crate") {
+        // These cannot be raw identifiers
+        out.push('_');
+    }
+    out
+}
+
+fn rust_type(field_type: &FieldType) -> &'static str {
+    match field_type {
+        FieldType::U8 => "u8",
+        FieldType::U16 | FieldType::IpPort => "u16",
+        FieldType::U32 => "u32",
+        FieldType::U64 => "u64",
+        FieldType::S8 => "i8",
+        FieldType::S16 => "i16",
+        FieldType::S32 => "i32",
+        FieldType::S64 => "i64",
+        FieldType::F32 => "f32",
+        FieldType::F64 => "f64",
+        FieldType::Bool => "bool",
+        FieldType::Uuid => "Uuid",
+        FieldType::Vector3 => "[f32; 3]",
+        FieldType::Vector3d => "[f64; 3]",
+        FieldType::Vector4 | FieldType::Quaternion => "[f32; 4]",
+        FieldType::IpAddr => "Ipv4Addr",
+        FieldType::Fixed(_) | FieldType::Variable(_) => "Vec<u8>",
+    }
+}
+
+/// Default for fields whose type default is not a valid wire value
+fn custom_default(field_type: &FieldType) -> Option<String> {
+    match field_type {
+        FieldType::IpAddr => Some("Ipv4Addr::UNSPECIFIED".to_string()),
+        FieldType::Quaternion => Some("[0.0, 0.0, 0.0, 1.0]".to_string()),
+        FieldType::Fixed(size) => Some(format!("vec![0; {}]", size)),
+        _ => None,
+    }
+}
+
+fn write_call(field_type: &FieldType, value: &str) -> String {
+    match field_type {
+        FieldType::U8 => format!("w.u8({})", value),
+        FieldType::U16 => format!("w.u16({})", value),
+        FieldType::U32 => format!("w.u32({})", value),
+        FieldType::U64 => format!("w.u64({})", value),
+        FieldType::S8 => format!("w.i8({})", value),
+        FieldType::S16 => format!("w.i16({})", value),
+        FieldType::S32 => format!("w.i32({})", value),
+        FieldType::S64 => format!("w.i64({})", value),
+        FieldType::F32 => format!("w.f32({})", value),
+        FieldType::F64 => format!("w.f64({})", value),
+        FieldType::Bool => format!("w.bool({})", value),
+        FieldType::Uuid => format!("w.uuid({})", value),
+        FieldType::Vector3 => format!("w.vector3({})", value),
+        FieldType::Vector3d => format!("w.vector3d({})", value),
+        FieldType::Vector4 => format!("w.vector4({})", value),
+        FieldType::Quaternion => format!("w.quaternion({})", value),
+        FieldType::IpAddr => format!("w.ip_addr({})", value),
+        FieldType::IpPort => format!("w.ip_port({})", value),
+        FieldType::Fixed(size) => format!("w.fixed(&{}, {})", value, size),
+        FieldType::Variable(1) => format!("w.var1(&{})", value),
+        FieldType::Variable(_) => format!("w.var2(&{})", value),
+    }
+}
+
+fn read_call(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::U8 => "r.u8()?".to_string(),
+        FieldType::U16 => "r.u16()?".to_string(),
+        FieldType::U32 => "r.u32()?".to_string(),
+        FieldType::U64 => "r.u64()?".to_string(),
+        FieldType::S8 => "r.i8()?".to_string(),
+        FieldType::S16 => "r.i16()?".to_string(),
+        FieldType::S32 => "r.i32()?".to_string(),
+        FieldType::S64 => "r.i64()?".to_string(),
+        FieldType::F32 => "r.f32()?".to_string(),
+        FieldType::F64 => "r.f64()?".to_string(),
+        FieldType::Bool => "r.bool()?".to_string(),
+        FieldType::Uuid => "r.uuid()?".to_string(),
+        FieldType::Vector3 => "r.vector3()?".to_string(),
+        FieldType::Vector3d => "r.vector3d()?".to_string(),
+        FieldType::Vector4 => "r.vector4()?".to_string(),
+        FieldType::Quaternion => "r.quaternion()?".to_string(),
+        FieldType::IpAddr => "r.ip_addr()?".to_string(),
+        FieldType::IpPort => "r.ip_port()?".to_string(),
+        FieldType::Fixed(size) => format!("r.bytes({})?.to_vec()", size),
+        FieldType::Variable(1) => "r.var1()?.to_vec()".to_string(),
+        FieldType::Variable(_) => "r.var2()?.to_vec()".to_string(),
+    }
+}
+
+fn frequency_name(frequency: Frequency) -> &'static str {
+    match frequency {
+        Frequency::High => "High",
+        Frequency::Medium => "Medium",
+        Frequency::Low => "Low",
+        Frequency::Fixed => "Fixed",
+    }
+}
+
+/// The `LLUDPMessageType` enum and its template metadata
+pub fn emit_message_types(template: &Template) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated from message_template.msg version {}; do not edit", template.version).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// LLUDP message types").unwrap();
+    writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]").unwrap();
+    writeln!(out, "pub enum LLUDPMessageType {{").unwrap();
+    for message in &template.messages {
+        writeln!(out, "    {},", message.name).unwrap();
+    }
+    writeln!(out, "    /// A message number the template does not define").unwrap();
+    writeln!(out, "    Unknown,").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "impl LLUDPMessageType {{").unwrap();
+    writeln!(out, "    /// Every message in the template").unwrap();
+    writeln!(out, "    pub const ALL: &'static [LLUDPMessageType] = &[").unwrap();
+    for message in &template.messages {
+        writeln!(out, "        Self::{},", message.name).unwrap();
+    }
+    writeln!(out, "    ];").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "    pub fn name(self) -> &'static str {{").unwrap();
+    writeln!(out, "        match self {{").unwrap();
+    for message in &template.messages {
+        writeln!(out, "            Self::{0} => \"{0}\",", message.name).unwrap();
+    }
+    writeln!(out, "            Self::Unknown => \"Unknown\",").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "    /// Frequency and number, or None for `Unknown`").unwrap();
+    writeln!(out, "    pub fn id(self) -> Option<(Frequency, u32)> {{").unwrap();
+    writeln!(out, "        match self {{").unwrap();
+    for message in &template.messages {
+        writeln!(
+            out,
+            "            Self::{} => Some((Frequency::{}, {:#X})),",
+            message.name,
+            frequency_name(message.frequency),
+            message.number
+        )
+        .unwrap();
+    }
+    writeln!(out, "            Self::Unknown => None,").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "    /// Map a frequency and message number back to its type").unwrap();
+    writeln!(out, "    pub fn from_id(frequency: Frequency, number: u32) -> Self {{").unwrap();
+    writeln!(out, "        match (frequency, number) {{").unwrap();
+    for message in &template.messages {
+        writeln!(
+            out,
+            "            (Frequency::{}, {:#X}) => Self::{},",
+            frequency_name(message.frequency),
+            message.number,
+            message.name
+        )
+        .unwrap();
+    }
+    writeln!(out, "            _ => Self::Unknown,").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+
+    emit_flag(&mut out, template, "is_trusted", "Only accepted from trusted peers such as other simulators", |m| m.trusted);
+    emit_flag(&mut out, template, "is_zerocoded", "Sent with runs of zero bytes compressed", |m| m.zerocoded);
+    emit_flag(&mut out, template, "is_deprecated", "Marked deprecated or blacklisted for UDP in the template", |m| m.deprecated);
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn emit_flag(out: &mut String, template: &Template, method: &str, doc: &str, flag: fn(&Message) -> bool) {
+    let names: Vec<String> = template
+        .messages
+        .iter()
+        .filter(|message| flag(message))
+        .map(|message| format!("Self::{}", message.name))
+        .collect();
+    writeln!(out).unwrap();
+    writeln!(out, "    /// {}", doc).unwrap();
+    writeln!(out, "    pub fn {}(self) -> bool {{", method).unwrap();
+    if names.is_empty() {
+        writeln!(out, "        false").unwrap();
+    } else {
+        writeln!(out, "        matches!(self, {})", names.join(" | ")).unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+}
+
+/// Typed structs for every message with their `MessageBody` implementations
+pub fn emit_message_bodies(template: &Template) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated from message_template.msg version {}; do not edit", template.version).unwrap();
+    for message in &template.messages {
+        emit_message(&mut out, message);
+    }
+    emit_any_message(&mut out, template);
+    out
+}
+
+fn emit_message(out: &mut String, message: &Message) {
+    let trust = if message.trusted { "Trusted" } else { "NotTrusted" };
+    let coding = if message.zerocoded { "Zerocoded" } else { "Unencoded" };
+    writeln!(out).unwrap();
+    writeln!(out, "/// {} {} {:#X}, {}, {}", message.name, frequency_name(message.frequency), message.number, trust, coding).unwrap();
+
+    if message.blocks.is_empty() {
+        writeln!(out, "#[derive(Debug, Clone, Default, PartialEq)]").unwrap();
+        writeln!(out, "pub struct {};", message.name).unwrap();
+    } else {
+        writeln!(out, "#[derive(Debug, Clone, Default, PartialEq)]").unwrap();
+        writeln!(out, "pub struct {} {{", message.name).unwrap();
+        for block in &message.blocks {
+            let ty = &block.type_name;
+            match block.quantity {
+                BlockQuantity::Single => writeln!(out, "    pub {}: {},", block.ident, ty).unwrap(),
+                BlockQuantity::Multiple(count) => {
+                    writeln!(out, "    /// Always {} blocks on the wire; missing ones are sent as defaults", count).unwrap();
+                    writeln!(out, "    pub {}: Vec<{}>,", block.ident, ty).unwrap();
+                }
+                BlockQuantity::Variable => writeln!(out, "    pub {}: Vec<{}>,", block.ident, ty).unwrap(),
+            }
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "impl MessageBody for {} {{", message.name).unwrap();
+    writeln!(out, "    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::{};", message.name).unwrap();
+    writeln!(out).unwrap();
+    if message.blocks.is_empty() {
+        writeln!(out, "    fn encode(&self, _w: &mut PayloadWriter) {{}}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn decode(_r: &mut PayloadReader<'_>) -> Result<Self> {{").unwrap();
+        writeln!(out, "        Ok(Self)").unwrap();
+        writeln!(out, "    }}").unwrap();
+    } else {
+        writeln!(out, "    fn encode(&self, w: &mut PayloadWriter) {{").unwrap();
+        for block in &message.blocks {
+            let field = &block.ident;
+            let ty = &block.type_name;
+            match block.quantity {
+                BlockQuantity::Single => writeln!(out, "        self.{}.encode(w);", field).unwrap(),
+                BlockQuantity::Multiple(count) => {
+                    writeln!(out, "        let padding = {}::default();", ty).unwrap();
+                    writeln!(
+                        out,
+                        "        for block in self.{}.iter().chain(std::iter::repeat(&padding)).take({}) {{",
+                        field, count
+                    )
+                    .unwrap();
+                    writeln!(out, "            block.encode(w);").unwrap();
+                    writeln!(out, "        }}").unwrap();
+                }
+                BlockQuantity::Variable => {
+                    writeln!(out, "        w.u8(self.{}.len().min(u8::MAX as usize) as u8);", field).unwrap();
+                    writeln!(out, "        for block in self.{}.iter().take(u8::MAX as usize) {{", field).unwrap();
+                    writeln!(out, "            block.encode(w);").unwrap();
+                    writeln!(out, "        }}").unwrap();
+                }
+            }
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {{").unwrap();
+        writeln!(out, "        Ok(Self {{").unwrap();
+        for block in &message.blocks {
+            let field = &block.ident;
+            let ty = &block.type_name;
+            match block.quantity {
+                BlockQuantity::Single => writeln!(out, "            {}: {}::decode(r)?,", field, ty).unwrap(),
+                BlockQuantity::Multiple(count) => writeln!(
+                    out,
+                    "            {}: (0..{}).map(|_| {}::decode(r)).collect::<Result<_>>()?,",
+                    field, count, ty
+                )
+                .unwrap(),
+                BlockQuantity::Variable => writeln!(
+                    out,
+                    "            {}: {{\n                let count = r.u8()?;\n                (0..count).map(|_| {}::decode(r)).collect::<Result<_>>()?\n            }},",
+                    field, ty
+                )
+                .unwrap(),
+            }
+        }
+        writeln!(out, "        }})").unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+
+    for block in &message.blocks {
+        emit_block(out, message, block);
+    }
+}
+
+fn emit_block(out: &mut String, message: &Message, block: &Block) {
+    let ty = &block.type_name;
+    writeln!(out).unwrap();
+    writeln!(out, "/// {} block of {}", block.name, message.name).unwrap();
+    let derive_default = block.fields.iter().all(|field| custom_default(&field.field_type).is_none());
+    if derive_default {
+        writeln!(out, "#[derive(Debug, Clone, Default, PartialEq)]").unwrap();
+    } else {
+        writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+    }
+    writeln!(out, "pub struct {} {{", ty).unwrap();
+    for field in &block.fields {
+        if field.ident != snake_case(&field.name) {
+            writeln!(out, "    /// `{}` in the template", field.name).unwrap();
+        }
+        if let FieldType::Fixed(size) = field.field_type {
+            writeln!(out, "    /// Exactly {} bytes on the wire", size).unwrap();
+        }
+        writeln!(out, "    pub {}: {},", field.ident, rust_type(&field.field_type)).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+
+    if !derive_default {
+        writeln!(out).unwrap();
+        writeln!(out, "impl Default for {} {{", ty).unwrap();
+        writeln!(out, "    fn default() -> Self {{").unwrap();
+        writeln!(out, "        Self {{").unwrap();
+        for field in &block.fields {
+            let value = custom_default(&field.field_type).unwrap_or_else(|| "Default::default()".to_string());
+            writeln!(out, "            {}: {},", field.ident, value).unwrap();
+        }
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "impl {} {{", ty).unwrap();
+    if block.fields.is_empty() {
+        writeln!(out, "    fn encode(&self, _w: &mut PayloadWriter) {{}}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn decode(_r: &mut PayloadReader<'_>) -> Result<Self> {{").unwrap();
+        writeln!(out, "        Ok(Self {{}})").unwrap();
+        writeln!(out, "    }}").unwrap();
+    } else {
+        writeln!(out, "    fn encode(&self, w: &mut PayloadWriter) {{").unwrap();
+        for field in &block.fields {
+            let name = &field.ident;
+            writeln!(out, "        {};", write_call(&field.field_type, &format!("self.{}", name))).unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {{").unwrap();
+        writeln!(out, "        Ok(Self {{").unwrap();
+        for field in &block.fields {
+            writeln!(out, "            {}: {},", field.ident, read_call(&field.field_type)).unwrap();
+        }
+        writeln!(out, "        }})").unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+}
+
+fn emit_any_message(out: &mut String, template: &Template) {
+    writeln!(out).unwrap();
+    writeln!(out, "/// Any message in the template, decoded by type").unwrap();
+    writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+    writeln!(out, "pub enum TemplateMessage {{").unwrap();
+    for message in &template.messages {
+        writeln!(out, "    {0}({0}),", message.name).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    let all: Vec<&Message> = template.messages.iter().collect();
+    writeln!(out, "impl TemplateMessage {{").unwrap();
+    writeln!(out, "    /// Decode a payload according to its message type").unwrap();
+    writeln!(out, "    pub fn decode(message_type: LLUDPMessageType, payload: &[u8]) -> Result<Self> {{").unwrap();
+    writeln!(out, "        Ok(match message_type {{").unwrap();
+    for message in &all {
+        writeln!(
+            out,
+            "            LLUDPMessageType::{0} => Self::{0}({0}::from_payload(payload)?),",
+            message.name
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "            LLUDPMessageType::Unknown => return Err(anyhow::anyhow!(\"Cannot decode a message of unknown type\")),"
+    )
+    .unwrap();
+    writeln!(out, "        }})").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    pub fn message_type(&self) -> LLUDPMessageType {{").unwrap();
+    writeln!(out, "        match self {{").unwrap();
+    for message in &all {
+        writeln!(out, "            Self::{0}(_) => LLUDPMessageType::{0},", message.name).unwrap();
+    }
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    pub fn to_payload(&self) -> Vec<u8> {{").unwrap();
+    writeln!(out, "        match self {{").unwrap();
+    for message in &all {
+        writeln!(out, "            Self::{}(body) => body.to_payload(),", message.name).unwrap();
+    }
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+}
@@ -0,0 +1,376 @@
+// File: crates/storm-opensim/src/appearance.rs
+// Avatar appearance: the agent's wearables and bakes, and AvatarAppearance of everyone in view
+
+use std::collections::HashMap;
+use anyhow::Result;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use storm_ecs::{AvatarAppearance, BakedRegion, Component, Transform, World};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::caps::{Capabilities, CapsClient};
+use crate::llsd::Llsd;
+use crate::messages::{LLUDPMessageType, LLUDPPacket, MessageBody};
+use crate::objects::{OpenSimObject, RegionObjects};
+use crate::template::{
+    AgentIsNowWearing, AgentIsNowWearingAgentData, AgentIsNowWearingWearableData, AgentSetAppearance,
+    AgentSetAppearanceAgentData, AgentSetAppearanceObjectData, AgentSetAppearanceVisualParam,
+    AgentSetAppearanceWearableData, AgentWearablesRequest, AgentWearablesRequestAgentData, AgentWearablesUpdate,
+    AvatarAppearance as AvatarAppearanceMessage,
+};
+use crate::texture_entry::{TextureEntry, DEFAULT_TEXTURE};
+
+/// Capability asking the simulator to bake the current outfit
+pub const UPDATE_APPEARANCE_CAP: &str = "UpdateAvatarAppearance";
+
+/// Placeholder the simulator puts on faces of avatars that have not baked yet
+pub const DEFAULT_AVATAR_TEXTURE: Uuid = Uuid::from_u128(0xc228d1cf_4b5d_4ba8_84f4_899a0796aa97);
+
+/// Visual parameter telling male (255) from female (0) shapes
+pub const MALE_PARAM_INDEX: usize = 31;
+
+/// Height of a default avatar, used until the simulator reports its size
+const DEFAULT_SIZE: [f32; 3] = [0.45, 0.6, 1.9];
+
+/// Kinds of wearable an agent can have on
+pub mod wearable_type {
+    pub const SHAPE: u8 = 0;
+    pub const SKIN: u8 = 1;
+    pub const HAIR: u8 = 2;
+    pub const EYES: u8 = 3;
+    pub const SHIRT: u8 = 4;
+    pub const PANTS: u8 = 5;
+    pub const SHOES: u8 = 6;
+    pub const SOCKS: u8 = 7;
+    pub const JACKET: u8 = 8;
+    pub const GLOVES: u8 = 9;
+    pub const UNDERSHIRT: u8 = 10;
+    pub const UNDERPANTS: u8 = 11;
+    pub const SKIRT: u8 = 12;
+    pub const ALPHA: u8 = 13;
+    pub const TATTOO: u8 = 14;
+    pub const PHYSICS: u8 = 15;
+    pub const UNIVERSAL: u8 = 16;
+}
+
+/// Avatar TextureEntry face holding the bake of a region
+pub fn baked_texture_index(region: BakedRegion) -> u8 {
+    match region {
+        BakedRegion::Head => 8,
+        BakedRegion::UpperBody => 9,
+        BakedRegion::LowerBody => 10,
+        BakedRegion::Eyes => 11,
+        BakedRegion::Skirt => 19,
+        BakedRegion::Hair => 20,
+    }
+}
+
+/// Wearable types whose layers end up in a region's bake
+fn baked_wearables(region: BakedRegion) -> &'static [u8] {
+    use wearable_type::*;
+    match region {
+        BakedRegion::Head => &[SHAPE, SKIN, HAIR, TATTOO, ALPHA, UNIVERSAL],
+        BakedRegion::UpperBody => &[SHAPE, SKIN, SHIRT, JACKET, GLOVES, UNDERSHIRT, TATTOO, ALPHA, UNIVERSAL],
+        BakedRegion::LowerBody => &[SHAPE, SKIN, PANTS, SHOES, SOCKS, JACKET, UNDERPANTS, TATTOO, ALPHA, UNIVERSAL],
+        BakedRegion::Eyes => &[EYES, ALPHA, UNIVERSAL],
+        BakedRegion::Skirt => &[SKIRT],
+        BakedRegion::Hair => &[HAIR, ALPHA, UNIVERSAL],
+    }
+}
+
+/// Salt mixed into a region's bake cache id so that regions sharing wearables differ
+fn baked_hash_id(region: BakedRegion) -> Uuid {
+    Uuid::from_u128(match region {
+        BakedRegion::Head => 0x18ded8d6_bcfc_e415_8539_944c0f5ea7a6,
+        BakedRegion::UpperBody => 0x338c29e3_3024_4dbb_998d_7c04cf4fa88f,
+        BakedRegion::LowerBody => 0x91b4a2c7_1b1a_ba16_9a16_1f8f8dcc1c3f,
+        BakedRegion::Eyes => 0xb2cf28af_b840_1071_3c6a_78085d8128b5,
+        BakedRegion::Skirt => 0xea800387_ea1a_14e0_56cb_24f2022f969a,
+        BakedRegion::Hair => 0x0af1ef7c_ad24_11dd_8790_001f5bf833e8,
+    })
+}
+
+/// Appearance requests the simulator turned down, reachable through `anyhow::Error::downcast_ref`
+#[derive(Debug, thiserror::Error)]
+pub enum AppearanceError {
+    #[error("Server-side bake failed: {0}")]
+    BakeFailed(String),
+}
+
+/// One worn item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wearable {
+    pub item_id: Uuid,
+    pub asset_id: Uuid,
+    pub wearable_type: u8,
+}
+
+/// What the agent is wearing, as last confirmed by AgentWearablesUpdate
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentWearables {
+    /// Serial number of the update the list came from; older updates are ignored
+    pub serial: u32,
+    pub worn: Vec<Wearable>,
+}
+
+impl AgentWearables {
+    /// Worn items of one type, in layer order
+    pub fn of_type(&self, wearable_type: u8) -> impl Iterator<Item = &Wearable> {
+        self.worn.iter().filter(move |wearable| wearable.wearable_type == wearable_type)
+    }
+
+    /// Cache id of a region's bake: MD5 over the asset ids of the wearables in it, nil when none are worn
+    pub fn bake_cache_id(&self, region: BakedRegion) -> Uuid {
+        let mut hash = Md5::new();
+        let mut worn = false;
+        for wearable_type in baked_wearables(region) {
+            for wearable in self.of_type(*wearable_type) {
+                hash.update(wearable.asset_id.as_bytes());
+                worn = true;
+            }
+        }
+        if !worn {
+            return Uuid::nil();
+        }
+        hash.update(baked_hash_id(region).as_bytes());
+        Uuid::from_bytes(hash.finalize().into())
+    }
+}
+
+/// The agent's own appearance and the messages that publish it
+///
+/// Request the wearables after connecting, then either send `set_appearance` with locally baked
+/// textures or ask the simulator to bake with `request_server_bake`.
+#[derive(Debug, Clone)]
+pub struct AgentAppearance {
+    agent_id: Uuid,
+    session_id: Uuid,
+    wearables: AgentWearables,
+    next_serial: u32,
+}
+
+impl AgentAppearance {
+    pub fn new(agent_id: Uuid, session_id: Uuid) -> Self {
+        Self { agent_id, session_id, wearables: AgentWearables::default(), next_serial: 1 }
+    }
+
+    pub fn wearables(&self) -> &AgentWearables {
+        &self.wearables
+    }
+
+    pub fn wearables_request(&self) -> AgentWearablesRequest {
+        AgentWearablesRequest {
+            agent_data: AgentWearablesRequestAgentData { agent_id: self.agent_id, session_id: self.session_id },
+        }
+    }
+
+    /// Adopt the simulator's list of worn items; returns whether it replaced the current one
+    pub fn apply_wearables(&mut self, message: &AgentWearablesUpdate) -> bool {
+        if message.agent_data.serial_num < self.wearables.serial {
+            debug!("Ignoring stale AgentWearablesUpdate {}", message.agent_data.serial_num);
+            return false;
+        }
+        self.wearables = AgentWearables {
+            serial: message.agent_data.serial_num,
+            worn: message
+                .wearable_data
+                .iter()
+                .filter(|block| !block.item_id.is_nil())
+                .map(|block| Wearable {
+                    item_id: block.item_id,
+                    asset_id: block.asset_id,
+                    wearable_type: block.wearable_type,
+                })
+                .collect(),
+        };
+        true
+    }
+
+    /// Apply an AgentWearablesUpdate; other message types change nothing
+    pub fn handle_packet(&mut self, packet: &LLUDPPacket) -> Result<bool> {
+        Ok(match packet.message_type {
+            LLUDPMessageType::AgentWearablesUpdate => self.apply_wearables(&AgentWearablesUpdate::from_payload(&packet.payload)?),
+            _ => false,
+        })
+    }
+
+    /// Tell the simulator which items are now worn
+    pub fn is_now_wearing(&mut self, worn: Vec<Wearable>) -> AgentIsNowWearing {
+        let message = AgentIsNowWearing {
+            agent_data: AgentIsNowWearingAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            wearable_data: worn
+                .iter()
+                .map(|wearable| AgentIsNowWearingWearableData {
+                    item_id: wearable.item_id,
+                    wearable_type: wearable.wearable_type,
+                })
+                .collect(),
+        };
+        self.wearables.worn = worn;
+        message
+    }
+
+    /// Publish locally baked textures and visual parameters, with the cache ids the simulator uses
+    /// to hand the bakes to other viewers
+    pub fn set_appearance(&mut self, size: [f32; 3], texture_entry: &TextureEntry, visual_params: &[u8]) -> AgentSetAppearance {
+        let serial_num = self.next_serial;
+        self.next_serial = self.next_serial.wrapping_add(1);
+        AgentSetAppearance {
+            agent_data: AgentSetAppearanceAgentData {
+                agent_id: self.agent_id,
+                session_id: self.session_id,
+                serial_num,
+                size,
+            },
+            wearable_data: BakedRegion::ALL
+                .iter()
+                .map(|region| AgentSetAppearanceWearableData {
+                    cache_id: self.wearables.bake_cache_id(*region),
+                    texture_index: baked_texture_index(*region),
+                })
+                .collect(),
+            object_data: AgentSetAppearanceObjectData { texture_entry: texture_entry.to_bytes() },
+            visual_param: visual_params.iter().map(|value| AgentSetAppearanceVisualParam { param_value: *value }).collect(),
+        }
+    }
+
+    /// Ask the simulator to bake the outfit in the Current Outfit folder at `cof_version`; the
+    /// result arrives as an AvatarAppearance packet for the agent
+    pub async fn request_server_bake(&self, client: &CapsClient, capabilities: &Capabilities, cof_version: i32) -> Result<()> {
+        let url = capabilities.url(UPDATE_APPEARANCE_CAP)?;
+        let mut body = Llsd::map();
+        body.insert("cof_version", cof_version);
+        let response = client.post(url, &body).await?;
+        match response.get("error") {
+            Llsd::Undefined => {
+                debug!("Server bake of outfit version {} accepted", cof_version);
+                Ok(())
+            }
+            error => Err(AppearanceError::BakeFailed(error.as_string()).into()),
+        }
+    }
+}
+
+/// AvatarAppearance as the simulator sent it, kept next to the derived `storm_ecs::AvatarAppearance`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenSimAppearance {
+    pub agent_id: Uuid,
+    pub texture_entry: TextureEntry,
+    /// Quantized visual parameters
+    pub visual_params: Vec<u8>,
+    pub appearance_version: u8,
+    pub cof_version: i32,
+    pub flags: u32,
+    pub hover_height: f32,
+    pub is_trial: bool,
+}
+
+impl OpenSimAppearance {
+    pub fn from_message(message: &AvatarAppearanceMessage) -> Result<Self> {
+        let data = message.appearance_data.first().cloned().unwrap_or_default();
+        Ok(Self {
+            agent_id: message.sender.id,
+            texture_entry: TextureEntry::from_bytes(&message.object_data.texture_entry)?,
+            visual_params: message.visual_param.iter().map(|param| param.param_value).collect(),
+            appearance_version: data.appearance_version,
+            cof_version: data.cof_version,
+            flags: data.flags,
+            hover_height: message.appearance_hover.first().map_or(0.0, |hover| hover.hover_height[2]),
+            is_trial: message.sender.is_trial,
+        })
+    }
+
+    /// Bakes that are actually there, skipping placeholders
+    pub fn baked_textures(&self) -> Vec<(BakedRegion, Uuid)> {
+        BakedRegion::ALL
+            .iter()
+            .map(|region| (*region, self.texture_entry.face(baked_texture_index(*region)).texture_id))
+            .filter(|(_, texture)| !texture.is_nil() && *texture != DEFAULT_TEXTURE && *texture != DEFAULT_AVATAR_TEXTURE)
+            .collect()
+    }
+
+    pub fn is_male(&self) -> bool {
+        self.visual_params.get(MALE_PARAM_INDEX).is_some_and(|value| *value > 127)
+    }
+
+    /// Protocol-independent view for rendering
+    pub fn to_avatar_appearance(&self, size: [f32; 3], revision: u64) -> AvatarAppearance {
+        AvatarAppearance {
+            agent_id: self.agent_id,
+            baked_textures: self.baked_textures(),
+            visual_params: self.visual_params.iter().map(|value| *value as f32 / 255.0).collect(),
+            size,
+            hover_height: self.hover_height,
+            revision,
+        }
+    }
+}
+
+impl Component for OpenSimAppearance {
+    fn type_name() -> &'static str {
+        "OpenSimAppearance"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Appearances of the avatars in a region, attached to their object entities
+///
+/// Avatars carry `OpenSimAppearance` and `storm_ecs::AvatarAppearance` once both their
+/// ObjectUpdate and their AvatarAppearance have arrived, in whichever order.
+#[derive(Debug, Default)]
+pub struct AvatarAppearances {
+    pending: HashMap<Uuid, OpenSimAppearance>,
+}
+
+impl AvatarAppearances {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appearances waiting for their avatar to come into view
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Apply an AvatarAppearance, or after any other packet attach appearances whose avatars have
+    /// since arrived; returns the agents whose appearance changed in the world
+    pub fn handle_packet(&mut self, world: &mut World, objects: &RegionObjects, packet: &LLUDPPacket) -> Result<Vec<Uuid>> {
+        if packet.message_type == LLUDPMessageType::AvatarAppearance {
+            let appearance = OpenSimAppearance::from_message(&AvatarAppearanceMessage::from_payload(&packet.payload)?)?;
+            self.pending.insert(appearance.agent_id, appearance);
+        }
+        Ok(self.attach_pending(world, objects))
+    }
+
+    /// Attach appearances whose avatars have since arrived
+    pub fn attach_pending(&mut self, world: &mut World, objects: &RegionObjects) -> Vec<Uuid> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let arrived: Vec<Uuid> = self.pending.keys().filter(|id| objects.entity_by_full_id(**id).is_some()).copied().collect();
+        let mut attached = Vec::new();
+        for agent_id in arrived {
+            let (Some(appearance), Some(entity)) = (self.pending.remove(&agent_id), objects.entity_by_full_id(agent_id)) else {
+                continue;
+            };
+            if !world.get_component::<OpenSimObject>(entity).is_some_and(|object| object.is_avatar()) {
+                warn!("AvatarAppearance for {} names an object that is not an avatar", agent_id);
+                continue;
+            }
+            let size = world.get_component::<Transform>(entity).map_or(DEFAULT_SIZE, |transform| transform.scale);
+            let revision = world.get_component::<AvatarAppearance>(entity).map_or(0, |current| current.revision) + 1;
+            world.add_component(entity, appearance.to_avatar_appearance(size, revision));
+            world.add_component(entity, appearance);
+            attached.push(agent_id);
+        }
+        attached
+    }
+}
@@ -0,0 +1,605 @@
+// File: crates/storm-opensim/src/caps.rs
+// Capabilities: seed capability requests, per-region registries and the EventQueueGet long-poll
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use storm_networking::ConnectionId;
+use storm_protocol_adapters::{OpenSimAdapter, RegionLink, SimulatorEvent};
+use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::{debug, info, warn};
+
+use crate::llsd::{Llsd, LlsdFormat};
+use crate::login::LoginResponse;
+
+/// Capability the simulator delivers region events through
+pub const EVENT_QUEUE_GET: &str = "EventQueueGet";
+
+/// Capabilities requested from a seed unless configured otherwise
+pub const DEFAULT_CAPABILITIES: &[&str] = &[
+    EVENT_QUEUE_GET,
+    "SimulatorFeatures",
+    "GetTexture",
+    "ViewerAsset",
+    "GetMesh",
+    "GetMesh2",
+    "FetchInventory2",
+    "FetchInventoryDescendents2",
+    "FetchLib2",
+    "FetchLibDescendents2",
+    "UpdateAvatarAppearance",
+    "UploadBakedTexture",
+    "NewFileAgentInventory",
+    "ChatSessionRequest",
+];
+
+/// Tuning for capability requests and the event queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapsConfig {
+    /// Capability names requested from each seed
+    pub capabilities: Vec<String>,
+    pub request_timeout: Duration,
+    /// Client-side limit for one long-poll; simulators answer with no events after about 30 seconds
+    pub poll_timeout: Duration,
+    /// Pause after a failed poll before trying again
+    pub retry_delay: Duration,
+    /// Consecutive failed polls before the event queue gives up
+    pub max_poll_failures: u32,
+}
+
+impl Default for CapsConfig {
+    fn default() -> Self {
+        Self {
+            capabilities: DEFAULT_CAPABILITIES.iter().map(|name| name.to_string()).collect(),
+            request_timeout: Duration::from_secs(30),
+            poll_timeout: Duration::from_secs(60),
+            retry_delay: Duration::from_secs(2),
+            max_poll_failures: 5,
+        }
+    }
+}
+
+/// Capability errors callers may want to handle, reachable through `anyhow::Error::downcast_ref`
+#[derive(Debug, thiserror::Error)]
+pub enum CapsError {
+    #[error("Region does not grant the {0} capability")]
+    Missing(String),
+    #[error("Capability request to {url} failed with HTTP {status}")]
+    Http { url: String, status: u16 },
+    #[error("Event queue at {0} is closed")]
+    QueueClosed(String),
+}
+
+/// Capability URLs granted by one region's seed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub seed: String,
+    pub urls: BTreeMap<String, String>,
+}
+
+impl Capabilities {
+    pub fn new(seed: impl Into<String>) -> Self {
+        Self { seed: seed.into(), urls: BTreeMap::new() }
+    }
+
+    /// Read the seed response, a map from capability name to URL
+    pub fn from_llsd(seed: impl Into<String>, response: &Llsd) -> Self {
+        let urls = response
+            .as_map()
+            .map(|map| {
+                map.iter()
+                    .map(|(name, url)| (name.clone(), url.as_string()))
+                    .filter(|(_, url)| !url.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { seed: seed.into(), urls }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.urls.get(name).map(String::as_str)
+    }
+
+    /// URL of a capability the caller cannot do without
+    pub fn url(&self, name: &str) -> Result<&str> {
+        Ok(self.get(name).ok_or_else(|| CapsError::Missing(name.to_string()))?)
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, url: impl Into<String>) {
+        self.urls.insert(name.into(), url.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+}
+
+/// Capabilities of every region the agent has a presence in, keyed by region handle
+#[derive(Debug, Clone, Default)]
+pub struct CapsRegistry {
+    regions: HashMap<u64, Capabilities>,
+}
+
+impl CapsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, region_handle: u64, capabilities: Capabilities) -> Option<Capabilities> {
+        self.regions.insert(region_handle, capabilities)
+    }
+
+    pub fn get(&self, region_handle: u64) -> Option<&Capabilities> {
+        self.regions.get(&region_handle)
+    }
+
+    pub fn remove(&mut self, region_handle: u64) -> Option<Capabilities> {
+        self.regions.remove(&region_handle)
+    }
+
+    /// URL of a capability in a region
+    pub fn url(&self, region_handle: u64, name: &str) -> Option<&str> {
+        self.regions.get(&region_handle).and_then(|caps| caps.get(name))
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (u64, &Capabilities)> {
+        self.regions.iter().map(|(handle, caps)| (*handle, caps))
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+/// HTTP client for capability requests, exchanging LLSD XML
+#[derive(Debug, Clone)]
+pub struct CapsClient {
+    http: reqwest::Client,
+    config: CapsConfig,
+}
+
+impl CapsClient {
+    pub fn new(config: CapsConfig) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(config.request_timeout).build()?;
+        Ok(Self { http, config })
+    }
+
+    pub fn config(&self) -> &CapsConfig {
+        &self.config
+    }
+
+    /// POST an LLSD document to a capability and parse the LLSD answer
+    pub async fn post(&self, url: &str, body: &Llsd) -> Result<Llsd> {
+        let response = self.send(url, body, self.config.request_timeout).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(CapsError::Http { url: url.to_string(), status: status.as_u16() }.into());
+        }
+        parse_body(response).await
+    }
+
+    /// GET a capability and parse the LLSD answer
+    pub async fn get(&self, url: &str) -> Result<Llsd> {
+        let response = self
+            .http
+            .get(url)
+            .header(reqwest::header::ACCEPT, LlsdFormat::Xml.content_type())
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(CapsError::Http { url: url.to_string(), status: status.as_u16() }.into());
+        }
+        parse_body(response).await
+    }
+
+    /// Ask a seed capability for the configured capabilities
+    pub async fn request_capabilities(&self, seed: &str) -> Result<Capabilities> {
+        let names = Llsd::Array(self.config.capabilities.iter().map(|name| Llsd::from(name.as_str())).collect());
+        let response = self.post(seed, &names).await?;
+        let capabilities = Capabilities::from_llsd(seed, &response);
+        info!("Seed {} granted {} of {} capabilities", seed, capabilities.len(), self.config.capabilities.len());
+        Ok(capabilities)
+    }
+
+    /// Event queue of a region
+    pub fn event_queue(&self, region_handle: u64, capabilities: &Capabilities) -> Result<EventQueue> {
+        Ok(EventQueue {
+            client: self.clone(),
+            region_handle,
+            url: capabilities.url(EVENT_QUEUE_GET)?.to_string(),
+            ack: None,
+            done: false,
+        })
+    }
+
+    async fn send(&self, url: &str, body: &Llsd, timeout: Duration) -> Result<reqwest::Response> {
+        Ok(self
+            .http
+            .post(url)
+            .timeout(timeout)
+            .header(reqwest::header::CONTENT_TYPE, LlsdFormat::Xml.content_type())
+            .header(reqwest::header::ACCEPT, LlsdFormat::Xml.content_type())
+            .body(body.encode(LlsdFormat::Xml))
+            .send()
+            .await?)
+    }
+}
+
+async fn parse_body(response: reqwest::Response) -> Result<Llsd> {
+    let format = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(LlsdFormat::from_content_type);
+    let body = response.bytes().await?;
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Llsd::Undefined);
+    }
+    match format {
+        Some(format) => Llsd::parse_as(format, &body),
+        None => Llsd::parse(&body),
+    }
+}
+
+/// One message delivered by the event queue
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapsEvent {
+    /// Region whose queue delivered the event
+    pub region_handle: u64,
+    pub message: String,
+    pub body: Llsd,
+}
+
+impl CapsEvent {
+    /// Region-level events the adapter understands; other messages decode to nothing
+    pub fn simulator_events(&self) -> Vec<SimulatorEvent> {
+        let body = &self.body;
+        match self.message.as_str() {
+            "EnableSimulator" => body
+                .get("SimulatorInfo")
+                .as_array()
+                .iter()
+                .filter_map(|info| {
+                    Some(SimulatorEvent::EnableSimulator {
+                        region_handle: info.get("Handle").as_u64(),
+                        sim_addr: sim_addr(info.get("IP"), info.get("Port"))?,
+                    })
+                })
+                .collect(),
+            "EstablishAgentCommunication" => body
+                .get("sim-ip-and-port")
+                .as_string()
+                .parse()
+                .ok()
+                .map(|sim_addr| SimulatorEvent::EstablishAgentCommunication {
+                    sim_addr,
+                    seed_capability: body.get("seed-capability").as_string(),
+                })
+                .into_iter()
+                .collect(),
+            "TeleportFinish" => {
+                let info = body.get("Info").at(0);
+                sim_addr(info.get("SimIP"), info.get("SimPort"))
+                    .map(|sim_addr| SimulatorEvent::TeleportFinish {
+                        region_handle: info.get("RegionHandle").as_u64(),
+                        sim_addr,
+                        seed_capability: info.get("SeedCapability").as_string(),
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            "CrossedRegion" => {
+                let region = body.get("RegionData").at(0);
+                let position = body.get("Info").at(0).get("Position");
+                sim_addr(region.get("SimIP"), region.get("SimPort"))
+                    .map(|sim_addr| SimulatorEvent::CrossedRegion {
+                        region_handle: region.get("RegionHandle").as_u64(),
+                        sim_addr,
+                        seed_capability: region.get("SeedCapability").as_string(),
+                        position: [0, 1, 2].map(|i| position.at(i).as_real() as f32),
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Simulator address from an IP sent as 4 network-order bytes (or dotted text) and a port
+fn sim_addr(ip: &Llsd, port: &Llsd) -> Option<SocketAddr> {
+    let ip = match ip {
+        Llsd::Binary(bytes) => Ipv4Addr::from(<[u8; 4]>::try_from(bytes.as_slice()).ok()?),
+        other => other.as_string().parse().ok()?,
+    };
+    let port = u16::try_from(port.as_integer()).ok()?;
+    Some(SocketAddr::from((ip, port)))
+}
+
+/// Long-poll client for a region's EventQueueGet capability
+///
+/// Each poll acknowledges the events of the previous answer by its id, so the simulator
+/// only discards events the client has seen.
+#[derive(Debug)]
+pub struct EventQueue {
+    client: CapsClient,
+    region_handle: u64,
+    url: String,
+    ack: Option<i32>,
+    done: bool,
+}
+
+impl EventQueue {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Id of the last answer received, acknowledged by the next poll
+    pub fn ack(&self) -> Option<i32> {
+        self.ack
+    }
+
+    fn request(&self) -> Llsd {
+        let mut request = Llsd::map();
+        request.insert("ack", self.ack.map_or(Llsd::Undefined, Llsd::Integer));
+        request.insert("done", self.done);
+        request
+    }
+
+    /// Wait for the next batch of events; an empty batch means the poll timed out
+    pub async fn poll(&mut self) -> Result<Vec<CapsEvent>> {
+        let response = match self.client.send(&self.url, &self.request(), self.client.config.poll_timeout).await {
+            Ok(response) => response,
+            Err(error) if error.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_timeout()) => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+
+        match response.status().as_u16() {
+            200 => {}
+            // Simulators end a long-poll without events with a gateway error
+            502 | 504 => return Ok(Vec::new()),
+            404 | 410 => return Err(CapsError::QueueClosed(self.url.clone()).into()),
+            status => return Err(CapsError::Http { url: self.url.clone(), status }.into()),
+        }
+
+        let body = parse_body(response).await?;
+        if body.is_undefined() {
+            return Ok(Vec::new());
+        }
+        if let Llsd::Integer(id) = body.get("id") {
+            self.ack = Some(*id);
+        }
+        Ok(body
+            .get("events")
+            .as_array()
+            .iter()
+            .map(|event| CapsEvent {
+                region_handle: self.region_handle,
+                message: event.get("message").as_string(),
+                body: event.get("body").clone(),
+            })
+            .collect())
+    }
+
+    /// Acknowledge what was received and tell the simulator the queue is no longer polled
+    pub async fn close(&mut self) -> Result<()> {
+        self.done = true;
+        self.client.send(&self.url, &self.request(), self.client.config.request_timeout).await?;
+        Ok(())
+    }
+
+    /// Poll until the simulator closes the queue, the receiver goes away or polls keep failing
+    pub async fn run(mut self, events: mpsc::Sender<CapsEvent>) -> Result<()> {
+        let mut failures = 0;
+        loop {
+            match self.poll().await {
+                Ok(batch) => {
+                    failures = 0;
+                    for event in batch {
+                        debug!("Event queue {} delivered {}", self.url, event.message);
+                        if events.send(event).await.is_err() {
+                            return self.close().await;
+                        }
+                    }
+                }
+                Err(error) if error.downcast_ref::<CapsError>().is_some_and(|e| matches!(e, CapsError::QueueClosed(_))) => {
+                    info!("Event queue {} closed by the simulator", self.url);
+                    return Ok(());
+                }
+                Err(error) => {
+                    failures += 1;
+                    if failures >= self.client.config.max_poll_failures {
+                        return Err(error.context(format!("Event queue {} failed {} times in a row", self.url, failures)));
+                    }
+                    warn!("Event queue {} poll failed: {:#}", self.url, error);
+                    tokio::time::sleep(self.client.config.retry_delay).await;
+                }
+            }
+        }
+    }
+}
+
+/// Where a `CapsSession` applies simulator events, and hears which regions it holds capabilities for
+pub trait RegionEvents: Send + Sync {
+    fn dispatch_event(&self, connection_id: ConnectionId, event: SimulatorEvent) -> impl Future<Output = Result<()>> + Send;
+
+    /// Neighbours known for the connection; EstablishAgentCommunication names its region by address only
+    fn neighbours(&self, connection_id: ConnectionId) -> impl Future<Output = Result<Vec<RegionLink>>> + Send;
+
+    /// A region's seed was resolved after the agent teleported, crossed or was established in it
+    fn region_connected(
+        &self,
+        _connection_id: ConnectionId,
+        _region_handle: u64,
+        _capabilities: &Capabilities,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// A region's capabilities were dropped because a teleport left it behind
+    fn region_disconnected(&self, _connection_id: ConnectionId, _region_handle: u64) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+impl RegionEvents for OpenSimAdapter {
+    fn dispatch_event(&self, connection_id: ConnectionId, event: SimulatorEvent) -> impl Future<Output = Result<()>> + Send {
+        OpenSimAdapter::dispatch_event(self, connection_id, event)
+    }
+
+    fn neighbours(&self, connection_id: ConnectionId) -> impl Future<Output = Result<Vec<RegionLink>>> + Send {
+        OpenSimAdapter::neighbours(self, connection_id)
+    }
+}
+
+/// Capabilities and event queues of every region one agent is present in
+///
+/// Events from all queues arrive on one channel.
+pub struct CapsSession {
+    client: CapsClient,
+    registry: CapsRegistry,
+    sender: mpsc::Sender<CapsEvent>,
+    receiver: mpsc::Receiver<CapsEvent>,
+    queues: JoinSet<(u64, Result<()>)>,
+    running: HashMap<u64, AbortHandle>,
+}
+
+impl CapsSession {
+    pub fn new(client: CapsClient) -> Self {
+        let (sender, receiver) = mpsc::channel(256);
+        Self {
+            client,
+            registry: CapsRegistry::new(),
+            sender,
+            receiver,
+            queues: JoinSet::new(),
+            running: HashMap::new(),
+        }
+    }
+
+    pub fn client(&self) -> &CapsClient {
+        &self.client
+    }
+
+    pub fn registry(&self) -> &CapsRegistry {
+        &self.registry
+    }
+
+    /// Resolve the seed capability handed out at login
+    pub async fn connect_login(&mut self, login: &LoginResponse) -> Result<&Capabilities> {
+        self.connect_region(login.region_handle(), &login.seed_capability).await
+    }
+
+    /// Resolve a region's seed and start polling its event queue, replacing any earlier one
+    pub async fn connect_region(&mut self, region_handle: u64, seed: &str) -> Result<&Capabilities> {
+        let capabilities = self.client.request_capabilities(seed).await?;
+        if let Some(queue) = self.running.remove(&region_handle) {
+            queue.abort();
+        }
+        match self.client.event_queue(region_handle, &capabilities) {
+            Ok(queue) => {
+                let sender = self.sender.clone();
+                let abort = self.queues.spawn(async move { (region_handle, queue.run(sender).await) });
+                self.running.insert(region_handle, abort);
+            }
+            Err(error) => warn!("Region {} has no event queue: {:#}", region_handle, error),
+        }
+        self.registry.insert(region_handle, capabilities);
+        Ok(self.registry.get(region_handle).expect("capabilities were just inserted"))
+    }
+
+    /// Stop polling a region and forget its capabilities
+    pub fn disconnect_region(&mut self, region_handle: u64) -> Option<Capabilities> {
+        if let Some(queue) = self.running.remove(&region_handle) {
+            queue.abort();
+        }
+        self.registry.remove(region_handle)
+    }
+
+    /// Next event from any region, or `None` once every event queue has stopped
+    pub async fn next_event(&mut self) -> Option<CapsEvent> {
+        loop {
+            tokio::select! {
+                biased;
+                Some(event) = self.receiver.recv() => return Some(event),
+                finished = self.queues.join_next() => match finished {
+                    Some(Ok((region_handle, result))) => {
+                        if self.running.get(&region_handle).is_some_and(|queue| queue.is_finished()) {
+                            self.running.remove(&region_handle);
+                        }
+                        if let Err(error) = result {
+                            warn!("Event queue of region {} stopped: {:#}", region_handle, error);
+                        }
+                    }
+                    // Aborted when the region was reconnected or dropped
+                    Some(Err(_)) => {}
+                    None => return None,
+                },
+            }
+        }
+    }
+
+    /// Feed events into the adapter until every event queue has stopped, following the
+    /// agent's seeds into the regions it teleports, crosses or is established in
+    pub async fn run<A: RegionEvents>(mut self, adapter: Arc<A>, connection_id: ConnectionId) -> Result<()> {
+        while let Some(event) = self.next_event().await {
+            let decoded = event.simulator_events();
+            if decoded.is_empty() {
+                debug!("Unhandled event queue message {} from region {}", event.message, event.region_handle);
+            }
+            for simulator_event in decoded {
+                if let Err(error) = adapter.dispatch_event(connection_id, simulator_event.clone()).await {
+                    warn!("Could not apply {} from region {}: {:#}", event.message, event.region_handle, error);
+                    continue;
+                }
+                self.follow_seed(adapter.as_ref(), connection_id, simulator_event).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn follow_seed<A: RegionEvents>(&mut self, adapter: &A, connection_id: ConnectionId, event: SimulatorEvent) {
+        let (region_handle, seed, teleported) = match event {
+            SimulatorEvent::TeleportFinish { region_handle, seed_capability, .. } => (region_handle, seed_capability, true),
+            SimulatorEvent::CrossedRegion { region_handle, seed_capability, .. } => (region_handle, seed_capability, false),
+            SimulatorEvent::EstablishAgentCommunication { sim_addr, seed_capability } => {
+                let neighbours = adapter.neighbours(connection_id).await.unwrap_or_default();
+                match neighbours.iter().find(|region| region.sim_addr == Some(sim_addr)) {
+                    Some(region) => (region.handle(), seed_capability, false),
+                    None => return,
+                }
+            }
+            SimulatorEvent::EnableSimulator { .. } => return,
+        };
+
+        // Child agents do not survive a teleport
+        if teleported {
+            let stale: Vec<u64> = self.registry.regions().map(|(handle, _)| handle).filter(|handle| *handle != region_handle).collect();
+            for handle in stale {
+                self.disconnect_region(handle);
+                adapter.region_disconnected(connection_id, handle).await;
+            }
+        }
+        if seed.is_empty() || self.registry.get(region_handle).is_some_and(|caps| caps.seed == seed) {
+            return;
+        }
+        match self.connect_region(region_handle, &seed).await {
+            Ok(capabilities) => adapter.region_connected(connection_id, region_handle, capabilities).await,
+            Err(error) => warn!("Could not resolve seed capability of region {}: {:#}", region_handle, error),
+        }
+    }
+}
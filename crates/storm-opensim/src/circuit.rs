@@ -1,18 +1,103 @@
 // File: crates/storm-opensim/src/circuit.rs
-// OpenSim circuit management
+// OpenSim circuit management: sequencing, acks, resends and ping checks
 
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use storm_networking::RttEstimator;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use crate::messages::{LLUDPMessageType, LLUDPPacket, MessageBody, FLAG_RELIABLE, FLAG_RESENT};
+use crate::serialization::{deserialize_packet, serialize_packet};
+use crate::template::{
+    CompletePingCheck, CompletePingCheckPingID, PacketAck, PacketAckPackets, StartPingCheck, StartPingCheckPingID,
+};
+
+/// Most sequence numbers one PacketAck message carries
+const ACKS_PER_PACKET_ACK: usize = u8::MAX as usize;
+
+/// Tuning for circuit reliability
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitConfig {
+    pub initial_rto: Duration,
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+    /// Close the circuit once a reliable packet has been resent this many times without an ack
+    pub max_resends: u32,
+    /// How long a received reliable packet may wait for a piggyback before a PacketAck is sent
+    pub ack_delay: Duration,
+    /// Acks appended to each outgoing datagram
+    pub max_appended_acks: usize,
+    /// Interval between StartPingCheck messages
+    pub ping_interval: Duration,
+    /// Recently received sequence numbers remembered for duplicate detection
+    pub duplicate_window: usize,
+}
+
+impl Default for CircuitConfig {
+    fn default() -> Self {
+        Self {
+            initial_rto: Duration::from_secs(1),
+            min_rto: Duration::from_millis(250),
+            max_rto: Duration::from_secs(10),
+            max_resends: 4,
+            ack_delay: Duration::from_millis(100),
+            max_appended_acks: 16,
+            ping_interval: Duration::from_secs(5),
+            duplicate_window: 1024,
+        }
+    }
+}
+
+/// Counters for one circuit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CircuitStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub resends: u64,
+    pub duplicates: u64,
+    /// PacketAck messages sent; piggybacked acks are not counted
+    pub acks_sent: u64,
+    /// Reliable packets waiting for an ack
+    pub unacked: usize,
+    pub srtt_ms: Option<f64>,
+    pub rto_ms: f64,
+    /// Round trip of the last answered StartPingCheck
+    pub ping_ms: Option<f64>,
+}
+
 /// Circuit state for LLUDP connection
+///
+/// Sans-IO: `send` turns packets into datagrams, `receive` consumes datagrams from the peer and
+/// `poll` produces acks, resends and pings that are due. The owner moves bytes on the socket.
 #[derive(Debug, Clone)]
 pub struct Circuit {
     pub code: u32,
     pub session_id: Uuid,
     pub secure_session_id: Uuid,
+    /// Highest sequence number received
     pub sequence_in: u32,
     pub sequence_out: u32,
+    /// Sequences of received reliable packets still to be acknowledged
     pub acks_pending: Vec<u32>,
+    /// Reliable packets sent but not yet acknowledged
     pub packets_pending: Vec<PendingPacket>,
+    config: CircuitConfig,
+    rtt: RttEstimator,
+    /// Recently received sequences; reliable resends can arrive long after newer packets
+    received: HashSet<u32>,
+    received_order: VecDeque<u32>,
+    acks_owed_since: Option<Instant>,
+    /// Replies produced while receiving, handed out by the next `poll`
+    outbox: Vec<Vec<u8>>,
+    next_ping_id: u8,
+    ping_sent: Option<(u8, Instant)>,
+    last_ping: Option<Instant>,
+    ping: Option<Duration>,
+    closed: bool,
+    stats: CircuitStats,
 }
 
 /// Pending packet for resend
@@ -20,12 +105,17 @@ pub struct Circuit {
 pub struct PendingPacket {
     pub sequence: u32,
     pub data: Vec<u8>,
-    pub timestamp: std::time::Instant,
+    /// When the packet was last (re)sent
+    pub timestamp: Instant,
     pub resend_count: u32,
 }
 
 impl Circuit {
     pub fn new(code: u32, session_id: Uuid, secure_session_id: Uuid) -> Self {
+        Self::with_config(code, session_id, secure_session_id, CircuitConfig::default())
+    }
+
+    pub fn with_config(code: u32, session_id: Uuid, secure_session_id: Uuid, config: CircuitConfig) -> Self {
         Self {
             code,
             session_id,
@@ -34,6 +124,18 @@ impl Circuit {
             sequence_out: 1,
             acks_pending: Vec::new(),
             packets_pending: Vec::new(),
+            rtt: RttEstimator::new(config.initial_rto, config.min_rto, config.max_rto),
+            config,
+            received: HashSet::new(),
+            received_order: VecDeque::new(),
+            acks_owed_since: None,
+            outbox: Vec::new(),
+            next_ping_id: 0,
+            ping_sent: None,
+            last_ping: None,
+            ping: None,
+            closed: false,
+            stats: CircuitStats::default(),
         }
     }
 
@@ -50,4 +152,221 @@ impl Circuit {
     pub fn process_ack(&mut self, sequence: u32) {
         self.packets_pending.retain(|p| p.sequence != sequence);
     }
+
+    pub fn config(&self) -> &CircuitConfig {
+        &self.config
+    }
+
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// Latency measured by the last answered ping check
+    pub fn ping(&self) -> Option<Duration> {
+        self.ping
+    }
+
+    /// Whether a reliable packet ran out of resends; nothing more is sent on a closed circuit
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn stats(&self) -> CircuitStats {
+        CircuitStats {
+            unacked: self.packets_pending.len(),
+            srtt_ms: self.rtt.srtt().map(|srtt| srtt.as_secs_f64() * 1000.0),
+            rto_ms: self.rtt.rto().as_secs_f64() * 1000.0,
+            ping_ms: self.ping.map(|ping| ping.as_secs_f64() * 1000.0),
+            ..self.stats.clone()
+        }
+    }
+
+    /// Sequence the packet, append owed acks and serialize it
+    ///
+    /// Reliable packets are kept until the peer acknowledges them and resent from `poll`.
+    pub fn send(&mut self, mut packet: LLUDPPacket, reliable: bool, now: Instant) -> Result<Vec<u8>> {
+        if self.closed {
+            return Err(anyhow::anyhow!("Circuit {} is closed", self.code));
+        }
+
+        packet.sequence = self.next_sequence();
+        if reliable {
+            packet.flags |= FLAG_RELIABLE;
+        } else {
+            packet.flags &= !FLAG_RELIABLE;
+        }
+        packet.flags &= !FLAG_RESENT;
+
+        let appended = self.acks_pending.len().min(self.config.max_appended_acks);
+        packet.acks.extend(self.acks_pending.drain(..appended));
+        if self.acks_pending.is_empty() {
+            self.acks_owed_since = None;
+        }
+
+        let data = serialize_packet(&packet)?;
+        if reliable {
+            self.packets_pending.push(PendingPacket {
+                sequence: packet.sequence,
+                data: data.clone(),
+                timestamp: now,
+                resend_count: 0,
+            });
+        }
+        self.stats.packets_sent += 1;
+        Ok(data)
+    }
+
+    /// Serialize a message body onto the circuit
+    pub fn send_message<M: MessageBody>(&mut self, body: &M, reliable: bool, now: Instant) -> Result<Vec<u8>> {
+        self.send(body.to_packet(), reliable, now)
+    }
+
+    /// Process a datagram from the peer
+    ///
+    /// Acks and ping checks are handled here and not returned; duplicates are acknowledged
+    /// again but dropped. Replies are queued for the next `poll`.
+    pub fn receive(&mut self, data: &[u8], now: Instant) -> Result<Option<LLUDPPacket>> {
+        let packet = deserialize_packet(data)?;
+        self.stats.packets_received += 1;
+
+        for sequence in &packet.acks {
+            self.acknowledged(*sequence, now);
+        }
+
+        // The peer resends when our ack was lost, so duplicates must be acked again
+        if packet.is_reliable() {
+            if self.acks_pending.is_empty() {
+                self.acks_owed_since = Some(now);
+            }
+            self.add_ack(packet.sequence);
+        }
+
+        if !self.remember(packet.sequence) {
+            debug!("Dropping duplicate packet {} on circuit {}", packet.sequence, self.code);
+            self.stats.duplicates += 1;
+            return Ok(None);
+        }
+        self.sequence_in = self.sequence_in.max(packet.sequence);
+
+        match packet.message_type {
+            LLUDPMessageType::PacketAck => {
+                for block in PacketAck::from_payload(&packet.payload)?.packets {
+                    self.acknowledged(block.id, now);
+                }
+                Ok(None)
+            }
+            LLUDPMessageType::StartPingCheck => {
+                let ping = StartPingCheck::from_payload(&packet.payload)?;
+                let reply = CompletePingCheck {
+                    ping_id: CompletePingCheckPingID {
+                        ping_id: ping.ping_id.ping_id,
+                    },
+                };
+                let datagram = self.send_message(&reply, false, now)?;
+                self.outbox.push(datagram);
+                Ok(None)
+            }
+            LLUDPMessageType::CompletePingCheck => {
+                let ping = CompletePingCheck::from_payload(&packet.payload)?;
+                if let Some((id, sent_at)) = self.ping_sent {
+                    if id == ping.ping_id.ping_id {
+                        self.ping = Some(now.duration_since(sent_at));
+                        self.ping_sent = None;
+                    }
+                }
+                Ok(None)
+            }
+            _ => Ok(Some(packet)),
+        }
+    }
+
+    /// Datagrams due now: replies, resends, PacketAck batches and ping checks
+    ///
+    /// Fails and closes the circuit once a reliable packet has been resent `max_resends` times.
+    pub fn poll(&mut self, now: Instant) -> Result<Vec<Vec<u8>>> {
+        if self.closed {
+            return Err(anyhow::anyhow!("Circuit {} is closed", self.code));
+        }
+        let mut datagrams = std::mem::take(&mut self.outbox);
+
+        for index in 0..self.packets_pending.len() {
+            let pending = &self.packets_pending[index];
+            if now.duration_since(pending.timestamp) < self.rtt.backoff(pending.resend_count + 1) {
+                continue;
+            }
+            if pending.resend_count >= self.config.max_resends {
+                warn!(
+                    "Closing circuit {}: packet {} unacknowledged after {} resends",
+                    self.code, pending.sequence, pending.resend_count
+                );
+                self.closed = true;
+                return Err(anyhow::anyhow!("Circuit {} timed out waiting for acks", self.code));
+            }
+
+            let pending = &mut self.packets_pending[index];
+            pending.data[0] |= FLAG_RESENT;
+            pending.timestamp = now;
+            pending.resend_count += 1;
+            debug!("Resending packet {} on circuit {}", pending.sequence, self.code);
+            datagrams.push(pending.data.clone());
+            self.stats.resends += 1;
+        }
+
+        if let Some(since) = self.acks_owed_since {
+            if now.duration_since(since) >= self.config.ack_delay {
+                let acks = std::mem::take(&mut self.acks_pending);
+                self.acks_owed_since = None;
+                for batch in acks.chunks(ACKS_PER_PACKET_ACK) {
+                    let ack = PacketAck {
+                        packets: batch.iter().map(|id| PacketAckPackets { id: *id }).collect(),
+                    };
+                    datagrams.push(self.send_message(&ack, false, now)?);
+                    self.stats.acks_sent += 1;
+                }
+            }
+        }
+
+        // The first ping goes out one interval after the circuit is first polled
+        let last_ping = *self.last_ping.get_or_insert(now);
+        if now.duration_since(last_ping) >= self.config.ping_interval {
+            let ping_id = self.next_ping_id;
+            self.next_ping_id = self.next_ping_id.wrapping_add(1);
+            let ping = StartPingCheck {
+                ping_id: StartPingCheckPingID {
+                    ping_id,
+                    oldest_unacked: self.packets_pending.iter().map(|p| p.sequence).min().unwrap_or_default(),
+                },
+            };
+            datagrams.push(self.send_message(&ping, false, now)?);
+            self.ping_sent = Some((ping_id, now));
+            self.last_ping = Some(now);
+        }
+
+        Ok(datagrams)
+    }
+
+    fn acknowledged(&mut self, sequence: u32, now: Instant) {
+        let Some(index) = self.packets_pending.iter().position(|p| p.sequence == sequence) else {
+            return;
+        };
+        let pending = self.packets_pending.remove(index);
+        // Karn's rule: an ack for a resent packet could belong to any transmission
+        if pending.resend_count == 0 {
+            self.rtt.sample(now.duration_since(pending.timestamp));
+        }
+    }
+
+    /// Record a received sequence, returning false if it was seen recently
+    fn remember(&mut self, sequence: u32) -> bool {
+        if !self.received.insert(sequence) {
+            return false;
+        }
+        self.received_order.push_back(sequence);
+        while self.received_order.len() > self.config.duplicate_window {
+            if let Some(oldest) = self.received_order.pop_front() {
+                self.received.remove(&oldest);
+            }
+        }
+        true
+    }
 }
@@ -3,18 +3,45 @@
 // Provides full compatibility with OpenSim while adding AI-driven features
 
 use std::collections::HashMap;
-use std::net::{SocketAddr, Ipv4Addr};
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use tokio::sync::{RwLock, Mutex};
+use tokio::task::JoinHandle;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use anyhow::Result;
 
-use storm_networking::{NetworkManager, ConnectionId, ProtocolType};
+use storm_networking::{NetworkManager, ConnectionId, PacketPriority, ProtocolType};
+use storm_protocol_adapters::{RegionLink, SimulatorEvent};
 use storm_ecs::{World, Entity, Component, Transform};
 use storm_ai::{AIDispatcher, AIRequest, TaskType, AITier};
+use storm_assets::AssetManager;
 use crate::messages::*;
 use crate::circuit::*;
+use crate::caps::{Capabilities, CapsClient, CapsConfig, CapsSession, RegionEvents};
+use crate::inventory::Inventory;
+use crate::login::LoginResponse;
+use crate::template::RequestImage;
+use crate::texture::{TextureEvent, TextureFetcher, TexturePipeline, TextureRequest};
+
+/// How often circuits are polled for owed acks, resends and ping checks
+const CIRCUIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Edge length of regions whose size the simulator has not told us
+const DEFAULT_REGION_SIZE: u32 = 256;
+
+/// How often pending textures are fetched over HTTP and re-requested over UDP
+const TEXTURE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// HTTP texture fetches made per poll, so image packets are not held up for long
+const TEXTURE_FETCHES_PER_POLL: usize = 4;
+
+/// Textures asked for in one RequestImage
+const TEXTURE_REQUESTS_PER_PACKET: usize = 16;
+
+const TEXTURE_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Enhanced OpenSim adapter with AI capabilities
 pub struct EnhancedOpenSimAdapter {
@@ -33,6 +60,23 @@ pub struct EnhancedOpenSimAdapter {
     /// Message handlers with AI enhancement
     message_handlers: HashMap<LLUDPMessageType, Box<dyn EnhancedMessageHandler>>,
 
+    /// Texture fetches and image messages, decoded into the shared asset cache
+    textures: EnhancedImageRequestHandler,
+
+    /// Inventory mirrors, also registered for the inventory messages
+    inventory: Arc<EnhancedInventoryHandler>,
+
+    /// Where each agent's inventory is cached between sessions; None keeps it in memory only
+    inventory_cache_dir: Option<PathBuf>,
+
+    caps_client: CapsClient,
+
+    /// Capabilities of every region each connection's agent is present in, by region handle
+    region_caps: Arc<RwLock<HashMap<ConnectionId, HashMap<u64, Capabilities>>>>,
+
+    /// Event queue sessions following each connection's agent between regions
+    caps_sessions: Mutex<HashMap<ConnectionId, JoinHandle<Result<()>>>>,
+
     /// Circuit state management
     circuits: Arc<RwLock<HashMap<u32, Circuit>>>,
 
@@ -53,6 +97,10 @@ pub struct OpenSimConnection {
     pub agent_id: Option<Uuid>,
     pub secure_session_id: Option<Uuid>,
     pub region_id: Option<Uuid>,
+    /// Region the agent is in, from login, AgentMovementComplete, TeleportFinish or CrossedRegion
+    pub region_handle: Option<u64>,
+    /// Neighbouring regions announced by EnableSimulator
+    pub neighbours: Vec<RegionLink>,
     pub sequence_number: u32,
     pub last_ack: u32,
     pub connection_state: ConnectionState,
@@ -134,6 +182,7 @@ impl EnhancedOpenSimAdapter {
         network_manager: Arc<NetworkManager>,
         ecs_world: Arc<RwLock<World>>,
         ai_dispatcher: Arc<AIDispatcher>,
+        asset_manager: Arc<AssetManager>,
     ) -> Result<Self> {
         let mut message_handlers: HashMap<LLUDPMessageType, Box<dyn EnhancedMessageHandler>> = HashMap::new();
 
@@ -141,9 +190,33 @@ impl EnhancedOpenSimAdapter {
         message_handlers.insert(LLUDPMessageType::UseCircuitCode, Box::new(EnhancedUseCircuitCodeHandler));
         message_handlers.insert(LLUDPMessageType::CompleteAgentMovement, Box::new(EnhancedCompleteAgentMovementHandler));
         message_handlers.insert(LLUDPMessageType::AgentUpdate, Box::new(EnhancedAgentUpdateHandler));
-        message_handlers.insert(LLUDPMessageType::ObjectUpdate, Box::new(EnhancedObjectUpdateHandler));
+        // One handler owns the region's object mirror for the whole ObjectUpdate family
+        let object_handler = Arc::new(EnhancedObjectUpdateHandler::default());
+        for message_type in [
+            LLUDPMessageType::ObjectUpdate,
+            LLUDPMessageType::ObjectUpdateCompressed,
+            LLUDPMessageType::ObjectUpdateCached,
+            LLUDPMessageType::ImprovedTerseObjectUpdate,
+            LLUDPMessageType::KillObject,
+            LLUDPMessageType::AvatarAppearance,
+        ] {
+            message_handlers.insert(message_type, Box::new(object_handler.clone()));
+        }
         message_handlers.insert(LLUDPMessageType::ChatFromViewer, Box::new(EnhancedChatHandler));
-        message_handlers.insert(LLUDPMessageType::RequestImage, Box::new(EnhancedImageRequestHandler));
+        let inventory_handler = Arc::new(EnhancedInventoryHandler::default());
+        for message_type in [
+            LLUDPMessageType::InventoryDescendents,
+            LLUDPMessageType::FetchInventoryReply,
+            LLUDPMessageType::UpdateCreateInventoryItem,
+            LLUDPMessageType::BulkUpdateInventory,
+        ] {
+            message_handlers.insert(message_type, Box::new(inventory_handler.clone()));
+        }
+        // Terrain is kept per region; arriving in another region evicts the last one's
+        let terrain_handler = Arc::new(EnhancedTerrainHandler::default());
+        for message_type in [LLUDPMessageType::LayerData, LLUDPMessageType::AgentMovementComplete] {
+            message_handlers.insert(message_type, Box::new(terrain_handler.clone()));
+        }
 
         let ai_features = AIFeatures {
             smart_pathfinding: true,
@@ -160,6 +233,12 @@ impl EnhancedOpenSimAdapter {
             ai_dispatcher,
             connections: Arc::new(RwLock::new(HashMap::new())),
             message_handlers,
+            textures: EnhancedImageRequestHandler::new(asset_manager)?,
+            inventory: inventory_handler,
+            inventory_cache_dir: None,
+            caps_client: CapsClient::new(CapsConfig::default())?,
+            region_caps: Arc::new(RwLock::new(HashMap::new())),
+            caps_sessions: Mutex::new(HashMap::new()),
             circuits: Arc::new(RwLock::new(HashMap::new())),
             ai_features,
             opensim_state: OpenSimState {
@@ -172,18 +251,21 @@ impl EnhancedOpenSimAdapter {
         })
     }
 
+    /// Keep each agent's inventory in `dir` between sessions, so only changed folders are refetched
+    pub fn with_inventory_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.inventory_cache_dir = Some(dir.into());
+        self
+    }
+
     /// Connect to OpenSim grid with AI enhancements
-    pub async fn connect_to_grid(&self, grid_url: &str, login_params: LoginParams) -> Result<ConnectionId> {
+    pub async fn connect_to_grid(self: &Arc<Self>, grid_url: &str, login_params: LoginParams) -> Result<ConnectionId> {
         tracing::info!("Connecting to OpenSim grid: {}", grid_url);
 
         // Step 1: Login to grid login service
         let login_response = self.perform_login(grid_url, &login_params).await?;
 
         // Step 2: Connect to region simulator
-        let sim_addr = SocketAddr::new(
-            login_response.sim_ip.into(),
-            login_response.sim_port,
-        );
+        let sim_addr = login_response.sim_address();
 
         let connection_id = self.network_manager
             .connect(sim_addr, ProtocolType::LLUDP)
@@ -206,7 +288,10 @@ impl EnhancedOpenSimAdapter {
             session_id: Some(login_response.session_id),
             agent_id: Some(login_response.agent_id),
             secure_session_id: Some(login_response.secure_session_id),
-            region_id: Some(login_response.region_id),
+            // The login service does not name the region; it is learned from RegionHandshake
+            region_id: None,
+            region_handle: Some(login_response.region_handle()),
+            neighbours: Vec::new(),
             sequence_number: 1,
             last_ack: 0,
             connection_state: ConnectionState::Connecting,
@@ -221,6 +306,21 @@ impl EnhancedOpenSimAdapter {
             connections.insert(connection_id, connection);
         }
 
+        // The login skeleton names every folder; the cache fills in contents that are still current
+        let cache_path = self.inventory_cache_path(login_response.agent_id);
+        self.inventory.start(&login_response, cache_path.as_deref())?;
+
+        // Capabilities and the event queue follow the agent through teleports and crossings
+        let mut caps = CapsSession::new(self.caps_client.clone());
+        match caps.connect_login(&login_response).await {
+            Ok(capabilities) => {
+                self.region_connected(connection_id, login_response.region_handle(), capabilities).await;
+                let session = tokio::spawn(caps.run(self.clone(), connection_id));
+                self.caps_sessions.lock().await.insert(connection_id, session);
+            }
+            Err(e) => tracing::warn!("Could not resolve seed capability, textures will come over UDP: {:#}", e),
+        }
+
         // Step 5: Send UseCircuitCode message
         self.send_use_circuit_code(connection_id, &login_response).await?;
 
@@ -234,8 +334,23 @@ impl EnhancedOpenSimAdapter {
     }
 
     /// Process incoming LLUDP packets with AI enhancement
+    ///
+    /// The datagram goes through the connection's circuit first, which acks it, drops
+    /// duplicates and answers ping checks.
     pub async fn process_packet(&self, connection_id: ConnectionId, data: Vec<u8>) -> Result<()> {
-        let packet = self.parse_lludp_packet(&data)?;
+        let circuit_code = self.circuit_code(connection_id).await?;
+        let now = Instant::now();
+        let (packet, datagrams) = {
+            let mut circuits = self.circuits.write().await;
+            let circuit = circuits
+                .get_mut(&circuit_code)
+                .ok_or_else(|| anyhow::anyhow!("No circuit {} for connection {}", circuit_code, connection_id))?;
+            (circuit.receive(&data, now)?, circuit.poll(now)?)
+        };
+        self.send_datagrams(connection_id, datagrams).await;
+        let Some(packet) = packet else {
+            return Ok(());
+        };
 
         let mut connection = {
             let mut connections = self.connections.write().await;
@@ -249,8 +364,13 @@ impl EnhancedOpenSimAdapter {
             self.ai_analyze_packet(&packet, &connection).await?;
         }
 
-        // Route to appropriate handler
-        if let Some(handler) = self.message_handlers.get(&packet.message_type) {
+        // Route to appropriate handler; image messages are decoded off the packet path
+        if matches!(
+            packet.message_type,
+            LLUDPMessageType::ImageData | LLUDPMessageType::ImagePacket | LLUDPMessageType::ImageNotInDatabase
+        ) {
+            self.textures.handle_packet(&packet).await?;
+        } else if let Some(handler) = self.message_handlers.get(&packet.message_type) {
             let mut world = self.ecs_world.write().await;
             let response_packets = handler.handle_message(
                 &packet,
@@ -261,7 +381,7 @@ impl EnhancedOpenSimAdapter {
 
             // Send response packets
             for response in response_packets {
-                self.send_packet(connection_id, response).await?;
+                self.send_packet(connection_id, response, true).await?;
             }
         }
 
@@ -380,21 +500,14 @@ impl EnhancedOpenSimAdapter {
 
     // Private helper methods
     async fn perform_login(&self, grid_url: &str, params: &LoginParams) -> Result<LoginResponse> {
-        // Implementation of OpenSim XMLRPC login process
-        // This would make HTTP requests to the grid's login service
-        // and parse the response to get connection details
-
-        // Placeholder implementation
-        Ok(LoginResponse {
-            session_id: Uuid::new_v4(),
-            secure_session_id: Uuid::new_v4(),
-            agent_id: Uuid::new_v4(),
-            region_id: Uuid::new_v4(),
-            circuit_code: rand::random::<u32>(),
-            sim_ip: Ipv4Addr::new(127, 0, 0, 1),
-            sim_port: 9000,
-            seed_capability: "http://localhost:9000/CAPS/seed".to_string(),
-        })
+        let (first, last) = params
+            .username
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("Username '{}' is not 'First Last'", params.username))?;
+        let mut grid_params = crate::login::LoginParams::new(first, last.trim(), params.password.as_str());
+        grid_params.start = params.start_location.parse()?;
+
+        crate::login::login_to_grid(grid_url, &grid_params).await
     }
 
     async fn initialize_ai_features(&self, connection_id: ConnectionId) -> Result<()> {
@@ -415,13 +528,258 @@ impl EnhancedOpenSimAdapter {
 
     async fn send_use_circuit_code(&self, connection_id: ConnectionId, login_response: &LoginResponse) -> Result<()> {
         // Send UseCircuitCode message to establish circuit
-        let packet = self.create_use_circuit_code_packet(login_response)?;
-        self.send_packet(connection_id, packet).await
+        let use_circuit_code = UseCircuitCode {
+            code: login_response.circuit_code,
+            session_id: login_response.session_id,
+            agent_id: login_response.agent_id,
+        };
+        self.send_packet(connection_id, use_circuit_code.to_packet(), true).await
+    }
+
+    /// Sequence the packet on the connection's circuit and send it; reliable packets are
+    /// resent from `poll_circuits` until acked
+    async fn send_packet(&self, connection_id: ConnectionId, packet: LLUDPPacket, reliable: bool) -> Result<()> {
+        let circuit_code = self.circuit_code(connection_id).await?;
+        let data = {
+            let mut circuits = self.circuits.write().await;
+            let circuit = circuits
+                .get_mut(&circuit_code)
+                .ok_or_else(|| anyhow::anyhow!("No circuit {} for connection {}", circuit_code, connection_id))?;
+            circuit.send(packet, reliable, Instant::now())?
+        };
+        self.network_manager.send_packet(connection_id, data, PacketPriority::Normal).await
+    }
+
+    /// Poll every circuit for owed acks, resends and ping checks, dropping circuits that timed out
+    pub async fn poll_circuits(&self) {
+        let now = Instant::now();
+        let connections: Vec<(ConnectionId, u32)> = self
+            .connections
+            .read()
+            .await
+            .values()
+            .map(|connection| (connection.id, connection.circuit_code))
+            .collect();
+
+        for (connection_id, circuit_code) in connections {
+            let polled = match self.circuits.write().await.get_mut(&circuit_code) {
+                Some(circuit) => circuit.poll(now),
+                None => continue,
+            };
+            match polled {
+                Ok(datagrams) => self.send_datagrams(connection_id, datagrams).await,
+                Err(e) => {
+                    tracing::warn!("Dropping circuit {} of connection {}: {}", circuit_code, connection_id, e);
+                    self.end_session(connection_id).await;
+                }
+            }
+        }
+    }
+
+    /// Poll circuits every `CIRCUIT_POLL_INTERVAL` until the adapter is dropped
+    pub fn spawn_circuit_timer(self: &Arc<Self>) -> JoinHandle<()> {
+        let adapter: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CIRCUIT_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(adapter) = adapter.upgrade() else {
+                    break;
+                };
+                adapter.poll_circuits().await;
+            }
+        })
+    }
+
+    /// Log the agent out and close the connection, caching its inventory first
+    pub async fn disconnect(&self, connection_id: ConnectionId) -> Result<()> {
+        let (agent_id, session_id) = {
+            let mut connections = self.connections.write().await;
+            let connection = connections
+                .get_mut(&connection_id)
+                .ok_or_else(|| anyhow::anyhow!("Connection not found"))?;
+            connection.connection_state = ConnectionState::Disconnecting;
+            (connection.agent_id, connection.session_id)
+        };
+        if let (Some(agent_id), Some(session_id)) = (agent_id, session_id) {
+            let logout = LogoutRequest { agent_id, session_id };
+            if let Err(e) = self.send_packet(connection_id, logout.to_packet(), false).await {
+                tracing::debug!("Failed to send LogoutRequest on connection {}: {}", connection_id, e);
+            }
+        }
+        self.end_session(connection_id).await;
+        self.network_manager.close_connection(connection_id).await;
+        Ok(())
+    }
+
+    /// Drop a connection's circuit and capabilities and save its agent's inventory
+    async fn end_session(&self, connection_id: ConnectionId) {
+        let (circuit_code, agent_id) = {
+            let mut connections = self.connections.write().await;
+            let Some(connection) = connections.get_mut(&connection_id) else {
+                return;
+            };
+            connection.connection_state = ConnectionState::Disconnected;
+            (connection.circuit_code, connection.agent_id)
+        };
+        self.circuits.write().await.remove(&circuit_code);
+        if let Some(session) = self.caps_sessions.lock().await.remove(&connection_id) {
+            session.abort();
+        }
+        self.region_caps.write().await.remove(&connection_id);
+        if let Some(agent_id) = agent_id {
+            let cache_path = self.inventory_cache_path(agent_id);
+            if let Err(e) = self.inventory.finish(agent_id, cache_path.as_deref()) {
+                tracing::warn!("Could not cache inventory of agent {}: {:#}", agent_id, e);
+            }
+        }
+    }
+
+    fn inventory_cache_path(&self, agent_id: Uuid) -> Option<PathBuf> {
+        self.inventory_cache_dir.as_ref().map(|dir| dir.join(format!("{}.inventory.json", agent_id)))
+    }
+
+    /// Ask for a texture, or update its importance and on-screen size as the view changes
+    pub async fn request_texture(&self, request: TextureRequest) {
+        self.textures.pipeline.lock().await.request(request).await;
+    }
+
+    /// Stop refining a texture that left the view
+    pub async fn cancel_texture(&self, id: Uuid) {
+        self.textures.pipeline.lock().await.cancel(id);
+    }
+
+    /// Fetch pending textures over HTTP and send RequestImage for those that have to come over UDP
+    pub async fn poll_textures(&self) {
+        let connections: Vec<OpenSimConnection> = self.connections.read().await.values().cloned().collect();
+        for connection in connections {
+            let capabilities = match connection.region_handle {
+                Some(region_handle) => self
+                    .region_caps
+                    .read()
+                    .await
+                    .get(&connection.id)
+                    .and_then(|regions| regions.get(&region_handle))
+                    .cloned(),
+                None => None,
+            };
+            let request = match self.textures.poll(&connection, capabilities.as_ref()).await {
+                Ok(request) => request,
+                Err(e) => {
+                    tracing::warn!("Texture fetch for connection {} failed: {:#}", connection.id, e);
+                    continue;
+                }
+            };
+            if let Some(request) = request {
+                if let Err(e) = self.send_packet(connection.id, request.to_packet(), false).await {
+                    tracing::debug!("Failed to send RequestImage on connection {}: {}", connection.id, e);
+                }
+            }
+        }
+    }
+
+    /// Poll textures every `TEXTURE_POLL_INTERVAL` until the adapter is dropped
+    ///
+    /// Runs beside the circuit timer so slow HTTP fetches never delay acks and resends.
+    pub fn spawn_texture_timer(self: &Arc<Self>) -> JoinHandle<()> {
+        let adapter: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TEXTURE_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(adapter) = adapter.upgrade() else {
+                    break;
+                };
+                adapter.poll_textures().await;
+            }
+        })
+    }
+
+    async fn circuit_code(&self, connection_id: ConnectionId) -> Result<u32> {
+        self.connections
+            .read()
+            .await
+            .get(&connection_id)
+            .map(|connection| connection.circuit_code)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found"))
+    }
+
+    async fn send_datagrams(&self, connection_id: ConnectionId, datagrams: Vec<Vec<u8>>) {
+        for data in datagrams {
+            if let Err(e) = self.network_manager.send_packet(connection_id, data, PacketPriority::High).await {
+                tracing::debug!("Failed to send on circuit of connection {}: {}", connection_id, e);
+            }
+        }
+    }
+}
+
+/// Region changes reported by the connection's `CapsSession`
+impl RegionEvents for EnhancedOpenSimAdapter {
+    async fn dispatch_event(&self, connection_id: ConnectionId, event: SimulatorEvent) -> Result<()> {
+        let mut connections = self.connections.write().await;
+        let connection = connections
+            .get_mut(&connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found"))?;
+        match event {
+            SimulatorEvent::EnableSimulator { region_handle, sim_addr } => {
+                let mut region = RegionLink::from_handle(RegionLink::id_for_handle(region_handle), "", region_handle);
+                region.sim_addr = Some(sim_addr);
+                connection.neighbours.retain(|neighbour| neighbour.handle() != region_handle);
+                connection.neighbours.push(region);
+            }
+            SimulatorEvent::EstablishAgentCommunication { sim_addr, seed_capability } => {
+                match connection.neighbours.iter_mut().find(|neighbour| neighbour.sim_addr == Some(sim_addr)) {
+                    Some(region) => region.seed_capability = Some(seed_capability).filter(|seed| !seed.is_empty()),
+                    None => tracing::warn!("EstablishAgentCommunication for unknown simulator {}", sim_addr),
+                }
+            }
+            SimulatorEvent::TeleportFinish { region_handle, sim_addr, .. } => {
+                tracing::info!("Teleported to region {} at {}", region_handle, sim_addr);
+                // Child agents do not survive a teleport
+                connection.neighbours.clear();
+                connection.region_handle = Some(region_handle);
+                connection.region_id = None;
+                connection.remote_addr = sim_addr;
+            }
+            SimulatorEvent::CrossedRegion { region_handle, sim_addr, .. } => {
+                tracing::info!("Crossed into region {} at {}", region_handle, sim_addr);
+                // The region left behind stays visible as a neighbour
+                let left = connection.region_handle.replace(region_handle);
+                let left_addr = std::mem::replace(&mut connection.remote_addr, sim_addr);
+                connection.neighbours.retain(|neighbour| neighbour.handle() != region_handle);
+                if let Some(left) = left.filter(|left| *left != region_handle) {
+                    let mut region = RegionLink::from_handle(RegionLink::id_for_handle(left), "", left);
+                    region.sim_addr = Some(left_addr);
+                    connection.neighbours.push(region);
+                }
+                connection.region_id = None;
+            }
+        }
+        Ok(())
+    }
+
+    async fn neighbours(&self, connection_id: ConnectionId) -> Result<Vec<RegionLink>> {
+        self.connections
+            .read()
+            .await
+            .get(&connection_id)
+            .map(|connection| connection.neighbours.clone())
+            .ok_or_else(|| anyhow::anyhow!("Connection not found"))
     }
 
-    async fn send_packet(&self, connection_id: ConnectionId, packet: LLUDPPacket) -> Result<()> {
-        let data = self.serialize_packet(&packet)?;
-        self.network_manager.send_packet(connection_id, data, crate::networking::PacketPriority::Normal).await
+    async fn region_connected(&self, connection_id: ConnectionId, region_handle: u64, capabilities: &Capabilities) {
+        self.region_caps
+            .write()
+            .await
+            .entry(connection_id)
+            .or_default()
+            .insert(region_handle, capabilities.clone());
+    }
+
+    async fn region_disconnected(&self, connection_id: ConnectionId, region_handle: u64) {
+        if let Some(regions) = self.region_caps.write().await.get_mut(&connection_id) {
+            regions.remove(&region_handle);
+        }
     }
 }
 
@@ -522,9 +880,14 @@ impl EnhancedMessageHandler for EnhancedAgentUpdateHandler {
     }
 }
 
-struct EnhancedObjectUpdateHandler;
+#[derive(Default)]
+struct EnhancedObjectUpdateHandler {
+    objects: std::sync::Mutex<crate::objects::RegionObjects>,
+    /// Avatar appearances wait here for the avatar's ObjectUpdate
+    appearances: std::sync::Mutex<crate::appearance::AvatarAppearances>,
+}
 
-impl EnhancedMessageHandler for EnhancedObjectUpdateHandler {
+impl EnhancedMessageHandler for Arc<EnhancedObjectUpdateHandler> {
     fn handle_message(
         &self,
         packet: &LLUDPPacket,
@@ -532,13 +895,26 @@ impl EnhancedMessageHandler for EnhancedObjectUpdateHandler {
         world: &mut World,
         ai_dispatcher: &AIDispatcher,
     ) -> Result<Vec<LLUDPPacket>> {
-        // Parse object data and update ECS
-
-        // AI enhancement: intelligent object culling and LOD
-        // Analyze which objects user is likely to interact with
-        // Optimize rendering based on user attention patterns
+        let objects = &mut *self.objects.lock().map_err(|_| anyhow::anyhow!("Object mirror lock poisoned"))?;
+        let changes = objects.handle_packet(world, packet)?;
+        let updated = self
+            .appearances
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Avatar appearance lock poisoned"))?
+            .handle_packet(world, objects, packet)?;
+        if !updated.is_empty() {
+            tracing::debug!("Updated appearance of {} avatars", updated.len());
+        }
 
-        Ok(vec![])
+        // Objects the cache could not supply are fetched in full
+        let (Some(agent_id), Some(session_id)) = (connection.agent_id, connection.session_id) else {
+            return Ok(vec![]);
+        };
+        if changes.cache_misses.is_empty() {
+            return Ok(vec![]);
+        }
+        let request = crate::objects::request_cache_misses(agent_id, session_id, &changes.cache_misses);
+        Ok(vec![request.to_packet()])
     }
 
     fn get_ai_enhancement_level(&self) -> AIEnhancementLevel {
@@ -579,9 +955,43 @@ impl EnhancedMessageHandler for EnhancedChatHandler {
     }
 }
 
-struct EnhancedImageRequestHandler;
+/// Inventory mirrors of the agents on this adapter's circuits
+#[derive(Default)]
+struct EnhancedInventoryHandler {
+    inventories: std::sync::Mutex<HashMap<Uuid, Inventory>>,
+}
 
-impl EnhancedMessageHandler for EnhancedImageRequestHandler {
+impl EnhancedInventoryHandler {
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<Uuid, Inventory>>> {
+        self.inventories.lock().map_err(|_| anyhow::anyhow!("Inventory lock poisoned"))
+    }
+
+    /// Seed an agent's inventory from its login skeleton and restore cached folder contents
+    fn start(&self, login: &LoginResponse, cache_path: Option<&Path>) -> Result<()> {
+        let mut inventory = Inventory::from_login(login);
+        if let Some(path) = cache_path.filter(|path| path.exists()) {
+            // A stale or corrupt cache only costs refetching
+            if let Err(e) = inventory.load_cache(path) {
+                tracing::warn!("Ignoring inventory cache {}: {:#}", path.display(), e);
+            }
+        }
+        self.lock()?.insert(login.agent_id, inventory);
+        Ok(())
+    }
+
+    /// Forget an agent's inventory, writing it to the cache first
+    fn finish(&self, agent_id: Uuid, cache_path: Option<&Path>) -> Result<()> {
+        let Some(inventory) = self.lock()?.remove(&agent_id) else {
+            return Ok(());
+        };
+        match cache_path {
+            Some(path) => inventory.save_cache(path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl EnhancedMessageHandler for Arc<EnhancedInventoryHandler> {
     fn handle_message(
         &self,
         packet: &LLUDPPacket,
@@ -589,13 +999,132 @@ impl EnhancedMessageHandler for EnhancedImageRequestHandler {
         world: &mut World,
         ai_dispatcher: &AIDispatcher,
     ) -> Result<Vec<LLUDPPacket>> {
-        // Handle texture/image requests
+        let Some(agent_id) = connection.agent_id else {
+            return Ok(vec![]);
+        };
+        let mut inventories = self.lock()?;
+        let Some(inventory) = inventories.get_mut(&agent_id) else {
+            tracing::debug!("Inventory message for agent {} outside a session", agent_id);
+            return Ok(vec![]);
+        };
+        let changes = inventory.handle_packet(packet)?;
+
+        // Subfolders announced by a listing are fetched in turn
+        let unfetched: Vec<Uuid> = changes
+            .folders
+            .iter()
+            .flat_map(|folder| inventory.tree().subfolders(*folder))
+            .filter(|folder| !folder.fetched)
+            .map(|folder| folder.id)
+            .collect();
+        Ok(unfetched.into_iter().map(|folder| inventory.fetch_folder_message(folder).to_packet()).collect())
+    }
 
-        // AI enhancements:
-        // 1. Predictive texture loading
-        // 2. Dynamic quality adjustment
-        // 3. AI upscaling for low-res textures
-        // 4. Intelligent caching
+    fn get_ai_enhancement_level(&self) -> AIEnhancementLevel {
+        AIEnhancementLevel::Standard
+    }
+}
+
+/// Textures wanted by this adapter's agents, fetched over HTTP or RequestImage
+///
+/// Decoding is async, so image messages reach it from `process_packet` rather than through
+/// `EnhancedMessageHandler`.
+struct EnhancedImageRequestHandler {
+    pipeline: Mutex<TexturePipeline>,
+}
+
+impl EnhancedImageRequestHandler {
+    fn new(asset_manager: Arc<AssetManager>) -> Result<Self> {
+        Ok(Self {
+            pipeline: Mutex::new(TexturePipeline::new(asset_manager, TEXTURE_FETCH_TIMEOUT)?),
+        })
+    }
+
+    /// Feed an ImageData, ImagePacket or ImageNotInDatabase message
+    async fn handle_packet(&self, packet: &LLUDPPacket) -> Result<()> {
+        let event = self.pipeline.lock().await.handle_packet(packet).await?;
+        log_texture_event(event);
+        Ok(())
+    }
+
+    /// Fetch the most important textures over HTTP, then build the RequestImage for the rest
+    async fn poll(&self, connection: &OpenSimConnection, capabilities: Option<&Capabilities>) -> Result<Option<RequestImage>> {
+        let mut pipeline = self.pipeline.lock().await;
+        let http_available = capabilities.and_then(TextureFetcher::capability).is_some();
+        if let Some(capabilities) = capabilities.filter(|_| http_available) {
+            for _ in 0..TEXTURE_FETCHES_PER_POLL {
+                // None once nothing is left for HTTP or a fetch fell back to UDP
+                let Some(event) = pipeline.fetch_next(capabilities).await? else {
+                    break;
+                };
+                log_texture_event(Some(event));
+            }
+        }
+
+        let (Some(agent_id), Some(session_id)) = (connection.agent_id, connection.session_id) else {
+            return Ok(None);
+        };
+        Ok(pipeline.udp_requests(agent_id, session_id, http_available, TEXTURE_REQUESTS_PER_PACKET))
+    }
+}
+
+fn log_texture_event(event: Option<TextureEvent>) {
+    match event {
+        Some(TextureEvent::Decoded { id, discard_level, width, height, source }) => {
+            tracing::debug!("Texture {} cached at discard {} ({}x{}) via {:?}", id, discard_level, width, height, source);
+        }
+        Some(TextureEvent::Missing(id)) => tracing::warn!("Texture {} is not in the asset database", id),
+        None => {}
+    }
+}
+
+/// Heightfields, wind and clouds of the regions the agent is in
+#[derive(Default)]
+struct EnhancedTerrainHandler {
+    terrains: std::sync::Mutex<HashMap<u64, crate::terrain::RegionTerrain>>,
+}
+
+impl EnhancedMessageHandler for Arc<EnhancedTerrainHandler> {
+    fn handle_message(
+        &self,
+        packet: &LLUDPPacket,
+        connection: &mut OpenSimConnection,
+        world: &mut World,
+        ai_dispatcher: &AIDispatcher,
+    ) -> Result<Vec<LLUDPPacket>> {
+        let mut terrains = self
+            .terrains
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Terrain lock poisoned"))?;
+
+        match packet.message_type {
+            LLUDPMessageType::AgentMovementComplete => {
+                let region_handle = AgentMovementComplete::from_payload(&packet.payload)?.region_handle;
+                let previous = connection.region_handle.replace(region_handle);
+                if let Some(previous) = previous.filter(|previous| *previous != region_handle) {
+                    if let Some(mut terrain) = terrains.remove(&previous) {
+                        tracing::debug!("Evicting terrain of region {}", previous);
+                        terrain.clear(world);
+                    }
+                }
+            }
+            LLUDPMessageType::LayerData => {
+                let Some(region_handle) = connection.region_handle else {
+                    tracing::debug!("LayerData before AgentMovementComplete on connection {}", connection.id);
+                    return Ok(vec![]);
+                };
+                let changes = terrains
+                    .entry(region_handle)
+                    .or_insert_with(|| {
+                        crate::terrain::RegionTerrain::new(region_handle, DEFAULT_REGION_SIZE, DEFAULT_REGION_SIZE)
+                    })
+                    .handle_packet(world, packet)?;
+                if !changes.land.is_empty() {
+                    tracing::debug!("Updated {} terrain patches in region {}", changes.land.len(), region_handle);
+                }
+            }
+            _ => {}
+        }
 
         Ok(vec![])
     }
@@ -616,18 +1145,6 @@ pub struct LoginParams {
     pub ai_enhancement_level: AIEnhancementLevel,
 }
 
-#[derive(Debug, Clone)]
-pub struct LoginResponse {
-    pub session_id: Uuid,
-    pub secure_session_id: Uuid,
-    pub agent_id: Uuid,
-    pub region_id: Uuid,
-    pub circuit_code: u32,
-    pub sim_ip: Ipv4Addr,
-    pub sim_port: u16,
-    pub seed_capability: String,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AIEnhancementLevel {
     None,
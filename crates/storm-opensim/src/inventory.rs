@@ -0,0 +1,960 @@
+// File: crates/storm-opensim/src/inventory.rs
+// Agent inventory: folder tree from the login skeleton, fetches over capabilities or LLUDP, edits and a disk cache
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::caps::{Capabilities, CapsClient};
+use crate::llsd::Llsd;
+use crate::login::{InventoryFolderSkeleton, LoginResponse};
+use crate::messages::{InventoryItem, InventoryPermissions, LLUDPMessageType, LLUDPPacket, MessageBody};
+use crate::serialization::{null_terminated, trim_null};
+use crate::template::{
+    BulkUpdateInventory, CreateInventoryFolder, CreateInventoryFolderAgentData, CreateInventoryFolderFolderData,
+    CreateInventoryItem, CreateInventoryItemAgentData, CreateInventoryItemInventoryBlock, FetchInventory,
+    FetchInventoryAgentData, FetchInventoryDescendents, FetchInventoryDescendentsAgentData,
+    FetchInventoryDescendentsInventoryData, FetchInventoryInventoryData, FetchInventoryReply, ImprovedInstantMessage,
+    ImprovedInstantMessageAgentData, ImprovedInstantMessageMessageBlock, InventoryDescendents, MoveInventoryFolder,
+    MoveInventoryFolderAgentData, MoveInventoryFolderInventoryData, MoveInventoryItem, MoveInventoryItemAgentData,
+    MoveInventoryItemInventoryData, RemoveInventoryFolder, RemoveInventoryFolderAgentData,
+    RemoveInventoryFolderFolderData, RemoveInventoryItem, RemoveInventoryItemAgentData, RemoveInventoryItemInventoryData,
+    RezObject, RezObjectAgentData, RezObjectInventoryData, RezObjectRezData, UpdateCreateInventoryItem,
+    UpdateInventoryFolder, UpdateInventoryFolderAgentData, UpdateInventoryFolderFolderData, UpdateInventoryItem,
+    UpdateInventoryItemAgentData, UpdateInventoryItemInventoryData,
+};
+
+/// Capability listing a folder's contents
+pub const FETCH_DESCENDENTS_CAP: &str = "FetchInventoryDescendents2";
+/// Capability returning items by id
+pub const FETCH_ITEMS_CAP: &str = "FetchInventory2";
+
+/// Asset types of items and of the system folders that collect them
+pub mod asset_type {
+    pub const NONE: i8 = -1;
+    pub const TEXTURE: i8 = 0;
+    pub const SOUND: i8 = 1;
+    pub const CALLING_CARD: i8 = 2;
+    pub const LANDMARK: i8 = 3;
+    pub const CLOTHING: i8 = 5;
+    pub const OBJECT: i8 = 6;
+    pub const NOTECARD: i8 = 7;
+    pub const FOLDER: i8 = 8;
+    pub const ROOT_FOLDER: i8 = 9;
+    pub const LSL_TEXT: i8 = 10;
+    pub const BODY_PART: i8 = 13;
+    pub const TRASH: i8 = 14;
+    pub const SNAPSHOT: i8 = 15;
+    pub const LOST_AND_FOUND: i8 = 16;
+    pub const ANIMATION: i8 = 20;
+    pub const GESTURE: i8 = 21;
+    pub const CURRENT_OUTFIT: i8 = 46;
+}
+
+/// Permission bits of the item masks
+pub mod permission {
+    pub const TRANSFER: u32 = 1 << 13;
+    pub const MODIFY: u32 = 1 << 14;
+    pub const COPY: u32 = 1 << 15;
+    pub const MOVE: u32 = 1 << 19;
+    pub const ALL: u32 = 0x7FFF_FFFF;
+}
+
+/// ImprovedInstantMessage dialog offering inventory to another agent
+const IM_INVENTORY_OFFERED: u8 = 4;
+
+/// Inventory edits that cannot be made, reachable through `anyhow::Error::downcast_ref`
+#[derive(Debug, thiserror::Error)]
+pub enum InventoryError {
+    #[error("Inventory folder {0} is not known")]
+    UnknownFolder(Uuid),
+    #[error("Inventory item {0} is not known")]
+    UnknownItem(Uuid),
+    #[error("Inventory folder {0} is a system folder")]
+    SystemFolder(Uuid),
+    #[error("Inventory folder {0} cannot move into its own subtree")]
+    MoveIntoSelf(Uuid),
+    #[error("Inventory item {0} may not be given away")]
+    NotTransferable(Uuid),
+    #[error("Inventory item {0} is not an object")]
+    NotAnObject(Uuid),
+}
+
+/// Inventory folder; LL code calls these categories
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InventoryFolder {
+    pub id: Uuid,
+    pub parent_id: Uuid,
+    pub name: String,
+    /// Asset type the folder collects by default, `asset_type::NONE` for user folders
+    pub type_default: i8,
+    /// Raised by the server whenever the folder's direct contents change
+    pub version: i32,
+    /// Whether the folder's contents were received at `version`
+    pub fetched: bool,
+}
+
+impl InventoryFolder {
+    pub fn from_skeleton(folder: &InventoryFolderSkeleton) -> Self {
+        Self {
+            id: folder.folder_id,
+            parent_id: folder.parent_id,
+            name: folder.name.clone(),
+            type_default: folder.type_default as i8,
+            version: folder.version,
+            fetched: false,
+        }
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.type_default != asset_type::NONE
+    }
+}
+
+/// Folders and items of one agent's inventory, linked by parent id
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InventoryTree {
+    pub root: Option<Uuid>,
+    folders: HashMap<Uuid, InventoryFolder>,
+    items: HashMap<Uuid, InventoryItem>,
+}
+
+impl InventoryTree {
+    /// Folder tree as listed at login, with no contents fetched yet
+    pub fn from_skeleton(root: Option<Uuid>, skeleton: &[InventoryFolderSkeleton]) -> Self {
+        Self {
+            root,
+            folders: skeleton.iter().map(|folder| (folder.folder_id, InventoryFolder::from_skeleton(folder))).collect(),
+            items: HashMap::new(),
+        }
+    }
+
+    pub fn folder(&self, id: Uuid) -> Option<&InventoryFolder> {
+        self.folders.get(&id)
+    }
+
+    pub fn item(&self, id: Uuid) -> Option<&InventoryItem> {
+        self.items.get(&id)
+    }
+
+    pub fn folders(&self) -> impl Iterator<Item = &InventoryFolder> {
+        self.folders.values()
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &InventoryItem> {
+        self.items.values()
+    }
+
+    /// Direct subfolders, by name
+    pub fn subfolders(&self, parent: Uuid) -> Vec<&InventoryFolder> {
+        let mut folders: Vec<_> = self.folders.values().filter(|folder| folder.parent_id == parent).collect();
+        folders.sort_by(|a, b| a.name.cmp(&b.name));
+        folders
+    }
+
+    /// Items directly in a folder, by name
+    pub fn folder_items(&self, parent: Uuid) -> Vec<&InventoryItem> {
+        let mut items: Vec<_> = self.items.values().filter(|item| item.parent_id == parent).collect();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        items
+    }
+
+    /// System folder collecting an asset type, such as Objects for rezzed-back objects
+    pub fn default_folder(&self, asset_type: i8) -> Option<Uuid> {
+        self.folders.values().find(|folder| folder.type_default == asset_type).map(|folder| folder.id)
+    }
+
+    /// Names from the root down to a folder or item
+    pub fn path(&self, id: Uuid) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut next = match self.items.get(&id) {
+            Some(item) => {
+                path.push(item.name.clone());
+                item.parent_id
+            }
+            None => id,
+        };
+        while let Some(folder) = self.folders.get(&next) {
+            path.push(folder.name.clone());
+            if path.len() > self.folders.len() {
+                break;
+            }
+            next = folder.parent_id;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Folders whose contents still have to be fetched
+    pub fn unfetched(&self) -> Vec<Uuid> {
+        self.folders.values().filter(|folder| !folder.fetched).map(|folder| folder.id).collect()
+    }
+
+    fn contains(&self, ancestor: Uuid, mut id: Uuid) -> bool {
+        let mut steps = 0;
+        while let Some(folder) = self.folders.get(&id) {
+            if folder.id == ancestor {
+                return true;
+            }
+            steps += 1;
+            if steps > self.folders.len() {
+                break;
+            }
+            id = folder.parent_id;
+        }
+        false
+    }
+
+    /// Record a local change to a folder's direct contents the way the server does
+    fn bump(&mut self, id: Uuid) {
+        if let Some(folder) = self.folders.get_mut(&id) {
+            folder.version += 1;
+        }
+    }
+
+    pub fn insert_folder(&mut self, folder: InventoryFolder) -> Option<InventoryFolder> {
+        self.folders.insert(folder.id, folder)
+    }
+
+    pub fn insert_item(&mut self, item: InventoryItem) -> Option<InventoryItem> {
+        self.items.insert(item.id, item)
+    }
+
+    pub fn remove_item(&mut self, id: Uuid) -> Option<InventoryItem> {
+        self.items.remove(&id)
+    }
+
+    /// Remove a folder together with everything below it
+    pub fn remove_folder(&mut self, id: Uuid) -> Option<InventoryFolder> {
+        let folder = self.folders.remove(&id)?;
+        self.items.retain(|_, item| item.parent_id != id);
+        let children: Vec<Uuid> = self.folders.values().filter(|child| child.parent_id == id).map(|child| child.id).collect();
+        for child in children {
+            self.remove_folder(child);
+        }
+        Some(folder)
+    }
+
+    /// Replace a folder's direct contents with what the server listed at `version`
+    pub fn set_contents(&mut self, id: Uuid, version: i32, folders: Vec<InventoryFolder>, items: Vec<InventoryItem>) {
+        let folder_ids: HashSet<Uuid> = folders.iter().map(|folder| folder.id).collect();
+        let stale: Vec<Uuid> = self
+            .folders
+            .values()
+            .filter(|child| child.parent_id == id && !folder_ids.contains(&child.id))
+            .map(|child| child.id)
+            .collect();
+        for child in stale {
+            self.remove_folder(child);
+        }
+        for mut child in folders {
+            // Contents already held at the listed version stay valid
+            if let Some(known) = self.folders.get(&child.id) {
+                child.fetched = known.fetched && known.version == child.version;
+            }
+            self.folders.insert(child.id, child);
+        }
+        self.items.retain(|_, item| item.parent_id != id);
+        for item in items {
+            self.items.insert(item.id, item);
+        }
+        if let Some(folder) = self.folders.get_mut(&id) {
+            folder.version = version;
+            folder.fetched = true;
+        }
+    }
+
+    /// Adopt the contents of cached folders the server still lists at the cached version
+    pub fn merge_cache(&mut self, cached: InventoryTree) -> usize {
+        let current: HashSet<Uuid> = self
+            .folders
+            .values_mut()
+            .filter_map(|folder| {
+                let cached = cached.folders.get(&folder.id)?;
+                (cached.fetched && cached.version == folder.version).then(|| {
+                    folder.fetched = true;
+                    folder.id
+                })
+            })
+            .collect();
+        for item in cached.items.into_values() {
+            if current.contains(&item.parent_id) {
+                self.items.insert(item.id, item);
+            }
+        }
+        current.len()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("reading inventory cache {}", path.display()))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?).with_context(|| format!("writing inventory cache {}", path.display()))
+    }
+}
+
+/// Inventory entries touched by one message or fetch
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InventoryChanges {
+    /// Folders whose contents were replaced
+    pub folders: Vec<Uuid>,
+    /// Items added or updated
+    pub items: Vec<Uuid>,
+}
+
+impl InventoryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.folders.is_empty() && self.items.is_empty()
+    }
+}
+
+/// InventoryDescendents packets received for one folder until its listing is complete
+#[derive(Debug, Clone, Default)]
+struct UdpListing {
+    version: i32,
+    folders: Vec<InventoryFolder>,
+    items: Vec<InventoryItem>,
+}
+
+/// The agent's inventory with the requests that read and change it
+///
+/// Edits apply to the local tree immediately and return the message telling the simulator;
+/// callers send it on the agent's circuit.
+#[derive(Debug, Clone)]
+pub struct Inventory {
+    agent_id: Uuid,
+    session_id: Uuid,
+    tree: InventoryTree,
+    next_callback: u32,
+    udp_listings: HashMap<Uuid, UdpListing>,
+}
+
+impl Inventory {
+    pub fn new(agent_id: Uuid, session_id: Uuid, tree: InventoryTree) -> Self {
+        Self { agent_id, session_id, tree, next_callback: 1, udp_listings: HashMap::new() }
+    }
+
+    /// Inventory skeleton sent with a successful login
+    pub fn from_login(login: &LoginResponse) -> Self {
+        let tree = InventoryTree::from_skeleton(login.inventory_root, &login.inventory_skeleton);
+        Self::new(login.agent_id, login.session_id, tree)
+    }
+
+    pub fn agent_id(&self) -> Uuid {
+        self.agent_id
+    }
+
+    pub fn tree(&self) -> &InventoryTree {
+        &self.tree
+    }
+
+    /// Restore folder contents from a disk cache; returns how many folders were still current
+    pub fn load_cache(&mut self, path: &Path) -> Result<usize> {
+        let cached = InventoryTree::load(path)?;
+        let restored = self.tree.merge_cache(cached);
+        debug!("Restored {} inventory folders from {}", restored, path.display());
+        Ok(restored)
+    }
+
+    pub fn save_cache(&self, path: &Path) -> Result<()> {
+        self.tree.save(path)
+    }
+
+    /// Fetch folder contents over FetchInventoryDescendents2; folders the capability cannot serve
+    /// come back as FetchInventoryDescendents packets for the circuit
+    pub async fn fetch_folders(
+        &mut self,
+        client: &CapsClient,
+        capabilities: &Capabilities,
+        folders: &[Uuid],
+    ) -> Result<(InventoryChanges, Vec<LLUDPPacket>)> {
+        if folders.is_empty() {
+            return Ok(Default::default());
+        }
+        let Some(url) = capabilities.get(FETCH_DESCENDENTS_CAP) else {
+            return Ok((InventoryChanges::default(), self.udp_folder_fetches(folders)));
+        };
+        let request: Vec<Llsd> = folders
+            .iter()
+            .map(|folder| {
+                let mut entry = Llsd::map();
+                entry
+                    .insert("folder_id", *folder)
+                    .insert("owner_id", self.agent_id)
+                    .insert("sort_order", 1)
+                    .insert("fetch_folders", true)
+                    .insert("fetch_items", true);
+                entry
+            })
+            .collect();
+        let mut body = Llsd::map();
+        body.insert("folders", request);
+        match client.post(url, &body).await {
+            Ok(response) => Ok((self.apply_descendents(&response), Vec::new())),
+            Err(error) => {
+                warn!("{} failed, fetching {} folders over UDP: {}", FETCH_DESCENDENTS_CAP, folders.len(), error);
+                Ok((InventoryChanges::default(), self.udp_folder_fetches(folders)))
+            }
+        }
+    }
+
+    /// Fetch items over FetchInventory2, falling back to a FetchInventory packet
+    pub async fn fetch_items(
+        &mut self,
+        client: &CapsClient,
+        capabilities: &Capabilities,
+        items: &[Uuid],
+    ) -> Result<(InventoryChanges, Vec<LLUDPPacket>)> {
+        if items.is_empty() {
+            return Ok(Default::default());
+        }
+        let Some(url) = capabilities.get(FETCH_ITEMS_CAP) else {
+            return Ok((InventoryChanges::default(), vec![self.fetch_items_message(items).to_packet()]));
+        };
+        let request: Vec<Llsd> = items
+            .iter()
+            .map(|item| {
+                let mut entry = Llsd::map();
+                entry.insert("owner_id", self.agent_id).insert("item_id", *item);
+                entry
+            })
+            .collect();
+        let mut body = Llsd::map();
+        body.insert("agent_id", self.agent_id).insert("items", request);
+        match client.post(url, &body).await {
+            Ok(response) => Ok((self.apply_items(&response), Vec::new())),
+            Err(error) => {
+                warn!("{} failed, fetching {} items over UDP: {}", FETCH_ITEMS_CAP, items.len(), error);
+                Ok((InventoryChanges::default(), vec![self.fetch_items_message(items).to_packet()]))
+            }
+        }
+    }
+
+    fn udp_folder_fetches(&mut self, folders: &[Uuid]) -> Vec<LLUDPPacket> {
+        folders.iter().map(|folder| self.fetch_folder_message(*folder).to_packet()).collect()
+    }
+
+    /// Apply a FetchInventoryDescendents2 answer
+    pub fn apply_descendents(&mut self, response: &Llsd) -> InventoryChanges {
+        let mut changes = InventoryChanges::default();
+        for listing in response.get("folders").as_array() {
+            let id = listing.get("folder_id").as_uuid();
+            let folders = listing
+                .get("categories")
+                .as_array()
+                .iter()
+                .map(folder_from_llsd)
+                .filter(|folder| !folder.id.is_nil() && folder.id != id)
+                .collect();
+            let items: Vec<InventoryItem> = listing.get("items").as_array().iter().map(item_from_llsd).collect();
+            changes.items.extend(items.iter().map(|item| item.id));
+            self.tree.set_contents(id, listing.get("version").as_integer(), folders, items);
+            changes.folders.push(id);
+        }
+        for bad in response.get("bad_folders").as_array() {
+            let id = match bad.get("folder_id") {
+                Llsd::Undefined => bad.as_uuid(),
+                id => id.as_uuid(),
+            };
+            warn!("Inventory folder {} could not be fetched: {}", id, bad.get("error").as_string());
+        }
+        changes
+    }
+
+    /// Apply a FetchInventory2 answer
+    pub fn apply_items(&mut self, response: &Llsd) -> InventoryChanges {
+        let mut changes = InventoryChanges::default();
+        for item in response.get("items").as_array().iter().map(item_from_llsd) {
+            changes.items.push(item.id);
+            self.tree.insert_item(item);
+        }
+        changes
+    }
+
+    /// LLUDP request for one folder's contents; the answer may span several InventoryDescendents
+    pub fn fetch_folder_message(&mut self, folder: Uuid) -> FetchInventoryDescendents {
+        self.udp_listings.remove(&folder);
+        FetchInventoryDescendents {
+            agent_data: FetchInventoryDescendentsAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            inventory_data: FetchInventoryDescendentsInventoryData {
+                folder_id: folder,
+                owner_id: self.agent_id,
+                sort_order: 1,
+                fetch_folders: true,
+                fetch_items: true,
+            },
+        }
+    }
+
+    pub fn fetch_items_message(&self, items: &[Uuid]) -> FetchInventory {
+        FetchInventory {
+            agent_data: FetchInventoryAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            inventory_data: items
+                .iter()
+                .map(|item| FetchInventoryInventoryData { owner_id: self.agent_id, item_id: *item })
+                .collect(),
+        }
+    }
+
+    /// Apply an inventory message from the simulator
+    pub fn handle_packet(&mut self, packet: &LLUDPPacket) -> Result<InventoryChanges> {
+        let payload = &packet.payload;
+        Ok(match packet.message_type {
+            LLUDPMessageType::InventoryDescendents => self.apply_udp_descendents(InventoryDescendents::from_payload(payload)?),
+            LLUDPMessageType::FetchInventoryReply => {
+                let message = FetchInventoryReply::from_payload(payload)?;
+                self.insert_items(message.inventory_data.iter().map(InventoryItem::from))
+            }
+            LLUDPMessageType::UpdateCreateInventoryItem => {
+                let message = UpdateCreateInventoryItem::from_payload(payload)?;
+                if !message.agent_data.sim_approved {
+                    warn!("Simulator refused to create inventory item");
+                    return Ok(InventoryChanges::default());
+                }
+                self.insert_items(message.inventory_data.iter().map(InventoryItem::from))
+            }
+            LLUDPMessageType::BulkUpdateInventory => {
+                let message = BulkUpdateInventory::from_payload(payload)?;
+                for block in message.folder_data.iter().filter(|block| !block.folder_id.is_nil()) {
+                    let known = self.tree.folder(block.folder_id).cloned();
+                    self.tree.insert_folder(InventoryFolder {
+                        id: block.folder_id,
+                        parent_id: block.parent_id,
+                        name: trim_null(&block.name),
+                        type_default: block.r#type,
+                        version: known.as_ref().map_or(0, |folder| folder.version),
+                        fetched: known.is_some_and(|folder| folder.fetched),
+                    });
+                }
+                self.insert_items(message.item_data.iter().map(InventoryItem::from))
+            }
+            _ => InventoryChanges::default(),
+        })
+    }
+
+    fn insert_items(&mut self, items: impl Iterator<Item = InventoryItem>) -> InventoryChanges {
+        let mut changes = InventoryChanges::default();
+        for item in items.filter(|item| !item.id.is_nil()) {
+            let previous = self.tree.insert_item(item.clone());
+            if previous.as_ref().is_none_or(|previous| previous.parent_id != item.parent_id) {
+                self.tree.bump(item.parent_id);
+            }
+            changes.items.push(item.id);
+        }
+        changes
+    }
+
+    fn apply_udp_descendents(&mut self, message: InventoryDescendents) -> InventoryChanges {
+        let id = message.agent_data.folder_id;
+        let listing = self.udp_listings.entry(id).or_default();
+        listing.version = message.agent_data.version;
+        // Empty folders are answered with a single all-zero block
+        listing.folders.extend(message.folder_data.iter().filter(|block| !block.folder_id.is_nil()).map(|block| {
+            InventoryFolder {
+                id: block.folder_id,
+                parent_id: block.parent_id,
+                name: trim_null(&block.name),
+                type_default: block.r#type,
+                version: 0,
+                fetched: false,
+            }
+        }));
+        listing.items.extend(message.item_data.iter().filter(|block| !block.item_id.is_nil()).map(InventoryItem::from));
+        if listing.folders.len() + listing.items.len() < message.agent_data.descendents.max(0) as usize {
+            return InventoryChanges::default();
+        }
+
+        let UdpListing { version, folders, items } = self.udp_listings.remove(&id).unwrap_or_default();
+        let changes = InventoryChanges { folders: vec![id], items: items.iter().map(|item| item.id).collect() };
+        // UDP listings carry no subfolder versions, so known subfolders keep theirs
+        let folders = folders
+            .into_iter()
+            .map(|mut folder| {
+                if let Some(known) = self.tree.folder(folder.id) {
+                    folder.version = known.version;
+                }
+                folder
+            })
+            .collect();
+        self.tree.set_contents(id, version, folders, items);
+        changes
+    }
+
+    fn folder(&self, id: Uuid) -> Result<&InventoryFolder> {
+        Ok(self.tree.folder(id).ok_or(InventoryError::UnknownFolder(id))?)
+    }
+
+    fn item(&self, id: Uuid) -> Result<&InventoryItem> {
+        Ok(self.tree.item(id).ok_or(InventoryError::UnknownItem(id))?)
+    }
+
+    pub fn create_folder(&mut self, parent: Uuid, name: &str) -> Result<CreateInventoryFolder> {
+        self.folder(parent)?;
+        let folder = InventoryFolder {
+            id: Uuid::new_v4(),
+            parent_id: parent,
+            name: name.to_string(),
+            type_default: asset_type::NONE,
+            version: 1,
+            fetched: true,
+        };
+        let message = CreateInventoryFolder {
+            agent_data: CreateInventoryFolderAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            folder_data: CreateInventoryFolderFolderData {
+                folder_id: folder.id,
+                parent_id: parent,
+                r#type: folder.type_default,
+                name: null_terminated(name, u8::MAX as usize),
+            },
+        };
+        self.tree.insert_folder(folder);
+        self.tree.bump(parent);
+        Ok(message)
+    }
+
+    pub fn rename_folder(&mut self, id: Uuid, name: &str) -> Result<UpdateInventoryFolder> {
+        let folder = self.folder(id)?;
+        if folder.is_system() {
+            return Err(InventoryError::SystemFolder(id).into());
+        }
+        let message = UpdateInventoryFolder {
+            agent_data: UpdateInventoryFolderAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            folder_data: vec![UpdateInventoryFolderFolderData {
+                folder_id: id,
+                parent_id: folder.parent_id,
+                r#type: folder.type_default,
+                name: null_terminated(name, u8::MAX as usize),
+            }],
+        };
+        let parent = folder.parent_id;
+        if let Some(folder) = self.tree.folders.get_mut(&id) {
+            folder.name = name.to_string();
+        }
+        self.tree.bump(parent);
+        Ok(message)
+    }
+
+    pub fn move_folder(&mut self, id: Uuid, parent: Uuid) -> Result<MoveInventoryFolder> {
+        let folder = self.folder(id)?;
+        if folder.is_system() {
+            return Err(InventoryError::SystemFolder(id).into());
+        }
+        let from = folder.parent_id;
+        self.folder(parent)?;
+        if self.tree.contains(id, parent) {
+            return Err(InventoryError::MoveIntoSelf(id).into());
+        }
+        if let Some(folder) = self.tree.folders.get_mut(&id) {
+            folder.parent_id = parent;
+        }
+        self.tree.bump(from);
+        self.tree.bump(parent);
+        Ok(MoveInventoryFolder {
+            agent_data: MoveInventoryFolderAgentData { agent_id: self.agent_id, session_id: self.session_id, stamp: false },
+            inventory_data: vec![MoveInventoryFolderInventoryData { folder_id: id, parent_id: parent }],
+        })
+    }
+
+    /// Delete a folder and everything in it
+    pub fn remove_folder(&mut self, id: Uuid) -> Result<RemoveInventoryFolder> {
+        if self.folder(id)?.is_system() {
+            return Err(InventoryError::SystemFolder(id).into());
+        }
+        if let Some(folder) = self.tree.remove_folder(id) {
+            self.tree.bump(folder.parent_id);
+        }
+        Ok(RemoveInventoryFolder {
+            agent_data: RemoveInventoryFolderAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            folder_data: vec![RemoveInventoryFolderFolderData { folder_id: id }],
+        })
+    }
+
+    /// Ask the simulator to create an item; it arrives with UpdateCreateInventoryItem carrying the
+    /// returned message's callback id
+    pub fn create_item(
+        &mut self,
+        folder: Uuid,
+        name: &str,
+        description: &str,
+        item_type: i8,
+        inv_type: i8,
+        wearable_type: u8,
+    ) -> Result<CreateInventoryItem> {
+        self.folder(folder)?;
+        let callback_id = self.next_callback;
+        self.next_callback = self.next_callback.wrapping_add(1).max(1);
+        Ok(CreateInventoryItem {
+            agent_data: CreateInventoryItemAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            inventory_block: CreateInventoryItemInventoryBlock {
+                callback_id,
+                folder_id: folder,
+                transaction_id: Uuid::nil(),
+                next_owner_mask: permission::MOVE | permission::TRANSFER,
+                r#type: item_type,
+                inv_type,
+                wearable_type,
+                name: null_terminated(name, u8::MAX as usize),
+                description: null_terminated(description, u8::MAX as usize),
+            },
+        })
+    }
+
+    pub fn rename_item(&mut self, id: Uuid, name: &str) -> Result<UpdateInventoryItem> {
+        let mut item = self.item(id)?.clone();
+        item.name = name.to_string();
+        let message = UpdateInventoryItem {
+            agent_data: UpdateInventoryItemAgentData {
+                agent_id: self.agent_id,
+                session_id: self.session_id,
+                transaction_id: Uuid::nil(),
+            },
+            inventory_data: vec![UpdateInventoryItemInventoryData {
+                item_id: item.id,
+                folder_id: item.parent_id,
+                callback_id: 0,
+                creator_id: item.creator_id,
+                owner_id: item.owner_id,
+                group_id: item.group_id,
+                base_mask: item.permissions.base_mask,
+                owner_mask: item.permissions.owner_mask,
+                group_mask: item.permissions.group_mask,
+                everyone_mask: item.permissions.everyone_mask,
+                next_owner_mask: item.permissions.next_owner_mask,
+                group_owned: item.group_owned,
+                transaction_id: Uuid::nil(),
+                r#type: item.item_type,
+                inv_type: item.inv_type,
+                flags: item.flags,
+                sale_type: item.sale_type,
+                sale_price: item.sale_price,
+                name: null_terminated(&item.name, u8::MAX as usize),
+                description: null_terminated(&item.description, u8::MAX as usize),
+                creation_date: item.creation_date,
+                crc: 0, // simulators do not check it
+            }],
+        };
+        self.tree.bump(item.parent_id);
+        self.tree.insert_item(item);
+        Ok(message)
+    }
+
+    pub fn move_item(&mut self, id: Uuid, folder: Uuid) -> Result<MoveInventoryItem> {
+        let from = self.item(id)?.parent_id;
+        self.folder(folder)?;
+        if let Some(item) = self.tree.items.get_mut(&id) {
+            item.parent_id = folder;
+        }
+        self.tree.bump(from);
+        self.tree.bump(folder);
+        Ok(MoveInventoryItem {
+            agent_data: MoveInventoryItemAgentData { agent_id: self.agent_id, session_id: self.session_id, stamp: false },
+            inventory_data: vec![MoveInventoryItemInventoryData { item_id: id, folder_id: folder, new_name: Vec::new() }],
+        })
+    }
+
+    pub fn remove_item(&mut self, id: Uuid) -> Result<RemoveInventoryItem> {
+        let parent = self.item(id)?.parent_id;
+        self.tree.remove_item(id);
+        self.tree.bump(parent);
+        Ok(RemoveInventoryItem {
+            agent_data: RemoveInventoryItemAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            inventory_data: vec![RemoveInventoryItemInventoryData { item_id: id }],
+        })
+    }
+
+    /// Offer an item to another agent; items without copy permission leave this inventory
+    pub fn give_item(&mut self, to_agent: Uuid, from_name: &str, id: Uuid) -> Result<ImprovedInstantMessage> {
+        let item = self.item(id)?.clone();
+        if item.permissions.owner_mask & permission::TRANSFER == 0 {
+            return Err(InventoryError::NotTransferable(id).into());
+        }
+        if item.permissions.owner_mask & permission::COPY == 0 {
+            self.tree.remove_item(id);
+            self.tree.bump(item.parent_id);
+        }
+        Ok(self.offer(to_agent, from_name, &item.name, item.item_type, id))
+    }
+
+    /// Offer a folder and its contents to another agent
+    pub fn give_folder(&mut self, to_agent: Uuid, from_name: &str, id: Uuid) -> Result<ImprovedInstantMessage> {
+        let folder = self.folder(id)?;
+        if folder.is_system() {
+            return Err(InventoryError::SystemFolder(id).into());
+        }
+        let name = folder.name.clone();
+        Ok(self.offer(to_agent, from_name, &name, asset_type::FOLDER, id))
+    }
+
+    fn offer(&self, to_agent: Uuid, from_name: &str, name: &str, item_type: i8, id: Uuid) -> ImprovedInstantMessage {
+        let mut bucket = vec![item_type as u8];
+        bucket.extend_from_slice(id.as_bytes());
+        ImprovedInstantMessage {
+            agent_data: ImprovedInstantMessageAgentData { agent_id: self.agent_id, session_id: self.session_id },
+            message_block: ImprovedInstantMessageMessageBlock {
+                to_agent_id: to_agent,
+                dialog: IM_INVENTORY_OFFERED,
+                // Transaction the recipient's accept or decline refers to
+                id: Uuid::new_v4(),
+                from_agent_name: null_terminated(from_name, u8::MAX as usize),
+                message: null_terminated(name, u16::MAX as usize),
+                binary_bucket: bucket,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Rez an object item where a ray from `ray_start` to `ray_end` meets the scene; no-copy
+    /// items move out of inventory into the region
+    pub fn rez_object(&mut self, id: Uuid, ray_start: [f32; 3], ray_end: [f32; 3]) -> Result<RezObject> {
+        let item = self.item(id)?.clone();
+        if item.item_type != asset_type::OBJECT {
+            return Err(InventoryError::NotAnObject(id).into());
+        }
+        let remove_item = item.permissions.owner_mask & permission::COPY == 0;
+        if remove_item {
+            self.tree.remove_item(id);
+            self.tree.bump(item.parent_id);
+        }
+        Ok(RezObject {
+            agent_data: RezObjectAgentData { agent_id: self.agent_id, session_id: self.session_id, group_id: Uuid::nil() },
+            rez_data: RezObjectRezData {
+                from_task_id: Uuid::nil(),
+                bypass_raycast: 1,
+                ray_start,
+                ray_end,
+                ray_target_id: Uuid::nil(),
+                ray_end_is_intersection: false,
+                rez_selected: false,
+                remove_item,
+                item_flags: item.flags,
+                group_mask: item.permissions.group_mask,
+                everyone_mask: item.permissions.everyone_mask,
+                next_owner_mask: item.permissions.next_owner_mask,
+            },
+            inventory_data: RezObjectInventoryData {
+                item_id: item.id,
+                folder_id: item.parent_id,
+                creator_id: item.creator_id,
+                owner_id: item.owner_id,
+                group_id: item.group_id,
+                base_mask: item.permissions.base_mask,
+                owner_mask: item.permissions.owner_mask,
+                group_mask: item.permissions.group_mask,
+                everyone_mask: item.permissions.everyone_mask,
+                next_owner_mask: item.permissions.next_owner_mask,
+                group_owned: item.group_owned,
+                transaction_id: Uuid::nil(),
+                r#type: item.item_type,
+                inv_type: item.inv_type,
+                flags: item.flags,
+                sale_type: item.sale_type,
+                sale_price: item.sale_price,
+                name: null_terminated(&item.name, u8::MAX as usize),
+                description: null_terminated(&item.description, u8::MAX as usize),
+                creation_date: item.creation_date,
+                crc: 0,
+            },
+        })
+    }
+}
+
+/// Folder as listed in a capability answer; simulators disagree on a few key names
+pub fn folder_from_llsd(value: &Llsd) -> InventoryFolder {
+    let id = match value.get("category_id") {
+        Llsd::Undefined => value.get("folder_id"),
+        id => id,
+    };
+    let type_default = match value.get("type_default") {
+        Llsd::Undefined => value.get("type"),
+        kind => kind,
+    };
+    InventoryFolder {
+        id: id.as_uuid(),
+        parent_id: value.get("parent_id").as_uuid(),
+        name: value.get("name").as_string(),
+        type_default: if type_default.is_undefined() { asset_type::NONE } else { type_default.as_integer() as i8 },
+        version: value.get("version").as_integer(),
+        fetched: false,
+    }
+}
+
+/// Item as listed in a capability answer
+pub fn item_from_llsd(value: &Llsd) -> InventoryItem {
+    let permissions = value.get("permissions");
+    let mask = |name: &str| permissions.get(name).as_integer() as u32;
+    let sale_info = value.get("sale_info");
+    InventoryItem {
+        id: value.get("item_id").as_uuid(),
+        parent_id: value.get("parent_id").as_uuid(),
+        name: value.get("name").as_string(),
+        description: value.get("desc").as_string(),
+        asset_id: value.get("asset_id").as_uuid(),
+        item_type: value.get("type").as_integer() as i8,
+        inv_type: value.get("inv_type").as_integer() as i8,
+        creator_id: permissions.get("creator_id").as_uuid(),
+        owner_id: permissions.get("owner_id").as_uuid(),
+        group_id: permissions.get("group_id").as_uuid(),
+        group_owned: permissions.get("is_owner_group").as_bool(),
+        permissions: InventoryPermissions {
+            base_mask: mask("base_mask"),
+            owner_mask: mask("owner_mask"),
+            group_mask: mask("group_mask"),
+            everyone_mask: mask("everyone_mask"),
+            next_owner_mask: mask("next_owner_mask"),
+        },
+        flags: value.get("flags").as_integer() as u32,
+        sale_type: sale_info.get("sale_type").as_integer() as u8,
+        sale_price: sale_info.get("sale_price").as_integer(),
+        creation_date: value.get("created_at").as_integer(),
+    }
+}
+
+/// The item blocks of InventoryDescendents, FetchInventoryReply, UpdateCreateInventoryItem and
+/// BulkUpdateInventory share their fields
+macro_rules! item_from_block {
+    ($($block:ty),*) => {$(
+        impl From<&$block> for InventoryItem {
+            fn from(block: &$block) -> Self {
+                InventoryItem {
+                    id: block.item_id,
+                    parent_id: block.folder_id,
+                    name: trim_null(&block.name),
+                    description: trim_null(&block.description),
+                    asset_id: block.asset_id,
+                    item_type: block.r#type,
+                    inv_type: block.inv_type,
+                    creator_id: block.creator_id,
+                    owner_id: block.owner_id,
+                    group_id: block.group_id,
+                    group_owned: block.group_owned,
+                    permissions: InventoryPermissions {
+                        base_mask: block.base_mask,
+                        owner_mask: block.owner_mask,
+                        group_mask: block.group_mask,
+                        everyone_mask: block.everyone_mask,
+                        next_owner_mask: block.next_owner_mask,
+                    },
+                    flags: block.flags,
+                    sale_type: block.sale_type,
+                    sale_price: block.sale_price,
+                    creation_date: block.creation_date,
+                }
+            }
+        }
+    )*};
+}
+
+item_from_block!(
+    crate::template::InventoryDescendentsItemData,
+    crate::template::FetchInventoryReplyInventoryData,
+    crate::template::UpdateCreateInventoryItemInventoryData,
+    crate::template::BulkUpdateInventoryItemData
+);
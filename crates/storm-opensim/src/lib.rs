@@ -6,11 +6,50 @@ pub mod messages;
 pub mod serialization;
 pub mod login;
 pub mod circuit;
+pub mod template;
+pub mod xmlrpc;
+pub mod texture_entry;
+pub mod objects;
+pub mod llsd;
+pub mod caps;
+pub mod terrain;
+pub mod texture;
+pub mod inventory;
+pub mod appearance;
+
+#[cfg(test)]
+#[path = "../codegen/template.rs"]
+mod codegen;
 
 pub use messages::*;
 pub use serialization::*;
 pub use login::*;
 pub use circuit::*;
+pub use texture_entry::{TextureEntry, TextureFace};
+pub use objects::{
+    request_cache_misses, ObjectCache, ObjectChanges, ObjectMotion, ObjectName, ObjectState, OpenSimObject, ParentLink,
+    PrimShape, RegionObjects, TerseUpdate,
+};
+pub use llsd::{from_llsd, to_llsd, Llsd, LlsdFormat};
+pub use caps::{
+    Capabilities, CapsClient, CapsConfig, CapsError, CapsEvent, CapsRegistry, CapsSession, EventQueue, RegionEvents,
+    DEFAULT_CAPABILITIES, EVENT_QUEUE_GET,
+};
+pub use terrain::{
+    decode_layer, encode_layer, CloudField, LayerGroup, LayerKind, PatchHeader, RegionTerrain, TerrainChanges, TerrainPatch,
+    WindField,
+};
+pub use texture::{
+    desired_discard, request_image, ImageProgress, TextureDownload, TextureError, TextureEvent, TextureFetcher, TexturePipeline,
+    TextureRequest, TextureSource, UdpTextures,
+};
+pub use inventory::{
+    folder_from_llsd, item_from_llsd, Inventory, InventoryChanges, InventoryError, InventoryFolder, InventoryTree,
+};
+pub use appearance::{
+    baked_texture_index, AgentAppearance, AgentWearables, AppearanceError, AvatarAppearances, OpenSimAppearance, Wearable,
+    UPDATE_APPEARANCE_CAP,
+};
 
 /// OpenSim protocol version information
 pub const OPENSIM_PROTOCOL_VERSION: &str = "0.9.2";
@@ -69,6 +108,13 @@ impl Default for RegionInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::{Duration, Instant};
+    use crate::texture_entry::MAX_FACES;
+    use proptest::prelude::*;
+    use storm_protocol_adapters::SimulatorEvent;
+    use std::sync::Arc;
+    use uuid::Uuid;
+    use crate::template::RequestImage;
 
     #[test]
     fn test_region_info_default() {
@@ -77,4 +123,1812 @@ mod tests {
         assert_eq!(region.region_size_x, 256);
         assert_eq!(region.water_height, 20.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_packet_round_trip() {
+        let body = UseCircuitCode {
+            code: 1234,
+            session_id: uuid::Uuid::new_v4(),
+            agent_id: uuid::Uuid::new_v4(),
+        };
+        let mut packet = body.to_packet();
+        packet.sequence = 7;
+
+        let decoded = deserialize_packet(&serialize_packet(&packet).unwrap()).unwrap();
+        assert_eq!(decoded.sequence, 7);
+        assert_eq!(decoded.message_type, LLUDPMessageType::UseCircuitCode);
+
+        let decoded_body = UseCircuitCode::from_payload(&decoded.payload).unwrap();
+        assert_eq!(decoded_body.code, 1234);
+        assert_eq!(decoded_body.agent_id, body.agent_id);
+    }
+
+    #[test]
+    fn test_chat_from_viewer_round_trip() {
+        let chat = ChatFromViewer {
+            agent_id: uuid::Uuid::new_v4(),
+            session_id: uuid::Uuid::new_v4(),
+            message: "Hello grid".to_string(),
+            chat_type: 1,
+            channel: 0,
+        };
+        let decoded = ChatFromViewer::from_payload(&chat.to_payload()).unwrap();
+        assert_eq!(decoded.message, "Hello grid");
+        assert_eq!(decoded.channel, 0);
+    }
+
+    #[test]
+    fn test_frequency_encoded_message_ids() {
+        let cases: [(LLUDPMessageType, &[u8]); 4] = [
+            (LLUDPMessageType::StartPingCheck, &[0x01]),
+            (LLUDPMessageType::CoarseLocationUpdate, &[0xFF, 0x06]),
+            (LLUDPMessageType::UseCircuitCode, &[0xFF, 0xFF, 0x00, 0x03]),
+            (LLUDPMessageType::PacketAck, &[0xFF, 0xFF, 0xFF, 0xFB]),
+        ];
+        for (message_type, wire) in cases {
+            let mut id = Vec::new();
+            message_type.write_id(&mut id).unwrap();
+            assert_eq!(id, wire, "{}", message_type.name());
+            assert_eq!(LLUDPMessageType::read_id(wire).unwrap(), (message_type, wire.len()));
+        }
+
+        for &message_type in LLUDPMessageType::ALL {
+            let mut id = Vec::new();
+            message_type.write_id(&mut id).unwrap();
+            assert_eq!(LLUDPMessageType::read_id(&id).unwrap().0, message_type);
+        }
+        assert_eq!(LLUDPMessageType::read_id(&[0xFF, 0xFF, 0x7F, 0x00]).unwrap().0, LLUDPMessageType::Unknown);
+        assert!(LLUDPMessageType::read_id(&[0xFF, 0xFF, 0x00]).is_err());
+        assert!(LLUDPMessageType::Unknown.write_id(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_adapter_message_ids_match_template() {
+        use storm_protocol_adapters::{LLUDPMessageType as AdapterMessage, MessageFrequency};
+
+        let frequency = |frequency| match frequency {
+            MessageFrequency::High => Frequency::High,
+            MessageFrequency::Medium => Frequency::Medium,
+            MessageFrequency::Low => Frequency::Low,
+        };
+
+        for &adapter in AdapterMessage::ALL {
+            let name = format!("{:?}", adapter);
+            let generated = LLUDPMessageType::ALL
+                .iter()
+                .find(|message| message.name() == name)
+                .unwrap_or_else(|| panic!("{} is not in message_template.msg", name));
+            assert_eq!(generated.id(), Some((frequency(adapter.frequency()), adapter as u32)), "{}", name);
+            assert_eq!(AdapterMessage::from_id(adapter.frequency(), adapter as u16), adapter, "{}", name);
+        }
+
+        // Whatever the adapter's decoder recognises must be the message the template numbers that way
+        for &message in LLUDPMessageType::ALL {
+            let adapter_frequency = match message.id() {
+                Some((Frequency::High, _)) => MessageFrequency::High,
+                Some((Frequency::Medium, _)) => MessageFrequency::Medium,
+                Some((Frequency::Low, _)) => MessageFrequency::Low,
+                _ => continue,
+            };
+            let number = message.id().unwrap().1 as u16;
+            let adapter = AdapterMessage::from_id(adapter_frequency, number);
+            if adapter != AdapterMessage::Unknown {
+                assert_eq!(format!("{:?}", adapter), message.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_zerocode_round_trip() {
+        let mut data = vec![0u8; 300];
+        data.extend_from_slice(&[1, 2, 0, 0, 3, 0]);
+        let encoded = zero_encode(&data);
+        assert_eq!(&encoded[..4], &[0, 255, 0, 45]);
+        assert_eq!(zero_decode(&encoded).unwrap(), data);
+        assert!(zero_decode(&[5, 0]).is_err());
+    }
+
+    #[test]
+    fn test_reliable_zerocoded_packet_with_appended_acks() {
+        let update = AgentUpdate {
+            agent_id: uuid::Uuid::new_v4(),
+            session_id: uuid::Uuid::new_v4(),
+            body_rotation: [0.0, 0.0, 0.0, 1.0],
+            head_rotation: [0.0, 0.0, 0.0, 1.0],
+            state: 0,
+            camera_center: [128.0, 128.0, 25.0],
+            camera_at_axis: [1.0, 0.0, 0.0],
+            camera_left_axis: [0.0, 1.0, 0.0],
+            camera_up_axis: [0.0, 0.0, 1.0],
+            far: 64.0,
+            control_flags: 0,
+            flags: 0,
+        };
+        let mut packet = update.to_packet();
+        assert!(packet.is_zerocoded());
+        packet.flags |= FLAG_RELIABLE;
+        packet.sequence = 42;
+        packet.acks = vec![7, 8, 0x0102_0304];
+
+        let data = serialize_packet(&packet).unwrap();
+        assert_eq!(data[0], FLAG_ZEROCODED | FLAG_RELIABLE | FLAG_ACK);
+        assert_eq!(*data.last().unwrap(), 3);
+        assert!(data.len() < PACKET_HEADER_SIZE + 1 + packet.payload.len());
+
+        let decoded = deserialize_packet(&data).unwrap();
+        assert!(decoded.is_reliable());
+        assert_eq!(decoded.sequence, 42);
+        assert_eq!(decoded.message_type, LLUDPMessageType::AgentUpdate);
+        assert_eq!(decoded.acks, packet.acks);
+        assert_eq!(decoded.payload, packet.payload);
+        assert_eq!(AgentUpdate::from_payload(&decoded.payload).unwrap(), update);
+
+        // Zero-coding that would not shrink the body is skipped
+        let handshake_reply = template::RegionHandshakeReply {
+            agent_data: template::RegionHandshakeReplyAgentData {
+                agent_id: uuid::Uuid::from_u128(u128::MAX),
+                session_id: uuid::Uuid::from_u128(u128::MAX),
+            },
+            region_info: template::RegionHandshakeReplyRegionInfo { flags: u32::MAX },
+        };
+        let data = serialize_packet(&handshake_reply.to_packet()).unwrap();
+        assert_eq!(data[0] & FLAG_ZEROCODED, 0);
+        assert_eq!(template::RegionHandshakeReply::from_payload(&deserialize_packet(&data).unwrap().payload).unwrap(), handshake_reply);
+    }
+
+    #[test]
+    fn test_every_template_message_round_trips() {
+        // All-zero bytes decode as every message with empty variable blocks and fields
+        let zeros = vec![0u8; 4096];
+        for &message_type in LLUDPMessageType::ALL {
+            let message = template::TemplateMessage::decode(message_type, &zeros).unwrap();
+            assert_eq!(message.message_type(), message_type);
+
+            let packet = LLUDPPacket {
+                flags: FLAG_ZEROCODED | FLAG_RELIABLE,
+                sequence: 9,
+                extra_header: Vec::new(),
+                message_type,
+                payload: message.to_payload(),
+                acks: vec![1],
+            };
+            let decoded = deserialize_packet(&serialize_packet(&packet).unwrap()).unwrap();
+            assert_eq!(decoded.message_type, message_type);
+            assert_eq!(decoded.payload, packet.payload, "{}", message_type.name());
+            assert_eq!(template::TemplateMessage::decode(message_type, &decoded.payload).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_codegen_parses_upstream_template_layout() {
+        let upstream = codegen::parse(include_str!("../codegen/upstream_excerpt.msg")).unwrap();
+        assert_eq!(upstream.version, "2.0");
+        assert_eq!(upstream.messages.len(), 9);
+
+        let find = |name: &str| upstream.messages.iter().find(|message| message.name == name).unwrap();
+        let test_message = find("TestMessage");
+        assert!(test_message.zerocoded && !test_message.trusted);
+        assert_eq!(test_message.blocks[1].quantity, codegen::BlockQuantity::Multiple(4));
+        assert!(find("ObjectAdd").blocks[1].fields.iter().any(|field| field.field_type == codegen::FieldType::S8));
+        assert_eq!(find("CameraConstraint").blocks[0].fields[0].field_type, codegen::FieldType::Vector4);
+        assert_eq!(find("FindAgent").blocks[1].fields[0].field_type, codegen::FieldType::F64);
+        assert!(find("TeleportFinish").deprecated);
+
+        // Shared messages keep upstream's numbers and layouts in the shipped subset
+        let shipped = codegen::parse(include_str!("../message_template.msg")).unwrap();
+        for name in ["EnableSimulator", "TeleportFinish"] {
+            let ours = shipped.messages.iter().find(|message| message.name == name).unwrap();
+            let theirs = find(name);
+            assert_eq!((ours.frequency, ours.number), (theirs.frequency, theirs.number), "{}", name);
+            let layout = |message: &codegen::Message| -> Vec<(String, codegen::FieldType)> {
+                message.blocks.iter().flat_map(|block| &block.fields).map(|field| (field.name.clone(), field.field_type.clone())).collect()
+            };
+            assert_eq!(layout(ours), layout(theirs), "{}", name);
+        }
+
+        let types = codegen::emit_message_types(&upstream);
+        assert!(types.contains("Self::CameraConstraint"));
+        let bodies = codegen::emit_message_bodies(&upstream);
+        assert!(bodies.contains("pub struct ObjectAdd "));
+    }
+
+    #[test]
+    fn test_codegen_keeps_generated_names_distinct() {
+        let source = "
+            { Ping Low 1 NotTrusted Unencoded
+                { Data Single { SimIP IPADDR } { SimIp IPPORT } { Self U8 } { Type U8 } }
+                { DATA Variable { Crate U32 } }
+            }
+            { PingData Low 2 NotTrusted Unencoded { Info Single { Value U32 } } }
+            { Result Low 3 NotTrusted Unencoded }
+        ";
+        assert!(codegen::parse(source).unwrap_err().contains("Result"));
+
+        let template = codegen::parse(&source.replace("Result", "Pong")).unwrap();
+        let ping = &template.messages[0];
+        assert_eq!(ping.blocks[0].type_name, "PingDataBlock");
+        assert_eq!(ping.blocks[1].type_name, "PingDATA");
+        assert_eq!(template.messages[1].blocks[0].type_name, "PingDataInfo");
+        assert_eq!((ping.blocks[0].ident.as_str(), ping.blocks[1].ident.as_str()), ("data", "data_2"));
+        let fields: Vec<&str> = ping.blocks[0].fields.iter().map(|field| field.ident.as_str()).collect();
+        assert_eq!(fields, ["sim_ip", "sim_ip_2", "self_", "r#type"]);
+        assert_eq!(ping.blocks[1].fields[0].ident, "crate_");
+
+        let bodies = codegen::emit_message_bodies(&template);
+        assert!(bodies.contains("pub struct PingDataBlock {"));
+        assert!(bodies.contains("pub data_2: Vec<PingDATA>,"));
+        assert!(bodies.contains("w.ip_port(self.sim_ip_2)"));
+        assert!(bodies.contains("/// `SimIp` in the template\n    pub sim_ip_2: u16,"));
+    }
+
+    #[test]
+    fn test_hand_written_bodies_match_template() {
+        let region_id = uuid::Uuid::new_v4();
+        let handshake = RegionHandshake {
+            region_flags: 4,
+            sim_access: 13,
+            sim_name: "Storm Island".to_string(),
+            sim_owner: uuid::Uuid::new_v4(),
+            is_estate_manager: true,
+            water_height: 20.0,
+            billable_factor: 1.0,
+            cache_id: uuid::Uuid::new_v4(),
+            terrain_textures: [uuid::Uuid::new_v4(); 4],
+            terrain_detail: [uuid::Uuid::nil(); 4],
+            terrain_start_heights: [10.0; 4],
+            terrain_height_ranges: [60.0; 4],
+            region_id,
+        };
+        let generated = template::RegionHandshake::from_payload(&handshake.to_payload()).unwrap();
+        assert_eq!(generated.region_info.sim_name, b"Storm Island\0");
+        assert_eq!(generated.region_info.terrain_height_range11, 60.0);
+        assert_eq!(generated.region_info2.region_id, region_id);
+        assert!(generated.region_info4.is_empty());
+
+        let object = ObjectUpdateBlock {
+            local_id: 77,
+            state: 0,
+            full_id: uuid::Uuid::new_v4(),
+            crc: 1,
+            pcode: ObjectUpdateBlock::PCODE_PRIM,
+            material: 3,
+            click_action: 0,
+            scale: [1.0, 2.0, 3.0],
+            position: [128.0, 128.0, 30.0],
+            velocity: [0.0; 3],
+            acceleration: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            angular_velocity: [0.0; 3],
+            parent_id: 0,
+            update_flags: 0,
+            texture_entry: vec![1, 2, 3],
+            name_value: String::new(),
+            text: "Hello".to_string(),
+            owner_id: uuid::Uuid::new_v4(),
+        };
+        let update = ObjectUpdate {
+            region_handle: 1,
+            time_dilation: u16::MAX,
+            objects: vec![object.clone()],
+        };
+        let generated = template::ObjectUpdate::from_payload(&update.to_payload()).unwrap();
+        let block = &generated.object_data[0];
+        assert_eq!(block.id, 77);
+        assert_eq!(block.full_id, object.full_id);
+        assert_eq!(block.p_code, ObjectUpdateBlock::PCODE_PRIM);
+        assert_eq!(block.text, b"Hello\0");
+        assert_eq!(block.owner_id, object.owner_id);
+        assert_eq!(ObjectUpdate::from_payload(&generated.to_payload()).unwrap(), update);
+    }
+
+    /// Stand-in HTTP service: answers each connection with the next canned response, with
+    /// `{base}` replaced by its own address, and hands back the request bodies it received
+    async fn serve_http(responses: Vec<String>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let url = format!("{}/login", base);
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                let body = loop {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..read]);
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length: usize = text[..end]
+                            .lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                            .unwrap_or(0);
+                        if data.len() >= end + 4 + length {
+                            break text[end + 4..].to_string();
+                        }
+                    }
+                };
+                requests.push(body);
+                let (head, body) = response.split_once("\r\n\r\n").unwrap();
+                let body = body.replace("{base}", &base);
+                let head: Vec<String> = head
+                    .lines()
+                    .map(|line| match line.to_ascii_lowercase().starts_with("content-length:") {
+                        true => format!("Content-Length: {}", body.len()),
+                        false => line.to_string(),
+                    })
+                    .collect();
+                stream.write_all(format!("{}\r\n\r\n{}", head.join("\r\n"), body).as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn xmlrpc_reply(members: &str) -> String {
+        let body = format!("<?xml version=\"1.0\"?><methodResponse><params><param><value><struct>{}</struct></value></param></params></methodResponse>", members);
+        format!("HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+
+    fn member(name: &str, value: &str) -> String {
+        format!("<member><name>{}</name><value>{}</value></member>", name, value)
+    }
+
+    const AGENT: &str = "11111111-2222-3333-4444-555555555555";
+    const ROOT: &str = "aaaaaaaa-0000-0000-0000-000000000001";
+
+    fn successful_login() -> String {
+        let folder = |id: &str, parent: &str, name: &str, kind: i32| {
+            format!(
+                "<value><struct>{}{}{}{}{}</struct></value>",
+                member("folder_id", id),
+                member("parent_id", parent),
+                member("name", name),
+                member("type_default", &format!("<i4>{}</i4>", kind)),
+                member("version", "<i4>3</i4>")
+            )
+        };
+        xmlrpc_reply(&[
+            member("login", "true"),
+            member("agent_id", AGENT),
+            member("session_id", "22222222-0000-0000-0000-000000000000"),
+            member("secure_session_id", "33333333-0000-0000-0000-000000000000"),
+            member("first_name", "\"Storm\""),
+            member("last_name", "Tester"),
+            member("circuit_code", "<i4>987654</i4>"),
+            member("sim_ip", "127.0.0.1"),
+            member("sim_port", "<i4>9005</i4>"),
+            member("region_x", "<i4>256000</i4>"),
+            member("region_y", "<i4>257024</i4>"),
+            member("seed_capability", "http://127.0.0.1:9005/CAPS/abc0000/"),
+            member("look_at", "[r0.5,r0.25,r0]"),
+            // Reordered, with a nested map whose keys shadow the real ones
+            member(
+                "home",
+                "{'look_at':[r0,r1,r0], 'last':{'position':[r1,r2,r3],'region_handle':[i0,i0]}, \
+                 'region_handle':[r256000,r257024], 'position':[r128,r64,r22.5]}",
+            ),
+            member("message", "Welcome &amp; enjoy"),
+            member("inventory-root", &format!("<array><data><value><struct>{}</struct></value></data></array>", member("folder_id", ROOT))),
+            member(
+                "inventory-skeleton",
+                &format!(
+                    "<array><data>{}{}</data></array>",
+                    folder(ROOT, "00000000-0000-0000-0000-000000000000", "My Inventory", 8),
+                    folder("aaaaaaaa-0000-0000-0000-000000000002", ROOT, "Notecards", 7)
+                ),
+            ),
+            member(
+                "buddy-list",
+                &format!(
+                    "<array><data><value><struct>{}{}{}</struct></value></data></array>",
+                    member("buddy_id", "44444444-0000-0000-0000-000000000000"),
+                    member("buddy_rights_given", "<i4>1</i4>"),
+                    member("buddy_rights_has", "<i4>3</i4>")
+                ),
+            ),
+        ]
+        .concat())
+    }
+
+    #[tokio::test]
+    async fn test_login_to_stand_in_grid() {
+        let (url, server) = serve_http(vec![successful_login()]).await;
+
+        let mut params = LoginParams::new("Storm", "Tester", "secret");
+        params.start = "uri:Storm Island&128&64&25".parse().unwrap();
+        let response = login_to_grid(&url, &params).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].contains("<methodName>login_to_simulator</methodName>"));
+        assert!(requests[0].contains("$1$5ebe2294ecd0e0f08eab7690d2a6ee69"));
+        assert!(requests[0].contains("uri:Storm Island&amp;128&amp;64&amp;25"));
+
+        assert_eq!(response.agent_id.to_string(), AGENT);
+        assert_eq!(response.first_name, "Storm");
+        assert_eq!(response.circuit_code, 987654);
+        assert_eq!(response.sim_address(), "127.0.0.1:9005".parse().unwrap());
+        assert_eq!(response.region_handle(), (256000u64 << 32) | 257024);
+        assert_eq!(response.look_at, [0.5, 0.25, 0.0]);
+        assert_eq!(response.message, "Welcome & enjoy");
+        assert!(response.seed_capability.ends_with("/CAPS/abc0000/"));
+
+        let home = response.home.clone().unwrap();
+        assert_eq!(home.region_handle, response.region_handle());
+        assert_eq!(home.position, [128.0, 64.0, 22.5]);
+        assert_eq!(home.look_at, [0.0, 1.0, 0.0]);
+
+        assert_eq!(response.inventory_root.unwrap().to_string(), ROOT);
+        assert_eq!(response.inventory_skeleton.len(), 2);
+        assert_eq!(response.inventory_skeleton[1].name, "Notecards");
+        assert_eq!(response.inventory_skeleton[1].parent_id.to_string(), ROOT);
+        assert_eq!(response.buddy_list[0].rights_has, 3);
+    }
+
+    #[tokio::test]
+    async fn test_login_follows_http_and_login_redirects() {
+        let moved = "HTTP/1.1 302 Found\r\nLocation: /moved\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+        let indeterminate = xmlrpc_reply(
+            &[
+                member("login", "indeterminate"),
+                member("next_url", "/second"),
+                member("next_method", "login_to_simulator"),
+                member("message", "Please wait"),
+            ]
+            .concat(),
+        );
+        let (url, server) = serve_http(vec![moved, indeterminate, successful_login()]).await;
+
+        let response = login_to_grid(&url, &LoginParams::new("Storm", "Tester", "secret")).await.unwrap();
+        assert_eq!(response.circuit_code, 987654);
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|request| request.contains("<methodName>login_to_simulator</methodName>")));
+    }
+
+    #[tokio::test]
+    async fn test_login_failure_reasons() {
+        for (reason, expected) in [("presence", LoginFailure::Presence), ("key", LoginFailure::Key), ("update", LoginFailure::Update)] {
+            let reply = xmlrpc_reply(&[member("login", "false"), member("reason", reason), member("message", "Nope")].concat());
+            let (url, server) = serve_http(vec![reply]).await;
+
+            let error = login_to_grid(&url, &LoginParams::new("Storm", "Tester", "wrong")).await.unwrap_err();
+            server.await.unwrap();
+            match error.downcast_ref::<LoginError>() {
+                Some(LoginError::Rejected { reason, message }) => {
+                    assert_eq!(*reason, expected);
+                    assert_eq!(message, "Nope");
+                }
+                other => panic!("unexpected error {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_start_location_and_password_hash() {
+        assert_eq!("last".parse::<StartLocation>().unwrap(), StartLocation::Last);
+        assert_eq!(StartLocation::Home.to_string(), "home");
+        let region: StartLocation = "uri:Storm Island&128&64&25".parse().unwrap();
+        assert_eq!(region.to_string(), "uri:Storm Island&128&64&25");
+        assert!("uri:Storm Island&128".parse::<StartLocation>().is_err());
+
+        let hashed = hash_password("secret");
+        assert_eq!(hashed, "$1$5ebe2294ecd0e0f08eab7690d2a6ee69");
+        assert_eq!(hash_password(&hashed), hashed);
+    }
+
+    fn circuit_pair() -> (Circuit, Circuit) {
+        let session_id = uuid::Uuid::new_v4();
+        (Circuit::new(7, session_id, uuid::Uuid::nil()), Circuit::new(7, session_id, uuid::Uuid::nil()))
+    }
+
+    fn chat(message: &str) -> ChatFromViewer {
+        ChatFromViewer {
+            agent_id: uuid::Uuid::new_v4(),
+            session_id: uuid::Uuid::new_v4(),
+            message: message.to_string(),
+            chat_type: 1,
+            channel: 0,
+        }
+    }
+
+    #[test]
+    fn test_circuit_acks_piggyback_and_batch() {
+        let (mut sim, mut viewer) = circuit_pair();
+        let start = Instant::now();
+
+        let first = viewer.send_message(&chat("one"), true, start).unwrap();
+        assert_ne!(first[0] & FLAG_RELIABLE, 0);
+        let delivered = sim.receive(&first, start).unwrap().unwrap();
+        assert_eq!(delivered.message_type, LLUDPMessageType::ChatFromViewer);
+        assert_eq!(sim.acks_pending, vec![1]);
+
+        // The owed ack rides on the next outgoing packet
+        let reply = sim.send_message(&chat("reply"), false, start).unwrap();
+        assert_eq!(deserialize_packet(&reply).unwrap().acks, vec![1]);
+        viewer.receive(&reply, start + Duration::from_millis(80)).unwrap();
+        assert!(viewer.packets_pending.is_empty());
+        assert_eq!(viewer.rtt().srtt(), Some(Duration::from_millis(80)));
+
+        // With nothing to piggyback on, acks go out as a PacketAck once the delay passes
+        let second = viewer.send_message(&chat("two"), true, start).unwrap();
+        sim.receive(&second, start).unwrap();
+        assert!(sim.poll(start).unwrap().is_empty());
+        let acks = sim.poll(start + sim.config().ack_delay).unwrap();
+        assert_eq!(acks.len(), 1);
+        let ack = deserialize_packet(&acks[0]).unwrap();
+        assert_eq!(ack.message_type, LLUDPMessageType::PacketAck);
+        assert!(!ack.is_reliable());
+        assert!(viewer.receive(&acks[0], start).unwrap().is_none());
+        assert!(viewer.packets_pending.is_empty());
+        assert_eq!(sim.stats().acks_sent, 1);
+    }
+
+    #[test]
+    fn test_circuit_resends_filters_duplicates_and_times_out() {
+        let (mut sim, mut viewer) = circuit_pair();
+        let start = Instant::now();
+
+        let original = viewer.send_message(&chat("lost ack"), true, start).unwrap();
+        assert!(sim.receive(&original, start).unwrap().is_some());
+
+        let rto = viewer.rtt().rto();
+        assert!(viewer.poll(start + rto / 2).unwrap().is_empty());
+        let resends = viewer.poll(start + rto).unwrap();
+        assert_eq!(resends.len(), 1);
+        assert_ne!(resends[0][0] & FLAG_RESENT, 0);
+
+        // A resend is acknowledged again but not delivered twice
+        sim.acks_pending.clear();
+        assert!(sim.receive(&resends[0], start + rto).unwrap().is_none());
+        assert_eq!(sim.acks_pending, vec![1]);
+        assert_eq!(sim.stats().duplicates, 1);
+
+        // Resend timeouts double until the circuit gives up
+        let mut now = start + rto;
+        for resend in 2..=viewer.config().max_resends {
+            now += viewer.rtt().backoff(resend);
+            let resent = viewer.poll(now).unwrap().iter().filter(|data| data[0] & FLAG_RESENT != 0).count();
+            assert_eq!(resent, 1, "resend {}", resend);
+        }
+        now += viewer.rtt().backoff(viewer.config().max_resends + 1);
+        assert!(viewer.poll(now).is_err());
+        assert!(viewer.is_closed());
+        assert!(viewer.send_message(&chat("late"), true, now).is_err());
+    }
+
+    #[test]
+    fn test_circuit_ping_check_latency() {
+        let (mut sim, mut viewer) = circuit_pair();
+        let start = Instant::now();
+        let interval = sim.config().ping_interval;
+
+        assert!(sim.poll(start).unwrap().is_empty());
+        let pings = sim.poll(start + interval).unwrap();
+        assert_eq!(pings.len(), 1);
+        assert_eq!(deserialize_packet(&pings[0]).unwrap().message_type, LLUDPMessageType::StartPingCheck);
+
+        // The viewer answers from its next poll
+        assert!(viewer.receive(&pings[0], start + interval).unwrap().is_none());
+        let replies = viewer.poll(start + interval).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(deserialize_packet(&replies[0]).unwrap().message_type, LLUDPMessageType::CompletePingCheck);
+
+        sim.receive(&replies[0], start + interval + Duration::from_millis(35)).unwrap();
+        assert_eq!(sim.ping(), Some(Duration::from_millis(35)));
+        assert_eq!(sim.stats().ping_ms, Some(35.0));
+    }
+
+    #[test]
+    fn test_texture_entry_round_trip() {
+        let mut entry = TextureEntry::default();
+        entry.default.texture_id = uuid::Uuid::from_u128(1);
+        entry.default.repeat_u = 2.0;
+        let mut face = entry.default.clone();
+        face.texture_id = uuid::Uuid::from_u128(2);
+        face.color = [255, 0, 0, 128];
+        entry.faces.insert(0, face.clone());
+        entry.faces.insert(5, face);
+        let mut bright = entry.default.clone();
+        bright.material = 0x20;
+        bright.glow = 1.0;
+        entry.faces.insert(MAX_FACES - 1, bright);
+
+        let decoded = TextureEntry::from_bytes(&entry.to_bytes()).unwrap();
+        assert_eq!(decoded, entry);
+        assert_eq!(decoded.face(3).texture_id, uuid::Uuid::from_u128(1));
+        assert_eq!(decoded.face(5).color, [255, 0, 0, 128]);
+
+        // Entries from older simulators end early and keep the remaining defaults
+        let short = &entry.to_bytes()[..16 + 1 + 16 + 1];
+        assert_eq!(TextureEntry::from_bytes(short).unwrap().default.repeat_u, 1.0);
+    }
+
+    fn scene_prim(local_id: u32, parent_id: u32, position: [f32; 3]) -> ObjectUpdateBlock {
+        ObjectUpdateBlock {
+            local_id,
+            state: 0,
+            full_id: uuid::Uuid::from_u128(local_id as u128),
+            crc: 7,
+            pcode: ObjectUpdateBlock::PCODE_PRIM,
+            material: 3,
+            click_action: 0,
+            scale: [1.0, 2.0, 3.0],
+            position,
+            velocity: [0.0; 3],
+            acceleration: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            angular_velocity: [0.0; 3],
+            parent_id,
+            update_flags: 0,
+            texture_entry: TextureEntry::default().to_bytes(),
+            name_value: String::new(),
+            text: "For sale".to_string(),
+            owner_id: uuid::Uuid::nil(),
+        }
+    }
+
+    fn object_packet(objects: Vec<ObjectUpdateBlock>) -> LLUDPPacket {
+        ObjectUpdate { region_handle: 0, time_dilation: 0xFFFF, objects }.to_packet()
+    }
+
+    #[test]
+    fn test_object_updates_link_parents_and_move_entities() {
+        let mut world = storm_ecs::World::new();
+        let mut objects = RegionObjects::new();
+
+        // A child can arrive before its root
+        let changes = objects.handle_packet(&mut world, &object_packet(vec![scene_prim(11, 10, [0.0, 0.0, 1.0])])).unwrap();
+        assert_eq!(changes.added, vec![11]);
+        let child = objects.entity(11).unwrap();
+        assert_eq!(world.get_component::<ParentLink>(child).unwrap().entity, None);
+
+        let mut avatar = scene_prim(20, 0, [128.0, 128.0, 25.0]);
+        avatar.pcode = ObjectUpdateBlock::PCODE_AVATAR;
+        avatar.name_value = "FirstName STRING RW SV Storm\nLastName STRING RW SV Tester".to_string();
+        objects.handle_packet(&mut world, &object_packet(vec![scene_prim(10, 0, [100.0, 90.0, 22.0]), avatar])).unwrap();
+
+        let root = objects.entity_by_full_id(uuid::Uuid::from_u128(10)).unwrap();
+        assert_eq!(world.get_component::<ParentLink>(child).unwrap().entity, Some(root));
+        let transform = world.get_component::<storm_ecs::Transform>(root).unwrap();
+        assert_eq!(transform.position, [100.0, 90.0, 22.0]);
+        assert_eq!(transform.scale, [1.0, 2.0, 3.0]);
+        assert_eq!(world.get_component::<PrimShape>(root).unwrap().path_curve, 0x10);
+        assert_eq!(world.get_component::<ObjectName>(root).unwrap().hover_text, "For sale");
+        let avatar = objects.entity(20).unwrap();
+        assert_eq!(world.get_component::<ObjectName>(avatar).unwrap().name.as_deref(), Some("Storm Tester"));
+        assert!(world.get_component::<OpenSimObject>(avatar).unwrap().is_avatar());
+
+        // Terse updates move known objects with quantized velocity and rotation
+        let mut data = PayloadWriter::new();
+        data.u32(10).u8(0).u8(0).vector3([101.0, 90.0, 22.0]);
+        for value in [65535, 32768, 32768, 32768, 32768, 32768, 32768, 32768, 32768, 65535, 32768, 32768, 32768] {
+            data.u16(value);
+        }
+        let terse = crate::template::ImprovedTerseObjectUpdate {
+            region_data: Default::default(),
+            object_data: vec![crate::template::ImprovedTerseObjectUpdateObjectData {
+                data: data.into_bytes(),
+                texture_entry: Vec::new(),
+            }],
+        };
+        assert_eq!(objects.handle_packet(&mut world, &terse.to_packet()).unwrap().updated, vec![10]);
+        assert_eq!(world.get_component::<storm_ecs::Transform>(root).unwrap().position, [101.0, 90.0, 22.0]);
+        assert_eq!(world.get_component::<storm_ecs::Transform>(root).unwrap().rotation, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(world.get_component::<storm_ecs::Velocity>(root).unwrap().linear, [128.0, 0.0, 0.0]);
+
+        // Killing the root takes its linked children with it
+        let kill = KillObject { local_ids: vec![10] };
+        let mut removed = objects.handle_packet(&mut world, &kill.to_packet()).unwrap().removed;
+        removed.sort();
+        assert_eq!(removed, vec![10, 11]);
+        assert_eq!(objects.len(), 1);
+        assert_eq!(world.entity_count(), 1);
+    }
+
+    #[test]
+    fn test_compressed_and_cached_object_updates() {
+        use crate::objects::compressed_flags::*;
+
+        let full_id = uuid::Uuid::new_v4();
+        let mut data = PayloadWriter::new();
+        data.uuid(full_id)
+            .u32(30)
+            .u8(crate::objects::PCODE_PRIM)
+            .u8(0)
+            .u32(99) // CRC
+            .u8(3)
+            .u8(0)
+            .vector3([0.5, 0.5, 0.5])
+            .vector3([10.0, 20.0, 30.0])
+            .quaternion([0.0, 0.0, 0.0, 1.0])
+            .u32(HAS_PARENT | HAS_TEXT | HAS_NAME_VALUES)
+            .uuid(uuid::Uuid::nil())
+            .u32(31) // parent
+            .bytes(b"Hello\0")
+            .bytes(&[255, 255, 255, 0])
+            .u8(0) // no extra params
+            .bytes(b"Title STRING RW SV Builder\0")
+            .u8(0x10)
+            .u16(0)
+            .u16(0)
+            .u8(100)
+            .u8(100)
+            .bytes(&[0; 9])
+            .u8(0x01)
+            .u16(0)
+            .u16(0)
+            .u16(25000);
+        let texture_entry = TextureEntry::default().to_bytes();
+        data.u32(texture_entry.len() as u32).bytes(&texture_entry);
+
+        let compressed = crate::template::ObjectUpdateCompressed {
+            region_data: Default::default(),
+            object_data: vec![crate::template::ObjectUpdateCompressedObjectData {
+                update_flags: 0,
+                data: data.into_bytes(),
+            }],
+        };
+        let mut world = storm_ecs::World::new();
+        let mut objects = RegionObjects::new();
+        assert_eq!(objects.handle_packet(&mut world, &compressed.to_packet()).unwrap().added, vec![30]);
+
+        let entity = objects.entity_by_full_id(full_id).unwrap();
+        assert_eq!(world.get_component::<storm_ecs::Transform>(entity).unwrap().position, [10.0, 20.0, 30.0]);
+        assert_eq!(world.get_component::<ParentLink>(entity).unwrap().parent_id, 31);
+        let name = world.get_component::<ObjectName>(entity).unwrap();
+        assert_eq!(name.hover_text, "Hello");
+        assert_eq!(name.name_values["Title"], "Builder");
+        assert_eq!(world.get_component::<PrimShape>(entity).unwrap().profile_hollow(), 0.5);
+
+        // Once gone, the object comes back from the cache while its CRC matches
+        objects.handle_packet(&mut world, &KillObject { local_ids: vec![30] }.to_packet()).unwrap();
+        assert!(objects.is_empty());
+        let cached = crate::template::ObjectUpdateCached {
+            region_data: Default::default(),
+            object_data: vec![
+                crate::template::ObjectUpdateCachedObjectData { id: 30, crc: 99, update_flags: 0 },
+                crate::template::ObjectUpdateCachedObjectData { id: 32, crc: 1, update_flags: 0 },
+            ],
+        };
+        let changes = objects.handle_packet(&mut world, &cached.to_packet()).unwrap();
+        assert_eq!(changes.added, vec![30]);
+        assert_eq!(changes.cache_misses, vec![32]);
+        assert_eq!(objects.local_id(full_id), Some(30));
+
+        let request = request_cache_misses(uuid::Uuid::nil(), uuid::Uuid::nil(), &changes.cache_misses);
+        assert_eq!(request.object_data[0].id, 32);
+    }
+
+    #[test]
+    fn test_terrain_patch_wire_layout() {
+        // Stride 264, 16 sample patches of land, then one patch at 1,2 holding only a DC coefficient of 512
+        let mut data = vec![0x08, 0x01, 0x10, b'L', 0x88];
+        data.extend_from_slice(&20.0f32.to_le_bytes());
+        data.extend_from_slice(&[0x01, 0x00, 0x22, 0x30, 0x05, 0x30, 0x80]);
+
+        let (group, patches) = decode_layer(&data).unwrap();
+        assert_eq!(group, LayerGroup { stride: 264, patch_size: 16, layer_type: b'L' });
+        assert_eq!(group.kind(), Some(LayerKind::Land));
+        assert_eq!(patches.len(), 1);
+        let header = patches[0].header;
+        assert_eq!((header.patch_x, header.patch_y, header.range), (1, 2, 1));
+        assert_eq!((header.prequant(), header.word_bits()), (10, 10));
+        // 512 / 16 from the transform, scaled by range / 1024 on top of the 20.5m midpoint
+        assert!(patches[0].samples.iter().all(|h| (h - 20.53125).abs() < 1e-4));
+
+        assert!(decode_layer(&data[..12]).is_err());
+    }
+
+    #[test]
+    fn test_terrain_layers_update_region_heightfield() {
+        let hills = |px: u32, py: u32| -> Vec<f32> {
+            (0..256)
+                .map(|i| {
+                    let (x, y) = ((px * 16 + i % 16) as f32, (py * 16 + i / 16) as f32);
+                    20.0 + 4.0 * (x / 9.0).sin() + 3.0 * (y / 7.0).cos()
+                })
+                .collect()
+        };
+        let mut world = storm_ecs::World::new();
+        let mut terrain = RegionTerrain::new(1099511628032000, 256, 256);
+
+        let first = hills(0, 0);
+        let second = hills(3, 2);
+        let data = encode_layer(terrain::layer_type::LAND, 16, &[([0, 0], &first), ([3, 2], &second)]);
+        let packet = terrain::layer_message(terrain::layer_type::LAND, data).to_packet();
+        let changes = terrain.handle_packet(&mut world, &packet).unwrap();
+        assert_eq!(changes.land, vec![[0, 0], [3, 2]]);
+
+        let entity = terrain.entity().unwrap();
+        let heightfield = world.get_component::<storm_ecs::Heightfield>(entity).unwrap();
+        assert_eq!((heightfield.width, heightfield.depth, heightfield.revision), (256, 256, 2));
+        for i in 0..256u32 {
+            let (x, y) = (i % 16, i / 16);
+            assert!((heightfield.get(x, y).unwrap() - first[i as usize]).abs() < 0.1);
+            assert!((heightfield.get(48 + x, 32 + y).unwrap() - second[i as usize]).abs() < 0.1);
+        }
+        assert_eq!(heightfield.get(100, 100), Some(0.0));
+
+        // Later patches replace only their own block
+        let flat = vec![35.0; 256];
+        let data = encode_layer(terrain::layer_type::LAND, 16, &[([0, 0], &flat)]);
+        terrain.apply_layer(&mut world, &terrain::layer_message(terrain::layer_type::LAND, data)).unwrap();
+        let heightfield = world.get_component::<storm_ecs::Heightfield>(entity).unwrap();
+        assert_eq!(heightfield.revision, 3);
+        assert!((heightfield.height_at(7.5, 7.5) - 35.0).abs() < 0.01);
+        assert!((heightfield.get(48, 32).unwrap() - second[0]).abs() < 0.1);
+
+        // Patches past the region edge are skipped
+        let data = encode_layer(terrain::layer_type::LAND_EXTENDED, 16, &[([16, 0], &flat)]);
+        let changes = terrain.apply_layer(&mut world, &terrain::layer_message(terrain::layer_type::LAND_EXTENDED, data));
+        assert!(changes.unwrap().is_empty());
+
+        // Wind arrives as x then y components for the whole region
+        let (east, north) = (vec![4.0; 256], vec![-2.0; 256]);
+        let data = encode_layer(terrain::layer_type::WIND, 16, &[([0, 0], &east), ([1, 0], &north)]);
+        assert!(terrain.apply_layer(&mut world, &terrain::layer_message(terrain::layer_type::WIND, data)).unwrap().wind);
+        let wind = world.get_component::<WindField>(entity).unwrap().at(5, 5);
+        assert!((wind[0] - 4.0).abs() < 0.01 && (wind[1] + 2.0).abs() < 0.01);
+
+        terrain.clear(&mut world);
+        assert_eq!(world.entity_count(), 0);
+    }
+
+    fn arbitrary_llsd() -> impl Strategy<Value = Llsd> {
+        // NaN never compares equal and text encodings keep dates to the millisecond
+        let text = "[a-zA-Z0-9 '\"<>&\\\\\n\t\r\u{e9}\u{4e2d}]{0,12}";
+        let leaf = prop_oneof![
+            Just(Llsd::Undefined),
+            any::<bool>().prop_map(Llsd::Boolean),
+            any::<i32>().prop_map(Llsd::Integer),
+            any::<f64>().prop_filter("nan", |v| !v.is_nan()).prop_map(Llsd::Real),
+            text.prop_map(Llsd::String),
+            any::<u128>().prop_map(|v| Llsd::Uuid(uuid::Uuid::from_u128(v))),
+            (-62_135_596_800_000i64..253_402_300_799_000).prop_map(|ms| Llsd::Date(ms as f64 / 1000.0)),
+            text.prop_map(Llsd::Uri),
+            proptest::collection::vec(any::<u8>(), 0..24).prop_map(Llsd::Binary),
+        ];
+        leaf.prop_recursive(4, 48, 6, move |inner| {
+            prop_oneof![
+                proptest::collection::vec(inner.clone(), 0..6).prop_map(Llsd::Array),
+                proptest::collection::btree_map(text, inner, 0..6).prop_map(Llsd::Map),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_llsd_round_trips_every_format(value in arbitrary_llsd()) {
+            for format in [LlsdFormat::Xml, LlsdFormat::Binary, LlsdFormat::Notation] {
+                let encoded = value.encode(format);
+                prop_assert_eq!(LlsdFormat::detect(&encoded), format);
+                prop_assert_eq!(&Llsd::parse(&encoded).unwrap(), &value);
+            }
+        }
+
+        #[test]
+        fn test_llsd_parsers_survive_garbage(data in proptest::collection::vec(any::<u8>(), 0..64)) {
+            for format in [LlsdFormat::Xml, LlsdFormat::Binary, LlsdFormat::Notation] {
+                let _ = Llsd::parse_as(format, &data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_llsd_xml_reference_sample() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>region_id</key>
+  <uuid>67153d5b-3659-afb4-8510-adda2c034649</uuid>
+  <key>scale</key>
+  <string>one minute</string>
+  <key>simulator statistics</key>
+  <map>
+    <key>time dilation</key><real>0.9878624</real>
+    <key>sim fps</key><real>44.38898</real>
+    <key>agent updates per second</key><real>nan</real>
+    <key>total task count</key><real>4</real>
+    <key>active task count</key><real>0</real>
+    <key>pending uploads</key><real>0.0001096525</real>
+  </map>
+  <key>flags</key><array><boolean>1</boolean><boolean /><integer /><undef /></array>
+  <key>born</key><date>2006-02-01T14:29:53.43Z</date>
+  <key>data</key><binary encoding="base16">48656C6C6F</binary>
+  <key>blob</key><binary>SGVsbG8=</binary>
+</map>
+</llsd>"#;
+        let value = Llsd::parse(xml.as_bytes()).unwrap();
+        assert_eq!(value.get("region_id").as_uuid().to_string(), "67153d5b-3659-afb4-8510-adda2c034649");
+        assert_eq!(value.get("scale").as_string(), "one minute");
+        let stats = value.get("simulator statistics");
+        assert_eq!(stats.get("sim fps").as_real(), 44.38898);
+        assert!(stats.get("agent updates per second").as_real().is_nan());
+        assert_eq!(stats.get("total task count").as_integer(), 4);
+        assert_eq!(
+            value.get("flags"),
+            &Llsd::Array(vec![Llsd::Boolean(true), Llsd::Boolean(false), Llsd::Integer(0), Llsd::Undefined])
+        );
+        assert_eq!(value.get("born").as_date(), 1138804193.43);
+        assert_eq!(value.get("born").as_string(), "2006-02-01T14:29:53.430Z");
+        assert_eq!(value.get("data").as_binary(), b"Hello");
+        assert_eq!(value.get("blob").as_binary(), b"Hello");
+        assert!(value.get("missing").is_undefined());
+    }
+
+    #[test]
+    fn test_llsd_notation_reference_sample() {
+        let notation = r#"[
+  {'destination':'http://secondlife.com'},
+  {'version':i1},
+  {
+    'agent_id':u3c115e51-04f4-523c-9fa6-98aff1034730,
+    "circuit_code":i1075,
+    'first_name':'Phoenix',
+    'last_name':s(6)"Linden",
+    'position':[r70.9247,r254.378,r38.7304],
+    'look_at':[r-0.043753,r-0.999042,r0],
+    'online':true, 'banned':F, 'greeting':'it\'s \x41 \"test\"\n',
+    'seed':l"https://sim.example.com:12043/cap/abc",
+    'born':d"2006-02-01T14:29:53Z",
+    'hash':b64"SGVsbG8=", 'raw':b(3)"a"c", 'hex':b16"FF00",
+    'nothing':!
+  }
+]"#;
+        let value = Llsd::parse(notation.as_bytes()).unwrap();
+        assert_eq!(value.at(0).get("destination").as_string(), "http://secondlife.com");
+        assert_eq!(value.at(1).get("version"), &Llsd::Integer(1));
+        let agent = value.at(2);
+        assert_eq!(agent.get("agent_id").as_uuid().to_string(), "3c115e51-04f4-523c-9fa6-98aff1034730");
+        assert_eq!(agent.get("circuit_code").as_integer(), 1075);
+        assert_eq!(agent.get("last_name").as_string(), "Linden");
+        assert_eq!(agent.get("position").at(2), &Llsd::Real(38.7304));
+        assert_eq!(agent.get("look_at").at(1).as_real(), -0.999042);
+        assert_eq!(agent.get("online"), &Llsd::Boolean(true));
+        assert_eq!(agent.get("banned"), &Llsd::Boolean(false));
+        assert_eq!(agent.get("greeting").as_string(), "it's A \"test\"\n");
+        assert_eq!(agent.get("seed"), &Llsd::Uri("https://sim.example.com:12043/cap/abc".to_string()));
+        assert_eq!(agent.get("born"), &Llsd::Date(1138804193.0));
+        assert_eq!(agent.get("hash").as_binary(), b"Hello");
+        assert_eq!(agent.get("raw").as_binary(), b"a\"c");
+        assert_eq!(agent.get("hex").as_binary(), vec![0xFF, 0x00]);
+        assert!(agent.get("nothing").is_undefined());
+
+        assert_eq!(Llsd::from_iter([("a", Llsd::Integer(1))]).to_string(), "{'a':i1}");
+        assert!(Llsd::parse_as(LlsdFormat::Notation, b"[i1,i2").is_err());
+        assert!(Llsd::parse_as(LlsdFormat::Notation, b"{'a' i1}").is_err());
+        assert!(Llsd::parse_as(LlsdFormat::Notation, b"s(10)\"short\"").is_err());
+    }
+
+    #[test]
+    fn test_llsd_binary_reference_sample() {
+        let mut data = llsd::binary::HEADER.to_vec();
+        data.extend_from_slice(b"{\x00\x00\x00\x02");
+        data.extend_from_slice(b"k\x00\x00\x00\x01ai\x00\x00\x01\x2c");
+        data.extend_from_slice(b"k\x00\x00\x00\x01b[\x00\x00\x00\x03");
+        data.extend_from_slice(b"1s\x00\x00\x00\x02hi");
+        data.extend_from_slice(b"r\x3f\xf8\x00\x00\x00\x00\x00\x00");
+        data.extend_from_slice(b"]}");
+
+        let expected: Llsd = [
+            ("a", Llsd::Integer(300)),
+            ("b", Llsd::Array(vec![Llsd::Boolean(true), Llsd::from("hi"), Llsd::Real(1.5)])),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(Llsd::parse(&data).unwrap(), expected);
+        assert_eq!(expected.encode(LlsdFormat::Binary), data);
+        assert_eq!(llsd::binary::from_slice(&data[llsd::binary::HEADER.len()..]).unwrap(), expected);
+
+        // Truncation, trailing bytes and hostile lengths are errors, not panics
+        for end in 0..data.len() {
+            assert!(llsd::binary::from_slice(&data[..end]).is_err());
+        }
+        assert!(llsd::binary::from_slice(&[data.as_slice(), b"!"].concat()).is_err());
+        assert!(llsd::binary::from_slice(b"[\xff\xff\xff\xff").is_err());
+        assert!(llsd::binary::from_slice(b"s\xff\xff\xff\xffab").is_err());
+    }
+
+    #[test]
+    fn test_llsd_rejects_deep_nesting() {
+        let depth = llsd::MAX_DEPTH + 1;
+        let notation = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Llsd::parse_as(LlsdFormat::Notation, notation.as_bytes()).is_err());
+        let binary = [b"[\x00\x00\x00\x01".repeat(depth), b"!".to_vec(), b"]".repeat(depth)].concat();
+        assert!(llsd::binary::from_slice(&binary).is_err());
+        let xml = format!("<llsd>{}{}</llsd>", "<array>".repeat(depth), "</array>".repeat(depth));
+        assert!(Llsd::parse(xml.as_bytes()).is_err());
+
+        let ok = llsd::MAX_DEPTH - 1;
+        let notation = format!("{}{}", "[".repeat(ok), "]".repeat(ok));
+        assert!(Llsd::parse_as(LlsdFormat::Notation, notation.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_llsd_rejects_malformed_base16() {
+        for data in ["aéb", "+f", "0g", "abc"] {
+            let xml = format!(r#"<llsd><binary encoding="base16">{}</binary></llsd>"#, data);
+            assert!(Llsd::parse(xml.as_bytes()).is_err(), "XML base16 {:?}", data);
+            let notation = format!(r#"b16"{}""#, data);
+            assert!(Llsd::parse(notation.as_bytes()).is_err(), "notation base16 {:?}", data);
+        }
+        let value = Llsd::parse(br#"<llsd><binary encoding="base16">00fFa9</binary></llsd>"#).unwrap();
+        assert_eq!(value.as_binary(), vec![0x00, 0xFF, 0xA9]);
+    }
+
+    #[test]
+    fn test_llsd_serde_round_trip_and_conversions() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        enum Access {
+            General,
+            Mature { rating: u8 },
+        }
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct RegionInfo {
+            region_id: uuid::Uuid,
+            region_handle: u64,
+            name: String,
+            position: [f32; 3],
+            neighbours: Vec<u32>,
+            estate: Option<i64>,
+            owner: Option<String>,
+            texture: Vec<u8>,
+            access: Access,
+            fallback: Access,
+        }
+
+        let info = RegionInfo {
+            region_id: uuid::Uuid::from_u128(0x1234),
+            region_handle: (256_000u64 << 32) | 256_256,
+            name: "Sandbox".to_string(),
+            position: [128.0, 64.5, 22.25],
+            neighbours: vec![1, 3_000_000_000],
+            estate: Some(1 << 40),
+            owner: None,
+            texture: vec![0, 1, 2, 255],
+            access: Access::Mature { rating: 2 },
+            fallback: Access::General,
+        };
+
+        let value = to_llsd(&info).unwrap();
+        assert_eq!(value.get("region_handle"), &Llsd::Binary(info.region_handle.to_be_bytes().to_vec()));
+        assert_eq!(value.get("region_id"), &Llsd::String("00000000-0000-0000-0000-000000001234".to_string()));
+        assert_eq!(value.get("neighbours").at(1), &Llsd::Real(3_000_000_000.0));
+        assert!(value.get("owner").is_undefined());
+        assert_eq!(value.get("fallback"), &Llsd::from("General"));
+        assert_eq!(value.get("access").get("Mature").get("rating"), &Llsd::Integer(2));
+
+        for format in [LlsdFormat::Xml, LlsdFormat::Binary, LlsdFormat::Notation] {
+            let parsed = Llsd::parse(&value.encode(format)).unwrap();
+            assert_eq!(from_llsd::<RegionInfo>(parsed).unwrap(), info);
+        }
+
+        // Typed LLSD converts to the fields it is read into
+        let typed: Llsd = [
+            ("region_id", Llsd::Uuid(info.region_id)),
+            ("region_handle", Llsd::from(info.region_handle)),
+            ("name", Llsd::from("Sandbox")),
+            ("position", Llsd::from([128.0, 64.5, 22.25])),
+            ("neighbours", Llsd::Array(vec![Llsd::from("1"), Llsd::Real(3e9)])),
+            ("estate", Llsd::Real((1u64 << 40) as f64)),
+            ("texture", Llsd::Binary(vec![0, 1, 2, 255])),
+            ("access", Llsd::from_iter([("Mature", Llsd::from_iter([("rating", Llsd::from("2"))]))])),
+            ("fallback", Llsd::from("General")),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(from_llsd::<RegionInfo>(typed.clone()).unwrap(), info);
+
+        let generic: Llsd = serde_json::from_str(&serde_json::to_string(&typed).unwrap()).unwrap();
+        assert_eq!(generic.get("name"), typed.get("name"));
+        assert_eq!(generic.get("region_id").as_uuid(), info.region_id);
+    }
+
+    fn llsd_reply(status: &str, body: &Llsd) -> String {
+        let body = String::from_utf8(body.encode(LlsdFormat::Xml)).unwrap();
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/llsd+xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    fn event_batch(id: i32, events: Vec<(&str, Llsd)>) -> String {
+        let events = events
+            .into_iter()
+            .map(|(message, body)| Llsd::from_iter([("message", Llsd::from(message)), ("body", body)]))
+            .collect::<Vec<_>>();
+        llsd_reply("200 OK", &Llsd::from_iter([("id", Llsd::Integer(id)), ("events", Llsd::Array(events))]))
+    }
+
+    #[tokio::test]
+    async fn test_seed_capability_and_event_queue_acks() {
+        let east = (256256u64 << 32) | 256000;
+        let seed = Llsd::from_iter([
+            ("EventQueueGet", Llsd::Uri("{base}/eq".to_string())),
+            ("GetTexture", Llsd::from("{base}/texture")),
+            ("ViewerAsset", Llsd::from("")),
+        ]);
+        let enable = Llsd::from_iter([(
+            "SimulatorInfo",
+            Llsd::Array(vec![Llsd::from_iter([
+                ("Handle", Llsd::from(east)),
+                ("IP", Llsd::Binary(vec![127, 0, 0, 1])),
+                ("Port", Llsd::Integer(9001)),
+            ])]),
+        )]);
+        let establish = Llsd::from_iter([
+            ("agent-id", Llsd::Uuid(uuid::Uuid::nil())),
+            ("sim-ip-and-port", Llsd::from("127.0.0.1:9001")),
+            ("seed-capability", Llsd::from("http://127.0.0.1:9001/CAPS/child/")),
+        ]);
+        let teleport = Llsd::from_iter([(
+            "Info",
+            Llsd::Array(vec![Llsd::from_iter([
+                ("AgentID", Llsd::Uuid(uuid::Uuid::nil())),
+                ("SimIP", Llsd::Binary(vec![10, 0, 0, 2])),
+                ("SimPort", Llsd::Integer(9010)),
+                ("RegionHandle", Llsd::from((512000u64 << 32) | 512000)),
+                ("SeedCapability", Llsd::from("http://10.0.0.2:9010/CAPS/tp/")),
+                ("TeleportFlags", Llsd::Binary(vec![0, 0, 0, 16])),
+            ])]),
+        )]);
+        let crossed = Llsd::from_iter([
+            (
+                "RegionData",
+                Llsd::Array(vec![Llsd::from_iter([
+                    ("SimIP", Llsd::Binary(vec![127, 0, 0, 1])),
+                    ("SimPort", Llsd::Integer(9001)),
+                    ("RegionHandle", Llsd::from(east)),
+                    ("SeedCapability", Llsd::from("http://127.0.0.1:9001/CAPS/root/")),
+                ])]),
+            ),
+            ("Info", Llsd::Array(vec![Llsd::from_iter([("Position", Llsd::from([2.0, 128.0, 20.0]))])])),
+        ]);
+
+        let (url, server) = serve_http(vec![
+            llsd_reply("200 OK", &seed),
+            event_batch(1, vec![("EnableSimulator", enable), ("EstablishAgentCommunication", establish)]),
+            "HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            event_batch(2, vec![("TeleportFinish", teleport), ("CrossedRegion", crossed), ("ParcelProperties", Llsd::map())]),
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        ])
+        .await;
+        let base = url.trim_end_matches("/login").to_string();
+
+        let client = CapsClient::new(CapsConfig::default()).unwrap();
+        let mut session = CapsSession::new(client);
+        let capabilities = session.connect_region(7, &format!("{}/seed", base)).await.unwrap().clone();
+        assert_eq!(capabilities.get(EVENT_QUEUE_GET), Some(format!("{}/eq", base).as_str()));
+        assert_eq!(session.registry().url(7, "GetTexture"), Some(format!("{}/texture", base).as_str()));
+        assert!(capabilities.get("ViewerAsset").is_none());
+        assert!(matches!(
+            capabilities.url("GetMesh").unwrap_err().downcast_ref::<CapsError>(),
+            Some(CapsError::Missing(name)) if name == "GetMesh"
+        ));
+
+        let mut events = Vec::new();
+        while let Some(event) = session.next_event().await {
+            assert_eq!(event.region_handle, 7);
+            events.push(event);
+        }
+        let messages: Vec<&str> = events.iter().map(|event| event.message.as_str()).collect();
+        assert_eq!(messages, ["EnableSimulator", "EstablishAgentCommunication", "TeleportFinish", "CrossedRegion", "ParcelProperties"]);
+
+        let decoded: Vec<SimulatorEvent> = events.iter().flat_map(CapsEvent::simulator_events).collect();
+        let east_addr: std::net::SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        assert_eq!(decoded, vec![
+            SimulatorEvent::EnableSimulator { region_handle: east, sim_addr: east_addr },
+            SimulatorEvent::EstablishAgentCommunication {
+                sim_addr: east_addr,
+                seed_capability: "http://127.0.0.1:9001/CAPS/child/".to_string(),
+            },
+            SimulatorEvent::TeleportFinish {
+                region_handle: (512000u64 << 32) | 512000,
+                sim_addr: "10.0.0.2:9010".parse().unwrap(),
+                seed_capability: "http://10.0.0.2:9010/CAPS/tp/".to_string(),
+            },
+            SimulatorEvent::CrossedRegion {
+                region_handle: east,
+                sim_addr: east_addr,
+                seed_capability: "http://127.0.0.1:9001/CAPS/root/".to_string(),
+                position: [2.0, 128.0, 20.0],
+            },
+        ]);
+
+        // The seed is asked for the configured names; each poll acknowledges the previous batch
+        let requests = server.await.unwrap();
+        let names = Llsd::parse(requests[0].as_bytes()).unwrap();
+        assert_eq!(names.as_array().len(), DEFAULT_CAPABILITIES.len());
+        assert_eq!(names.at(0), &Llsd::from(EVENT_QUEUE_GET));
+        let polls: Vec<Llsd> = requests[1..].iter().map(|body| Llsd::parse(body.as_bytes()).unwrap()).collect();
+        let acks: Vec<&Llsd> = polls.iter().map(|poll| poll.get("ack")).collect();
+        assert_eq!(acks, [&Llsd::Undefined, &Llsd::Integer(1), &Llsd::Integer(1), &Llsd::Integer(2)]);
+        assert!(polls.iter().all(|poll| poll.get("done") == &Llsd::Boolean(false)));
+    }
+
+    /// Applies events the way an adapter would and records which regions the session reports
+    #[derive(Default)]
+    struct RecordingRegions {
+        neighbours: tokio::sync::Mutex<Vec<storm_protocol_adapters::RegionLink>>,
+        log: tokio::sync::Mutex<Vec<String>>,
+    }
+
+    impl RegionEvents for RecordingRegions {
+        async fn dispatch_event(&self, _connection_id: storm_networking::ConnectionId, event: SimulatorEvent) -> anyhow::Result<()> {
+            if let SimulatorEvent::EnableSimulator { region_handle, sim_addr } = event {
+                let mut region = storm_protocol_adapters::RegionLink::from_handle(Uuid::nil(), "neighbour", region_handle);
+                region.sim_addr = Some(sim_addr);
+                self.neighbours.lock().await.push(region);
+            }
+            Ok(())
+        }
+
+        async fn neighbours(&self, _connection_id: storm_networking::ConnectionId) -> anyhow::Result<Vec<storm_protocol_adapters::RegionLink>> {
+            Ok(self.neighbours.lock().await.clone())
+        }
+
+        async fn region_connected(&self, _connection_id: storm_networking::ConnectionId, region_handle: u64, capabilities: &Capabilities) {
+            self.log.lock().await.push(format!("connected {} {}", region_handle, capabilities.seed));
+        }
+
+        async fn region_disconnected(&self, _connection_id: storm_networking::ConnectionId, region_handle: u64) {
+            self.log.lock().await.push(format!("disconnected {}", region_handle));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_caps_session_follows_the_agent_between_regions() {
+        let (east, west) = ((256256u64 << 32) | 256000, (512000u64 << 32) | 512000);
+        // Child and teleport seeds are served apart from the root event queue, which keeps polling meanwhile
+        let (seeds, seed_server) = serve_http(vec![
+            llsd_reply("200 OK", &Llsd::from_iter([("GetTexture", Llsd::from("{base}/child-texture"))])),
+            llsd_reply("200 OK", &Llsd::from_iter([("GetTexture", Llsd::from("{base}/tp-texture"))])),
+        ])
+        .await;
+        let seeds = seeds.trim_end_matches("/login").to_string();
+        let enable = Llsd::from_iter([(
+            "SimulatorInfo",
+            Llsd::Array(vec![Llsd::from_iter([
+                ("Handle", Llsd::from(east)),
+                ("IP", Llsd::Binary(vec![127, 0, 0, 1])),
+                ("Port", Llsd::Integer(9001)),
+            ])]),
+        )]);
+        let establish = Llsd::from_iter([
+            ("agent-id", Llsd::Uuid(Uuid::nil())),
+            ("sim-ip-and-port", Llsd::from("127.0.0.1:9001")),
+            ("seed-capability", Llsd::from(format!("{}/child", seeds))),
+        ]);
+        let teleport = Llsd::from_iter([(
+            "Info",
+            Llsd::Array(vec![Llsd::from_iter([
+                ("AgentID", Llsd::Uuid(Uuid::nil())),
+                ("SimIP", Llsd::Binary(vec![10, 0, 0, 2])),
+                ("SimPort", Llsd::Integer(9010)),
+                ("RegionHandle", Llsd::from(west)),
+                ("SeedCapability", Llsd::from(format!("{}/tp", seeds))),
+                ("TeleportFlags", Llsd::Binary(vec![0, 0, 0, 16])),
+            ])]),
+        )]);
+        let (url, root_server) = serve_http(vec![
+            llsd_reply("200 OK", &Llsd::from_iter([("EventQueueGet", Llsd::from("{base}/eq"))])),
+            event_batch(1, vec![("EnableSimulator", enable), ("EstablishAgentCommunication", establish)]),
+            event_batch(2, vec![("TeleportFinish", teleport)]),
+        ])
+        .await;
+
+        let mut session = CapsSession::new(CapsClient::new(CapsConfig::default()).unwrap());
+        session.connect_region(7, &url.replace("/login", "/seed")).await.unwrap();
+        let regions = Arc::new(RecordingRegions::default());
+        session.run(regions.clone(), storm_networking::ConnectionId::new_v4()).await.unwrap();
+
+        // A teleport drops the root and child regions; their order follows the registry
+        let log = regions.log.lock().await.clone();
+        assert_eq!(log[0], format!("connected {} {}/child", east, seeds));
+        let mut dropped = log[1..3].to_vec();
+        dropped.sort();
+        let mut expected = vec![format!("disconnected {}", east), "disconnected 7".to_string()];
+        expected.sort();
+        assert_eq!(dropped, expected);
+        assert_eq!(log[3..], [format!("connected {} {}/tp", west, seeds)]);
+        assert_eq!(seed_server.await.unwrap().len(), 2);
+        root_server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_event_queue_gives_up_after_repeated_failures() {
+        let failure = "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+        let (url, server) = serve_http(vec![failure.clone(), failure]).await;
+
+        let config = CapsConfig { retry_delay: Duration::from_millis(10), max_poll_failures: 2, ..Default::default() };
+        let client = CapsClient::new(config).unwrap();
+        let mut capabilities = Capabilities::new("unused");
+        capabilities.insert(EVENT_QUEUE_GET, url);
+        let queue = client.event_queue(1, &capabilities).unwrap();
+
+        let (sender, _receiver) = tokio::sync::mpsc::channel(4);
+        let error = queue.run(sender).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<CapsError>(), Some(CapsError::Http { status: 500, .. })));
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
+
+    /// Serve a codestream over plain HTTP, honouring Range headers; returns the Range of each request
+    async fn serve_texture(stream: Vec<u8>, statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/caps/texture", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut ranges = Vec::new();
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !data.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..read]);
+                }
+                let head = String::from_utf8_lossy(&data).to_string();
+                assert!(head.starts_with("GET /caps/texture/?texture_id="));
+                let range = head
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("range: bytes=").map(str::to_string))
+                    .unwrap_or_default();
+                ranges.push(range.clone());
+                let (start, end) = range.split_once('-').unwrap();
+                let start: usize = start.parse().unwrap();
+                let end = (end.parse::<usize>().unwrap() + 1).min(stream.len());
+                let response = match status {
+                    206 => {
+                        let mut response = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Type: image/x-j2c\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                            start, end - 1, stream.len(), end - start
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(&stream[start..end]);
+                        response
+                    }
+                    other => format!("HTTP/1.1 {} Error\r\nContent-Length: 0\r\n\r\n", other).into_bytes(),
+                };
+                socket.write_all(&response).await.unwrap();
+                socket.shutdown().await.unwrap();
+            }
+            ranges
+        });
+        (url, server)
+    }
+
+    fn test_codestream(size: u32) -> Vec<u8> {
+        let data = (0..size * size * 3).map(|i| ((i % 251) ^ (i / 97)) as u8).collect();
+        let texture = storm_assets::TextureData { width: size, height: size, format: storm_assets::TextureFormat::RGB8, data, mip_levels: 1 };
+        let options = storm_assets::j2k::EncodeOptions { layers: 3, progression: storm_assets::j2k::Progression::Rpcl, ..Default::default() };
+        storm_assets::j2k::encode(&texture, &options).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_texture_range_requests_refine_cached_texture() {
+        let stream = test_codestream(128);
+        let id = Uuid::new_v4();
+        let (url, server) = serve_texture(stream.clone(), vec![206, 206]).await;
+        let mut capabilities = Capabilities::new("seed");
+        capabilities.insert("GetTexture", url);
+
+        let assets = Arc::new(storm_assets::AssetManager::new("."));
+        let mut pipeline = TexturePipeline::new(assets.clone(), Duration::from_secs(5)).unwrap();
+        pipeline.request(TextureRequest { id, importance: 10.0, screen_size: 32 }).await;
+
+        let event = pipeline.fetch_next(&capabilities).await.unwrap();
+        assert_eq!(
+            event,
+            Some(TextureEvent::Decoded { id, discard_level: 2, width: 32, height: 32, source: TextureSource::Http })
+        );
+        let Some(storm_assets::AssetData::Texture(texture)) = assets.get_asset(id).await else { panic!("texture not cached") };
+        assert_eq!((texture.width, texture.height), (32, 32));
+        assert!(pipeline.pending().is_empty());
+
+        // Moving closer asks for more detail, continuing the earlier range
+        pipeline.request(TextureRequest { id, importance: 50.0, screen_size: 128 }).await;
+        assert_eq!(pipeline.pending().len(), 1);
+        let event = pipeline.fetch_next(&capabilities).await.unwrap();
+        assert!(matches!(event, Some(TextureEvent::Decoded { discard_level: 0, width: 128, height: 128, .. })));
+        assert_eq!(pipeline.in_flight(), 0);
+
+        let ranges = server.await.unwrap();
+        // The first packet covers a 32x32 decode; full size asks for w*h*components/8 bytes
+        assert_eq!(ranges, vec!["0-599".to_string(), format!("600-{}", 128 * 128 * 3 / 8 - 1)]);
+        assert!(pipeline.fetch_next(&capabilities).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_texture_udp_fallback_assembles_image_packets() {
+        let stream = test_codestream(64);
+        let (agent_id, session_id, id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let (url, server) = serve_texture(stream.clone(), vec![503]).await;
+        let mut capabilities = Capabilities::new("seed");
+        capabilities.insert("ViewerAsset", url);
+
+        let assets = Arc::new(storm_assets::AssetManager::new("."));
+        let mut pipeline = TexturePipeline::new(assets.clone(), Duration::from_secs(5)).unwrap();
+        pipeline.request(TextureRequest { id, importance: 1.0, screen_size: 64 }).await;
+        assert!(pipeline.udp_requests(agent_id, session_id, true, 8).is_none());
+
+        // A failing capability moves the texture to RequestImage
+        assert_eq!(pipeline.fetch_next(&capabilities).await.unwrap(), None);
+        assert_eq!(server.await.unwrap().len(), 1);
+        let request = pipeline.udp_requests(agent_id, session_id, true, 8).unwrap();
+        assert_eq!(request.request_image.len(), 1);
+        assert_eq!((request.request_image[0].image, request.request_image[0].packet), (id, 0));
+        let request = RequestImage::from_payload(&request.to_packet().payload).unwrap();
+        assert_eq!(request.request_image[0].discard_level, texture::MAX_DISCARD_LEVEL as i8);
+
+        let packets = 1 + (stream.len() - 600).div_ceil(texture::IMAGE_PACKET_SIZE);
+        let image_data = template::ImageData {
+            image_id: template::ImageDataImageID { id, codec: 2, size: stream.len() as u32, packets: packets as u16 },
+            image_data: template::ImageDataImageData { data: stream[..600].to_vec() },
+        };
+        let mut events = Vec::new();
+        // Packets arriving ahead of a gap wait for it
+        for packet in (1..packets).rev() {
+            let start = 600 + (packet - 1) * texture::IMAGE_PACKET_SIZE;
+            let message = template::ImagePacket {
+                image_id: template::ImagePacketImageID { id, packet: packet as u16 },
+                image_data: template::ImagePacketImageData {
+                    data: stream[start..(start + texture::IMAGE_PACKET_SIZE).min(stream.len())].to_vec(),
+                },
+            };
+            events.push(pipeline.handle_packet(&message.to_packet()).await.unwrap());
+        }
+        assert!(events.iter().all(Option::is_none));
+        let event = pipeline.handle_packet(&image_data.to_packet()).await.unwrap();
+        assert_eq!(
+            event,
+            Some(TextureEvent::Decoded { id, discard_level: 0, width: 64, height: 64, source: TextureSource::Udp })
+        );
+        let Some(storm_assets::AssetData::Texture(texture)) = assets.get_asset(id).await else { panic!("texture not cached") };
+        let original = storm_assets::j2k::decode(&stream, &Default::default()).unwrap();
+        assert_eq!(texture.data, original.data);
+        assert!(pipeline.udp_requests(agent_id, session_id, false, 8).is_none());
+        assert_eq!(pipeline.in_flight(), 0);
+
+        // A resend arriving after the texture completed does not start a new download
+        assert_eq!(pipeline.handle_packet(&image_data.to_packet()).await.unwrap(), None);
+        assert_eq!(pipeline.in_flight(), 0);
+
+        let missing = Uuid::new_v4();
+        pipeline.request(TextureRequest { id: missing, importance: 1.0, screen_size: 16 }).await;
+        let not_found = template::ImageNotInDatabase { image_id: template::ImageNotInDatabaseImageID { id: missing } };
+        assert_eq!(pipeline.handle_packet(&not_found.to_packet()).await.unwrap(), Some(TextureEvent::Missing(missing)));
+        assert!(pipeline.pending().is_empty());
+    }
+
+    fn skeleton_folder(id: Uuid, parent: Uuid, name: &str, type_default: i32, version: i32) -> InventoryFolderSkeleton {
+        InventoryFolderSkeleton { folder_id: id, parent_id: parent, name: name.to_string(), type_default, version }
+    }
+
+    fn llsd_item(id: Uuid, parent: Uuid, name: &str, item_type: i8, owner_mask: u32) -> Llsd {
+        Llsd::from_iter([
+            ("item_id", Llsd::Uuid(id)),
+            ("parent_id", Llsd::Uuid(parent)),
+            ("asset_id", Llsd::Uuid(Uuid::new_v4())),
+            ("name", Llsd::from(name)),
+            ("desc", Llsd::from("")),
+            ("type", Llsd::Integer(item_type as i32)),
+            ("inv_type", Llsd::Integer(item_type as i32)),
+            ("created_at", Llsd::Integer(1_700_000_000)),
+            (
+                "permissions",
+                Llsd::from_iter([("owner_mask", Llsd::from(owner_mask)), ("base_mask", Llsd::from(inventory::permission::ALL))]),
+            ),
+            ("sale_info", Llsd::from_iter([("sale_type", Llsd::Integer(0)), ("sale_price", Llsd::Integer(10))])),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_inventory_fetch_over_caps_and_udp() {
+        use inventory::{asset_type, permission};
+
+        let (agent_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let (root, objects, stuff, sub) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let skeleton = vec![
+            skeleton_folder(root, Uuid::nil(), "My Inventory", 8, 5),
+            skeleton_folder(objects, root, "Objects", 6, 2),
+            skeleton_folder(stuff, root, "Stuff", -1, 3),
+        ];
+        let mut inventory = Inventory::new(agent_id, session_id, InventoryTree::from_skeleton(Some(root), &skeleton));
+        assert_eq!(inventory.tree().unfetched().len(), 3);
+
+        let (chair, hat) = (Uuid::new_v4(), Uuid::new_v4());
+        let listing = Llsd::from_iter([(
+            "folders",
+            Llsd::Array(vec![Llsd::from_iter([
+                ("folder_id", Llsd::Uuid(stuff)),
+                ("owner_id", Llsd::Uuid(agent_id)),
+                ("version", Llsd::Integer(3)),
+                ("descendents", Llsd::Integer(3)),
+                (
+                    "categories",
+                    Llsd::Array(vec![Llsd::from_iter([
+                        ("category_id", Llsd::Uuid(sub)),
+                        ("parent_id", Llsd::Uuid(stuff)),
+                        ("name", Llsd::from("Builds")),
+                        ("type_default", Llsd::Integer(-1)),
+                        ("version", Llsd::Integer(1)),
+                    ])]),
+                ),
+                (
+                    "items",
+                    Llsd::Array(vec![
+                        llsd_item(chair, stuff, "Chair", asset_type::OBJECT, permission::ALL),
+                        llsd_item(hat, stuff, "Hat", asset_type::OBJECT, permission::MOVE | permission::TRANSFER),
+                    ]),
+                ),
+            ])]),
+        )]);
+        let (url, server) = serve_http(vec![llsd_reply("200 OK", &listing)]).await;
+        let mut capabilities = Capabilities::new("seed");
+        capabilities.insert(inventory::FETCH_DESCENDENTS_CAP, url);
+        let client = CapsClient::new(CapsConfig::default()).unwrap();
+
+        let (changes, packets) = inventory.fetch_folders(&client, &capabilities, &[stuff]).await.unwrap();
+        let requests = server.await.unwrap();
+        let request = Llsd::parse(requests[0].as_bytes()).unwrap();
+        assert_eq!(request.get("folders").at(0).get("folder_id").as_uuid(), stuff);
+        assert!(packets.is_empty());
+        assert_eq!(changes.folders, vec![stuff]);
+        let names: Vec<_> = inventory.tree().folder_items(stuff).iter().map(|item| item.name.clone()).collect();
+        assert_eq!(names, ["Chair", "Hat"]);
+        assert_eq!(inventory.tree().path(hat), ["My Inventory", "Stuff", "Hat"]);
+        assert_eq!(inventory.tree().item(chair).unwrap().sale_price, 10);
+        assert!(inventory.tree().folder(stuff).unwrap().fetched);
+        assert!(!inventory.tree().folder(sub).unwrap().fetched);
+
+        // Without the capability the listing arrives in several InventoryDescendents packets
+        let (_, packets) = inventory.fetch_folders(&client, &Capabilities::new("seed"), &[objects]).await.unwrap();
+        let request = template::FetchInventoryDescendents::from_payload(&packets[0].payload).unwrap();
+        assert_eq!((request.inventory_data.folder_id, request.inventory_data.owner_id), (objects, agent_id));
+        let box_item = |id: Uuid, name: &str| template::InventoryDescendentsItemData {
+            item_id: id,
+            folder_id: objects,
+            owner_id: agent_id,
+            owner_mask: permission::ALL,
+            r#type: asset_type::OBJECT,
+            name: format!("{}\0", name).into_bytes(),
+            ..Default::default()
+        };
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let part = |item| template::InventoryDescendents {
+            agent_data: template::InventoryDescendentsAgentData {
+                agent_id,
+                folder_id: objects,
+                owner_id: agent_id,
+                version: 4,
+                descendents: 2,
+            },
+            folder_data: vec![Default::default()],
+            item_data: vec![item],
+        };
+        let changes = inventory.handle_packet(&part(box_item(first, "Box")).to_packet()).unwrap();
+        assert!(changes.is_empty());
+        assert!(inventory.tree().item(first).is_none());
+        let changes = inventory.handle_packet(&part(box_item(second, "Crate")).to_packet()).unwrap();
+        assert_eq!(changes.folders, vec![objects]);
+        assert_eq!(inventory.tree().folder_items(objects).len(), 2);
+        assert_eq!(inventory.tree().folder(objects).unwrap().version, 4);
+
+        // Items created on request come back from the simulator
+        let create = inventory.create_item(stuff, "Note", "", asset_type::NOTECARD, 7, 0).unwrap();
+        let created = template::UpdateCreateInventoryItem {
+            agent_data: template::UpdateCreateInventoryItemAgentData { agent_id, sim_approved: true, transaction_id: Uuid::nil() },
+            inventory_data: vec![template::UpdateCreateInventoryItemInventoryData {
+                item_id: Uuid::new_v4(),
+                folder_id: stuff,
+                callback_id: create.inventory_block.callback_id,
+                r#type: asset_type::NOTECARD,
+                name: b"Note\0".to_vec(),
+                ..Default::default()
+            }],
+        };
+        let changes = inventory.handle_packet(&created.to_packet()).unwrap();
+        assert_eq!(inventory.tree().item(changes.items[0]).unwrap().name, "Note");
+        assert_eq!(inventory.tree().folder(stuff).unwrap().version, 4);
+    }
+
+    #[test]
+    fn test_inventory_edits_and_disk_cache() {
+        use inventory::{asset_type, permission};
+
+        let (agent_id, session_id, friend) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let (root, trash, stuff) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let skeleton = vec![
+            skeleton_folder(root, Uuid::nil(), "My Inventory", 8, 1),
+            skeleton_folder(trash, root, "Trash", 14, 1),
+            skeleton_folder(stuff, root, "Stuff", -1, 1),
+        ];
+        let mut inventory = Inventory::new(agent_id, session_id, InventoryTree::from_skeleton(Some(root), &skeleton));
+        let (chair, hat) = (Uuid::new_v4(), Uuid::new_v4());
+        inventory.apply_descendents(&Llsd::from_iter([(
+            "folders",
+            Llsd::Array(vec![Llsd::from_iter([
+                ("folder_id", Llsd::Uuid(stuff)),
+                ("version", Llsd::Integer(2)),
+                (
+                    "items",
+                    Llsd::Array(vec![
+                        llsd_item(chair, stuff, "Chair", asset_type::OBJECT, permission::ALL),
+                        llsd_item(hat, stuff, "Hat", asset_type::CLOTHING, permission::MOVE | permission::TRANSFER),
+                    ]),
+                ),
+            ])]),
+        )]));
+
+        let create = inventory.create_folder(stuff, "Builds").unwrap();
+        let builds = create.folder_data.folder_id;
+        assert_eq!(inventory.tree().subfolders(stuff)[0].name, "Builds");
+        assert_eq!(inventory.tree().folder(stuff).unwrap().version, 3);
+        let error = inventory.move_folder(stuff, builds).unwrap_err();
+        assert!(matches!(error.downcast_ref::<InventoryError>(), Some(InventoryError::MoveIntoSelf(_))));
+        assert!(inventory.remove_folder(trash).is_err());
+        inventory.rename_folder(builds, "Projects").unwrap();
+
+        let moved = inventory.move_item(chair, builds).unwrap();
+        assert_eq!(moved.inventory_data[0].folder_id, builds);
+        let rename = inventory.rename_item(chair, "Armchair").unwrap();
+        let rename = template::UpdateInventoryItem::from_payload(&rename.to_payload()).unwrap();
+        assert_eq!(rename.inventory_data[0].name, b"Armchair\0");
+        assert_eq!(rename.inventory_data[0].owner_mask, permission::ALL);
+        assert_eq!(inventory.tree().path(chair), ["My Inventory", "Stuff", "Projects", "Armchair"]);
+
+        // A copyable object stays in inventory when rezzed, a no-copy gift leaves it
+        let rez = inventory.rez_object(chair, [128.0, 128.0, 30.0], [128.0, 128.0, 20.0]).unwrap();
+        assert!(!rez.rez_data.remove_item);
+        assert!(inventory.tree().item(chair).is_some());
+        assert!(inventory.rez_object(hat, [0.0; 3], [0.0; 3]).is_err());
+        let offer = inventory.give_item(friend, "Storm Tester", hat).unwrap();
+        assert_eq!(offer.message_block.to_agent_id, friend);
+        assert_eq!(offer.message_block.dialog, 4);
+        assert_eq!(offer.message_block.binary_bucket[0], asset_type::CLOTHING as u8);
+        assert_eq!(&offer.message_block.binary_bucket[1..], hat.as_bytes());
+        assert!(inventory.tree().item(hat).is_none());
+
+        let path = std::env::temp_dir().join(format!("storm-inventory-{}.json", Uuid::new_v4()));
+        inventory.save_cache(&path).unwrap();
+        // Next login lists Stuff at the version the edits reached; Trash changed elsewhere and the root was never fetched
+        let current = |folder: Uuid| inventory.tree().folder(folder).unwrap().version;
+        let skeleton = vec![
+            skeleton_folder(root, Uuid::nil(), "My Inventory", 8, current(root)),
+            skeleton_folder(trash, root, "Trash", 14, current(trash) + 1),
+            skeleton_folder(stuff, root, "Stuff", -1, current(stuff)),
+            skeleton_folder(builds, stuff, "Projects", -1, current(builds)),
+        ];
+        let mut next = Inventory::new(agent_id, session_id, InventoryTree::from_skeleton(Some(root), &skeleton));
+        assert_eq!(next.load_cache(&path).unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+        let mut unfetched = next.tree().unfetched();
+        unfetched.sort();
+        let mut expected = vec![root, trash];
+        expected.sort();
+        assert_eq!(unfetched, expected);
+        assert_eq!(next.tree().item(chair).unwrap().name, "Armchair");
+
+        next.remove_folder(builds).unwrap();
+        assert!(next.tree().item(chair).is_none());
+    }
+
+    fn appearance_packet(agent_id: Uuid, head: Uuid, cof_version: i32) -> LLUDPPacket {
+        let mut texture_entry = TextureEntry::default();
+        texture_entry.default.texture_id = appearance::DEFAULT_AVATAR_TEXTURE;
+        texture_entry.faces.insert(8, TextureFace { texture_id: head, ..Default::default() });
+        texture_entry.faces.insert(20, TextureFace { texture_id: Uuid::nil(), ..Default::default() });
+        let mut visual_params = vec![template::AvatarAppearanceVisualParam { param_value: 0 }; 218];
+        visual_params[0].param_value = 255;
+        visual_params[appearance::MALE_PARAM_INDEX].param_value = 255;
+        template::AvatarAppearance {
+            sender: template::AvatarAppearanceSender { id: agent_id, is_trial: false },
+            object_data: template::AvatarAppearanceObjectData { texture_entry: texture_entry.to_bytes() },
+            visual_param: visual_params,
+            appearance_data: vec![template::AvatarAppearanceAppearanceData { appearance_version: 1, cof_version, flags: 0 }],
+            appearance_hover: vec![template::AvatarAppearanceAppearanceHover { hover_height: [0.0, 0.0, 0.25] }],
+        }
+        .to_packet()
+    }
+
+    #[test]
+    fn test_avatar_appearance_waits_for_avatar_and_bumps_revision() {
+        let mut world = storm_ecs::World::new();
+        let mut objects = RegionObjects::new();
+        let mut appearances = AvatarAppearances::new();
+        let (agent_id, head) = (Uuid::from_u128(30), Uuid::new_v4());
+
+        // Appearance can arrive before the avatar is in view
+        let updated = appearances.handle_packet(&mut world, &objects, &appearance_packet(agent_id, head, 7)).unwrap();
+        assert!(updated.is_empty());
+        assert_eq!(appearances.pending(), 1);
+
+        let mut avatar = scene_prim(30, 0, [128.0, 128.0, 25.0]);
+        avatar.pcode = ObjectUpdateBlock::PCODE_AVATAR;
+        avatar.scale = [0.45, 0.6, 1.8];
+        let update = object_packet(vec![avatar]);
+        objects.handle_packet(&mut world, &update).unwrap();
+        let updated = appearances.handle_packet(&mut world, &objects, &update).unwrap();
+        assert_eq!(updated, vec![agent_id]);
+        assert_eq!(appearances.pending(), 0);
+
+        let entity = objects.entity(30).unwrap();
+        let raw = world.get_component::<OpenSimAppearance>(entity).unwrap();
+        assert_eq!((raw.cof_version, raw.hover_height), (7, 0.25));
+        assert!(raw.is_male());
+        // Placeholders and missing bakes are left out
+        let generic = world.get_component::<storm_ecs::AvatarAppearance>(entity).unwrap();
+        assert_eq!(generic.baked_textures, vec![(storm_ecs::BakedRegion::Head, head)]);
+        assert_eq!(generic.baked_texture(storm_ecs::BakedRegion::Hair), None);
+        assert_eq!((generic.visual_params.len(), generic.visual_params[0]), (218, 1.0));
+        assert_eq!((generic.size, generic.revision), ([0.45, 0.6, 1.8], 1));
+
+        let rebaked = Uuid::new_v4();
+        appearances.handle_packet(&mut world, &objects, &appearance_packet(agent_id, rebaked, 8)).unwrap();
+        let generic = world.get_component::<storm_ecs::AvatarAppearance>(entity).unwrap();
+        assert_eq!((generic.baked_texture(storm_ecs::BakedRegion::Head), generic.revision), (Some(rebaked), 2));
+    }
+
+    #[tokio::test]
+    async fn test_agent_wearables_set_appearance_and_server_bake() {
+        use appearance::wearable_type;
+        use md5::{Digest, Md5};
+
+        let (agent_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut agent = AgentAppearance::new(agent_id, session_id);
+        let request = agent.wearables_request();
+        assert_eq!(request.agent_data.agent_id, agent_id);
+
+        let (eyes, hair) = (Uuid::new_v4(), Uuid::new_v4());
+        let update = |serial_num, worn: Vec<(Uuid, u8)>| template::AgentWearablesUpdate {
+            agent_data: template::AgentWearablesUpdateAgentData { agent_id, session_id, serial_num },
+            wearable_data: worn
+                .into_iter()
+                .map(|(asset_id, wearable_type)| template::AgentWearablesUpdateWearableData {
+                    item_id: Uuid::new_v4(),
+                    asset_id,
+                    wearable_type,
+                })
+                .collect(),
+        };
+        assert!(agent.handle_packet(&update(3, vec![(eyes, wearable_type::EYES), (hair, wearable_type::HAIR)]).to_packet()).unwrap());
+        assert!(!agent.apply_wearables(&update(2, vec![])));
+        assert_eq!(agent.wearables().worn.len(), 2);
+
+        let message = agent.set_appearance([0.45, 0.6, 1.9], &TextureEntry::default(), &[128; 4]);
+        assert_eq!(message.agent_data.serial_num, 1);
+        assert_eq!(message.visual_param.len(), 4);
+        let cache_id = |index: u8| message.wearable_data.iter().find(|block| block.texture_index == index).unwrap().cache_id;
+        let mut expected = Md5::new();
+        expected.update(eyes.as_bytes());
+        expected.update(Uuid::from_u128(0xb2cf28af_b840_1071_3c6a_78085d8128b5).as_bytes());
+        assert_eq!(cache_id(11), Uuid::from_bytes(expected.finalize().into()));
+        assert_eq!(cache_id(19), Uuid::nil());
+        assert_ne!(cache_id(8), cache_id(20));
+
+        let (url, server) = serve_http(vec![
+            llsd_reply("200 OK", &Llsd::from_iter([("success", Llsd::from(true))])),
+            llsd_reply("200 OK", &Llsd::from_iter([("error", Llsd::from("Outfit is out of date"))])),
+        ])
+        .await;
+        let mut capabilities = Capabilities::new("seed");
+        capabilities.insert(UPDATE_APPEARANCE_CAP, url);
+        let client = CapsClient::new(CapsConfig::default()).unwrap();
+        agent.request_server_bake(&client, &capabilities, 12).await.unwrap();
+        let error = agent.request_server_bake(&client, &capabilities, 11).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<AppearanceError>(), Some(AppearanceError::BakeFailed(reason)) if reason == "Outfit is out of date"));
+        let requests = server.await.unwrap();
+        assert_eq!(Llsd::parse(requests[0].as_bytes()).unwrap().get("cof_version").as_integer(), 12);
+
+        let error = agent.request_server_bake(&client, &Capabilities::new("seed"), 12).await.unwrap_err();
+        assert!(error.downcast_ref::<CapsError>().is_some());
+    }
+}
@@ -0,0 +1,179 @@
+// File: crates/storm-opensim/src/llsd/binary.rs
+// LLSD binary encoding
+
+use std::collections::BTreeMap;
+use anyhow::Result;
+use uuid::Uuid;
+
+use super::{check_depth, Llsd};
+
+/// Optional header in front of binary documents
+pub const HEADER: &[u8] = b"<? LLSD/Binary ?>\n";
+
+/// Encode with the binary header
+pub fn to_vec(value: &Llsd) -> Vec<u8> {
+    let mut out = HEADER.to_vec();
+    write(value, &mut out);
+    out
+}
+
+/// Encode without the header, as embedded in other documents
+pub fn to_vec_headerless(value: &Llsd) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(value, &mut out);
+    out
+}
+
+/// Decode a document with or without the header
+pub fn from_slice(data: &[u8]) -> Result<Llsd> {
+    let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+    let mut body = &data[start..];
+    let header = HEADER.trim_ascii_end();
+    if body.starts_with(header) {
+        body = &body[header.len()..];
+        body = body.strip_prefix(b"\n").unwrap_or(body);
+    }
+
+    let mut reader = Reader { data: body, pos: 0 };
+    let value = reader.value(0)?;
+    if reader.pos != body.len() {
+        return Err(anyhow::anyhow!("{} trailing bytes after LLSD binary value", body.len() - reader.pos));
+    }
+    Ok(value)
+}
+
+fn write(value: &Llsd, out: &mut Vec<u8>) {
+    match value {
+        Llsd::Undefined => out.push(b'!'),
+        Llsd::Boolean(value) => out.push(if *value { b'1' } else { b'0' }),
+        Llsd::Integer(value) => {
+            out.push(b'i');
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        Llsd::Real(value) => {
+            out.push(b'r');
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        Llsd::String(value) => write_sized(b's', value.as_bytes(), out),
+        Llsd::Uuid(value) => {
+            out.push(b'u');
+            out.extend_from_slice(value.as_bytes());
+        }
+        // Dates are the one little-endian value in the format
+        Llsd::Date(value) => {
+            out.push(b'd');
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        Llsd::Uri(value) => write_sized(b'l', value.as_bytes(), out),
+        Llsd::Binary(value) => write_sized(b'b', value, out),
+        Llsd::Array(values) => {
+            out.push(b'[');
+            out.extend_from_slice(&(values.len() as u32).to_be_bytes());
+            for value in values {
+                write(value, out);
+            }
+            out.push(b']');
+        }
+        Llsd::Map(map) => {
+            out.push(b'{');
+            out.extend_from_slice(&(map.len() as u32).to_be_bytes());
+            for (key, value) in map {
+                write_sized(b'k', key.as_bytes(), out);
+                write(value, out);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+fn write_sized(marker: u8, bytes: &[u8], out: &mut Vec<u8>) {
+    out.push(marker);
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len()).ok_or_else(|| {
+            anyhow::anyhow!("LLSD binary value needs {} bytes at offset {}, {} left", len, self.pos, self.data.len() - self.pos)
+        })?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn sized(&mut self) -> Result<&'a [u8]> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.sized()?.to_vec())?)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Llsd> {
+        let marker = self.u8()?;
+        Ok(match marker {
+            b'!' => Llsd::Undefined,
+            b'1' => Llsd::Boolean(true),
+            b'0' => Llsd::Boolean(false),
+            b'i' => Llsd::Integer(i32::from_be_bytes(self.array()?)),
+            b'r' => Llsd::Real(f64::from_be_bytes(self.array()?)),
+            b's' => Llsd::String(self.string()?),
+            b'u' => Llsd::Uuid(Uuid::from_bytes(self.array()?)),
+            b'd' => Llsd::Date(f64::from_le_bytes(self.array()?)),
+            b'l' => Llsd::Uri(self.string()?),
+            b'b' => Llsd::Binary(self.sized()?.to_vec()),
+            b'[' => {
+                check_depth(depth)?;
+                let count = self.len()?;
+                // Every element is at least one byte, which bounds hostile counts
+                let mut values = Vec::with_capacity(count.min(self.data.len() - self.pos));
+                for _ in 0..count {
+                    values.push(self.value(depth + 1)?);
+                }
+                self.expect(b']')?;
+                Llsd::Array(values)
+            }
+            b'{' => {
+                check_depth(depth)?;
+                let count = self.len()?;
+                let mut map = BTreeMap::new();
+                for _ in 0..count {
+                    let key = match self.u8()? {
+                        b'k' | b's' => self.string()?,
+                        other => return Err(anyhow::anyhow!("Expected LLSD binary map key, found {:?}", other as char)),
+                    };
+                    map.insert(key, self.value(depth + 1)?);
+                }
+                self.expect(b'}')?;
+                Llsd::Map(map)
+            }
+            other => return Err(anyhow::anyhow!("Unknown LLSD binary marker {:?} at offset {}", other as char, self.pos - 1)),
+        })
+    }
+
+    fn expect(&mut self, marker: u8) -> Result<()> {
+        match self.u8()? {
+            found if found == marker => Ok(()),
+            found => Err(anyhow::anyhow!("Expected {:?} in LLSD binary, found {:?}", marker as char, found as char)),
+        }
+    }
+}
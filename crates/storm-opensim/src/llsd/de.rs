@@ -0,0 +1,364 @@
+// File: crates/storm-opensim/src/llsd/de.rs
+// serde Deserializer reading LLSD values
+
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{format_date, Llsd, LlsdError};
+
+/// Convert LLSD to any deserializable value, applying the LLSD scalar conversions
+pub fn from_llsd<T: DeserializeOwned>(value: Llsd) -> Result<T, LlsdError> {
+    T::deserialize(Deserializer(value))
+}
+
+impl<'de> de::Deserialize<'de> for Llsd {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LlsdVisitor)
+    }
+}
+
+struct LlsdVisitor;
+
+impl<'de> Visitor<'de> for LlsdVisitor {
+    type Value = Llsd;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an LLSD value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Llsd, E> {
+        Ok(Llsd::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Llsd, E> {
+        Ok(i32::try_from(v).map_or(Llsd::Real(v as f64), Llsd::Integer))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Llsd, E> {
+        Ok(i32::try_from(v).map_or(Llsd::Real(v as f64), Llsd::Integer))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Llsd, E> {
+        Ok(Llsd::Real(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Llsd, E> {
+        Ok(Llsd::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Llsd, E> {
+        Ok(Llsd::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Llsd, E> {
+        Ok(Llsd::Binary(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Llsd, E> {
+        Ok(Llsd::Binary(v))
+    }
+
+    fn visit_none<E>(self) -> Result<Llsd, E> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Llsd, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_unit<E>(self) -> Result<Llsd, E> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Llsd, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Llsd::Array(values))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<Llsd, A::Error> {
+        let mut map = BTreeMap::new();
+        while let Some((key, value)) = access.next_entry()? {
+            map.insert(key, value);
+        }
+        Ok(Llsd::Map(map))
+    }
+}
+
+struct Deserializer(Llsd);
+
+impl Deserializer {
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match &self.0 {
+            Llsd::Undefined => de::Unexpected::Unit,
+            Llsd::Boolean(value) => de::Unexpected::Bool(*value),
+            Llsd::Integer(value) => de::Unexpected::Signed(*value as i64),
+            Llsd::Real(value) | Llsd::Date(value) => de::Unexpected::Float(*value),
+            Llsd::String(value) | Llsd::Uri(value) => de::Unexpected::Str(value),
+            Llsd::Uuid(_) => de::Unexpected::Other("uuid"),
+            Llsd::Binary(value) => de::Unexpected::Bytes(value),
+            Llsd::Array(_) => de::Unexpected::Seq,
+            Llsd::Map(_) => de::Unexpected::Map,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = LlsdError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Undefined => visitor.visit_unit(),
+            Llsd::Boolean(value) => visitor.visit_bool(value),
+            Llsd::Integer(value) => visitor.visit_i32(value),
+            Llsd::Real(value) => visitor.visit_f64(value),
+            Llsd::String(value) | Llsd::Uri(value) => visitor.visit_string(value),
+            Llsd::Uuid(value) => visitor.visit_string(value.to_string()),
+            Llsd::Date(value) => visitor.visit_string(format_date(value)),
+            Llsd::Binary(value) => visitor.visit_byte_buf(value),
+            Llsd::Array(values) => visitor.visit_seq(SeqAccess(values.into_iter())),
+            Llsd::Map(map) => visitor.visit_map(MapAccess { entries: map.into_iter(), value: None }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_bool(self.0.as_bool())
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_i32(self.0.as_integer())
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_i32(self.0.as_integer())
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_i32(self.0.as_integer())
+    }
+
+    /// Wide integers are serialized as reals once they leave the i32 range
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Integer(value) => visitor.visit_i32(value),
+            other => visitor.visit_i64(other.as_real() as i64),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_i32(self.0.as_integer())
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_i32(self.0.as_integer())
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            // Integers sent by other implementations may carry u32 bits
+            Llsd::Integer(value) => visitor.visit_u32(value as u32),
+            other => visitor.visit_u64(other.as_real().max(0.0) as u64),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_u64(self.0.as_u64())
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_f32(self.0.as_real() as f32)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_f64(self.0.as_real())
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Array(_) | Llsd::Map(_) => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            Llsd::String(value) | Llsd::Uri(value) => visitor.visit_string(value),
+            other => visitor.visit_string(other.as_string()),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Array(_) => self.deserialize_any(visitor),
+            other => visitor.visit_byte_buf(other.as_binary()),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Undefined => visitor.visit_none(),
+            other => visitor.visit_some(Deserializer(other)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Binary values read as sequences of bytes, so `Vec<u8>` accepts them; undefined is empty
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Undefined => visitor.visit_seq(SeqAccess(Vec::new().into_iter())),
+            Llsd::Binary(bytes) => visitor.visit_seq(de::value::SeqDeserializer::new(bytes.into_iter())),
+            Llsd::Array(values) => visitor.visit_seq(SeqAccess(values.into_iter())),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_seq(visitor)
+    }
+
+    /// Missing maps read as empty, so structs fall back to their field defaults
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::Undefined => visitor.visit_map(MapAccess { entries: BTreeMap::new().into_iter(), value: None }),
+            Llsd::Map(map) => visitor.visit_map(MapAccess { entries: map.into_iter(), value: None }),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, LlsdError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, LlsdError> {
+        match self.0 {
+            Llsd::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Llsd::Map(map) if map.len() == 1 => {
+                let (variant, value) = map.into_iter().next().unwrap_or_default();
+                visitor.visit_enum(EnumAccess { variant, value })
+            }
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, LlsdError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! { i128 u128 }
+}
+
+struct SeqAccess(std::vec::IntoIter<Llsd>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = LlsdError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, LlsdError> {
+        self.0.next().map(|value| seed.deserialize(Deserializer(value))).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct MapAccess {
+    entries: std::collections::btree_map::IntoIter<String, Llsd>,
+    value: Option<Llsd>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = LlsdError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, LlsdError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer(Llsd::String(key))).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, LlsdError> {
+        let value = self.value.take().ok_or_else(|| LlsdError("LLSD map value requested before its key".to_string()))?;
+        seed.deserialize(Deserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Llsd,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = LlsdError;
+    type Variant = Deserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Deserializer), LlsdError> {
+        let variant = seed.deserialize(Deserializer(Llsd::String(self.variant)))?;
+        Ok((variant, Deserializer(self.value)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer {
+    type Error = LlsdError;
+
+    fn unit_variant(self) -> Result<(), LlsdError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, LlsdError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, LlsdError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, LlsdError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
@@ -0,0 +1,449 @@
+// File: crates/storm-opensim/src/llsd/mod.rs
+// LLSD: the structured data format of capabilities, the event queue and assets
+
+pub mod binary;
+pub mod notation;
+pub mod xml;
+mod de;
+mod ser;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use anyhow::Result;
+use uuid::Uuid;
+
+pub use de::from_llsd;
+pub use ser::to_llsd;
+
+/// Failure converting between LLSD and Rust types through serde
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct LlsdError(String);
+
+impl serde::ser::Error for LlsdError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl serde::de::Error for LlsdError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// An LLSD value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Llsd {
+    #[default]
+    Undefined,
+    Boolean(bool),
+    Integer(i32),
+    Real(f64),
+    String(String),
+    Uuid(Uuid),
+    /// Seconds since the Unix epoch
+    Date(f64),
+    Uri(String),
+    Binary(Vec<u8>),
+    Array(Vec<Llsd>),
+    Map(BTreeMap<String, Llsd>),
+}
+
+/// Encodings LLSD documents come in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlsdFormat {
+    Xml,
+    Binary,
+    Notation,
+}
+
+impl LlsdFormat {
+    /// HTTP content type of the encoding
+    pub fn content_type(self) -> &'static str {
+        match self {
+            LlsdFormat::Xml => "application/llsd+xml",
+            LlsdFormat::Binary => "application/llsd+binary",
+            LlsdFormat::Notation => "application/llsd+notation",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime {
+            "application/llsd+xml" | "application/xml" | "text/xml" => Some(LlsdFormat::Xml),
+            "application/llsd+binary" | "application/octet-stream" => Some(LlsdFormat::Binary),
+            "application/llsd+notation" | "text/plain" => Some(LlsdFormat::Notation),
+            _ => None,
+        }
+    }
+
+    /// Guess the encoding from a document's first bytes
+    pub fn detect(data: &[u8]) -> Self {
+        let start = data.iter().position(|b| !b.is_ascii_whitespace()).map_or(&[][..], |i| &data[i..]);
+        if start.starts_with(binary::HEADER.trim_ascii_end()) {
+            LlsdFormat::Binary
+        } else if start.starts_with(b"<") {
+            LlsdFormat::Xml
+        } else {
+            LlsdFormat::Notation
+        }
+    }
+}
+
+impl Llsd {
+    /// Parse a document in any encoding, detected from its header
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        Self::parse_as(LlsdFormat::detect(data), data)
+    }
+
+    pub fn parse_as(format: LlsdFormat, data: &[u8]) -> Result<Self> {
+        match format {
+            LlsdFormat::Xml => xml::from_str(std::str::from_utf8(data)?),
+            LlsdFormat::Binary => binary::from_slice(data),
+            LlsdFormat::Notation => notation::from_str(std::str::from_utf8(data)?),
+        }
+    }
+
+    pub fn encode(&self, format: LlsdFormat) -> Vec<u8> {
+        match format {
+            LlsdFormat::Xml => xml::to_string(self).into_bytes(),
+            LlsdFormat::Binary => binary::to_vec(self),
+            LlsdFormat::Notation => notation::to_string(self).into_bytes(),
+        }
+    }
+
+    pub fn map() -> Self {
+        Llsd::Map(BTreeMap::new())
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, Llsd::Undefined)
+    }
+
+    /// Map member, undefined for missing keys and non-maps
+    pub fn get(&self, key: &str) -> &Llsd {
+        match self {
+            Llsd::Map(map) => map.get(key).unwrap_or(&Llsd::Undefined),
+            _ => &Llsd::Undefined,
+        }
+    }
+
+    /// Array element, undefined when out of range or not an array
+    pub fn at(&self, index: usize) -> &Llsd {
+        match self {
+            Llsd::Array(values) => values.get(index).unwrap_or(&Llsd::Undefined),
+            _ => &Llsd::Undefined,
+        }
+    }
+
+    /// Insert into a map, turning an undefined value into one first
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Llsd>) -> &mut Self {
+        if self.is_undefined() {
+            *self = Llsd::map();
+        }
+        if let Llsd::Map(map) = self {
+            map.insert(key.into(), value.into());
+        }
+        self
+    }
+
+    pub fn as_array(&self) -> &[Llsd] {
+        match self {
+            Llsd::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&BTreeMap<String, Llsd>> {
+        match self {
+            Llsd::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    // Conversions follow the LLSD rules: every scalar converts to every other, with
+    // the type's default when the value makes no sense as that type
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Llsd::Boolean(value) => *value,
+            Llsd::Integer(value) => *value != 0,
+            Llsd::Real(value) => *value != 0.0 && !value.is_nan(),
+            Llsd::String(value) => !value.is_empty(),
+            Llsd::Uuid(value) => !value.is_nil(),
+            Llsd::Binary(value) => value.iter().any(|b| *b != 0),
+            _ => false,
+        }
+    }
+
+    pub fn as_integer(&self) -> i32 {
+        match self {
+            Llsd::Boolean(value) => *value as i32,
+            Llsd::Integer(value) => *value,
+            Llsd::Real(value) | Llsd::Date(value) => saturate(value.round()),
+            Llsd::String(value) => value
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .or_else(|| value.trim().parse::<f64>().ok().map(|real| saturate(real.round())))
+                .unwrap_or_default(),
+            Llsd::Binary(value) => value.get(..4).map_or(0, |b| i32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+            _ => 0,
+        }
+    }
+
+    pub fn as_real(&self) -> f64 {
+        match self {
+            Llsd::Boolean(value) => *value as i32 as f64,
+            Llsd::Integer(value) => *value as f64,
+            Llsd::Real(value) | Llsd::Date(value) => *value,
+            Llsd::String(value) => value.trim().parse().unwrap_or_default(),
+            Llsd::Binary(value) => value.get(..8).map_or(0.0, |b| f64::from_be_bytes(b.try_into().unwrap_or_default())),
+            _ => 0.0,
+        }
+    }
+
+    /// 64 bit values such as region handles travel as 8 big-endian bytes
+    pub fn as_u64(&self) -> u64 {
+        match self {
+            Llsd::Binary(value) if value.len() >= 8 => u64::from_be_bytes(value[..8].try_into().unwrap_or_default()),
+            Llsd::String(value) => value.trim().parse().unwrap_or_default(),
+            Llsd::Integer(value) => *value as u32 as u64,
+            other => other.as_real().max(0.0) as u64,
+        }
+    }
+
+    pub fn as_string(&self) -> String {
+        match self {
+            Llsd::Undefined | Llsd::Array(_) | Llsd::Map(_) => String::new(),
+            Llsd::Boolean(value) => if *value { "true" } else { "" }.to_string(),
+            Llsd::Integer(value) => value.to_string(),
+            Llsd::Real(value) => format_real(*value),
+            Llsd::String(value) | Llsd::Uri(value) => value.clone(),
+            Llsd::Uuid(value) => value.to_string(),
+            Llsd::Date(value) => format_date(*value),
+            Llsd::Binary(value) => String::from_utf8_lossy(value).into_owned(),
+        }
+    }
+
+    pub fn as_uuid(&self) -> Uuid {
+        match self {
+            Llsd::Uuid(value) => *value,
+            Llsd::String(value) => Uuid::parse_str(value.trim()).unwrap_or_default(),
+            Llsd::Binary(value) => Uuid::from_slice(value).unwrap_or_default(),
+            _ => Uuid::nil(),
+        }
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn as_date(&self) -> f64 {
+        match self {
+            Llsd::Date(value) | Llsd::Real(value) => *value,
+            Llsd::Integer(value) => *value as f64,
+            Llsd::String(value) => parse_date(value).unwrap_or_default(),
+            _ => 0.0,
+        }
+    }
+
+    pub fn as_binary(&self) -> Vec<u8> {
+        match self {
+            Llsd::Binary(value) => value.clone(),
+            Llsd::String(value) | Llsd::Uri(value) => value.as_bytes().to_vec(),
+            Llsd::Uuid(value) => value.as_bytes().to_vec(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Llsd {
+    /// Notation is the readable encoding, so values display as notation
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&notation::to_string(self))
+    }
+}
+
+impl From<bool> for Llsd {
+    fn from(value: bool) -> Self {
+        Llsd::Boolean(value)
+    }
+}
+
+impl From<i32> for Llsd {
+    fn from(value: i32) -> Self {
+        Llsd::Integer(value)
+    }
+}
+
+impl From<u32> for Llsd {
+    /// LLSD has no unsigned integers; u32 values keep their bits
+    fn from(value: u32) -> Self {
+        Llsd::Integer(value as i32)
+    }
+}
+
+impl From<u64> for Llsd {
+    fn from(value: u64) -> Self {
+        Llsd::Binary(value.to_be_bytes().to_vec())
+    }
+}
+
+impl From<f64> for Llsd {
+    fn from(value: f64) -> Self {
+        Llsd::Real(value)
+    }
+}
+
+impl From<f32> for Llsd {
+    fn from(value: f32) -> Self {
+        Llsd::Real(value as f64)
+    }
+}
+
+impl From<&str> for Llsd {
+    fn from(value: &str) -> Self {
+        Llsd::String(value.to_string())
+    }
+}
+
+impl From<String> for Llsd {
+    fn from(value: String) -> Self {
+        Llsd::String(value)
+    }
+}
+
+impl From<Uuid> for Llsd {
+    fn from(value: Uuid) -> Self {
+        Llsd::Uuid(value)
+    }
+}
+
+impl From<Vec<u8>> for Llsd {
+    fn from(value: Vec<u8>) -> Self {
+        Llsd::Binary(value)
+    }
+}
+
+impl From<[f32; 3]> for Llsd {
+    /// Vectors are arrays of reals
+    fn from(value: [f32; 3]) -> Self {
+        Llsd::Array(value.iter().map(|v| Llsd::Real(*v as f64)).collect())
+    }
+}
+
+impl From<Vec<Llsd>> for Llsd {
+    fn from(value: Vec<Llsd>) -> Self {
+        Llsd::Array(value)
+    }
+}
+
+impl From<BTreeMap<String, Llsd>> for Llsd {
+    fn from(value: BTreeMap<String, Llsd>) -> Self {
+        Llsd::Map(value)
+    }
+}
+
+impl<K: Into<String>, V: Into<Llsd>> FromIterator<(K, V)> for Llsd {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Llsd::Map(iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+/// Containers nested deeper than this are rejected rather than risking the stack
+pub const MAX_DEPTH: usize = 128;
+
+pub(crate) fn check_depth(depth: usize) -> Result<()> {
+    if depth >= MAX_DEPTH {
+        return Err(anyhow::anyhow!("LLSD nested deeper than {} levels", MAX_DEPTH));
+    }
+    Ok(())
+}
+
+fn saturate(value: f64) -> i32 {
+    if value.is_nan() {
+        0
+    } else {
+        value.clamp(i32::MIN as f64, i32::MAX as f64) as i32
+    }
+}
+
+/// Reals as the reference implementation writes them: shortest round-trip form, named specials
+pub(crate) fn format_real(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+pub(crate) fn parse_real(text: &str) -> Option<f64> {
+    match text.trim() {
+        "nan" | "NaN" => Some(f64::NAN),
+        "inf" | "Infinity" => Some(f64::INFINITY),
+        "-inf" | "-Infinity" => Some(f64::NEG_INFINITY),
+        other => other.parse().ok(),
+    }
+}
+
+/// ISO 8601 UTC, with fractional seconds only when present
+pub(crate) fn format_date(seconds: f64) -> String {
+    let whole = seconds.floor();
+    let millis = ((seconds - whole) * 1000.0).round() as u32;
+    let (whole, millis) = if millis == 1000 { (whole + 1.0, 0) } else { (whole, millis) };
+    let days = (whole / 86400.0).floor() as i64;
+    let secs = (whole as i64 - days * 86400) as u32;
+    let (year, month, day) = civil_from_days(days);
+    let time = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if millis == 0 {
+        format!("{:04}-{:02}-{:02}T{}Z", year, month, day, time)
+    } else {
+        format!("{:04}-{:02}-{:02}T{}.{:03}Z", year, month, day, time, millis)
+    }
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS[.fff]Z`; the time part may be omitted
+pub(crate) fn parse_date(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (date, time) = text.split_once('T').unwrap_or((text, "00:00:00Z"));
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let time = time.trim_end_matches('Z');
+    let mut parts = time.splitn(3, ':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next().unwrap_or("0").parse().ok()?;
+    Some(days_from_civil(year, month, day) as f64 * 86400.0 + hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+// Calendar conversions from Howard Hinnant's date algorithms
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
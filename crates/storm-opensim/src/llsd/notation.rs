@@ -0,0 +1,323 @@
+// File: crates/storm-opensim/src/llsd/notation.rs
+// LLSD notation encoding
+
+use std::collections::BTreeMap;
+use anyhow::Result;
+use base64::Engine;
+use uuid::Uuid;
+
+use super::xml::decode_base16;
+use super::{check_depth, format_date, format_real, parse_date, parse_real, Llsd};
+
+pub fn to_string(value: &Llsd) -> String {
+    let mut out = String::new();
+    write(value, &mut out);
+    out
+}
+
+pub fn from_str(text: &str) -> Result<Llsd> {
+    let mut parser = Parser { data: text.as_bytes(), pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != parser.data.len() {
+        return Err(anyhow::anyhow!("Trailing characters after LLSD notation value at offset {}", parser.pos));
+    }
+    Ok(value)
+}
+
+fn write(value: &Llsd, out: &mut String) {
+    match value {
+        Llsd::Undefined => out.push('!'),
+        Llsd::Boolean(value) => out.push(if *value { 't' } else { 'f' }),
+        Llsd::Integer(value) => out.push_str(&format!("i{}", value)),
+        Llsd::Real(value) => out.push_str(&format!("r{}", format_real(*value))),
+        Llsd::String(value) => quote(value, '\'', out),
+        Llsd::Uuid(value) => out.push_str(&format!("u{}", value)),
+        Llsd::Date(value) => out.push_str(&format!("d\"{}\"", format_date(*value))),
+        Llsd::Uri(value) => {
+            out.push('l');
+            quote(value, '"', out);
+        }
+        Llsd::Binary(value) => {
+            out.push_str("b64\"");
+            out.push_str(&base64::engine::general_purpose::STANDARD.encode(value));
+            out.push('"');
+        }
+        Llsd::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write(value, out);
+            }
+            out.push(']');
+        }
+        Llsd::Map(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                quote(key, '\'', out);
+                out.push(':');
+                write(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn quote(text: &str, delimiter: char, out: &mut String) {
+    out.push(delimiter);
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c == delimiter => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u8)),
+            c => out.push(c),
+        }
+    }
+    out.push(delimiter);
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        let byte = self.peek().ok_or_else(|| anyhow::anyhow!("Unexpected end of LLSD notation"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<()> {
+        self.skip_whitespace();
+        match self.next()? {
+            found if found == expected => Ok(()),
+            found => Err(anyhow::anyhow!(
+                "Expected {:?} in LLSD notation at offset {}, found {:?}",
+                expected as char,
+                self.pos - 1,
+                found as char
+            )),
+        }
+    }
+
+    /// Consume a keyword such as `true` if it comes next
+    fn keyword(&mut self, word: &str) -> bool {
+        if self.data[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Characters up to the next delimiter of a bare token
+    fn token(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| !b.is_ascii_whitespace() && !matches!(b, b',' | b']' | b'}' | b':')) {
+            self.pos += 1;
+        }
+        // Tokens end on ASCII delimiters, so they are valid UTF-8
+        std::str::from_utf8(&self.data[start..self.pos]).unwrap_or_default()
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Llsd> {
+        self.skip_whitespace();
+        let start = self.pos;
+        Ok(match self.next()? {
+            b'!' => Llsd::Undefined,
+            b'1' => Llsd::Boolean(true),
+            b'0' => Llsd::Boolean(false),
+            b't' | b'T' => {
+                let _ = self.keyword("rue") || self.keyword("RUE");
+                Llsd::Boolean(true)
+            }
+            b'f' | b'F' => {
+                let _ = self.keyword("alse") || self.keyword("ALSE");
+                Llsd::Boolean(false)
+            }
+            b'i' => {
+                let digits = self.token();
+                Llsd::Integer(digits.parse().map_err(|_| anyhow::anyhow!("Invalid LLSD integer '{}'", digits))?)
+            }
+            b'r' => {
+                let real = self.token();
+                Llsd::Real(parse_real(real).ok_or_else(|| anyhow::anyhow!("Invalid LLSD real '{}'", real))?)
+            }
+            b'u' => {
+                let text = self.take(36)?;
+                let text = std::str::from_utf8(text)?;
+                Llsd::Uuid(Uuid::parse_str(text).map_err(|_| anyhow::anyhow!("Invalid LLSD uuid '{}'", text))?)
+            }
+            quote @ (b'\'' | b'"') => Llsd::String(self.quoted(quote)?),
+            b's' => Llsd::String(String::from_utf8(self.sized()?)?),
+            b'l' => Llsd::Uri(self.string()?),
+            b'd' => {
+                let date = self.string()?;
+                Llsd::Date(parse_date(&date).ok_or_else(|| anyhow::anyhow!("Invalid LLSD date '{}'", date))?)
+            }
+            b'b' => Llsd::Binary(self.binary()?),
+            b'[' => {
+                check_depth(depth)?;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        self.skip_whitespace();
+                        match self.next()? {
+                            b',' => continue,
+                            b']' => break,
+                            other => return Err(anyhow::anyhow!("Expected ',' or ']' in LLSD array, found {:?}", other as char)),
+                        }
+                    }
+                }
+                Llsd::Array(values)
+            }
+            b'{' => {
+                check_depth(depth)?;
+                let mut map = BTreeMap::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key = match self.next()? {
+                            quote @ (b'\'' | b'"') => self.quoted(quote)?,
+                            b's' => String::from_utf8(self.sized()?)?,
+                            other => return Err(anyhow::anyhow!("Expected LLSD map key, found {:?}", other as char)),
+                        };
+                        self.expect(b':')?;
+                        map.insert(key, self.value(depth + 1)?);
+                        self.skip_whitespace();
+                        match self.next()? {
+                            b',' => continue,
+                            b'}' => break,
+                            other => return Err(anyhow::anyhow!("Expected ',' or '}}' in LLSD map, found {:?}", other as char)),
+                        }
+                    }
+                }
+                Llsd::Map(map)
+            }
+            other => return Err(anyhow::anyhow!("Unexpected {:?} in LLSD notation at offset {}", other as char, start)),
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos + len;
+        if end > self.data.len() {
+            return Err(anyhow::anyhow!("Unexpected end of LLSD notation"));
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Any quoted string, used after the `l` and `d` markers
+    fn string(&mut self) -> Result<String> {
+        match self.next()? {
+            quote @ (b'\'' | b'"') => self.quoted(quote),
+            other => Err(anyhow::anyhow!("Expected quoted string in LLSD notation, found {:?}", other as char)),
+        }
+    }
+
+    /// Escaped string body after its opening quote
+    fn quoted(&mut self, quote: u8) -> Result<String> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.next()? {
+                byte if byte == quote => break,
+                b'\\' => bytes.push(match self.next()? {
+                    b'n' => b'\n',
+                    b't' => b'\t',
+                    b'r' => b'\r',
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b'f' => 0x0C,
+                    b'v' => 0x0B,
+                    b'x' => {
+                        let hex = std::str::from_utf8(self.take(2)?)?;
+                        u8::from_str_radix(hex, 16).map_err(|_| anyhow::anyhow!("Invalid escape '\\x{}' in LLSD notation", hex))?
+                    }
+                    other => other,
+                }),
+                byte => bytes.push(byte),
+            }
+        }
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// `(len)"raw bytes"`, used by sized strings and raw binary
+    fn sized(&mut self) -> Result<Vec<u8>> {
+        self.expect(b'(')?;
+        let len = self.token_until(b')')?;
+        let len: usize = len.parse().map_err(|_| anyhow::anyhow!("Invalid LLSD notation length '{}'", len))?;
+        self.pos += 1;
+        let quote = self.next()?;
+        if quote != b'"' && quote != b'\'' {
+            return Err(anyhow::anyhow!("Expected quote after LLSD notation length, found {:?}", quote as char));
+        }
+        let bytes = self.take(len)?.to_vec();
+        match self.next()? {
+            end if end == quote => Ok(bytes),
+            _ => Err(anyhow::anyhow!("LLSD notation sized value is longer than {} bytes", len)),
+        }
+    }
+
+    fn token_until(&mut self, end: u8) -> Result<&'a str> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b != end) {
+            self.pos += 1;
+        }
+        if self.peek().is_none() {
+            return Err(anyhow::anyhow!("Unexpected end of LLSD notation"));
+        }
+        Ok(std::str::from_utf8(&self.data[start..self.pos])?)
+    }
+
+    fn binary(&mut self) -> Result<Vec<u8>> {
+        if self.peek() == Some(b'(') {
+            return self.sized();
+        }
+        let base = if self.keyword("64") {
+            64
+        } else if self.keyword("16") {
+            16
+        } else {
+            return Err(anyhow::anyhow!("Unsupported LLSD notation binary encoding at offset {}", self.pos));
+        };
+        let text = self.string()?;
+        let data: String = text.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        if base == 64 {
+            base64::engine::general_purpose::STANDARD
+                .decode(&data)
+                .map_err(|e| anyhow::anyhow!("Invalid LLSD base64: {}", e))
+        } else {
+            decode_base16(&data)
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// File: crates/storm-opensim/src/llsd/ser.rs
+// serde Serializer producing LLSD values
+
+use std::collections::BTreeMap;
+use serde::ser::{self, Serialize};
+
+use super::{format_date, Llsd, LlsdError};
+
+/// Convert any serializable value to LLSD
+pub fn to_llsd<T: Serialize + ?Sized>(value: &T) -> Result<Llsd, LlsdError> {
+    value.serialize(Serializer)
+}
+
+impl Serialize for Llsd {
+    /// Types without a serde counterpart (uuids, dates, uris) serialize as strings
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Llsd::Undefined => serializer.serialize_unit(),
+            Llsd::Boolean(value) => serializer.serialize_bool(*value),
+            Llsd::Integer(value) => serializer.serialize_i32(*value),
+            Llsd::Real(value) => serializer.serialize_f64(*value),
+            Llsd::String(value) | Llsd::Uri(value) => serializer.serialize_str(value),
+            Llsd::Uuid(value) => serializer.collect_str(value),
+            Llsd::Date(value) => serializer.serialize_str(&format_date(*value)),
+            Llsd::Binary(value) => serializer.serialize_bytes(value),
+            Llsd::Array(values) => serializer.collect_seq(values),
+            Llsd::Map(map) => serializer.collect_map(map),
+        }
+    }
+}
+
+struct Serializer;
+
+/// LLSD integers are 32 bit; wider values fall back to reals
+fn integer(value: i64) -> Llsd {
+    match i32::try_from(value) {
+        Ok(value) => Llsd::Integer(value),
+        Err(_) => Llsd::Real(value as f64),
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Llsd;
+    type Error = LlsdError;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeVariant<SerializeArray>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, v: bool) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Llsd, LlsdError> {
+        Ok(integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Integer(v as i32))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Llsd, LlsdError> {
+        Ok(integer(v as i64))
+    }
+
+    /// Region handles and other 64 bit ids travel as 8 big-endian bytes
+    fn serialize_u64(self, v: u64) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::from(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Real(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Binary(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Llsd, LlsdError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Undefined)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Llsd, LlsdError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::from_iter([(variant, to_llsd(value)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, LlsdError> {
+        Ok(SerializeArray(Vec::with_capacity(len.unwrap_or_default())))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, LlsdError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeArray, LlsdError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeArray>, LlsdError> {
+        Ok(SerializeVariant { variant, inner: self.serialize_seq(Some(len))? })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, LlsdError> {
+        Ok(SerializeMap { map: BTreeMap::new(), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, LlsdError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeMap>, LlsdError> {
+        Ok(SerializeVariant { variant, inner: self.serialize_map(Some(len))? })
+    }
+}
+
+struct SerializeArray(Vec<Llsd>);
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        self.0.push(to_llsd(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct SerializeMap {
+    map: BTreeMap<String, Llsd>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    /// LLSD keys are strings, so other key types are stringified
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), LlsdError> {
+        self.key = Some(match to_llsd(key)? {
+            key @ (Llsd::Array(_) | Llsd::Map(_) | Llsd::Binary(_)) => {
+                return Err(LlsdError(format!("LLSD map keys must be scalars, got {}", key)))
+            }
+            key => key.as_string(),
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        let key = self.key.take().ok_or_else(|| LlsdError("LLSD map value without a key".to_string()))?;
+        self.map.insert(key, to_llsd(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Map(self.map))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), LlsdError> {
+        self.map.insert(key.to_string(), to_llsd(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::Map(self.map))
+    }
+}
+
+/// Data-carrying enum variants become a single-key map
+struct SerializeVariant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeArray> {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), LlsdError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::from_iter([(self.variant, ser::SerializeSeq::end(self.inner)?)]))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = Llsd;
+    type Error = LlsdError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), LlsdError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Llsd, LlsdError> {
+        Ok(Llsd::from_iter([(self.variant, ser::SerializeStruct::end(self.inner)?)]))
+    }
+}
@@ -0,0 +1,160 @@
+// File: crates/storm-opensim/src/llsd/xml.rs
+// LLSD XML encoding
+
+use std::collections::BTreeMap;
+use anyhow::Result;
+use base64::Engine;
+use uuid::Uuid;
+
+use super::{check_depth, format_date, format_real, parse_date, parse_real, Llsd};
+
+pub fn to_string(value: &Llsd) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?><llsd>");
+    write(value, &mut out);
+    out.push_str("</llsd>");
+    out
+}
+
+pub fn from_str(xml: &str) -> Result<Llsd> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| anyhow::anyhow!("Malformed LLSD XML: {}", e))?;
+    let root = document.root_element();
+    if !root.has_tag_name("llsd") {
+        return Err(anyhow::anyhow!("Expected <llsd>, found <{}>", root.tag_name().name()));
+    }
+    // An empty document is undefined
+    let value = match elements(root).next() {
+        Some(node) => parse(node, 0)?,
+        None => Llsd::Undefined,
+    };
+    Ok(value)
+}
+
+fn write(value: &Llsd, out: &mut String) {
+    match value {
+        Llsd::Undefined => out.push_str("<undef />"),
+        Llsd::Boolean(value) => out.push_str(if *value { "<boolean>true</boolean>" } else { "<boolean>false</boolean>" }),
+        Llsd::Integer(value) => out.push_str(&format!("<integer>{}</integer>", value)),
+        Llsd::Real(value) => out.push_str(&format!("<real>{}</real>", format_real(*value))),
+        Llsd::String(value) => element("string", value, out),
+        Llsd::Uuid(value) => out.push_str(&format!("<uuid>{}</uuid>", value)),
+        Llsd::Date(value) => out.push_str(&format!("<date>{}</date>", format_date(*value))),
+        Llsd::Uri(value) => element("uri", value, out),
+        Llsd::Binary(value) => {
+            out.push_str("<binary encoding=\"base64\">");
+            out.push_str(&base64::engine::general_purpose::STANDARD.encode(value));
+            out.push_str("</binary>");
+        }
+        Llsd::Array(values) => {
+            out.push_str("<array>");
+            for value in values {
+                write(value, out);
+            }
+            out.push_str("</array>");
+        }
+        Llsd::Map(map) => {
+            out.push_str("<map>");
+            for (key, value) in map {
+                element("key", key, out);
+                write(value, out);
+            }
+            out.push_str("</map>");
+        }
+    }
+}
+
+fn element(name: &str, text: &str, out: &mut String) {
+    if text.is_empty() {
+        out.push_str(&format!("<{} />", name));
+        return;
+    }
+    out.push_str(&format!("<{}>", name));
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            // Carriage returns would be normalised away by the parser
+            '\r' => out.push_str("&#13;"),
+            _ => out.push(c),
+        }
+    }
+    out.push_str(&format!("</{}>", name));
+}
+
+fn elements<'a, 'input>(node: roxmltree::Node<'a, 'input>) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(|child| child.is_element())
+}
+
+/// Element text; empty elements such as `<integer />` mean the type's default
+fn text<'a>(node: roxmltree::Node<'a, '_>) -> &'a str {
+    node.text().unwrap_or_default()
+}
+
+fn parse(node: roxmltree::Node, depth: usize) -> Result<Llsd> {
+    let text = text(node);
+    Ok(match node.tag_name().name() {
+        "undef" => Llsd::Undefined,
+        "boolean" => Llsd::Boolean(matches!(text.trim(), "1" | "true")),
+        "integer" => Llsd::Integer(match text.trim() {
+            "" => 0,
+            digits => digits.parse().map_err(|_| anyhow::anyhow!("Invalid LLSD integer '{}'", digits))?,
+        }),
+        "real" => Llsd::Real(match text.trim() {
+            "" => 0.0,
+            real => parse_real(real).ok_or_else(|| anyhow::anyhow!("Invalid LLSD real '{}'", real))?,
+        }),
+        "string" => Llsd::String(text.to_string()),
+        "uuid" => Llsd::Uuid(match text.trim() {
+            "" => Uuid::nil(),
+            uuid => Uuid::parse_str(uuid).map_err(|_| anyhow::anyhow!("Invalid LLSD uuid '{}'", uuid))?,
+        }),
+        "date" => Llsd::Date(match text.trim() {
+            "" => 0.0,
+            date => parse_date(date).ok_or_else(|| anyhow::anyhow!("Invalid LLSD date '{}'", date))?,
+        }),
+        "uri" => Llsd::Uri(text.to_string()),
+        "binary" => {
+            let data: String = text.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+            Llsd::Binary(match node.attribute("encoding").unwrap_or("base64") {
+                "base64" => base64::engine::general_purpose::STANDARD
+                    .decode(&data)
+                    .map_err(|e| anyhow::anyhow!("Invalid LLSD base64: {}", e))?,
+                "base16" => decode_base16(&data)?,
+                other => return Err(anyhow::anyhow!("Unsupported LLSD binary encoding '{}'", other)),
+            })
+        }
+        "array" => {
+            check_depth(depth)?;
+            Llsd::Array(elements(node).map(|child| parse(child, depth + 1)).collect::<Result<_>>()?)
+        }
+        "map" => {
+            check_depth(depth)?;
+            let mut map = BTreeMap::new();
+            let mut children = elements(node);
+            while let Some(key) = children.next() {
+                if !key.has_tag_name("key") {
+                    return Err(anyhow::anyhow!("Expected <key> in LLSD map, found <{}>", key.tag_name().name()));
+                }
+                let value = children.next().ok_or_else(|| anyhow::anyhow!("LLSD map key '{}' has no value", self::text(key)))?;
+                map.insert(self::text(key).to_string(), parse(value, depth + 1)?);
+            }
+            Llsd::Map(map)
+        }
+        other => return Err(anyhow::anyhow!("Unknown LLSD XML element <{}>", other)),
+    })
+}
+
+pub(crate) fn decode_base16(data: &str) -> Result<Vec<u8>> {
+    let data = data.as_bytes();
+    if !data.len().is_multiple_of(2) {
+        return Err(anyhow::anyhow!("Odd length LLSD base16 data"));
+    }
+    let nibble = |digit: u8| {
+        char::from(digit)
+            .to_digit(16)
+            .ok_or_else(|| anyhow::anyhow!("Invalid LLSD base16 data"))
+    };
+    data.chunks_exact(2)
+        .map(|pair| Ok((nibble(pair[0])? << 4 | nibble(pair[1])?) as u8))
+        .collect()
+}
@@ -1,54 +1,453 @@
 // File: crates/storm-opensim/src/login.rs
-// OpenSim login process
+// OpenSim login process over the login_to_simulator XML-RPC call
 
+use crate::llsd::{Llsd, LlsdFormat};
+use crate::xmlrpc::{self, MethodResponse, XmlRpcValue};
 use anyhow::Result;
+use md5::{Digest, Md5};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// XML-RPC method the grid login service answers
+pub const LOGIN_METHOD: &str = "login_to_simulator";
+
+/// HTTP and login-level redirects followed before giving up
+pub const MAX_LOGIN_REDIRECTS: usize = 5;
+
+/// How long one request to the login service may take
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Response sections requested unless the caller asks for others
+pub const DEFAULT_LOGIN_OPTIONS: &[&str] = &[
+    "inventory-root",
+    "inventory-skeleton",
+    "buddy-list",
+    "login-flags",
+    "global-textures",
+];
+
+/// Where the avatar should appear after login
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StartLocation {
+    Last,
+    Home,
+    Region { name: String, x: f32, y: f32, z: f32 },
+}
+
+impl fmt::Display for StartLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Last => write!(f, "last"),
+            Self::Home => write!(f, "home"),
+            Self::Region { name, x, y, z } => write!(f, "uri:{}&{}&{}&{}", name, x, y, z),
+        }
+    }
+}
+
+impl FromStr for StartLocation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "last" => return Ok(Self::Last),
+            "home" => return Ok(Self::Home),
+            _ => {}
+        }
+        let uri = s.strip_prefix("uri:").ok_or_else(|| anyhow::anyhow!("Unknown start location '{}'", s))?;
+        let parts: Vec<&str> = uri.split('&').collect();
+        let [name, x, y, z] = parts[..] else {
+            return Err(anyhow::anyhow!("Start location '{}' is not uri:Region&x&y&z", s));
+        };
+        let coordinate = |value: &str| {
+            value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| anyhow::anyhow!("Invalid coordinate '{}' in start location", value))
+        };
+        Ok(Self::Region {
+            name: name.to_string(),
+            x: coordinate(x)?,
+            y: coordinate(y)?,
+            z: coordinate(z)?,
+        })
+    }
+}
+
+/// Hash a password the way the login service expects it; already hashed values pass through
+pub fn hash_password(password: &str) -> String {
+    if password.starts_with("$1$") {
+        return password.to_string();
+    }
+    format!("$1${:x}", Md5::digest(password.as_bytes()))
+}
 
 /// Login parameters
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginParams {
     pub first: String,
     pub last: String,
+    /// Plain text or already `$1$`-hashed password
     pub passwd: String,
-    pub start: String,
+    pub start: StartLocation,
     pub channel: String,
     pub version: String,
     pub platform: String,
     pub mac: String,
+    pub id0: String,
+    pub agree_to_tos: bool,
+    pub read_critical: bool,
     pub options: Vec<String>,
 }
 
+impl LoginParams {
+    pub fn new(first: impl Into<String>, last: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            first: first.into(),
+            last: last.into(),
+            passwd: password.into(),
+            start: StartLocation::Last,
+            channel: "StormCore".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            platform: match std::env::consts::OS {
+                "windows" => "Win",
+                "macos" => "Mac",
+                _ => "Lnx",
+            }
+            .to_string(),
+            mac: "00000000000000000000000000000000".to_string(),
+            id0: "00000000000000000000000000000000".to_string(),
+            agree_to_tos: false,
+            read_critical: false,
+            options: DEFAULT_LOGIN_OPTIONS.iter().map(|option| option.to_string()).collect(),
+        }
+    }
+
+    fn to_xmlrpc(&self) -> XmlRpcValue {
+        let string = |value: &str| XmlRpcValue::String(value.to_string());
+        let members = BTreeMap::from([
+            ("first".to_string(), string(&self.first)),
+            ("last".to_string(), string(&self.last)),
+            ("passwd".to_string(), XmlRpcValue::String(hash_password(&self.passwd))),
+            ("start".to_string(), XmlRpcValue::String(self.start.to_string())),
+            ("channel".to_string(), string(&self.channel)),
+            ("version".to_string(), string(&self.version)),
+            ("platform".to_string(), string(&self.platform)),
+            ("mac".to_string(), string(&self.mac)),
+            ("id0".to_string(), string(&self.id0)),
+            ("agree_to_tos".to_string(), XmlRpcValue::Boolean(self.agree_to_tos)),
+            ("read_critical".to_string(), XmlRpcValue::Boolean(self.read_critical)),
+            ("options".to_string(), XmlRpcValue::Array(self.options.iter().map(|option| string(option)).collect())),
+        ]);
+        XmlRpcValue::Struct(members)
+    }
+}
+
+/// Why the login service turned a login down
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoginFailure {
+    /// The agent is still logged in elsewhere
+    Presence,
+    /// Wrong name or password
+    Key,
+    /// The viewer version is no longer accepted
+    Update,
+    /// Terms of service must be accepted first
+    Tos,
+    /// A critical message must be read first
+    Critical,
+    Other(String),
+}
+
+impl From<&str> for LoginFailure {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "presence" => Self::Presence,
+            "key" => Self::Key,
+            "update" | "optional" => Self::Update,
+            "tos" => Self::Tos,
+            "critical" => Self::Critical,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for LoginFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Presence => write!(f, "presence"),
+            Self::Key => write!(f, "key"),
+            Self::Update => write!(f, "update"),
+            Self::Tos => write!(f, "tos"),
+            Self::Critical => write!(f, "critical"),
+            Self::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Login errors callers may want to handle, reachable through `anyhow::Error::downcast_ref`
+#[derive(Debug, thiserror::Error)]
+pub enum LoginError {
+    #[error("Login rejected ({reason}): {message}")]
+    Rejected { reason: LoginFailure, message: String },
+    #[error("Login service fault {code}: {message}")]
+    Fault { code: i64, message: String },
+    #[error("Login redirected more than {0} times")]
+    TooManyRedirects(usize),
+}
+
+/// Region the agent calls home
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HomeLocation {
+    pub region_handle: u64,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+}
+
+/// Folder of the inventory skeleton sent at login
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InventoryFolderSkeleton {
+    pub folder_id: Uuid,
+    pub parent_id: Uuid,
+    pub name: String,
+    pub type_default: i32,
+    pub version: i32,
+}
+
+/// Friend entry from the buddy list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Buddy {
+    pub buddy_id: Uuid,
+    pub rights_given: u32,
+    pub rights_has: u32,
+}
+
 /// Login response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginResponse {
-    pub session_id: uuid::Uuid,
-    pub secure_session_id: uuid::Uuid,
-    pub agent_id: uuid::Uuid,
+    pub session_id: Uuid,
+    pub secure_session_id: Uuid,
+    pub agent_id: Uuid,
+    pub first_name: String,
+    pub last_name: String,
+    /// Region origin in meters
     pub region_x: u32,
     pub region_y: u32,
-    pub sim_ip: std::net::Ipv4Addr,
+    pub region_size_x: u32,
+    pub region_size_y: u32,
+    pub sim_ip: Ipv4Addr,
     pub sim_port: u16,
     pub seed_capability: String,
     pub circuit_code: u32,
     pub look_at: [f32; 3],
+    pub home: Option<HomeLocation>,
+    pub start_location: String,
+    pub agent_access: String,
+    pub message: String,
+    pub inventory_root: Option<Uuid>,
+    pub inventory_skeleton: Vec<InventoryFolderSkeleton>,
+    pub buddy_list: Vec<Buddy>,
 }
 
-/// Perform login to OpenSim grid
+impl LoginResponse {
+    pub fn region_handle(&self) -> u64 {
+        ((self.region_x as u64) << 32) | self.region_y as u64
+    }
+
+    pub fn sim_address(&self) -> SocketAddr {
+        SocketAddr::from((self.sim_ip, self.sim_port))
+    }
+}
+
+enum LoginReply {
+    Success(Box<LoginResponse>),
+    Redirect { url: String, method: String },
+}
+
+/// Perform login to OpenSim grid, following HTTP and login-level redirects
 pub async fn login_to_grid(login_uri: &str, params: &LoginParams) -> Result<LoginResponse> {
-    // Implementation would make XMLRPC call to login service
-    // For now, return a mock response
-
-    Ok(LoginResponse {
-        session_id: uuid::Uuid::new_v4(),
-        secure_session_id: uuid::Uuid::new_v4(),
-        agent_id: uuid::Uuid::new_v4(),
-        region_x: 1000,
-        region_y: 1000,
-        sim_ip: std::net::Ipv4Addr::new(127, 0, 0, 1),
-        sim_port: 9000,
-        seed_capability: "http://localhost:9000/CAPS/seed".to_string(),
-        circuit_code: rand::random(),
-        look_at: [1.0, 0.0, 0.0],
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(LOGIN_TIMEOUT)
+        .build()?;
+
+    let mut url = reqwest::Url::parse(login_uri)?;
+    let mut method = LOGIN_METHOD.to_string();
+    let params = [params.to_xmlrpc()];
+
+    for _ in 0..=MAX_LOGIN_REDIRECTS {
+        debug!("Calling {} on {}", method, url);
+        let response = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "text/xml")
+            .body(xmlrpc::encode_call(&method, &params))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| anyhow::anyhow!("Login service sent HTTP {} without a location", status))?;
+            url = url.join(location)?;
+            info!("Login service redirected to {}", url);
+            continue;
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("Login service returned HTTP {}", status));
+        }
+
+        let body = response.text().await?;
+        let value = match xmlrpc::decode_response(&body)? {
+            MethodResponse::Success(value) => value,
+            MethodResponse::Fault { code, message } => return Err(LoginError::Fault { code, message }.into()),
+        };
+        match parse_login_reply(&value)? {
+            LoginReply::Success(response) => {
+                info!("Logged in as {} {} on {}", response.first_name, response.last_name, response.sim_address());
+                return Ok(*response);
+            }
+            LoginReply::Redirect { url: next_url, method: next_method } => {
+                url = url.join(&next_url)?;
+                method = next_method;
+                info!("Login continues at {}", url);
+            }
+        }
+    }
+
+    Err(LoginError::TooManyRedirects(MAX_LOGIN_REDIRECTS).into())
+}
+
+fn parse_login_reply(value: &XmlRpcValue) -> Result<LoginReply> {
+    let text = |name: &str| value.get(name).and_then(XmlRpcValue::as_str).unwrap_or_default().to_string();
+    let login = match value.get("login") {
+        Some(XmlRpcValue::Boolean(true)) => "true".to_string(),
+        Some(XmlRpcValue::Boolean(false)) => "false".to_string(),
+        _ => text("login"),
+    };
+
+    match login.as_str() {
+        "true" => {}
+        "indeterminate" => {
+            let url = text("next_url");
+            if url.is_empty() {
+                return Err(anyhow::anyhow!("Indeterminate login without next_url"));
+            }
+            let method = Some(text("next_method")).filter(|method| !method.is_empty()).unwrap_or_else(|| LOGIN_METHOD.to_string());
+            return Ok(LoginReply::Redirect { url, method });
+        }
+        _ => {
+            return Err(LoginError::Rejected {
+                reason: LoginFailure::from(text("reason").as_str()),
+                message: text("message"),
+            }
+            .into())
+        }
+    }
+
+    let uuid = |name: &str| -> Result<Uuid> {
+        let field = value.get(name).and_then(XmlRpcValue::as_str).ok_or_else(|| missing(name))?;
+        Uuid::parse_str(field).map_err(|_| anyhow::anyhow!("Invalid UUID '{}' in login field '{}'", field, name))
+    };
+    let number = |name: &str| value.get(name).and_then(XmlRpcValue::as_i64).ok_or_else(|| missing(name));
+
+    let sim_ip = text("sim_ip");
+    let skeleton = value.get("inventory-skeleton").and_then(XmlRpcValue::as_array).unwrap_or_default();
+    let buddies = value.get("buddy-list").and_then(XmlRpcValue::as_array).unwrap_or_default();
+
+    Ok(LoginReply::Success(Box::new(LoginResponse {
+        session_id: uuid("session_id")?,
+        secure_session_id: uuid("secure_session_id")?,
+        agent_id: uuid("agent_id")?,
+        first_name: text("first_name").trim_matches('"').to_string(),
+        last_name: text("last_name").trim_matches('"').to_string(),
+        region_x: number("region_x")? as u32,
+        region_y: number("region_y")? as u32,
+        region_size_x: number("region_size_x").unwrap_or(256) as u32,
+        region_size_y: number("region_size_y").unwrap_or(256) as u32,
+        sim_ip: sim_ip.parse().map_err(|_| anyhow::anyhow!("Invalid sim_ip '{}'", sim_ip))?,
+        sim_port: u16::try_from(number("sim_port")?)?,
+        seed_capability: text("seed_capability"),
+        circuit_code: number("circuit_code")? as u32,
+        look_at: notation_vector(&text("look_at")).unwrap_or([1.0, 0.0, 0.0]),
+        home: parse_home(&text("home")),
+        start_location: text("start_location"),
+        agent_access: text("agent_access"),
+        message: text("message"),
+        inventory_root: value
+            .get("inventory-root")
+            .and_then(XmlRpcValue::as_array)
+            .and_then(|roots| roots.first())
+            .and_then(|root| root.get("folder_id"))
+            .and_then(XmlRpcValue::as_str)
+            .and_then(|id| Uuid::parse_str(id).ok()),
+        inventory_skeleton: skeleton.iter().map(parse_folder).collect::<Result<_>>()?,
+        buddy_list: buddies.iter().map(parse_buddy).collect::<Result<_>>()?,
+    })))
+}
+
+fn parse_folder(value: &XmlRpcValue) -> Result<InventoryFolderSkeleton> {
+    let uuid = |name: &str| {
+        value
+            .get(name)
+            .and_then(XmlRpcValue::as_str)
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| missing(name))
+    };
+    Ok(InventoryFolderSkeleton {
+        folder_id: uuid("folder_id")?,
+        parent_id: uuid("parent_id")?,
+        name: value.get("name").and_then(XmlRpcValue::as_str).unwrap_or_default().to_string(),
+        type_default: value.get("type_default").and_then(XmlRpcValue::as_i64).unwrap_or(-1) as i32,
+        version: value.get("version").and_then(XmlRpcValue::as_i64).unwrap_or_default() as i32,
+    })
+}
+
+fn parse_buddy(value: &XmlRpcValue) -> Result<Buddy> {
+    let rights = |name: &str| value.get(name).and_then(XmlRpcValue::as_i64).unwrap_or_default() as u32;
+    Ok(Buddy {
+        buddy_id: value
+            .get("buddy_id")
+            .and_then(XmlRpcValue::as_str)
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| missing("buddy_id"))?,
+        rights_given: rights("buddy_rights_given"),
+        rights_has: rights("buddy_rights_has"),
     })
 }
+
+/// Home arrives as LLSD notation: `{'region_handle':[r256000,r256000], 'position':[r128,r128,r20], 'look_at':[r1,r0,r0]}`
+fn parse_home(text: &str) -> Option<HomeLocation> {
+    let home = Llsd::parse_as(LlsdFormat::Notation, text.as_bytes()).ok()?;
+    let [x, y] = home.get("region_handle").as_array() else {
+        return None;
+    };
+    Some(HomeLocation {
+        region_handle: ((x.as_real() as u64) << 32) | y.as_real() as u64,
+        position: llsd_vector(home.get("position"))?,
+        look_at: llsd_vector(home.get("look_at")).unwrap_or([1.0, 0.0, 0.0]),
+    })
+}
+
+/// Parse a notation vector such as `[r1,r0.5,r0]`
+fn notation_vector(text: &str) -> Option<[f32; 3]> {
+    llsd_vector(&Llsd::parse_as(LlsdFormat::Notation, text.as_bytes()).ok()?)
+}
+
+fn llsd_vector(value: &Llsd) -> Option<[f32; 3]> {
+    let [x, y, z] = value.as_array() else {
+        return None;
+    };
+    Some([x, y, z].map(|component| component.as_real() as f32))
+}
+
+fn missing(name: &str) -> anyhow::Error {
+    anyhow::anyhow!("Login response is missing '{}'", name)
+}
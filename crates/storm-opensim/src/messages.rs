@@ -1,27 +1,75 @@
 // File: crates/storm-opensim/src/messages.rs
 // OpenSim LLUDP message definitions
 
+use std::net::Ipv4Addr;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-/// LLUDP message types
+use crate::serialization::{PayloadReader, PayloadWriter};
+
+/// How often a message is sent, which decides how its number is encoded
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum LLUDPMessageType {
-    UseCircuitCode = 3,
-    CompleteAgentMovement = 249,
-    AgentUpdate = 4,
-    AgentAnimation = 20,
-    ObjectUpdate = 12,
-    ObjectUpdateCompressed = 13,
-    ObjectUpdateCached = 14,
-    RequestImage = 21,
-    ImageData = 22,
-    ChatFromViewer = 77,
-    ChatFromSimulator = 139,
-    FetchInventory = 73,
-    InventoryDescendents = 74,
-    Unknown = 0,
+pub enum Frequency {
+    /// One byte, 1-254
+    High,
+    /// 0xFF then one byte
+    Medium,
+    /// 0xFF 0xFF then a big-endian u16
+    Low,
+    /// Four bytes, 0xFFFFFFxx
+    Fixed,
+}
+
+include!(concat!(env!("OUT_DIR"), "/message_types.rs"));
+
+impl LLUDPMessageType {
+    /// Append the frequency-encoded message number
+    pub fn write_id(self, out: &mut Vec<u8>) -> Result<()> {
+        let (frequency, number) = self.id().ok_or_else(|| anyhow::anyhow!("Cannot encode a message of unknown type"))?;
+        match frequency {
+            Frequency::High => out.push(number as u8),
+            Frequency::Medium => out.extend_from_slice(&[0xFF, number as u8]),
+            Frequency::Low => {
+                out.extend_from_slice(&[0xFF, 0xFF]);
+                out.extend_from_slice(&(number as u16).to_be_bytes());
+            }
+            Frequency::Fixed => out.extend_from_slice(&number.to_be_bytes()),
+        }
+        Ok(())
+    }
+
+    /// Read a frequency-encoded message number; returns the type and the bytes it took
+    pub fn read_id(data: &[u8]) -> Result<(Self, usize)> {
+        let truncated = || anyhow::anyhow!("Message number truncated");
+        let (frequency, number, len) = match data {
+            [] => return Err(truncated()),
+            [0xFF, 0xFF, 0xFF, rest @ ..] => {
+                let low = *rest.first().ok_or_else(truncated)?;
+                (Frequency::Fixed, 0xFFFF_FF00 | low as u32, 4)
+            }
+            [0xFF, 0xFF, rest @ ..] => {
+                let [high, low, ..] = rest else {
+                    return Err(truncated());
+                };
+                (Frequency::Low, u16::from_be_bytes([*high, *low]) as u32, 4)
+            }
+            [0xFF, rest @ ..] => (Frequency::Medium, *rest.first().ok_or_else(truncated)? as u32, 2),
+            [id, ..] => (Frequency::High, *id as u32, 1),
+        };
+        Ok((Self::from_id(frequency, number), len))
+    }
 }
 
+/// Header flag: the body after the header is zero-coded
+pub const FLAG_ZEROCODED: u8 = 0x80;
+/// Header flag: the receiver must acknowledge this packet
+pub const FLAG_RELIABLE: u8 = 0x40;
+/// Header flag: this is a resend of an earlier packet
+pub const FLAG_RESENT: u8 = 0x20;
+/// Header flag: acknowledgements are appended after the body
+pub const FLAG_ACK: u8 = 0x10;
+
 /// LLUDP packet structure
 #[derive(Debug, Clone)]
 pub struct LLUDPPacket {
@@ -30,6 +78,22 @@ pub struct LLUDPPacket {
     pub extra_header: Vec<u8>,
     pub message_type: LLUDPMessageType,
     pub payload: Vec<u8>,
+    /// Sequence numbers acknowledged at the end of the datagram
+    pub acks: Vec<u32>,
+}
+
+impl LLUDPPacket {
+    pub fn is_reliable(&self) -> bool {
+        self.flags & FLAG_RELIABLE != 0
+    }
+
+    pub fn is_resent(&self) -> bool {
+        self.flags & FLAG_RESENT != 0
+    }
+
+    pub fn is_zerocoded(&self) -> bool {
+        self.flags & FLAG_ZEROCODED != 0
+    }
 }
 
 /// Grid information
@@ -62,12 +126,907 @@ pub struct AssetInfo {
 }
 
 /// Inventory item
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct InventoryItem {
     pub id: uuid::Uuid,
     pub parent_id: uuid::Uuid,
     pub name: String,
     pub description: String,
     pub asset_id: uuid::Uuid,
-    pub item_type: u32,
+    /// Asset type of the item
+    pub item_type: i8,
+    /// How the viewer presents the item, e.g. snapshots are textures with their own inventory type
+    pub inv_type: i8,
+    pub creator_id: uuid::Uuid,
+    pub owner_id: uuid::Uuid,
+    pub group_id: uuid::Uuid,
+    pub group_owned: bool,
+    pub permissions: InventoryPermissions,
+    pub flags: u32,
+    pub sale_type: u8,
+    pub sale_price: i32,
+    /// Seconds since the Unix epoch
+    pub creation_date: i32,
+}
+
+/// Permission masks of an inventory item
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryPermissions {
+    pub base_mask: u32,
+    pub owner_mask: u32,
+    pub group_mask: u32,
+    pub everyone_mask: u32,
+    pub next_owner_mask: u32,
+}
+
+/// Typed LLUDP message body with its wire encoding
+pub trait MessageBody: Sized {
+    const MESSAGE_TYPE: LLUDPMessageType;
+
+    fn encode(&self, writer: &mut PayloadWriter);
+    fn decode(reader: &mut PayloadReader<'_>) -> Result<Self>;
+
+    /// Encode into a standalone payload
+    fn to_payload(&self) -> Vec<u8> {
+        let mut writer = PayloadWriter::new();
+        self.encode(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Decode from a packet payload
+    fn from_payload(payload: &[u8]) -> Result<Self> {
+        Self::decode(&mut PayloadReader::new(payload))
+    }
+
+    /// Wrap the body in an unsequenced packet; the circuit assigns sequence numbers
+    fn to_packet(&self) -> LLUDPPacket {
+        LLUDPPacket {
+            flags: if Self::MESSAGE_TYPE.is_zerocoded() { FLAG_ZEROCODED } else { 0 },
+            sequence: 0,
+            extra_header: Vec::new(),
+            message_type: Self::MESSAGE_TYPE,
+            payload: self.to_payload(),
+            acks: Vec::new(),
+        }
+    }
+}
+
+/// Viewer's first message on a new circuit
+#[derive(Debug, Clone, PartialEq)]
+pub struct UseCircuitCode {
+    pub code: u32,
+    pub session_id: Uuid,
+    pub agent_id: Uuid,
+}
+
+impl MessageBody for UseCircuitCode {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::UseCircuitCode;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u32(self.code).uuid(self.session_id).uuid(self.agent_id);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            code: r.u32()?,
+            session_id: r.uuid()?,
+            agent_id: r.uuid()?,
+        })
+    }
+}
+
+/// Viewer asks to be placed in the region
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompleteAgentMovement {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub circuit_code: u32,
+}
+
+impl MessageBody for CompleteAgentMovement {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::CompleteAgentMovement;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id).uuid(self.session_id).u32(self.circuit_code);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            circuit_code: r.u32()?,
+        })
+    }
+}
+
+/// Simulator confirms the agent has arrived
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentMovementComplete {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    pub region_handle: u64,
+    pub timestamp: u32,
+    pub channel_version: String,
+}
+
+impl MessageBody for AgentMovementComplete {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::AgentMovementComplete;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .uuid(self.session_id)
+            .vector3(self.position)
+            .vector3(self.look_at)
+            .u64(self.region_handle)
+            .u32(self.timestamp)
+            .string2(&self.channel_version);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            position: r.vector3()?,
+            look_at: r.vector3()?,
+            region_handle: r.u64()?,
+            timestamp: r.u32()?,
+            channel_version: r.string2()?,
+        })
+    }
+}
+
+/// Region description sent when an agent's circuit opens
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionHandshake {
+    pub region_flags: u32,
+    pub sim_access: u8,
+    pub sim_name: String,
+    pub sim_owner: Uuid,
+    pub is_estate_manager: bool,
+    pub water_height: f32,
+    pub billable_factor: f32,
+    pub cache_id: Uuid,
+    pub terrain_textures: [Uuid; 4],
+    pub terrain_detail: [Uuid; 4],
+    pub terrain_start_heights: [f32; 4],
+    pub terrain_height_ranges: [f32; 4],
+    pub region_id: Uuid,
+}
+
+impl MessageBody for RegionHandshake {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::RegionHandshake;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u32(self.region_flags)
+            .u8(self.sim_access)
+            .string1(&self.sim_name)
+            .uuid(self.sim_owner)
+            .bool(self.is_estate_manager)
+            .f32(self.water_height)
+            .f32(self.billable_factor)
+            .uuid(self.cache_id);
+        for id in self.terrain_textures.iter().chain(self.terrain_detail.iter()) {
+            w.uuid(*id);
+        }
+        for value in self.terrain_start_heights.iter().chain(self.terrain_height_ranges.iter()) {
+            w.f32(*value);
+        }
+        w.uuid(self.region_id);
+        // RegionInfo3: CPU class/ratio, colo, SKU and product name
+        w.i32(1).i32(1).string1("").string1("").string1("StormCore");
+        // RegionInfo4: no extended flags
+        w.u8(0);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        let region_flags = r.u32()?;
+        let sim_access = r.u8()?;
+        let sim_name = r.string1()?;
+        let sim_owner = r.uuid()?;
+        let is_estate_manager = r.bool()?;
+        let water_height = r.f32()?;
+        let billable_factor = r.f32()?;
+        let cache_id = r.uuid()?;
+        let mut terrain_textures = [Uuid::nil(); 4];
+        for id in terrain_textures.iter_mut() {
+            *id = r.uuid()?;
+        }
+        let mut terrain_detail = [Uuid::nil(); 4];
+        for id in terrain_detail.iter_mut() {
+            *id = r.uuid()?;
+        }
+        let mut terrain_start_heights = [0.0; 4];
+        for value in terrain_start_heights.iter_mut() {
+            *value = r.f32()?;
+        }
+        let mut terrain_height_ranges = [0.0; 4];
+        for value in terrain_height_ranges.iter_mut() {
+            *value = r.f32()?;
+        }
+        let region_id = r.uuid()?;
+
+        Ok(Self {
+            region_flags,
+            sim_access,
+            sim_name,
+            sim_owner,
+            is_estate_manager,
+            water_height,
+            billable_factor,
+            cache_id,
+            terrain_textures,
+            terrain_detail,
+            terrain_start_heights,
+            terrain_height_ranges,
+            region_id,
+        })
+    }
+}
+
+/// Viewer reply to RegionHandshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionHandshakeReply {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub flags: u32,
+}
+
+impl MessageBody for RegionHandshakeReply {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::RegionHandshakeReply;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id).uuid(self.session_id).u32(self.flags);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            flags: r.u32()?,
+        })
+    }
+}
+
+/// Per-frame agent control state from the viewer
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentUpdate {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub body_rotation: [f32; 4],
+    pub head_rotation: [f32; 4],
+    pub state: u8,
+    pub camera_center: [f32; 3],
+    pub camera_at_axis: [f32; 3],
+    pub camera_left_axis: [f32; 3],
+    pub camera_up_axis: [f32; 3],
+    pub far: f32,
+    pub control_flags: u32,
+    pub flags: u8,
+}
+
+impl MessageBody for AgentUpdate {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::AgentUpdate;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .uuid(self.session_id)
+            .quaternion(self.body_rotation)
+            .quaternion(self.head_rotation)
+            .u8(self.state)
+            .vector3(self.camera_center)
+            .vector3(self.camera_at_axis)
+            .vector3(self.camera_left_axis)
+            .vector3(self.camera_up_axis)
+            .f32(self.far)
+            .u32(self.control_flags)
+            .u8(self.flags);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            body_rotation: r.quaternion()?,
+            head_rotation: r.quaternion()?,
+            state: r.u8()?,
+            camera_center: r.vector3()?,
+            camera_at_axis: r.vector3()?,
+            camera_left_axis: r.vector3()?,
+            camera_up_axis: r.vector3()?,
+            far: r.f32()?,
+            control_flags: r.u32()?,
+            flags: r.u8()?,
+        })
+    }
+}
+
+/// Local chat typed by a viewer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatFromViewer {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub message: String,
+    pub chat_type: u8,
+    pub channel: i32,
+}
+
+impl MessageBody for ChatFromViewer {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::ChatFromViewer;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .uuid(self.session_id)
+            .string2(&self.message)
+            .u8(self.chat_type)
+            .i32(self.channel);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            message: r.string2()?,
+            chat_type: r.u8()?,
+            channel: r.i32()?,
+        })
+    }
+}
+
+/// Chat relayed by the simulator to nearby agents
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatFromSimulator {
+    pub from_name: String,
+    pub source_id: Uuid,
+    pub owner_id: Uuid,
+    pub source_type: u8,
+    pub chat_type: u8,
+    pub audible: u8,
+    pub position: [f32; 3],
+    pub message: String,
+}
+
+impl MessageBody for ChatFromSimulator {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::ChatFromSimulator;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.string1(&self.from_name)
+            .uuid(self.source_id)
+            .uuid(self.owner_id)
+            .u8(self.source_type)
+            .u8(self.chat_type)
+            .u8(self.audible)
+            .vector3(self.position)
+            .string2(&self.message);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            from_name: r.string1()?,
+            source_id: r.uuid()?,
+            owner_id: r.uuid()?,
+            source_type: r.u8()?,
+            chat_type: r.u8()?,
+            audible: r.u8()?,
+            position: r.vector3()?,
+            message: r.string2()?,
+        })
+    }
+}
+
+/// Full object state for one or more prims or avatars
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectUpdate {
+    pub region_handle: u64,
+    pub time_dilation: u16,
+    pub objects: Vec<ObjectUpdateBlock>,
+}
+
+/// One object in an ObjectUpdate; path/profile parameters default to a unit box
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectUpdateBlock {
+    pub local_id: u32,
+    pub state: u8,
+    pub full_id: Uuid,
+    pub crc: u32,
+    pub pcode: u8,
+    pub material: u8,
+    pub click_action: u8,
+    pub scale: [f32; 3],
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub acceleration: [f32; 3],
+    pub rotation: [f32; 4],
+    pub angular_velocity: [f32; 3],
+    pub parent_id: u32,
+    pub update_flags: u32,
+    pub texture_entry: Vec<u8>,
+    pub name_value: String,
+    pub text: String,
+    pub owner_id: Uuid,
+}
+
+impl ObjectUpdateBlock {
+    /// Primitive volume
+    pub const PCODE_PRIM: u8 = 9;
+    /// Avatar
+    pub const PCODE_AVATAR: u8 = 47;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u32(self.local_id)
+            .u8(self.state)
+            .uuid(self.full_id)
+            .u32(self.crc)
+            .u8(self.pcode)
+            .u8(self.material)
+            .u8(self.click_action)
+            .vector3(self.scale);
+
+        // ObjectData: position, velocity, acceleration, rotation, angular velocity
+        let mut motion = PayloadWriter::new();
+        motion
+            .vector3(self.position)
+            .vector3(self.velocity)
+            .vector3(self.acceleration)
+            .quaternion(self.rotation)
+            .vector3(self.angular_velocity);
+        w.var1(&motion.into_bytes());
+
+        w.u32(self.parent_id).u32(self.update_flags);
+
+        // Path and profile parameters for a default box
+        w.u8(0x10) // PathCurve: line
+            .u8(0x01) // ProfileCurve: square
+            .u16(0) // PathBegin
+            .u16(0) // PathEnd
+            .u8(100) // PathScaleX
+            .u8(100) // PathScaleY
+            .u8(0) // PathShearX
+            .u8(0) // PathShearY
+            .u8(0) // PathTwist
+            .u8(0) // PathTwistBegin
+            .u8(0) // PathRadiusOffset
+            .u8(0) // PathTaperX
+            .u8(0) // PathTaperY
+            .u8(0) // PathRevolutions
+            .u8(0) // PathSkew
+            .u16(0) // ProfileBegin
+            .u16(0) // ProfileEnd
+            .u16(0); // ProfileHollow
+
+        w.var2(&self.texture_entry)
+            .var1(&[]) // TextureAnim
+            .string2(&self.name_value)
+            .var2(&[]) // Data
+            .string1(&self.text)
+            .bytes(&[0, 0, 0, 0]) // TextColor
+            .var1(&[]) // MediaURL
+            .var1(&[]) // PSBlock
+            .var1(&[]) // ExtraParams
+            .uuid(Uuid::nil()) // Sound
+            .uuid(self.owner_id)
+            .f32(0.0) // Gain
+            .u8(0) // Flags
+            .f32(0.0) // Radius
+            .u8(0) // JointType
+            .vector3([0.0; 3]) // JointPivot
+            .vector3([0.0; 3]); // JointAxisOrAnchor
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        let local_id = r.u32()?;
+        let state = r.u8()?;
+        let full_id = r.uuid()?;
+        let crc = r.u32()?;
+        let pcode = r.u8()?;
+        let material = r.u8()?;
+        let click_action = r.u8()?;
+        let scale = r.vector3()?;
+
+        // Avatars prefix the motion block with a 16 byte collision plane
+        let motion = r.var1()?;
+        let mut m = PayloadReader::new(motion);
+        if motion.len() >= 76 {
+            m.bytes(16)?;
+        }
+        let position = m.vector3()?;
+        let velocity = m.vector3()?;
+        let acceleration = m.vector3()?;
+        let rotation = m.quaternion()?;
+        let angular_velocity = m.vector3()?;
+
+        let parent_id = r.u32()?;
+        let update_flags = r.u32()?;
+
+        // Path/profile parameters: 2 + 4 + 11 + 6 bytes
+        r.bytes(23)?;
+
+        let texture_entry = r.var2()?.to_vec();
+        r.var1()?; // TextureAnim
+        let name_value = r.string2()?;
+        r.var2()?; // Data
+        let text = r.string1()?;
+        r.bytes(4)?; // TextColor
+        r.var1()?; // MediaURL
+        r.var1()?; // PSBlock
+        r.var1()?; // ExtraParams
+        r.uuid()?; // Sound
+        let owner_id = r.uuid()?;
+        r.bytes(4 + 1 + 4 + 1 + 12 + 12)?; // Gain, Flags, Radius, joint data
+
+        Ok(Self {
+            local_id,
+            state,
+            full_id,
+            crc,
+            pcode,
+            material,
+            click_action,
+            scale,
+            position,
+            velocity,
+            acceleration,
+            rotation,
+            angular_velocity,
+            parent_id,
+            update_flags,
+            texture_entry,
+            name_value,
+            text,
+            owner_id,
+        })
+    }
+}
+
+impl MessageBody for ObjectUpdate {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::ObjectUpdate;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u64(self.region_handle).u16(self.time_dilation);
+        w.u8(self.objects.len().min(u8::MAX as usize) as u8);
+        for object in self.objects.iter().take(u8::MAX as usize) {
+            object.encode(w);
+        }
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        let region_handle = r.u64()?;
+        let time_dilation = r.u16()?;
+        let count = r.u8()? as usize;
+        let mut objects = Vec::with_capacity(count);
+        for _ in 0..count {
+            objects.push(ObjectUpdateBlock::decode(r)?);
+        }
+        Ok(Self { region_handle, time_dilation, objects })
+    }
+}
+
+/// Objects removed from the region
+#[derive(Debug, Clone, PartialEq)]
+pub struct KillObject {
+    pub local_ids: Vec<u32>,
+}
+
+impl MessageBody for KillObject {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::KillObject;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u8(self.local_ids.len().min(u8::MAX as usize) as u8);
+        for id in self.local_ids.iter().take(u8::MAX as usize) {
+            w.u32(*id);
+        }
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        let count = r.u8()? as usize;
+        let mut local_ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            local_ids.push(r.u32()?);
+        }
+        Ok(Self { local_ids })
+    }
+}
+
+/// Viewer is leaving the region
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogoutRequest {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+}
+
+impl MessageBody for LogoutRequest {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::LogoutRequest;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id).uuid(self.session_id);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+        })
+    }
+}
+
+/// Viewer asks to teleport to a region by id
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportRequest {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub region_id: Uuid,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+}
+
+impl MessageBody for TeleportRequest {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportRequest;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .uuid(self.session_id)
+            .uuid(self.region_id)
+            .vector3(self.position)
+            .vector3(self.look_at);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            region_id: r.uuid()?,
+            position: r.vector3()?,
+            look_at: r.vector3()?,
+        })
+    }
+}
+
+/// Viewer asks to teleport to a position in a region identified by handle
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportLocationRequest {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub region_handle: u64,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+}
+
+impl MessageBody for TeleportLocationRequest {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportLocationRequest;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .uuid(self.session_id)
+            .u64(self.region_handle)
+            .vector3(self.position)
+            .vector3(self.look_at);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            region_handle: r.u64()?,
+            position: r.vector3()?,
+            look_at: r.vector3()?,
+        })
+    }
+}
+
+/// Teleport within the current region
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportLocal {
+    pub agent_id: Uuid,
+    pub location_id: u32,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    pub teleport_flags: u32,
+}
+
+impl MessageBody for TeleportLocal {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportLocal;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .u32(self.location_id)
+            .vector3(self.position)
+            .vector3(self.look_at)
+            .u32(self.teleport_flags);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            location_id: r.u32()?,
+            position: r.vector3()?,
+            look_at: r.vector3()?,
+            teleport_flags: r.u32()?,
+        })
+    }
+}
+
+/// Simulator has accepted a teleport request
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportStart {
+    pub teleport_flags: u32,
+}
+
+impl MessageBody for TeleportStart {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportStart;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u32(self.teleport_flags);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self { teleport_flags: r.u32()? })
+    }
+}
+
+/// Status text shown while a teleport is in flight
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportProgress {
+    pub agent_id: Uuid,
+    pub teleport_flags: u32,
+    pub message: String,
+}
+
+impl MessageBody for TeleportProgress {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportProgress;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id).u32(self.teleport_flags).string1(&self.message);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            teleport_flags: r.u32()?,
+            message: r.string1()?,
+        })
+    }
+}
+
+/// Teleport succeeded; the viewer should connect to the destination simulator
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportFinish {
+    pub agent_id: Uuid,
+    pub location_id: u32,
+    pub sim_ip: Ipv4Addr,
+    pub sim_port: u16,
+    pub region_handle: u64,
+    pub seed_capability: String,
+    pub sim_access: u8,
+    pub teleport_flags: u32,
+}
+
+impl MessageBody for TeleportFinish {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportFinish;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .u32(self.location_id)
+            .ip_addr(self.sim_ip)
+            .ip_port(self.sim_port)
+            .u64(self.region_handle)
+            .string2(&self.seed_capability)
+            .u8(self.sim_access)
+            .u32(self.teleport_flags);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            location_id: r.u32()?,
+            sim_ip: r.ip_addr()?,
+            sim_port: r.ip_port()?,
+            region_handle: r.u64()?,
+            seed_capability: r.string2()?,
+            sim_access: r.u8()?,
+            teleport_flags: r.u32()?,
+        })
+    }
+}
+
+/// Teleport was refused or could not be completed
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportFailed {
+    pub agent_id: Uuid,
+    pub reason: String,
+}
+
+impl MessageBody for TeleportFailed {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::TeleportFailed;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        // Empty AlertInfo block list
+        w.uuid(self.agent_id).string1(&self.reason).u8(0);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            reason: r.string1()?,
+        })
+    }
+}
+
+/// Agent walked across the border into a neighbouring simulator
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossedRegion {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub sim_ip: Ipv4Addr,
+    pub sim_port: u16,
+    pub region_handle: u64,
+    pub seed_capability: String,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+}
+
+impl MessageBody for CrossedRegion {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::CrossedRegion;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.uuid(self.agent_id)
+            .uuid(self.session_id)
+            .ip_addr(self.sim_ip)
+            .ip_port(self.sim_port)
+            .u64(self.region_handle)
+            .string2(&self.seed_capability)
+            .vector3(self.position)
+            .vector3(self.look_at);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            agent_id: r.uuid()?,
+            session_id: r.uuid()?,
+            sim_ip: r.ip_addr()?,
+            sim_port: r.ip_port()?,
+            region_handle: r.u64()?,
+            seed_capability: r.string2()?,
+            position: r.vector3()?,
+            look_at: r.vector3()?,
+        })
+    }
+}
+
+/// Tells the viewer to open a child circuit to a neighbouring simulator
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnableSimulator {
+    pub region_handle: u64,
+    pub sim_ip: Ipv4Addr,
+    pub sim_port: u16,
+}
+
+impl MessageBody for EnableSimulator {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::EnableSimulator;
+
+    fn encode(&self, w: &mut PayloadWriter) {
+        w.u64(self.region_handle).ip_addr(self.sim_ip).ip_port(self.sim_port);
+    }
+
+    fn decode(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            region_handle: r.u64()?,
+            sim_ip: r.ip_addr()?,
+            sim_port: r.ip_port()?,
+        })
+    }
+}
+
+/// Tells the viewer to close the circuit this message arrived on
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisableSimulator;
+
+impl MessageBody for DisableSimulator {
+    const MESSAGE_TYPE: LLUDPMessageType = LLUDPMessageType::DisableSimulator;
+
+    fn encode(&self, _w: &mut PayloadWriter) {}
+
+    fn decode(_r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self)
+    }
 }
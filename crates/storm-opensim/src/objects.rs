@@ -0,0 +1,783 @@
+// File: crates/storm-opensim/src/objects.rs
+// Region objects: decoding the ObjectUpdate family into ECS entities
+
+use std::collections::{BTreeMap, HashMap};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use storm_ecs::{Component, Entity, Transform, Velocity, World};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::messages::{LLUDPMessageType, LLUDPPacket, MessageBody};
+use crate::serialization::PayloadReader;
+use crate::template::{
+    ImprovedTerseObjectUpdate, ImprovedTerseObjectUpdateObjectData, KillObject, ObjectUpdate, ObjectUpdateCached,
+    ObjectUpdateCompressed, ObjectUpdateObjectData, RequestMultipleObjects, RequestMultipleObjectsAgentData,
+    RequestMultipleObjectsObjectData,
+};
+use crate::texture_entry::TextureEntry;
+
+/// Primitive volume
+pub const PCODE_PRIM: u8 = 9;
+/// Avatar
+pub const PCODE_AVATAR: u8 = 47;
+/// Linden grass
+pub const PCODE_GRASS: u8 = 95;
+/// Linden tree
+pub const PCODE_TREE: u8 = 255;
+
+/// Flags announcing the optional sections of an ObjectUpdateCompressed record
+pub mod compressed_flags {
+    pub const SCRATCH_PAD: u32 = 0x001;
+    pub const TREE: u32 = 0x002;
+    pub const HAS_TEXT: u32 = 0x004;
+    pub const HAS_PARTICLES: u32 = 0x008;
+    pub const HAS_SOUND: u32 = 0x010;
+    pub const HAS_PARENT: u32 = 0x020;
+    pub const TEXTURE_ANIMATION: u32 = 0x040;
+    pub const HAS_ANGULAR_VELOCITY: u32 = 0x080;
+    pub const HAS_NAME_VALUES: u32 = 0x100;
+    pub const MEDIA_URL: u32 = 0x200;
+}
+
+/// Legacy particle system block size
+const PARTICLE_BLOCK_SIZE: usize = 86;
+
+/// Identity and simulator flags of a region object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenSimObject {
+    pub local_id: u32,
+    pub full_id: Uuid,
+    pub pcode: u8,
+    pub state: u8,
+    /// Changes whenever the simulator's copy of the object changes; drives the object cache
+    pub crc: u32,
+    pub material: u8,
+    pub click_action: u8,
+    pub update_flags: u32,
+    pub owner_id: Uuid,
+}
+
+impl OpenSimObject {
+    pub fn is_avatar(&self) -> bool {
+        self.pcode == PCODE_AVATAR
+    }
+}
+
+/// Path and profile parameters of a prim, in their quantized wire form
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrimShape {
+    pub path_curve: u8,
+    pub profile_curve: u8,
+    pub path_begin: u16,
+    pub path_end: u16,
+    pub path_scale_x: u8,
+    pub path_scale_y: u8,
+    pub path_shear_x: u8,
+    pub path_shear_y: u8,
+    pub path_twist: i8,
+    pub path_twist_begin: i8,
+    pub path_radius_offset: i8,
+    pub path_taper_x: i8,
+    pub path_taper_y: i8,
+    pub path_revolutions: u8,
+    pub path_skew: i8,
+    pub profile_begin: u16,
+    pub profile_end: u16,
+    pub profile_hollow: u16,
+}
+
+impl PrimShape {
+    /// Path cut start, 0..1
+    pub fn path_begin(&self) -> f32 {
+        self.path_begin as f32 * 2.0e-5
+    }
+
+    /// Path cut end, 0..1
+    pub fn path_end(&self) -> f32 {
+        1.0 - self.path_end as f32 * 2.0e-5
+    }
+
+    /// Top size, 0..2 per axis
+    pub fn path_scale(&self) -> [f32; 2] {
+        [(200 - self.path_scale_x as i32) as f32 * 0.01, (200 - self.path_scale_y as i32) as f32 * 0.01]
+    }
+
+    /// Hollow fraction, 0..1
+    pub fn profile_hollow(&self) -> f32 {
+        self.profile_hollow as f32 * 2.0e-5
+    }
+
+    /// Compressed updates order the fields differently from ObjectUpdate
+    fn read_compressed(r: &mut PayloadReader<'_>) -> Result<Self> {
+        Ok(Self {
+            path_curve: r.u8()?,
+            path_begin: r.u16()?,
+            path_end: r.u16()?,
+            path_scale_x: r.u8()?,
+            path_scale_y: r.u8()?,
+            path_shear_x: r.u8()?,
+            path_shear_y: r.u8()?,
+            path_twist: r.i8()?,
+            path_twist_begin: r.i8()?,
+            path_radius_offset: r.i8()?,
+            path_taper_x: r.i8()?,
+            path_taper_y: r.i8()?,
+            path_revolutions: r.u8()?,
+            path_skew: r.i8()?,
+            profile_curve: r.u8()?,
+            profile_begin: r.u16()?,
+            profile_end: r.u16()?,
+            profile_hollow: r.u16()?,
+        })
+    }
+}
+
+/// Avatar names and hover text; prim names arrive separately in ObjectProperties
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectName {
+    /// "First Last" for avatars
+    pub name: Option<String>,
+    pub name_values: BTreeMap<String, String>,
+    pub hover_text: String,
+    pub hover_color: [u8; 4],
+}
+
+impl ObjectName {
+    /// Parse NameValue lines of the form `Name TYPE CLASS SENDTO value`
+    pub fn from_name_values(name_values: &str, hover_text: &str, hover_color: [u8; 4]) -> Self {
+        let name_values: BTreeMap<String, String> = name_values
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(5, ' ');
+                let name = parts.next()?;
+                let value = parts.nth(3)?;
+                Some((name.to_string(), value.trim().to_string()))
+            })
+            .collect();
+        let name = match (name_values.get("FirstName"), name_values.get("LastName")) {
+            (Some(first), Some(last)) => Some(format!("{} {}", first, last)),
+            _ => None,
+        };
+        Self {
+            name,
+            name_values,
+            hover_text: hover_text.to_string(),
+            hover_color,
+        }
+    }
+}
+
+/// Link to the object this one is attached to or sits on
+///
+/// Children's transforms are relative to the parent. `entity` stays empty until the parent arrives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentLink {
+    /// Parent local id, 0 for root objects
+    pub parent_id: u32,
+    pub entity: Option<Entity>,
+}
+
+/// Motion state carried by full and terse updates
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectMotion {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub acceleration: [f32; 3],
+    pub rotation: [f32; 4],
+    pub angular_velocity: [f32; 3],
+    /// Avatars' foot collision plane
+    pub collision_plane: Option<[f32; 4]>,
+}
+
+impl ObjectMotion {
+    /// Decode an ObjectUpdate motion block; its size selects full, 16-bit or 8-bit precision
+    pub fn from_object_data(data: &[u8]) -> Result<Self> {
+        let mut r = PayloadReader::new(data);
+        let collision_plane = match data.len() {
+            76 | 48 => Some(r.vector4()?),
+            60 | 32 | 16 => None,
+            other => return Err(anyhow::anyhow!("Unexpected ObjectUpdate motion block of {} bytes", other)),
+        };
+
+        let mut motion = match data.len() {
+            76 | 60 => Self {
+                position: r.vector3()?,
+                velocity: r.vector3()?,
+                acceleration: r.vector3()?,
+                rotation: r.quaternion()?,
+                angular_velocity: r.vector3()?,
+                collision_plane: None,
+            },
+            48 | 32 => Self {
+                position: read_u16_vector(&mut r, -0.5 * 256.0, 1.5 * 256.0)?,
+                velocity: read_u16_vector(&mut r, -256.0, 256.0)?,
+                acceleration: read_u16_vector(&mut r, -256.0, 256.0)?,
+                rotation: read_u16_rotation(&mut r)?,
+                angular_velocity: read_u16_vector(&mut r, -256.0, 256.0)?,
+                collision_plane: None,
+            },
+            _ => {
+                let vector = |r: &mut PayloadReader<'_>| -> Result<[f32; 3]> {
+                    Ok([u8_to_float(r.u8()?, -256.0, 256.0), u8_to_float(r.u8()?, -256.0, 256.0), u8_to_float(r.u8()?, -256.0, 256.0)])
+                };
+                Self {
+                    position: vector(&mut r)?,
+                    velocity: vector(&mut r)?,
+                    acceleration: vector(&mut r)?,
+                    rotation: [
+                        u8_to_float(r.u8()?, -1.0, 1.0),
+                        u8_to_float(r.u8()?, -1.0, 1.0),
+                        u8_to_float(r.u8()?, -1.0, 1.0),
+                        u8_to_float(r.u8()?, -1.0, 1.0),
+                    ],
+                    angular_velocity: vector(&mut r)?,
+                    collision_plane: None,
+                }
+            }
+        };
+        motion.collision_plane = collision_plane;
+        Ok(motion)
+    }
+}
+
+/// Everything a full or compressed update says about an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectState {
+    pub object: OpenSimObject,
+    pub scale: [f32; 3],
+    pub motion: ObjectMotion,
+    pub parent_id: u32,
+    pub shape: PrimShape,
+    pub texture_entry: TextureEntry,
+    pub name: ObjectName,
+}
+
+impl ObjectState {
+    pub fn from_update(block: &ObjectUpdateObjectData) -> Result<Self> {
+        Ok(Self {
+            object: OpenSimObject {
+                local_id: block.id,
+                full_id: block.full_id,
+                pcode: block.p_code,
+                state: block.state,
+                crc: block.crc,
+                material: block.material,
+                click_action: block.click_action,
+                update_flags: block.update_flags,
+                owner_id: block.owner_id,
+            },
+            scale: block.scale,
+            motion: ObjectMotion::from_object_data(&block.object_data)?,
+            parent_id: block.parent_id,
+            shape: PrimShape {
+                path_curve: block.path_curve,
+                profile_curve: block.profile_curve,
+                path_begin: block.path_begin,
+                path_end: block.path_end,
+                path_scale_x: block.path_scale_x,
+                path_scale_y: block.path_scale_y,
+                path_shear_x: block.path_shear_x,
+                path_shear_y: block.path_shear_y,
+                path_twist: block.path_twist,
+                path_twist_begin: block.path_twist_begin,
+                path_radius_offset: block.path_radius_offset,
+                path_taper_x: block.path_taper_x,
+                path_taper_y: block.path_taper_y,
+                path_revolutions: block.path_revolutions,
+                path_skew: block.path_skew,
+                profile_begin: block.profile_begin,
+                profile_end: block.profile_end,
+                profile_hollow: block.profile_hollow,
+            },
+            texture_entry: TextureEntry::from_bytes(&block.texture_entry)?,
+            name: ObjectName::from_name_values(
+                &text(&block.name_value),
+                &text(&block.text),
+                block.text_color.as_slice().try_into().unwrap_or_default(),
+            ),
+        })
+    }
+
+    /// Decode one ObjectUpdateCompressed record
+    pub fn from_compressed(update_flags: u32, data: &[u8]) -> Result<Self> {
+        use compressed_flags::*;
+
+        let mut r = PayloadReader::new(data);
+        let full_id = r.uuid()?;
+        let local_id = r.u32()?;
+        let pcode = r.u8()?;
+        let state = r.u8()?;
+        let crc = r.u32()?;
+        let material = r.u8()?;
+        let click_action = r.u8()?;
+        let scale = r.vector3()?;
+        let position = r.vector3()?;
+        let rotation = r.quaternion()?;
+        let flags = r.u32()?;
+        let owner_id = r.uuid()?;
+
+        let angular_velocity = if flags & HAS_ANGULAR_VELOCITY != 0 { r.vector3()? } else { [0.0; 3] };
+        let parent_id = if flags & HAS_PARENT != 0 { r.u32()? } else { 0 };
+        if flags & TREE != 0 {
+            r.u8()?; // species
+        } else if flags & SCRATCH_PAD != 0 {
+            let len = r.u8()? as usize;
+            r.bytes(len)?;
+        }
+        let (hover_text, hover_color) = if flags & HAS_TEXT != 0 {
+            (c_string(&mut r)?, r.bytes(4)?.try_into()?)
+        } else {
+            (String::new(), [0; 4])
+        };
+        if flags & MEDIA_URL != 0 {
+            c_string(&mut r)?;
+        }
+        if flags & HAS_PARTICLES != 0 {
+            r.bytes(PARTICLE_BLOCK_SIZE)?;
+        }
+
+        // Extra parameters (flexi, light, sculpt) are always present, if only as a zero count
+        for _ in 0..r.u8()? {
+            r.u16()?;
+            let len = r.u32()? as usize;
+            r.bytes(len)?;
+        }
+        if flags & HAS_SOUND != 0 {
+            r.bytes(16 + 4 + 1 + 4)?; // sound id, gain, flags, radius
+        }
+        let name_values = if flags & HAS_NAME_VALUES != 0 { c_string(&mut r)? } else { String::new() };
+
+        let shape = PrimShape::read_compressed(&mut r)?;
+        let texture_entry = {
+            let len = r.u32()? as usize;
+            TextureEntry::from_bytes(r.bytes(len)?)?
+        };
+
+        Ok(Self {
+            object: OpenSimObject {
+                local_id,
+                full_id,
+                pcode,
+                state,
+                crc,
+                material,
+                click_action,
+                update_flags,
+                owner_id,
+            },
+            scale,
+            motion: ObjectMotion {
+                position,
+                rotation,
+                angular_velocity,
+                ..Default::default()
+            },
+            parent_id,
+            shape,
+            texture_entry,
+            name: ObjectName::from_name_values(&name_values, &hover_text, hover_color),
+        })
+    }
+}
+
+/// Motion-only update from ImprovedTerseObjectUpdate
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerseUpdate {
+    pub local_id: u32,
+    pub state: u8,
+    pub motion: ObjectMotion,
+    pub texture_entry: Option<TextureEntry>,
+}
+
+impl TerseUpdate {
+    pub fn decode(block: &ImprovedTerseObjectUpdateObjectData) -> Result<Self> {
+        let mut r = PayloadReader::new(&block.data);
+        let local_id = r.u32()?;
+        let state = r.u8()?;
+        let avatar = r.bool()?;
+        let collision_plane = if avatar { Some(r.vector4()?) } else { None };
+        let motion = ObjectMotion {
+            position: r.vector3()?,
+            velocity: read_u16_vector(&mut r, -128.0, 128.0)?,
+            acceleration: read_u16_vector(&mut r, -64.0, 64.0)?,
+            rotation: read_u16_rotation(&mut r)?,
+            angular_velocity: read_u16_vector(&mut r, -64.0, 64.0)?,
+            collision_plane,
+        };
+
+        // Terse texture entries carry a 4 byte length prefix
+        let texture_entry = match block.texture_entry.get(4..) {
+            Some(bytes) if !bytes.is_empty() => Some(TextureEntry::from_bytes(bytes)?),
+            _ => None,
+        };
+        Ok(Self {
+            local_id,
+            state,
+            motion,
+            texture_entry,
+        })
+    }
+}
+
+/// Objects touched by one message, by local id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectChanges {
+    pub added: Vec<u32>,
+    pub updated: Vec<u32>,
+    pub removed: Vec<u32>,
+    /// ObjectUpdateCached entries the cache could not satisfy; request them with RequestMultipleObjects
+    pub cache_misses: Vec<u32>,
+}
+
+/// Last full state of each object seen, keyed by local id and validated by CRC
+#[derive(Debug, Clone, Default)]
+pub struct ObjectCache {
+    entries: HashMap<u32, ObjectState>,
+}
+
+impl ObjectCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached state, if it still matches the simulator's CRC
+    pub fn get(&self, local_id: u32, crc: u32) -> Option<&ObjectState> {
+        self.entries.get(&local_id).filter(|state| state.object.crc == crc)
+    }
+
+    pub fn insert(&mut self, state: ObjectState) {
+        self.entries.insert(state.object.local_id, state);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Objects of one region, mirrored into the ECS world
+///
+/// Maps simulator local ids and full UUIDs to entities carrying `Transform`, `Velocity`,
+/// `OpenSimObject`, `PrimShape`, `TextureEntry`, `ObjectName` and `ParentLink`.
+#[derive(Debug, Default)]
+pub struct RegionObjects {
+    entities: HashMap<u32, Entity>,
+    full_ids: HashMap<Uuid, u32>,
+    /// Children that arrived before their parent, keyed by parent local id
+    orphans: HashMap<u32, Vec<u32>>,
+    cache: ObjectCache,
+}
+
+impl RegionObjects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entity(&self, local_id: u32) -> Option<Entity> {
+        self.entities.get(&local_id).copied()
+    }
+
+    pub fn entity_by_full_id(&self, full_id: Uuid) -> Option<Entity> {
+        self.entity(self.local_id(full_id)?)
+    }
+
+    pub fn local_id(&self, full_id: Uuid) -> Option<u32> {
+        self.full_ids.get(&full_id).copied()
+    }
+
+    /// Number of objects in the world
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn cache(&self) -> &ObjectCache {
+        &self.cache
+    }
+
+    /// Apply any object message; other message types change nothing
+    pub fn handle_packet(&mut self, world: &mut World, packet: &LLUDPPacket) -> Result<ObjectChanges> {
+        let payload = &packet.payload;
+        Ok(match packet.message_type {
+            LLUDPMessageType::ObjectUpdate => self.apply_update(world, &ObjectUpdate::from_payload(payload)?),
+            LLUDPMessageType::ObjectUpdateCompressed => {
+                self.apply_compressed(world, &ObjectUpdateCompressed::from_payload(payload)?)
+            }
+            LLUDPMessageType::ObjectUpdateCached => self.apply_cached(world, &ObjectUpdateCached::from_payload(payload)?),
+            LLUDPMessageType::ImprovedTerseObjectUpdate => {
+                self.apply_terse(world, &ImprovedTerseObjectUpdate::from_payload(payload)?)
+            }
+            LLUDPMessageType::KillObject => self.apply_kill(world, &KillObject::from_payload(payload)?),
+            _ => ObjectChanges::default(),
+        })
+    }
+
+    pub fn apply_update(&mut self, world: &mut World, message: &ObjectUpdate) -> ObjectChanges {
+        let mut changes = ObjectChanges::default();
+        for block in &message.object_data {
+            match ObjectState::from_update(block) {
+                Ok(state) => self.apply_state(world, state, &mut changes),
+                Err(e) => warn!("Skipping ObjectUpdate for {}: {}", block.id, e),
+            }
+        }
+        changes
+    }
+
+    pub fn apply_compressed(&mut self, world: &mut World, message: &ObjectUpdateCompressed) -> ObjectChanges {
+        let mut changes = ObjectChanges::default();
+        for block in &message.object_data {
+            match ObjectState::from_compressed(block.update_flags, &block.data) {
+                Ok(state) => self.apply_state(world, state, &mut changes),
+                Err(e) => warn!("Skipping malformed ObjectUpdateCompressed record: {}", e),
+            }
+        }
+        changes
+    }
+
+    /// Restore cached objects whose CRC still matches and report the rest as misses
+    pub fn apply_cached(&mut self, world: &mut World, message: &ObjectUpdateCached) -> ObjectChanges {
+        let mut changes = ObjectChanges::default();
+        for block in &message.object_data {
+            match self.cache.get(block.id, block.crc).cloned() {
+                Some(mut state) => {
+                    state.object.update_flags = block.update_flags;
+                    self.apply_state(world, state, &mut changes);
+                }
+                None => changes.cache_misses.push(block.id),
+            }
+        }
+        changes
+    }
+
+    pub fn apply_terse(&mut self, world: &mut World, message: &ImprovedTerseObjectUpdate) -> ObjectChanges {
+        let mut changes = ObjectChanges::default();
+        for block in &message.object_data {
+            let update = match TerseUpdate::decode(block) {
+                Ok(update) => update,
+                Err(e) => {
+                    warn!("Skipping malformed ImprovedTerseObjectUpdate: {}", e);
+                    continue;
+                }
+            };
+            // Terse updates only move objects the client already knows
+            let Some(entity) = self.entity(update.local_id) else {
+                debug!("Terse update for unknown object {}", update.local_id);
+                continue;
+            };
+
+            if let Some(transform) = world.get_component_mut::<Transform>(entity) {
+                transform.position = update.motion.position;
+                transform.rotation = update.motion.rotation;
+            }
+            world.add_component(entity, Velocity {
+                linear: update.motion.velocity,
+                angular: update.motion.angular_velocity,
+            });
+            if let Some(object) = world.get_component_mut::<OpenSimObject>(entity) {
+                object.state = update.state;
+            }
+            if let Some(texture_entry) = update.texture_entry {
+                world.add_component(entity, texture_entry);
+            }
+            changes.updated.push(update.local_id);
+        }
+        changes
+    }
+
+    /// Remove killed objects and whatever is linked to them
+    pub fn apply_kill(&mut self, world: &mut World, message: &KillObject) -> ObjectChanges {
+        let mut changes = ObjectChanges::default();
+        let mut doomed: Vec<u32> = message.object_data.iter().map(|block| block.id).collect();
+        while let Some(local_id) = doomed.pop() {
+            let Some(entity) = self.entities.remove(&local_id) else {
+                continue;
+            };
+            if let Some(object) = world.get_component::<OpenSimObject>(entity) {
+                self.full_ids.remove(&object.full_id);
+            }
+            world.remove_entity(entity);
+            self.orphans.remove(&local_id);
+            changes.removed.push(local_id);
+
+            doomed.extend(world.query::<ParentLink>().filter(|(_, link)| link.parent_id == local_id).filter_map(
+                |(child, _)| world.get_component::<OpenSimObject>(child).map(|object| object.local_id),
+            ));
+        }
+        changes
+    }
+
+    fn apply_state(&mut self, world: &mut World, state: ObjectState, changes: &mut ObjectChanges) {
+        let local_id = state.object.local_id;
+
+        // Local ids are reused within a region once an object is gone
+        let existing = self.entity(local_id).filter(|entity| {
+            world.get_component::<OpenSimObject>(*entity).is_some_and(|object| object.full_id == state.object.full_id)
+        });
+        let entity = match existing {
+            Some(entity) => {
+                changes.updated.push(local_id);
+                entity
+            }
+            None => {
+                if let Some(stale) = self.entities.remove(&local_id) {
+                    world.remove_entity(stale);
+                }
+                changes.added.push(local_id);
+                world.create_entity()
+            }
+        };
+        self.entities.insert(local_id, entity);
+        self.full_ids.insert(state.object.full_id, local_id);
+
+        let parent = (state.parent_id != 0).then(|| self.entity(state.parent_id)).flatten();
+        if state.parent_id != 0 && parent.is_none() {
+            self.orphans.entry(state.parent_id).or_default().push(local_id);
+        }
+        world.add_component(entity, ParentLink {
+            parent_id: state.parent_id,
+            entity: parent,
+        });
+        // Children that were waiting for this object can link up now
+        for child in self.orphans.remove(&local_id).unwrap_or_default() {
+            if let Some(link) = self.entity(child).and_then(|child| world.get_component_mut::<ParentLink>(child)) {
+                if link.parent_id == local_id {
+                    link.entity = Some(entity);
+                }
+            }
+        }
+
+        world.add_component(entity, Transform {
+            position: state.motion.position,
+            rotation: state.motion.rotation,
+            scale: state.scale,
+        });
+        world.add_component(entity, Velocity {
+            linear: state.motion.velocity,
+            angular: state.motion.angular_velocity,
+        });
+        world.add_component(entity, state.object.clone());
+        world.add_component(entity, state.shape.clone());
+        world.add_component(entity, state.texture_entry.clone());
+        world.add_component(entity, state.name.clone());
+        self.cache.insert(state);
+    }
+}
+
+/// Ask the simulator for full updates of objects the cache could not supply
+pub fn request_cache_misses(agent_id: Uuid, session_id: Uuid, misses: &[u32]) -> RequestMultipleObjects {
+    RequestMultipleObjects {
+        agent_data: RequestMultipleObjectsAgentData { agent_id, session_id },
+        object_data: misses
+            .iter()
+            .map(|id| RequestMultipleObjectsObjectData {
+                cache_miss_type: 0, // full update
+                id: *id,
+            })
+            .collect(),
+    }
+}
+
+impl Component for OpenSimObject {
+    fn type_name() -> &'static str {
+        "OpenSimObject"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Component for PrimShape {
+    fn type_name() -> &'static str {
+        "PrimShape"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Component for ObjectName {
+    fn type_name() -> &'static str {
+        "ObjectName"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Component for ParentLink {
+    fn type_name() -> &'static str {
+        "ParentLink"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Variable-length text fields are NUL terminated
+fn text(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn c_string(r: &mut PayloadReader<'_>) -> Result<String> {
+    let mut bytes = Vec::new();
+    loop {
+        match r.u8()? {
+            0 => return Ok(String::from_utf8_lossy(&bytes).into_owned()),
+            byte => bytes.push(byte),
+        }
+    }
+}
+
+/// Dequantize, snapping values within one step of zero to exactly zero
+fn u16_to_float(value: u16, lower: f32, upper: f32) -> f32 {
+    let range = upper - lower;
+    let result = value as f32 / u16::MAX as f32 * range + lower;
+    if result.abs() < range / u16::MAX as f32 { 0.0 } else { result }
+}
+
+fn u8_to_float(value: u8, lower: f32, upper: f32) -> f32 {
+    let range = upper - lower;
+    let result = value as f32 / u8::MAX as f32 * range + lower;
+    if result.abs() < range / u8::MAX as f32 { 0.0 } else { result }
+}
+
+fn read_u16_vector(r: &mut PayloadReader<'_>, lower: f32, upper: f32) -> Result<[f32; 3]> {
+    Ok([u16_to_float(r.u16()?, lower, upper), u16_to_float(r.u16()?, lower, upper), u16_to_float(r.u16()?, lower, upper)])
+}
+
+fn read_u16_rotation(r: &mut PayloadReader<'_>) -> Result<[f32; 4]> {
+    Ok([
+        u16_to_float(r.u16()?, -1.0, 1.0),
+        u16_to_float(r.u16()?, -1.0, 1.0),
+        u16_to_float(r.u16()?, -1.0, 1.0),
+        u16_to_float(r.u16()?, -1.0, 1.0),
+    ])
+}
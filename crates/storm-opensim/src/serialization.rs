@@ -1,58 +1,108 @@
 // File: crates/storm-opensim/src/serialization.rs
 // OpenSim message serialization
 
+use std::net::Ipv4Addr;
 use anyhow::Result;
-use crate::messages::{LLUDPPacket, LLUDPMessageType};
+use uuid::Uuid;
+use crate::messages::{LLUDPPacket, LLUDPMessageType, FLAG_ACK, FLAG_ZEROCODED};
+
+/// Header size: flags, sequence and the extra header length byte
+pub const PACKET_HEADER_SIZE: usize = 6;
+
+/// Most acks one datagram can carry after its body
+pub const MAX_APPENDED_ACKS: usize = u8::MAX as usize;
 
 /// Serialize LLUDP packet to bytes
+///
+/// The message number and body are zero-coded when `FLAG_ZEROCODED` is set and that makes them
+/// smaller; otherwise the flag is cleared. `FLAG_ACK` follows whether `acks` is empty.
 pub fn serialize_packet(packet: &LLUDPPacket) -> Result<Vec<u8>> {
-    let mut data = Vec::new();
+    if packet.acks.len() > MAX_APPENDED_ACKS {
+        return Err(anyhow::anyhow!("{} appended acks exceed the limit of {}", packet.acks.len(), MAX_APPENDED_ACKS));
+    }
 
-    data.push(packet.flags);
-    data.extend_from_slice(&packet.sequence.to_be_bytes());
+    let mut body = Vec::with_capacity(4 + packet.payload.len());
+    packet.message_type.write_id(&mut body)?;
+    body.extend_from_slice(&packet.payload);
 
-    if !packet.extra_header.is_empty() {
-        data.push(packet.extra_header.len() as u8);
-        data.extend_from_slice(&packet.extra_header);
+    let mut flags = packet.flags & !(FLAG_ACK | FLAG_ZEROCODED);
+    if packet.flags & FLAG_ZEROCODED != 0 {
+        let encoded = zero_encode(&body);
+        if encoded.len() < body.len() {
+            body = encoded;
+            flags |= FLAG_ZEROCODED;
+        }
+    }
+    if !packet.acks.is_empty() {
+        flags |= FLAG_ACK;
     }
 
-    data.push(packet.message_type as u8);
-    data.extend_from_slice(&packet.payload);
+    let mut data = Vec::with_capacity(PACKET_HEADER_SIZE + packet.extra_header.len() + body.len() + packet.acks.len() * 4 + 1);
+    data.push(flags);
+    data.extend_from_slice(&packet.sequence.to_be_bytes());
+
+    // The extra header length byte is always present, even when zero
+    data.push(packet.extra_header.len() as u8);
+    data.extend_from_slice(&packet.extra_header);
+    data.extend_from_slice(&body);
+
+    if !packet.acks.is_empty() {
+        for ack in &packet.acks {
+            data.extend_from_slice(&ack.to_be_bytes());
+        }
+        data.push(packet.acks.len() as u8);
+    }
 
     Ok(data)
 }
 
 /// Deserialize bytes to LLUDP packet
 pub fn deserialize_packet(data: &[u8]) -> Result<LLUDPPacket> {
-    if data.len() < 6 {
+    if data.len() < PACKET_HEADER_SIZE + 1 {
         return Err(anyhow::anyhow!("Packet too short"));
     }
 
     let flags = data[0];
     let sequence = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
 
-    let extra_header_len = if flags & 0x40 != 0 { data[5] as usize } else { 0 };
-    let header_end = 6 + extra_header_len;
+    let extra_header_len = data[5] as usize;
+    let header_end = PACKET_HEADER_SIZE + extra_header_len;
 
     if data.len() < header_end + 1 {
         return Err(anyhow::anyhow!("Invalid packet format"));
     }
 
-    let extra_header = if extra_header_len > 0 {
-        data[6..header_end].to_vec()
-    } else {
-        Vec::new()
-    };
+    let extra_header = data[PACKET_HEADER_SIZE..header_end].to_vec();
+
+    // Appended acks sit outside the zero-coded region: ids, then their count in the last byte
+    let mut body_end = data.len();
+    let mut acks = Vec::new();
+    if flags & FLAG_ACK != 0 {
+        let count = data[body_end - 1] as usize;
+        let acks_start = (body_end - 1)
+            .checked_sub(count * 4)
+            .filter(|start| *start > header_end)
+            .ok_or_else(|| anyhow::anyhow!("Packet too short for {} appended acks", count))?;
+        acks = data[acks_start..body_end - 1]
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|ack| u32::from_be_bytes(*ack))
+            .collect();
+        body_end = acks_start;
+    }
 
-    let message_type_byte = data[header_end];
-    let message_type = match message_type_byte {
-        3 => LLUDPMessageType::UseCircuitCode,
-        4 => LLUDPMessageType::AgentUpdate,
-        12 => LLUDPMessageType::ObjectUpdate,
-        _ => LLUDPMessageType::Unknown,
+    let body = &data[header_end..body_end];
+    let decoded;
+    let body = if flags & FLAG_ZEROCODED != 0 {
+        decoded = zero_decode(body)?;
+        &decoded[..]
+    } else {
+        body
     };
 
-    let payload = data[header_end + 1..].to_vec();
+    let (message_type, id_len) = LLUDPMessageType::read_id(body)?;
+    let payload = body[id_len..].to_vec();
 
     Ok(LLUDPPacket {
         flags,
@@ -60,5 +110,326 @@ pub fn deserialize_packet(data: &[u8]) -> Result<LLUDPPacket> {
         extra_header,
         message_type,
         payload,
+        acks,
     })
 }
+
+/// Replace each run of zero bytes with a zero and the run length
+pub fn zero_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != 0 {
+            out.push(data[i]);
+            i += 1;
+            continue;
+        }
+        let run = data[i..].iter().take(u8::MAX as usize).take_while(|&&b| b == 0).count();
+        out.push(0);
+        out.push(run as u8);
+        i += run;
+    }
+    out
+}
+
+/// Expand zero-coded data
+pub fn zero_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 2);
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        if b != 0 {
+            out.push(b);
+            continue;
+        }
+        let run = *bytes.next().ok_or_else(|| anyhow::anyhow!("Zero-coded data ends inside a run"))?;
+        out.resize(out.len() + run as usize, 0);
+    }
+    Ok(out)
+}
+
+/// Little-endian writer for LLUDP message bodies
+#[derive(Debug, Default)]
+pub struct PayloadWriter {
+    buf: Vec<u8>,
+}
+
+impl PayloadWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn i8(&mut self, value: i8) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub fn i16(&mut self, value: i16) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn i32(&mut self, value: i32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn f32(&mut self, value: f32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn f64(&mut self, value: f64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn uuid(&mut self, value: Uuid) -> &mut Self {
+        self.buf.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    pub fn vector3(&mut self, value: [f32; 3]) -> &mut Self {
+        for component in value {
+            self.f32(component);
+        }
+        self
+    }
+
+    pub fn vector3d(&mut self, value: [f64; 3]) -> &mut Self {
+        for component in value {
+            self.f64(component);
+        }
+        self
+    }
+
+    pub fn vector4(&mut self, value: [f32; 4]) -> &mut Self {
+        for component in value {
+            self.f32(component);
+        }
+        self
+    }
+
+    /// Quaternions travel as x, y, z with w implied by normalisation
+    pub fn quaternion(&mut self, value: [f32; 4]) -> &mut Self {
+        let sign = if value[3] < 0.0 { -1.0 } else { 1.0 };
+        self.vector3([value[0] * sign, value[1] * sign, value[2] * sign])
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    /// Fixed-size field: truncated or zero-padded to exactly `len` bytes
+    pub fn fixed(&mut self, value: &[u8], len: usize) -> &mut Self {
+        let used = value.len().min(len);
+        self.bytes(&value[..used]);
+        self.buf.resize(self.buf.len() + len - used, 0);
+        self
+    }
+
+    /// IPADDR fields are the four address octets in network order
+    pub fn ip_addr(&mut self, value: Ipv4Addr) -> &mut Self {
+        self.bytes(&value.octets())
+    }
+
+    /// IPPORT fields are the one big-endian integer in the protocol
+    pub fn ip_port(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Variable 1 field: u8 length prefix
+    pub fn var1(&mut self, value: &[u8]) -> &mut Self {
+        let len = value.len().min(u8::MAX as usize);
+        self.u8(len as u8).bytes(&value[..len])
+    }
+
+    /// Variable 2 field: u16 length prefix
+    pub fn var2(&mut self, value: &[u8]) -> &mut Self {
+        let len = value.len().min(u16::MAX as usize);
+        self.u16(len as u16).bytes(&value[..len])
+    }
+
+    /// Strings are sent null-terminated inside variable fields
+    pub fn string1(&mut self, value: &str) -> &mut Self {
+        self.var1(&null_terminated(value, u8::MAX as usize))
+    }
+
+    pub fn string2(&mut self, value: &str) -> &mut Self {
+        self.var2(&null_terminated(value, u16::MAX as usize))
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub(crate) fn null_terminated(value: &str, max_len: usize) -> Vec<u8> {
+    if value.is_empty() {
+        return Vec::new();
+    }
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.truncate(max_len - 1);
+    bytes.push(0);
+    bytes
+}
+
+/// Little-endian reader for LLUDP message bodies
+#[derive(Debug)]
+pub struct PayloadReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PayloadReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(anyhow::anyhow!(
+                "Message body truncated: wanted {} bytes at offset {}, {} left",
+                len, self.pos, self.remaining()
+            ));
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    pub fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.array()?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    pub fn uuid(&mut self) -> Result<Uuid> {
+        Ok(Uuid::from_bytes(self.array()?))
+    }
+
+    pub fn vector3(&mut self) -> Result<[f32; 3]> {
+        Ok([self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    pub fn vector3d(&mut self) -> Result<[f64; 3]> {
+        Ok([self.f64()?, self.f64()?, self.f64()?])
+    }
+
+    pub fn vector4(&mut self) -> Result<[f32; 4]> {
+        Ok([self.f32()?, self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    pub fn quaternion(&mut self) -> Result<[f32; 4]> {
+        let [x, y, z] = self.vector3()?;
+        let w = (1.0 - (x * x + y * y + z * z)).max(0.0).sqrt();
+        Ok([x, y, z, w])
+    }
+
+    pub fn ip_addr(&mut self) -> Result<Ipv4Addr> {
+        Ok(Ipv4Addr::from(self.array::<4>()?))
+    }
+
+    pub fn ip_port(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn var1(&mut self) -> Result<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.bytes(len)
+    }
+
+    pub fn var2(&mut self) -> Result<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    pub fn string1(&mut self) -> Result<String> {
+        Ok(trim_null(self.var1()?))
+    }
+
+    pub fn string2(&mut self) -> Result<String> {
+        Ok(trim_null(self.var2()?))
+    }
+}
+
+pub(crate) fn trim_null(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
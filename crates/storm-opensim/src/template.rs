@@ -0,0 +1,14 @@
+// File: crates/storm-opensim/src/template.rs
+// Typed bodies for every message in message_template.msg, generated by build.rs
+//
+// Each struct mirrors its message's blocks one to one. The hand-written bodies in `messages`
+// flatten the common messages into friendlier shapes and share the same wire format.
+
+use std::net::Ipv4Addr;
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::messages::{LLUDPMessageType, MessageBody};
+use crate::serialization::{PayloadReader, PayloadWriter};
+
+include!(concat!(env!("OUT_DIR"), "/message_bodies.rs"));
@@ -0,0 +1,623 @@
+// File: crates/storm-opensim/src/terrain.rs
+// Terrain: decoding LayerData patches into region heightfields, wind and clouds
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use storm_ecs::{Component, Entity, Heightfield, World};
+use tracing::{debug, warn};
+
+use crate::messages::{LLUDPMessageType, LLUDPPacket, MessageBody};
+use crate::template::{LayerData, LayerDataLayerData, LayerDataLayerID};
+
+/// Layer codes carried in LayerData; the extended variants come from variable-size regions
+pub mod layer_type {
+    pub const LAND: u8 = b'L';
+    pub const LAND_EXTENDED: u8 = b'M';
+    pub const WATER: u8 = b'W';
+    pub const WATER_EXTENDED: u8 = b'X';
+    pub const WIND: u8 = b'7';
+    pub const WIND_EXTENDED: u8 = b'9';
+    pub const CLOUD: u8 = b'8';
+    pub const CLOUD_EXTENDED: u8 = b':';
+}
+
+/// Patch header quantization value marking the end of a layer
+pub const END_OF_PATCHES: u8 = 97;
+/// Edge length of a terrain patch in samples
+pub const PATCH_SIZE: u32 = 16;
+/// Stride the simulator advertises for 256m regions
+const STRIDE: u16 = 264;
+/// Quantization bits the encoder spreads a patch's height range over
+const PREQUANT: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayerKind {
+    Land,
+    Water,
+    Wind,
+    Cloud,
+}
+
+/// Header in front of every LayerData payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerGroup {
+    pub stride: u16,
+    pub patch_size: u8,
+    pub layer_type: u8,
+}
+
+impl LayerGroup {
+    pub fn kind(&self) -> Option<LayerKind> {
+        use layer_type::*;
+        match self.layer_type {
+            LAND | LAND_EXTENDED => Some(LayerKind::Land),
+            WATER | WATER_EXTENDED => Some(LayerKind::Water),
+            WIND | WIND_EXTENDED => Some(LayerKind::Wind),
+            CLOUD | CLOUD_EXTENDED => Some(LayerKind::Cloud),
+            _ => None,
+        }
+    }
+
+    /// Extended layers use 16 bit patch coordinates instead of 5 bit ones
+    pub fn is_extended(&self) -> bool {
+        use layer_type::*;
+        matches!(self.layer_type, LAND_EXTENDED | WATER_EXTENDED | WIND_EXTENDED | CLOUD_EXTENDED)
+    }
+}
+
+/// Quantization and placement of one compressed patch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PatchHeader {
+    /// High nibble: quantization bits - 2, low nibble: coefficient word bits - 2
+    pub quant_wbits: u8,
+    pub dc_offset: f32,
+    pub range: u16,
+    pub patch_x: u32,
+    pub patch_y: u32,
+}
+
+impl PatchHeader {
+    pub fn word_bits(&self) -> u32 {
+        (self.quant_wbits & 0x0f) as u32 + 2
+    }
+
+    pub fn prequant(&self) -> u32 {
+        (self.quant_wbits >> 4) as u32 + 2
+    }
+}
+
+/// A decompressed patch; `samples` are row-major, `patch_size` squared
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainPatch {
+    pub header: PatchHeader,
+    pub samples: Vec<f32>,
+}
+
+/// Decode every patch in a LayerData payload
+pub fn decode_layer(data: &[u8]) -> Result<(LayerGroup, Vec<TerrainPatch>)> {
+    let mut reader = BitReader { data, pos: 0 };
+    let group = LayerGroup {
+        stride: reader.bits(16)? as u16,
+        patch_size: reader.bits(8)? as u8,
+        layer_type: reader.bits(8)? as u8,
+    };
+    if group.patch_size != 16 && group.patch_size != 32 {
+        return Err(anyhow::anyhow!("Unsupported terrain patch size {}", group.patch_size));
+    }
+    let dct = Dct::new(group.patch_size as usize);
+
+    let mut patches = Vec::new();
+    // Some simulators end the payload without the marker
+    while reader.remaining() >= 8 {
+        let quant_wbits = reader.bits(8)? as u8;
+        if quant_wbits == END_OF_PATCHES {
+            break;
+        }
+        let dc_offset = reader.f32()?;
+        let range = reader.bits(16)? as u16;
+        let (patch_x, patch_y) = if group.is_extended() {
+            let ids = reader.bits(32)?;
+            (ids >> 16, ids & 0xffff)
+        } else {
+            let ids = reader.bits(10)?;
+            (ids >> 5, ids & 0x1f)
+        };
+        let header = PatchHeader {
+            quant_wbits,
+            dc_offset,
+            range,
+            patch_x,
+            patch_y,
+        };
+        let coefficients = decode_coefficients(&mut reader, &header, dct.size * dct.size)?;
+        patches.push(TerrainPatch {
+            samples: dct.decompress(&coefficients, &header),
+            header,
+        });
+    }
+    Ok((group, patches))
+}
+
+/// Compress square patches of `patch_size` samples, placed at patch coordinates
+pub fn encode_layer(layer_type: u8, patch_size: u8, patches: &[([u32; 2], &[f32])]) -> Vec<u8> {
+    let group = LayerGroup {
+        stride: STRIDE,
+        patch_size,
+        layer_type,
+    };
+    let dct = Dct::new(patch_size as usize);
+    let mut writer = BitWriter::default();
+    writer.bits(group.stride as u32, 16);
+    writer.bits(group.patch_size as u32, 8);
+    writer.bits(group.layer_type as u32, 8);
+
+    for ([patch_x, patch_y], samples) in patches {
+        let (header, coefficients) = dct.compress(samples);
+        writer.bits(header.quant_wbits as u32, 8);
+        writer.bits(header.dc_offset.to_bits(), 32);
+        writer.bits(header.range as u32, 16);
+        if group.is_extended() {
+            writer.bits((patch_x << 16) | (patch_y & 0xffff), 32);
+        } else {
+            writer.bits(((patch_x & 0x1f) << 5) | (patch_y & 0x1f), 10);
+        }
+        encode_coefficients(&mut writer, &header, &coefficients);
+    }
+    writer.bits(END_OF_PATCHES as u32, 8);
+    writer.finish()
+}
+
+/// Build a LayerData message from already compressed patches
+pub fn layer_message(layer_type: u8, data: Vec<u8>) -> LayerData {
+    LayerData {
+        layer_id: LayerDataLayerID { r#type: layer_type },
+        layer_data: LayerDataLayerData { data },
+    }
+}
+
+/// Zigzag-ordered coefficients: `0` is a zero, `10` ends the block, `11` is followed by sign and magnitude
+fn decode_coefficients(reader: &mut BitReader<'_>, header: &PatchHeader, count: usize) -> Result<Vec<i32>> {
+    let mut coefficients = vec![0; count];
+    for coefficient in coefficients.iter_mut() {
+        if !reader.bit()? {
+            continue;
+        }
+        if !reader.bit()? {
+            break;
+        }
+        let negative = reader.bit()?;
+        let magnitude = reader.bits(header.word_bits())? as i32;
+        *coefficient = if negative { -magnitude } else { magnitude };
+    }
+    Ok(coefficients)
+}
+
+fn encode_coefficients(writer: &mut BitWriter, header: &PatchHeader, coefficients: &[i32]) {
+    let used = coefficients.iter().rposition(|c| *c != 0).map_or(0, |last| last + 1);
+    for coefficient in &coefficients[..used] {
+        if *coefficient == 0 {
+            writer.bit(false);
+        } else {
+            writer.bit(true);
+            writer.bit(true);
+            writer.bit(*coefficient < 0);
+            writer.bits(coefficient.unsigned_abs(), header.word_bits());
+        }
+    }
+    if used < coefficients.len() {
+        writer.bit(true);
+        writer.bit(false);
+    }
+}
+
+/// Tables for the patch transform at one patch size
+struct Dct {
+    size: usize,
+    /// `cosine[u * size + n]`
+    cosine: Vec<f32>,
+    /// Position of each block coefficient in zigzag transmission order
+    zigzag: Vec<usize>,
+}
+
+impl Dct {
+    fn new(size: usize) -> Self {
+        let half_period = std::f32::consts::PI * 0.5 / size as f32;
+        let mut cosine = vec![0.0; size * size];
+        for u in 0..size {
+            for n in 0..size {
+                cosine[u * size + n] = ((2.0 * n as f32 + 1.0) * u as f32 * half_period).cos();
+            }
+        }
+
+        let mut zigzag = vec![0; size * size];
+        let (mut i, mut j) = (0, 0);
+        let (mut diagonal, mut right) = (false, true);
+        for count in 0..size * size {
+            zigzag[j * size + i] = count;
+            if !diagonal {
+                if right {
+                    if i < size - 1 {
+                        i += 1;
+                    } else {
+                        j += 1;
+                    }
+                } else if j < size - 1 {
+                    j += 1;
+                } else {
+                    i += 1;
+                }
+                right = !right;
+                diagonal = true;
+            } else if right {
+                i += 1;
+                j -= 1;
+                diagonal = !(i == size - 1 || j == 0);
+            } else {
+                i -= 1;
+                j += 1;
+                diagonal = !(j == size - 1 || i == 0);
+            }
+        }
+        Self { size, cosine, zigzag }
+    }
+
+    fn dequantize(&self, index: usize) -> f32 {
+        let (row, column) = (index / self.size, index % self.size);
+        1.0 + 2.0 * (row + column) as f32
+    }
+
+    /// Inverse transform along columns, then rows
+    fn idct(&self, block: &[f32]) -> Vec<f32> {
+        let size = self.size;
+        let mut columns = vec![0.0; size * size];
+        for column in 0..size {
+            for n in 0..size {
+                let mut total = std::f32::consts::FRAC_1_SQRT_2 * block[column];
+                for u in 1..size {
+                    total += block[u * size + column] * self.cosine[u * size + n];
+                }
+                columns[n * size + column] = total;
+            }
+        }
+        let mut output = vec![0.0; size * size];
+        for row in 0..size {
+            for n in 0..size {
+                let mut total = std::f32::consts::FRAC_1_SQRT_2 * columns[row * size];
+                for u in 1..size {
+                    total += columns[row * size + u] * self.cosine[u * size + n];
+                }
+                output[row * size + n] = total * 2.0 / size as f32;
+            }
+        }
+        output
+    }
+
+    /// Exact inverse of `idct`
+    fn dct(&self, samples: &[f32]) -> Vec<f32> {
+        let size = self.size;
+        let weight = |u: usize| if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+        let mut rows = vec![0.0; size * size];
+        for row in 0..size {
+            for u in 0..size {
+                let total: f32 = (0..size).map(|n| samples[row * size + n] * self.cosine[u * size + n]).sum();
+                rows[row * size + u] = total * weight(u);
+            }
+        }
+        let mut output = vec![0.0; size * size];
+        for column in 0..size {
+            for u in 0..size {
+                let total: f32 = (0..size).map(|n| rows[n * size + column] * self.cosine[u * size + n]).sum();
+                output[u * size + column] = total * weight(u) * 2.0 / size as f32;
+            }
+        }
+        output
+    }
+
+    fn decompress(&self, coefficients: &[i32], header: &PatchHeader) -> Vec<f32> {
+        let block: Vec<f32> = (0..self.size * self.size)
+            .map(|index| coefficients[self.zigzag[index]] as f32 * self.dequantize(index))
+            .collect();
+        let quantize = (1u32 << header.prequant()) as f32;
+        let scale = header.range as f32 / quantize;
+        let offset = scale * (quantize / 2.0) + header.dc_offset;
+        self.idct(&block).into_iter().map(|value| value * scale + offset).collect()
+    }
+
+    fn compress(&self, samples: &[f32]) -> (PatchHeader, Vec<i32>) {
+        let min = samples.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = samples.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let range = (max - min + 1.0).clamp(1.0, u16::MAX as f32) as u16;
+        let quantize = (1u32 << PREQUANT) as f32;
+        let normalized: Vec<f32> = samples
+            .iter()
+            .map(|height| (height - min) * quantize / range as f32 - quantize / 2.0)
+            .collect();
+
+        let transformed = self.dct(&normalized);
+        let mut coefficients = vec![0; self.size * self.size];
+        for (index, value) in transformed.iter().enumerate() {
+            coefficients[self.zigzag[index]] = (value / self.dequantize(index)).round() as i32;
+        }
+        let largest = coefficients.iter().map(|c| c.unsigned_abs()).max().unwrap_or(0);
+        let word_bits = (u32::BITS - largest.leading_zeros()).clamp(2, 17);
+        let header = PatchHeader {
+            quant_wbits: (((PREQUANT - 2) << 4) | (word_bits - 2)) as u8,
+            dc_offset: min,
+            range,
+            patch_x: 0,
+            patch_y: 0,
+        };
+        (header, coefficients)
+    }
+}
+
+/// Reads fields the way the simulator packs them: whole bytes least significant first,
+/// the bits of each byte most significant first
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    fn bit(&mut self) -> Result<bool> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| anyhow::anyhow!("LayerData ended inside a terrain patch"))?;
+        let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        let mut shift = 0;
+        let mut remaining = count;
+        while remaining > 0 {
+            let chunk = remaining.min(8);
+            let mut byte = 0u32;
+            for _ in 0..chunk {
+                byte = (byte << 1) | self.bit()? as u32;
+            }
+            value |= byte << shift;
+            shift += 8;
+            remaining -= chunk;
+        }
+        Ok(value)
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.bits(32)?))
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitWriter {
+    fn bit(&mut self, bit: bool) {
+        if self.pos.is_multiple_of(8) {
+            self.data.push(0);
+        }
+        if bit {
+            let last = self.data.len() - 1;
+            self.data[last] |= 0x80 >> (self.pos % 8);
+        }
+        self.pos += 1;
+    }
+
+    fn bits(&mut self, value: u32, count: u32) {
+        let mut remaining = count;
+        let mut shift = 0;
+        while remaining > 0 {
+            let chunk = remaining.min(8);
+            let byte = (value >> shift) & 0xff;
+            for bit in (0..chunk).rev() {
+                self.bit(byte & (1 << bit) != 0);
+            }
+            shift += 8;
+            remaining -= chunk;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Wind velocity over a region, one sample per cell
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindField {
+    /// Cells per side; each covers region size / cells meters
+    pub cells: u32,
+    /// Row-major horizontal wind in m/s
+    pub velocity: Vec<[f32; 2]>,
+}
+
+impl WindField {
+    pub fn new(cells: u32) -> Self {
+        Self {
+            cells,
+            velocity: vec![[0.0; 2]; (cells * cells) as usize],
+        }
+    }
+
+    /// Wind at a cell, clamped to the field
+    pub fn at(&self, x: u32, y: u32) -> [f32; 2] {
+        let (x, y) = (x.min(self.cells - 1), y.min(self.cells - 1));
+        self.velocity[(y * self.cells + x) as usize]
+    }
+}
+
+/// Cloud density over a region, one sample per 16m cell
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloudField {
+    pub width: u32,
+    pub depth: u32,
+    pub density: Vec<f32>,
+}
+
+impl CloudField {
+    pub fn new(width: u32, depth: u32) -> Self {
+        Self {
+            width,
+            depth,
+            density: vec![0.0; (width * depth) as usize],
+        }
+    }
+}
+
+/// Patches and layers a LayerData message changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TerrainChanges {
+    /// Land patch coordinates
+    pub land: Vec<[u32; 2]>,
+    pub wind: bool,
+    pub clouds: bool,
+}
+
+impl TerrainChanges {
+    pub fn is_empty(&self) -> bool {
+        self.land.is_empty() && !self.wind && !self.clouds
+    }
+}
+
+/// Terrain of one region: a single entity carrying its heightfield, wind and clouds
+pub struct RegionTerrain {
+    region_handle: u64,
+    size_x: u32,
+    size_y: u32,
+    entity: Option<Entity>,
+}
+
+impl RegionTerrain {
+    pub fn new(region_handle: u64, size_x: u32, size_y: u32) -> Self {
+        Self {
+            region_handle,
+            size_x,
+            size_y,
+            entity: None,
+        }
+    }
+
+    /// Entity holding the region's terrain components, once the first layer arrived
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    /// Apply a LayerData packet; other message types change nothing
+    pub fn handle_packet(&mut self, world: &mut World, packet: &LLUDPPacket) -> Result<TerrainChanges> {
+        match packet.message_type {
+            LLUDPMessageType::LayerData => self.apply_layer(world, &LayerData::from_payload(&packet.payload)?),
+            _ => Ok(TerrainChanges::default()),
+        }
+    }
+
+    pub fn apply_layer(&mut self, world: &mut World, message: &LayerData) -> Result<TerrainChanges> {
+        let (group, patches) = decode_layer(&message.layer_data.data)?;
+        let entity = self.ensure_entity(world);
+        let patch_size = group.patch_size as u32;
+        let mut changes = TerrainChanges::default();
+
+        match group.kind() {
+            Some(LayerKind::Land) => {
+                let Some(heightfield) = world.get_component_mut::<Heightfield>(entity) else {
+                    return Ok(changes);
+                };
+                for patch in patches {
+                    let PatchHeader { patch_x, patch_y, .. } = patch.header;
+                    if (patch_x + 1) * patch_size > self.size_x || (patch_y + 1) * patch_size > self.size_y {
+                        warn!("Terrain patch {},{} lies outside region {}", patch_x, patch_y, self.region_handle);
+                        continue;
+                    }
+                    heightfield.set_block(patch_x * patch_size, patch_y * patch_size, patch_size, &patch.samples);
+                    changes.land.push([patch_x, patch_y]);
+                }
+            }
+            // The first wind patch carries the x components, the second the y components
+            Some(LayerKind::Wind) => {
+                let [x, y, ..] = patches.as_slice() else {
+                    warn!("Wind layer for region {} has {} patches", self.region_handle, patches.len());
+                    return Ok(changes);
+                };
+                let wind = WindField {
+                    cells: patch_size,
+                    velocity: x.samples.iter().zip(&y.samples).map(|(x, y)| [*x, *y]).collect(),
+                };
+                world.add_component(entity, wind);
+                changes.wind = true;
+            }
+            Some(LayerKind::Cloud) => {
+                if let Some(clouds) = world.get_component_mut::<CloudField>(entity) {
+                    for patch in &patches {
+                        let (left, top) = (patch.header.patch_x * patch_size, patch.header.patch_y * patch_size);
+                        for row in 0..patch_size.min(clouds.depth.saturating_sub(top)) {
+                            for column in 0..patch_size.min(clouds.width.saturating_sub(left)) {
+                                clouds.density[((top + row) * clouds.width + left + column) as usize] =
+                                    patch.samples[(row * patch_size + column) as usize];
+                            }
+                        }
+                    }
+                    changes.clouds = !patches.is_empty();
+                }
+            }
+            Some(LayerKind::Water) => debug!("Ignoring water layer for region {}", self.region_handle),
+            None => warn!("Unknown terrain layer type {:#04x}", group.layer_type),
+        }
+        Ok(changes)
+    }
+
+    /// Drop the terrain entity, e.g. when the region is no longer in view
+    pub fn clear(&mut self, world: &mut World) {
+        if let Some(entity) = self.entity.take() {
+            world.remove_entity(entity);
+        }
+    }
+
+    fn ensure_entity(&mut self, world: &mut World) -> Entity {
+        if let Some(entity) = self.entity {
+            return entity;
+        }
+        let entity = world.create_entity();
+        world.add_component(entity, Heightfield::new(self.region_handle, self.size_x, self.size_y));
+        world.add_component(entity, WindField::new(PATCH_SIZE));
+        world.add_component(entity, CloudField::new(self.size_x / PATCH_SIZE, self.size_y / PATCH_SIZE));
+        self.entity = Some(entity);
+        entity
+    }
+}
+
+impl Component for WindField {
+    fn type_name() -> &'static str {
+        "WindField"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Component for CloudField {
+    fn type_name() -> &'static str {
+        "CloudField"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
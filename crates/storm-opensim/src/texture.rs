@@ -0,0 +1,451 @@
+// File: crates/storm-opensim/src/texture.rs
+// Textures: GetTexture/ViewerAsset range requests with a RequestImage fallback, decoded into the asset cache
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use storm_assets::j2k::{self, DecodeOptions, J2kInfo, FIRST_PACKET_SIZE};
+use storm_assets::{AssetData, AssetManager, AssetType};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::caps::{Capabilities, CapsError};
+use crate::messages::{LLUDPMessageType, LLUDPPacket, MessageBody};
+use crate::template::{ImageData, ImageNotInDatabase, ImagePacket, RequestImage, RequestImageAgentData, RequestImageRequestImage};
+
+/// Texture capabilities, newest first
+pub const TEXTURE_CAPABILITIES: &[&str] = &["ViewerAsset", "GetTexture"];
+/// Codestream bytes carried by each ImagePacket after the first ImageData
+pub const IMAGE_PACKET_SIZE: usize = 1000;
+/// Lowest resolution viewers ask for; each level halves width and height
+pub const MAX_DISCARD_LEVEL: u8 = 5;
+/// RequestImage type for ordinary textures
+const IMAGE_TYPE_NORMAL: u8 = 0;
+
+/// Texture failures callers may want to handle, reachable through `anyhow::Error::downcast_ref`
+#[derive(Debug, thiserror::Error)]
+pub enum TextureError {
+    #[error("Texture {0} is not in the asset database")]
+    NotFound(Uuid),
+    #[error("Texture {0} ended before its header")]
+    Truncated(Uuid),
+}
+
+/// Path a texture's bytes arrived through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureSource {
+    Http,
+    Udp,
+}
+
+/// Byte offset of an image packet in the codestream
+fn packet_offset(packet: u16) -> usize {
+    match packet {
+        0 => 0,
+        n => FIRST_PACKET_SIZE + (n as usize - 1) * IMAGE_PACKET_SIZE,
+    }
+}
+
+/// The discard level that still covers `screen_size` pixels along the texture's longer side
+pub fn desired_discard(info: &J2kInfo, screen_size: u32) -> u8 {
+    let largest = info.width.max(info.height);
+    let mut discard = 0;
+    while discard < info.levels.min(MAX_DISCARD_LEVEL) && largest >> (discard + 1) >= screen_size.max(1) {
+        discard += 1;
+    }
+    discard
+}
+
+/// Leading bytes of one texture's codestream, grown by range requests or image packets
+#[derive(Debug, Clone, Default)]
+pub struct TextureDownload {
+    pub id: Uuid,
+    /// Full codestream size once the server has said
+    pub total_size: Option<usize>,
+    pub data: Vec<u8>,
+    /// Bytes that arrived ahead of a gap, by offset
+    pending: BTreeMap<usize, Vec<u8>>,
+}
+
+impl TextureDownload {
+    pub fn new(id: Uuid) -> Self {
+        Self { id, ..Default::default() }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total_size.is_some_and(|size| self.data.len() >= size)
+    }
+
+    /// Image layout, once the main header has arrived
+    pub fn info(&self) -> Option<J2kInfo> {
+        j2k::read_info(&self.data).ok()
+    }
+
+    /// Place bytes at `offset`, extending the contiguous data as far as possible
+    pub fn write_at(&mut self, offset: usize, bytes: &[u8]) {
+        self.pending.insert(offset, bytes.to_vec());
+        while let Some(entry) = self.pending.first_entry() {
+            let start = *entry.key();
+            if start > self.data.len() {
+                break;
+            }
+            let bytes = entry.remove();
+            let skip = self.data.len() - start;
+            if skip < bytes.len() {
+                self.data.extend_from_slice(&bytes[skip..]);
+            }
+        }
+        if let Some(size) = self.total_size {
+            self.data.truncate(size);
+        }
+    }
+
+    /// Image packet to resume a UDP transfer from
+    pub fn next_packet(&self) -> u16 {
+        match self.data.len() {
+            len if len < FIRST_PACKET_SIZE => 0,
+            len => (1 + (len - FIRST_PACKET_SIZE) / IMAGE_PACKET_SIZE) as u16,
+        }
+    }
+
+    /// Codestream bytes needed before `discard_level` can be decoded
+    pub fn bytes_needed(&self, discard_level: u8) -> usize {
+        let needed = self.info().map_or(FIRST_PACKET_SIZE, |info| info.estimated_bytes(discard_level));
+        self.total_size.map_or(needed, |size| needed.min(size))
+    }
+}
+
+/// HTTP texture downloads with byte ranges
+#[derive(Debug, Clone)]
+pub struct TextureFetcher {
+    http: reqwest::Client,
+}
+
+impl TextureFetcher {
+    pub fn new(timeout: Duration) -> Result<Self> {
+        Ok(Self { http: reqwest::Client::builder().timeout(timeout).build()? })
+    }
+
+    /// First texture capability a region granted
+    pub fn capability(capabilities: &Capabilities) -> Option<&str> {
+        TEXTURE_CAPABILITIES.iter().find_map(|name| capabilities.get(name))
+    }
+
+    /// Download bytes up to `end` (exclusive) after what the download already holds
+    pub async fn fetch_range(&self, capability: &str, download: &mut TextureDownload, end: usize) -> Result<()> {
+        let start = download.data.len();
+        if end <= start || download.is_complete() {
+            return Ok(());
+        }
+        let url = format!("{}/?texture_id={}", capability.trim_end_matches('/'), download.id);
+        let response = self
+            .http
+            .get(&url)
+            .header(reqwest::header::ACCEPT, "image/x-j2c")
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end - 1))
+            .send()
+            .await?;
+        let status = response.status();
+        match status.as_u16() {
+            200 => {
+                // The server ignored the range and sent everything
+                let body = response.bytes().await?;
+                download.total_size = Some(body.len());
+                download.data.clear();
+                download.write_at(0, &body);
+            }
+            206 => {
+                let total = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.rsplit_once('/'))
+                    .and_then(|(_, total)| total.parse().ok());
+                let body = response.bytes().await?;
+                download.total_size = total.or(download.total_size);
+                if body.len() < end - start {
+                    download.total_size = Some(start + body.len());
+                }
+                download.write_at(start, &body);
+            }
+            // Nothing past what we have
+            416 => download.total_size = Some(start),
+            404 => return Err(TextureError::NotFound(download.id).into()),
+            _ => return Err(CapsError::Http { url, status: status.as_u16() }.into()),
+        }
+        debug!("Texture {} has {} of {:?} bytes", download.id, download.data.len(), download.total_size);
+        Ok(())
+    }
+}
+
+/// Ask the simulator to stream textures over UDP; entries are (texture, discard level, priority, first packet)
+pub fn request_image(agent_id: Uuid, session_id: Uuid, requests: &[(Uuid, i8, f32, u32)]) -> RequestImage {
+    RequestImage {
+        agent_data: RequestImageAgentData { agent_id, session_id },
+        request_image: requests
+            .iter()
+            .map(|&(image, discard_level, download_priority, packet)| RequestImageRequestImage {
+                image,
+                discard_level,
+                download_priority,
+                packet,
+                r#type: IMAGE_TYPE_NORMAL,
+            })
+            .collect(),
+    }
+}
+
+/// What an image message did to a UDP transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProgress {
+    Received(Uuid),
+    NotFound(Uuid),
+}
+
+/// Reassembles ImageData and ImagePacket messages into codestreams
+#[derive(Debug, Default)]
+pub struct UdpTextures {
+    downloads: HashMap<Uuid, TextureDownload>,
+}
+
+impl UdpTextures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_packet(&mut self, packet: &LLUDPPacket) -> Result<Option<ImageProgress>> {
+        Ok(Some(match packet.message_type {
+            LLUDPMessageType::ImageData => {
+                let message = ImageData::from_payload(&packet.payload)?;
+                let download = self.download(message.image_id.id);
+                download.total_size = Some(message.image_id.size as usize);
+                download.write_at(0, &message.image_data.data);
+                ImageProgress::Received(message.image_id.id)
+            }
+            LLUDPMessageType::ImagePacket => {
+                let message = ImagePacket::from_payload(&packet.payload)?;
+                self.download(message.image_id.id)
+                    .write_at(packet_offset(message.image_id.packet), &message.image_data.data);
+                ImageProgress::Received(message.image_id.id)
+            }
+            LLUDPMessageType::ImageNotInDatabase => {
+                let message = ImageNotInDatabase::from_payload(&packet.payload)?;
+                self.downloads.remove(&message.image_id.id);
+                ImageProgress::NotFound(message.image_id.id)
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    pub fn download(&mut self, id: Uuid) -> &mut TextureDownload {
+        self.downloads.entry(id).or_insert_with(|| TextureDownload::new(id))
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&TextureDownload> {
+        self.downloads.get(&id)
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<TextureDownload> {
+        self.downloads.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.downloads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty()
+    }
+}
+
+/// A texture wanted on screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRequest {
+    pub id: Uuid,
+    /// Relative on-screen importance, e.g. covered pixel area; also the cache priority
+    pub importance: f32,
+    /// Pixels the texture spans along its longer side on screen
+    pub screen_size: u32,
+}
+
+/// Result of a fetch or an image message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureEvent {
+    /// Decoded into the asset cache at this discard level
+    Decoded { id: Uuid, discard_level: u8, width: u32, height: u32, source: TextureSource },
+    /// The grid does not have the texture
+    Missing(Uuid),
+}
+
+/// Fetches requested textures by importance, refines them as they grow on screen and caches the results
+pub struct TexturePipeline {
+    fetcher: TextureFetcher,
+    assets: Arc<AssetManager>,
+    requests: HashMap<Uuid, TextureRequest>,
+    downloads: UdpTextures,
+    /// Discard level each texture is cached at
+    decoded: HashMap<Uuid, u8>,
+    /// Textures whose HTTP fetch failed and that now go over UDP
+    udp_fallback: HashSet<Uuid>,
+}
+
+impl TexturePipeline {
+    pub fn new(assets: Arc<AssetManager>, timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            fetcher: TextureFetcher::new(timeout)?,
+            assets,
+            requests: HashMap::new(),
+            downloads: UdpTextures::new(),
+            decoded: HashMap::new(),
+            udp_fallback: HashSet::new(),
+        })
+    }
+
+    pub fn assets(&self) -> &Arc<AssetManager> {
+        &self.assets
+    }
+
+    /// Ask for a texture, or update its importance and size as the view changes
+    pub async fn request(&mut self, request: TextureRequest) {
+        if self.decoded.contains_key(&request.id) {
+            self.assets.set_priority(request.id, request.importance).await;
+        }
+        self.requests.insert(request.id, request);
+    }
+
+    /// Stop refining a texture that left the view; it stays cached until evicted
+    pub fn cancel(&mut self, id: Uuid) {
+        self.requests.remove(&id);
+        self.downloads.remove(id);
+    }
+
+    pub fn decoded_discard(&self, id: Uuid) -> Option<u8> {
+        self.decoded.get(&id).copied()
+    }
+
+    /// Textures whose partial codestreams are held for refinement
+    pub fn in_flight(&self) -> usize {
+        self.downloads.len()
+    }
+
+    fn target_discard(&self, request: &TextureRequest) -> Option<u8> {
+        let info = self.downloads.get(request.id)?.info()?;
+        Some(desired_discard(&info, request.screen_size))
+    }
+
+    /// Requests still wanting more detail, most important first
+    pub fn pending(&self) -> Vec<TextureRequest> {
+        let mut pending: Vec<TextureRequest> = self
+            .requests
+            .values()
+            .filter(|request| match (self.decoded.get(&request.id), self.target_discard(request)) {
+                (Some(decoded), Some(target)) => *decoded > target,
+                (decoded, _) => decoded.is_none(),
+            })
+            .copied()
+            .collect();
+        pending.sort_by(|a, b| b.importance.total_cmp(&a.importance));
+        pending
+    }
+
+    /// Fetch the most important pending texture over HTTP; textures that fail move to the UDP path
+    pub async fn fetch_next(&mut self, capabilities: &Capabilities) -> Result<Option<TextureEvent>> {
+        let Some(capability) = TextureFetcher::capability(capabilities) else {
+            return Ok(None);
+        };
+        let Some(request) = self.pending().into_iter().find(|request| !self.udp_fallback.contains(&request.id)) else {
+            return Ok(None);
+        };
+        match self.fetch(capability, &request).await {
+            Ok(event) => Ok(Some(event)),
+            Err(error) if error.downcast_ref::<TextureError>().is_some_and(|e| matches!(e, TextureError::NotFound(_))) => {
+                self.cancel(request.id);
+                Ok(Some(TextureEvent::Missing(request.id)))
+            }
+            Err(error) => {
+                warn!("HTTP fetch of texture {} failed, falling back to UDP: {}", request.id, error);
+                self.udp_fallback.insert(request.id);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn fetch(&mut self, capability: &str, request: &TextureRequest) -> Result<TextureEvent> {
+        let download = self.downloads.download(request.id);
+        if download.info().is_none() {
+            self.fetcher.fetch_range(capability, download, FIRST_PACKET_SIZE).await?;
+        }
+        let info = download.info().ok_or(TextureError::Truncated(request.id))?;
+        let discard = desired_discard(&info, request.screen_size);
+        let needed = download.bytes_needed(discard);
+        self.fetcher.fetch_range(capability, download, needed).await?;
+        self.decode(request.id, discard, TextureSource::Http).await
+    }
+
+    /// RequestImage for pending textures without an HTTP path, at most `limit` of them
+    pub fn udp_requests(&self, agent_id: Uuid, session_id: Uuid, http_available: bool, limit: usize) -> Option<RequestImage> {
+        let requests: Vec<(Uuid, i8, f32, u32)> = self
+            .pending()
+            .into_iter()
+            .filter(|request| !http_available || self.udp_fallback.contains(&request.id))
+            .take(limit)
+            .map(|request| {
+                let discard = self.target_discard(&request).unwrap_or(MAX_DISCARD_LEVEL);
+                let packet = self.downloads.get(request.id).map_or(0, TextureDownload::next_packet);
+                (request.id, discard as i8, request.importance, packet as u32)
+            })
+            .collect();
+        (!requests.is_empty()).then(|| request_image(agent_id, session_id, &requests))
+    }
+
+    /// Feed an image message; decodes once enough of the texture has arrived
+    pub async fn handle_packet(&mut self, packet: &LLUDPPacket) -> Result<Option<TextureEvent>> {
+        match self.downloads.handle_packet(packet)? {
+            Some(ImageProgress::Received(id)) => {
+                // Late or resent packets for textures nobody wants, or that are already at full detail
+                let wanted = self.requests.get(&id).copied().filter(|_| self.decoded.get(&id) != Some(&0));
+                let Some(request) = wanted else {
+                    self.downloads.remove(id);
+                    return Ok(None);
+                };
+                let download = self.downloads.download(id);
+                let Some(info) = download.info() else {
+                    return Ok(None);
+                };
+                let discard = desired_discard(&info, request.screen_size);
+                if !download.is_complete() && download.data.len() < download.bytes_needed(discard) {
+                    return Ok(None);
+                }
+                if self.decoded.get(&id).is_some_and(|decoded| *decoded <= discard) {
+                    return Ok(None);
+                }
+                self.decode(id, discard, TextureSource::Udp).await.map(Some)
+            }
+            Some(ImageProgress::NotFound(id)) => {
+                self.cancel(id);
+                Ok(Some(TextureEvent::Missing(id)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn decode(&mut self, id: Uuid, discard_level: u8, source: TextureSource) -> Result<TextureEvent> {
+        let data = self.downloads.download(id).data.clone();
+        let options = DecodeOptions { discard_level, max_layers: None };
+        let texture = tokio::task::spawn_blocking(move || j2k::decode(&data, &options)).await??;
+        let (width, height) = (texture.width, texture.height);
+        let importance = self.requests.get(&id).map_or(0.0, |request| request.importance);
+        self.assets
+            .insert_asset(id, &id.to_string(), AssetType::Texture, AssetData::Texture(texture), importance)
+            .await;
+        self.decoded.insert(id, discard_level);
+        if discard_level == 0 {
+            // Nothing finer is left to fetch
+            self.downloads.remove(id);
+        }
+        debug!("Texture {} decoded at discard {} ({}x{})", id, discard_level, width, height);
+        Ok(TextureEvent::Decoded { id, discard_level, width, height, source })
+    }
+}
@@ -0,0 +1,217 @@
+// File: crates/storm-opensim/src/texture_entry.rs
+// TextureEntry: per-face textures, colours and mapping of prims and avatars
+
+use std::collections::BTreeMap;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use storm_ecs::Component;
+use uuid::Uuid;
+
+use crate::serialization::PayloadReader;
+
+/// Faces a TextureEntry can address
+pub const MAX_FACES: u8 = 32;
+
+/// Texture used for faces nobody has textured yet ("plywood")
+pub const DEFAULT_TEXTURE: Uuid = Uuid::from_u128(0x89556747_24cb_43ed_920b_47caed15465f);
+
+/// Appearance of one face
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextureFace {
+    pub texture_id: Uuid,
+    /// RGBA
+    pub color: [u8; 4],
+    pub repeat_u: f32,
+    pub repeat_v: f32,
+    pub offset_u: f32,
+    pub offset_v: f32,
+    /// Radians
+    pub rotation: f32,
+    /// Bump, shininess and fullbright bits
+    pub material: u8,
+    /// Media and texture mapping bits
+    pub media: u8,
+    pub glow: f32,
+    pub material_id: Uuid,
+}
+
+impl Default for TextureFace {
+    fn default() -> Self {
+        Self {
+            texture_id: DEFAULT_TEXTURE,
+            color: [255; 4],
+            repeat_u: 1.0,
+            repeat_v: 1.0,
+            offset_u: 0.0,
+            offset_v: 0.0,
+            rotation: 0.0,
+            material: 0,
+            media: 0,
+            glow: 0.0,
+            material_id: Uuid::nil(),
+        }
+    }
+}
+
+/// Default face plus the faces that differ from it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextureEntry {
+    pub default: TextureFace,
+    pub faces: BTreeMap<u8, TextureFace>,
+}
+
+impl TextureEntry {
+    /// Appearance of a face, falling back to the default
+    pub fn face(&self, index: u8) -> &TextureFace {
+        self.faces.get(&index).unwrap_or(&self.default)
+    }
+
+    /// Decode the wire format: one section per property, each a default value followed by
+    /// (face bitfield, value) overrides and terminated by an empty bitfield
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut entry = Self::default();
+        if data.is_empty() {
+            return Ok(entry);
+        }
+
+        let mut r = PayloadReader::new(data);
+        entry.section(&mut r, |r| r.uuid(), |face, value| face.texture_id = value)?;
+        // Colours are sent inverted so that the common white face is all zeroes
+        entry.section(&mut r, |r| Ok(r.bytes(4)?.try_into().map(invert)?), |face, value| face.color = value)?;
+        entry.section(&mut r, |r| r.f32(), |face, value| face.repeat_u = value)?;
+        entry.section(&mut r, |r| r.f32(), |face, value| face.repeat_v = value)?;
+        entry.section(&mut r, |r| Ok(r.i16()? as f32 / 32767.0), |face, value| face.offset_u = value)?;
+        entry.section(&mut r, |r| Ok(r.i16()? as f32 / 32767.0), |face, value| face.offset_v = value)?;
+        entry.section(
+            &mut r,
+            |r| Ok(r.i16()? as f32 / 32768.0 * std::f32::consts::TAU),
+            |face, value| face.rotation = value,
+        )?;
+        entry.section(&mut r, |r| r.u8(), |face, value| face.material = value)?;
+        entry.section(&mut r, |r| r.u8(), |face, value| face.media = value)?;
+        entry.section(&mut r, |r| Ok(r.u8()? as f32 / 255.0), |face, value| face.glow = value)?;
+        entry.section(&mut r, |r| r.uuid(), |face, value| face.material_id = value)?;
+        Ok(entry)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_section(&mut out, |face| face.texture_id, |out, value| out.extend_from_slice(value.as_bytes()));
+        self.write_section(&mut out, |face| face.color, |out, value| out.extend_from_slice(&invert(value)));
+        self.write_section(&mut out, |face| face.repeat_u, |out, value| out.extend_from_slice(&value.to_le_bytes()));
+        self.write_section(&mut out, |face| face.repeat_v, |out, value| out.extend_from_slice(&value.to_le_bytes()));
+        self.write_section(&mut out, |face| face.offset_u, |out, value| {
+            out.extend_from_slice(&((value * 32767.0).round() as i16).to_le_bytes())
+        });
+        self.write_section(&mut out, |face| face.offset_v, |out, value| {
+            out.extend_from_slice(&((value * 32767.0).round() as i16).to_le_bytes())
+        });
+        self.write_section(&mut out, |face| face.rotation, |out, value| {
+            let turns = value.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU;
+            out.extend_from_slice(&((turns * 32768.0).round() as i32 as i16).to_le_bytes())
+        });
+        self.write_section(&mut out, |face| face.material, |out, value| out.push(value));
+        self.write_section(&mut out, |face| face.media, |out, value| out.push(value));
+        self.write_section(&mut out, |face| face.glow, |out, value| out.push((value * 255.0).round() as u8));
+        self.write_section(&mut out, |face| face.material_id, |out, value| out.extend_from_slice(value.as_bytes()));
+        out
+    }
+
+    /// Read one property section; entries from older viewers stop early and keep the defaults
+    fn section<T: Clone>(
+        &mut self,
+        r: &mut PayloadReader<'_>,
+        read: impl Fn(&mut PayloadReader<'_>) -> Result<T>,
+        set: impl Fn(&mut TextureFace, T),
+    ) -> Result<()> {
+        if r.remaining() == 0 {
+            return Ok(());
+        }
+
+        // Faces created by earlier sections have not been overridden for this one yet
+        let default = read(r)?;
+        set(&mut self.default, default.clone());
+        for face in self.faces.values_mut() {
+            set(face, default.clone());
+        }
+
+        loop {
+            let faces = read_face_bits(r)?;
+            if faces == 0 {
+                return Ok(());
+            }
+            let value = read(r)?;
+            for index in (0..MAX_FACES).filter(|index| faces & (1 << index) != 0) {
+                let face = self.faces.entry(index).or_insert_with(|| self.default.clone());
+                set(face, value.clone());
+            }
+        }
+    }
+
+    fn write_section<T: PartialEq + Copy>(&self, out: &mut Vec<u8>, get: impl Fn(&TextureFace) -> T, write: impl Fn(&mut Vec<u8>, T)) {
+        let default = get(&self.default);
+        write(out, default);
+
+        // Faces sharing a value go out under one bitfield
+        let mut groups: Vec<(T, u64)> = Vec::new();
+        for (index, face) in &self.faces {
+            let value = get(face);
+            if value == default {
+                continue;
+            }
+            match groups.iter_mut().find(|(group, _)| *group == value) {
+                Some((_, faces)) => *faces |= 1 << index,
+                None => groups.push((value, 1 << index)),
+            }
+        }
+        for (value, faces) in groups {
+            write_face_bits(out, faces);
+            write(out, value);
+        }
+        out.push(0);
+    }
+}
+
+impl Component for TextureEntry {
+    fn type_name() -> &'static str {
+        "TextureEntry"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+fn invert(color: [u8; 4]) -> [u8; 4] {
+    color.map(|channel| 255 - channel)
+}
+
+/// Face bitfields are big-endian groups of 7 bits, the high bit marking that more follow
+fn read_face_bits(r: &mut PayloadReader<'_>) -> Result<u64> {
+    let mut faces = 0u64;
+    loop {
+        let byte = r.u8()?;
+        faces = (faces << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Ok(faces);
+        }
+    }
+}
+
+fn write_face_bits(out: &mut Vec<u8>, faces: u64) {
+    let mut groups = Vec::new();
+    let mut rest = faces;
+    loop {
+        groups.push((rest & 0x7F) as u8);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    let last = groups.len() - 1;
+    out.extend(groups.iter().rev().enumerate().map(|(i, group)| if i < last { group | 0x80 } else { *group }));
+}
@@ -0,0 +1,187 @@
+// File: crates/storm-opensim/src/xmlrpc.rs
+// Minimal XML-RPC encoding and decoding for the grid login service
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// An XML-RPC value
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlRpcValue {
+    String(String),
+    Int(i32),
+    Boolean(bool),
+    Double(f64),
+    /// dateTime.iso8601 and base64 values are kept in their text form
+    Text(String),
+    Array(Vec<XmlRpcValue>),
+    Struct(BTreeMap<String, XmlRpcValue>),
+}
+
+impl XmlRpcValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) | Self::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Integers, also accepting the numeric strings some grids send in their place
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(value) => Some(*value as i64),
+            Self::Double(value) => Some(*value as i64),
+            Self::String(value) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[XmlRpcValue]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct(&self) -> Option<&BTreeMap<String, XmlRpcValue>> {
+        match self {
+            Self::Struct(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    /// Member of a struct value
+    pub fn get(&self, name: &str) -> Option<&XmlRpcValue> {
+        self.as_struct()?.get(name)
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str("<value>");
+        match self {
+            Self::String(value) => {
+                out.push_str("<string>");
+                escape(value, out);
+                out.push_str("</string>");
+            }
+            Self::Int(value) => out.push_str(&format!("<i4>{}</i4>", value)),
+            Self::Boolean(value) => out.push_str(&format!("<boolean>{}</boolean>", *value as u8)),
+            Self::Double(value) => out.push_str(&format!("<double>{}</double>", value)),
+            Self::Text(value) => {
+                out.push_str("<base64>");
+                escape(value, out);
+                out.push_str("</base64>");
+            }
+            Self::Array(values) => {
+                out.push_str("<array><data>");
+                for value in values {
+                    value.write(out);
+                }
+                out.push_str("</data></array>");
+            }
+            Self::Struct(members) => {
+                out.push_str("<struct>");
+                for (name, value) in members {
+                    out.push_str("<member><name>");
+                    escape(name, out);
+                    out.push_str("</name>");
+                    value.write(out);
+                    out.push_str("</member>");
+                }
+                out.push_str("</struct>");
+            }
+        }
+        out.push_str("</value>");
+    }
+
+    fn parse(node: roxmltree::Node) -> Result<Self> {
+        // A <value> without a type element is a string
+        let Some(typed) = node.children().find(|child| child.is_element()) else {
+            return Ok(Self::String(node.text().unwrap_or_default().to_string()));
+        };
+        let text = typed.text().unwrap_or_default();
+        Ok(match typed.tag_name().name() {
+            "string" => Self::String(text.to_string()),
+            "i4" | "int" => Self::Int(text.trim().parse().map_err(|_| anyhow::anyhow!("Invalid XML-RPC int '{}'", text))?),
+            "boolean" => Self::Boolean(matches!(text.trim(), "1" | "true")),
+            "double" => Self::Double(text.trim().parse().map_err(|_| anyhow::anyhow!("Invalid XML-RPC double '{}'", text))?),
+            "dateTime.iso8601" | "base64" => Self::Text(text.trim().to_string()),
+            "nil" => Self::String(String::new()),
+            "array" => {
+                let data = child(typed, "data")?;
+                Self::Array(
+                    data.children()
+                        .filter(|value| value.has_tag_name("value"))
+                        .map(Self::parse)
+                        .collect::<Result<_>>()?,
+                )
+            }
+            "struct" => {
+                let mut members = BTreeMap::new();
+                for member in typed.children().filter(|member| member.has_tag_name("member")) {
+                    let name = child(member, "name")?.text().unwrap_or_default().to_string();
+                    members.insert(name, Self::parse(child(member, "value")?)?);
+                }
+                Self::Struct(members)
+            }
+            other => return Err(anyhow::anyhow!("Unsupported XML-RPC type '{}'", other)),
+        })
+    }
+}
+
+/// Outcome of an XML-RPC call
+#[derive(Debug, Clone, PartialEq)]
+pub enum MethodResponse {
+    Success(XmlRpcValue),
+    Fault { code: i64, message: String },
+}
+
+/// Encode a `methodCall` document
+pub fn encode_call(method: &str, params: &[XmlRpcValue]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\"?><methodCall><methodName>");
+    escape(method, &mut out);
+    out.push_str("</methodName><params>");
+    for param in params {
+        out.push_str("<param>");
+        param.write(&mut out);
+        out.push_str("</param>");
+    }
+    out.push_str("</params></methodCall>");
+    out
+}
+
+/// Decode a `methodResponse` document
+pub fn decode_response(xml: &str) -> Result<MethodResponse> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| anyhow::anyhow!("Malformed XML-RPC response: {}", e))?;
+    let root = document.root_element();
+    if !root.has_tag_name("methodResponse") {
+        return Err(anyhow::anyhow!("Expected methodResponse, found {}", root.tag_name().name()));
+    }
+
+    if let Some(fault) = root.children().find(|node| node.has_tag_name("fault")) {
+        let value = XmlRpcValue::parse(child(fault, "value")?)?;
+        return Ok(MethodResponse::Fault {
+            code: value.get("faultCode").and_then(XmlRpcValue::as_i64).unwrap_or_default(),
+            message: value.get("faultString").and_then(XmlRpcValue::as_str).unwrap_or_default().to_string(),
+        });
+    }
+
+    let param = child(child(root, "params")?, "param")?;
+    Ok(MethodResponse::Success(XmlRpcValue::parse(child(param, "value")?)?))
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Result<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .ok_or_else(|| anyhow::anyhow!("XML-RPC <{}> is missing <{}>", node.tag_name().name(), name))
+}
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+// File: crates/storm-physics/src/error.rs
+// Typed physics errors with stable codes and retry classification
+
+use thiserror::Error;
+
+/// Physics failures
+///
+/// Functions in this crate return `anyhow::Result`; these errors travel inside it and can be
+/// recovered with `downcast_ref::<PhysicsError>()`.
+#[derive(Error, Debug)]
+pub enum PhysicsError {
+    #[error("Invalid physics configuration: {reason}")]
+    InvalidConfig { reason: String },
+
+    #[error("Physics step failed: {reason}")]
+    StepFailed { reason: String },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl PhysicsError {
+    /// Stable numeric code, in the 8000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            PhysicsError::InvalidConfig { .. } => 8001,
+            PhysicsError::StepFailed { .. } => 8002,
+            PhysicsError::Other(_) => 8000,
+        }
+    }
+
+    /// A failed step leaves the previous state intact, so the next one may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, PhysicsError::StepFailed { .. })
+    }
+
+    /// Objects stop moving, but the world stays usable
+    pub fn is_fatal(&self) -> bool {
+        false
+    }
+}
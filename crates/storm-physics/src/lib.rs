@@ -4,9 +4,15 @@
 // Fixed version with proper mutable update method
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use storm_ecs::Heightfield;
 use tracing::{info, warn};
 use anyhow::Result;
 
+pub mod error;
+
+pub use error::PhysicsError;
+
 /// Physics configuration for the StormCore physics engine
 /// Defines simulation parameters including gravity, timestep, and feature flags
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +38,19 @@ impl Default for PhysicsConfig {
     }
 }
 
+impl PhysicsConfig {
+    /// Reject settings the simulation cannot step with
+    pub fn validate(&self) -> Result<(), PhysicsError> {
+        if !(self.timestep.is_finite() && self.timestep > 0.0) {
+            return Err(PhysicsError::InvalidConfig { reason: format!("timestep {} must be positive", self.timestep) });
+        }
+        if self.max_substeps == 0 {
+            return Err(PhysicsError::InvalidConfig { reason: "max_substeps must be at least 1".to_string() });
+        }
+        Ok(())
+    }
+}
+
 /// Main physics world simulation container
 /// Manages different physics backend implementations based on feature flags
 /// Integrates with ECS for entity-component synchronization
@@ -50,6 +69,9 @@ pub struct PhysicsWorld {
     bullet_world: Option<BulletWorld>,
     #[cfg(not(feature = "bullet"))]
     bullet_world: Option<()>, // Explicit type annotation for disabled feature
+
+    /// Static terrain colliders keyed by region handle
+    terrains: HashMap<u64, Heightfield>,
 }
 
 impl PhysicsWorld {
@@ -58,6 +80,7 @@ impl PhysicsWorld {
     /// Returns Result for error handling during initialization
     pub fn new(config: &PhysicsConfig) -> Result<Self> {
         info!("Initializing physics world with config: {:?}", config);
+        config.validate()?;
 
         // Initialize Rapier backend if feature is enabled
         #[cfg(feature = "rapier")]
@@ -75,6 +98,7 @@ impl PhysicsWorld {
             config: config.clone(),
             rapier_world,
             bullet_world,
+            terrains: HashMap::new(),
         })
     }
 
@@ -108,10 +132,31 @@ impl PhysicsWorld {
         &self.config
     }
 
+    /// Install or replace the terrain collider of a region
+    pub fn set_terrain(&mut self, heightfield: &Heightfield) {
+        // TODO: Rebuild the backend heightfield colliders from the samples
+        self.terrains.insert(heightfield.region_handle, heightfield.clone());
+    }
+
+    pub fn remove_terrain(&mut self, region_handle: u64) -> bool {
+        self.terrains.remove(&region_handle).is_some()
+    }
+
+    /// Revision of the terrain a region's collider was built from
+    pub fn terrain_revision(&self, region_handle: u64) -> Option<u64> {
+        self.terrains.get(&region_handle).map(|terrain| terrain.revision)
+    }
+
+    /// Ground height at a point in region meters
+    pub fn terrain_height(&self, region_handle: u64, x: f32, y: f32) -> Option<f32> {
+        self.terrains.get(&region_handle).map(|terrain| terrain.height_at(x, y))
+    }
+
     /// Updates the physics configuration at runtime
     /// Note: Some changes may require world recreation
     pub fn set_config(&mut self, config: PhysicsConfig) -> Result<()> {
         info!("Updating physics configuration");
+        config.validate()?;
         self.config = config;
 
         // TODO: Propagate config changes to active backends
@@ -226,6 +271,17 @@ mod tests {
         assert!(config.collision_detection_enabled);
     }
 
+    #[test]
+    fn test_invalid_config_is_typed() {
+        let config = PhysicsConfig { timestep: 0.0, ..Default::default() };
+        let err = PhysicsWorld::new(&config).err().unwrap();
+        assert_eq!(err.downcast_ref::<PhysicsError>().unwrap().code(), 8001);
+
+        let mut world = PhysicsWorld::new(&PhysicsConfig::default()).unwrap();
+        assert!(world.set_config(PhysicsConfig { max_substeps: 0, ..Default::default() }).is_err());
+        assert_eq!(world.get_config().max_substeps, 4);
+    }
+
     #[test]
     fn test_physics_update_disabled() {
         let mut config = PhysicsConfig::default();
@@ -254,6 +310,21 @@ mod tests {
         assert_eq!(world.get_config().gravity, [0.0, -3.71, 0.0]);
     }
 
+    #[test]
+    fn test_terrain_collider_heights() {
+        let mut world = PhysicsWorld::new(&PhysicsConfig::default()).unwrap();
+        let mut terrain = Heightfield::new(7, 2, 2);
+        terrain.set_block(0, 0, 2, &[10.0, 20.0, 30.0, 40.0]);
+
+        world.set_terrain(&terrain);
+        assert_eq!(world.terrain_revision(7), Some(1));
+        assert_eq!(world.terrain_height(7, 0.5, 0.5), Some(25.0));
+        assert_eq!(world.terrain_height(8, 0.5, 0.5), None);
+
+        assert!(world.remove_terrain(7));
+        assert_eq!(world.terrain_height(7, 0.5, 0.5), None);
+    }
+
     #[test]
     fn test_physics_update_mutable() {
         let config = PhysicsConfig::default();
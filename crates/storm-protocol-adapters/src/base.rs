@@ -1,6 +1,7 @@
 // File: crates/storm-protocol-adapters/src/base.rs
 // Base protocol adapter traits and implementations
 
+use std::net::SocketAddr;
 use async_trait::async_trait;
 use anyhow::Result;
 use storm_networking::{ConnectionId, ProtocolType as NetworkProtocolType};
@@ -19,12 +20,28 @@ pub trait ProtocolAdapter: Send + Sync {
     /// Disconnect from all active connections
     async fn disconnect_all(&mut self) -> Result<()>;
 
+    /// Queue raw bytes received on a connection for the next `process_pending_messages`
+    async fn receive(&mut self, connection_id: ConnectionId, data: Vec<u8>) -> Result<()>;
+
     /// Process pending messages from all connections
     async fn process_pending_messages(&mut self) -> Result<()>;
 
+    /// Drain datagrams the adapter produced while processing, for the network layer to send
+    async fn take_outgoing(&mut self) -> Vec<OutgoingDatagram> {
+        Vec::new()
+    }
+
     /// Send a message to a specific connection
     async fn send_message(&mut self, connection_id: ConnectionId, message: &ProtocolMessage) -> Result<()>;
 
     /// Get the protocol type this adapter handles (network layer type)
     fn protocol_type(&self) -> NetworkProtocolType;
-}
\ No newline at end of file
+}
+
+/// Datagram an adapter wants sent; `remote_addr` may be a child simulator rather than the root
+#[derive(Debug, Clone)]
+pub struct OutgoingDatagram {
+    pub connection_id: ConnectionId,
+    pub remote_addr: SocketAddr,
+    pub data: Vec<u8>,
+}
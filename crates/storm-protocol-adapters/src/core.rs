@@ -111,16 +111,16 @@ impl WorldConfig {
     /// Validate the configuration
     pub fn validate(&self) -> Result<()> {
         if self.name.is_empty() {
-            return Err(anyhow::anyhow!("World name cannot be empty"));
+            return Err(ProtocolError::InvalidConfiguration("World name cannot be empty".to_string()).into());
         }
 
         if self.url.is_empty() {
-            return Err(anyhow::anyhow!("World URL cannot be empty"));
+            return Err(ProtocolError::InvalidConfiguration("World URL cannot be empty".to_string()).into());
         }
 
         // Validate URL format
         url::Url::parse(&self.url)
-            .map_err(|e| anyhow::anyhow!("Invalid world URL: {}", e))?;
+            .map_err(|e| ProtocolError::InvalidConfiguration(format!("Invalid world URL: {}", e)))?;
 
         Ok(())
     }
@@ -130,6 +130,9 @@ impl WorldConfig {
 pub type ConnectionHandle = Uuid;
 
 /// Error types specific to protocol adapters
+///
+/// Adapter methods return `anyhow::Result`; these errors travel inside it and can be
+/// recovered with `downcast_ref::<ProtocolError>()`.
 #[derive(Debug, thiserror::Error)]
 pub enum ProtocolError {
     #[error("Connection failed: {0}")]
@@ -155,6 +158,66 @@ pub enum ProtocolError {
 
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
+
+    #[error("Connection not found: {connection_id}")]
+    ConnectionNotFound { connection_id: Uuid },
+
+    #[error("Malformed packet: {reason}")]
+    MalformedPacket { reason: String },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl ProtocolError {
+    /// Stable numeric code, in the 2000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            ProtocolError::ConnectionFailed(_) => 2001,
+            ProtocolError::AuthenticationFailed(_) => 2002,
+            ProtocolError::ProtocolError(_) => 2003,
+            ProtocolError::Timeout(_) => 2004,
+            ProtocolError::InvalidConfiguration(_) => 2005,
+            ProtocolError::UnsupportedProtocol(_) => 2006,
+            ProtocolError::NetworkError(_) => 2007,
+            ProtocolError::SerializationError(_) => 2008,
+            ProtocolError::ConnectionNotFound { .. } => 2009,
+            ProtocolError::MalformedPacket { .. } => 2010,
+            ProtocolError::Other(_) => 2000,
+        }
+    }
+
+    /// Whether reconnecting or resending may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProtocolError::ConnectionFailed(_) | ProtocolError::Timeout(_) | ProtocolError::NetworkError(_) => true,
+            ProtocolError::Other(err) => err
+                .downcast_ref::<storm_networking::NetworkError>()
+                .is_some_and(|err| err.is_retryable()),
+            _ => false,
+        }
+    }
+
+    /// Whether the world connection should be abandoned rather than retried
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ProtocolError::AuthenticationFailed(_)
+                | ProtocolError::InvalidConfiguration(_)
+                | ProtocolError::UnsupportedProtocol(_)
+        )
+    }
+
+    /// Connection the error relates to, if any
+    pub fn connection_id(&self) -> Option<Uuid> {
+        match self {
+            ProtocolError::ConnectionNotFound { connection_id } => Some(*connection_id),
+            ProtocolError::Other(err) => err
+                .downcast_ref::<storm_networking::NetworkError>()
+                .and_then(|err| err.connection_id()),
+            _ => None,
+        }
+    }
 }
 
 /// Result type for protocol operations
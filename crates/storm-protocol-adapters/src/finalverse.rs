@@ -3,28 +3,69 @@
 
 use async_trait::async_trait;
 use anyhow::Result;
+use std::collections::VecDeque;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use storm_ecs::World;
+use tokio::sync::{broadcast, RwLock};
+use storm_ecs::{Entity, World};
 use storm_ai::AIDispatcher;
+use storm_finalverse::messages::serialization;
+use storm_finalverse::FinalverseMessage;
 use storm_networking::{ConnectionId, ProtocolType as NetworkProtocolType};
-use crate::{ProtocolAdapter, ProtocolMessage, WorldConfig};
+use crate::{
+    ProtocolAdapter, ProtocolError, ProtocolMessage, RegionEvent, RegionLink, RegionTracker, TransitionKind, WorldConfig,
+};
 
 pub struct FinalverseAdapter {
     ecs_world: Arc<RwLock<World>>,
     ai_dispatcher: Arc<AIDispatcher>,
+    region: RegionTracker,
+    /// Local avatar, moved along with region changes
+    avatar: Option<Entity>,
+    /// Messages received from the server, applied on the next `process_pending_messages`
+    inbound: VecDeque<FinalverseMessage>,
 }
 
 impl FinalverseAdapter {
     pub async fn new(
         ecs_world: Arc<RwLock<World>>,
         ai_dispatcher: Arc<AIDispatcher>,
+        region_events: broadcast::Sender<RegionEvent>,
     ) -> Result<Self> {
         Ok(Self {
             ecs_world,
             ai_dispatcher,
+            region: RegionTracker::new(region_events),
+            avatar: None,
+            inbound: VecDeque::new(),
         })
     }
+
+    /// Set the entity that region changes move
+    pub fn set_avatar(&mut self, avatar: Option<Entity>) {
+        self.avatar = avatar;
+    }
+
+    /// Apply a Finalverse region change; moving into a known neighbour counts as a crossing
+    pub async fn change_region(
+        &mut self,
+        destination: RegionLink,
+        avatar: Option<Entity>,
+        position: [f32; 3],
+    ) -> Result<usize> {
+        let kind = if self.region.neighbour(destination.region_id).is_some() {
+            TransitionKind::Crossing
+        } else {
+            TransitionKind::Teleport
+        };
+
+        let mut world = self.ecs_world.write().await;
+        self.region.complete_transition(&mut world, kind, destination, avatar, Some(position))
+    }
+
+    /// Current region tracking state
+    pub fn region(&self) -> &RegionTracker {
+        &self.region
+    }
 }
 
 #[async_trait]
@@ -47,8 +88,27 @@ impl ProtocolAdapter for FinalverseAdapter {
         Ok(())
     }
 
+    async fn receive(&mut self, connection_id: ConnectionId, data: Vec<u8>) -> Result<()> {
+        let message = serialization::deserialize_message(&data).map_err(|e| ProtocolError::MalformedPacket {
+            reason: format!("Finalverse message on {}: {}", connection_id, e),
+        })?;
+        self.inbound.push_back(message);
+        Ok(())
+    }
+
     async fn process_pending_messages(&mut self) -> Result<()> {
-        // Process incoming messages from WebSocket connections
+        while let Some(message) = self.inbound.pop_front() {
+            match message {
+                FinalverseMessage::RegionChange { region_id, region_name, origin, position } => {
+                    let region_id = uuid::Uuid::parse_str(&region_id).map_err(|e| ProtocolError::MalformedPacket {
+                        reason: format!("RegionChange region id {:?}: {}", region_id, e),
+                    })?;
+                    let destination = RegionLink::new(region_id, region_name, origin);
+                    self.change_region(destination, self.avatar, position).await?;
+                }
+                other => tracing::debug!("Ignoring Finalverse message {:?}", other),
+            }
+        }
         Ok(())
     }
 
@@ -4,7 +4,7 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn, error};
 use anyhow::Result;
@@ -17,9 +17,11 @@ pub mod opensim;
 pub mod finalverse;
 pub mod base;
 pub mod core;
+pub mod region;
 
 pub use base::*;
 pub use core::*;
+pub use region::*;
 
 #[cfg(feature = "opensim")]
 pub use opensim::*;
@@ -33,6 +35,7 @@ pub struct ProtocolRouter {
     ecs_world: Arc<RwLock<World>>,
     ai_dispatcher: Arc<AIDispatcher>,
     active_connections: HashMap<ConnectionId, NetworkProtocolType>,
+    region_events: broadcast::Sender<RegionEvent>,
 }
 
 impl ProtocolRouter {
@@ -43,6 +46,7 @@ impl ProtocolRouter {
         info!("Initializing protocol router");
 
         let mut adapters: HashMap<NetworkProtocolType, Box<dyn ProtocolAdapter>> = HashMap::new();
+        let (region_events, _) = broadcast::channel(64);
 
         // Initialize OpenSim adapter
         #[cfg(feature = "opensim")]
@@ -50,6 +54,7 @@ impl ProtocolRouter {
             let opensim_adapter = opensim::OpenSimAdapter::new(
                 ecs_world.clone(),
                 ai_dispatcher.clone(),
+                region_events.clone(),
             ).await?;
             adapters.insert(NetworkProtocolType::LLUDP, Box::new(opensim_adapter));
         }
@@ -60,6 +65,7 @@ impl ProtocolRouter {
             let finalverse_adapter = finalverse::FinalverseAdapter::new(
                 ecs_world.clone(),
                 ai_dispatcher.clone(),
+                region_events.clone(),
             ).await?;
             adapters.insert(NetworkProtocolType::WebSocket, Box::new(finalverse_adapter));
         }
@@ -69,6 +75,7 @@ impl ProtocolRouter {
             ecs_world,
             ai_dispatcher,
             active_connections: HashMap::new(),
+            region_events,
         })
     }
 
@@ -90,7 +97,7 @@ impl ProtocolRouter {
             info!("Connected to world {} via {:?}", world_config.name, network_protocol);
             Ok(connection_id)
         } else {
-            Err(anyhow::anyhow!("No adapter available for protocol: {:?}", network_protocol))
+            Err(ProtocolError::UnsupportedProtocol(world_config.protocol).into())
         }
     }
 
@@ -102,6 +109,28 @@ impl ProtocolRouter {
         Ok(())
     }
 
+    /// Hand bytes received on a connection to the adapter that owns it
+    pub async fn receive(&mut self, connection_id: ConnectionId, data: Vec<u8>) -> Result<()> {
+        let protocol = *self
+            .active_connections
+            .get(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        let adapter = self
+            .adapters
+            .get_mut(&protocol)
+            .ok_or_else(|| ProtocolError::ProtocolError(format!("No adapter found for {:?}", protocol)))?;
+        adapter.receive(connection_id, data).await
+    }
+
+    /// Datagrams produced by all adapters since the last call
+    pub async fn take_outgoing(&mut self) -> Vec<OutgoingDatagram> {
+        let mut outgoing = Vec::new();
+        for adapter in self.adapters.values_mut() {
+            outgoing.extend(adapter.take_outgoing().await);
+        }
+        outgoing
+    }
+
     /// Disconnect from all worlds
     pub async fn disconnect_all(&mut self) -> Result<()> {
         for adapter in self.adapters.values_mut() {
@@ -117,14 +146,19 @@ impl ProtocolRouter {
             if let Some(adapter) = self.adapters.get_mut(&protocol) {
                 adapter.send_message(connection_id, message).await?;
             } else {
-                return Err(anyhow::anyhow!("No adapter found for connection"));
+                return Err(ProtocolError::ProtocolError(format!("No adapter found for {:?}", protocol)).into());
             }
         } else {
-            return Err(anyhow::anyhow!("Connection not found: {}", connection_id));
+            return Err(ProtocolError::ConnectionNotFound { connection_id }.into());
         }
         Ok(())
     }
 
+    /// Receive teleport, region crossing and neighbour events from all adapters
+    pub fn subscribe_region_events(&self) -> broadcast::Receiver<RegionEvent> {
+        self.region_events.subscribe()
+    }
+
     /// Get active connections count
     pub fn active_connections_count(&self) -> usize {
         self.active_connections.len()
@@ -170,4 +204,256 @@ impl ProtocolMessage {
         let data = serde_json::to_value(json)?;
         Ok(Self::new(message_type, data))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storm_ecs::Transform;
+
+    fn spawn(world: &mut World, tracker: &RegionTracker, region_id: uuid::Uuid, position: [f32; 3]) -> Entity {
+        let entity = world.create_entity();
+        world.add_component(entity, Transform { position, ..Default::default() });
+        tracker.assign(world, entity, region_id);
+        entity
+    }
+
+    #[test]
+    fn test_region_crossing_rebases_and_evicts() {
+        let (events, mut receiver) = broadcast::channel(16);
+        let mut tracker = RegionTracker::new(events);
+        let mut world = World::new();
+
+        let home = RegionLink::new(uuid::Uuid::new_v4(), "Home", [256000.0, 256000.0]);
+        let east = RegionLink::new(uuid::Uuid::new_v4(), "East", [256256.0, 256000.0]);
+        tracker.complete_transition(&mut world, TransitionKind::Teleport, home.clone(), None, None).unwrap();
+        tracker.add_neighbour(east.clone());
+
+        let avatar = spawn(&mut world, &tracker, home.region_id, [250.0, 128.0, 20.0]);
+        let near = spawn(&mut world, &tracker, home.region_id, [200.0, 128.0, 20.0]);
+        let far = spawn(&mut world, &tracker, home.region_id, [10.0, 128.0, 20.0]);
+
+        let evicted = tracker
+            .complete_transition(&mut world, TransitionKind::Crossing, east.clone(), Some(avatar), Some([2.0, 128.0, 20.0]))
+            .unwrap();
+
+        // The far object ends up more than the draw distance behind the border
+        assert_eq!(evicted, 1);
+        assert!(world.get_component::<Transform>(far).is_none());
+        assert_eq!(tracker.current_region_id(), Some(east.region_id));
+        assert!(tracker.neighbour(home.region_id).is_some());
+        assert_eq!(world.get_component::<Transform>(avatar).unwrap().position, [2.0, 128.0, 20.0]);
+        assert_eq!(world.get_component::<Transform>(near).unwrap().position, [-56.0, 128.0, 20.0]);
+
+        let tracker = tracker.with_draw_distance(32.0);
+        assert_eq!(tracker.evict_invisible(&mut world, Some(avatar)), 1);
+        assert!(world.get_component::<Transform>(near).is_none());
+        assert!(world.get_component::<Transform>(avatar).is_some());
+
+        let mut saw_change = false;
+        while let Ok(event) = receiver.try_recv() {
+            if let RegionEvent::RegionChanged { kind: TransitionKind::Crossing, .. } = event {
+                saw_change = true;
+            }
+        }
+        assert!(saw_change);
+    }
+
+    #[test]
+    fn test_teleport_evicts_previous_region() {
+        let (events, _receiver) = broadcast::channel(16);
+        let mut tracker = RegionTracker::new(events);
+        let mut world = World::new();
+
+        let home = RegionLink::from_handle(uuid::Uuid::new_v4(), "Home", (256000u64 << 32) | 256000);
+        let away = RegionLink::from_handle(uuid::Uuid::new_v4(), "Away", (512000u64 << 32) | 512000);
+        tracker.complete_transition(&mut world, TransitionKind::Teleport, home.clone(), None, None).unwrap();
+
+        let avatar = spawn(&mut world, &tracker, home.region_id, [128.0, 128.0, 20.0]);
+        spawn(&mut world, &tracker, home.region_id, [100.0, 100.0, 20.0]);
+
+        tracker.begin_teleport("Away");
+        assert!(tracker.is_teleporting());
+        let evicted = tracker
+            .complete_transition(&mut world, TransitionKind::Teleport, away.clone(), Some(avatar), Some([64.0, 64.0, 30.0]))
+            .unwrap();
+
+        assert_eq!(evicted, 1);
+        assert!(!tracker.is_teleporting());
+        assert_eq!(away.handle(), (512000u64 << 32) | 512000);
+        assert_eq!(world.get_component::<RegionMember>(avatar).unwrap().region_id, away.region_id);
+    }
+
+    #[tokio::test]
+    async fn test_simulator_events_move_agent_between_regions() {
+        let (events, _receiver) = broadcast::channel(16);
+        let config = storm_ai::AIConfig { grok_api_key: None, local_ml_enabled: false, ..Default::default() };
+        let ai = Arc::new(AIDispatcher::new(&config).await.unwrap());
+        let mut adapter = OpenSimAdapter::new(Arc::new(RwLock::new(World::new())), ai, events).await.unwrap();
+        let connection = adapter.connect_to_world(&WorldConfig::opensim("Grid", "http://127.0.0.1:9000/")).await.unwrap();
+
+        let east = (256256u64 << 32) | 256000;
+        let east_addr: std::net::SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        adapter
+            .dispatch_event(connection, SimulatorEvent::EnableSimulator { region_handle: east, sim_addr: east_addr })
+            .await
+            .unwrap();
+        adapter
+            .dispatch_event(connection, SimulatorEvent::EstablishAgentCommunication {
+                sim_addr: east_addr,
+                seed_capability: "http://127.0.0.1:9001/CAPS/child/".to_string(),
+            })
+            .await
+            .unwrap();
+        let neighbours = adapter.neighbours(connection).await.unwrap();
+        assert_eq!(neighbours.len(), 1);
+        assert_eq!(neighbours[0].handle(), east);
+        assert_eq!(neighbours[0].seed_capability.as_deref(), Some("http://127.0.0.1:9001/CAPS/child/"));
+
+        adapter
+            .dispatch_event(connection, SimulatorEvent::CrossedRegion {
+                region_handle: east,
+                sim_addr: east_addr,
+                seed_capability: "http://127.0.0.1:9001/CAPS/root/".to_string(),
+                position: [2.0, 128.0, 20.0],
+            })
+            .await
+            .unwrap();
+        let current = adapter.current_region(connection).await.unwrap().unwrap();
+        assert_eq!(current.handle(), east);
+        assert_eq!(current.sim_addr, Some(east_addr));
+        assert_eq!(current.seed_capability.as_deref(), Some("http://127.0.0.1:9001/CAPS/root/"));
+
+        let away = (512000u64 << 32) | 512000;
+        adapter
+            .dispatch_event(connection, SimulatorEvent::TeleportFinish {
+                region_handle: away,
+                sim_addr: "127.0.0.1:9010".parse().unwrap(),
+                seed_capability: String::new(),
+            })
+            .await
+            .unwrap();
+        let current = adapter.current_region(connection).await.unwrap().unwrap();
+        assert_eq!(current.handle(), away);
+        assert_eq!(current.seed_capability, None);
+
+        let unknown = ConnectionId::new_v4();
+        assert!(adapter
+            .dispatch_event(unknown, SimulatorEvent::EnableSimulator { region_handle: east, sim_addr: east_addr })
+            .await
+            .is_err());
+    }
+
+    /// LLUDP datagram with a Low frequency message number and no extra header
+    fn low_datagram(flags: u8, sequence: u32, id: u16, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![flags];
+        data.extend_from_slice(&sequence.to_be_bytes());
+        data.push(0);
+        data.extend_from_slice(&[0xFF, 0xFF]);
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn sim_address(handle: u64, addr: std::net::SocketAddrV4) -> Vec<u8> {
+        let mut data = handle.to_le_bytes().to_vec();
+        data.extend_from_slice(&addr.ip().octets());
+        data.extend_from_slice(&addr.port().to_be_bytes());
+        data
+    }
+
+    #[tokio::test]
+    async fn test_received_datagrams_open_circuits_and_hand_off_agent() {
+        let (events, _receiver) = broadcast::channel(16);
+        let config = storm_ai::AIConfig { grok_api_key: None, local_ml_enabled: false, ..Default::default() };
+        let ai = Arc::new(AIDispatcher::new(&config).await.unwrap());
+        let world = Arc::new(RwLock::new(World::new()));
+        let mut adapter = OpenSimAdapter::new(world.clone(), ai, events).await.unwrap();
+        let connection = adapter.connect_to_world(&WorldConfig::opensim("Grid", "http://127.0.0.1:9000/")).await.unwrap();
+        let (agent_id, session_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        adapter.set_session(connection, agent_id, session_id, 0x1234).await.unwrap();
+        let avatar = {
+            let mut world = world.write().await;
+            let avatar = world.create_entity();
+            world.add_component(avatar, Transform { position: [64.0, 64.0, 30.0], ..Default::default() });
+            world.add_component(avatar, OpenSimAgent { agent_id, session_id: Some(session_id), connection_id: connection });
+            avatar
+        };
+
+        // EnableSimulator opens a child circuit with UseCircuitCode; a truncated packet is dropped
+        let east = (256256u64 << 32) | 256000;
+        let east_addr: std::net::SocketAddrV4 = "127.0.0.1:9001".parse().unwrap();
+        adapter.receive(connection, low_datagram(0x40, 7, 151, &sim_address(east, east_addr))).await.unwrap();
+        adapter.receive(connection, vec![0x40, 0, 0]).await.unwrap();
+        adapter.process_pending_messages().await.unwrap();
+
+        let outgoing = adapter.take_outgoing().await;
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].remote_addr, std::net::SocketAddr::V4(east_addr));
+        let data = &outgoing[0].data;
+        assert_eq!(&data[..10], &[0x40, 0, 0, 0, 1, 0, 0xFF, 0xFF, 0, 3]);
+        assert_eq!(&data[10..14], &0x1234u32.to_le_bytes());
+        assert_eq!(&data[14..30], session_id.as_bytes());
+        assert_eq!(&data[30..46], agent_id.as_bytes());
+
+        // TeleportFinish moves the root circuit to a simulator the agent has no circuit with yet
+        let away = (512000u64 << 32) | 512000;
+        let away_addr: std::net::SocketAddrV4 = "127.0.0.1:9010".parse().unwrap();
+        let mut finish = agent_id.as_bytes().to_vec();
+        finish.extend_from_slice(&0u32.to_le_bytes());
+        let address = sim_address(away, away_addr);
+        finish.extend_from_slice(&address[8..]);
+        finish.extend_from_slice(&address[..8]);
+        finish.extend_from_slice(&0u16.to_le_bytes());
+        finish.extend_from_slice(&[13, 0, 0, 0, 0]);
+        adapter.receive(connection, low_datagram(0x40, 8, 69, &finish)).await.unwrap();
+        adapter.process_pending_messages().await.unwrap();
+
+        let current = adapter.current_region(connection).await.unwrap().unwrap();
+        assert_eq!(current.handle(), away);
+        let outgoing = adapter.take_outgoing().await;
+        assert_eq!(outgoing.len(), 3);
+        assert!(outgoing.iter().all(|datagram| datagram.remote_addr == std::net::SocketAddr::V4(away_addr)));
+        assert_eq!(&outgoing[0].data[1..10], &[0, 0, 0, 1, 0, 0xFF, 0xFF, 0, 3]);
+        assert_eq!(&outgoing[1].data[1..10], &[0, 0, 0, 2, 0, 0xFF, 0xFF, 0, 249]);
+        assert_eq!(&outgoing[1].data[42..46], &0x1234u32.to_le_bytes());
+        // AgentUpdate is High frequency, with the camera centred on the avatar
+        let update = &outgoing[2].data;
+        assert_eq!(&update[1..7], &[0, 0, 0, 3, 0, 4]);
+        let camera_x = f32::from_le_bytes(update[64..68].try_into().unwrap());
+        let position = world.read().await.get_component::<Transform>(avatar).unwrap().position;
+        assert_eq!(camera_x, position[0]);
+    }
+
+    #[tokio::test]
+    async fn test_finalverse_region_change_moves_avatar() {
+        let (events, _receiver) = broadcast::channel(16);
+        let config = storm_ai::AIConfig { grok_api_key: None, local_ml_enabled: false, ..Default::default() };
+        let ai = Arc::new(AIDispatcher::new(&config).await.unwrap());
+        let world = Arc::new(RwLock::new(World::new()));
+        let mut adapter = FinalverseAdapter::new(world.clone(), ai, events).await.unwrap();
+        let connection = adapter.connect_to_world(&WorldConfig::finalverse("Finalverse", "ws://127.0.0.1:3000/")).await.unwrap();
+        let avatar = {
+            let mut world = world.write().await;
+            let avatar = world.create_entity();
+            world.add_component(avatar, Transform::default());
+            avatar
+        };
+        adapter.set_avatar(Some(avatar));
+
+        let region_id = uuid::Uuid::new_v4();
+        let message = storm_finalverse::FinalverseMessage::RegionChange {
+            region_id: region_id.to_string(),
+            region_name: "Echo Plains".to_string(),
+            origin: [512.0, 256.0],
+            position: [10.0, 20.0, 5.0],
+        };
+        let data = storm_finalverse::messages::serialization::serialize_message(&message).unwrap();
+        adapter.receive(connection, data).await.unwrap();
+        assert!(adapter.receive(connection, b"not json".to_vec()).await.is_err());
+        adapter.process_pending_messages().await.unwrap();
+
+        assert_eq!(adapter.region().current_region_id(), Some(region_id));
+        let world = world.read().await;
+        assert_eq!(world.get_component::<Transform>(avatar).unwrap().position, [10.0, 20.0, 5.0]);
+    }
+}
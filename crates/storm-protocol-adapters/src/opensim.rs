@@ -2,10 +2,10 @@
 // OpenSim/MutSea protocol adapter implementation
 // Handles LLUDP protocol for OpenSimulator grids
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::{RwLock, Mutex};
+use tokio::sync::{broadcast, RwLock, Mutex};
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn, error, debug};
 use anyhow::Result;
@@ -13,7 +13,19 @@ use anyhow::Result;
 use storm_ecs::{World, Entity, Transform, Velocity};
 use storm_ai::AIDispatcher;
 use storm_networking::{NetworkManager, ConnectionId, ProtocolType, IncomingPacket, OutgoingPacket};
-use crate::{ProtocolAdapter, ProtocolMessage, WorldConfig};
+use crate::{
+    OutgoingDatagram, ProtocolAdapter, ProtocolError, ProtocolMessage, RegionEvent, RegionLink, RegionTracker, TransitionKind,
+    WorldConfig,
+};
+
+/// Header flag: the body is zero-coded
+const FLAG_ZEROCODED: u8 = 0x80;
+/// Header flag: the sender wants this packet acked
+const FLAG_RELIABLE: u8 = 0x40;
+/// Header flag: acks for earlier packets follow the body
+const FLAG_ACKS: u8 = 0x10;
+/// Draw distance reported to a simulator the agent is handed over to
+const AGENT_DRAW_DISTANCE: f32 = 128.0;
 
 /// OpenSim protocol adapter
 pub struct OpenSimAdapter {
@@ -21,20 +33,59 @@ pub struct OpenSimAdapter {
     ai_dispatcher: Arc<AIDispatcher>,
     connections: Arc<Mutex<HashMap<ConnectionId, OpenSimConnection>>>,
     message_handlers: HashMap<LLUDPMessageType, Box<dyn MessageHandler>>,
+    region_events: broadcast::Sender<RegionEvent>,
+    /// Datagrams received from the network, routed on the next `process_pending_messages`
+    inbound: Arc<Mutex<VecDeque<ReceivedDatagram>>>,
 }
 
+/// Raw datagram and the connection it arrived on
+type ReceivedDatagram = (ConnectionId, Vec<u8>);
+
 /// OpenSim connection state
 struct OpenSimConnection {
     id: ConnectionId,
     remote_addr: SocketAddr,
     session_id: Option<uuid::Uuid>,
     agent_id: Option<uuid::Uuid>,
-    region_id: Option<uuid::Uuid>,
+    circuit_code: u32,
+    region: RegionTracker,
+    child_circuits: HashMap<u64, ChildCircuit>,
     sequence_number: u32,
     last_ack: u32,
+    /// Datagrams for the root and child simulators, waiting for `take_outgoing`
+    outbox: Vec<OutgoingDatagram>,
+}
+
+/// Circuit to a neighbouring simulator, opened on EnableSimulator
+#[derive(Debug, Clone)]
+pub struct ChildCircuit {
+    pub region_handle: u64,
+    pub remote_addr: SocketAddr,
+    /// Sequence number of the next packet sent on this circuit
+    pub next_sequence: u32,
+}
+
+/// Region-level events, delivered as LLUDP messages or through the capability event queue
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulatorEvent {
+    /// A neighbouring simulator the agent should open a child circuit to
+    EnableSimulator { region_handle: u64, sim_addr: SocketAddr },
+    /// A child agent exists in the neighbour at this address, with its own capabilities
+    EstablishAgentCommunication { sim_addr: SocketAddr, seed_capability: String },
+    TeleportFinish { region_handle: u64, sim_addr: SocketAddr, seed_capability: String },
+    /// Region-local position after walking or flying across a border
+    CrossedRegion { region_handle: u64, sim_addr: SocketAddr, seed_capability: String, position: [f32; 3] },
 }
 
-/// LLUDP message types
+/// How often a message is sent, which decides how many bytes its number takes on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageFrequency {
+    High,
+    Medium,
+    Low,
+}
+
+/// LLUDP message types, numbered within their frequency as in message_template.msg
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LLUDPMessageType {
     // Login/Session
@@ -43,7 +94,7 @@ pub enum LLUDPMessageType {
 
     // Agent movement
     AgentUpdate = 4,
-    AgentAnimation = 20,
+    AgentAnimation = 5,
 
     // Object updates
     ObjectUpdate = 12,
@@ -51,21 +102,93 @@ pub enum LLUDPMessageType {
     ObjectUpdateCached = 14,
 
     // Asset requests
-    RequestImage = 21,
-    ImageData = 22,
+    RequestImage = 8,
+    ImageData = 9,
 
     // Chat
-    ChatFromViewer = 77,
+    ChatFromViewer = 80,
     ChatFromSimulator = 139,
 
+    // Teleport and region crossing
+    CrossedRegion = 7,
+    TeleportProgress = 66,
+    TeleportFinish = 69,
+    TeleportStart = 73,
+    TeleportFailed = 74,
+    EnableSimulator = 151,
+
     // Inventory
-    FetchInventory = 73,
-    InventoryDescendents = 74,
+    FetchInventory = 279,
+    InventoryDescendents = 278,
 
     // Unknown/Unsupported
     Unknown = 0,
 }
 
+impl LLUDPMessageType {
+    /// Every known message, excluding `Unknown`
+    pub const ALL: &'static [Self] = &[
+        Self::UseCircuitCode,
+        Self::CompleteAgentMovement,
+        Self::AgentUpdate,
+        Self::AgentAnimation,
+        Self::ObjectUpdate,
+        Self::ObjectUpdateCompressed,
+        Self::ObjectUpdateCached,
+        Self::RequestImage,
+        Self::ImageData,
+        Self::ChatFromViewer,
+        Self::ChatFromSimulator,
+        Self::CrossedRegion,
+        Self::TeleportProgress,
+        Self::TeleportFinish,
+        Self::TeleportStart,
+        Self::TeleportFailed,
+        Self::EnableSimulator,
+        Self::FetchInventory,
+        Self::InventoryDescendents,
+    ];
+
+    pub fn frequency(self) -> MessageFrequency {
+        match self {
+            Self::AgentUpdate
+            | Self::AgentAnimation
+            | Self::ObjectUpdate
+            | Self::ObjectUpdateCompressed
+            | Self::ObjectUpdateCached
+            | Self::RequestImage
+            | Self::ImageData => MessageFrequency::High,
+            Self::CrossedRegion => MessageFrequency::Medium,
+            _ => MessageFrequency::Low,
+        }
+    }
+
+    pub fn from_id(frequency: MessageFrequency, id: u16) -> Self {
+        match (frequency, id) {
+            (MessageFrequency::High, 4) => Self::AgentUpdate,
+            (MessageFrequency::High, 5) => Self::AgentAnimation,
+            (MessageFrequency::High, 8) => Self::RequestImage,
+            (MessageFrequency::High, 9) => Self::ImageData,
+            (MessageFrequency::High, 12) => Self::ObjectUpdate,
+            (MessageFrequency::High, 13) => Self::ObjectUpdateCompressed,
+            (MessageFrequency::High, 14) => Self::ObjectUpdateCached,
+            (MessageFrequency::Medium, 7) => Self::CrossedRegion,
+            (MessageFrequency::Low, 3) => Self::UseCircuitCode,
+            (MessageFrequency::Low, 66) => Self::TeleportProgress,
+            (MessageFrequency::Low, 69) => Self::TeleportFinish,
+            (MessageFrequency::Low, 73) => Self::TeleportStart,
+            (MessageFrequency::Low, 74) => Self::TeleportFailed,
+            (MessageFrequency::Low, 80) => Self::ChatFromViewer,
+            (MessageFrequency::Low, 139) => Self::ChatFromSimulator,
+            (MessageFrequency::Low, 151) => Self::EnableSimulator,
+            (MessageFrequency::Low, 249) => Self::CompleteAgentMovement,
+            (MessageFrequency::Low, 278) => Self::InventoryDescendents,
+            (MessageFrequency::Low, 279) => Self::FetchInventory,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 /// LLUDP packet structure
 #[derive(Debug)]
 pub struct LLUDPPacket {
@@ -76,6 +199,17 @@ pub struct LLUDPPacket {
     pub payload: Vec<u8>,
 }
 
+impl LLUDPPacket {
+    /// Packet the receiver must ack; the sequence is assigned when it is queued on a circuit
+    pub fn reliable(message_type: LLUDPMessageType, payload: Vec<u8>) -> Self {
+        Self { flags: FLAG_RELIABLE, sequence: 0, extra_header: Vec::new(), message_type, payload }
+    }
+
+    pub fn unreliable(message_type: LLUDPMessageType, payload: Vec<u8>) -> Self {
+        Self { flags: 0, sequence: 0, extra_header: Vec::new(), message_type, payload }
+    }
+}
+
 /// Trait for handling specific LLUDP messages
 trait MessageHandler: Send + Sync {
     fn handle_message(
@@ -90,6 +224,7 @@ impl OpenSimAdapter {
     pub async fn new(
         ecs_world: Arc<RwLock<World>>,
         ai_dispatcher: Arc<AIDispatcher>,
+        region_events: broadcast::Sender<RegionEvent>,
     ) -> Result<Self> {
         info!("Initializing OpenSim protocol adapter");
 
@@ -101,83 +236,179 @@ impl OpenSimAdapter {
         message_handlers.insert(LLUDPMessageType::AgentUpdate, Box::new(AgentUpdateHandler));
         message_handlers.insert(LLUDPMessageType::ObjectUpdate, Box::new(ObjectUpdateHandler));
         message_handlers.insert(LLUDPMessageType::ChatFromViewer, Box::new(ChatHandler));
+        message_handlers.insert(LLUDPMessageType::EnableSimulator, Box::new(EnableSimulatorHandler));
+        message_handlers.insert(LLUDPMessageType::TeleportStart, Box::new(TeleportStatusHandler));
+        message_handlers.insert(LLUDPMessageType::TeleportProgress, Box::new(TeleportStatusHandler));
+        message_handlers.insert(LLUDPMessageType::TeleportFailed, Box::new(TeleportStatusHandler));
+        message_handlers.insert(LLUDPMessageType::TeleportFinish, Box::new(RegionTransitionHandler));
+        message_handlers.insert(LLUDPMessageType::CrossedRegion, Box::new(RegionTransitionHandler));
 
         Ok(Self {
             ecs_world,
             ai_dispatcher,
             connections: Arc::new(Mutex::new(HashMap::new())),
             message_handlers,
+            region_events,
+            inbound: Arc::new(Mutex::new(VecDeque::new())),
         })
     }
 
-    /// Parse LLUDP packet from raw bytes
-    fn parse_packet(&self, data: &[u8]) -> Result<LLUDPPacket> {
-        if data.len() < 6 {
-            return Err(anyhow::anyhow!("Packet too short"));
+    /// Record the identity a login handed out; child circuits and region handoffs need it
+    pub async fn set_session(
+        &self,
+        connection_id: ConnectionId,
+        agent_id: uuid::Uuid,
+        session_id: uuid::Uuid,
+        circuit_code: u32,
+    ) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection = connections
+            .get_mut(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        connection.agent_id = Some(agent_id);
+        connection.session_id = Some(session_id);
+        connection.circuit_code = circuit_code;
+        Ok(())
+    }
+
+    /// Route one received datagram through the message handlers
+    async fn route_datagram(&self, connection_id: ConnectionId, data: &[u8]) -> Result<()> {
+        let packet = Self::parse_packet(data)?;
+        let Some(handler) = self.message_handlers.get(&packet.message_type) else {
+            debug!("No handler for {:?}", packet.message_type);
+            return Ok(());
+        };
+
+        let mut connections = self.connections.lock().await;
+        let connection = connections
+            .get_mut(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        let mut world = self.ecs_world.write().await;
+        for reply in handler.handle_message(&packet, connection, &mut world)? {
+            connection.send_root(reply);
         }
+        Ok(())
+    }
 
-        let flags = data[0];
-        let sequence = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    /// Mark an agent as teleporting; the simulator's TeleportFinish or TeleportFailed completes it
+    pub async fn begin_teleport(&self, connection_id: ConnectionId, destination: &str) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection = connections
+            .get_mut(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        connection.region.begin_teleport(destination);
+        Ok(())
+    }
 
-        let extra_header_len = if flags & 0x40 != 0 { data[5] as usize } else { 0 };
-        let header_end = 6 + extra_header_len;
+    /// Apply an event that arrived outside LLUDP, such as from the capability event queue
+    pub async fn dispatch_event(&self, connection_id: ConnectionId, event: SimulatorEvent) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection = connections
+            .get_mut(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        let mut world = self.ecs_world.write().await;
+        connection.apply_event(&mut world, event)
+    }
 
-        if data.len() < header_end + 1 {
-            return Err(anyhow::anyhow!("Invalid packet format"));
+    /// Neighbouring regions known for a connection, with their addresses and seed capabilities
+    pub async fn neighbours(&self, connection_id: ConnectionId) -> Result<Vec<RegionLink>> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        Ok(connection.region.neighbours().cloned().collect())
+    }
+
+    /// Region a connection's agent is currently in
+    pub async fn current_region(&self, connection_id: ConnectionId) -> Result<Option<RegionLink>> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&connection_id)
+            .ok_or(ProtocolError::ConnectionNotFound { connection_id })?;
+        Ok(connection.region.current().cloned())
+    }
+
+    /// Parse an LLUDP datagram: header, optional extra header, message number by frequency and
+    /// body, undoing zero-coding and dropping appended acks
+    fn parse_packet(data: &[u8]) -> Result<LLUDPPacket> {
+        let malformed = |reason: &str| ProtocolError::MalformedPacket { reason: reason.to_string() };
+        if data.len() < 7 {
+            return Err(malformed("packet too short").into());
+        }
+
+        let flags = data[0];
+        let sequence = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+        let header_end = 6 + data[5] as usize;
+        let mut end = data.len();
+        if flags & FLAG_ACKS != 0 {
+            let count = data[end - 1] as usize;
+            end = end
+                .checked_sub(1 + count * 4)
+                .filter(|end| *end > header_end)
+                .ok_or_else(|| malformed("appended acks overrun packet"))?;
+        }
+        if end <= header_end {
+            return Err(malformed("extra header overruns packet").into());
         }
 
-        let extra_header = if extra_header_len > 0 {
-            data[6..header_end].to_vec()
+        let extra_header = data[6..header_end].to_vec();
+        let body = if flags & FLAG_ZEROCODED != 0 {
+            Self::zero_decode(&data[header_end..end])
         } else {
-            Vec::new()
+            data[header_end..end].to_vec()
         };
 
-        let message_type_byte = data[header_end];
-        let message_type = self.message_type_from_byte(message_type_byte);
-
-        let payload = data[header_end + 1..].to_vec();
+        let (message_type, id_len) = match body.as_slice() {
+            [0xFF, 0xFF, high, low, ..] => {
+                (LLUDPMessageType::from_id(MessageFrequency::Low, u16::from_be_bytes([*high, *low])), 4)
+            }
+            [0xFF, id, ..] if *id != 0xFF => (LLUDPMessageType::from_id(MessageFrequency::Medium, *id as u16), 2),
+            [id, ..] if *id != 0xFF => (LLUDPMessageType::from_id(MessageFrequency::High, *id as u16), 1),
+            _ => return Err(malformed("message number truncated").into()),
+        };
 
         Ok(LLUDPPacket {
             flags,
             sequence,
             extra_header,
             message_type,
-            payload,
+            payload: body[id_len..].to_vec(),
         })
     }
 
-    /// Convert byte to message type
-    fn message_type_from_byte(&self, byte: u8) -> LLUDPMessageType {
-        match byte {
-            3 => LLUDPMessageType::UseCircuitCode,
-            4 => LLUDPMessageType::AgentUpdate,
-            12 => LLUDPMessageType::ObjectUpdate,
-            13 => LLUDPMessageType::ObjectUpdateCompressed,
-            20 => LLUDPMessageType::AgentAnimation,
-            21 => LLUDPMessageType::RequestImage,
-            22 => LLUDPMessageType::ImageData,
-            73 => LLUDPMessageType::FetchInventory,
-            74 => LLUDPMessageType::InventoryDescendents,
-            77 => LLUDPMessageType::ChatFromViewer,
-            139 => LLUDPMessageType::ChatFromSimulator,
-            249 => LLUDPMessageType::CompleteAgentMovement,
-            _ => LLUDPMessageType::Unknown,
+    /// Expand runs of zeroes, sent as a zero byte followed by the run length
+    fn zero_decode(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() * 2);
+        let mut bytes = data.iter();
+        while let Some(&byte) = bytes.next() {
+            if byte == 0 {
+                let run = bytes.next().copied().unwrap_or(1);
+                out.resize(out.len() + run as usize, 0);
+            } else {
+                out.push(byte);
+            }
         }
+        out
     }
 
-    /// Create LLUDP packet bytes
-    fn create_packet(&self, packet: &LLUDPPacket) -> Vec<u8> {
-        let mut data = Vec::new();
+    /// Create LLUDP packet bytes; bodies are sent without zero-coding
+    fn create_packet(packet: &LLUDPPacket) -> Vec<u8> {
+        let mut data = Vec::with_capacity(10 + packet.extra_header.len() + packet.payload.len());
 
-        data.push(packet.flags);
+        data.push(packet.flags & !(FLAG_ZEROCODED | FLAG_ACKS));
         data.extend_from_slice(&packet.sequence.to_be_bytes());
-
-        if !packet.extra_header.is_empty() {
-            data.push(packet.extra_header.len() as u8);
-            data.extend_from_slice(&packet.extra_header);
+        data.push(packet.extra_header.len() as u8);
+        data.extend_from_slice(&packet.extra_header);
+
+        let id = packet.message_type as u16;
+        match packet.message_type.frequency() {
+            MessageFrequency::High => data.push(id as u8),
+            MessageFrequency::Medium => data.extend_from_slice(&[0xFF, id as u8]),
+            MessageFrequency::Low => {
+                data.extend_from_slice(&[0xFF, 0xFF]);
+                data.extend_from_slice(&id.to_be_bytes());
+            }
         }
-
-        data.push(packet.message_type as u8);
         data.extend_from_slice(&packet.payload);
 
         data
@@ -191,7 +422,7 @@ impl ProtocolAdapter for OpenSimAdapter {
 
         // Parse grid URL to get login server address
         let login_url = url::Url::parse(&config.url)?;
-        let host = login_url.host_str().ok_or_else(|| anyhow::anyhow!("Invalid URL"))?;
+        let host = login_url.host_str().ok_or_else(|| ProtocolError::InvalidConfiguration(format!("Grid URL has no host: {}", config.url)))?;
         let port = login_url.port().unwrap_or(9000);
         let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
 
@@ -202,9 +433,12 @@ impl ProtocolAdapter for OpenSimAdapter {
             remote_addr: addr,
             session_id: None,
             agent_id: None,
-            region_id: None,
+            circuit_code: 0,
+            region: RegionTracker::new(self.region_events.clone()),
+            child_circuits: HashMap::new(),
             sequence_number: 1,
             last_ack: 0,
+            outbox: Vec::new(),
         };
 
         let mut connections = self.connections.lock().await;
@@ -228,12 +462,27 @@ impl ProtocolAdapter for OpenSimAdapter {
         Ok(())
     }
 
+    async fn receive(&mut self, connection_id: ConnectionId, data: Vec<u8>) -> Result<()> {
+        self.inbound.lock().await.push_back((connection_id, data));
+        Ok(())
+    }
+
     async fn process_pending_messages(&mut self) -> Result<()> {
-        // In a real implementation, this would process incoming packets
-        // from the network layer and route them to appropriate handlers
+        let pending: Vec<_> = self.inbound.lock().await.drain(..).collect();
+        for (connection_id, data) in pending {
+            // One bad datagram must not hold up the rest
+            if let Err(e) = self.route_datagram(connection_id, &data).await {
+                warn!("Dropping LLUDP datagram from connection {}: {}", connection_id, e);
+            }
+        }
         Ok(())
     }
 
+    async fn take_outgoing(&mut self) -> Vec<OutgoingDatagram> {
+        let mut connections = self.connections.lock().await;
+        connections.values_mut().flat_map(|connection| std::mem::take(&mut connection.outbox)).collect()
+    }
+
     async fn send_message(&mut self, connection_id: ConnectionId, message: &ProtocolMessage) -> Result<()> {
         debug!("Sending message to OpenSim connection {}: {}", connection_id, message.message_type);
         // Implementation would serialize the message and send via network layer
@@ -258,13 +507,11 @@ impl MessageHandler for UseCircuitCodeHandler {
     ) -> Result<Vec<LLUDPPacket>> {
         info!("Handling UseCircuitCode message");
 
-        // Parse circuit code from payload
-        if packet.payload.len() >= 16 {
-            // Extract session and agent IDs from payload
-            // This is a simplified implementation
-            connection.session_id = Some(uuid::Uuid::new_v4());
-            connection.agent_id = Some(uuid::Uuid::new_v4());
-        }
+        // CircuitCode: Code U32, SessionID, ID
+        let mut reader = PayloadCursor::new(&packet.payload);
+        connection.circuit_code = reader.u32()?;
+        connection.session_id = Some(reader.uuid()?);
+        connection.agent_id = Some(reader.uuid()?);
 
         Ok(vec![])
     }
@@ -352,6 +599,337 @@ impl MessageHandler for ChatHandler {
     }
 }
 
+struct EnableSimulatorHandler;
+
+impl MessageHandler for EnableSimulatorHandler {
+    fn handle_message(
+        &self,
+        packet: &LLUDPPacket,
+        connection: &mut OpenSimConnection,
+        world: &mut World,
+    ) -> Result<Vec<LLUDPPacket>> {
+        // SimulatorInfo: Handle U64, IP IPADDR, Port IPPORT (big-endian)
+        let mut reader = PayloadCursor::new(&packet.payload);
+        let region_handle = reader.u64()?;
+        let sim_addr = SocketAddr::new(reader.ip()?.into(), reader.port()?);
+
+        connection.apply_event(world, SimulatorEvent::EnableSimulator { region_handle, sim_addr })?;
+        Ok(vec![])
+    }
+}
+
+struct TeleportStatusHandler;
+
+impl MessageHandler for TeleportStatusHandler {
+    fn handle_message(
+        &self,
+        packet: &LLUDPPacket,
+        connection: &mut OpenSimConnection,
+        _world: &mut World,
+    ) -> Result<Vec<LLUDPPacket>> {
+        let mut reader = PayloadCursor::new(&packet.payload);
+
+        match packet.message_type {
+            LLUDPMessageType::TeleportStart => {
+                // Teleports requested by the simulator (e.g. god or script) start here
+                if !connection.region.is_teleporting() {
+                    connection.region.begin_teleport("requested by simulator");
+                }
+            }
+            LLUDPMessageType::TeleportProgress => {
+                reader.skip(16 + 4)?; // AgentID, TeleportFlags
+                let message = reader.string1()?;
+                connection.region.teleport_progress(message);
+            }
+            LLUDPMessageType::TeleportFailed => {
+                reader.skip(16)?; // AgentID
+                let reason = reader.string1()?;
+                connection.region.fail_teleport(reason);
+            }
+            _ => {}
+        }
+
+        Ok(vec![])
+    }
+}
+
+struct RegionTransitionHandler;
+
+impl MessageHandler for RegionTransitionHandler {
+    fn handle_message(
+        &self,
+        packet: &LLUDPPacket,
+        connection: &mut OpenSimConnection,
+        world: &mut World,
+    ) -> Result<Vec<LLUDPPacket>> {
+        let mut reader = PayloadCursor::new(&packet.payload);
+
+        let event = match packet.message_type {
+            LLUDPMessageType::TeleportFinish => {
+                // Info: AgentID, LocationID, SimIP, SimPort, RegionHandle, SeedCapability, SimAccess, TeleportFlags
+                reader.skip(16 + 4)?;
+                let sim_addr = SocketAddr::new(reader.ip()?.into(), reader.port()?);
+                let region_handle = reader.u64()?;
+                let seed_capability = reader.string2()?;
+                SimulatorEvent::TeleportFinish { region_handle, sim_addr, seed_capability }
+            }
+            LLUDPMessageType::CrossedRegion => {
+                // AgentData: AgentID, SessionID; RegionData: SimIP, SimPort, RegionHandle, SeedCapability; Info: Position, LookAt
+                reader.skip(32)?;
+                let sim_addr = SocketAddr::new(reader.ip()?.into(), reader.port()?);
+                let region_handle = reader.u64()?;
+                let seed_capability = reader.string2()?;
+                let position = [reader.f32()?, reader.f32()?, reader.f32()?];
+                SimulatorEvent::CrossedRegion { region_handle, sim_addr, seed_capability, position }
+            }
+            _ => return Ok(vec![]),
+        };
+
+        connection.apply_event(world, event)?;
+        Ok(vec![])
+    }
+}
+
+impl OpenSimConnection {
+    /// Apply a region-level event, whether it arrived over LLUDP or the event queue
+    fn apply_event(&mut self, world: &mut World, event: SimulatorEvent) -> Result<()> {
+        let (kind, region_handle, sim_addr, seed_capability, position) = match event {
+            SimulatorEvent::EnableSimulator { region_handle, sim_addr } => {
+                if let std::collections::hash_map::Entry::Vacant(entry) = self.child_circuits.entry(region_handle) {
+                    info!("Opening child circuit to neighbour {} at {}", region_handle, sim_addr);
+                    entry.insert(ChildCircuit { region_handle, remote_addr: sim_addr, next_sequence: 1 });
+                    if let Some(payload) = self.use_circuit_code_payload() {
+                        self.send_child(region_handle, LLUDPMessageType::UseCircuitCode, payload);
+                    }
+                }
+
+                let mut region = self.region_for_handle(region_handle);
+                region.sim_addr = Some(sim_addr);
+                self.region.add_neighbour(region);
+                return Ok(());
+            }
+            SimulatorEvent::EstablishAgentCommunication { sim_addr, seed_capability } => {
+                // Only the address identifies the neighbour the child agent was established in
+                let neighbour = self.region.neighbours().find(|region| region.sim_addr == Some(sim_addr)).cloned();
+                match neighbour {
+                    Some(mut region) => {
+                        debug!("Child agent established in {} with seed {}", region.name, seed_capability);
+                        region.seed_capability = Some(seed_capability).filter(|seed| !seed.is_empty());
+                        self.region.add_neighbour(region);
+                    }
+                    None => warn!("EstablishAgentCommunication for unknown simulator {}", sim_addr),
+                }
+                return Ok(());
+            }
+            SimulatorEvent::TeleportFinish { region_handle, sim_addr, seed_capability } => {
+                (TransitionKind::Teleport, region_handle, sim_addr, seed_capability, None)
+            }
+            SimulatorEvent::CrossedRegion { region_handle, sim_addr, seed_capability, position } => {
+                (TransitionKind::Crossing, region_handle, sim_addr, seed_capability, Some(position))
+            }
+        };
+
+        let mut destination = self.region_for_handle(region_handle);
+        destination.sim_addr = Some(sim_addr);
+        destination.seed_capability = Some(seed_capability).filter(|seed| !seed.is_empty());
+
+        // The destination simulator becomes the root circuit, keeping the child circuit's sequence
+        let child = self.child_circuits.remove(&region_handle);
+        let previous_addr = std::mem::replace(&mut self.remote_addr, sim_addr);
+        let previous_sequence =
+            std::mem::replace(&mut self.sequence_number, child.as_ref().map_or(1, |child| child.next_sequence));
+        let previous_handle = self.region.current().map(|region| region.handle());
+        match (kind, previous_handle) {
+            (TransitionKind::Crossing, Some(previous_handle)) => {
+                self.child_circuits.insert(previous_handle, ChildCircuit {
+                    region_handle: previous_handle,
+                    remote_addr: previous_addr,
+                    next_sequence: previous_sequence,
+                });
+            }
+            _ => self.child_circuits.clear(),
+        }
+
+        let avatar = world
+            .query::<OpenSimAgent>()
+            .find(|(_, agent)| agent.connection_id == self.id)
+            .map(|(entity, _)| entity);
+        self.region.complete_transition(world, kind, destination, avatar, position)?;
+
+        // Hand the agent over: open the circuit unless it was already a child, then complete the
+        // movement and tell the new root where the avatar is
+        if child.is_none() {
+            if let Some(payload) = self.use_circuit_code_payload() {
+                self.send_root(LLUDPPacket::reliable(LLUDPMessageType::UseCircuitCode, payload));
+            }
+        }
+        if let Some(payload) = self.complete_agent_movement_payload() {
+            self.send_root(LLUDPPacket::reliable(LLUDPMessageType::CompleteAgentMovement, payload));
+        }
+        let transform = avatar.and_then(|avatar| world.get_component::<Transform>(avatar)).cloned();
+        if let Some(payload) = transform.and_then(|transform| self.agent_update_payload(&transform)) {
+            self.send_root(LLUDPPacket::unreliable(LLUDPMessageType::AgentUpdate, payload));
+        }
+        Ok(())
+    }
+
+    /// Queue a packet on the root circuit
+    fn send_root(&mut self, mut packet: LLUDPPacket) {
+        packet.sequence = self.sequence_number;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        self.outbox.push(OutgoingDatagram {
+            connection_id: self.id,
+            remote_addr: self.remote_addr,
+            data: OpenSimAdapter::create_packet(&packet),
+        });
+    }
+
+    /// Queue a reliable packet on the child circuit to a neighbour
+    fn send_child(&mut self, region_handle: u64, message_type: LLUDPMessageType, payload: Vec<u8>) {
+        let Some(child) = self.child_circuits.get_mut(&region_handle) else {
+            return;
+        };
+        let mut packet = LLUDPPacket::reliable(message_type, payload);
+        packet.sequence = child.next_sequence;
+        child.next_sequence = child.next_sequence.wrapping_add(1);
+        self.outbox.push(OutgoingDatagram {
+            connection_id: self.id,
+            remote_addr: child.remote_addr,
+            data: OpenSimAdapter::create_packet(&packet),
+        });
+    }
+
+    /// Agent and session ids from login, needed by every circuit-level message
+    fn identity(&self) -> Option<(uuid::Uuid, uuid::Uuid)> {
+        match (self.agent_id, self.session_id) {
+            (Some(agent_id), Some(session_id)) => Some((agent_id, session_id)),
+            _ => {
+                warn!("Connection {} has no agent session yet, not signalling simulators", self.id);
+                None
+            }
+        }
+    }
+
+    /// UseCircuitCode body: CircuitCode { Code, SessionID, ID }
+    fn use_circuit_code_payload(&self) -> Option<Vec<u8>> {
+        let (agent_id, session_id) = self.identity()?;
+        let mut payload = Vec::with_capacity(36);
+        payload.extend_from_slice(&self.circuit_code.to_le_bytes());
+        payload.extend_from_slice(session_id.as_bytes());
+        payload.extend_from_slice(agent_id.as_bytes());
+        Some(payload)
+    }
+
+    /// CompleteAgentMovement body: AgentData { AgentID, SessionID, CircuitCode }
+    fn complete_agent_movement_payload(&self) -> Option<Vec<u8>> {
+        let (agent_id, session_id) = self.identity()?;
+        let mut payload = Vec::with_capacity(36);
+        payload.extend_from_slice(agent_id.as_bytes());
+        payload.extend_from_slice(session_id.as_bytes());
+        payload.extend_from_slice(&self.circuit_code.to_le_bytes());
+        Some(payload)
+    }
+
+    /// AgentUpdate body carrying the avatar's rotation, with the camera at the avatar
+    fn agent_update_payload(&self, transform: &Transform) -> Option<Vec<u8>> {
+        let (agent_id, session_id) = self.identity()?;
+        let mut payload = Vec::with_capacity(114);
+        payload.extend_from_slice(agent_id.as_bytes());
+        payload.extend_from_slice(session_id.as_bytes());
+        // BodyRotation and HeadRotation are packed quaternions: x, y, z with w implied
+        for _ in 0..2 {
+            for component in &transform.rotation[..3] {
+                payload.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        payload.push(0); // State
+        let camera = [transform.position, [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        for vector in camera {
+            for component in vector {
+                payload.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        payload.extend_from_slice(&AGENT_DRAW_DISTANCE.to_le_bytes()); // Far
+        payload.extend_from_slice(&0u32.to_le_bytes()); // ControlFlags
+        payload.push(0); // Flags
+        Some(payload)
+    }
+
+    /// Known neighbour with this handle, or a stand-in link for it
+    fn region_for_handle(&self, handle: u64) -> RegionLink {
+        self.region
+            .neighbour_by_handle(handle)
+            .cloned()
+            .unwrap_or_else(|| RegionLink::from_handle(RegionLink::id_for_handle(handle), format!("Region {}", handle), handle))
+    }
+}
+
+/// Little-endian cursor over an LLUDP payload, for the fields the handlers need
+struct PayloadCursor<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PayloadCursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset + len;
+        if end > self.data.len() {
+            return Err(ProtocolError::MalformedPacket { reason: format!("payload truncated at byte {}", self.offset) }.into());
+        }
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn uuid(&mut self) -> Result<uuid::Uuid> {
+        Ok(uuid::Uuid::from_slice(self.take(16)?)?)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn ip(&mut self) -> Result<std::net::Ipv4Addr> {
+        let bytes: [u8; 4] = self.take(4)?.try_into()?;
+        Ok(std::net::Ipv4Addr::from(bytes))
+    }
+
+    fn port(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn string1(&mut self) -> Result<String> {
+        let len = self.take(1)?[0] as usize;
+        Ok(Self::text(self.take(len)?))
+    }
+
+    fn string2(&mut self) -> Result<String> {
+        let len = u16::from_le_bytes(self.take(2)?.try_into()?) as usize;
+        Ok(Self::text(self.take(len)?))
+    }
+
+    fn text(bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }
+}
+
 /// OpenSim agent component
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct OpenSimAgent {
@@ -0,0 +1,342 @@
+// File: crates/storm-protocol-adapters/src/region.rs
+// Region tracking for connected agents: neighbours, teleports and region crossings
+// Keeps ECS coordinates relative to the agent's current region
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use anyhow::Result;
+
+use storm_ecs::{Component, Entity, Transform, World};
+
+/// Default width of a region in meters
+pub const DEFAULT_REGION_SIZE: f32 = 256.0;
+
+/// Default distance beyond the current region's edges that entities are kept for
+pub const DEFAULT_DRAW_DISTANCE: f32 = 128.0;
+
+/// A region the agent is in or can see into
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionLink {
+    pub region_id: Uuid,
+    pub name: String,
+    /// Global coordinates of the region's south-west corner in meters
+    pub origin: [f64; 2],
+    pub size: [f32; 2],
+    /// Simulator address for LLUDP regions
+    pub sim_addr: Option<SocketAddr>,
+    pub seed_capability: Option<String>,
+}
+
+impl RegionLink {
+    pub fn new(region_id: Uuid, name: impl Into<String>, origin: [f64; 2]) -> Self {
+        Self {
+            region_id,
+            name: name.into(),
+            origin,
+            size: [DEFAULT_REGION_SIZE; 2],
+            sim_addr: None,
+            seed_capability: None,
+        }
+    }
+
+    /// Build a link from an LLUDP region handle (global meters packed as x << 32 | y)
+    pub fn from_handle(region_id: Uuid, name: impl Into<String>, handle: u64) -> Self {
+        Self::new(region_id, name, [(handle >> 32) as f64, (handle & 0xFFFF_FFFF) as f64])
+    }
+
+    /// Stand-in region id for LLUDP messages that only carry a handle
+    pub fn id_for_handle(handle: u64) -> Uuid {
+        Uuid::from_u128(handle as u128)
+    }
+
+    /// LLUDP region handle for this region
+    pub fn handle(&self) -> u64 {
+        ((self.origin[0] as u64) << 32) | (self.origin[1] as u64 & 0xFFFF_FFFF)
+    }
+
+    /// Whether a local position lies inside the region
+    pub fn contains_local(&self, position: [f32; 3]) -> bool {
+        position[0] >= 0.0 && position[0] < self.size[0] && position[1] >= 0.0 && position[1] < self.size[1]
+    }
+
+    /// Horizontal distance from a local position to the region's edges, zero inside
+    pub fn distance_to_edge(&self, position: [f32; 3]) -> f32 {
+        let dx = (-position[0]).max(position[0] - self.size[0]).max(0.0);
+        let dy = (-position[1]).max(position[1] - self.size[1]).max(0.0);
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+/// How the agent moved between regions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransitionKind {
+    /// Walked or flew across a region border into a neighbour
+    Crossing,
+    /// Jumped to an arbitrary region
+    Teleport,
+}
+
+/// Region changes surfaced to the UI
+#[derive(Debug, Clone)]
+pub enum RegionEvent {
+    TeleportStarted { destination: String },
+    TeleportProgress { message: String },
+    TeleportFailed { reason: String },
+    RegionChanged {
+        from: Option<RegionLink>,
+        to: RegionLink,
+        kind: TransitionKind,
+        evicted: usize,
+    },
+    NeighbourAdded { region: RegionLink },
+    NeighbourRemoved { region_id: Uuid },
+}
+
+/// Marks which region an entity was received from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionMember {
+    pub region_id: Uuid,
+}
+
+impl Component for RegionMember {
+    fn type_name() -> &'static str {
+        "RegionMember"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Tracks an agent's current region and neighbours, and applies region transitions to the ECS world
+pub struct RegionTracker {
+    current: Option<RegionLink>,
+    neighbours: HashMap<Uuid, RegionLink>,
+    pending_teleport: Option<String>,
+    draw_distance: f32,
+    events: broadcast::Sender<RegionEvent>,
+}
+
+impl RegionTracker {
+    pub fn new(events: broadcast::Sender<RegionEvent>) -> Self {
+        Self {
+            current: None,
+            neighbours: HashMap::new(),
+            pending_teleport: None,
+            draw_distance: DEFAULT_DRAW_DISTANCE,
+            events,
+        }
+    }
+
+    /// Set how far past the region edges entities are kept
+    pub fn with_draw_distance(mut self, draw_distance: f32) -> Self {
+        self.draw_distance = draw_distance;
+        self
+    }
+
+    pub fn current(&self) -> Option<&RegionLink> {
+        self.current.as_ref()
+    }
+
+    pub fn current_region_id(&self) -> Option<Uuid> {
+        self.current.as_ref().map(|region| region.region_id)
+    }
+
+    pub fn neighbours(&self) -> impl Iterator<Item = &RegionLink> {
+        self.neighbours.values()
+    }
+
+    pub fn neighbour(&self, region_id: Uuid) -> Option<&RegionLink> {
+        self.neighbours.get(&region_id)
+    }
+
+    /// Find a known neighbour by its LLUDP handle
+    pub fn neighbour_by_handle(&self, handle: u64) -> Option<&RegionLink> {
+        self.neighbours.values().find(|region| region.handle() == handle)
+    }
+
+    pub fn is_teleporting(&self) -> bool {
+        self.pending_teleport.is_some()
+    }
+
+    /// Record a neighbouring region the agent can see into
+    pub fn add_neighbour(&mut self, region: RegionLink) {
+        if self.current_region_id() == Some(region.region_id) {
+            return;
+        }
+        debug!("Neighbour region {} at {:?}", region.name, region.origin);
+        self.neighbours.insert(region.region_id, region.clone());
+        self.emit(RegionEvent::NeighbourAdded { region });
+    }
+
+    /// Forget a neighbour and drop its entities
+    pub fn remove_neighbour(&mut self, world: &mut World, region_id: Uuid) -> usize {
+        if self.neighbours.remove(&region_id).is_none() {
+            return 0;
+        }
+        let evicted = Self::evict_where(world, None, |member, _| member.region_id == region_id);
+        self.emit(RegionEvent::NeighbourRemoved { region_id });
+        evicted
+    }
+
+    /// Convert a position local to `region_id` into coordinates local to the current region
+    pub fn to_local(&self, region_id: Uuid, position: [f32; 3]) -> Option<[f32; 3]> {
+        let current = self.current.as_ref()?;
+        if current.region_id == region_id {
+            return Some(position);
+        }
+        let region = self.neighbours.get(&region_id)?;
+        let offset = Self::offset_between(region, current);
+        Some([position[0] + offset[0], position[1] + offset[1], position[2]])
+    }
+
+    /// Tag an entity as belonging to a region
+    pub fn assign(&self, world: &mut World, entity: Entity, region_id: Uuid) {
+        world.add_component(entity, RegionMember { region_id });
+    }
+
+    /// Teleport requested; the region stays current until it completes or fails
+    pub fn begin_teleport(&mut self, destination: impl Into<String>) {
+        let destination = destination.into();
+        info!("Teleport to {} started", destination);
+        self.pending_teleport = Some(destination.clone());
+        self.emit(RegionEvent::TeleportStarted { destination });
+    }
+
+    pub fn teleport_progress(&mut self, message: impl Into<String>) {
+        self.emit(RegionEvent::TeleportProgress { message: message.into() });
+    }
+
+    pub fn fail_teleport(&mut self, reason: impl Into<String>) {
+        let reason = reason.into();
+        warn!("Teleport failed: {}", reason);
+        self.pending_teleport = None;
+        self.emit(RegionEvent::TeleportFailed { reason });
+    }
+
+    /// Move the agent into `destination`
+    ///
+    /// Entity positions are rebased onto the new region's origin and entities from regions
+    /// that are no longer visible are removed. The avatar is placed at `avatar_position` when
+    /// the protocol supplies one, otherwise it is rebased like everything else.
+    /// Returns the number of evicted entities.
+    pub fn complete_transition(
+        &mut self,
+        world: &mut World,
+        kind: TransitionKind,
+        destination: RegionLink,
+        avatar: Option<Entity>,
+        avatar_position: Option<[f32; 3]>,
+    ) -> Result<usize> {
+        let previous = self.current.take();
+        self.pending_teleport = None;
+
+        match kind {
+            TransitionKind::Crossing => {
+                // The region we left becomes a neighbour; the one we entered no longer is
+                self.neighbours.remove(&destination.region_id);
+                if let Some(previous) = &previous {
+                    self.neighbours.insert(previous.region_id, previous.clone());
+                }
+            }
+            TransitionKind::Teleport => {
+                // The destination simulator announces its own neighbours
+                self.neighbours.clear();
+            }
+        }
+
+        let offset = match &previous {
+            Some(previous) => Self::offset_between(previous, &destination),
+            None => [0.0, 0.0],
+        };
+
+        if let Some(avatar) = avatar {
+            world.add_component(avatar, RegionMember { region_id: destination.region_id });
+        }
+
+        let members: Vec<Entity> = world.query::<RegionMember>().map(|(entity, _)| entity).collect();
+        for entity in members {
+            if let Some(transform) = world.get_component_mut::<Transform>(entity) {
+                transform.position[0] += offset[0];
+                transform.position[1] += offset[1];
+            }
+        }
+
+        if let (Some(avatar), Some(position)) = (avatar, avatar_position) {
+            let mut transform = world.get_component::<Transform>(avatar).cloned().unwrap_or_default();
+            transform.position = position;
+            world.add_component(avatar, transform);
+        }
+
+        self.current = Some(destination.clone());
+        let evicted = self.evict_invisible(world, avatar);
+
+        info!(
+            "{:?} into region {} complete, {} entities evicted",
+            kind, destination.name, evicted
+        );
+        self.emit(RegionEvent::RegionChanged {
+            from: previous,
+            to: destination,
+            kind,
+            evicted,
+        });
+        Ok(evicted)
+    }
+
+    /// Remove entities from regions that are not visible or that lie beyond the draw distance
+    pub fn evict_invisible(&self, world: &mut World, keep: Option<Entity>) -> usize {
+        let Some(current) = &self.current else {
+            return 0;
+        };
+        let draw_distance = self.draw_distance;
+        let neighbours = &self.neighbours;
+
+        Self::evict_where(world, keep, |member, position| {
+            let visible = member.region_id == current.region_id || neighbours.contains_key(&member.region_id);
+            let in_range = position.is_none_or(|p| current.distance_to_edge(p) <= draw_distance);
+            !(visible && in_range)
+        })
+    }
+
+    fn evict_where<F>(world: &mut World, keep: Option<Entity>, mut evict: F) -> usize
+    where
+        F: FnMut(&RegionMember, Option<[f32; 3]>) -> bool,
+    {
+        let doomed: Vec<Entity> = world
+            .query::<RegionMember>()
+            .filter(|(entity, _)| Some(*entity) != keep)
+            .filter(|(entity, member)| {
+                let position = world.get_component::<Transform>(*entity).map(|t| t.position);
+                evict(member, position)
+            })
+            .map(|(entity, _)| entity)
+            .collect();
+
+        for entity in &doomed {
+            world.remove_entity(*entity);
+        }
+        doomed.len()
+    }
+
+    /// Offset to add to positions local to `from` to express them relative to `to`
+    fn offset_between(from: &RegionLink, to: &RegionLink) -> [f32; 2] {
+        [
+            (from.origin[0] - to.origin[0]) as f32,
+            (from.origin[1] - to.origin[1]) as f32,
+        ]
+    }
+
+    fn emit(&self, event: RegionEvent) {
+        // No subscribers simply means no UI is listening
+        let _ = self.events.send(event);
+    }
+}
@@ -0,0 +1,47 @@
+// File: crates/storm-rendering/src/error.rs
+// Typed rendering errors with stable codes and retry classification
+
+use thiserror::Error;
+
+use crate::RenderBackend;
+
+/// Rendering failures
+///
+/// Functions in this crate return `anyhow::Result`; these errors travel inside it and can be
+/// recovered with `downcast_ref::<RenderError>()`.
+#[derive(Error, Debug)]
+pub enum RenderError {
+    #[error("{backend:?} backend is not available in this build")]
+    BackendUnavailable { backend: RenderBackend },
+
+    #[error("Graphics device lost: {reason}")]
+    DeviceLost { reason: String },
+
+    #[error("Render surface lost; it must be recreated")]
+    SurfaceLost,
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl RenderError {
+    /// Stable numeric code, in the 6000 range
+    pub fn code(&self) -> u32 {
+        match self {
+            RenderError::BackendUnavailable { .. } => 6001,
+            RenderError::DeviceLost { .. } => 6002,
+            RenderError::SurfaceLost => 6003,
+            RenderError::Other(_) => 6000,
+        }
+    }
+
+    /// A lost device or surface can be recreated for the next frame
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, RenderError::DeviceLost { .. } | RenderError::SurfaceLost)
+    }
+
+    /// Without a backend nothing can be drawn
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, RenderError::BackendUnavailable { .. })
+    }
+}
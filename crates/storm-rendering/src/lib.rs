@@ -3,9 +3,14 @@
 // Supports Metal, Vulkan, WebGL, and software rendering
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tracing::{info, warn};
 use anyhow::Result;
 
+pub mod error;
+
+pub use error::RenderError;
+
 /// Rendering configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RenderConfig {
@@ -41,10 +46,98 @@ pub enum TextureQuality {
     Ultra,
 }
 
+/// Scene-wide lighting supplied by the environment simulation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentLighting {
+    pub sun_direction: [f32; 3],
+    pub sun_color: [f32; 3],
+    pub sun_intensity: f32,
+    pub ambient_color: [f32; 3],
+    pub ambient_intensity: f32,
+    pub fog_density: f32,
+}
+
+impl Default for EnvironmentLighting {
+    fn default() -> Self {
+        Self {
+            sun_direction: [0.0, 0.0, 1.0],
+            sun_color: [1.0, 1.0, 1.0],
+            sun_intensity: 1.0,
+            ambient_color: [0.7, 0.75, 0.85],
+            ambient_intensity: 0.4,
+            fog_density: 0.001,
+        }
+    }
+}
+
+/// Triangulated region terrain, z up, one vertex per height sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainMesh {
+    pub region_handle: u64,
+    /// Revision of the heightfield the mesh was built from
+    pub revision: u64,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl TerrainMesh {
+    /// Build a grid mesh from row-major heights sampled every meter
+    pub fn from_heights(region_handle: u64, revision: u64, width: u32, depth: u32, heights: &[f32]) -> Self {
+        let height = |x: u32, y: u32| heights[(y.min(depth - 1) * width + x.min(width - 1)) as usize];
+        let mut positions = Vec::with_capacity(heights.len());
+        let mut normals = Vec::with_capacity(heights.len());
+        for y in 0..depth {
+            for x in 0..width {
+                positions.push([x as f32, y as f32, height(x, y)]);
+                // Central differences, one-sided at the edges
+                let dx = height(x + 1, y) - height(x.saturating_sub(1), y);
+                let dy = height(x, y + 1) - height(x, y.saturating_sub(1));
+                let normal = [-dx, -dy, 2.0];
+                let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+                normals.push([normal[0] / length, normal[1] / length, normal[2] / length]);
+            }
+        }
+
+        let mut indices = Vec::with_capacity((width.saturating_sub(1) * depth.saturating_sub(1) * 6) as usize);
+        for y in 0..depth.saturating_sub(1) {
+            for x in 0..width.saturating_sub(1) {
+                let corner = y * width + x;
+                indices.extend_from_slice(&[corner, corner + 1, corner + width, corner + 1, corner + width + 1, corner + width]);
+            }
+        }
+
+        Self {
+            region_handle,
+            revision,
+            positions,
+            normals,
+            indices,
+        }
+    }
+}
+
+/// Avatar to draw: baked skin and clothing plus the shape parameters that morph the base mesh
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AvatarModel {
+    pub entity_id: u64,
+    /// Revision of the appearance the model was built from
+    pub revision: u64,
+    /// Baked texture ids for head, upper body, lower body, eyes, skirt and hair
+    pub baked_textures: [Option<u128>; 6],
+    /// Shape parameters from 0 to 1
+    pub visual_params: Vec<f32>,
+    pub size: [f32; 3],
+    pub hover_height: f32,
+}
+
 /// Main rendering pipeline
 pub struct RenderPipeline {
     config: RenderConfig,
     backend: Box<dyn RenderBackendTrait>,
+    environment: std::sync::RwLock<EnvironmentLighting>,
+    terrain: std::sync::RwLock<HashMap<u64, TerrainMesh>>,
+    avatars: std::sync::RwLock<HashMap<u64, AvatarModel>>,
 }
 
 impl RenderPipeline {
@@ -59,7 +152,7 @@ impl RenderPipeline {
                 }
                 #[cfg(not(feature = "metal"))]
                 {
-                    return Err(anyhow::anyhow!("Metal backend not compiled"));
+                    return Err(RenderError::BackendUnavailable { backend: RenderBackend::Metal }.into());
                 }
             }
             RenderBackend::Vulkan => {
@@ -69,7 +162,7 @@ impl RenderPipeline {
                 }
                 #[cfg(not(feature = "vulkan"))]
                 {
-                    return Err(anyhow::anyhow!("Vulkan backend not compiled"));
+                    return Err(RenderError::BackendUnavailable { backend: RenderBackend::Vulkan }.into());
                 }
             }
             RenderBackend::WebGL => {
@@ -79,7 +172,7 @@ impl RenderPipeline {
                 }
                 #[cfg(not(feature = "wasm"))]
                 {
-                    return Err(anyhow::anyhow!("WebGL backend not compiled"));
+                    return Err(RenderError::BackendUnavailable { backend: RenderBackend::WebGL }.into());
                 }
             }
             RenderBackend::Software => {
@@ -90,6 +183,9 @@ impl RenderPipeline {
         Ok(Self {
             config: config.clone(),
             backend,
+            environment: std::sync::RwLock::new(EnvironmentLighting::default()),
+            terrain: std::sync::RwLock::new(HashMap::new()),
+            avatars: std::sync::RwLock::new(HashMap::new()),
         })
     }
 
@@ -97,6 +193,66 @@ impl RenderPipeline {
         self.backend.render(delta_time)
     }
 
+    /// Update sun, ambient and fog parameters used for the next frames
+    pub fn set_environment_lighting(&self, lighting: EnvironmentLighting) {
+        if let Ok(mut environment) = self.environment.write() {
+            *environment = lighting;
+        }
+    }
+
+    pub fn environment_lighting(&self) -> EnvironmentLighting {
+        self.environment.read().map(|environment| *environment).unwrap_or_default()
+    }
+
+    /// Replace the terrain mesh of a region
+    pub fn update_terrain(&self, mesh: TerrainMesh) {
+        if let Ok(mut terrain) = self.terrain.write() {
+            terrain.insert(mesh.region_handle, mesh);
+        }
+    }
+
+    pub fn remove_terrain(&self, region_handle: u64) {
+        if let Ok(mut terrain) = self.terrain.write() {
+            terrain.remove(&region_handle);
+        }
+    }
+
+    /// Revision of the heightfield behind a region's terrain mesh
+    pub fn terrain_revision(&self, region_handle: u64) -> Option<u64> {
+        self.terrain.read().ok()?.get(&region_handle).map(|mesh| mesh.revision)
+    }
+
+    pub fn terrain_mesh(&self, region_handle: u64) -> Option<TerrainMesh> {
+        self.terrain.read().ok()?.get(&region_handle).cloned()
+    }
+
+    /// Replace the model of an avatar entity
+    pub fn update_avatar(&self, model: AvatarModel) {
+        if let Ok(mut avatars) = self.avatars.write() {
+            avatars.insert(model.entity_id, model);
+        }
+    }
+
+    pub fn remove_avatar(&self, entity_id: u64) {
+        if let Ok(mut avatars) = self.avatars.write() {
+            avatars.remove(&entity_id);
+        }
+    }
+
+    /// Revision of the appearance behind an avatar's model
+    pub fn avatar_revision(&self, entity_id: u64) -> Option<u64> {
+        self.avatars.read().ok()?.get(&entity_id).map(|model| model.revision)
+    }
+
+    pub fn avatar_model(&self, entity_id: u64) -> Option<AvatarModel> {
+        self.avatars.read().ok()?.get(&entity_id).cloned()
+    }
+
+    /// Entities that currently have an avatar model
+    pub fn avatar_entities(&self) -> Vec<u64> {
+        self.avatars.read().map(|avatars| avatars.keys().copied().collect()).unwrap_or_default()
+    }
+
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down rendering pipeline");
         self.backend.shutdown()
@@ -213,4 +369,32 @@ mod tests {
         let pipeline = RenderPipeline::new(&config).await;
         assert!(pipeline.is_ok());
     }
-}
\ No newline at end of file
+
+    #[cfg(not(feature = "metal"))]
+    #[tokio::test]
+    async fn test_missing_backend_is_typed() {
+        let config = RenderConfig {
+            backend: RenderBackend::Metal,
+            vsync_enabled: true,
+            max_fps: 60,
+            shadow_quality: ShadowQuality::Medium,
+            texture_quality: TextureQuality::High,
+        };
+
+        let err = RenderPipeline::new(&config).await.err().unwrap();
+        let err = err.downcast_ref::<RenderError>().unwrap();
+        assert_eq!(err.code(), 6001);
+        assert!(err.is_fatal() && !err.is_retryable());
+    }
+
+    #[test]
+    fn test_terrain_mesh_from_heights() {
+        let mesh = TerrainMesh::from_heights(3, 5, 3, 2, &[0.0, 0.0, 0.0, 0.0, 2.0, 0.0]);
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.positions[4], [1.0, 1.0, 2.0]);
+        assert_eq!(mesh.indices, vec![0, 1, 3, 1, 4, 3, 1, 2, 4, 2, 5, 4]);
+        // Flat corners point straight up, slopes lean away from the bump
+        assert_eq!(mesh.normals[0], [0.0, 0.0, 1.0]);
+        assert!(mesh.normals[3][0] < 0.0 && mesh.normals[5][0] > 0.0);
+    }
+}
@@ -0,0 +1,85 @@
+// File: crates/storm-server/src/bin/netem.rs
+// storm-netem: UDP proxy that impairs traffic between viewers and a region for manual testing
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use clap::Parser;
+use tracing::info;
+use anyhow::Result;
+
+use storm_networking::{LinkConditions, UdpProxy};
+
+#[derive(Parser)]
+#[command(name = "storm-netem")]
+#[command(about = "Impair LLUDP traffic between viewers and a region")]
+struct Cli {
+    /// Address viewers connect to
+    #[arg(long, default_value = "0.0.0.0:9100")]
+    listen: SocketAddr,
+
+    /// Region the traffic is forwarded to
+    #[arg(long, default_value = "127.0.0.1:9000")]
+    upstream: SocketAddr,
+
+    /// One-way delay in milliseconds, applied in each direction
+    #[arg(long, default_value_t = 0)]
+    latency_ms: u64,
+
+    /// Random extra delay in milliseconds, up to this much
+    #[arg(long, default_value_t = 0)]
+    jitter_ms: u64,
+
+    /// Packet loss probability, 0.0 to 1.0
+    #[arg(long, default_value_t = 0.0)]
+    loss: f64,
+
+    /// Packet duplication probability
+    #[arg(long, default_value_t = 0.0)]
+    duplicate: f64,
+
+    /// Probability a packet is held back so later ones overtake it
+    #[arg(long, default_value_t = 0.0)]
+    reorder: f64,
+
+    /// Link rate in kilobits per second, per direction
+    #[arg(long)]
+    bandwidth_kbps: Option<u64>,
+
+    /// Drop datagrams larger than this many bytes
+    #[arg(long)]
+    mtu: Option<usize>,
+
+    /// Seed for the random decisions
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
+
+    let conditions = LinkConditions {
+        latency: Duration::from_millis(cli.latency_ms),
+        jitter: Duration::from_millis(cli.jitter_ms),
+        loss: cli.loss,
+        duplicate: cli.duplicate,
+        reorder: cli.reorder,
+        bandwidth_bps: cli.bandwidth_kbps.map(|kbps| kbps * 1000),
+        mtu: cli.mtu,
+        seed: cli.seed,
+        ..LinkConditions::ideal()
+    };
+    info!("Link conditions: {:?}", conditions);
+
+    let proxy = UdpProxy::bind(cli.listen, cli.upstream, conditions.clone(), conditions).await?;
+    info!("storm-netem listening on {}, forwarding to {}", proxy.local_addr()?, cli.upstream);
+
+    tokio::select! {
+        result = proxy.run() => result,
+        _ = tokio::signal::ctrl_c() => {
+            info!("Shutting down storm-netem");
+            Ok(())
+        }
+    }
+}
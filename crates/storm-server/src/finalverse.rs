@@ -0,0 +1,158 @@
+// File: crates/storm-server/src/finalverse.rs
+// Finalverse front end: WebSocket clients speaking JSON FinalverseMessage
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use anyhow::Result;
+
+use storm_finalverse::FinalverseMessage;
+
+use crate::region::{AgentEndpoint, Region};
+
+/// Accepts WebSocket clients and feeds their messages into the region
+pub struct FinalverseFrontEnd {
+    region: Arc<Region>,
+}
+
+impl FinalverseFrontEnd {
+    pub fn new(region: Arc<Region>) -> Self {
+        Self { region }
+    }
+
+    /// Bind the WebSocket listener and spawn the accept loop
+    pub async fn start(self: Arc<Self>, addr: SocketAddr) -> Result<(SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        info!("Finalverse WebSocket endpoint listening on {}", local_addr);
+
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let front_end = self.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = front_end.handle_client(stream, peer).await {
+                                debug!("Finalverse client {} disconnected: {}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Finalverse accept failed: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok((local_addr, task))
+    }
+
+    async fn handle_client(&self, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        let ws = tokio_tungstenite::accept_async(stream).await?;
+        let (mut sink, mut stream) = ws.split();
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<FinalverseMessage>();
+        debug!("Finalverse client connected from {}", peer);
+
+        let writer = tokio::spawn(async move {
+            while let Some(message) = outbound_rx.recv().await {
+                let text = match serde_json::to_string(&message) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        warn!("Failed to encode Finalverse message: {}", e);
+                        continue;
+                    }
+                };
+                if sink.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        let mut agent_id: Option<Uuid> = None;
+        let result = async {
+            while let Some(frame) = stream.next().await {
+                let text = match frame? {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+
+                let message: FinalverseMessage = match serde_json::from_str(&text) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("Invalid Finalverse message from {}: {}", peer, e);
+                        continue;
+                    }
+                };
+                self.handle_message(message, &mut agent_id, &outbound).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        }
+        .await;
+
+        if let Some(agent_id) = agent_id {
+            self.region.remove_agent(agent_id).await;
+        }
+        drop(outbound);
+        let _ = writer.await;
+        result
+    }
+
+    async fn handle_message(
+        &self,
+        message: FinalverseMessage,
+        agent_id: &mut Option<Uuid>,
+        outbound: &mpsc::UnboundedSender<FinalverseMessage>,
+    ) -> Result<()> {
+        match (message, *agent_id) {
+            (FinalverseMessage::Login { username, token: _ }, None) => {
+                // Tokens are not verified until the region sits behind an auth service
+                let id = Uuid::new_v4();
+                let session_id = Uuid::new_v4();
+                self.region.add_agent(
+                    id,
+                    session_id,
+                    username,
+                    AgentEndpoint::Finalverse { outbound: outbound.clone() },
+                ).await;
+                *agent_id = Some(id);
+
+                self.region.send_to_finalverse_agent(id, FinalverseMessage::LoginResponse {
+                    success: true,
+                    session_id: Some(session_id.to_string()),
+                }).await?;
+                self.region.send_full_scene(id).await
+            }
+            (FinalverseMessage::Login { .. }, Some(_)) => {
+                warn!("Ignoring repeated login on an authenticated connection");
+                Ok(())
+            }
+            (FinalverseMessage::Movement { position, rotation }, Some(id)) => {
+                self.region.update_agent_transform(id, Some(position), Some(rotation)).await;
+                Ok(())
+            }
+            (FinalverseMessage::Chat { message, channel: _ }, Some(id)) => {
+                let name = self.region.agent_name(id).await.unwrap_or_default();
+                self.region.relay_chat(id, &name, &message, 0).await;
+                Ok(())
+            }
+            (other, Some(_)) => {
+                debug!("Ignoring unhandled Finalverse message {:?}", other);
+                Ok(())
+            }
+            (_, None) => {
+                outbound
+                    .send(FinalverseMessage::LoginResponse { success: false, session_id: None })
+                    .map_err(|_| anyhow::anyhow!("Finalverse client went away"))?;
+                Ok(())
+            }
+        }
+    }
+}
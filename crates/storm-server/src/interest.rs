@@ -0,0 +1,290 @@
+// File: crates/storm-server/src/interest.rs
+// Area-of-interest filtering: which objects each agent is told about, how often and in what order
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use storm_ecs::EntityId;
+
+use crate::SceneObject;
+
+/// Objects within `radius` meters are updated at most once per `interval`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DistanceBand {
+    pub radius: f32,
+    pub interval: Duration,
+}
+
+/// Interest management tuning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterestConfig {
+    /// Bands by increasing radius; objects beyond the last one are out of view
+    pub bands: Vec<DistanceBand>,
+    /// Extra distance an object must move past the last band before it is removed, so
+    /// objects on the edge do not flicker in and out
+    pub hysteresis: f32,
+    /// Spatial index cell size in meters
+    pub cell_size: f32,
+    /// Most object updates sent to one agent per tick; removals are not counted
+    pub max_updates_per_tick: usize,
+    /// Priority multiplier for objects within 60 degrees of where the agent faces
+    pub forward_weight: f32,
+    /// Priority multiplier for avatars
+    pub avatar_weight: f32,
+}
+
+impl Default for InterestConfig {
+    fn default() -> Self {
+        Self {
+            bands: vec![
+                DistanceBand { radius: 32.0, interval: Duration::ZERO },
+                DistanceBand { radius: 96.0, interval: Duration::from_millis(500) },
+                DistanceBand { radius: 256.0, interval: Duration::from_secs(2) },
+            ],
+            hysteresis: 8.0,
+            cell_size: 32.0,
+            max_updates_per_tick: 64,
+            forward_weight: 2.0,
+            avatar_weight: 4.0,
+        }
+    }
+}
+
+impl InterestConfig {
+    fn view_radius(&self) -> f32 {
+        self.bands.last().map(|band| band.radius).unwrap_or(0.0)
+    }
+
+    /// Band an object at `distance` falls in, if any
+    fn band(&self, distance: f32) -> Option<&DistanceBand> {
+        self.bands.iter().find(|band| distance <= band.radius)
+    }
+}
+
+/// Indexed positions inside one grid cell
+type Cell = Vec<(usize, [f32; 3])>;
+
+/// Uniform grid over the horizontal plane, holding indices into a slice of positions
+#[derive(Debug)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Cell>,
+}
+
+impl SpatialGrid {
+    pub fn build(cell_size: f32, positions: impl IntoIterator<Item = [f32; 3]>) -> Self {
+        let mut grid = Self {
+            cell_size: cell_size.max(1.0),
+            cells: HashMap::new(),
+        };
+        for (index, position) in positions.into_iter().enumerate() {
+            let cell = grid.cell(position[0], position[1]);
+            grid.cells.entry(cell).or_default().push((index, position));
+        }
+        grid
+    }
+
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+
+    /// Indices of positions within `radius` of `center`, with their distances
+    pub fn query(&self, center: [f32; 3], radius: f32) -> Vec<(usize, f32)> {
+        let (min_x, min_y) = self.cell(center[0] - radius, center[1] - radius);
+        let (max_x, max_y) = self.cell(center[0] + radius, center[1] + radius);
+
+        let mut found = Vec::new();
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                let Some(cell) = self.cells.get(&(x, y)) else {
+                    continue;
+                };
+                for (index, position) in cell {
+                    let distance = distance(center, *position);
+                    if distance <= radius {
+                        found.push((*index, distance));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Where an agent is looking from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewer {
+    pub agent_id: Uuid,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+impl Viewer {
+    /// Unit vector the agent faces; avatars look along their local +X axis
+    fn forward(&self) -> [f32; 3] {
+        let [x, y, z, w] = self.rotation;
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z), 2.0 * (x * z - w * y)]
+    }
+}
+
+/// What one agent should be sent this tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterestUpdate {
+    pub agent_id: Uuid,
+    /// New or changed objects, most important first
+    pub updates: Vec<SceneObject>,
+    /// Objects deleted or out of view, as last sent
+    pub removals: Vec<SceneObject>,
+}
+
+impl InterestUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty() && self.removals.is_empty()
+    }
+}
+
+struct KnownObject {
+    object: SceneObject,
+    sent_at: Instant,
+}
+
+/// Tracks what each agent has been told and decides what to send next
+pub struct InterestManager {
+    config: InterestConfig,
+    known: HashMap<Uuid, HashMap<EntityId, KnownObject>>,
+}
+
+impl InterestManager {
+    pub fn new(config: InterestConfig) -> Self {
+        Self {
+            config,
+            known: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &InterestConfig {
+        &self.config
+    }
+
+    /// Number of objects an agent currently knows about
+    pub fn known_count(&self, agent_id: Uuid) -> usize {
+        self.known.get(&agent_id).map(HashMap::len).unwrap_or(0)
+    }
+
+    /// Forget an agent, e.g. when it leaves
+    pub fn remove_viewer(&mut self, agent_id: Uuid) {
+        self.known.remove(&agent_id);
+    }
+
+    /// Everything in view of a newly arrived agent, ignoring the per-tick budget
+    pub fn initial_view(&mut self, viewer: &Viewer, objects: &[SceneObject], now: Instant) -> InterestUpdate {
+        self.known.remove(&viewer.agent_id);
+        let grid = SpatialGrid::build(self.config.cell_size, objects.iter().map(|object| object.position));
+        self.update_viewer(viewer, objects, &grid, now, usize::MAX)
+    }
+
+    /// Work out each viewer's updates for this tick
+    pub fn update(&mut self, objects: &[SceneObject], viewers: &[Viewer], now: Instant) -> Vec<InterestUpdate> {
+        let grid = SpatialGrid::build(self.config.cell_size, objects.iter().map(|object| object.position));
+        let budget = self.config.max_updates_per_tick;
+        viewers
+            .iter()
+            .map(|viewer| self.update_viewer(viewer, objects, &grid, now, budget))
+            .filter(|update| !update.is_empty())
+            .collect()
+    }
+
+    fn update_viewer(
+        &mut self,
+        viewer: &Viewer,
+        objects: &[SceneObject],
+        grid: &SpatialGrid,
+        now: Instant,
+        budget: usize,
+    ) -> InterestUpdate {
+        let config = &self.config;
+        let known = self.known.entry(viewer.agent_id).or_default();
+        let forward = viewer.forward();
+        let outer = config.view_radius();
+
+        let mut in_range = std::collections::HashSet::new();
+        let mut due: Vec<(f32, usize)> = Vec::new();
+        for (index, distance) in grid.query(viewer.position, outer + config.hysteresis) {
+            let object = &objects[index];
+            let previous = known.get(&object.entity);
+            // Past the last band only objects already in view stay, at the slowest rate
+            let band = match (config.band(distance), previous) {
+                (Some(band), _) => band,
+                (None, Some(_)) => config.bands.last().expect("objects in range imply a band"),
+                (None, None) => continue,
+            };
+            in_range.insert(object.entity);
+
+            let waited = match previous {
+                Some(previous) if previous.object == *object => continue,
+                Some(previous) => match now.saturating_duration_since(previous.sent_at).checked_sub(band.interval) {
+                    Some(overdue) => Some(overdue),
+                    None => continue,
+                },
+                None => None,
+            };
+
+            let mut score = object.scale.iter().cloned().fold(0.1, f32::max) / distance.max(1.0);
+            if distance > 0.0 {
+                let direction = [
+                    (object.position[0] - viewer.position[0]) / distance,
+                    (object.position[1] - viewer.position[1]) / distance,
+                    (object.position[2] - viewer.position[2]) / distance,
+                ];
+                let facing = direction[0] * forward[0] + direction[1] * forward[1] + direction[2] * forward[2];
+                if facing >= 0.5 {
+                    score *= config.forward_weight;
+                }
+            }
+            if object.avatar.is_some() {
+                score *= config.avatar_weight;
+            }
+            score *= match waited {
+                // Objects coming into view matter most; waiting updates age up so none starve
+                None => 2.0,
+                Some(overdue) => 1.0 + overdue.as_secs_f32(),
+            };
+            due.push((score, index));
+        }
+
+        let mut removals: Vec<SceneObject> = known
+            .iter()
+            .filter(|(entity, _)| !in_range.contains(entity))
+            .map(|(_, previous)| previous.object.clone())
+            .collect();
+        removals.sort_by_key(|object| object.entity);
+        for object in &removals {
+            known.remove(&object.entity);
+        }
+
+        due.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        let updates: Vec<SceneObject> = due
+            .into_iter()
+            .take(budget)
+            .map(|(_, index)| objects[index].clone())
+            .collect();
+        for object in &updates {
+            known.insert(object.entity, KnownObject {
+                object: object.clone(),
+                sent_at: now,
+            });
+        }
+
+        InterestUpdate {
+            agent_id: viewer.agent_id,
+            updates,
+            removals,
+        }
+    }
+}
@@ -0,0 +1,481 @@
+// File: crates/storm-server/src/lib.rs
+// Headless region server for StormCore
+// Hosts an ECS world and serves it to LLUDP viewers and Finalverse WebSocket clients
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+use anyhow::Result;
+
+use storm_ecs::{Entity, Transform, World};
+use storm_networking::{NetworkConfig, NetworkManager, ProtocolType};
+use storm_opensim::RegionInfo;
+
+pub mod region;
+pub mod lludp;
+pub mod finalverse;
+pub mod interest;
+
+pub use region::*;
+pub use lludp::*;
+pub use finalverse::*;
+pub use interest::{DistanceBand, InterestConfig, InterestManager, InterestUpdate, SpatialGrid, Viewer};
+
+/// Region server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub region_name: String,
+    pub region_id: Uuid,
+    pub grid_x: u32,
+    pub grid_y: u32,
+    pub udp_addr: SocketAddr,
+    pub websocket_addr: Option<SocketAddr>,
+    pub spawn_position: [f32; 3],
+    pub tick_rate_hz: u32,
+    pub water_height: f32,
+    pub network: NetworkConfig,
+    /// Which objects each agent is sent, and how often
+    pub interest: InterestConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            region_name: "StormCore Sim".to_string(),
+            region_id: Uuid::new_v4(),
+            grid_x: 1000,
+            grid_y: 1000,
+            udp_addr: "0.0.0.0:9000".parse().unwrap(),
+            websocket_addr: Some("0.0.0.0:9001".parse().unwrap()),
+            spawn_position: [128.0, 128.0, 25.0],
+            tick_rate_hz: 10,
+            water_height: 20.0,
+            network: NetworkConfig {
+                max_connections: 100,
+                connection_timeout_ms: 30000,
+                packet_buffer_size: 8192,
+                compression_enabled: false,
+                encryption_enabled: false,
+                heartbeat_interval_ms: 1000,
+            },
+            interest: InterestConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Region handle: global meter coordinates of the region's south-west corner
+    pub fn region_handle(&self) -> u64 {
+        ((self.grid_x as u64 * 256) << 32) | (self.grid_y as u64 * 256)
+    }
+
+    fn region_info(&self) -> RegionInfo {
+        RegionInfo {
+            region_id: self.region_id,
+            region_name: self.region_name.clone(),
+            region_handle: self.region_handle(),
+            sim_port: self.udp_addr.port(),
+            water_height: self.water_height,
+            agent_limit: self.network.max_connections as u32,
+            ..Default::default()
+        }
+    }
+}
+
+/// Notable things happening in the region
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    AgentJoined { agent_id: Uuid, name: String, protocol: AgentProtocol },
+    AgentLeft { agent_id: Uuid },
+    Chat { source_id: Uuid, from_name: String, message: String },
+}
+
+/// Addresses the server ended up bound to
+#[derive(Debug, Clone, Copy)]
+pub struct ServerAddresses {
+    pub udp: SocketAddr,
+    pub websocket: Option<SocketAddr>,
+}
+
+/// Headless region server
+pub struct RegionServer {
+    config: ServerConfig,
+    region: Arc<Region>,
+    network: Arc<NetworkManager>,
+    events: broadcast::Sender<ServerEvent>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl RegionServer {
+    pub async fn new(config: ServerConfig, world: World) -> Result<Self> {
+        let network = Arc::new(NetworkManager::new(&config.network).await?);
+        let (events, _) = broadcast::channel(256);
+        let region = Arc::new(Region::new(
+            config.region_info(),
+            config.spawn_position,
+            Arc::new(RwLock::new(world)),
+            network.clone(),
+            events.clone(),
+            config.interest.clone(),
+        ));
+
+        Ok(Self {
+            config,
+            region,
+            network,
+            events,
+            tasks: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Bind the endpoints and start the simulation loop
+    pub async fn start(&self) -> Result<ServerAddresses> {
+        let udp = self.network.start_listener(self.config.udp_addr, ProtocolType::LLUDP).await?;
+        let incoming = self
+            .network
+            .take_incoming()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Region server already started"))?;
+
+        let mut tasks = self.tasks.lock().await;
+        let lludp = Arc::new(LludpFrontEnd::new(self.region.clone()));
+        tasks.push(tokio::spawn(lludp.run(incoming)));
+
+        let websocket = match self.config.websocket_addr {
+            Some(addr) => {
+                let front_end = Arc::new(FinalverseFrontEnd::new(self.region.clone()));
+                let (bound, task) = front_end.start(addr).await?;
+                tasks.push(task);
+                Some(bound)
+            }
+            None => None,
+        };
+
+        tasks.push(tokio::spawn(Self::tick_loop(
+            self.region.clone(),
+            self.network.clone(),
+            self.config.tick_rate_hz.max(1),
+        )));
+
+        info!("Region '{}' serving LLUDP on {}", self.config.region_name, udp);
+        Ok(ServerAddresses { udp, websocket })
+    }
+
+    /// Receive server events
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Shared region state
+    pub fn region(&self) -> Arc<Region> {
+        self.region.clone()
+    }
+
+    /// Place a static object in the world
+    pub async fn spawn_object(&self, position: [f32; 3], scale: [f32; 3]) -> Entity {
+        let world = self.region.world();
+        let mut world = world.write().await;
+        let entity = world.create_entity();
+        world.add_component(entity, Transform {
+            position,
+            scale,
+            ..Default::default()
+        });
+        entity
+    }
+
+    /// Stop all tasks and close connections
+    pub async fn shutdown(&self) -> Result<()> {
+        for task in self.tasks.lock().await.drain(..) {
+            task.abort();
+        }
+        self.network.shutdown().await
+    }
+
+    async fn tick_loop(region: Arc<Region>, network: Arc<NetworkManager>, tick_rate_hz: u32) {
+        let mut interval = tokio::time::interval(Duration::from_secs_f32(1.0 / tick_rate_hz as f32));
+        let mut last_tick = Instant::now();
+
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            let delta_time = now.duration_since(last_tick).as_secs_f32();
+            last_tick = now;
+
+            // The ECS error type is not Send, so only its message crosses the await points below
+            let update_error = {
+                let world = region.world();
+                let mut world = world.write().await;
+                world.update(delta_time).err().map(|e| e.to_string())
+            };
+            if let Some(e) = update_error {
+                warn!("World update failed: {}", e);
+            }
+
+            if let Err(e) = network.update().await {
+                warn!("Network update failed: {}", e);
+            }
+            region.reap_disconnected().await;
+            if let Err(e) = region.broadcast_changes().await {
+                warn!("Failed to broadcast region changes: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use storm_finalverse::FinalverseMessage;
+    use storm_networking::ConnectionId;
+    use storm_opensim::{
+        template, AgentMovementComplete, Circuit, CompleteAgentMovement, LLUDPMessageType, MessageBody,
+        ObjectUpdateBlock, OpenSimObject, RegionHandshake, RegionObjects, UseCircuitCode,
+    };
+    use tokio::net::UdpSocket;
+    use tokio_tungstenite::tungstenite::Message;
+
+    fn test_config() -> ServerConfig {
+        ServerConfig {
+            udp_addr: "127.0.0.1:0".parse().unwrap(),
+            websocket_addr: Some("127.0.0.1:0".parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    /// Receive through the viewer's circuit, sending its acks, until a message of the given type arrives
+    async fn recv_message(socket: &UdpSocket, circuit: &mut Circuit, message_type: LLUDPMessageType) -> Vec<u8> {
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = tokio::time::timeout(Duration::from_secs(2), socket.recv(&mut buf))
+                .await
+                .expect("timed out waiting for LLUDP reply")
+                .unwrap();
+            // Heartbeats from the network layer are not LLUDP packets
+            let Ok(packet) = circuit.receive(&buf[..len], Instant::now()) else {
+                continue;
+            };
+            for data in circuit.poll(Instant::now()).unwrap() {
+                socket.send(&data).await.unwrap();
+            }
+            if let Some(packet) = packet.filter(|packet| packet.message_type == message_type) {
+                return packet.payload;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lludp_agent_joins_and_sees_scene() {
+        let server = RegionServer::new(test_config(), World::new()).await.unwrap();
+        let prim = server.spawn_object([100.0, 100.0, 22.0], [2.0, 2.0, 2.0]).await;
+        let addrs = server.start().await.unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addrs.udp).await.unwrap();
+
+        let agent_id = Uuid::new_v4();
+        let session_id = Uuid::new_v4();
+        let mut circuit = Circuit::new(42, session_id, Uuid::nil());
+        let use_circuit = UseCircuitCode { code: 42, session_id, agent_id };
+        let data = circuit.send_message(&use_circuit, true, Instant::now()).unwrap();
+        client.send(&data).await.unwrap();
+        // A resend of UseCircuitCode must not open a second circuit
+        client.send(&data).await.unwrap();
+
+        let handshake = RegionHandshake::from_payload(
+            &recv_message(&client, &mut circuit, LLUDPMessageType::RegionHandshake).await,
+        ).unwrap();
+        assert_eq!(handshake.sim_name, "StormCore Sim");
+
+        let complete = CompleteAgentMovement { agent_id, session_id, circuit_code: 42 };
+        client.send(&circuit.send_message(&complete, true, Instant::now()).unwrap()).await.unwrap();
+
+        let movement = AgentMovementComplete::from_payload(
+            &recv_message(&client, &mut circuit, LLUDPMessageType::AgentMovementComplete).await,
+        ).unwrap();
+        assert_eq!(movement.agent_id, agent_id);
+        assert_eq!(movement.position, [128.0, 128.0, 25.0]);
+
+        // The viewer mirrors what it is sent into its own world
+        let prim_id = server.region().full_id(prim.id);
+        let mut viewer_world = World::new();
+        let mut objects = RegionObjects::new();
+        while objects.entity_by_full_id(prim_id).is_none() {
+            let payload = recv_message(&client, &mut circuit, LLUDPMessageType::ObjectUpdate).await;
+            objects.apply_update(&mut viewer_world, &template::ObjectUpdate::from_payload(&payload).unwrap());
+        }
+        let entity = objects.entity_by_full_id(prim_id).unwrap();
+        assert_eq!(viewer_world.get_component::<OpenSimObject>(entity).unwrap().pcode, ObjectUpdateBlock::PCODE_PRIM);
+        assert_eq!(viewer_world.get_component::<Transform>(entity).unwrap().position, [100.0, 100.0, 22.0]);
+
+        assert_eq!(server.region().agent_count().await, 1);
+        // The server acknowledged our reliable packets
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let mut buf = [0u8; 2048];
+        while let Ok(Ok(len)) = tokio::time::timeout(Duration::from_millis(100), client.recv(&mut buf)).await {
+            let _ = circuit.receive(&buf[..len], Instant::now());
+        }
+        assert!(circuit.packets_pending.is_empty());
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconnecting_agent_drops_its_old_circuit() {
+        let server = RegionServer::new(test_config(), World::new()).await.unwrap();
+        let region = server.region();
+        let (agent_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let endpoint = |connection_id| AgentEndpoint::Lludp {
+            connection_id,
+            circuit: Box::new(Circuit::new(42, session_id, Uuid::nil())),
+        };
+
+        let (old, new) = (ConnectionId::new_v4(), ConnectionId::new_v4());
+        let first = region.add_agent(agent_id, session_id, "Storm Tester".into(), endpoint(old)).await;
+        let second = region.add_agent(agent_id, session_id, "Storm Tester".into(), endpoint(new)).await;
+
+        assert_eq!(region.agent_count().await, 1);
+        assert_eq!(region.agent_for_connection(old).await, None);
+        assert_eq!(region.agent_for_connection(new).await, Some(agent_id));
+        let world = region.world();
+        let world = world.read().await;
+        assert!(world.get_component::<RegionAgent>(first).is_none());
+        assert!(world.get_component::<RegionAgent>(second).is_some());
+    }
+
+    fn scene_object(entity: u64, position: [f32; 3]) -> SceneObject {
+        SceneObject {
+            entity,
+            local_id: entity as u32,
+            full_id: Uuid::from_u128(entity as u128),
+            position,
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0, 1.0, 1.0],
+            avatar: None,
+        }
+    }
+
+    #[test]
+    fn test_interest_bands_rate_limit_and_remove_out_of_view() {
+        let mut interest = InterestManager::new(InterestConfig::default());
+        let viewer = Viewer {
+            agent_id: Uuid::new_v4(),
+            position: [128.0, 128.0, 25.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+        };
+        let mut objects = vec![
+            scene_object(1, [138.0, 128.0, 25.0]),
+            scene_object(2, [128.0, 188.0, 25.0]),
+            scene_object(3, [128.0, 1000.0, 25.0]),
+        ];
+        let start = Instant::now();
+
+        // Far objects stay out of view
+        let first = interest.update(&objects, &[viewer], start);
+        let sent: Vec<u64> = first[0].updates.iter().map(|o| o.entity).collect();
+        assert_eq!(sent, vec![1, 2]);
+        assert_eq!(interest.known_count(viewer.agent_id), 2);
+        assert!(interest.update(&objects, &[viewer], start).is_empty());
+
+        // The near band updates every tick, the middle band every 500 ms
+        objects[0].position[2] += 1.0;
+        objects[1].position[2] += 1.0;
+        let tick = start + Duration::from_millis(100);
+        let second = interest.update(&objects, &[viewer], tick);
+        assert_eq!(second[0].updates, vec![objects[0].clone()]);
+        let later = start + Duration::from_millis(600);
+        let third = interest.update(&objects, &[viewer], later);
+        assert_eq!(third[0].updates, vec![objects[1].clone()]);
+
+        // Leaving beyond the hysteresis margin and deletion both remove
+        objects[1].position = [128.0, 128.0 + 256.0 + 4.0, 25.0];
+        let at_edge = interest.update(&objects, &[viewer], later + Duration::from_secs(3));
+        assert!(at_edge.iter().all(|update| update.removals.is_empty()));
+        objects[1].position = [128.0, 128.0 + 256.0 + 20.0, 25.0];
+        objects.remove(0);
+        let gone = interest.update(&objects, &[viewer], later + Duration::from_secs(6));
+        let removed: Vec<u64> = gone[0].removals.iter().map(|o| o.entity).collect();
+        assert_eq!(removed, vec![1, 2]);
+        assert_eq!(interest.known_count(viewer.agent_id), 0);
+    }
+
+    #[test]
+    fn test_interest_budget_prefers_visible_objects() {
+        let config = InterestConfig {
+            max_updates_per_tick: 2,
+            ..Default::default()
+        };
+        let mut interest = InterestManager::new(config);
+        // Facing +X
+        let viewer = Viewer {
+            agent_id: Uuid::new_v4(),
+            position: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+        };
+
+        let behind = scene_object(1, [-10.0, 0.0, 0.0]);
+        let ahead = scene_object(2, [10.0, 0.0, 0.0]);
+        let mut large_far = scene_object(3, [-40.0, 0.0, 0.0]);
+        large_far.scale = [20.0, 20.0, 20.0];
+        let mut avatar = scene_object(4, [0.0, -30.0, 0.0]);
+        avatar.avatar = Some(RegionAgent { agent_id: Uuid::new_v4(), name: "Test User".to_string() });
+        let objects = vec![behind, ahead, large_far, avatar];
+
+        let now = Instant::now();
+        let first = interest.update(&objects, &[viewer], now);
+        let sent: Vec<u64> = first[0].updates.iter().map(|o| o.entity).collect();
+        assert_eq!(sent, vec![3, 2]);
+
+        // What did not fit goes out on the following ticks
+        let second = interest.update(&objects, &[viewer], now + Duration::from_millis(100));
+        let sent: Vec<u64> = second[0].updates.iter().map(|o| o.entity).collect();
+        assert_eq!(sent, vec![4, 1]);
+
+        // A newly arrived agent gets everything in view at once
+        let other = Viewer { agent_id: Uuid::new_v4(), ..viewer };
+        assert_eq!(interest.initial_view(&other, &objects, now).updates.len(), 4);
+
+        let grid = SpatialGrid::build(32.0, objects.iter().map(|o| o.position));
+        let mut near: Vec<usize> = grid.query([0.0, 0.0, 0.0], 12.0).into_iter().map(|(i, _)| i).collect();
+        near.sort();
+        assert_eq!(near, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_finalverse_client_login() {
+        let server = RegionServer::new(test_config(), World::new()).await.unwrap();
+        let mut events = server.subscribe();
+        let addrs = server.start().await.unwrap();
+
+        let url = format!("ws://{}", addrs.websocket.unwrap());
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let login = FinalverseMessage::Login {
+            username: "Test User".to_string(),
+            token: "token".to_string(),
+        };
+        ws.send(Message::Text(serde_json::to_string(&login).unwrap())).await.unwrap();
+
+        let reply = tokio::time::timeout(Duration::from_secs(2), ws.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let reply: FinalverseMessage = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert!(matches!(reply, FinalverseMessage::LoginResponse { success: true, session_id: Some(_) }));
+
+        match events.recv().await.unwrap() {
+            ServerEvent::AgentJoined { name, protocol, .. } => {
+                assert_eq!(name, "Test User");
+                assert_eq!(protocol, AgentProtocol::Finalverse);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        server.shutdown().await.unwrap();
+    }
+}
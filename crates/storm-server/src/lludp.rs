@@ -0,0 +1,294 @@
+// File: crates/storm-server/src/lludp.rs
+// LLUDP front end: circuit setup and viewer message handling
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, warn};
+use uuid::Uuid;
+use anyhow::Result;
+
+use storm_networking::{ConnectionId, IncomingPacket, PacketPriority};
+use storm_opensim::{
+    deserialize_packet, AgentMovementComplete, AgentUpdate, ChatFromViewer,
+    Circuit, CompleteAgentMovement, LLUDPMessageType, LLUDPPacket, LogoutRequest, MessageBody,
+    RegionHandshake, RegionHandshakeReply, TeleportFailed, TeleportLocal, TeleportLocationRequest,
+    TeleportRequest, UseCircuitCode,
+};
+
+use crate::region::{AgentEndpoint, Region};
+
+/// Channel version reported to viewers in AgentMovementComplete
+const CHANNEL_VERSION: &str = "StormCore Sim";
+
+/// How often circuits are polled for owed acks, resends and ping checks
+const CIRCUIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Circuit opened by UseCircuitCode that has not completed agent movement yet
+struct PendingCircuit {
+    agent_id: Uuid,
+    session_id: Uuid,
+    circuit: Circuit,
+}
+
+/// Handles viewer traffic arriving on the region's UDP listener
+pub struct LludpFrontEnd {
+    region: Arc<Region>,
+    pending: Mutex<HashMap<ConnectionId, PendingCircuit>>,
+}
+
+impl LludpFrontEnd {
+    pub fn new(region: Arc<Region>) -> Self {
+        Self {
+            region,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Process packets until the network layer closes the incoming channel, polling circuits in between
+    pub async fn run(self: Arc<Self>, mut incoming: mpsc::UnboundedReceiver<IncomingPacket>) {
+        let mut interval = tokio::time::interval(CIRCUIT_POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                packet = incoming.recv() => {
+                    let Some(packet) = packet else {
+                        break;
+                    };
+                    if let Err(e) = self.handle_datagram(packet.connection_id, &packet.data).await {
+                        warn!("Dropping LLUDP packet from {}: {}", packet.connection_id, e);
+                    }
+                }
+                _ = interval.tick() => self.poll_circuits().await,
+            }
+        }
+        debug!("LLUDP front end stopped");
+    }
+
+    async fn handle_datagram(&self, connection_id: ConnectionId, data: &[u8]) -> Result<()> {
+        let Some(packet) = self.receive_on_circuit(connection_id, data).await? else {
+            return Ok(());
+        };
+
+        match packet.message_type {
+            LLUDPMessageType::UseCircuitCode => {
+                // Resent UseCircuitCodes are filtered by the circuit; a new code on a live connection is ignored
+                debug!("Connection {} already has a circuit", connection_id);
+                Ok(())
+            }
+            LLUDPMessageType::RegionHandshakeReply => {
+                let reply = RegionHandshakeReply::from_payload(&packet.payload)?;
+                debug!("Agent {} acknowledged region handshake", reply.agent_id);
+                Ok(())
+            }
+            LLUDPMessageType::CompleteAgentMovement => {
+                self.complete_agent_movement(connection_id, CompleteAgentMovement::from_payload(&packet.payload)?).await
+            }
+            LLUDPMessageType::AgentUpdate => {
+                let update = AgentUpdate::from_payload(&packet.payload)?;
+                if self.owns_connection(connection_id, update.agent_id).await {
+                    self.region.update_agent_transform(update.agent_id, None, Some(update.body_rotation)).await;
+                }
+                Ok(())
+            }
+            LLUDPMessageType::ChatFromViewer => {
+                let chat = ChatFromViewer::from_payload(&packet.payload)?;
+                if self.owns_connection(connection_id, chat.agent_id).await {
+                    let name = self.region.agent_name(chat.agent_id).await.unwrap_or_default();
+                    self.region.relay_chat(chat.agent_id, &name, &chat.message, chat.channel).await;
+                }
+                Ok(())
+            }
+            LLUDPMessageType::TeleportLocationRequest => {
+                let request = TeleportLocationRequest::from_payload(&packet.payload)?;
+                if !self.owns_connection(connection_id, request.agent_id).await {
+                    return Ok(());
+                }
+                let local = request.region_handle == self.region.info.region_handle;
+                self.teleport(request.agent_id, local, request.position, request.look_at).await
+            }
+            LLUDPMessageType::TeleportRequest => {
+                let request = TeleportRequest::from_payload(&packet.payload)?;
+                if !self.owns_connection(connection_id, request.agent_id).await {
+                    return Ok(());
+                }
+                let local = request.region_id == self.region.info.region_id;
+                self.teleport(request.agent_id, local, request.position, request.look_at).await
+            }
+            LLUDPMessageType::LogoutRequest => {
+                let logout = LogoutRequest::from_payload(&packet.payload)?;
+                if self.owns_connection(connection_id, logout.agent_id).await {
+                    self.region.remove_agent(logout.agent_id).await;
+                }
+                Ok(())
+            }
+            other => {
+                debug!("Ignoring unhandled LLUDP message {:?}", other);
+                Ok(())
+            }
+        }
+    }
+
+    /// Run a datagram through its connection's circuit, opening one for UseCircuitCode
+    ///
+    /// Returns `None` for duplicates and circuit housekeeping such as acks and ping checks.
+    async fn receive_on_circuit(&self, connection_id: ConnectionId, data: &[u8]) -> Result<Option<LLUDPPacket>> {
+        let now = Instant::now();
+        let receive = |circuit: &mut Circuit| -> Result<(Option<LLUDPPacket>, Vec<Vec<u8>>)> {
+            let packet = circuit.receive(data, now)?;
+            Ok((packet, circuit.poll(now)?))
+        };
+
+        let pending = self
+            .pending
+            .lock()
+            .await
+            .get_mut(&connection_id)
+            .map(|pending| receive(&mut pending.circuit));
+        let received = match pending {
+            Some(received) => Some(received),
+            None => self.region.with_circuit(connection_id, &receive).await,
+        };
+        let Some(received) = received else {
+            return self.use_circuit_code(connection_id, data, now).await;
+        };
+
+        let (packet, datagrams) = received?;
+        self.send_datagrams(connection_id, datagrams).await;
+        Ok(packet)
+    }
+
+    /// Open a circuit for a connection that has none; anything but UseCircuitCode is dropped
+    async fn use_circuit_code(&self, connection_id: ConnectionId, data: &[u8], now: Instant) -> Result<Option<LLUDPPacket>> {
+        let packet = deserialize_packet(data)?;
+        if packet.message_type != LLUDPMessageType::UseCircuitCode {
+            return Err(anyhow::anyhow!("{:?} on connection {} without a circuit", packet.message_type, connection_id));
+        }
+        let message = UseCircuitCode::from_payload(&packet.payload)?;
+
+        // There is no login service in front of the region yet, so any circuit code is accepted
+        let mut circuit = Circuit::new(message.code, message.session_id, Uuid::nil());
+        // Let the circuit see UseCircuitCode so it is acknowledged and its resends are filtered
+        circuit.receive(data, now)?;
+        let handshake = self.region_handshake();
+        let data = circuit.send_message(&handshake, true, now)?;
+        self.send_datagrams(connection_id, vec![data]).await;
+
+        self.pending.lock().await.insert(connection_id, PendingCircuit {
+            agent_id: message.agent_id,
+            session_id: message.session_id,
+            circuit,
+        });
+        Ok(None)
+    }
+
+    /// Send owed acks, resends and ping checks; circuits that run out of resends are dropped
+    async fn poll_circuits(&self) {
+        let now = Instant::now();
+        let mut outgoing = Vec::new();
+        self.pending.lock().await.retain(|connection_id, pending| match pending.circuit.poll(now) {
+            Ok(datagrams) => {
+                outgoing.push((*connection_id, datagrams));
+                true
+            }
+            Err(e) => {
+                warn!("Dropping circuit for agent {}: {}", pending.agent_id, e);
+                false
+            }
+        });
+        for (connection_id, datagrams) in outgoing {
+            self.send_datagrams(connection_id, datagrams).await;
+        }
+
+        self.region.poll_circuits(now).await;
+    }
+
+    async fn send_datagrams(&self, connection_id: ConnectionId, datagrams: Vec<Vec<u8>>) {
+        let network = self.region.network();
+        for data in datagrams {
+            if let Err(e) = network.send_packet(connection_id, data, PacketPriority::High).await {
+                debug!("Failed to send on circuit {}: {}", connection_id, e);
+            }
+        }
+    }
+
+    async fn complete_agent_movement(&self, connection_id: ConnectionId, message: CompleteAgentMovement) -> Result<()> {
+        let pending = self.pending.lock().await.remove(&connection_id);
+        let Some(pending) = pending else {
+            return Err(anyhow::anyhow!("CompleteAgentMovement on connection {} without a circuit", connection_id));
+        };
+        if pending.agent_id != message.agent_id || pending.session_id != message.session_id {
+            return Err(anyhow::anyhow!("CompleteAgentMovement does not match circuit for agent {}", pending.agent_id));
+        }
+
+        let name = format!("Agent {}", &message.agent_id.simple().to_string()[..8]);
+        self.region.add_agent(
+            message.agent_id,
+            message.session_id,
+            name,
+            AgentEndpoint::Lludp {
+                connection_id,
+                circuit: Box::new(pending.circuit),
+            },
+        ).await;
+
+        let complete = AgentMovementComplete {
+            agent_id: message.agent_id,
+            session_id: message.session_id,
+            position: self.region.spawn_position,
+            look_at: [1.0, 0.0, 0.0],
+            region_handle: self.region.info.region_handle,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as u32)
+                .unwrap_or_default(),
+            channel_version: CHANNEL_VERSION.to_string(),
+        };
+        self.region.send_to_agent(message.agent_id, &complete).await?;
+        self.region.send_full_scene(message.agent_id).await
+    }
+
+    /// Teleports within the region move the avatar; this server has no neighbours to hand off to
+    async fn teleport(&self, agent_id: Uuid, local: bool, position: [f32; 3], look_at: [f32; 3]) -> Result<()> {
+        if !local {
+            let failed = TeleportFailed {
+                agent_id,
+                reason: "Destination region is not hosted by this simulator".to_string(),
+            };
+            return self.region.send_to_agent(agent_id, &failed).await;
+        }
+
+        self.region.update_agent_transform(agent_id, Some(position), None).await;
+        let teleport = TeleportLocal {
+            agent_id,
+            location_id: 0,
+            position,
+            look_at,
+            teleport_flags: 0,
+        };
+        self.region.send_to_agent(agent_id, &teleport).await
+    }
+
+    async fn owns_connection(&self, connection_id: ConnectionId, agent_id: Uuid) -> bool {
+        self.region.agent_for_connection(connection_id).await == Some(agent_id)
+    }
+
+    fn region_handshake(&self) -> RegionHandshake {
+        let info = &self.region.info;
+        RegionHandshake {
+            region_flags: info.region_flags,
+            sim_access: 13, // PG
+            sim_name: info.region_name.clone(),
+            sim_owner: Uuid::nil(),
+            is_estate_manager: false,
+            water_height: info.water_height,
+            billable_factor: info.object_bonus_factor,
+            cache_id: info.region_id,
+            terrain_textures: [Uuid::nil(); 4],
+            terrain_detail: [Uuid::nil(); 4],
+            terrain_start_heights: [10.0; 4],
+            terrain_height_ranges: [60.0; 4],
+            region_id: info.region_id,
+        }
+    }
+}
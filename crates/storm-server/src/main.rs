@@ -0,0 +1,69 @@
+// File: crates/storm-server/src/main.rs
+// storm-sim: run a headless StormCore region
+
+use std::net::SocketAddr;
+use clap::Parser;
+use tracing::info;
+use anyhow::Result;
+
+use storm_ecs::World;
+use storm_server::{RegionServer, ServerConfig, ServerEvent};
+
+#[derive(Parser)]
+#[command(name = "storm-sim")]
+#[command(about = "Headless StormCore region server")]
+struct Cli {
+    /// Region name shown to clients
+    #[arg(long, default_value = "StormCore Sim")]
+    name: String,
+
+    /// LLUDP listen address
+    #[arg(long, default_value = "0.0.0.0:9000")]
+    udp: SocketAddr,
+
+    /// Finalverse WebSocket listen address
+    #[arg(long, default_value = "0.0.0.0:9001")]
+    ws: SocketAddr,
+
+    /// Number of demo objects to place in the region
+    #[arg(long, default_value_t = 4)]
+    objects: u32,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
+
+    let config = ServerConfig {
+        region_name: cli.name,
+        udp_addr: cli.udp,
+        websocket_addr: Some(cli.ws),
+        ..Default::default()
+    };
+    let server = RegionServer::new(config, World::new()).await?;
+
+    for i in 0..cli.objects {
+        let offset = i as f32 * 4.0;
+        server.spawn_object([120.0 + offset, 128.0, 22.0], [1.0, 1.0, 1.0]).await;
+    }
+
+    let addrs = server.start().await?;
+    info!("storm-sim running: LLUDP {} / WebSocket {:?}", addrs.udp, addrs.websocket);
+
+    let mut events = server.subscribe();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            event = events.recv() => match event {
+                Ok(ServerEvent::AgentJoined { name, protocol, .. }) => info!("{} joined via {:?}", name, protocol),
+                Ok(ServerEvent::AgentLeft { agent_id }) => info!("{} left", agent_id),
+                Ok(ServerEvent::Chat { from_name, message, .. }) => info!("[chat] {}: {}", from_name, message),
+                Err(_) => {}
+            },
+        }
+    }
+
+    info!("Shutting down storm-sim");
+    server.shutdown().await
+}
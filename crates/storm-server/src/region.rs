@@ -0,0 +1,603 @@
+// File: crates/storm-server/src/region.rs
+// Shared region state: agents, scene snapshots and fan-out to connected clients
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use anyhow::Result;
+
+use storm_ecs::{Component, Entity, EntityId, Transform, World};
+use storm_finalverse::{EntityData, EntityUpdate, FinalverseMessage};
+use storm_networking::{ConnectionId, NetworkManager, PacketPriority};
+use storm_opensim::{
+    ChatFromSimulator, Circuit, KillObject, LLUDPPacket, MessageBody,
+    ObjectUpdate, ObjectUpdateBlock, RegionInfo,
+};
+
+use crate::{InterestConfig, InterestManager, InterestUpdate, ServerEvent, Viewer};
+
+/// Objects per ObjectUpdate packet, keeping datagrams well under the UDP MTU
+const OBJECTS_PER_UPDATE: usize = 4;
+
+/// Marks an ECS entity as a connected agent's avatar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionAgent {
+    pub agent_id: Uuid,
+    pub name: String,
+}
+
+impl Component for RegionAgent {
+    fn type_name() -> &'static str {
+        "RegionAgent"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Client protocol an agent joined with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentProtocol {
+    Lludp,
+    Finalverse,
+}
+
+/// Where to deliver messages for an agent
+pub enum AgentEndpoint {
+    Lludp {
+        connection_id: ConnectionId,
+        circuit: Box<Circuit>,
+    },
+    Finalverse {
+        outbound: mpsc::UnboundedSender<FinalverseMessage>,
+    },
+}
+
+impl AgentEndpoint {
+    pub fn protocol(&self) -> AgentProtocol {
+        match self {
+            AgentEndpoint::Lludp { .. } => AgentProtocol::Lludp,
+            AgentEndpoint::Finalverse { .. } => AgentProtocol::Finalverse,
+        }
+    }
+}
+
+/// Connected agent
+pub struct Agent {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub name: String,
+    pub entity: Entity,
+    pub endpoint: AgentEndpoint,
+}
+
+/// Object as seen by clients, derived from the ECS world
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneObject {
+    pub entity: EntityId,
+    pub local_id: u32,
+    pub full_id: Uuid,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub avatar: Option<RegionAgent>,
+}
+
+impl SceneObject {
+    fn to_object_block(&self) -> ObjectUpdateBlock {
+        let (pcode, name_value, owner_id) = match &self.avatar {
+            Some(agent) => {
+                let mut parts = agent.name.splitn(2, ' ');
+                let first = parts.next().unwrap_or_default();
+                let last = parts.next().unwrap_or("Resident");
+                (
+                    ObjectUpdateBlock::PCODE_AVATAR,
+                    format!("FirstName STRING RW SV {}\nLastName STRING RW SV {}", first, last),
+                    agent.agent_id,
+                )
+            }
+            None => (ObjectUpdateBlock::PCODE_PRIM, String::new(), Uuid::nil()),
+        };
+
+        ObjectUpdateBlock {
+            local_id: self.local_id,
+            state: 0,
+            full_id: self.full_id,
+            crc: 0,
+            pcode,
+            material: 3, // wood
+            click_action: 0,
+            scale: self.scale,
+            position: self.position,
+            velocity: [0.0; 3],
+            acceleration: [0.0; 3],
+            rotation: self.rotation,
+            angular_velocity: [0.0; 3],
+            parent_id: 0,
+            update_flags: 0,
+            texture_entry: Vec::new(),
+            name_value,
+            text: String::new(),
+            owner_id,
+        }
+    }
+
+    fn to_entity_data(&self) -> EntityData {
+        let (name, entity_type) = match &self.avatar {
+            Some(agent) => (agent.name.clone(), "avatar"),
+            None => (format!("Object {}", self.local_id), "prim"),
+        };
+
+        EntityData {
+            id: self.full_id.to_string(),
+            name,
+            entity_type: entity_type.to_string(),
+            position: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+            mesh_url: None,
+            texture_urls: Vec::new(),
+            properties: HashMap::new(),
+        }
+    }
+}
+
+/// Region hosted by the server, shared between the LLUDP and Finalverse front ends
+pub struct Region {
+    pub info: RegionInfo,
+    pub spawn_position: [f32; 3],
+    world: Arc<RwLock<World>>,
+    network: Arc<NetworkManager>,
+    agents: RwLock<HashMap<Uuid, Agent>>,
+    connections: RwLock<HashMap<ConnectionId, Uuid>>,
+    interest: Mutex<InterestManager>,
+    events: broadcast::Sender<ServerEvent>,
+}
+
+impl Region {
+    pub fn new(
+        info: RegionInfo,
+        spawn_position: [f32; 3],
+        world: Arc<RwLock<World>>,
+        network: Arc<NetworkManager>,
+        events: broadcast::Sender<ServerEvent>,
+        interest: InterestConfig,
+    ) -> Self {
+        Self {
+            info,
+            spawn_position,
+            world,
+            network,
+            agents: RwLock::new(HashMap::new()),
+            connections: RwLock::new(HashMap::new()),
+            interest: Mutex::new(InterestManager::new(interest)),
+            events,
+        }
+    }
+
+    pub fn world(&self) -> Arc<RwLock<World>> {
+        self.world.clone()
+    }
+
+    pub fn network(&self) -> Arc<NetworkManager> {
+        self.network.clone()
+    }
+
+    /// Stable object id clients see for an entity
+    pub fn full_id(&self, entity: EntityId) -> Uuid {
+        Uuid::from_u128(self.info.region_id.as_u128() ^ entity as u128)
+    }
+
+    /// Number of connected agents
+    pub async fn agent_count(&self) -> usize {
+        self.agents.read().await.len()
+    }
+
+    /// Agent owning an LLUDP connection
+    pub async fn agent_for_connection(&self, connection_id: ConnectionId) -> Option<Uuid> {
+        self.connections.read().await.get(&connection_id).copied()
+    }
+
+    /// Display name of a connected agent
+    pub async fn agent_name(&self, agent_id: Uuid) -> Option<String> {
+        self.agents.read().await.get(&agent_id).map(|agent| agent.name.clone())
+    }
+
+    /// Place a new agent's avatar in the world and start tracking it
+    pub async fn add_agent(
+        &self,
+        agent_id: Uuid,
+        session_id: Uuid,
+        name: String,
+        endpoint: AgentEndpoint,
+    ) -> Entity {
+        let protocol = endpoint.protocol();
+        let connection = match &endpoint {
+            AgentEndpoint::Lludp { connection_id, .. } => Some(*connection_id),
+            AgentEndpoint::Finalverse { .. } => None,
+        };
+        if let Some(connection_id) = connection {
+            self.connections.write().await.insert(connection_id, agent_id);
+        }
+
+        let entity = {
+            let mut world = self.world.write().await;
+            let entity = world.create_entity();
+            world.add_component(entity, Transform {
+                position: self.spawn_position,
+                ..Default::default()
+            });
+            world.add_component(entity, RegionAgent {
+                agent_id,
+                name: name.clone(),
+            });
+            entity
+        };
+
+        let previous = self.agents.write().await.insert(agent_id, Agent {
+            agent_id,
+            session_id,
+            name: name.clone(),
+            entity,
+            endpoint,
+        });
+
+        // A reconnecting agent replaces its old avatar, circuit and view
+        if let Some(previous) = previous {
+            if let AgentEndpoint::Lludp { connection_id, .. } = &previous.endpoint {
+                if connection != Some(*connection_id) {
+                    self.connections.write().await.remove(connection_id);
+                    self.network.close_connection(*connection_id).await;
+                }
+            }
+            self.world.write().await.remove_entity(previous.entity);
+            self.interest.lock().await.remove_viewer(agent_id);
+        }
+
+        info!("Agent {} ({}) joined region {} via {:?}", name, agent_id, self.info.region_name, protocol);
+        let _ = self.events.send(ServerEvent::AgentJoined { agent_id, name, protocol });
+        entity
+    }
+
+    /// Remove an agent and its avatar
+    pub async fn remove_agent(&self, agent_id: Uuid) -> bool {
+        let Some(agent) = self.agents.write().await.remove(&agent_id) else {
+            return false;
+        };
+
+        if let AgentEndpoint::Lludp { connection_id, .. } = &agent.endpoint {
+            self.connections.write().await.remove(connection_id);
+            self.network.close_connection(*connection_id).await;
+        }
+        self.world.write().await.remove_entity(agent.entity);
+        self.interest.lock().await.remove_viewer(agent_id);
+
+        info!("Agent {} ({}) left region {}", agent.name, agent_id, self.info.region_name);
+        let _ = self.events.send(ServerEvent::AgentLeft { agent_id });
+        true
+    }
+
+    /// Update an agent's avatar transform from client input
+    pub async fn update_agent_transform(&self, agent_id: Uuid, position: Option<[f32; 3]>, rotation: Option<[f32; 4]>) {
+        let Some(entity) = self.agents.read().await.get(&agent_id).map(|agent| agent.entity) else {
+            return;
+        };
+
+        let mut world = self.world.write().await;
+        if let Some(transform) = world.get_component_mut::<Transform>(entity) {
+            if let Some(position) = position {
+                transform.position = position;
+            }
+            if let Some(rotation) = rotation {
+                transform.rotation = rotation;
+            }
+        }
+    }
+
+    /// Current position of an agent's avatar
+    pub async fn agent_position(&self, agent_id: Uuid) -> Option<[f32; 3]> {
+        let entity = self.agents.read().await.get(&agent_id)?.entity;
+        let world = self.world.read().await;
+        world.get_component::<Transform>(entity).map(|t| t.position)
+    }
+
+    /// Every entity with a transform, as clients should see it
+    pub async fn snapshot(&self) -> Vec<SceneObject> {
+        let world = self.world.read().await;
+        let mut objects: Vec<SceneObject> = world
+            .query::<Transform>()
+            .map(|(entity, transform)| {
+                let avatar = world.get_component::<RegionAgent>(entity).cloned();
+                let full_id = match &avatar {
+                    Some(agent) => agent.agent_id,
+                    None => self.full_id(entity.id),
+                };
+                SceneObject {
+                    entity: entity.id,
+                    local_id: entity.id as u32,
+                    full_id,
+                    position: transform.position,
+                    rotation: transform.rotation,
+                    scale: transform.scale,
+                    avatar,
+                }
+            })
+            .collect();
+        objects.sort_by_key(|object| object.entity);
+        objects
+    }
+
+    /// Send a message body to an LLUDP agent on its circuit
+    pub async fn send_to_agent<M: MessageBody>(&self, agent_id: Uuid, body: &M) -> Result<()> {
+        self.send_lludp_packet(agent_id, body.to_packet()).await
+    }
+
+    async fn send_lludp_packet(&self, agent_id: Uuid, packet: LLUDPPacket) -> Result<()> {
+        let (connection_id, data) = {
+            let mut agents = self.agents.write().await;
+            let agent = agents
+                .get_mut(&agent_id)
+                .ok_or_else(|| anyhow::anyhow!("Agent not found: {}", agent_id))?;
+            match &mut agent.endpoint {
+                AgentEndpoint::Lludp { connection_id, circuit } => {
+                    (*connection_id, circuit.send(packet, true, Instant::now())?)
+                }
+                AgentEndpoint::Finalverse { .. } => {
+                    return Err(anyhow::anyhow!("Agent {} is not on an LLUDP circuit", agent_id));
+                }
+            }
+        };
+
+        self.network.send_packet(connection_id, data, PacketPriority::Normal).await
+    }
+
+    /// Run `f` on the circuit of the agent owning an LLUDP connection
+    pub async fn with_circuit<R>(&self, connection_id: ConnectionId, f: impl FnOnce(&mut Circuit) -> R) -> Option<R> {
+        let agent_id = self.agent_for_connection(connection_id).await?;
+        let mut agents = self.agents.write().await;
+        match &mut agents.get_mut(&agent_id)?.endpoint {
+            AgentEndpoint::Lludp { circuit, .. } => Some(f(circuit)),
+            AgentEndpoint::Finalverse { .. } => None,
+        }
+    }
+
+    /// Send owed acks, resends and ping checks on every agent circuit
+    ///
+    /// Agents whose circuits run out of resends are removed.
+    pub async fn poll_circuits(&self, now: Instant) {
+        let mut outgoing = Vec::new();
+        let mut timed_out = Vec::new();
+        for agent in self.agents.write().await.values_mut() {
+            if let AgentEndpoint::Lludp { connection_id, circuit } = &mut agent.endpoint {
+                match circuit.poll(now) {
+                    Ok(datagrams) => outgoing.push((*connection_id, datagrams)),
+                    Err(e) => {
+                        warn!("Agent {} lost its circuit: {}", agent.agent_id, e);
+                        timed_out.push(agent.agent_id);
+                    }
+                }
+            }
+        }
+
+        for (connection_id, datagrams) in outgoing {
+            for data in datagrams {
+                if let Err(e) = self.network.send_packet(connection_id, data, PacketPriority::High).await {
+                    debug!("Failed to send on circuit {}: {}", connection_id, e);
+                }
+            }
+        }
+        for agent_id in timed_out {
+            self.remove_agent(agent_id).await;
+        }
+    }
+
+    /// Send a Finalverse message to a WebSocket agent
+    pub async fn send_to_finalverse_agent(&self, agent_id: Uuid, message: FinalverseMessage) -> Result<()> {
+        let agents = self.agents.read().await;
+        match agents.get(&agent_id).map(|agent| &agent.endpoint) {
+            Some(AgentEndpoint::Finalverse { outbound }) => outbound
+                .send(message)
+                .map_err(|_| anyhow::anyhow!("Finalverse agent {} has disconnected", agent_id)),
+            Some(AgentEndpoint::Lludp { .. }) => {
+                Err(anyhow::anyhow!("Agent {} is not a Finalverse client", agent_id))
+            }
+            None => Err(anyhow::anyhow!("Agent not found: {}", agent_id)),
+        }
+    }
+
+    /// Send everything in view to one agent, used right after it arrives
+    pub async fn send_full_scene(&self, agent_id: Uuid) -> Result<()> {
+        let objects = self.snapshot().await;
+        let Some((viewer, protocol)) = self.viewer(agent_id).await else {
+            return Ok(());
+        };
+
+        let update = self
+            .interest
+            .lock()
+            .await
+            .initial_view(&viewer, &objects, std::time::Instant::now());
+        match protocol {
+            AgentProtocol::Lludp => self.send_lludp_changes(agent_id, &update.updates, &[]).await,
+            AgentProtocol::Finalverse => {
+                for object in &update.updates {
+                    self.send_to_finalverse_agent(agent_id, FinalverseMessage::EntitySpawn {
+                        entity: object.to_entity_data(),
+                    }).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Relay local chat to every agent in the region
+    pub async fn relay_chat(&self, source_id: Uuid, from_name: &str, message: &str, channel: i32) {
+        // Non-zero channels are script channels and never reach viewers
+        if channel != 0 {
+            debug!("Dropping chat on channel {} from {}", channel, from_name);
+            return;
+        }
+
+        let position = self.agent_position(source_id).await.unwrap_or(self.spawn_position);
+        let chat = ChatFromSimulator {
+            from_name: from_name.to_string(),
+            source_id,
+            owner_id: source_id,
+            source_type: 1, // agent
+            chat_type: 1,   // normal
+            audible: 1,     // fully audible
+            position,
+            message: message.to_string(),
+        };
+
+        for (agent_id, protocol) in self.agent_protocols().await {
+            let result = match protocol {
+                AgentProtocol::Lludp => self.send_to_agent(agent_id, &chat).await,
+                AgentProtocol::Finalverse => {
+                    self.send_to_finalverse_agent(agent_id, FinalverseMessage::Chat {
+                        message: format!("{}: {}", from_name, message),
+                        channel: "local".to_string(),
+                    }).await
+                }
+            };
+            if let Err(e) = result {
+                warn!("Failed to relay chat to {}: {}", agent_id, e);
+            }
+        }
+
+        let _ = self.events.send(ServerEvent::Chat {
+            source_id,
+            from_name: from_name.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Push changes to each agent, limited to what it can see and its per-tick budget
+    pub async fn broadcast_changes(&self) -> Result<()> {
+        let objects = self.snapshot().await;
+        let viewers = self.viewers().await;
+        if viewers.is_empty() {
+            return Ok(());
+        }
+
+        let protocols: HashMap<Uuid, AgentProtocol> =
+            viewers.iter().map(|(viewer, protocol)| (viewer.agent_id, *protocol)).collect();
+        let viewers: Vec<Viewer> = viewers.into_iter().map(|(viewer, _)| viewer).collect();
+        let updates: Vec<InterestUpdate> = self
+            .interest
+            .lock()
+            .await
+            .update(&objects, &viewers, std::time::Instant::now());
+
+        for InterestUpdate { agent_id, updates, removals } in updates {
+            let result = match protocols[&agent_id] {
+                AgentProtocol::Lludp => self.send_lludp_changes(agent_id, &updates, &removals).await,
+                AgentProtocol::Finalverse => self.send_finalverse_changes(agent_id, &updates, &removals).await,
+            };
+            if let Err(e) = result {
+                warn!("Failed to send scene changes to {}: {}", agent_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop agents whose LLUDP connection timed out in the network layer
+    pub async fn reap_disconnected(&self) {
+        let lludp_agents: Vec<(Uuid, ConnectionId)> = self
+            .connections
+            .read()
+            .await
+            .iter()
+            .map(|(connection_id, agent_id)| (*agent_id, *connection_id))
+            .collect();
+
+        for (agent_id, connection_id) in lludp_agents {
+            if self.network.remote_addr(connection_id).await.is_none() {
+                self.remove_agent(agent_id).await;
+            }
+        }
+    }
+
+    async fn send_lludp_changes(&self, agent_id: Uuid, changed: &[SceneObject], removed: &[SceneObject]) -> Result<()> {
+        for chunk in changed.chunks(OBJECTS_PER_UPDATE) {
+            let update = self.object_update(chunk);
+            self.send_to_agent(agent_id, &update).await?;
+        }
+        if !removed.is_empty() {
+            let kill = KillObject {
+                local_ids: removed.iter().map(|object| object.local_id).collect(),
+            };
+            self.send_to_agent(agent_id, &kill).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_finalverse_changes(&self, agent_id: Uuid, changed: &[SceneObject], removed: &[SceneObject]) -> Result<()> {
+        if !changed.is_empty() {
+            let entities = changed
+                .iter()
+                .map(|object| EntityUpdate::transform_update(
+                    object.full_id.to_string(),
+                    object.position,
+                    object.rotation,
+                    object.scale,
+                ))
+                .collect();
+            self.send_to_finalverse_agent(agent_id, FinalverseMessage::WorldUpdate { entities }).await?;
+        }
+        for object in removed {
+            self.send_to_finalverse_agent(agent_id, FinalverseMessage::EntityDespawn {
+                entity_id: object.full_id.to_string(),
+            }).await?;
+        }
+        Ok(())
+    }
+
+    /// Every agent's viewpoint, from its avatar's transform
+    async fn viewers(&self) -> Vec<(Viewer, AgentProtocol)> {
+        let agents = self.agents.read().await;
+        let world = self.world.read().await;
+        agents
+            .values()
+            .filter_map(|agent| {
+                let transform = world.get_component::<Transform>(agent.entity)?;
+                let viewer = Viewer {
+                    agent_id: agent.agent_id,
+                    position: transform.position,
+                    rotation: transform.rotation,
+                };
+                Some((viewer, agent.endpoint.protocol()))
+            })
+            .collect()
+    }
+
+    async fn viewer(&self, agent_id: Uuid) -> Option<(Viewer, AgentProtocol)> {
+        self.viewers().await.into_iter().find(|(viewer, _)| viewer.agent_id == agent_id)
+    }
+
+    async fn agent_protocols(&self) -> Vec<(Uuid, AgentProtocol)> {
+        self.agents
+            .read()
+            .await
+            .values()
+            .map(|agent| (agent.agent_id, agent.endpoint.protocol()))
+            .collect()
+    }
+
+    fn object_update(&self, objects: &[SceneObject]) -> ObjectUpdate {
+        ObjectUpdate {
+            region_handle: self.info.region_handle,
+            time_dilation: u16::MAX,
+            objects: objects.iter().map(SceneObject::to_object_block).collect(),
+        }
+    }
+}
@@ -124,7 +124,7 @@ impl WasmStormEngine {
         // Create the engine instance
         let engine = StormCore::new(storm_config)
             .await
-            .map_err(|e| JsValue::from_str(&format!("Failed to initialize StormCore: {}", e)))?;
+            .map_err(|e| storm_error_to_js("Failed to initialize StormCore", e))?;
 
         // Store in global state
         let mut global_engine = ENGINE.lock().unwrap();
@@ -164,7 +164,7 @@ impl WasmStormEngine {
         if let Some(ref engine) = *engine {
             engine.connect_to_world(&world_config)
                 .await
-                .map_err(|e| JsValue::from_str(&format!("Failed to connect: {}", e)))?;
+                .map_err(|e| storm_error_to_js("Failed to connect", e))?;
         } else {
             return Err(JsValue::from_str("Engine not available"));
         }
@@ -184,7 +184,7 @@ impl WasmStormEngine {
         if let Some(ref engine) = *engine {
             engine.update(delta_time)
                 .await
-                .map_err(|e| JsValue::from_str(&format!("Update failed: {}", e)))?;
+                .map_err(|e| storm_error_to_js("Update failed", e))?;
         }
 
         Ok(())
@@ -206,6 +206,23 @@ impl WasmStormEngine {
         Ok(stats.into())
     }
 
+    /// Appearances of the avatars in view: baked textures, visual params, size and revision
+    #[wasm_bindgen]
+    pub async fn get_avatar_appearances(&self) -> Result<JsValue, JsValue> {
+        if !self.initialized {
+            return Err(JsValue::from_str("Engine not initialized"));
+        }
+
+        let world = match ENGINE.lock().unwrap().as_ref() {
+            Some(engine) => engine.ecs_world(),
+            None => return Ok(js_sys::Array::new().into()),
+        };
+        let world = world.read().await;
+        let appearances: Vec<&storm_ecs::AvatarAppearance> =
+            world.query::<storm_ecs::AvatarAppearance>().map(|(_, appearance)| appearance).collect();
+        serde_wasm_bindgen::to_value(&appearances).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
     /// Shutdown engine
     #[wasm_bindgen]
     pub async fn shutdown(&mut self) -> Result<(), JsValue> {
@@ -219,7 +236,7 @@ impl WasmStormEngine {
         if let Some(engine) = engine.take() {
             engine.shutdown()
                 .await
-                .map_err(|e| JsValue::from_str(&format!("Shutdown failed: {}", e)))?;
+                .map_err(|e| storm_error_to_js("Shutdown failed", e))?;
         }
 
         self.initialized = false;
@@ -303,6 +320,37 @@ impl WasmEntity {
     }
 }
 
+/// Convert an engine error into a JS `Error` carrying its code, retry hints and context
+///
+/// The returned object has `code`, `domain`, `retryable` and `fatal` properties, plus
+/// `connectionId`, `entity` and `assetId` when the error relates to one.
+fn storm_error_to_js(action: &str, error: storm_core::StormError) -> JsValue {
+    let js_error = js_sys::Error::new(&format!("{}: {}", action, error));
+    let context = error.context();
+
+    let mut properties: Vec<(&str, JsValue)> = vec![
+        ("code", error.code().into()),
+        ("domain", format!("{:?}", error.domain()).into()),
+        ("retryable", error.is_retryable().into()),
+        ("fatal", error.is_fatal().into()),
+    ];
+    if let Some(connection_id) = context.connection_id {
+        properties.push(("connectionId", connection_id.to_string().into()));
+    }
+    if let Some(entity) = context.entity {
+        // Entity ids fit comfortably in a JS number
+        properties.push(("entity", (entity as f64).into()));
+    }
+    if let Some(asset_id) = context.asset_id {
+        properties.push(("assetId", asset_id.to_string().into()));
+    }
+
+    for (key, value) in properties {
+        let _ = Reflect::set(&js_error, &key.into(), &value);
+    }
+    js_error.into()
+}
+
 /// Utility functions for WASM environment
 #[wasm_bindgen]
 pub fn get_performance_now() -> f64 {
@@ -4,6 +4,11 @@
 
 use clap::{Parser, Subcommand};
 use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+#[path = "../../../crates/storm-opensim/codegen/template.rs"]
+mod template;
 
 #[derive(Parser)]
 #[command(name = "storm-code-gen")]
@@ -24,9 +29,9 @@ enum Commands {
         #[arg(short, long)]
         output: String,
     },
-    /// Generate protocol serializers
+    /// Generate LLUDP message code from a message_template.msg
     Protocol {
-        /// Protocol specification file
+        /// Message template file
         #[arg(short, long)]
         spec: String,
         /// Output directory
@@ -58,8 +63,16 @@ fn generate_ffi_bindings(input: &str, output: &str) -> Result<()> {
     Ok(())
 }
 
+/// Writes the same files the storm-opensim build script generates, for inspection or vendoring
 fn generate_protocol_code(spec: &str, output: &str) -> Result<()> {
-    // Placeholder for protocol code generation
-    println!("Protocol code generation not yet implemented");
+    let source = fs::read_to_string(spec)?;
+    let template = template::parse(&source).map_err(|e| anyhow::anyhow!("{}: {}", spec, e))?;
+
+    let output = Path::new(output);
+    fs::create_dir_all(output)?;
+    fs::write(output.join("message_types.rs"), template::emit_message_types(&template))?;
+    fs::write(output.join("message_bodies.rs"), template::emit_message_bodies(&template))?;
+
+    println!("Generated {} messages (template version {})", template.messages.len(), template.version);
     Ok(())
 }
\ No newline at end of file